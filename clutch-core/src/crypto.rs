@@ -0,0 +1,397 @@
+use blake2::{digest::consts::U32, Blake2b};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use rand_core::RngCore;
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use serde::Deserialize;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+/// `(r, s, v)`: hex `r` and `s`, as blocks and transactions carry them.
+pub type Signature = (String, String, i32);
+
+/// A 32-byte hash function the chain spec can select.
+pub trait ChainHash {
+    /// Name used in the config.
+    const NAME: &'static str;
+
+    fn digest(data: &[u8]) -> [u8; 32];
+}
+
+/// Keccak-256, as Ethereum uses; transaction hashes, signatures and addresses by default.
+pub struct Keccak256Hash;
+
+impl ChainHash for Keccak256Hash {
+    const NAME: &'static str = "keccak256";
+
+    fn digest(data: &[u8]) -> [u8; 32] {
+        Keccak256::digest(data).into()
+    }
+}
+
+/// SHA-256; block hashes by default.
+pub struct Sha256Hash;
+
+impl ChainHash for Sha256Hash {
+    const NAME: &'static str = "sha256";
+
+    fn digest(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+}
+
+/// BLAKE2b with a 32-byte output.
+pub struct Blake2b256Hash;
+
+impl ChainHash for Blake2b256Hash {
+    const NAME: &'static str = "blake2b256";
+
+    fn digest(data: &[u8]) -> [u8; 32] {
+        Blake2b::<U32>::digest(data).into()
+    }
+}
+
+/// Runtime choice of `ChainHash`, as named in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Keccak256,
+    Sha256,
+    Blake2b256,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Keccak256 => Keccak256Hash::NAME,
+            HashAlgorithm::Sha256 => Sha256Hash::NAME,
+            HashAlgorithm::Blake2b256 => Blake2b256Hash::NAME,
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Keccak256 => Keccak256Hash::digest(data),
+            HashAlgorithm::Sha256 => Sha256Hash::digest(data),
+            HashAlgorithm::Blake2b256 => Blake2b256Hash::digest(data),
+        }
+    }
+}
+
+/// A signature scheme the chain spec can select. Signatures travel in the `(r, s, v)`
+/// fields blocks and transactions already have and are checked against the signer's
+/// address, so no public key has to be added to either.
+pub trait SignatureScheme {
+    /// Name used in the config.
+    const NAME: &'static str;
+
+    fn generate_secret_key<R: RngCore + ?Sized>(rng: &mut R) -> Vec<u8>;
+    /// The serialized public key of `secret_key`.
+    fn public_key(secret_key: &[u8]) -> Result<Vec<u8>, String>;
+    /// The bytes of `public_key` an address is hashed from.
+    fn address_bytes(public_key: &[u8]) -> &[u8] {
+        public_key
+    }
+    fn sign(secret_key: &[u8], digest: &[u8; 32]) -> Result<Signature, String>;
+    /// The public key `signature` was made with over `digest`; fails if it isn't a valid
+    /// signature.
+    fn signer(digest: &[u8; 32], signature: (&str, &str, i32)) -> Result<Vec<u8>, String>;
+}
+
+/// Recoverable ECDSA over secp256k1, Ethereum style: `r` and `s` hex with `v` = 27 or 28.
+pub struct Secp256k1Scheme;
+
+impl SignatureScheme for Secp256k1Scheme {
+    const NAME: &'static str = "secp256k1";
+
+    fn generate_secret_key<R: RngCore + ?Sized>(rng: &mut R) -> Vec<u8> {
+        loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            if let Ok(secret_key) = SecretKey::from_slice(&bytes) {
+                return secret_key.secret_bytes().to_vec();
+            }
+        }
+    }
+
+    fn public_key(secret_key: &[u8]) -> Result<Vec<u8>, String> {
+        let secret_key =
+            SecretKey::from_slice(secret_key).map_err(|e| format!("Invalid secret key: {}", e))?;
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        Ok(public_key.serialize_uncompressed().to_vec())
+    }
+
+    /// The uncompressed key without its `0x04` tag.
+    fn address_bytes(public_key: &[u8]) -> &[u8] {
+        &public_key[1..]
+    }
+
+    fn sign(secret_key: &[u8], digest: &[u8; 32]) -> Result<Signature, String> {
+        let secret_key =
+            SecretKey::from_slice(secret_key).map_err(|e| format!("Invalid secret key: {}", e))?;
+        let message = Message::from_digest(*digest);
+        let (recovery_id, signature) = Secp256k1::new()
+            .sign_ecdsa_recoverable(&message, &secret_key)
+            .serialize_compact();
+        Ok((
+            hex::encode(&signature[..32]),
+            hex::encode(&signature[32..]),
+            recovery_id.to_i32() + 27,
+        ))
+    }
+
+    fn signer(digest: &[u8; 32], (r, s, v): (&str, &str, i32)) -> Result<Vec<u8>, String> {
+        let r = hex::decode(strip_hex_prefix(r)).map_err(|_| "Invalid hex in r".to_string())?;
+        let s = hex::decode(strip_hex_prefix(s)).map_err(|_| "Invalid hex in s".to_string())?;
+        let recovery_id =
+            RecoveryId::from_i32(v - 27).map_err(|_| "Invalid recovery ID".to_string())?;
+        let signature = RecoverableSignature::from_compact(&[r, s].concat(), recovery_id)
+            .map_err(|_| "Valid signature could not be created".to_string())?;
+        Secp256k1::new()
+            .recover_ecdsa(&Message::from_digest(*digest), &signature)
+            .map(|public_key| public_key.serialize_uncompressed().to_vec())
+            .map_err(|_| "Public key could not be recovered".to_string())
+    }
+}
+
+/// Ed25519. Its public key can't be recovered from a signature, so `r` holds the 64-byte
+/// signature, `s` the signer's 32-byte public key and `v` is 0.
+pub struct Ed25519Scheme;
+
+impl SignatureScheme for Ed25519Scheme {
+    const NAME: &'static str = "ed25519";
+
+    fn generate_secret_key<R: RngCore + ?Sized>(rng: &mut R) -> Vec<u8> {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        bytes.to_vec()
+    }
+
+    fn public_key(secret_key: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(Self::signing_key(secret_key)?
+            .verifying_key()
+            .to_bytes()
+            .to_vec())
+    }
+
+    fn sign(secret_key: &[u8], digest: &[u8; 32]) -> Result<Signature, String> {
+        let signing_key = Self::signing_key(secret_key)?;
+        Ok((
+            hex::encode(signing_key.sign(digest).to_bytes()),
+            hex::encode(signing_key.verifying_key().to_bytes()),
+            0,
+        ))
+    }
+
+    fn signer(digest: &[u8; 32], (r, s, v): (&str, &str, i32)) -> Result<Vec<u8>, String> {
+        if v != 0 {
+            return Err("Invalid recovery ID".to_string());
+        }
+        let signature = hex::decode(strip_hex_prefix(r))
+            .ok()
+            .and_then(|bytes| ed25519_dalek::Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| "Invalid signature in r".to_string())?;
+        let public_key: [u8; 32] = hex::decode(strip_hex_prefix(s))
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| "Invalid public key in s".to_string())?;
+        let verifying_key = VerifyingKey::from_bytes(&public_key)
+            .map_err(|_| "Invalid public key in s".to_string())?;
+        verifying_key
+            .verify_strict(digest, &signature)
+            .map_err(|_| "Invalid signature".to_string())?;
+        Ok(public_key.to_vec())
+    }
+}
+
+impl Ed25519Scheme {
+    fn signing_key(secret_key: &[u8]) -> Result<SigningKey, String> {
+        let secret_key: [u8; 32] = secret_key
+            .try_into()
+            .map_err(|_| "Invalid secret key: expected 32 bytes".to_string())?;
+        Ok(SigningKey::from_bytes(&secret_key))
+    }
+}
+
+/// Runtime choice of `SignatureScheme`, as named in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureAlgorithm {
+    Secp256k1,
+    Ed25519,
+}
+
+impl SignatureAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::NAME,
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::NAME,
+        }
+    }
+
+    pub fn generate_secret_key<R: RngCore + ?Sized>(self, rng: &mut R) -> Vec<u8> {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::generate_secret_key(rng),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::generate_secret_key(rng),
+        }
+    }
+
+    pub fn public_key(self, secret_key: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::public_key(secret_key),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::public_key(secret_key),
+        }
+    }
+
+    pub fn address_bytes(self, public_key: &[u8]) -> &[u8] {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::address_bytes(public_key),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::address_bytes(public_key),
+        }
+    }
+
+    pub fn sign(self, secret_key: &[u8], digest: &[u8; 32]) -> Result<Signature, String> {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::sign(secret_key, digest),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::sign(secret_key, digest),
+        }
+    }
+
+    pub fn signer(
+        self,
+        digest: &[u8; 32],
+        signature: (&str, &str, i32),
+    ) -> Result<Vec<u8>, String> {
+        match self {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::signer(digest, signature),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::signer(digest, signature),
+        }
+    }
+}
+
+fn strip_hex_prefix(s: &str) -> &str {
+    s.trim_start_matches("0x").trim_start_matches("0X")
+}
+
+/// The primitives of a chain, from its config. Every node of a network must use the same
+/// suite: blocks and transactions hashed or signed with another don't validate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CryptoSuite {
+    /// Transaction hashes, the digest signatures are made over, and addresses.
+    pub hash: HashAlgorithm,
+    pub block_hash: HashAlgorithm,
+    pub signature: SignatureAlgorithm,
+}
+
+/// Keccak-256 and secp256k1 like Ethereum, with SHA-256 block hashes: the primitives
+/// Clutch used before they were configurable.
+impl Default for CryptoSuite {
+    fn default() -> Self {
+        Self {
+            hash: HashAlgorithm::Keccak256,
+            block_hash: HashAlgorithm::Sha256,
+            signature: SignatureAlgorithm::Secp256k1,
+        }
+    }
+}
+
+impl CryptoSuite {
+    pub fn hash(&self, data: &[u8]) -> [u8; 32] {
+        self.hash.digest(data)
+    }
+
+    pub fn block_hash(&self, data: &[u8]) -> [u8; 32] {
+        self.block_hash.digest(data)
+    }
+
+    /// `0x` + the last 20 bytes of the hash of `public_key`.
+    pub fn address(&self, public_key: &[u8]) -> String {
+        let hash = self.hash(self.signature.address_bytes(public_key));
+        format!("0x{}", hex::encode(&hash[12..]))
+    }
+
+    /// Signs the hash of `data`.
+    pub fn sign(&self, secret_key: &[u8], data: &[u8]) -> Result<Signature, String> {
+        self.signature.sign(secret_key, &self.hash(data))
+    }
+
+    /// Whether `(r, s, v)` is a signature over the hash of `data` by the key of `address`.
+    pub fn verify(
+        &self,
+        address: &str,
+        data: &[u8],
+        r: &str,
+        s: &str,
+        v: i32,
+    ) -> Result<bool, String> {
+        Ok(self.recover_address(data, r, s, v)? == address)
+    }
+
+    /// Address of the key that made `(r, s, v)` over the hash of `data`.
+    pub fn recover_address(&self, data: &[u8], r: &str, s: &str, v: i32) -> Result<String, String> {
+        let public_key = self.signature.signer(&self.hash(data), (r, s, v))?;
+        Ok(self.address(&public_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn hashes_match_their_reference_vectors() {
+        let vectors = [
+            (
+                HashAlgorithm::Keccak256,
+                "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+            ),
+            (
+                HashAlgorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgorithm::Blake2b256,
+                "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            ),
+        ];
+        for (algorithm, expected) in vectors {
+            assert_eq!(hex::encode(algorithm.digest(b"abc")), expected);
+        }
+    }
+
+    #[test]
+    fn every_suite_verifies_its_own_signatures_only() {
+        for signature in [SignatureAlgorithm::Secp256k1, SignatureAlgorithm::Ed25519] {
+            for hash in [HashAlgorithm::Keccak256, HashAlgorithm::Blake2b256] {
+                let suite = CryptoSuite {
+                    hash,
+                    block_hash: hash,
+                    signature,
+                };
+                let secret_key = signature.generate_secret_key(&mut OsRng);
+                let address = suite.address(&signature.public_key(&secret_key).unwrap());
+                let (r, s, v) = suite.sign(&secret_key, b"block hash").unwrap();
+
+                assert_eq!(suite.verify(&address, b"block hash", &r, &s, v), Ok(true));
+                assert_ne!(suite.verify(&address, b"other hash", &r, &s, v), Ok(true));
+                let other = CryptoSuite {
+                    hash: HashAlgorithm::Sha256,
+                    ..suite
+                };
+                assert_ne!(other.verify(&address, b"block hash", &r, &s, v), Ok(true));
+            }
+        }
+    }
+
+    #[test]
+    fn ed25519_keys_match_rfc8032() {
+        let secret_key =
+            hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+                .unwrap();
+        assert_eq!(
+            hex::encode(SignatureAlgorithm::Ed25519.public_key(&secret_key).unwrap()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+    }
+}
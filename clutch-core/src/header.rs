@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::CryptoSuite;
+use crate::rlp_encoding::RlpCodec;
+
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct BlockHeaders {
+    #[rlp(list)]
+    pub block_headers: Vec<BlockHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpCodec)]
+pub struct BlockHeader {
+    pub index: usize,
+    pub previous_hash: String,
+    pub author: String,
+    pub signature_r: String,
+    pub signature_s: String,
+    #[rlp(as = "u64")]
+    pub signature_v: i32,
+    pub hash: String,
+    /// Commitments to the body, checked when it is downloaded; empty on blocks from
+    /// before the `header_roots` fork.
+    #[serde(default)]
+    #[rlp(default)]
+    pub transactions_root: String,
+    #[serde(default)]
+    #[rlp(default)]
+    pub receipts_root: String,
+    /// Set on the last block of each epoch: the authorities of the next one. Trusting a
+    /// header signed by a current authority, a light client takes the next epoch's
+    /// authors from here.
+    #[serde(default)]
+    #[rlp(list, default)]
+    pub next_authorities: Vec<String>,
+}
+
+impl BlockHeader {
+    /// Key of the header stored next to its block in the `block` column family.
+    pub fn construct_key(index: usize) -> Vec<u8> {
+        format!("header_{}", index).into_bytes()
+    }
+
+    /// Checks that `author` signed the header's hash, as block import does; a light
+    /// client also has to check `author` is an authority of the chain.
+    pub fn verify_signature(&self, suite: &CryptoSuite) -> Result<(), String> {
+        let signed = suite.verify(
+            &self.author,
+            self.hash.as_bytes(),
+            &self.signature_r,
+            &self.signature_s,
+            self.signature_v,
+        )?;
+        if !signed {
+            return Err(format!(
+                "header {} is not signed by {}",
+                self.index, self.author
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl BlockHeaders {
+    pub fn to_block_indexes(&self) -> Vec<usize> {
+        self.block_headers
+            .iter()
+            .map(|header| header.index)
+            .collect()
+    }
+
+    /// Checks that the headers are consecutive blocks, the first one building on
+    /// `parent_hash`. Matching indexes alone don't mean two chains agree.
+    pub fn verify_chain(&self, parent_hash: &str) -> Result<(), String> {
+        let mut parent: Option<&BlockHeader> = None;
+        for header in &self.block_headers {
+            let (expected_hash, expected_index) = match parent {
+                Some(parent) => (parent.hash.as_str(), Some(parent.index + 1)),
+                None => (parent_hash, None),
+            };
+            if header.previous_hash != expected_hash {
+                return Err(format!(
+                    "header {} does not build on {}",
+                    header.index, expected_hash
+                ));
+            }
+            if expected_index.is_some_and(|index| index != header.index) {
+                return Err(format!("header {} is out of sequence", header.index));
+            }
+            parent = Some(header);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlp_encoding::{decode, encode};
+    use rand::rngs::OsRng;
+
+    fn header(index: usize, previous_hash: &str, hash: &str) -> BlockHeader {
+        BlockHeader {
+            index,
+            previous_hash: previous_hash.to_string(),
+            author: String::new(),
+            signature_r: String::new(),
+            signature_s: String::new(),
+            signature_v: 0,
+            hash: hash.to_string(),
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn headers_must_link_back_to_the_anchor() {
+        let headers = BlockHeaders {
+            block_headers: vec![header(5, "tip", "h5"), header(6, "h5", "h6")],
+        };
+        assert!(headers.verify_chain("tip").is_ok());
+        assert!(headers.verify_chain("other_fork_tip").is_err());
+
+        let gap = BlockHeaders {
+            block_headers: vec![header(5, "tip", "h5"), header(7, "h5", "h7")],
+        };
+        assert!(gap.verify_chain("tip").is_err());
+    }
+
+    #[test]
+    fn headers_verify_against_their_author_only() {
+        let suite = CryptoSuite::default();
+        let secret_key = suite.signature.generate_secret_key(&mut OsRng);
+        let author = suite.address(&suite.signature.public_key(&secret_key).unwrap());
+        let mut signed = header(5, "tip", "h5");
+        let (r, s, v) = suite.sign(&secret_key, signed.hash.as_bytes()).unwrap();
+        signed.author = author;
+        (signed.signature_r, signed.signature_s, signed.signature_v) = (r, s, v);
+        assert_eq!(signed.verify_signature(&suite), Ok(()));
+
+        let mut impostor = signed.clone();
+        impostor.author = "0x8f19077627cde4848b090c53c83b12956837d5e9".to_string();
+        assert!(impostor.verify_signature(&suite).is_err());
+
+        let mut rehashed = signed;
+        rehashed.hash = "h6".to_string();
+        assert!(rehashed.verify_signature(&suite).is_err());
+    }
+
+    #[test]
+    fn next_authorities_are_only_encoded_when_set() {
+        let plain = header(5, "tip", "h5");
+        let encoded = encode(&plain);
+        // Empty trailing fields are left off, so such a header encodes as it always did.
+        assert_eq!(rlp::Rlp::new(&encoded).item_count(), Ok(7));
+        assert_eq!(decode::<BlockHeader>(&encoded).unwrap(), plain);
+
+        let mut boundary = header(9, "h8", "h9");
+        boundary.next_authorities = vec!["0xa".to_string(), "0xb".to_string()];
+        assert_eq!(decode::<BlockHeader>(&encode(&boundary)).unwrap(), boundary);
+    }
+}
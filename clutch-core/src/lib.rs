@@ -0,0 +1,29 @@
+/*
+ * Copyright 2024 Mehran Mazhar and Clutch Protocol Contributors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The parts of a Clutch node a light client needs, without storage or networking:
+//! block headers and their chain and signature checks, the RLP codecs, the configurable
+//! hash and signature primitives, and Merkle proofs against header roots. clutch-node
+//! runs this same code, and it builds for `wasm32-unknown-unknown` so wallet frontends
+//! can verify headers and proofs in the browser.
+
+// `#[derive(RlpCodec)]` names `::clutch_core`, which has to resolve in here too.
+extern crate self as clutch_core;
+
+pub mod crypto;
+pub mod header;
+pub mod merkle;
+pub mod rlp_encoding;
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::HashAlgorithm;
+use crate::rlp_encoding::RlpCodec;
+
+/// Hex root of a binary Merkle tree over `leaves`, hashed with `hash` (the chain's
+/// `CryptoSuite::hash`): each leaf is hashed, then each level is hashed in pairs, the
+/// last node of an odd level being paired with itself. No leaves give the hash of the
+/// empty string.
+pub fn merkle_root<L: AsRef<[u8]>>(
+    hash: HashAlgorithm,
+    leaves: impl IntoIterator<Item = L>,
+) -> String {
+    let mut level = leaf_hashes(hash, leaves);
+    if level.is_empty() {
+        return hex::encode(hash.digest(&[]));
+    }
+    while level.len() > 1 {
+        level = next_level(hash, &level);
+    }
+    hex::encode(level[0])
+}
+
+/// The path from leaf `index` of the tree `merkle_root` builds over `leaves` to its root,
+/// or `None` past the last leaf.
+pub fn merkle_proof<L: AsRef<[u8]>>(
+    hash: HashAlgorithm,
+    leaves: impl IntoIterator<Item = L>,
+    index: usize,
+) -> Option<MerkleProof> {
+    let mut level = leaf_hashes(hash, leaves);
+    if index >= level.len() {
+        return None;
+    }
+    let mut siblings = Vec::new();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+        siblings.push(hex::encode(sibling));
+        level = next_level(hash, &level);
+        position /= 2;
+    }
+    Some(MerkleProof {
+        index: index as u64,
+        siblings,
+    })
+}
+
+/// Proof that a leaf is in a tree with a given root, e.g. that a transaction hash is
+/// committed to by a header's `transactions_root`, so a light client holding only
+/// verified headers can check it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpCodec)]
+pub struct MerkleProof {
+    /// Position of the leaf; its bits say on which side each sibling goes.
+    pub index: u64,
+    /// Hex hashes of the sibling at each level, from the leaves up.
+    #[rlp(list)]
+    pub siblings: Vec<String>,
+}
+
+impl MerkleProof {
+    /// Checks that hashing `leaf` up through the siblings gives `root`.
+    pub fn verify(&self, hash: HashAlgorithm, leaf: &[u8], root: &str) -> Result<(), String> {
+        let mut node = hash.digest(leaf);
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            let sibling: [u8; 32] = hex::decode(sibling)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("Invalid sibling hash {:?} in Merkle proof", sibling))?;
+            node = if position & 1 == 0 {
+                hash.digest(&[node, sibling].concat())
+            } else {
+                hash.digest(&[sibling, node].concat())
+            };
+            position /= 2;
+        }
+        if position != 0 {
+            return Err(format!(
+                "Merkle proof index {} is past its {} levels",
+                self.index,
+                self.siblings.len()
+            ));
+        }
+        if hex::encode(node) != root {
+            return Err(format!("Merkle proof does not lead to root {}", root));
+        }
+        Ok(())
+    }
+}
+
+fn leaf_hashes<L: AsRef<[u8]>>(
+    hash: HashAlgorithm,
+    leaves: impl IntoIterator<Item = L>,
+) -> Vec<[u8; 32]> {
+    leaves
+        .into_iter()
+        .map(|leaf| hash.digest(leaf.as_ref()))
+        .collect()
+}
+
+fn next_level(hash: HashAlgorithm, level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| {
+            let right = pair.get(1).unwrap_or(&pair[0]);
+            hash.digest(&[pair[0], *right].concat())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: HashAlgorithm = HashAlgorithm::Keccak256;
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        HASH.digest(data)
+    }
+
+    #[test]
+    fn odd_levels_pair_the_last_node_with_itself() {
+        let (a, b, c) = (hash(b"a"), hash(b"b"), hash(b"c"));
+        let ab = hash(&[a, b].concat());
+        let cc = hash(&[c, c].concat());
+
+        assert_eq!(
+            merkle_root(HASH, Vec::<&[u8]>::new()),
+            hex::encode(hash(b""))
+        );
+        assert_eq!(merkle_root(HASH, ["a"]), hex::encode(a));
+        assert_eq!(merkle_root(HASH, ["a", "b"]), hex::encode(ab));
+        assert_eq!(
+            merkle_root(HASH, ["a", "b", "c"]),
+            hex::encode(hash(&[ab, cc].concat()))
+        );
+        assert_ne!(merkle_root(HASH, ["b", "a"]), merkle_root(HASH, ["a", "b"]));
+    }
+
+    #[test]
+    fn every_leaf_proves_against_the_root() {
+        for count in 1..=9 {
+            let leaves: Vec<String> = (0..count).map(|i| format!("tx{}", i)).collect();
+            let root = merkle_root(HASH, &leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(HASH, &leaves, index).unwrap();
+                assert_eq!(proof.verify(HASH, leaf.as_bytes(), &root), Ok(()));
+                assert!(proof.verify(HASH, b"other", &root).is_err());
+            }
+            assert_eq!(merkle_proof(HASH, &leaves, count), None);
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_are_rejected() {
+        let leaves = ["a", "b", "c", "d", "e"];
+        let root = merkle_root(HASH, leaves);
+        let proof = merkle_proof(HASH, leaves, 2).unwrap();
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(moved.verify(HASH, b"c", &root).is_err());
+        moved.index = 2 + (1 << moved.siblings.len());
+        assert!(moved.verify(HASH, b"c", &root).is_err());
+
+        let mut short = proof.clone();
+        short.siblings.pop();
+        assert!(short.verify(HASH, b"c", &root).is_err());
+
+        let mut garbled = proof;
+        garbled.siblings[0] = "not hex".to_string();
+        assert!(garbled.verify(HASH, b"c", &root).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::marker::PhantomData;
+
+pub use clutch_rlp_derive::RlpCodec;
+
+/// Bridge between the RLP wire encoding and the serde (JSON) form of a type. Every direct
+/// message framed by `encode_message` implements it; `#[derive(RlpCodec)]` provides the
+/// impl, so only hand-written codecs (`Transaction`) need to add it themselves.
+pub trait RlpMessage:
+    Encodable + Decodable + serde::Serialize + serde::de::DeserializeOwned
+{
+}
+
+pub fn encode<T: Encodable>(data: &T) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+    data.rlp_append(&mut stream);
+    stream.out().to_vec()
+}
+
+pub fn decode<T: Decodable>(bytes: &[u8]) -> Result<T, DecoderError> {
+    let rlp = Rlp::new(bytes);
+    T::decode(&rlp)
+}
+
+/// Lazily decodes the items of an RLP list, one per `next()`, borrowing from the input
+/// buffer. Lets a caller check the item count and reject a bad item without first
+/// materializing every item in the list.
+pub struct ListDecoder<'a, T> {
+    list: Rlp<'a>,
+    next: usize,
+    len: usize,
+    item: PhantomData<T>,
+}
+
+impl<'a, T: Decodable> ListDecoder<'a, T> {
+    pub fn new(list: Rlp<'a>) -> Result<Self, DecoderError> {
+        if !list.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        Ok(Self {
+            len: list.item_count()?,
+            list,
+            next: 0,
+            item: PhantomData,
+        })
+    }
+
+    /// Number of items in the list, known before any of them is decoded.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Decodable> Iterator for ListDecoder<'_, T> {
+    type Item = Result<T, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        let item = self.list.at(self.next).and_then(|item| T::decode(&item));
+        self.next += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Decodable> ExactSizeIterator for ListDecoder<'_, T> {}
+
+/// Streaming counterpart of `decode` for a message that is an RLP list with `field_count`
+/// fields, the one at `index` being a list: e.g. `BlockBodies` is `[blocks]`.
+pub fn decode_list_field<T: Decodable>(
+    bytes: &[u8],
+    field_count: usize,
+    index: usize,
+) -> Result<ListDecoder<'_, T>, DecoderError> {
+    let rlp = Rlp::new(bytes);
+    if !rlp.is_list() || rlp.item_count()? != field_count {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+    ListDecoder::new(rlp.at(index)?)
+}
@@ -0,0 +1,274 @@
+//! `#[derive(RlpCodec)]` for clutch-node wire and transaction types.
+//!
+//! A struct with named fields is encoded as an RLP list of its fields in declaration
+//! order, and decoding rejects a list with any other item count. An enum must have only
+//! single-field tuple variants, each with an explicit `#[rlp(tag = N)]`; it is encoded as
+//! `[tag, payload]`. Tags are part of the wire format, so they are never derived from
+//! variant order.
+//!
+//! Field attributes:
+//! - `#[rlp(list)]`: a `Vec<T>` written as a nested list / read with `list_at`.
+//! - `#[rlp(as = "u64")]`: a numeric field cast to and from the given integer type.
+//! - `#[rlp(with = "path")]`: a field encoded by `path::rlp_append(&value, stream)` and
+//!   decoded by `path::decode(&rlp)`.
+//! - `#[rlp(default)]`: an optional trailing field, combinable with the above. It is left
+//!   off the list while it equals `Default::default()` and defaulted when absent, so a
+//!   field can be added to a message without changing how existing values encode.
+//!
+//! The generated code also implements `clutch_core::rlp_encoding::RlpMessage`, the serde
+//! bridge that lets the type be framed by `encode_message`, so the macro is only usable
+//! in crates depending on clutch-core (and in clutch-core itself, which names itself
+//! `clutch_core` for the purpose).
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DataEnum, DeriveInput, Fields, FieldsNamed, Ident, LitInt, LitStr,
+    Path, Type,
+};
+
+#[proc_macro_derive(RlpCodec, attributes(rlp))]
+pub fn derive_rlp_codec(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (encode, decode) = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => expand_struct(fields)?,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "RlpCodec structs must have named fields",
+                ))
+            }
+        },
+        Data::Enum(data) => expand_enum(name, data)?,
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "RlpCodec cannot be derived for unions",
+            ))
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rlp::Encodable for #name #ty_generics #where_clause {
+            fn rlp_append(&self, stream: &mut ::rlp::RlpStream) {
+                #encode
+            }
+        }
+
+        impl #impl_generics ::rlp::Decodable for #name #ty_generics #where_clause {
+            fn decode(rlp: &::rlp::Rlp) -> ::std::result::Result<Self, ::rlp::DecoderError> {
+                #decode
+            }
+        }
+
+        impl #impl_generics ::clutch_core::rlp_encoding::RlpMessage for #name #ty_generics #where_clause {}
+    })
+}
+
+enum FieldCodec {
+    Value,
+    List,
+    As(Type),
+    With(Path),
+}
+
+/// Returns the field's codec and whether it is an optional `#[rlp(default)]` field.
+fn field_codec(field: &syn::Field) -> syn::Result<(FieldCodec, bool)> {
+    let mut codec = FieldCodec::Value;
+    let mut default = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("rlp"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                default = true;
+                return Ok(());
+            }
+            if !matches!(codec, FieldCodec::Value) {
+                return Err(meta.error("only one of `list`, `as` or `with` is allowed"));
+            }
+            if meta.path.is_ident("list") {
+                codec = FieldCodec::List;
+            } else if meta.path.is_ident("as") {
+                codec = FieldCodec::As(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("with") {
+                codec = FieldCodec::With(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else {
+                return Err(
+                    meta.error("expected `list`, `default`, `as = \"..\"` or `with = \"..\"`")
+                );
+            }
+            Ok(())
+        })?;
+    }
+    Ok((codec, default))
+}
+
+fn expand_struct(fields: &FieldsNamed) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let count = fields.named.len();
+    let mut required = count;
+    let mut present = Vec::new();
+    let mut appends = Vec::with_capacity(count);
+    let mut values = Vec::with_capacity(count);
+
+    for (index, field) in fields.named.iter().enumerate() {
+        let ident = field.ident.as_ref().expect("named field");
+        let (codec, default) = field_codec(field)?;
+        if default && required == count {
+            required = index;
+        } else if !default && required < count {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "fields after an `#[rlp(default)]` field must also be `#[rlp(default)]`",
+            ));
+        }
+
+        let (append, value) = match codec {
+            FieldCodec::Value => (
+                quote! { stream.append(&self.#ident); },
+                quote! { rlp.val_at(#index)? },
+            ),
+            FieldCodec::List => (
+                quote! {
+                    stream.begin_list(self.#ident.len());
+                    for item in &self.#ident {
+                        stream.append(item);
+                    }
+                },
+                quote! { rlp.list_at(#index)? },
+            ),
+            FieldCodec::As(ty) => (
+                quote! { stream.append(&(self.#ident as #ty)); },
+                quote! { rlp.val_at::<#ty>(#index)? as _ },
+            ),
+            FieldCodec::With(path) => (
+                quote! { #path::rlp_append(&self.#ident, stream); },
+                quote! { #path::decode(&rlp.at(#index)?)? },
+            ),
+        };
+
+        if default {
+            // Written only up to the last optional field that differs from its default.
+            let len = index + 1;
+            let ty = &field.ty;
+            present.push(quote! {
+                if self.#ident != <#ty as ::std::default::Default>::default() {
+                    count = #len;
+                }
+            });
+            appends.push(quote! {
+                if count > #index {
+                    #append
+                }
+            });
+            values.push(quote! {
+                #ident: if count > #index { #value } else { ::std::default::Default::default() }
+            });
+        } else {
+            appends.push(append);
+            values.push(quote! { #ident: #value });
+        }
+    }
+
+    let encode = quote! {
+        let mut count = #required;
+        #(#present)*
+        stream.begin_list(count);
+        #(#appends)*
+    };
+    let bad_count = if required == count {
+        quote! { count != #count }
+    } else {
+        quote! { !(#required..=#count).contains(&count) }
+    };
+    let decode = quote! {
+        if !rlp.is_list() {
+            return Err(::rlp::DecoderError::RlpIncorrectListLen);
+        }
+        let count = rlp.item_count()?;
+        if #bad_count {
+            return Err(::rlp::DecoderError::RlpIncorrectListLen);
+        }
+        Ok(Self { #(#values),* })
+    };
+    Ok((encode, decode))
+}
+
+fn variant_tag(variant: &syn::Variant) -> syn::Result<u8> {
+    let mut tag = None;
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("rlp"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u8>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag = N`"))
+            }
+        })?;
+    }
+    tag.ok_or_else(|| syn::Error::new_spanned(&variant.ident, "missing `#[rlp(tag = N)]`"))
+}
+
+fn expand_enum(name: &Ident, data: &DataEnum) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let mut seen = Vec::new();
+    let mut encode_arms = Vec::new();
+    let mut decode_arms = Vec::new();
+
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        if !matches!(&variant.fields, Fields::Unnamed(fields) if fields.unnamed.len() == 1) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "RlpCodec enum variants must have exactly one unnamed field",
+            ));
+        }
+        let tag = variant_tag(variant)?;
+        if seen.contains(&tag) {
+            return Err(syn::Error::new_spanned(ident, "duplicate RLP tag"));
+        }
+        seen.push(tag);
+
+        encode_arms.push(quote! {
+            Self::#ident(payload) => {
+                stream.begin_list(2);
+                stream.append(&#tag);
+                stream.append(payload);
+            }
+        });
+        decode_arms.push(quote! { #tag => Ok(Self::#ident(rlp.val_at(1)?)), });
+    }
+
+    let unknown = LitStr::new(&format!("Unknown {} variant", name), name.span());
+    let encode = quote! {
+        match self {
+            #(#encode_arms)*
+        }
+    };
+    let decode = quote! {
+        if !rlp.is_list() || rlp.item_count()? != 2 {
+            return Err(::rlp::DecoderError::RlpIncorrectListLen);
+        }
+        let tag: u8 = rlp.val_at(0)?;
+        match tag {
+            #(#decode_arms)*
+            _ => Err(::rlp::DecoderError::Custom(#unknown)),
+        }
+    };
+    Ok((encode, decode))
+}
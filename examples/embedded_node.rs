@@ -0,0 +1,36 @@
+// Runs a Clutch node inside another program: the configuration comes from
+// `config/node/default.toml`, the database is kept in `./embedded-data`, and a plugin logs
+// every imported block.
+use clutch_node::node::blocks::block::Block;
+use clutch_node::{Config, NodeBuilder, NodePlugin};
+
+struct BlockLogger;
+
+impl NodePlugin for BlockLogger {
+    fn name(&self) -> &str {
+        "block-logger"
+    }
+
+    fn on_block_imported(&self, block: &Block) {
+        println!(
+            "Imported block {} with {} transactions",
+            block.index,
+            block.transactions.len()
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load_configuration("default")?;
+    std::fs::create_dir_all("embedded-data")?;
+
+    let node = NodeBuilder::new(config)
+        .with_storage("embedded-data")
+        .with_plugin(BlockLogger)
+        .build();
+    let head = node.blockchain().get_latest_block()?;
+    println!("Starting at block {}", head.map_or(0, |block| block.index));
+    node.run().await;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+/*
+ * C ABI of clutch_node built with `--features ffi` (src/node/ffi.rs).
+ *
+ * Functions that can fail return -1 or NULL; clutch_last_error() then describes the
+ * failure on the calling thread. Strings the library returns are freed with
+ * clutch_string_free().
+ */
+#ifndef CLUTCH_NODE_H
+#define CLUTCH_NODE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ClutchNode ClutchNode;
+
+/* Runs on the node's thread while it holds the chain lock: return quickly and don't call
+ * back into the node. `hash` is only valid during the call. */
+typedef void (*ClutchBlockCallback)(void *user_data, uint64_t index, const char *hash);
+
+/* Starts the node configured by config/node/<env>.toml, relative to the working
+ * directory, with its database in storage_dir (NULL: where the binary keeps it). */
+ClutchNode *clutch_node_start(const char *env, const char *storage_dir);
+
+/* Stops the node, waits for its chain to shut down and frees it. */
+void clutch_node_stop(ClutchNode *node);
+
+/* Adds an RLP-encoded transaction to the pool and gossips it. */
+int clutch_node_submit_transaction(const ClutchNode *node, const uint8_t *rlp, size_t len);
+
+/* The head block as JSON. */
+char *clutch_node_head(const ClutchNode *node);
+
+/* Calls callback with user_data for every block imported until the node stops. */
+int clutch_node_on_block(const ClutchNode *node, ClutchBlockCallback callback, void *user_data);
+
+char *clutch_last_error(void);
+
+void clutch_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CLUTCH_NODE_H */
@@ -14,4 +14,11 @@
  * limitations under the License.
  */
 
-pub mod node;
\ No newline at end of file
+pub mod node;
+
+pub use node::blockchain::Blockchain;
+pub use node::configuration::AppConfig as Config;
+pub use node::node_builder::{Node, NodeBuilder};
+pub use node::node_services::NodeHandle;
+pub use node::p2p_server::P2PServer;
+pub use node::plugin::NodePlugin;
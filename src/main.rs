@@ -14,39 +14,345 @@
  * limitations under the License.
  */
 
-use clap::Parser;
-mod node;
-use node::blockchain::Blockchain;
-use node::configuration::AppConfig;
-use node::tracing::setup_tracing;
+use clap::{Parser, Subcommand};
+use clutch_node::node::address::{display_address, Address};
+use clutch_node::node::backup;
+use clutch_node::node::blocks::era::{self, EraArchive};
+use clutch_node::node::conformance::TestVectors;
+use clutch_node::node::crypto;
+use clutch_node::node::database::Database;
+use clutch_node::node::mnemonic::{DerivationPath, Mnemonic, DEFAULT_DERIVATION_PATH};
+use clutch_node::node::runtime;
+use clutch_node::node::signature_keys::SignatureKeys;
+use clutch_node::node::testnet::{run_testnet, testnet_nodes};
+use clutch_node::node::tracing::setup_tracing;
+use clutch_node::{Config, NodeBuilder};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     #[clap(short, long, default_value = "default")]
     env: String,
+    /// Show a live dashboard instead of logs, which go to `log_file`.
+    #[clap(long)]
+    tui: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let env = &Args::parse().env;
-    let config = AppConfig::load_configuration(env)?;
-    setup_tracing(&config.log_level, &config.seq_url, &config.seq_api_key)?;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Unwind the chain head, state and balance effects back to block `--to`, e.g. after
+    /// importing a bad block. Run it with the node stopped.
+    Rollback {
+        #[clap(long)]
+        to: usize,
+        /// Remove more blocks than `max_reorg_depth` allows.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Write a compressed backup of the database to `--output`, a file or `-` for stdout
+    /// (e.g. piped to `aws s3 cp - s3://...`). Safe while the node runs: the backup is of
+    /// one point in time.
+    Backup {
+        #[clap(long)]
+        output: String,
+        /// Continue an interrupted backup file instead of refusing to overwrite it.
+        #[clap(long)]
+        resume: bool,
+    },
+    /// Restore a backup from `--input`, a file or `-` for stdin, as this node's database.
+    /// Run it with the node stopped.
+    Restore {
+        #[clap(long)]
+        input: String,
+        /// Replace an existing database.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Database maintenance. Run it with the node stopped.
+    #[clap(subcommand)]
+    Db(DbCommand),
+    /// Run a local network of `--nodes` validators with fresh keys in this process,
+    /// configured from `--env` with their own ports, bootnodes and data directories.
+    Testnet {
+        #[clap(long, default_value_t = 3)]
+        nodes: usize,
+        /// Node `i` serves RPC on `base_port + 2i` and P2P on `base_port + 2i + 1`.
+        #[clap(long, default_value_t = 18000)]
+        base_port: u16,
+        #[clap(long, default_value = "testnet")]
+        dir: PathBuf,
+    },
+    /// Protocol conformance vectors: canonical RLP encodings and hashes that other client
+    /// implementations check themselves against.
+    #[clap(subcommand)]
+    TestVectors(TestVectorsCommand),
+    /// Validator keys for `author_public_key` and `author_secret_key`.
+    #[clap(subcommand)]
+    Account(AccountCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum AccountCommand {
+    /// Generate a keypair and print it with the config lines that make it the author key.
+    New {
+        /// Generate a BIP39 phrase and derive the key from it, so `account recover` can
+        /// restore it from the phrase alone.
+        #[clap(long)]
+        mnemonic: bool,
+        #[clap(long, default_value_t = 24)]
+        words: usize,
+        #[clap(long, default_value = DEFAULT_DERIVATION_PATH)]
+        path: DerivationPath,
+        /// Read a BIP39 passphrase from stdin; recovering needs the same one. It is never
+        /// taken as an argument, which would leave it in shell history and `ps`.
+        #[clap(long)]
+        passphrase: bool,
+    },
+    /// Derive the key at `--path` from a mnemonic phrase read from stdin.
+    Recover {
+        #[clap(long, default_value = DEFAULT_DERIVATION_PATH)]
+        path: DerivationPath,
+        /// Read the BIP39 passphrase from stdin after the phrase.
+        #[clap(long)]
+        passphrase: bool,
+    },
+    /// Check an address, in `<chain_id>:0x…` form or plain hex, against this network and
+    /// print it in the forms the node uses.
+    Show { address: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// Compact every column family, reclaiming space from deleted and overwritten keys.
+    Compact,
+    /// Recover a damaged database: salvage what RocksDB can read, then rebuild the head
+    /// pointer, state and balance effects from the stored blocks.
+    Repair,
+    /// Write finalized history into era files in `era_dir`, a fixed range of blocks each.
+    Freeze {
+        /// Then delete the archived block bodies from the database and compact it.
+        #[clap(long)]
+        prune: bool,
+    },
+    /// Import the blocks above our head from a directory of era files, e.g. one
+    /// downloaded from another node's `/era`.
+    ImportEra {
+        #[clap(long)]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TestVectorsCommand {
+    /// Write this node's vectors to `--out`.
+    Generate {
+        #[clap(long, default_value = "conformance/vectors.json")]
+        out: PathBuf,
+    },
+    /// Check a vectors file against this node's encodings and hashes.
+    Verify {
+        #[clap(default_value = "conformance/vectors.json")]
+        path: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let config = Config::load_configuration(&args.env)?;
+    runtime::install_cpu_pool(config.cpu_threads);
+    runtime::build(&config)?.block_on(run(args, config))
+}
 
-    let blockchain = initialize_blockchain(&config);
-    blockchain.start_network_services(&config).await;
+async fn run(args: Args, mut config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    // Before anything is hashed or signed, which would fix the default primitives.
+    crypto::install(config.crypto_suite())?;
+    // Before tracing, which logs to stdout: the backup may be streamed there.
+    if let Some(Command::Backup { output, resume }) = &args.command {
+        let db_path = Database::db_path(&config.blockchain_name);
+        let summary = backup::backup(&db_path, output, *resume)?;
+        eprintln!(
+            "Backed up {} entries at block {}",
+            summary.entries,
+            summary
+                .head_index
+                .map_or("-".to_string(), |index| index.to_string())
+        );
+        return Ok(());
+    }
+    // Only a running node draws the dashboard; other commands print their results.
+    config.tui_enabled = (args.tui || config.tui_enabled) && args.command.is_none();
+    let log_file = Some(config.log_file.as_str()).filter(|_| config.tui_enabled);
+    setup_tracing(
+        &config.log_level,
+        &config.seq_url,
+        &config.seq_api_key,
+        log_file,
+    )?;
+
+    if let Some(Command::Testnet {
+        nodes,
+        base_port,
+        dir,
+    }) = &args.command
+    {
+        run_testnet(testnet_nodes(&config, *nodes, *base_port, dir)?).await?;
+        return Ok(());
+    }
+    if let Some(Command::TestVectors(command)) = &args.command {
+        match command {
+            TestVectorsCommand::Generate { out } => {
+                let vectors = TestVectors::generate();
+                vectors.save(out)?;
+                println!(
+                    "Wrote {} test vectors to {}",
+                    vectors.vectors.len(),
+                    out.to_string_lossy()
+                );
+            }
+            TestVectorsCommand::Verify { path } => {
+                let vectors = TestVectors::load(path)?;
+                let failures = vectors.verify();
+                for failure in &failures {
+                    println!("FAIL {}", failure);
+                }
+                if !failures.is_empty() {
+                    return Err(format!(
+                        "{} of {} test vectors failed",
+                        failures.len(),
+                        vectors.vectors.len()
+                    )
+                    .into());
+                }
+                println!("All {} test vectors passed", vectors.vectors.len());
+            }
+        }
+        return Ok(());
+    }
+    if let Some(Command::Account(command)) = &args.command {
+        match command {
+            AccountCommand::New {
+                mnemonic: false, ..
+            } => print_keys(&SignatureKeys::generate_new_keypair(), &config.chain_id),
+            AccountCommand::New {
+                mnemonic: true,
+                words,
+                path,
+                passphrase,
+            } => {
+                let passphrase = read_passphrase(*passphrase)?;
+                let mnemonic = Mnemonic::generate(*words)?;
+                println!("mnemonic:   {}", mnemonic.phrase());
+                println!("path:       {}", path);
+                print_keys(&mnemonic.derive_keys(&passphrase, path)?, &config.chain_id);
+                println!();
+                println!("Write the mnemonic down and keep it offline; it restores this key.");
+            }
+            AccountCommand::Recover { path, passphrase } => {
+                eprintln!("Enter the mnemonic phrase:");
+                let mut phrase = String::new();
+                std::io::stdin().read_line(&mut phrase)?;
+                let mnemonic = Mnemonic::parse(&phrase)?;
+                let passphrase = read_passphrase(*passphrase)?;
+                println!("path:       {}", path);
+                print_keys(&mnemonic.derive_keys(&passphrase, path)?, &config.chain_id);
+            }
+            AccountCommand::Show { address } => {
+                let address = Address::parse(address, &config.chain_id)?;
+                println!("address:    {}", address.display(&config.chain_id));
+                println!("hex:        {}", address.to_hex());
+            }
+        }
+        return Ok(());
+    }
+    if let Some(Command::Db(DbCommand::Repair)) = &args.command {
+        Database::repair(&config.blockchain_name)?;
+    }
+    let mut restored = None;
+    if let Some(Command::Restore { input, force }) = &args.command {
+        let db_path = Database::db_path(&config.blockchain_name);
+        restored = Some(backup::restore(&db_path, input, *force)?);
+    }
+    let node = NodeBuilder::new(config).build();
+    let blockchain = node.blockchain();
+    match args.command {
+        Some(Command::Rollback { to, force }) => {
+            let removed = if force {
+                blockchain.force_rollback_to(to)?
+            } else {
+                blockchain.rollback_to(to)?
+            };
+            println!(
+                "Removed {} blocks; the chain head is now block {}",
+                removed, to
+            );
+        }
+        Some(Command::Db(DbCommand::Compact)) => {
+            blockchain.compact_database()?;
+            println!("Compacted the database");
+        }
+        Some(Command::Db(DbCommand::Repair)) => {
+            let head = blockchain.rebuild_indexes()?;
+            println!("Rebuilt indexes; the chain head is block {}", head);
+        }
+        Some(Command::Db(DbCommand::Freeze { prune })) => {
+            let archive = era::installed_archive()
+                .ok_or("Set era_dir to the directory era files are written to")?;
+            let report = blockchain.freeze_blocks(&archive, prune)?;
+            for info in &report.frozen {
+                println!("Froze era {} into {}", info.era, info.file);
+            }
+            if prune {
+                blockchain.compact_database()?;
+                println!("Pruned {} archived blocks", report.pruned);
+            }
+        }
+        Some(Command::Db(DbCommand::ImportEra { dir })) => {
+            let archive = EraArchive::open(&dir, &node.config().chain_id)?;
+            let imported = blockchain.import_era(&archive, node.config().sync_write_batch_size)?;
+            println!("Imported {} blocks from {}", imported, dir.display());
+        }
+        Some(Command::Restore { .. }) => {
+            let summary = restored.expect("the backup is restored before the node is built");
+            println!("Restored {} entries", summary.entries);
+            // A resumed backup mixes entries read at two points in time.
+            if summary.resumed {
+                let head = blockchain.rebuild_indexes()?;
+                println!("Rebuilt indexes; the chain head is block {}", head);
+            }
+        }
+        Some(Command::Backup { .. }) => unreachable!("the backup runs before tracing is set up"),
+        Some(Command::Testnet { .. }) => unreachable!("the testnet runs before the node is built"),
+        Some(Command::TestVectors(_)) => unreachable!("test vectors need no node"),
+        Some(Command::Account(_)) => unreachable!("accounts need no node"),
+        None => node.run().await,
+    }
     Ok(())
 }
 
-fn initialize_blockchain(config: &AppConfig) -> Blockchain {
-    Blockchain::new(
-        config.blockchain_name.clone(),
-        config.author_public_key.clone(),
-        config.author_secret_key.clone(),
-        config.developer_mode.clone(),
-        config.authorities.clone(),
-        config.block_reward_amount,
-        config.ride_request_referrer_fee_percent,
-        config.ride_offer_referrer_fee_percent,
-    )
+/// The BIP39 passphrase from the next line of stdin when `prompt` is set, else none.
+/// Only the line ending is stripped, since spaces are part of a passphrase.
+fn read_passphrase(prompt: bool) -> std::io::Result<String> {
+    if !prompt {
+        return Ok(String::new());
+    }
+    eprintln!("Enter the BIP39 passphrase:");
+    let mut passphrase = String::new();
+    std::io::stdin().read_line(&mut passphrase)?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// The address in this network's format; the config lines take plain hex.
+fn print_keys(keys: &SignatureKeys, chain_id: &str) {
+    println!(
+        "address:    {}",
+        display_address(&keys.address_key, chain_id)
+    );
+    println!("public key: {}", keys.public_key);
+    println!("secret key: {}", keys.secret_key);
+    println!();
+    println!("author_public_key = \"{}\"", keys.address_key);
+    println!("author_secret_key = \"{}\"", keys.secret_key);
 }
@@ -24,6 +24,16 @@ pub struct AccountState {
     pub balance: u64,
 }
 
+/// An account as of a given block, as returned by the account query RPCs.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct AccountSnapshot {
+    pub address: String,
+    pub balance: u64,
+    /// Nonce of the account's last transaction at or below `block_index`; 0 if none.
+    pub nonce: u64,
+    pub block_index: usize,
+}
+
 impl AccountState {
     fn new_account_state(public_key: &str) -> AccountState {
         AccountState {
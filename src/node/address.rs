@@ -0,0 +1,152 @@
+//! The human-facing address format: `<chain_id>:0x<EIP-55 checksummed hex>`, e.g.
+//! `clutch-mainnet:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`. The prefix names the
+//! network an address was given out for and the checksum catches mistyped characters.
+//! State stays keyed by the plain lowercase hex of `canonical_account_address`.
+
+use sha3::{Digest, Keccak256};
+use std::fmt;
+
+/// A 20-byte account address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address([u8; 20]);
+
+impl Address {
+    pub fn from_bytes(bytes: [u8; 20]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// Plain hex, `0x` optional. All-lowercase or all-uppercase hex is taken as is; mixed
+    /// case must be a valid EIP-55 checksum.
+    pub fn from_hex(input: &str) -> Result<Self, String> {
+        let hex_part = input
+            .strip_prefix("0x")
+            .or_else(|| input.strip_prefix("0X"))
+            .unwrap_or(input);
+        if hex_part.len() != 40 || !hex_part.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Address {:?} must be 40 hex characters", input));
+        }
+        let mut bytes = [0u8; 20];
+        hex::decode_to_slice(hex_part.to_ascii_lowercase(), &mut bytes)
+            .map_err(|e| format!("Invalid address {:?}: {}", input, e))?;
+        let address = Self(bytes);
+
+        let mixed_case = hex_part.chars().any(|c| c.is_ascii_lowercase())
+            && hex_part.chars().any(|c| c.is_ascii_uppercase());
+        if mixed_case && address.to_checksum_hex()[2..] != *hex_part {
+            return Err(format!("Address {:?} has an invalid checksum", input));
+        }
+        Ok(address)
+    }
+
+    /// An address as a user or RPC client gives it: this network's `<chain_id>:0x…` form,
+    /// or plain hex. A prefix naming another network is rejected, so an address handed out
+    /// for one network can't be used on another by mistake.
+    pub fn parse(input: &str, chain_id: &str) -> Result<Self, String> {
+        let input = input.trim();
+        match input.rsplit_once(':') {
+            Some((network, _)) if network != chain_id => Err(format!(
+                "Address {} is for network {}, not {}",
+                input, network, chain_id
+            )),
+            Some((_, hex_part)) => Self::from_hex(hex_part),
+            None => Self::from_hex(input),
+        }
+    }
+
+    /// `0x` + lowercase hex, the form account state is keyed by.
+    pub fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.0))
+    }
+
+    /// `0x` + EIP-55 mixed-case hex: a letter is uppercase when the matching nibble of
+    /// Keccak-256 over the lowercase hex is 8 or more.
+    pub fn to_checksum_hex(&self) -> String {
+        let lower = hex::encode(self.0);
+        let hash = Keccak256::digest(lower.as_bytes());
+        let checksummed: String = lower
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        format!("0x{}", checksummed)
+    }
+
+    /// The human-facing form on network `chain_id`.
+    pub fn display(&self, chain_id: &str) -> String {
+        format!("{}:{}", chain_id, self.to_checksum_hex())
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum_hex())
+    }
+}
+
+/// `address` in the human-facing form of network `chain_id`, for logs and messages;
+/// returned unchanged if it isn't a valid address.
+pub fn display_address(address: &str, chain_id: &str) -> String {
+    Address::from_hex(address.trim())
+        .map(|address| address.display(chain_id))
+        .unwrap_or_else(|_| address.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn checksums_match_eip55() {
+        for checksummed in [
+            CHECKSUMMED,
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0x9858EfFD232B4033E47d90003D41EC34EcaEda94",
+        ] {
+            let address = Address::from_hex(&checksummed.to_lowercase()).unwrap();
+            assert_eq!(address.to_checksum_hex(), checksummed);
+            assert_eq!(address.to_hex(), checksummed.to_lowercase());
+            assert_eq!(Address::from_hex(checksummed), Ok(address));
+        }
+
+        let mistyped = CHECKSUMMED.replace("aAeb", "aaEb");
+        assert!(Address::from_hex(&mistyped).is_err());
+        assert!(Address::from_hex(&CHECKSUMMED.to_uppercase().replacen("0X", "0x", 1)).is_ok());
+        assert!(Address::from_hex(&CHECKSUMMED[2..]).is_ok());
+        assert!(Address::from_hex(&CHECKSUMMED[..40]).is_err());
+    }
+
+    #[test]
+    fn parsing_rejects_other_networks() {
+        let address = Address::from_hex(CHECKSUMMED).unwrap();
+        let displayed = address.display("clutch-mainnet");
+        assert_eq!(displayed, format!("clutch-mainnet:{}", CHECKSUMMED));
+
+        assert_eq!(Address::parse(&displayed, "clutch-mainnet"), Ok(address));
+        assert_eq!(Address::parse(CHECKSUMMED, "clutch-mainnet"), Ok(address));
+        let err = Address::parse(&displayed, "clutch-devnet").unwrap_err();
+        assert!(err.contains("clutch-mainnet"), "{}", err);
+        assert!(Address::parse(":0x00", "clutch-mainnet").is_err());
+    }
+
+    #[test]
+    fn invalid_addresses_are_displayed_as_given() {
+        assert_eq!(
+            display_address(&CHECKSUMMED.to_lowercase(), "clutch-devnet"),
+            format!("clutch-devnet:{}", CHECKSUMMED)
+        );
+        assert_eq!(display_address("genesis", "clutch-devnet"), "genesis");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::node::consensus::Consensus;
+use std::collections::HashSet;
 
 use super::{blocks::block::Block, time_utils::get_current_timespan};
 
@@ -17,7 +18,7 @@ impl Aura {
     }
 
     // Determine the slot number based on a given timestamp
-    fn slot_at_time(&self, timestamp: u64) -> u64 {
+    pub fn slot_at_time(&self, timestamp: u64) -> u64 {
         timestamp / self.step_duration
     }
 
@@ -28,9 +29,45 @@ impl Aura {
     }
 
     // Determine the author based on a given slot number
-    fn author_at_slot(&self, slot: u64) -> &String {
+    pub fn author_at_slot(&self, slot: u64) -> &String {
         &self.authorities[slot as usize % self.authorities.len()]
     }
+
+    /// Whether `block`'s slot is more than one slot past the current one. Such blocks are
+    /// rejected for now but become valid once our clock reaches their slot.
+    pub fn is_early(&self, block: &Block) -> bool {
+        self.slot_at_time(block.timestamp) > self.current_slot().saturating_add(1)
+    }
+
+    /// The slot that ended last and its authority, if `head` is older than that slot.
+    /// Only slots within one round of `head` count, so a node far behind or a chain that
+    /// has stopped doesn't report every slot since.
+    pub fn missed_slot(&self, head: &Block, now: u64) -> Option<(u64, &String)> {
+        let slot = self.slot_at_time(now).checked_sub(1)?;
+        let head_slot = self.slot_at_time(head.timestamp);
+        let missed = slot > head_slot && slot - head_slot <= self.authorities.len() as u64;
+        missed.then(|| (slot, self.author_at_slot(slot)))
+    }
+
+    /// Index of the newest final block, given `(index, author)` of blocks from the head
+    /// down. A block is final once it and the blocks on top of it were authored by more
+    /// than half of the authorities, since undoing it would take a majority to sign a
+    /// competing chain. `None` if no block in `blocks` reaches that.
+    pub fn finalized_index<'a>(
+        &self,
+        blocks: impl IntoIterator<Item = (usize, &'a str)>,
+    ) -> Option<usize> {
+        let mut signers = HashSet::new();
+        for (index, author) in blocks {
+            if self.authorities.iter().any(|authority| authority == author) {
+                signers.insert(author);
+            }
+            if signers.len() * 2 > self.authorities.len() {
+                return Some(index);
+            }
+        }
+        None
+    }
 }
 
 impl Consensus for Aura {
@@ -46,11 +83,11 @@ impl Consensus for Aura {
         // future timestamp that maps to a slot it owns and author out of turn. Only the
         // future is bounded (1 slot of clock-skew tolerance); past slots must stay valid
         // so historical blocks still import during sync.
-        let current_slot = self.current_slot();
-        if block_slot > current_slot.saturating_add(1) {
+        if self.is_early(block) {
             return Err(format!(
                 "Block slot {} is too far ahead of the current slot {}",
-                block_slot, current_slot
+                block_slot,
+                self.current_slot()
             ));
         }
 
@@ -85,12 +122,39 @@ mod tests {
         assert_eq!(aura.current_author(), expected_author);
     }
 
+    #[test]
+    fn block_is_final_once_a_majority_built_on_it() {
+        let aura = Aura::new(vec!["a".to_string(), "b".to_string(), "c".to_string()], 20);
+        assert_eq!(
+            aura.finalized_index([(9, "a"), (8, "a"), (7, "b")]),
+            Some(7)
+        );
+        assert_eq!(aura.finalized_index([(9, "a"), (8, "x"), (7, "a")]), None);
+
+        let solo = Aura::new(vec!["a".to_string()], 60);
+        assert_eq!(solo.finalized_index([(4, "a")]), Some(4));
+    }
+
+    #[test]
+    fn missed_slots_are_reported_for_one_round_after_the_head() {
+        let aura = Aura::new(vec!["a".to_string(), "b".to_string()], 30);
+        let mut head = Block::new_block(1, "0".to_string(), vec![]);
+        head.timestamp = 300; // slot 10
+
+        assert_eq!(aura.missed_slot(&head, 310), None, "slot 10 still running");
+        assert_eq!(aura.missed_slot(&head, 339), None, "the head is in slot 10");
+        assert_eq!(aura.missed_slot(&head, 360), Some((11, &"b".to_string())));
+        assert_eq!(aura.missed_slot(&head, 390), Some((12, &"a".to_string())));
+        assert_eq!(aura.missed_slot(&head, 420), None, "a round past the head");
+    }
+
     #[test]
     fn rejects_block_slot_far_in_future() {
         let aura = Aura::new(vec!["node_1".to_string(), "node_2".to_string()], 20);
         let mut block = Block::new_block(1, "0".to_string(), vec![]);
         block.timestamp = u64::MAX; // slot far beyond current_slot + 1
         block.author = "node_1".to_string();
+        assert!(aura.is_early(&block));
         assert!(aura.verify_block_author(&block).is_err());
     }
 }
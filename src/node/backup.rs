@@ -0,0 +1,517 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::node::blocks::block::Block;
+use crate::node::database::{Database, COLUMN_FAMILIES};
+use crate::node::time_utils::get_current_timespan;
+
+/// Backup format version, checked on restore.
+const BACKUP_VERSION: u32 = 1;
+const MAGIC: &[u8; 8] = b"CLUTCHBK";
+/// Raw entry bytes compressed together into one chunk.
+const CHUNK_BYTES: usize = 4 << 20;
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Chunk kinds. A backup is the magic bytes, then chunks of one kind byte, a little-endian
+/// u32 length and that many zstd-compressed bytes: a header, any number of entry chunks,
+/// and a summary that marks it complete.
+const HEADER_CHUNK: u8 = 0;
+const ENTRIES_CHUNK: u8 = 1;
+const SUMMARY_CHUNK: u8 = 2;
+
+/// What a backup was taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupHeader {
+    pub version: u32,
+    pub created: u64,
+    pub head_index: Option<usize>,
+    pub head_hash: Option<String>,
+}
+
+/// Written last; a backup without it is incomplete.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupSummary {
+    pub head_index: Option<usize>,
+    pub entries: u64,
+    /// Part of the entries were read after a `--resume`, at a later point than the rest,
+    /// so restoring it rebuilds the state from the blocks.
+    pub resumed: bool,
+}
+
+/// The last entry written to an interrupted backup file, and where its last complete
+/// chunk ends.
+#[derive(Debug, PartialEq, Eq)]
+struct ResumePoint {
+    end: u64,
+    header: BackupHeader,
+    entries: u64,
+    last: Option<(u8, Vec<u8>)>,
+    summary: Option<BackupSummary>,
+}
+
+struct ChunkWriter<W: Write> {
+    out: W,
+    chunk_bytes: usize,
+    buffer: Vec<u8>,
+    entries: u64,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    fn new(out: W, chunk_bytes: usize, entries: u64) -> Self {
+        ChunkWriter {
+            out,
+            chunk_bytes,
+            buffer: Vec::new(),
+            entries,
+        }
+    }
+
+    fn chunk(&mut self, kind: u8, data: &[u8]) -> Result<(), String> {
+        let compressed = zstd::encode_all(data, COMPRESSION_LEVEL)
+            .map_err(|e| format!("Failed to compress backup chunk: {}", e))?;
+        self.out
+            .write_all(&[kind])
+            .and_then(|_| self.out.write_all(&(compressed.len() as u32).to_le_bytes()))
+            .and_then(|_| self.out.write_all(&compressed))
+            .map_err(|e| format!("Failed to write backup: {}", e))
+    }
+
+    fn json_chunk(&mut self, kind: u8, value: &impl Serialize) -> Result<(), String> {
+        let data = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        self.chunk(kind, &data)
+    }
+
+    fn entry(&mut self, cf: u8, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.buffer.push(cf);
+        for bytes in [key, value] {
+            self.buffer
+                .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            self.buffer.extend_from_slice(bytes);
+        }
+        self.entries += 1;
+        if self.buffer.len() >= self.chunk_bytes {
+            self.flush_entries()?;
+        }
+        Ok(())
+    }
+
+    fn flush_entries(&mut self) -> Result<(), String> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let buffer = std::mem::take(&mut self.buffer);
+        self.chunk(ENTRIES_CHUNK, &buffer)
+    }
+
+    fn finish(mut self, summary: &BackupSummary) -> Result<W, String> {
+        self.flush_entries()?;
+        self.json_chunk(SUMMARY_CHUNK, summary)?;
+        self.out
+            .flush()
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+        Ok(self.out)
+    }
+}
+
+/// The next chunk, `None` at the end of the input. A chunk cut short is an error.
+fn read_chunk(input: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>, String> {
+    let mut kind = [0u8; 1];
+    match input.read(&mut kind) {
+        Ok(0) => return Ok(None),
+        Ok(_) => {}
+        Err(e) => return Err(format!("Failed to read backup: {}", e)),
+    }
+    let mut len = [0u8; 4];
+    input
+        .read_exact(&mut len)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+    let mut compressed = vec![0u8; u32::from_le_bytes(len) as usize];
+    input
+        .read_exact(&mut compressed)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+    let data = zstd::decode_all(compressed.as_slice())
+        .map_err(|e| format!("Failed to decompress backup chunk: {}", e))?;
+    Ok(Some((kind[0], data)))
+}
+
+/// The entries of an entries chunk as (column family, key, value).
+fn parse_entries(data: &[u8]) -> Result<Vec<(u8, &[u8], &[u8])>, String> {
+    fn take<'a>(data: &'a [u8], at: &mut usize) -> Result<&'a [u8], String> {
+        let len_end = *at + 4;
+        let len = data
+            .get(*at..len_end)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+            .ok_or_else(|| "Truncated backup entry".to_string())?;
+        let bytes = data
+            .get(len_end..len_end + len)
+            .ok_or_else(|| "Truncated backup entry".to_string())?;
+        *at = len_end + len;
+        Ok(bytes)
+    }
+
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        let cf = data[at];
+        at += 1;
+        let key = take(data, &mut at)?;
+        let value = take(data, &mut at)?;
+        entries.push((cf, key, value));
+    }
+    Ok(entries)
+}
+
+fn check_magic(input: &mut impl Read) -> Result<(), String> {
+    let mut magic = [0u8; 8];
+    input
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+    if &magic != MAGIC {
+        return Err("Not a clutch-node backup".to_string());
+    }
+    Ok(())
+}
+
+fn read_header(input: &mut impl Read) -> Result<BackupHeader, String> {
+    check_magic(input)?;
+    let header = match read_chunk(input)? {
+        Some((HEADER_CHUNK, data)) => serde_json::from_slice::<BackupHeader>(&data)
+            .map_err(|e| format!("Failed to read backup header: {}", e))?,
+        _ => return Err("Backup has no header".to_string()),
+    };
+    if header.version != BACKUP_VERSION {
+        return Err(format!(
+            "Backup version {} is not supported; this node reads version {}",
+            header.version, BACKUP_VERSION
+        ));
+    }
+    Ok(header)
+}
+
+/// Reads an interrupted backup up to its last complete chunk.
+fn resume_point(path: &Path) -> Result<ResumePoint, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut input = CountingReader {
+        inner: BufReader::new(file),
+        read: 0,
+    };
+    let header = read_header(&mut input)?;
+    let mut point = ResumePoint {
+        end: input.read,
+        header,
+        entries: 0,
+        last: None,
+        summary: None,
+    };
+    // A cut-off chunk fails to read; everything before it is kept.
+    while let Ok(Some((kind, data))) = read_chunk(&mut input) {
+        match kind {
+            ENTRIES_CHUNK => {
+                let entries = parse_entries(&data)?;
+                point.entries += entries.len() as u64;
+                point.last = entries.last().map(|(cf, key, _)| (*cf, key.to_vec()));
+            }
+            SUMMARY_CHUNK => {
+                point.summary = serde_json::from_slice(&data).ok();
+            }
+            _ => return Err(format!("Unexpected chunk {} in backup", kind)),
+        }
+        point.end = input.read;
+    }
+    Ok(point)
+}
+
+struct CountingReader<R: Read> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// Writes every entry of `db` after `after` (or all of them) to `writer`.
+fn write_entries<W: Write>(
+    db: &Database,
+    writer: &mut ChunkWriter<W>,
+    after: Option<&(u8, Vec<u8>)>,
+) -> Result<(), String> {
+    for (cf, cf_name) in COLUMN_FAMILIES.iter().enumerate() {
+        let cf = cf as u8;
+        let from: &[u8] = match after {
+            Some((after_cf, _)) if *after_cf > cf => continue,
+            Some((after_cf, key)) if *after_cf == cf => key,
+            _ => &[],
+        };
+        db.for_each_from(cf_name, from, |key, value| {
+            if after.is_some_and(|(after_cf, after_key)| *after_cf == cf && after_key == key) {
+                return Ok(());
+            }
+            writer.entry(cf, key, value)
+        })?;
+    }
+    Ok(())
+}
+
+/// Backs up the database at `db_path` to `output`, a file or `-` for stdout. The database
+/// is read through a secondary instance, so the node can keep running and every entry is
+/// read at the same point in time. `resume` continues an interrupted backup file after
+/// its last complete chunk instead of refusing to overwrite it.
+pub fn backup(db_path: &str, output: &str, resume: bool) -> Result<BackupSummary, String> {
+    let secondary_path = format!("{}.backup-{}", db_path, std::process::id());
+    let result = Database::open_secondary(db_path, &secondary_path)
+        .and_then(|db| backup_from(&db, output, resume, CHUNK_BYTES));
+    let _ = fs::remove_dir_all(&secondary_path);
+    result
+}
+
+fn backup_from(
+    db: &Database,
+    output: &str,
+    resume: bool,
+    chunk_bytes: usize,
+) -> Result<BackupSummary, String> {
+    let head = Block::get_latest_block(db)?;
+    let header = BackupHeader {
+        version: BACKUP_VERSION,
+        created: get_current_timespan(),
+        head_index: head.as_ref().map(|head| head.index),
+        head_hash: head.map(|head| head.hash),
+    };
+
+    if output == "-" {
+        if resume {
+            return Err("Only a backup written to a file can be resumed".to_string());
+        }
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        stdout
+            .write_all(MAGIC)
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+        let mut writer = ChunkWriter::new(stdout, chunk_bytes, 0);
+        writer.json_chunk(HEADER_CHUNK, &header)?;
+        write_entries(db, &mut writer, None)?;
+        let summary = BackupSummary {
+            head_index: header.head_index,
+            entries: writer.entries,
+            resumed: false,
+        };
+        writer.finish(&summary)?;
+        return Ok(summary);
+    }
+
+    let path = Path::new(output);
+    let point = match (path.exists(), resume) {
+        (true, false) => {
+            return Err(format!(
+                "{} exists; pass --resume to continue an interrupted backup",
+                output
+            ))
+        }
+        (true, true) => Some(resume_point(path)?),
+        (false, _) => None,
+    };
+    if let Some(summary) = point.as_ref().and_then(|point| point.summary.clone()) {
+        return Ok(summary);
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(point.is_some())
+        .truncate(point.is_none())
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", output, e))?;
+    let (mut writer, resumed, head_index) = match &point {
+        Some(point) => {
+            // Drop the chunk the interruption cut off.
+            file.set_len(point.end)
+                .map_err(|e| format!("Failed to truncate {}: {}", output, e))?;
+            (
+                ChunkWriter::new(BufWriter::new(file), chunk_bytes, point.entries),
+                true,
+                point.header.head_index,
+            )
+        }
+        None => {
+            let mut out = BufWriter::new(file);
+            out.write_all(MAGIC)
+                .map_err(|e| format!("Failed to write backup: {}", e))?;
+            let mut writer = ChunkWriter::new(out, chunk_bytes, 0);
+            writer.json_chunk(HEADER_CHUNK, &header)?;
+            (writer, false, header.head_index)
+        }
+    };
+    write_entries(
+        db,
+        &mut writer,
+        point.as_ref().and_then(|point| point.last.as_ref()),
+    )?;
+    let summary = BackupSummary {
+        head_index,
+        entries: writer.entries,
+        resumed,
+    };
+    writer
+        .finish(&summary)?
+        .into_inner()
+        .map_err(|e| format!("Failed to write backup: {}", e))?
+        .sync_all()
+        .map_err(|e| format!("Failed to write backup: {}", e))?;
+    Ok(summary)
+}
+
+/// Restores a backup from `input`, a file or `-` for stdin, into a new database at
+/// `db_path`; `force` replaces one that exists. A backup that fails to restore, or turns
+/// out incomplete, leaves no database behind.
+pub fn restore(db_path: &str, input: &str, force: bool) -> Result<BackupSummary, String> {
+    if Path::new(db_path).exists() {
+        if !force {
+            return Err(format!(
+                "{} exists; pass --force to replace it with the backup",
+                db_path
+            ));
+        }
+        Database::destroy(db_path)?;
+    }
+    let mut db = Database::open(db_path);
+    let restored = if input == "-" {
+        restore_into(&db, &mut BufReader::new(io::stdin().lock()))
+    } else {
+        File::open(input)
+            .map_err(|e| format!("Failed to open {}: {}", input, e))
+            .and_then(|file| restore_into(&db, &mut BufReader::new(file)))
+    };
+    db.close();
+    if restored.is_err() {
+        let _ = Database::destroy(db_path);
+    }
+    restored
+}
+
+fn restore_into(db: &Database, input: &mut impl Read) -> Result<BackupSummary, String> {
+    read_header(input)?;
+    let mut entries = 0;
+    loop {
+        match read_chunk(input)? {
+            Some((ENTRIES_CHUNK, data)) => {
+                let parsed = parse_entries(&data)?;
+                let mut operations = Vec::with_capacity(parsed.len());
+                for (cf, key, value) in parsed {
+                    let cf_name = COLUMN_FAMILIES
+                        .get(cf as usize)
+                        .ok_or_else(|| format!("Unknown column family {} in backup", cf))?;
+                    operations.push((*cf_name, key, Some(value)));
+                }
+                entries += operations.len() as u64;
+                db.write(operations)?;
+            }
+            Some((SUMMARY_CHUNK, data)) => {
+                let summary = serde_json::from_slice::<BackupSummary>(&data)
+                    .map_err(|e| format!("Failed to read backup summary: {}", e))?;
+                if summary.entries != entries {
+                    return Err(format!(
+                        "Backup holds {} entries but its summary counts {}",
+                        entries, summary.entries
+                    ));
+                }
+                return Ok(summary);
+            }
+            Some((kind, _)) => return Err(format!("Unexpected chunk {} in backup", kind)),
+            None => return Err("Backup is incomplete: it ends before its summary".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_backup(path: &Path, entries: &[(u8, &[u8], &[u8])], complete: bool) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        out.write_all(MAGIC).unwrap();
+        let mut writer = ChunkWriter::new(out, 8, 0);
+        let header = BackupHeader {
+            version: BACKUP_VERSION,
+            created: 0,
+            head_index: Some(3),
+            head_hash: None,
+        };
+        writer.json_chunk(HEADER_CHUNK, &header).unwrap();
+        for (cf, key, value) in entries {
+            writer.entry(*cf, key, value).unwrap();
+        }
+        if complete {
+            let summary = BackupSummary {
+                head_index: Some(3),
+                entries: writer.entries,
+                resumed: false,
+            };
+            writer.finish(&summary).unwrap();
+        } else {
+            writer.out.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn an_interrupted_backup_resumes_after_its_last_complete_chunk() {
+        let path = std::env::temp_dir().join(format!("clutch-backup-test-{}", std::process::id()));
+        // Each entry fills a chunk of its own.
+        let entries: [(u8, &[u8], &[u8]); 3] = [
+            (0, b"block_1", b"one"),
+            (0, b"block_2", b"two"),
+            (1, b"balance", b"three"),
+        ];
+        write_backup(&path, &entries, false);
+        let full_len = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(full_len - 3)
+            .unwrap();
+
+        let point = resume_point(&path).unwrap();
+        assert_eq!(point.entries, 2);
+        assert_eq!(point.last, Some((0, b"block_2".to_vec())));
+        assert_eq!(point.header.head_index, Some(3));
+        assert!(point.summary.is_none());
+        assert!(point.end < full_len - 3);
+
+        write_backup(&path, &entries, true);
+        let point = resume_point(&path).unwrap();
+        assert_eq!(point.entries, 3);
+        assert_eq!(point.summary.map(|summary| summary.entries), Some(3));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_backup_without_its_summary_is_refused() {
+        let mut data = MAGIC.to_vec();
+        let mut writer = ChunkWriter::new(&mut data, CHUNK_BYTES, 0);
+        let header = BackupHeader {
+            version: BACKUP_VERSION,
+            created: 0,
+            head_index: None,
+            head_hash: None,
+        };
+        writer.json_chunk(HEADER_CHUNK, &header).unwrap();
+        writer.entry(0, b"key", b"value").unwrap();
+        writer.flush_entries().unwrap();
+
+        let mut input = data.as_slice();
+        assert_eq!(read_header(&mut input), Ok(header));
+        let (kind, chunk) = read_chunk(&mut input).unwrap().unwrap();
+        assert_eq!(kind, ENTRIES_CHUNK);
+        assert_eq!(
+            parse_entries(&chunk).unwrap(),
+            vec![(0, &b"key"[..], &b"value"[..])]
+        );
+        assert_eq!(read_chunk(&mut input), Ok(None));
+    }
+}
@@ -13,6 +13,8 @@ pub enum BalanceEffectKind {
     ReferrerOfferFee,
     RideCancelRefund,
     BlockReward,
+    Stake,
+    Unstake,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -199,6 +201,23 @@ pub fn load_block_effects(db: &Database, block_height: u64) -> Vec<StoredBalance
     }
 }
 
+/// Net balance change of `address` from blocks above `block_height`. The account index is
+/// ordered newest block first, so the scan stops at the first older effect.
+pub fn sum_account_deltas_after(db: &Database, address: &str, block_height: u64) -> i128 {
+    let canonical = canonical_account_address(address);
+    let prefix = format!("account_effect_{}_", canonical);
+    let entries = db
+        .prefix_scan("state", prefix.as_bytes())
+        .unwrap_or_default();
+
+    entries
+        .into_iter()
+        .filter_map(|(_, value)| serde_json::from_slice::<StoredBalanceEffect>(&value).ok())
+        .take_while(|stored| stored.block_height > block_height)
+        .map(|stored| stored.effect.delta as i128)
+        .sum()
+}
+
 pub fn get_account_balance_effects(
     db: &Database,
     address: &str,
@@ -0,0 +1,501 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::node::rlp_encoding::RlpCodec;
+use crate::node::signature_keys::SignatureKeys;
+use crate::node::signer::BlockSigner;
+
+/// Heights past the one being decided whose votes are kept, so a node slightly behind
+/// can still count them.
+const MAX_HEIGHTS_AHEAD: u64 = 16;
+/// Rounds past the current one whose votes are kept.
+const MAX_ROUNDS_AHEAD: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VoteKind {
+    /// The voter holds `block_hash` at the height and would finalize it.
+    Prepare,
+    /// The voter saw a quorum prepare `block_hash` in the round.
+    Commit,
+    /// The voter gave up on the round before it; `block_hash` is empty.
+    RoundChange,
+}
+
+impl VoteKind {
+    pub fn as_code(&self) -> u8 {
+        match self {
+            VoteKind::Prepare => 0x01,
+            VoteKind::Commit => 0x02,
+            VoteKind::RoundChange => 0x03,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x01 => Some(VoteKind::Prepare),
+            0x02 => Some(VoteKind::Commit),
+            0x03 => Some(VoteKind::RoundChange),
+            _ => None,
+        }
+    }
+}
+
+/// A validator's vote on the block at `height`, gossiped as `GossipMessageType::Vote`
+/// and signed like a block, by `voter`'s authority key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpCodec)]
+pub struct Vote {
+    pub kind: VoteKind,
+    pub height: u64,
+    pub round: u64,
+    pub block_hash: String,
+    pub voter: String,
+    pub signature_r: String,
+    pub signature_s: String,
+    #[rlp(as = "u64")]
+    pub signature_v: i32,
+}
+
+impl Vote {
+    fn unsigned(kind: VoteKind, height: u64, round: u64, block_hash: String, voter: &str) -> Self {
+        Vote {
+            kind,
+            height,
+            round,
+            block_hash,
+            voter: voter.to_string(),
+            signature_r: String::new(),
+            signature_s: String::new(),
+            signature_v: 0,
+        }
+    }
+
+    fn signing_data(&self) -> Vec<u8> {
+        format!(
+            "clutch-vote/{}/{}/{}/{}",
+            self.kind.as_code(),
+            self.height,
+            self.round,
+            self.block_hash
+        )
+        .into_bytes()
+    }
+
+    pub fn sign_with(&mut self, signer: &BlockSigner) -> Result<(), String> {
+        let (r, s, v) = signer.sign(&self.voter, &self.signing_data())?;
+        self.signature_r = r;
+        self.signature_s = s;
+        self.signature_v = v;
+        Ok(())
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        let data = self.signing_data();
+        match SignatureKeys::verify(
+            &self.voter,
+            &data,
+            &self.signature_r,
+            &self.signature_s,
+            self.signature_v,
+        ) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!(
+                "{:?} vote for height {} is not signed by {}",
+                self.kind, self.height, self.voter
+            )),
+            Err(e) => Err(format!("Failed to verify vote: {}", e)),
+        }
+    }
+}
+
+/// Votes cast at one height and round.
+#[derive(Debug, Default)]
+struct RoundVotes {
+    prepares: HashMap<String, HashSet<String>>,
+    commits: HashMap<String, HashSet<String>>,
+    round_changes: HashSet<String>,
+}
+
+impl RoundVotes {
+    /// Counts `vote` unless its voter already cast one of its kind here, for any block.
+    fn insert(&mut self, vote: &Vote) -> bool {
+        let by_hash = match vote.kind {
+            VoteKind::Prepare => &mut self.prepares,
+            VoteKind::Commit => &mut self.commits,
+            VoteKind::RoundChange => return self.round_changes.insert(vote.voter.clone()),
+        };
+        if by_hash.values().any(|voters| voters.contains(&vote.voter)) {
+            return false;
+        }
+        by_hash
+            .entry(vote.block_hash.clone())
+            .or_default()
+            .insert(vote.voter.clone())
+    }
+}
+
+fn quorum_hash(by_hash: &HashMap<String, HashSet<String>>, quorum: usize) -> Option<&String> {
+    by_hash
+        .iter()
+        .find(|(_, voters)| voters.len() >= quorum)
+        .map(|(hash, _)| hash)
+}
+
+/// Two-phase BFT finality on top of Aura. Aura keeps choosing who authors each block;
+/// the authorities then vote on the block at each height in rounds: a validator
+/// prepares the block it holds there, commits once a quorum prepared the same block in
+/// the round, and the block is final once a quorum committed to it. A round that ends
+/// without a commit quorum, because its block never came (the proposer is offline) or
+/// the votes split between competing blocks, is abandoned after `round_timeout`: each
+/// validator asks to change round and, once a quorum asked, prepares again in the next
+/// round for whatever block it then holds. A validator that committed to a block
+/// prepares only that block in later rounds of the height.
+///
+/// Votes from non-authorities are refused; signatures are checked before votes get
+/// here (`Blockchain::add_vote`). Our own votes are left unsigned in an outbox that
+/// `tick` empties.
+#[derive(Debug)]
+pub struct BftEngine {
+    authorities: Vec<String>,
+    validator: String,
+    round_timeout: u64,
+    /// Height being decided; 0 until `catch_up` first runs.
+    height: u64,
+    round: u64,
+    round_started: u64,
+    locked: Option<String>,
+    votes: BTreeMap<(u64, u64), RoundVotes>,
+    cast: HashSet<(VoteKind, u64, u64)>,
+    outbox: Vec<Vote>,
+    finalized: Option<(u64, String)>,
+}
+
+impl BftEngine {
+    pub fn new(authorities: Vec<String>, validator: String, round_timeout_secs: u64) -> Self {
+        BftEngine {
+            authorities,
+            validator,
+            round_timeout: round_timeout_secs,
+            height: 0,
+            round: 0,
+            round_started: 0,
+            locked: None,
+            votes: BTreeMap::new(),
+            cast: HashSet::new(),
+            outbox: Vec::new(),
+            finalized: None,
+        }
+    }
+
+    /// Votes needed to commit, finalize or change round: more than two thirds of the
+    /// authorities, so any two quorums share an honest one while fewer than a third are
+    /// faulty. Tolerating one faulty authority takes four.
+    pub fn quorum(&self) -> usize {
+        let authorities = self.authorities.len();
+        authorities - authorities.saturating_sub(1) / 3
+    }
+
+    /// Switches to the authorities of the height being decided, when an epoch changes them.
+    pub fn set_authorities(&mut self, authorities: &[String]) {
+        if self.authorities != authorities {
+            self.authorities = authorities.to_vec();
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Height and hash of the newest block a quorum committed to.
+    pub fn finalized(&self) -> Option<&(u64, String)> {
+        self.finalized.as_ref()
+    }
+
+    /// Starts deciding at `head` if nothing is being decided yet, or if what is lies so
+    /// far below `head` that its votes are gone.
+    pub fn catch_up(&mut self, head: u64, now: u64) {
+        if self.height == 0 || self.height + MAX_HEIGHTS_AHEAD < head {
+            self.begin_height(head.max(1), now);
+        }
+    }
+
+    /// Counts a vote whose signature was checked. Returns whether it was new and for a
+    /// height still being decided, i.e. worth relaying.
+    pub fn on_vote(&mut self, vote: &Vote, now: u64) -> Result<bool, String> {
+        if !self.authorities.contains(&vote.voter) {
+            return Err(format!(
+                "Vote from {}, which is not an authority",
+                vote.voter
+            ));
+        }
+        if self.height == 0
+            || vote.height < self.height
+            || vote.height > self.height + MAX_HEIGHTS_AHEAD
+            || vote.round > self.round + MAX_ROUNDS_AHEAD
+        {
+            return Ok(false);
+        }
+        if !self
+            .votes
+            .entry((vote.height, vote.round))
+            .or_default()
+            .insert(vote)
+        {
+            return Ok(false);
+        }
+        self.advance(now);
+        Ok(true)
+    }
+
+    /// Prepares `candidate`, the block we hold at `height()`, asks to change round if
+    /// this one timed out, and returns every vote of ours cast since the last tick.
+    pub fn tick(&mut self, candidate: Option<&str>, now: u64) -> Vec<Vote> {
+        if self.height > 0 {
+            if let Some(hash) = self.locked.clone().or(candidate.map(str::to_string)) {
+                self.cast(VoteKind::Prepare, self.round, hash);
+            }
+            if now >= self.round_started + self.round_timeout {
+                self.cast(VoteKind::RoundChange, self.round + 1, String::new());
+            }
+            self.advance(now);
+        }
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn begin_height(&mut self, height: u64, now: u64) {
+        self.height = height;
+        self.round = 0;
+        self.round_started = now;
+        self.locked = None;
+        self.votes = self.votes.split_off(&(height, 0));
+        self.cast
+            .retain(|(_, cast_height, _)| *cast_height >= height);
+    }
+
+    /// Casts our vote unless we aren't an authority or already cast this one.
+    fn cast(&mut self, kind: VoteKind, round: u64, block_hash: String) -> bool {
+        if !self.authorities.contains(&self.validator)
+            || !self.cast.insert((kind, self.height, round))
+        {
+            return false;
+        }
+        let vote = Vote::unsigned(kind, self.height, round, block_hash, &self.validator);
+        self.votes
+            .entry((self.height, round))
+            .or_default()
+            .insert(&vote);
+        self.outbox.push(vote);
+        true
+    }
+
+    /// Acts on every quorum the counted votes reach.
+    fn advance(&mut self, now: u64) {
+        let quorum = self.quorum();
+        loop {
+            let decided = self.votes.iter().rev().find_map(|(&(height, _), votes)| {
+                quorum_hash(&votes.commits, quorum).map(|hash| (height, hash.clone()))
+            });
+            if let Some((height, hash)) = decided {
+                self.finalized = Some((height, hash));
+                self.begin_height(height + 1, now);
+                continue;
+            }
+
+            let height = self.height;
+            let next_round = self
+                .votes
+                .range((height, self.round + 1)..=(height, u64::MAX))
+                .rev()
+                .find(|(_, votes)| votes.round_changes.len() >= quorum)
+                .map(|(&(_, round), _)| round);
+            if let Some(round) = next_round {
+                self.round = round;
+                self.round_started = now;
+            }
+
+            let prepared = self
+                .votes
+                .get(&(height, self.round))
+                .and_then(|votes| quorum_hash(&votes.prepares, quorum))
+                .filter(|hash| self.locked.as_ref().is_none_or(|locked| locked == *hash))
+                .cloned();
+            if let Some(hash) = prepared {
+                if self.cast(VoteKind::Commit, self.round, hash.clone()) {
+                    self.locked = Some(hash);
+                    continue;
+                }
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::rlp_encoding::{decode, encode};
+
+    fn authorities() -> Vec<String> {
+        ["a", "b", "c", "d"].iter().map(|a| a.to_string()).collect()
+    }
+
+    fn engine() -> BftEngine {
+        let mut engine = BftEngine::new(authorities(), "a".to_string(), 10);
+        engine.catch_up(5, 100);
+        engine
+    }
+
+    fn vote(kind: VoteKind, round: u64, hash: &str, voter: &str) -> Vote {
+        Vote::unsigned(kind, 5, round, hash.to_string(), voter)
+    }
+
+    fn kinds(votes: &[Vote]) -> Vec<(VoteKind, u64)> {
+        votes.iter().map(|vote| (vote.kind, vote.round)).collect()
+    }
+
+    #[test]
+    fn a_block_committed_by_a_quorum_is_final() {
+        let mut engine = engine();
+        assert_eq!(engine.quorum(), 3);
+        assert_eq!(
+            kinds(&engine.tick(Some("h5"), 100)),
+            vec![(VoteKind::Prepare, 0)]
+        );
+
+        assert_eq!(
+            engine.on_vote(&vote(VoteKind::Prepare, 0, "h5", "b"), 101),
+            Ok(true)
+        );
+        assert!(engine.tick(Some("h5"), 101).is_empty());
+        engine
+            .on_vote(&vote(VoteKind::Prepare, 0, "h5", "c"), 101)
+            .unwrap();
+        assert_eq!(
+            kinds(&engine.tick(Some("h5"), 101)),
+            vec![(VoteKind::Commit, 0)]
+        );
+
+        engine
+            .on_vote(&vote(VoteKind::Commit, 0, "h5", "b"), 102)
+            .unwrap();
+        assert_eq!(engine.finalized(), None);
+        engine
+            .on_vote(&vote(VoteKind::Commit, 0, "h5", "c"), 102)
+            .unwrap();
+        assert_eq!(engine.finalized(), Some(&(5, "h5".to_string())));
+        assert_eq!((engine.height(), engine.round()), (6, 0));
+
+        assert_eq!(
+            engine.on_vote(&vote(VoteKind::Commit, 0, "h5", "d"), 103),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn a_round_without_a_block_times_out() {
+        let mut engine = engine();
+        assert!(engine.tick(None, 105).is_empty());
+        assert_eq!(
+            kinds(&engine.tick(None, 110)),
+            vec![(VoteKind::RoundChange, 1)]
+        );
+        assert!(engine.tick(None, 111).is_empty());
+
+        engine
+            .on_vote(&vote(VoteKind::RoundChange, 1, "", "b"), 112)
+            .unwrap();
+        engine
+            .on_vote(&vote(VoteKind::RoundChange, 1, "", "c"), 112)
+            .unwrap();
+        assert_eq!(engine.round(), 1);
+
+        // The next authority's block arrives and is prepared in the new round.
+        assert_eq!(
+            kinds(&engine.tick(Some("h5"), 113)),
+            vec![(VoteKind::Prepare, 1)]
+        );
+        assert!(engine.tick(Some("h5"), 121).is_empty());
+        assert_eq!(
+            kinds(&engine.tick(Some("h5"), 122)),
+            vec![(VoteKind::RoundChange, 2)]
+        );
+    }
+
+    #[test]
+    fn a_locked_validator_prepares_only_its_block() {
+        let mut engine = engine();
+        engine.tick(Some("x"), 100);
+        engine
+            .on_vote(&vote(VoteKind::Prepare, 0, "x", "b"), 101)
+            .unwrap();
+        engine
+            .on_vote(&vote(VoteKind::Prepare, 0, "x", "c"), 101)
+            .unwrap();
+        assert_eq!(
+            kinds(&engine.tick(Some("x"), 101)),
+            vec![(VoteKind::Commit, 0)]
+        );
+        for voter in ["b", "c", "d"] {
+            engine
+                .on_vote(&vote(VoteKind::RoundChange, 1, "", voter), 102)
+                .unwrap();
+        }
+        assert_eq!(engine.round(), 1);
+
+        let votes = engine.tick(Some("y"), 103);
+        assert_eq!(kinds(&votes), vec![(VoteKind::Prepare, 1)]);
+        assert_eq!(votes[0].block_hash, "x");
+    }
+
+    #[test]
+    fn outsiders_and_second_votes_are_not_counted() {
+        let mut engine = engine();
+        assert!(engine
+            .on_vote(&vote(VoteKind::Prepare, 0, "h5", "z"), 100)
+            .is_err());
+        assert_eq!(
+            engine.on_vote(&vote(VoteKind::Prepare, 0, "h5", "b"), 100),
+            Ok(true)
+        );
+        assert_eq!(
+            engine.on_vote(&vote(VoteKind::Prepare, 0, "other", "b"), 100),
+            Ok(false)
+        );
+        let mut far = vote(VoteKind::Prepare, 0, "h", "b");
+        far.height = 5 + MAX_HEIGHTS_AHEAD + 1;
+        assert_eq!(engine.on_vote(&far, 100), Ok(false));
+    }
+
+    #[test]
+    fn a_node_behind_jumps_to_the_newest_final_height() {
+        let mut engine = engine();
+        for voter in ["b", "c", "d"] {
+            let mut commit = vote(VoteKind::Commit, 0, "h8", voter);
+            commit.height = 8;
+            engine.on_vote(&commit, 100).unwrap();
+        }
+        assert_eq!(engine.finalized(), Some(&(8, "h8".to_string())));
+        assert_eq!(engine.height(), 9);
+    }
+
+    #[test]
+    fn signed_votes_verify_and_round_trip() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let signer = BlockSigner::Local {
+            secret_key: keys.secret_key.clone(),
+        };
+        let mut signed = vote(VoteKind::Commit, 2, "h5", &keys.address_key);
+        signed.sign_with(&signer).unwrap();
+        assert_eq!(signed.verify(), Ok(()));
+
+        let decoded: Vote = decode(&encode(&signed)).unwrap();
+        assert_eq!(decoded, signed);
+
+        let mut moved = signed;
+        moved.round = 3;
+        assert!(moved.verify().is_err());
+    }
+}
@@ -1,31 +1,98 @@
-use tracing::{error, info};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
 
-use super::blocks::block::Block;
+use super::blocks::bad_blocks::{BadBlock, BadBlocks};
+use super::blocks::block::{Block, BlockIter};
+use super::blocks::block_cache::BlockCache;
+use super::blocks::block_headers::BlockHeader;
+use super::blocks::block_limits::BlockLimits;
+use super::blocks::block_tag::{BlockTag, NamedBlock};
+use super::blocks::era::{self, EraArchive, EraInfo};
+use super::blocks::state_diff::StateDiff;
 use super::configuration::AppConfig;
 use super::consensus::Consensus;
-use super::p2p_server::handshake::Handshake;
-use super::transactions::transaction_pool::TransactionPool;
-use crate::node::account_state::AccountState;
+use super::firehose::FirehoseCursor;
+use super::hardfork::{Hardfork, HardforkSchedule};
+use super::p2p_server::handshake::{Handshake, HandshakeSnapshot};
+use super::p2p_server::sync_pipeline::SyncCheckpoint;
+use super::plugin::{NodePlugin, PluginRegistry};
+use super::reorg::{ReorgPolicy, ReorgSummary};
+use super::webhook::{NodeEvent, WebhookNotifier};
+use super::transactions::transaction_pool::{
+    EvictionReason, PoolAdmission, PoolContent, PoolExpiry, TransactionPool,
+};
+use crate::node::account_state::{AccountSnapshot, AccountState};
 use crate::node::aura::Aura;
-use crate::node::balance_effect::{get_account_balance_effects, load_block_effects, load_tx_effects, StoredBalanceEffect};
-use crate::node::database::Database;
+use crate::node::balance_effect::{get_account_balance_effects, load_block_effects, load_tx_effects, sum_account_deltas_after, StoredBalanceEffect};
+use crate::node::bft::{BftEngine, Vote};
+use crate::node::database::{Database, DatabaseReader};
+use crate::node::disk_monitor;
+use crate::node::duties::{upcoming_duties, DutyReport, DutyTracker};
+use crate::node::error::{NodeError, StorageError};
 use crate::node::file_utils::write_to_file;
+use crate::node::metric::TX_POOL_EVICTED;
 use crate::node::node_services::NodeServices;
+use crate::node::signer::BlockSigner;
+use crate::node::time_utils::get_current_timespan;
+use crate::node::transactions::address::canonical_account_address;
+use crate::node::transactions::receipt::{TransactionReceipt, TxLocation};
 use crate::node::transactions::ride_acceptance::{AvailableActiveTrip, AvailableRecentTrip, RideAcceptance};
 use crate::node::transactions::ride_offer::{AvailableRideOffer, RideOffer};
 use crate::node::transactions::ride_request::{AvailableRideRequest, MapBounds, RideRequest};
+use crate::node::transactions::stake::validator_registry;
+use crate::node::transactions::trace::{BlockTrace, TransactionTrace};
 use crate::node::transactions::transaction::Transaction;
+use crate::node::validator_set::{ValidatorSchedule, ValidatorsReport};
 
 pub struct Blockchain {
     pub name: String,
+    chain_id: String,
     db: Database,
     developer_mode: bool,
-    consensus: Aura,
+    validators: ValidatorSchedule,
     author_public_key: String,
-    author_secret_key: String,
+    signer: BlockSigner,
     block_reward_amount: u64,
     ride_request_referrer_fee_percent: u8,
     ride_offer_referrer_fee_percent: u8,
+    persist_state_diffs: bool,
+    hardforks: HardforkSchedule,
+    block_limits: BlockLimits,
+    pool_expiry: PoolExpiry,
+    pool_capacity: usize,
+    reorg_policy: ReorgPolicy,
+    notifier: WebhookNotifier,
+    cache: Arc<BlockCache>,
+    bad_blocks: BadBlocks,
+    duties: DutyTracker,
+    bft: Option<Mutex<BftEngine>>,
+    plugins: PluginRegistry,
+    handshake: HandshakeSnapshot,
+}
+
+/// Default LRU sizes used until `with_block_cache` is called.
+const DEFAULT_HEADER_CACHE_SIZE: usize = 1024;
+const DEFAULT_BODY_CACHE_SIZE: usize = 256;
+const DEFAULT_BLOCK_HASH_CACHE_SIZE: usize = 4096;
+/// Rejected blocks remembered for `debug_getBadBlocks` and to turn repeats away.
+const BAD_BLOCK_CACHE_SIZE: usize = 256;
+
+/// Network name used until `with_chain_id` is called, as in `AppConfig`.
+const DEFAULT_CHAIN_ID: &str = "clutch-devnet";
+
+const SYNC_CHECKPOINT_KEY: &[u8] = b"sync_checkpoint";
+const FIREHOSE_CURSOR_KEY: &[u8] = b"firehose_cursor";
+
+/// How many blocks below the head the `finalized` tag looks before settling for genesis.
+const FINALITY_SEARCH_DEPTH: usize = 256;
+
+/// A `block` column family entry that `rewrite_chain` keeps; see `kept_block_key`.
+enum BlockKey {
+    /// The stored body of this block.
+    Body(usize),
+    /// A header or transaction index entry.
+    Index,
 }
 
 impl Blockchain {
@@ -40,23 +107,253 @@ impl Blockchain {
         ride_offer_referrer_fee_percent: u8,
     ) -> Blockchain {
         let db = Database::new_db(&name);
-        let step_duration = 60 / authorities.len() as u64;
+        Self::open(
+            db,
+            name,
+            author_public_key,
+            author_secret_key,
+            developer_mode,
+            authorities,
+            block_reward_amount,
+            ride_request_referrer_fee_percent,
+            ride_offer_referrer_fee_percent,
+        )
+    }
+
+    /// The chain `config` describes, stored in `db` rather than at the default path.
+    pub fn from_config(config: &AppConfig, db: Database) -> Blockchain {
+        let blockchain = Self::open(
+            db,
+            config.blockchain_name.clone(),
+            config.author_public_key.clone(),
+            config.author_secret_key.clone(),
+            config.developer_mode,
+            config.authorities.clone(),
+            config.block_reward_amount,
+            config.ride_request_referrer_fee_percent,
+            config.ride_offer_referrer_fee_percent,
+        )
+        .with_block_cache(BlockCache::new(
+            config.header_cache_size,
+            config.body_cache_size,
+            config.block_hash_cache_size,
+        ))
+        .with_state_diffs(config.persist_state_diffs)
+        .with_hardforks(config.hardforks.clone())
+        .with_block_limits(BlockLimits {
+            max_size_bytes: config.max_block_size_bytes,
+            gas_limit: config.block_gas_limit,
+        })
+        .with_pool_expiry(PoolExpiry {
+            max_age_secs: config.tx_pool_ttl_secs,
+            max_blocks: config.tx_pool_ttl_blocks,
+        })
+        .with_pool_capacity(config.tx_pool_max_transactions)
+        .with_reorg_policy(ReorgPolicy {
+            max_depth: config.max_reorg_depth,
+            alert_depth: config.reorg_alert_depth,
+        })
+        .with_notifier(WebhookNotifier::from_config(config))
+        .with_signer(BlockSigner::from_config(config))
+        .with_validator_schedule(
+            config
+                .validator_schedule()
+                .expect("The validator schedule is checked when the config loads"),
+        )
+        .with_chain_id(&config.chain_id);
+        if config.bft_enabled {
+            blockchain.with_bft(config.bft_round_timeout_secs)
+        } else {
+            blockchain
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn open(
+        db: Database,
+        name: String,
+        author_public_key: String,
+        author_secret_key: String,
+        developer_mode: bool,
+        authorities: Vec<String>,
+        block_reward_amount: u64,
+        ride_request_referrer_fee_percent: u8,
+        ride_offer_referrer_fee_percent: u8,
+    ) -> Blockchain {
         let blockchain = Blockchain {
             name,
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
             db,
             developer_mode,
-            consensus: Aura::new(authorities, step_duration),
+            validators: ValidatorSchedule::fixed(authorities),
             author_public_key,
-            author_secret_key,
+            signer: BlockSigner::Local {
+                secret_key: author_secret_key,
+            },
             block_reward_amount,
             ride_request_referrer_fee_percent,
             ride_offer_referrer_fee_percent,
+            persist_state_diffs: false,
+            hardforks: HardforkSchedule::default(),
+            block_limits: BlockLimits::default(),
+            pool_expiry: PoolExpiry::default(),
+            pool_capacity: 0,
+            reorg_policy: ReorgPolicy::default(),
+            notifier: WebhookNotifier::default(),
+            cache: Arc::new(BlockCache::new(
+                DEFAULT_HEADER_CACHE_SIZE,
+                DEFAULT_BODY_CACHE_SIZE,
+                DEFAULT_BLOCK_HASH_CACHE_SIZE,
+            )),
+            bad_blocks: BadBlocks::new(BAD_BLOCK_CACHE_SIZE),
+            duties: DutyTracker::default(),
+            bft: None,
+            plugins: PluginRegistry::default(),
+            handshake: HandshakeSnapshot::default(),
         };
 
         Block::genesis_import_block(&blockchain.db);
         blockchain
     }
 
+    /// The network this chain belongs to; addresses are shown and parsed with it as
+    /// their prefix, see `Address`.
+    pub fn with_chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = chain_id.to_string();
+        self
+    }
+
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Replaces the default block caches, e.g. with sizes from `AppConfig`.
+    pub fn with_block_cache(mut self, cache: BlockCache) -> Self {
+        self.cache = Arc::new(cache);
+        self
+    }
+
+    /// Stores each imported block's `StateDiff` alongside it.
+    pub fn with_state_diffs(mut self, persist: bool) -> Self {
+        self.persist_state_diffs = persist;
+        self
+    }
+
+    /// State diffs are the first writes to go in disk safe mode; blocks keep importing.
+    fn writes_state_diffs(&self) -> bool {
+        self.persist_state_diffs && !disk_monitor::safe_mode()
+    }
+
+    /// Activates protocol changes on the schedule `hardforks` gives. Every node of the
+    /// network needs the same schedule, or they split at the first fork.
+    pub fn with_hardforks(mut self, hardforks: HardforkSchedule) -> Self {
+        self.hardforks = hardforks;
+        self
+    }
+
+    /// Caps block size and gas, for authored and imported blocks alike. Unlimited by
+    /// default; every node of the network needs the same limits.
+    pub fn with_block_limits(mut self, block_limits: BlockLimits) -> Self {
+        self.block_limits = block_limits;
+        self
+    }
+
+    /// Lets `evict_expired_transactions` drop remote transactions that waited too long.
+    /// Nothing expires by default.
+    pub fn with_pool_expiry(mut self, pool_expiry: PoolExpiry) -> Self {
+        self.pool_expiry = pool_expiry;
+        self
+    }
+
+    pub fn pool_expiry(&self) -> PoolExpiry {
+        self.pool_expiry
+    }
+
+    /// Refuses new transactions while the pool holds `max_transactions`; 0, the default,
+    /// is unlimited.
+    pub fn with_pool_capacity(mut self, max_transactions: usize) -> Self {
+        self.pool_capacity = max_transactions;
+        self
+    }
+
+    pub fn hardforks(&self) -> &HardforkSchedule {
+        &self.hardforks
+    }
+
+    /// Limits how deep `rollback_to` may go and when it alerts.
+    pub fn with_reorg_policy(mut self, reorg_policy: ReorgPolicy) -> Self {
+        self.reorg_policy = reorg_policy;
+        self
+    }
+
+    /// Posts deep reorgs here; the P2P server and the authoring job post their events
+    /// through the same notifier.
+    pub fn with_notifier(mut self, notifier: WebhookNotifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    pub fn notifier(&self) -> &WebhookNotifier {
+        &self.notifier
+    }
+
+    /// Signs authored blocks with `signer` instead of the secret key passed to `new`.
+    pub fn with_signer(mut self, signer: BlockSigner) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Changes the authorities at epoch boundaries instead of keeping those passed to
+    /// `new` for every block. Sets elected by stake are read back from the epoch-boundary
+    /// blocks already on the chain.
+    pub fn with_validator_schedule(mut self, validators: ValidatorSchedule) -> Self {
+        self.validators = validators;
+        if let Err(e) = self.learn_elected_validators() {
+            error!("Failed to read the elected validator sets: {}", e);
+        }
+        self
+    }
+
+    fn learn_elected_validators(&self) -> Result<(), String> {
+        if self.validators.min_stake() == 0 {
+            return Ok(());
+        }
+        let epoch_length = self.validators.epoch_length();
+        let head = self.get_latest_block()?.map_or(0, |head| head.index);
+        let boundaries = (epoch_length - 1..=head).step_by(epoch_length).collect();
+        for block in self.get_blocks_by_indexes(boundaries)? {
+            self.validators.learn(&block);
+        }
+        Ok(())
+    }
+
+    /// Votes on finality with the other authorities; see `BftEngine`. Rounds that see no
+    /// commit quorum within `round_timeout_secs` are changed.
+    pub fn with_bft(mut self, round_timeout_secs: u64) -> Self {
+        self.bft = Some(Mutex::new(BftEngine::new(
+            self.validators.aura_at(0).authorities.clone(),
+            self.author_public_key.clone(),
+            round_timeout_secs,
+        )));
+        self
+    }
+
+    /// Registers `plugin` for chain and network events; see `NodePlugin`.
+    pub fn with_plugin(mut self, plugin: impl NodePlugin + 'static) -> Self {
+        self.plugins.register(Arc::new(plugin));
+        self
+    }
+
+    /// Replaces the registered plugins, e.g. with those collected by `NodeBuilder`.
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    pub fn plugins(&self) -> &PluginRegistry {
+        &self.plugins
+    }
+
     pub fn get_latest_block(&self) -> Result<Option<Block>, String> {
         Block::get_latest_block(&self.db)
     }
@@ -82,6 +379,32 @@ impl Blockchain {
         load_block_effects(&self.db, block_height)
     }
 
+    /// Balance effects of blocks `from..=to`, in chain order (each block's transactions,
+    /// then its own effects such as the reward), optionally only those of `address`.
+    pub fn get_logs(
+        &self,
+        from: usize,
+        to: usize,
+        address: Option<&str>,
+    ) -> Result<Vec<StoredBalanceEffect>, String> {
+        let address = address.map(canonical_account_address);
+        let mut logs = Vec::new();
+        if from > to {
+            return Ok(logs);
+        }
+        for block in self.iter_blocks(from, to - from + 1) {
+            let block = block?;
+            for tx in &block.transactions {
+                logs.extend(self.get_tx_balance_effects(&tx.hash));
+            }
+            logs.extend(self.get_block_balance_effects(block.index as u64));
+        }
+        if let Some(address) = address {
+            logs.retain(|log| log.effect.address == address);
+        }
+        Ok(logs)
+    }
+
     pub fn get_account_balance_effects(
         &self,
         address: &str,
@@ -95,6 +418,91 @@ impl Blockchain {
         AccountState::get_current_nonce(public_key, &self.db)
     }
 
+    /// Block index `tag` refers to; explicit indexes above the head are an error.
+    pub fn resolve_block_tag(&self, tag: BlockTag) -> Result<usize, String> {
+        let head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        match tag {
+            BlockTag::Index(index) if index > head => {
+                Err(format!("Block {} is above the chain head {}", index, head))
+            }
+            BlockTag::Index(index) => Ok(index),
+            BlockTag::Named(NamedBlock::Latest) => Ok(head),
+            BlockTag::Named(NamedBlock::Finalized) => self.finalized_block_index(head),
+        }
+    }
+
+    fn finalized_block_index(&self, head: usize) -> Result<usize, String> {
+        let start = head.saturating_sub(FINALITY_SEARCH_DEPTH - 1);
+        let authors = self
+            .iter_blocks(start, head + 1 - start)
+            .map(|block| block.map(|block| (block.index, block.author)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let newest_first = authors
+            .iter()
+            .rev()
+            .map(|(index, author)| (*index, author.as_str()));
+        let by_authors = self
+            .validators
+            .aura_at(head)
+            .finalized_index(newest_first)
+            .unwrap_or(0);
+        Ok(by_authors.max(self.bft_finalized_index(head)?))
+    }
+
+    /// The newest block the authorities' votes finalized, if it is still on our chain.
+    fn bft_finalized_index(&self, head: usize) -> Result<usize, String> {
+        let finalized = match &self.bft {
+            Some(bft) => bft.lock().unwrap().finalized().cloned(),
+            None => None,
+        };
+        match finalized {
+            Some((height, hash)) if height as usize <= head => {
+                let on_chain = self.get_block_hash(height as usize)?.as_ref() == Some(&hash);
+                Ok(if on_chain { height as usize } else { 0 })
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Balance and nonce of an account as of block `index`. State is only kept for the
+    /// head, so for older blocks the balance is found by undoing the account's balance
+    /// effects above `index`, and the nonce by discounting its transactions there.
+    pub fn get_account_at(
+        &self,
+        public_key: &String,
+        index: usize,
+    ) -> Result<AccountSnapshot, String> {
+        let head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        let address = canonical_account_address(public_key);
+        let mut balance = self.get_account_balance(&address) as i128;
+        let mut nonce = self.get_current_nonce(&address)?;
+
+        if index < head {
+            balance -= sum_account_deltas_after(&self.db, &address, index as u64);
+            for block in self.iter_blocks(index + 1, head - index) {
+                let sent = block?
+                    .transactions
+                    .iter()
+                    .filter(|tx| canonical_account_address(&tx.from) == address)
+                    .count();
+                nonce = nonce.saturating_sub(sent as u64);
+            }
+        }
+
+        Ok(AccountSnapshot {
+            address,
+            balance: u64::try_from(balance.max(0)).unwrap_or(u64::MAX),
+            nonce,
+            block_index: index,
+        })
+    }
+
     pub fn shutdown_blockchain(&mut self) {
         if self.developer_mode {
             self.blockchain_write_to_file();
@@ -103,8 +511,9 @@ impl Blockchain {
     }
 
     fn cleanup_db(&mut self) {
+        let path = self.db.path().to_string();
         self.db.close();
-        match self.db.delete_database(self.name.as_str()) {
+        match Database::destroy(&path) {
             Ok(_) => {
                 info!("Developer mode: Database cleaned up successfully.");               
             }
@@ -113,24 +522,614 @@ impl Blockchain {
     }
 
     pub fn import_block(&self, block: &Block) -> Result<(), String> {
-        self.consensus.verify_block_author(&block)?;
-        block.validate_block(&self.db)?;
-        Transaction::validate_transactions(&self.db, &block.transactions)?;
+        self.apply_block(block)?;
+        self.cache.insert(block);
+        self.refresh_handshake();
+        self.plugins.block_imported(block);
+        Ok(())
+    }
+
+    /// Whether `block` may become importable later: it builds past our head, so its parent
+    /// may still arrive, or its slot hasn't started on our clock yet.
+    pub fn is_future_block(&self, block: &Block) -> Result<bool, String> {
+        let parent_missing = self
+            .get_latest_block()?
+            .is_some_and(|head| block.index > head.index + 1);
+        Ok(parent_missing || self.validators.aura_at(block.index).is_early(block))
+    }
+
+    /// The last slot to have ended at `now_secs` if its authority authored no block in
+    /// it; see `Aura::missed_slot`.
+    pub fn missed_slot(&self, now_secs: u64) -> Result<Option<NodeEvent>, String> {
+        let Some(head) = self.get_latest_block()? else {
+            return Ok(None);
+        };
+        Ok(self
+            .validators
+            .aura_at(head.index + 1)
+            .missed_slot(&head, now_secs)
+            .map(|(slot, author)| NodeEvent::MissedSlot {
+                slot,
+                author: author.clone(),
+                head_index: head.index,
+            }))
+    }
+
+    /// Counts `block`, just authored by this node, as a proposal made in its slot.
+    pub fn record_proposal(&self, block: &Block) {
+        let aura = self.validators.aura_at(block.index);
+        self.duties.proposed(aura.slot_at_time(block.timestamp));
+    }
+
+    /// Whether `missed`, as `missed_slot` reports it, was this node's slot; if so it is
+    /// counted as a missed proposal the first time it is seen.
+    pub fn record_missed_slot(&self, missed: &NodeEvent) -> bool {
+        match missed {
+            NodeEvent::MissedSlot { slot, author, .. } if *author == self.author_public_key => {
+                self.duties.missed(*slot)
+            }
+            _ => false,
+        }
+    }
+
+    /// This node's next `count` slots as an authority at `now_secs`, and its record so far.
+    pub fn duties(&self, now_secs: u64, count: usize) -> DutyReport {
+        let aura = self.aura_after_head();
+        DutyReport {
+            validator: self.author_public_key.clone(),
+            current_slot: aura.slot_at_time(now_secs),
+            step_duration: aura.step_duration,
+            upcoming: upcoming_duties(&aura, &self.author_public_key, now_secs, count),
+            record: self.duties.record(),
+        }
+    }
+
+    /// Counts a gossiped BFT vote once its signature checks out. Returns whether it was new
+    /// and still relevant; votes of ours it leads to go out with the next `bft_tick`.
+    pub fn add_vote(&self, vote: &Vote, now_secs: u64) -> Result<bool, String> {
+        let Some(bft) = &self.bft else {
+            return Err("BFT voting is not enabled".to_string());
+        };
+        vote.verify()?;
+        bft.lock().unwrap().on_vote(vote, now_secs)
+    }
+
+    /// Moves the BFT engine on at `now_secs`, preparing the block we hold at the height
+    /// it decides, and returns our votes to gossip, signed. Empty without `with_bft`.
+    pub fn bft_tick(&self, now_secs: u64) -> Result<Vec<Vote>, String> {
+        let Some(bft) = &self.bft else {
+            return Ok(Vec::new());
+        };
+        let head = self.get_latest_block()?.map_or(0, |head| head.index);
+        let votes = {
+            let mut engine = bft.lock().unwrap();
+            engine.catch_up(head as u64, now_secs);
+            let height = engine.height() as usize;
+            engine.set_authorities(&self.validators.aura_at(height).authorities);
+            let candidate = self.get_block_hash(height)?;
+            engine.tick(candidate.as_deref(), now_secs)
+        };
+        votes
+            .into_iter()
+            .map(|mut vote| {
+                vote.sign_with(&self.signer)?;
+                Ok(vote)
+            })
+            .collect()
+    }
+
+    /// Blocks that failed validation on top of their parent, most recently received first.
+    pub fn bad_blocks(&self) -> Vec<BadBlock> {
+        self.bad_blocks.list()
+    }
+
+    /// Validates and writes `block` without caching it or telling plugins, for callers
+    /// that do both once the write is committed. A block rejected before is turned away without validating
+    /// it again.
+    fn apply_block(&self, block: &Block) -> Result<(), String> {
+        if let Some(reason) = self.bad_blocks.rejection(block) {
+            return Err(format!(
+                "Block {} was already rejected: {}",
+                block.hash, reason
+            ));
+        }
+        if let Err(e) = self.validate_block(block) {
+            // Only a block on top of our head fails for good; anything else may just be
+            // early, or on a branch we haven't got.
+            let builds_on_head = self.get_latest_block()?.is_some_and(|head| {
+                head.index + 1 == block.index && head.hash == block.previous_hash
+            });
+            if builds_on_head && !self.validators.aura_at(block.index).is_early(block) {
+                self.bad_blocks.insert(block, &e);
+            }
+            return Err(e);
+        }
         Block::add_block_to_chain(
             &self.db,
             block,
-            self.block_reward_amount,
+            self.block_reward_for(block),
             self.ride_request_referrer_fee_percent,
             self.ride_offer_referrer_fee_percent,
+            self.writes_state_diffs(),
         )?;
+        self.validators.learn(block);
 
         Ok(())
     }
 
+    fn validate_block(&self, block: &Block) -> Result<(), String> {
+        self.validators
+            .aura_at(block.index)
+            .verify_block_author(block)?;
+        self.validators.validate_block(block, &self.db)?;
+        self.block_limits.check(block)?;
+        block.validate_block(&self.db, &self.hardforks)?;
+        Transaction::validate_transactions(&self.db, &block.transactions)?;
+        block.verify_roots(
+            &self.db,
+            self.ride_request_referrer_fee_percent,
+            self.ride_offer_referrer_fee_percent,
+        )
+    }
+
+    /// Starts a bulk import for a run of synced blocks: their writes are buffered and
+    /// committed every `batch_size` blocks (head pointer included), instead of one RocksDB
+    /// write per block. The remainder is committed by `BulkImport::finish` or on drop.
+    pub fn bulk_import(&self, batch_size: usize) -> BulkImport<'_> {
+        self.db.begin_batch();
+        BulkImport {
+            blockchain: self,
+            batch_size: batch_size.max(1),
+            unflushed: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Unwinds the chain to block `index` and returns how many blocks were removed. Later
+    /// blocks are deleted and the `state` column family (balances, nonces, ride state,
+    /// balance effects and their account index) is rebuilt by replaying blocks
+    /// `0..=index`, since state keeps no history to undo. All of it is committed in one
+    /// write, so a crash leaves either the old chain or the rolled-back one. Transactions
+    /// from the removed blocks are not put back in the pool. Removing more blocks than the
+    /// reorg policy's `max_depth` is refused; see `force_rollback_to`.
+    pub fn rollback_to(&self, index: usize) -> Result<usize, String> {
+        self.unwind_to(index, false)
+    }
+
+    /// `rollback_to` without the reorg depth limit, for an operator who means it.
+    pub fn force_rollback_to(&self, index: usize) -> Result<usize, String> {
+        self.unwind_to(index, true)
+    }
+
+    fn unwind_to(&self, index: usize, force: bool) -> Result<usize, String> {
+        let latest_block = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?;
+        if index > latest_block.index {
+            return Err(format!(
+                "Cannot roll back to block {}: the chain head is block {}",
+                index, latest_block.index
+            ));
+        }
+        if index == latest_block.index {
+            return Ok(0);
+        }
+        let depth = latest_block.index - index;
+        self.reorg_policy.check(depth, force)?;
+
+        let mut removed_hashes = Vec::new();
+        if !self.plugins.is_empty() {
+            for removed in (index + 1..=latest_block.index).rev() {
+                removed_hashes.extend(self.get_block_hash(removed)?);
+            }
+        }
+
+        // Read before the rewrite deletes them.
+        let alert = if self.reorg_policy.alerts(depth) {
+            let blocks = Block::get_blocks_with_limit_and_skip(&self.db, index, 0, depth + 1)?;
+            blocks
+                .split_first()
+                .map(|(common, removed)| ReorgSummary::new(common, removed, &[]))
+        } else {
+            None
+        };
+
+        self.rewrite_chain(index)
+            .map_err(|e| format!("Failed to roll back to block {}: {}", index, e))?;
+        self.plugins.reorg(index, &removed_hashes);
+        if let Some(summary) = alert {
+            warn!(
+                "Reorg of {} blocks above block {}: old branch {:?}, new branch {:?}",
+                summary.depth, summary.common_index, summary.old_branch, summary.new_branch
+            );
+            self.notifier.notify_and_wait(NodeEvent::DeepReorg(summary));
+        }
+        info!(
+            "Rolled back from block {} to block {}",
+            latest_block.index, index
+        );
+        Ok(latest_block.index - index)
+    }
+
+    /// Whether our chain may be unwound to block `ancestor` for a peer's branch: not below
+    /// the finalized block, and by no more blocks than the reorg policy's `max_depth`.
+    pub fn check_reorg(&self, ancestor: usize) -> Result<(), String> {
+        let head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        self.check_reorg_below(head, ancestor)
+    }
+
+    fn check_reorg_below(&self, head: usize, ancestor: usize) -> Result<(), String> {
+        if ancestor > head {
+            return Err(format!(
+                "Block {} is above the chain head {}",
+                ancestor, head
+            ));
+        }
+        let finalized = self.finalized_block_index(head)?;
+        if ancestor < finalized {
+            return Err(format!(
+                "Block {} is finalized; not unwinding below it to block {}",
+                finalized, ancestor
+            ));
+        }
+        self.reorg_policy.check(head - ancestor, false)
+    }
+
+    /// Replaces our blocks above `ancestor` with `branch`, a peer's blocks on top of it,
+    /// if that makes the chain heavier; checked like `check_reorg`. It is one write: the
+    /// chain is unwound to `ancestor` and every branch block validated and applied on top
+    /// in the same batch, which is dropped if any of them fails, so readers only ever see
+    /// our chain or the whole new one. Returns how many of our blocks were removed.
+    pub fn switch_to_branch(&self, ancestor: usize, branch: &[Block]) -> Result<usize, String> {
+        let latest_block = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?;
+        let new_head = ancestor + branch.len();
+        if new_head <= latest_block.index {
+            return Err(format!(
+                "A branch to block {} is no heavier than our chain to block {}",
+                new_head, latest_block.index
+            ));
+        }
+        self.check_reorg_below(latest_block.index, ancestor)?;
+        let depth = latest_block.index - ancestor;
+
+        let mut removed_hashes = Vec::new();
+        if !self.plugins.is_empty() {
+            for removed in (ancestor + 1..=latest_block.index).rev() {
+                removed_hashes.extend(self.get_block_hash(removed)?);
+            }
+        }
+        let alert = if self.reorg_policy.alerts(depth) {
+            let blocks = Block::get_blocks_with_limit_and_skip(&self.db, ancestor, 0, depth + 1)?;
+            blocks
+                .split_first()
+                .map(|(common, removed)| ReorgSummary::new(common, removed, branch))
+        } else {
+            None
+        };
+
+        self.rewrite_chain_onto(ancestor, branch).map_err(|e| {
+            format!(
+                "Not switching to the branch above block {}: {}",
+                ancestor, e
+            )
+        })?;
+        self.plugins.reorg(ancestor, &removed_hashes);
+        for block in branch {
+            self.cache.insert(block);
+            self.plugins.block_imported(block);
+        }
+        if let Some(summary) = alert {
+            warn!(
+                "Reorg of {} blocks above block {}: old branch {:?}, new branch {:?}",
+                summary.depth, summary.common_index, summary.old_branch, summary.new_branch
+            );
+            self.notifier.notify(NodeEvent::DeepReorg(summary));
+        }
+        info!(
+            "Switched from block {} to a branch above block {} ending at block {}",
+            latest_block.index, ancestor, new_head
+        );
+        Ok(depth)
+    }
+
+    /// Rebuilds the head pointer, state and balance effects from the stored blocks, for a
+    /// database whose indexes are damaged. Blocks are taken from genesis up to the first
+    /// one that is missing, unreadable or doesn't chain onto its parent; anything above
+    /// that is dropped and left to sync. Returns the index of the new head.
+    pub fn rebuild_indexes(&self) -> Result<usize, String> {
+        let mut head: Option<Block> = None;
+        for block in Block::iter_blocks(&self.db, 0, 0, usize::MAX) {
+            let next = head.as_ref().map_or(0, |parent| parent.index + 1);
+            let block = match block {
+                Ok(block) => block,
+                Err(e) => {
+                    warn!("Stopping the rebuild at block {}: {}", next, e);
+                    break;
+                }
+            };
+            if let Some(parent) = &head {
+                if block.previous_hash != parent.hash {
+                    warn!(
+                        "Stopping the rebuild at block {}: it does not chain onto block {}",
+                        block.index, parent.index
+                    );
+                    break;
+                }
+            }
+            head = Some(block);
+        }
+        let Some(head) = head.map(|block| block.index) else {
+            return Err(
+                "Genesis block is missing or unreadable; nothing to rebuild from".to_string(),
+            );
+        };
+
+        self.rewrite_chain(head)
+            .map_err(|e| format!("Failed to rebuild indexes: {}", e))?;
+        info!("Rebuilt indexes from {} blocks", head + 1);
+        Ok(head)
+    }
+
+    pub fn compact_database(&self) -> Result<(), String> {
+        self.db.compact()
+    }
+
+    /// Writes every complete era up to the finalized block into `archive`, skipping eras
+    /// it already holds. With `prune`, the bodies of archived blocks are then deleted from
+    /// the database, keeping their headers; reads fall back to the archive once it is
+    /// installed (see `era::install_archive`). Genesis is always kept.
+    pub fn freeze_blocks(&self, archive: &EraArchive, prune: bool) -> Result<FreezeReport, String> {
+        let head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        let finalized = self.finalized_block_index(head)?;
+        let mut report = FreezeReport::default();
+        for era in 0.. {
+            let range = archive.era_range(era);
+            if range.end > finalized + 1 {
+                break;
+            }
+            if !archive.contains_era(era) {
+                let blocks =
+                    Block::get_blocks_with_limit_and_skip(&self.db, range.start, 0, range.len())?;
+                let info = archive.freeze(era, &blocks)?;
+                info!(
+                    "Froze blocks {} to {} into {}",
+                    range.start,
+                    range.end - 1,
+                    info.file
+                );
+                report.frozen.push(info);
+            }
+            if prune {
+                report.pruned += self.prune_era(archive, era)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Deletes the bodies of era `era`'s blocks once its file is verified against the
+    /// stored headers, and returns how many were deleted.
+    fn prune_era(&self, archive: &EraArchive, era: usize) -> Result<usize, String> {
+        let file = archive
+            .file(era)
+            .ok_or_else(|| format!("Era {} is not archived", era))?;
+        let blocks = file.verify()?;
+        let mut headers = Vec::with_capacity(blocks.len());
+        for header in Block::iter_headers(&self.db, file.start_index(), 0, file.len()) {
+            headers.push(header?);
+        }
+        let hashes = headers.iter().map(|header| header.hash.as_str());
+        if headers.len() != blocks.len() || era::accumulator(hashes) != file.accumulator() {
+            return Err(format!(
+                "Era file {} does not match our chain",
+                file.path().display()
+            ));
+        }
+
+        let mut keys = Vec::new();
+        let mut header_values = Vec::new();
+        for block in blocks.iter().filter(|block| block.index > 0) {
+            let key = format!("block_{}", block.index).into_bytes();
+            if self.db.get("block", &key)?.is_some() {
+                keys.push(key);
+                // Headers of blocks imported before they were stored on their own.
+                header_values.push((
+                    BlockHeader::construct_key(block.index),
+                    serde_json::to_vec(&block.to_block_header()).unwrap(),
+                ));
+            }
+        }
+        let mut operations: Vec<(&str, &[u8], Option<&[u8]>)> = Vec::new();
+        for (key, value) in &header_values {
+            operations.push(("block", key.as_slice(), Some(value.as_slice())));
+        }
+        for key in &keys {
+            operations.push(("block", key.as_slice(), None));
+        }
+        self.db.write(operations)?;
+        Ok(keys.len())
+    }
+
+    /// Imports the blocks of `archive`'s eras that are above our head, in order, after
+    /// verifying each file; the out-of-band counterpart of syncing them from peers.
+    /// Returns how many blocks were imported.
+    pub fn import_era(&self, archive: &EraArchive, batch_size: usize) -> Result<usize, String> {
+        let mut head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        let mut imported = 0;
+        for info in archive.eras() {
+            if info.start_index + info.count <= head + 1 {
+                continue;
+            }
+            if info.start_index > head + 1 {
+                return Err(format!(
+                    "Era {} starts at block {}, but the chain head is block {}",
+                    info.era, info.start_index, head
+                ));
+            }
+            let file = archive
+                .file(info.era)
+                .ok_or_else(|| format!("Era {} is not archived", info.era))?;
+            let blocks = file.verify()?;
+            let mut import = self.bulk_import(batch_size);
+            for block in blocks.iter().filter(|block| block.index > head) {
+                import.import_block(block)?;
+                imported += 1;
+            }
+            import.finish()?;
+            head = info.start_index + info.count - 1;
+            info!("Imported era {} from {}", info.era, info.file);
+        }
+        Ok(imported)
+    }
+
+    /// Makes stored blocks `0..=head` the whole chain in one write: drops all state and
+    /// every later block with its header and transaction index, then re-applies the kept
+    /// blocks from genesis, streaming them from the database one at a time. Blocks pruned
+    /// into the era archive are read from it and stay pruned. Reads during the replay see
+    /// the buffered writes. The block cache is cleared whether or not the write lands,
+    /// since it may hold blocks of either chain by then.
+    fn rewrite_chain(&self, head: usize) -> Result<(), String> {
+        self.rewrite_chain_onto(head, &[])
+    }
+
+    /// `rewrite_chain` to `head` with `branch` validated and applied on top in the same
+    /// write; nothing is written unless all of it applies.
+    fn rewrite_chain_onto(&self, head: usize, branch: &[Block]) -> Result<(), String> {
+        self.db.begin_batch();
+        let replayed = self
+            .replay_blocks(head)
+            .and_then(|()| branch.iter().try_for_each(|block| self.apply_block(block)));
+        let result = match replayed {
+            Ok(()) => self.db.end_batch().map(|_| ()),
+            Err(e) => {
+                self.db.discard_batch();
+                Err(e)
+            }
+        };
+        self.cache.clear();
+        result?;
+        self.refresh_handshake();
+        Ok(())
+    }
+
+    fn replay_blocks(&self, head: usize) -> Result<(), String> {
+        for key in self.db.get_keys_by_cf_name("state")? {
+            self.db.delete("state", &key)?;
+        }
+        let mut stored = HashSet::new();
+        for key in self.db.get_keys_by_cf_name("block")? {
+            match self.kept_block_key(&key, head)? {
+                Some(BlockKey::Body(index)) => {
+                    stored.insert(index);
+                }
+                Some(BlockKey::Index) => {}
+                None => self.db.delete("block", &key)?,
+            }
+        }
+        self.db.delete("blockchain", SYNC_CHECKPOINT_KEY)?;
+
+        let mut replayed = 0;
+        for block in Block::iter_blocks(&self.db, 0, 0, head + 1) {
+            let block = block?;
+            Block::add_block_to_chain(
+                &self.db,
+                &block,
+                self.block_reward_for(&block),
+                self.ride_request_referrer_fee_percent,
+                self.ride_offer_referrer_fee_percent,
+                self.writes_state_diffs(),
+            )?;
+            // Read from the era archive: keep it pruned rather than storing the body again.
+            if !stored.contains(&block.index) {
+                self.db
+                    .delete("block", format!("block_{}", block.index).as_bytes())?;
+            }
+            replayed += 1;
+        }
+        if replayed != head + 1 {
+            return Err(format!(
+                "Block {} is missing; cannot replay state up to block {}",
+                replayed, head
+            ));
+        }
+        Ok(())
+    }
+
+    /// What `key` of the `block` column family is, if a rewrite to `head` keeps it: a
+    /// block body or header at or below `head`, or the transaction index of such a block.
+    /// The header of a pruned block must outlive the replay, since reading the block back
+    /// from the era archive checks its hash against it.
+    fn kept_block_key(&self, key: &[u8], head: usize) -> Result<Option<BlockKey>, String> {
+        let Ok(key) = std::str::from_utf8(key) else {
+            return Ok(None);
+        };
+        if let Some(tx_hash) = key.strip_prefix("tx_location_") {
+            let location = TxLocation::load(&self.db, tx_hash)?;
+            return Ok(location
+                .filter(|location| location.block_index <= head)
+                .map(|_| BlockKey::Index));
+        }
+        let index_of = |prefix: &str| key.strip_prefix(prefix)?.parse::<usize>().ok();
+        if let Some(index) = index_of("block_").filter(|index| *index <= head) {
+            return Ok(Some(BlockKey::Body(index)));
+        }
+        Ok(index_of("header_")
+            .filter(|index| *index <= head)
+            .map(|_| BlockKey::Index))
+    }
+
     pub fn get_blocks(&self) -> Result<Vec<Block>, String> {
         Block::get_blocks(&self.db)
     }
 
+    pub fn load_sync_checkpoint(&self) -> Result<Option<SyncCheckpoint>, String> {
+        match self.db.get("blockchain", SYNC_CHECKPOINT_KEY)? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to decode sync checkpoint: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Persists where a sync run is, or clears it with `None` once the run has caught up.
+    pub fn save_sync_checkpoint(&self, checkpoint: Option<&SyncCheckpoint>) -> Result<(), String> {
+        match checkpoint {
+            Some(checkpoint) => {
+                let value = serde_json::to_vec(checkpoint)
+                    .map_err(|e| format!("Failed to encode sync checkpoint: {}", e))?;
+                self.db.put("blockchain", SYNC_CHECKPOINT_KEY, &value)
+            }
+            None => self.db.delete("blockchain", SYNC_CHECKPOINT_KEY),
+        }
+    }
+
+    pub fn load_firehose_cursor(&self) -> Result<Option<FirehoseCursor>, String> {
+        match self.db.get("blockchain", FIREHOSE_CURSOR_KEY)? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to decode firehose cursor: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Unlike the sync checkpoint, kept across `rollback_to` and `rebuild_indexes`: the
+    /// firehose compares it with the rewritten chain to report the blocks removed.
+    pub fn save_firehose_cursor(&self, cursor: &FirehoseCursor) -> Result<(), String> {
+        let value = serde_json::to_vec(cursor)
+            .map_err(|e| format!("Failed to encode firehose cursor: {}", e))?;
+        self.db.put("blockchain", FIREHOSE_CURSOR_KEY, &value)
+    }
+
     pub fn get_blocks_with_limit_and_skip(
         &self,
         start_index: usize,
@@ -140,14 +1139,54 @@ impl Blockchain {
         Block::get_blocks_with_limit_and_skip(&self.db, start_index, skip, limit)
     }
 
+    /// Up to `limit` blocks from `start_index`, read one at a time; prefer it over
+    /// `get_blocks_with_limit_and_skip` when only part of each block is kept.
+    pub fn iter_blocks(&self, start_index: usize, limit: usize) -> BlockIter<'_, Database> {
+        Block::iter_blocks(&self.db, start_index, 0, limit)
+    }
+
     pub fn get_blocks_by_indexes(&self, indexes: Vec<usize>) -> Result<Vec<Block>, String> {
-        Block::get_blocks_by_indexes(&self.db, indexes)
+        self.cache.blocks_by_indexes(&indexes, |misses| {
+            Block::get_blocks_by_indexes(&self.db, misses)
+        })
+    }
+
+    /// Hash of the block at `index`, from the hash cache when possible.
+    pub fn get_block_hash(&self, index: usize) -> Result<Option<String>, String> {
+        if let Some(hash) = self.cache.hash(index) {
+            return Ok(Some(hash));
+        }
+        let header = Block::iter_headers(&self.db, index, 0, 1)
+            .next()
+            .transpose()?;
+        match header {
+            Some(header) => {
+                self.cache.insert_header(&header);
+                Ok(Some(header.hash))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Snapshot-capable read handle that outlives the `Blockchain` lock guard, for serving
+    /// block data to peers while imports hold the write lock.
+    pub fn reader(&self) -> Result<ChainReader, NodeError> {
+        Ok(ChainReader {
+            db: self.db.reader()?,
+            cache: Arc::clone(&self.cache),
+        })
     }
 
     pub fn block_reward_amount(&self) -> u64 {
         self.block_reward_amount
     }
 
+    /// What `block`'s author is minted under the hardforks active at it. Genesis mints
+    /// nothing regardless.
+    pub fn block_reward_for(&self, block: &Block) -> u64 {
+        self.hardforks.block_reward(block, self.block_reward_amount)
+    }
+
     pub fn ride_request_referrer_fee_percent(&self) -> u8 {
         self.ride_request_referrer_fee_percent
     }
@@ -157,8 +1196,30 @@ impl Blockchain {
     }
 
     #[allow(dead_code)]
-    pub fn current_author(&self) -> &String {
-        self.consensus.current_author()
+    pub fn current_author(&self) -> String {
+        self.aura_after_head().current_author().clone()
+    }
+
+    /// The validator set authoring the block after the head, and the registry the next
+    /// elected set is chosen from.
+    pub fn validators(&self) -> Result<ValidatorsReport, String> {
+        let next = self.get_latest_block()?.map_or(0, |head| head.index + 1);
+        Ok(ValidatorsReport {
+            epoch: self.validators.epoch_of(next),
+            epoch_length: self.validators.epoch_length(),
+            authorities: self.validators.aura_at(next).authorities.clone(),
+            min_stake: self.validators.min_stake(),
+            registry: validator_registry(&self.db)?,
+        })
+    }
+
+    /// Aura over the authorities of the block after the head, the next one to author.
+    fn aura_after_head(&self) -> Arc<Aura> {
+        let next = match self.get_latest_block() {
+            Ok(Some(head)) => head.index + 1,
+            _ => 0,
+        };
+        self.validators.aura_at(next)
     }
 
     pub fn handshake(&self) -> Result<Handshake, String> {
@@ -166,26 +1227,213 @@ impl Blockchain {
             .get_latest_block()?
             .ok_or_else(|| "Failed to get latest block".to_string())?;
 
-        let genesis_block = self
-            .get_genesis_block()?
+        let genesis_block_hash = self
+            .get_block_hash(0)?
             .ok_or_else(|| "Failed to get genesis block".to_string())?;
 
         Ok(Handshake {
-            genesis_block_hash: genesis_block.hash,
+            genesis_block_hash,
             latest_block_hash: latest_block.hash,
             latest_block_index: latest_block.index,
+            capabilities: Vec::new(),
+            hardforks: self.hardforks.advertised(),
+            node_record: Vec::new(),
+            protocol_version: 0,
         })
     }
 
+    /// Shared snapshot of `handshake`, kept current from here on.
+    pub fn handshake_snapshot(&self) -> HandshakeSnapshot {
+        self.refresh_handshake();
+        self.handshake.clone()
+    }
+
+    fn refresh_handshake(&self) {
+        match self.handshake() {
+            Ok(handshake) => self.handshake.set(handshake),
+            Err(e) => warn!("Failed to refresh the handshake snapshot: {}", e),
+        }
+    }
+
+    /// Adds a transaction for the sender's next nonce, or queues one with a nonce gap
+    /// until the transactions before it are included; see `TransactionPool::validate`.
     pub fn add_transaction_to_pool(&self, transaction: &Transaction) -> Result<(), String> {
-        transaction.validate_transaction(&self.db)?;
-        TransactionPool::add_transaction(&self.db, &transaction)
+        TransactionPool::validate(&self.db, transaction)?;
+        self.block_limits.check_transaction(transaction)?;
+        let is_new = TransactionPool::get_transaction(&self.db, &transaction.hash)?.is_none();
+        if is_new
+            && self.pool_capacity > 0
+            && TransactionPool::count(&self.db)? >= self.pool_capacity
+        {
+            return Err(format!(
+                "Transaction pool is full ({} transactions)",
+                self.pool_capacity
+            ));
+        }
+        TransactionPool::add_transaction(&self.db, &transaction)?;
+        if is_new {
+            TransactionPool::mark_admitted(&self.db, &transaction.hash, &self.pool_admission()?)?;
+            self.plugins.tx_pool_insert(transaction);
+        }
+        Ok(())
+    }
+
+    fn pool_admission(&self) -> Result<PoolAdmission, String> {
+        Ok(PoolAdmission {
+            timestamp: get_current_timespan(),
+            block_index: self.get_latest_block()?.map_or(0, |block| block.index),
+        })
+    }
+
+    /// Drops the remote pool transactions the `PoolExpiry` has expired, telling plugins
+    /// and counting them in `tx_pool_evicted`. A transaction pooled without an admission
+    /// record, e.g. by an older version, is timed from this call.
+    pub fn evict_expired_transactions(&self) -> Result<Vec<(Transaction, EvictionReason)>, String> {
+        if !self.pool_expiry.is_enabled() {
+            return Ok(Vec::new());
+        }
+        let now = self.pool_admission()?;
+        let local = TransactionPool::get_local_hashes(&self.db)?;
+        let mut evicted = Vec::new();
+        for transaction in TransactionPool::get_transactions(&self.db)? {
+            if local.contains(&transaction.hash) {
+                continue;
+            }
+            let Some(admission) = TransactionPool::get_admission(&self.db, &transaction.hash)?
+            else {
+                TransactionPool::mark_admitted(&self.db, &transaction.hash, &now)?;
+                continue;
+            };
+            let Some(reason) = self
+                .pool_expiry
+                .check(&admission, now.timestamp, now.block_index)
+            else {
+                continue;
+            };
+            TransactionPool::remove_transaction(&self.db, &transaction.hash)?;
+            self.plugins.tx_pool_evict(&transaction, reason);
+            TX_POOL_EVICTED.inc();
+            evicted.push((transaction, reason));
+        }
+        Ok(evicted)
+    }
+
+    /// Adds a transaction submitted through this node's RPC. Local transactions go first
+    /// in blocks this node authors and are re-broadcast until a block includes them.
+    pub fn add_local_transaction(&self, transaction: &Transaction) -> Result<(), String> {
+        self.add_transaction_to_pool(transaction)?;
+        TransactionPool::mark_local(&self.db, &transaction.hash)
+    }
+
+    pub fn get_local_transactions(&self) -> Result<Vec<Transaction>, String> {
+        TransactionPool::get_local_transactions(&self.db)
     }
 
     pub fn get_transactions_from_pool(&self) -> Result<Vec<Transaction>, String> {
         TransactionPool::get_transactions(&self.db)
     }
 
+    pub fn get_transaction_from_pool(&self, tx_hash: &str) -> Result<Option<Transaction>, String> {
+        TransactionPool::get_transaction(&self.db, tx_hash)
+    }
+
+    /// The pool grouped by sender into pending, queued and stale transactions.
+    pub fn get_pool_content(&self) -> Result<PoolContent<Transaction>, String> {
+        PoolContent::classify(TransactionPool::get_transactions(&self.db)?, |sender| {
+            AccountState::get_current_nonce(sender, &self.db)
+        })
+    }
+
+    /// The block holding an imported transaction, with the transaction's position in it.
+    pub fn find_transaction(&self, tx_hash: &str) -> Result<Option<(Block, usize)>, String> {
+        let Some(location) = TxLocation::load(&self.db, tx_hash)? else {
+            return Ok(None);
+        };
+        let block = self
+            .get_blocks_by_indexes(vec![location.block_index])?
+            .pop()
+            .ok_or_else(|| format!("Block {} not found", location.block_index))?;
+        let indexed = block.transactions.get(location.tx_index);
+        if indexed.map(|tx| tx.hash.as_str()) != Some(tx_hash) {
+            return Err(format!(
+                "Transaction index for {} does not match block {}",
+                tx_hash, block.index
+            ));
+        }
+        Ok(Some((block, location.tx_index)))
+    }
+
+    pub fn get_transaction_receipt(
+        &self,
+        tx_hash: &str,
+    ) -> Result<Option<TransactionReceipt>, String> {
+        let Some((block, tx_index)) = self.find_transaction(tx_hash)? else {
+            return Ok(None);
+        };
+        let logs = self.get_tx_balance_effects(tx_hash);
+        Ok(Some(TransactionReceipt::new(&block, tx_index, logs)))
+    }
+
+    pub fn get_block_receipts(&self, block: &Block) -> Vec<TransactionReceipt> {
+        block
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(tx_index, tx)| {
+                TransactionReceipt::new(block, tx_index, self.get_tx_balance_effects(&tx.hash))
+            })
+            .collect()
+    }
+
+    /// Re-executes block `index` against the state before it and reports every update.
+    /// That state is rebuilt by replaying the chain from genesis in a batch that is then
+    /// discarded, so callers must hold the chain exclusively.
+    pub fn trace_block(&self, index: usize) -> Result<BlockTrace, String> {
+        let head = self
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?;
+        if index > head.index {
+            return Err(format!(
+                "Block {} is above the chain head {}",
+                index, head.index
+            ));
+        }
+        let blocks = self.get_blocks_by_indexes((0..=index).collect())?;
+        if blocks.len() != index + 1 {
+            return Err(format!("Blocks up to {} are not all stored", index));
+        }
+
+        self.db.begin_batch();
+        let trace = self.replay_blocks(&blocks[..index]).and_then(|()| {
+            BlockTrace::execute(
+                &self.db,
+                &blocks[index],
+                self.block_reward_for(&blocks[index]),
+                self.ride_request_referrer_fee_percent,
+                self.ride_offer_referrer_fee_percent,
+            )
+        });
+        self.db.discard_batch();
+        // The replay reported its blocks as the head.
+        Block::record_latest_block(&head);
+        trace
+    }
+
+    /// The stored diff of block `index`; `None` if it was imported without
+    /// `persist_state_diffs`, or in disk safe mode.
+    pub fn get_state_diff(&self, index: usize) -> Result<Option<StateDiff>, String> {
+        StateDiff::load(&self.db, index)
+    }
+
+    /// Trace of an imported transaction, from re-executing its block; see `trace_block`.
+    pub fn trace_transaction(&self, tx_hash: &str) -> Result<Option<TransactionTrace>, String> {
+        let Some((block, tx_index)) = self.find_transaction(tx_hash)? else {
+            return Ok(None);
+        };
+        let trace = self.trace_block(block.index)?;
+        Ok(trace.transactions.into_iter().nth(tx_index))
+    }
+
     pub fn list_available_ride_requests(&self, bounds: Option<MapBounds>) -> Result<Vec<AvailableRideRequest>, String> {
         RideRequest::list_available_ride_requests(&self.db, bounds)
     }
@@ -226,13 +1474,36 @@ impl Blockchain {
 
         let index = latest_block.index + 1;
         let previous_hash = latest_block.hash;
-        let transactions = match TransactionPool::get_transactions(&self.db) {
-            Ok(transactions) => Self::one_tx_per_sender(transactions),
+        let pending = match self.get_pool_content() {
+            Ok(content) => content.pending.into_values().flatten(),
             Err(e) => return Err(format!("Failed to get transactions from pool: {}", e)),
         };
+        // Queued transactions were admitted without a state check, so a promoted one may
+        // not execute; it waits in the pool rather than invalidating the block.
+        let transactions = Self::one_tx_per_sender(
+            pending
+                .filter(|tx| tx.validate_transaction(&self.db).is_ok())
+                .collect(),
+        );
+        let local = TransactionPool::get_local_hashes(&self.db)?;
+        let transactions = self
+            .block_limits
+            .pack(Self::local_first(transactions, &local));
 
         let mut new_block = Block::new_block(index, previous_hash, transactions);
-        new_block.sign(&self.author_public_key, &self.author_secret_key);
+        new_block.next_authorities = self.validators.next_authorities_at(index, &self.db)?;
+        new_block.hash = new_block.calculate_hash();
+        if self
+            .hardforks
+            .is_active_for(Hardfork::HeaderRoots, &new_block)
+        {
+            new_block.commit_roots(
+                &self.db,
+                self.ride_request_referrer_fee_percent,
+                self.ride_offer_referrer_fee_percent,
+            )?;
+        }
+        new_block.sign_with(&self.author_public_key, &self.signer)?;
         self.import_block(&new_block)?;
         Ok(new_block)
     }
@@ -250,6 +1521,16 @@ impl Blockchain {
         transactions
     }
 
+    /// Moves transactions submitted through this node ahead of the rest, keeping the
+    /// order within each group, so they are packed first when the block is full.
+    fn local_first(transactions: Vec<Transaction>, local: &HashSet<String>) -> Vec<Transaction> {
+        let (mut first, rest): (Vec<Transaction>, Vec<Transaction>) = transactions
+            .into_iter()
+            .partition(|tx| local.contains(&tx.hash));
+        first.extend(rest);
+        first
+    }
+
     pub async fn start_network_services(self, config: &AppConfig) {
         NodeServices::start_services(config, self).await;
     }
@@ -283,6 +1564,155 @@ impl Blockchain {
     }
 }
 
+/// Read-only view used to serve peers: block lookups go through the shared block cache,
+/// and misses are read from a RocksDB snapshot rather than under the `Blockchain` lock.
+#[derive(Debug, Clone)]
+pub struct ChainReader {
+    db: DatabaseReader,
+    cache: Arc<BlockCache>,
+}
+
+impl ChainReader {
+    pub fn get_block_headers(
+        &self,
+        start_index: usize,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<BlockHeader>, NodeError> {
+        let start = start_index.saturating_add(skip);
+        let end = start.saturating_add(limit);
+        self.db
+            .with_snapshot(|snapshot| {
+                self.cache
+                    .headers_in_range(start, end, |from, end| {
+                        Block::iter_headers(snapshot, from, 0, end - from)
+                    })
+                    .map_err(StorageError::read("block headers"))
+            })
+            .map_err(NodeError::from)
+    }
+
+    pub fn get_blocks_by_indexes(&self, indexes: &[usize]) -> Result<Vec<Block>, NodeError> {
+        let blocks = self
+            .cache
+            .blocks_by_indexes(indexes, |misses| {
+                self.db
+                    .with_snapshot(|snapshot| Block::get_blocks_by_indexes(snapshot, misses))
+            })
+            .map_err(StorageError::read("blocks"))?;
+        Ok(blocks)
+    }
+
+    /// Up to `count` consecutive blocks from `start_index`, fewer when the chain ends first.
+    pub fn get_block_range(
+        &self,
+        start_index: usize,
+        count: usize,
+    ) -> Result<Vec<Block>, NodeError> {
+        self.db
+            .with_snapshot(|snapshot| {
+                Block::iter_blocks(snapshot, start_index, 0, count)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(StorageError::read("blocks"))
+            })
+            .map_err(NodeError::from)
+    }
+
+    pub fn get_latest_block(&self) -> Result<Option<Block>, NodeError> {
+        self.db
+            .with_snapshot(|snapshot| {
+                Block::get_latest_block(snapshot).map_err(StorageError::read("latest block"))
+            })
+            .map_err(NodeError::from)
+    }
+
+    pub fn get_block_hash(&self, index: usize) -> Result<Option<String>, NodeError> {
+        if let Some(hash) = self.cache.hash(index) {
+            return Ok(Some(hash));
+        }
+        let header = self.db.with_snapshot(|snapshot| {
+            Block::iter_headers(snapshot, index, 0, 1)
+                .next()
+                .transpose()
+                .map_err(StorageError::read("block header"))
+        })?;
+        match header {
+            Some(header) => {
+                self.cache.insert_header(&header);
+                Ok(Some(header.hash))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// What `Blockchain::freeze_blocks` did.
+#[derive(Debug, Default)]
+pub struct FreezeReport {
+    /// Eras written by this run.
+    pub frozen: Vec<EraInfo>,
+    /// Block bodies deleted from the database.
+    pub pruned: usize,
+}
+
+/// Open write batch from `Blockchain::bulk_import`. Each flush is one atomic write, so a
+/// crash mid-sync leaves the chain at the last flushed block, never half a block. Plugins
+/// hear of the blocks in a flush once it is committed.
+pub struct BulkImport<'a> {
+    blockchain: &'a Blockchain,
+    batch_size: usize,
+    unflushed: Vec<Block>,
+    finished: bool,
+}
+
+impl BulkImport<'_> {
+    pub fn import_block(&mut self, block: &Block) -> Result<(), String> {
+        self.blockchain.apply_block(block)?;
+        self.unflushed.push(block.clone());
+        if self.unflushed.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let result = self.blockchain.db.flush_batch();
+        self.committed(result)
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        self.finished = true;
+        let result = self.blockchain.db.end_batch();
+        self.committed(result)
+    }
+
+    /// Caches the blocks of a committed flush and tells plugins about them. A failed flush
+    /// leaves them buffered, so the cache is cleared of anything read from the batch.
+    fn committed<T>(&mut self, result: Result<T, String>) -> Result<(), String> {
+        if let Err(e) = result {
+            self.blockchain.cache.clear();
+            return Err(format!("Failed to commit imported blocks: {}", e));
+        }
+        self.blockchain.refresh_handshake();
+        for block in std::mem::take(&mut self.unflushed) {
+            self.blockchain.cache.insert(&block);
+            self.blockchain.plugins.block_imported(&block);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BulkImport<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let result = self.blockchain.db.end_batch();
+            if let Err(e) = self.committed(result) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,6 +1743,19 @@ mod tests {
         assert!(kept.iter().any(|t| t.from == "0xB"));
     }
 
+    #[test]
+    fn local_transactions_are_packed_first() {
+        let transactions = vec![
+            tf("0xA", 1, "0xB"),
+            tf("0xB", 1, "0xC"),
+            tf("0xC", 1, "0xA"),
+        ];
+        let local: HashSet<String> = [transactions[2].hash.clone()].into();
+        let ordered = Blockchain::local_first(transactions, &local);
+        let senders: Vec<&str> = ordered.iter().map(|tx| tx.from.as_str()).collect();
+        assert_eq!(senders, ["0xC", "0xA", "0xB"]);
+    }
+
     #[test]
     fn one_tx_per_sender_collapses_duplicate_nonce_mint_vector() {
         // Same account, same nonce, different recipients — the double-spend/mint input.
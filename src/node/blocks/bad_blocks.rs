@@ -0,0 +1,123 @@
+use lru::LruCache;
+use serde::Serialize;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use super::block::Block;
+use crate::node::time_utils::get_current_timespan;
+
+/// A block that failed validation on top of its own parent, so it can never become valid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BadBlock {
+    pub hash: String,
+    pub index: usize,
+    pub author: String,
+    pub timestamp: u64,
+    pub reason: String,
+    /// Unix seconds of the first and latest time the block was received.
+    pub first_seen: u64,
+    pub last_seen: u64,
+    /// How many times it was received again after the first rejection.
+    pub repeats: u64,
+}
+
+/// Recently rejected blocks, so a block pushed again is turned away without re-validating
+/// it. The block hash covers neither the author, the timestamp nor the signature, so all of
+/// them are part of the key: an honest block must not be refused because a tampered copy
+/// with the same hash was rejected first.
+#[derive(Debug)]
+pub struct BadBlocks {
+    blocks: Option<Mutex<LruCache<String, BadBlock>>>,
+}
+
+fn key(block: &Block) -> String {
+    format!(
+        "{}/{}/{}/{}/{}/{}",
+        block.hash,
+        block.author,
+        block.timestamp,
+        block.signature_r,
+        block.signature_s,
+        block.signature_v
+    )
+}
+
+impl BadBlocks {
+    /// Keeps the `capacity` most recently rejected blocks; 0 disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            blocks: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    pub fn insert(&self, block: &Block, reason: &str) {
+        if let Some(blocks) = &self.blocks {
+            let now = get_current_timespan();
+            blocks.lock().unwrap().put(
+                key(block),
+                BadBlock {
+                    hash: block.hash.clone(),
+                    index: block.index,
+                    author: block.author.clone(),
+                    timestamp: block.timestamp,
+                    reason: reason.to_string(),
+                    first_seen: now,
+                    last_seen: now,
+                    repeats: 0,
+                },
+            );
+        }
+    }
+
+    /// Why `block` was rejected before, counting this as another receipt.
+    pub fn rejection(&self, block: &Block) -> Option<String> {
+        let mut blocks = self.blocks.as_ref()?.lock().unwrap();
+        let bad = blocks.get_mut(&key(block))?;
+        bad.last_seen = get_current_timespan();
+        bad.repeats += 1;
+        Some(bad.reason.clone())
+    }
+
+    /// The recorded blocks, most recently received first.
+    pub fn list(&self) -> Vec<BadBlock> {
+        match &self.blocks {
+            Some(blocks) => blocks
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, bad)| bad.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_exact_rejected_block_is_turned_away() {
+        let bad_blocks = BadBlocks::new(2);
+        let mut forged = Block::new_block(1, "parent".to_string(), Vec::new());
+        forged.author = "mallory".to_string();
+        let mut honest = forged.clone();
+        honest.author = "alice".to_string();
+        assert_eq!(forged.hash, honest.hash);
+
+        bad_blocks.insert(&forged, "wrong author");
+        assert_eq!(bad_blocks.rejection(&honest), None);
+        assert_eq!(
+            bad_blocks.rejection(&forged).as_deref(),
+            Some("wrong author")
+        );
+        bad_blocks.rejection(&forged);
+
+        let listed = bad_blocks.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].author, "mallory");
+        assert_eq!(listed[0].repeats, 2);
+
+        assert!(BadBlocks::new(0).list().is_empty());
+    }
+}
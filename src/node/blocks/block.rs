@@ -1,20 +1,27 @@
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use tracing::{error, info, warn};
 
-use crate::node::database::Database;
+use crate::node::database::{Database, ReadStore};
+use crate::node::hardfork::HardforkSchedule;
+use crate::node::rlp_encoding::RlpCodec;
+use crate::node::signer::{BlockSigner, Signature};
 use crate::node::time_utils::get_current_timespan;
 use crate::node::account_state::AccountState;
 use crate::node::balance_effect::{
-    persist_block_effects, persist_tx_effects, BalanceEffectKind, StateUpdate,
+    persist_block_effects, persist_tx_effects, BalanceEffect, BalanceEffectKind, StateUpdate,
 };
+use crate::node::transactions::receipt::{ReceiptCommitment, TxLocation};
+use crate::node::transactions::trace::StorageWrite;
 use crate::node::transactions::transaction::Transaction;
 use crate::node::transactions::transaction_pool::TransactionPool;
-use crate::node::{metric, signature_keys};
+use crate::node::{crypto, metric, signature_keys};
 
 use super::block_headers::BlockHeader;
+use super::era;
+use super::merkle::{merkle_proof, merkle_root, MerkleProof};
+use super::state_diff::StateDiff;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, RlpCodec)]
 pub struct Block {
     pub index: usize,
     pub timestamp: u64,
@@ -22,15 +29,32 @@ pub struct Block {
     pub author: String,
     pub signature_r: String,
     pub signature_s: String,
+    #[rlp(as = "u64")]
     pub signature_v: i32,
     pub hash: String,
+    #[rlp(list)]
     pub transactions: Vec<Transaction>,
+    /// Merkle root of the transaction hashes; empty on blocks from before the
+    /// `header_roots` fork.
+    #[serde(default)]
+    #[rlp(default)]
+    pub transactions_root: String,
+    /// Merkle root of the transactions' receipts; empty before `header_roots`.
+    #[serde(default)]
+    #[rlp(default)]
+    pub receipts_root: String,
+    /// The authorities of the next epoch, on the last block of each epoch only; see
+    /// `ValidatorSchedule`.
+    #[serde(default)]
+    #[rlp(list, default)]
+    pub next_authorities: Vec<String>,
 }
 
 impl Block {
-    fn calculate_hash(&self) -> String {
-        let mut hasher = Sha256::new();
-
+    /// The chain's `block_hash` over the index, parent hash, transaction hashes, roots and
+    /// next authorities. Empty ones add nothing, so blocks from before `header_roots` and
+    /// blocks inside an epoch keep their hash.
+    pub fn calculate_hash(&self) -> String {
         let transactions_hash_string = self
             .transactions
             .iter()
@@ -38,12 +62,16 @@ impl Block {
             .collect::<Vec<String>>()
             .join("");
 
-        hasher.update(format!(
-            "{}{}{}",
-            self.index, self.previous_hash, transactions_hash_string
-        ));
-        let result = hasher.finalize();
-        format!("{:x}", result)
+        let preimage = format!(
+            "{}{}{}{}{}{}",
+            self.index,
+            self.previous_hash,
+            transactions_hash_string,
+            self.transactions_root,
+            self.receipts_root,
+            self.next_authorities.join(",")
+        );
+        hex::encode(crypto::suite().block_hash(preimage.as_bytes()))
     }
 
     pub fn new_genesis_block() -> Block {
@@ -57,6 +85,9 @@ impl Block {
             signature_v: 0,
             hash: String::new(),
             transactions: vec![],
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         genesis_block.transactions = Transaction::new_genesis_transactions();
@@ -75,16 +106,149 @@ impl Block {
             signature_v: 0,
             hash: String::new(),
             transactions,
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         block.hash = block.calculate_hash();
         block
     }
 
+    pub fn calculate_transactions_root(&self) -> String {
+        merkle_root(self.transactions.iter().map(|tx| tx.hash.as_bytes()))
+    }
+
+    /// Proof that the transaction `tx_hash` is committed to by `transactions_root`, which a
+    /// light client checks with `MerkleProof::verify` against the header.
+    pub fn transaction_proof(&self, tx_hash: &str) -> Option<MerkleProof> {
+        let index = self.transactions.iter().position(|tx| tx.hash == tx_hash)?;
+        merkle_proof(self.transactions.iter().map(|tx| tx.hash.as_bytes()), index)
+    }
+
+    /// Merkle root of the receipts the transactions produce on `db`, which must hold the
+    /// state before the block. Each leaf is a `ReceiptCommitment`: the transaction hash,
+    /// its execution status and the balance effects it causes.
+    pub fn calculate_receipts_root(
+        &self,
+        db: &Database,
+        ride_request_referrer_fee_percent: u8,
+        ride_offer_referrer_fee_percent: u8,
+    ) -> Result<String, String> {
+        let mut leaves = Vec::with_capacity(self.transactions.len());
+        for tx in &self.transactions {
+            let logs: Vec<BalanceEffect> = tx
+                .state_transaction(
+                    db,
+                    ride_request_referrer_fee_percent,
+                    ride_offer_referrer_fee_percent,
+                )
+                .into_iter()
+                .filter_map(|update| update.effect)
+                .collect();
+            let receipt = ReceiptCommitment {
+                transaction_hash: &tx.hash,
+                status: tx.execution_status(db),
+                logs: &logs,
+            };
+            leaves.push(
+                serde_json::to_vec(&receipt)
+                    .map_err(|e| format!("Failed to serialize receipt of {}: {}", tx.hash, e))?,
+            );
+        }
+        Ok(merkle_root(leaves))
+    }
+
+    /// Sets both roots on `db`'s state before the block, then rehashes; sign afterwards.
+    pub fn commit_roots(
+        &mut self,
+        db: &Database,
+        ride_request_referrer_fee_percent: u8,
+        ride_offer_referrer_fee_percent: u8,
+    ) -> Result<(), String> {
+        self.transactions_root = self.calculate_transactions_root();
+        self.receipts_root = self.calculate_receipts_root(
+            db,
+            ride_request_referrer_fee_percent,
+            ride_offer_referrer_fee_percent,
+        )?;
+        self.hash = self.calculate_hash();
+        Ok(())
+    }
+
+    /// Checks the roots the block commits to, if any, against its transactions and the
+    /// receipts they produce on `db`.
+    pub fn verify_roots(
+        &self,
+        db: &Database,
+        ride_request_referrer_fee_percent: u8,
+        ride_offer_referrer_fee_percent: u8,
+    ) -> Result<(), String> {
+        if !self.transactions_root.is_empty()
+            && self.transactions_root != self.calculate_transactions_root()
+        {
+            return Err(format!(
+                "Invalid block: The transactions root of block {} does not match its transactions.",
+                self.index
+            ));
+        }
+        if !self.receipts_root.is_empty()
+            && self.receipts_root
+                != self.calculate_receipts_root(
+                    db,
+                    ride_request_referrer_fee_percent,
+                    ride_offer_referrer_fee_percent,
+                )?
+        {
+            return Err(format!(
+                "Invalid block: The receipts root of block {} does not match its receipts.",
+                self.index
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks a downloaded body against the header it was advertised with: the same
+    /// header fields, a hash that covers its transactions and roots, and transactions
+    /// matching the transactions root.
+    pub fn verify_against_header(&self, header: &BlockHeader) -> Result<(), String> {
+        if self.to_block_header() != *header {
+            return Err(format!(
+                "body of block {} differs from its header",
+                header.index
+            ));
+        }
+        if self.calculate_hash() != self.hash {
+            return Err(format!(
+                "body of block {} does not hash to {}",
+                self.index, self.hash
+            ));
+        }
+        if !self.transactions_root.is_empty()
+            && self.transactions_root != self.calculate_transactions_root()
+        {
+            return Err(format!(
+                "transactions of block {} don't match its transactions root",
+                self.index
+            ));
+        }
+        Ok(())
+    }
+
     pub fn sign(&mut self, author: &str, secret_key: &str) {
         let hash_bytes = self.hash.as_bytes();
-        let (r, s, v) = signature_keys::SignatureKeys::sign(secret_key, hash_bytes);
+        let signature = signature_keys::SignatureKeys::sign(secret_key, hash_bytes);
+        self.set_signature(author, signature);
+    }
+
+    /// Signs with the node's key or a remote signer; fails if the signer does.
+    pub fn sign_with(&mut self, author: &str, signer: &BlockSigner) -> Result<(), String> {
+        let signature = signer.sign(author, self.hash.as_bytes())?;
+        self.set_signature(author, signature);
+        Ok(())
+    }
 
+    fn set_signature(&mut self, author: &str, (r, s, v): Signature) {
         self.signature_r = r;
         self.signature_s = s;
         self.signature_v = v;
@@ -101,7 +265,7 @@ impl Block {
         signature_keys::SignatureKeys::verify(author, data, r, s, v)
     }
 
-    pub fn get_latest_block(db: &Database) -> Result<Option<Block>, String> {
+    pub fn get_latest_block(db: &impl ReadStore) -> Result<Option<Block>, String> {
         match db.get("blockchain", b"blockchain_latest_block") {
             Ok(Some(value)) => {
                 let block_str = String::from_utf8(value)
@@ -115,7 +279,11 @@ impl Block {
         }
     }
 
-    pub fn validate_block(&self, db: &Database) -> Result<bool, String> {
+    pub fn validate_block(
+        &self,
+        db: &Database,
+        hardforks: &HardforkSchedule,
+    ) -> Result<bool, String> {
         match Block::get_latest_block(db)? {
             Some(latest_block) => {
                 match self.verify_signature() {
@@ -145,6 +313,8 @@ impl Block {
                     ));
                 }
 
+                hardforks.validate_block(self, &latest_block)?;
+
                 Ok(true)
             }
             None => Ok(true),
@@ -156,8 +326,9 @@ impl Block {
             Ok(entries) => {
                 let mut blocks = Vec::new();
 
-                for (_key, value) in entries {
-                    match serde_json::from_slice::<Block>(&value) {
+                // The column family also holds the `tx_location_*` index and `header_*` copies.
+                for (_key, value) in entries.iter().filter(|(key, _)| key.starts_with(b"block_")) {
+                    match serde_json::from_slice::<Block>(value) {
                         Ok(block) => {
                             blocks.push(block);
                         }
@@ -173,42 +344,40 @@ impl Block {
         }
     }
 
-    pub fn get_blocks_with_limit_and_skip(
-        db: &Database,
+    /// Stored blocks from `start_index + skip`, at most `limit` of them, read one at a
+    /// time as the iterator is advanced; see `BlockIter`.
+    pub fn iter_blocks<S: ReadStore>(
+        db: &S,
         start_index: usize,
         skip: usize,
         limit: usize,
-    ) -> Result<Vec<Block>, String> {
-        let mut blocks = Vec::new();
-        // saturating so a peer-supplied huge skip/limit can't overflow-panic here.
-        let start = start_index.saturating_add(skip);
-        let end_index = start.saturating_add(limit);
+    ) -> BlockIter<'_, S> {
+        BlockIter::new(db, start_index, skip, limit, read_block)
+    }
 
-        for index in start..end_index {
-            let key = format!("block_{}", index);
-            match db.get("block", key.as_bytes()) {
-                Ok(Some(value)) => match serde_json::from_slice::<Block>(&value) {
-                    Ok(block) => {
-                        blocks.push(block);
-                    }
-                    Err(e) => {
-                        return Err(format!("Failed to deserialize block {}: {}", key, e));
-                    }
-                },
-                Ok(None) => {
-                    // Stop if we reach a point where no more blocks are found
-                    break;
-                }
-                Err(e) => {
-                    return Err(format!("Failed to retrieve block {}: {}", key, e));
-                }
-            }
-        }
+    /// Headers of the same range as `iter_blocks`, read without deserializing bodies.
+    pub fn iter_headers<S: ReadStore>(
+        db: &S,
+        start_index: usize,
+        skip: usize,
+        limit: usize,
+    ) -> BlockIter<'_, S, BlockHeader> {
+        BlockIter::new(db, start_index, skip, limit, read_header)
+    }
 
-        Ok(blocks)
+    pub fn get_blocks_with_limit_and_skip(
+        db: &impl ReadStore,
+        start_index: usize,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<Block>, String> {
+        Self::iter_blocks(db, start_index, skip, limit).collect()
     }
 
-    pub fn get_blocks_by_indexes(db: &Database, indexes: Vec<usize>) -> Result<Vec<Block>, String> {
+    pub fn get_blocks_by_indexes(
+        db: &impl ReadStore,
+        indexes: Vec<usize>,
+    ) -> Result<Vec<Block>, String> {
         let mut blocks = Vec::new();
 
         for index in indexes {
@@ -222,9 +391,10 @@ impl Block {
                         return Err(format!("Failed to deserialize block {}: {}", key, e));
                     }
                 },
-                Ok(None) => {
-                    return Err(format!("Block {} not found in database", index));
-                }
+                Ok(None) => match read_archived_block(db, index)? {
+                    Some(block) => blocks.push(block),
+                    None => return Err(format!("Block {} not found in database", index)),
+                },
                 Err(e) => {
                     return Err(format!("Failed to retrieve block {}: {}", key, e));
                 }
@@ -244,6 +414,10 @@ impl Block {
         keys.push(block_key);
         values.push(block_value);
 
+        // And its header on its own, so header reads skip the body
+        keys.push(BlockHeader::construct_key(self.index));
+        values.push(serde_json::to_vec(&self.to_block_header()).unwrap());
+
         Some((keys, values))
     }
 
@@ -271,7 +445,7 @@ impl Block {
             Ok(None) => {
                 info!("Genesis block does not exist, creating new one...");
                 let genesis_block = Self::new_genesis_block();
-                if let Err(e) = Self::add_block_to_chain(db, &genesis_block, 0, 0, 0) {
+                if let Err(e) = Self::add_block_to_chain(db, &genesis_block, 0, 0, 0, false) {
                     panic!("Failed to import genesis block: {}", e);
                 }
             }
@@ -279,7 +453,7 @@ impl Block {
         }
     }
 
-    pub fn get_genesis_block(db: &Database) -> Result<Option<Block>, String> {
+    pub fn get_genesis_block(db: &impl ReadStore) -> Result<Option<Block>, String> {
         match db.get("block", b"block_0") {
             Ok(Some(value)) => {
                 let block_str = String::from_utf8(value)
@@ -299,6 +473,7 @@ impl Block {
         block_reward_amount: u64,
         ride_request_referrer_fee_percent: u8,
         ride_offer_referrer_fee_percent: u8,
+        persist_state_diff: bool,
     ) -> Result<(), String> {
         // Storage for keys and values
         let mut cf_storage: Vec<String> = Vec::new();
@@ -307,6 +482,7 @@ impl Block {
 
         let mut operations: Vec<(&str, &[u8], Option<&[u8]>)> = Vec::new();
         let mut tx_keys_to_delete: Vec<Vec<u8>> = Vec::new(); // Store tx keys to delete
+        let mut state_writes: Vec<StorageWrite> = Vec::new(); // Only with persist_state_diff
 
         // Handle block state
         if let Some((block_keys, block_values)) = block.state_block() {
@@ -343,6 +519,10 @@ impl Block {
             let mut tx_effects = Vec::new();
             for update in updates {
                 if let Some((key, value)) = update.storage {
+                    if persist_state_diff {
+                        let before = db.get("state", &key)?;
+                        state_writes.push(StorageWrite::new(&key, before.as_deref(), &value));
+                    }
                     cf_storage.push("state".to_string());
                     keys_storage.push(key);
                     values_storage.push(value);
@@ -367,9 +547,20 @@ impl Block {
                 }
             }
 
+            // Index the transaction's position for lookups by hash
+            let location = TxLocation {
+                block_index: block.index,
+                tx_index,
+            };
+            cf_storage.push("block".to_string());
+            keys_storage.push(TxLocation::construct_key(&tx.hash));
+            values_storage.push(serde_json::to_string(&location).unwrap().into_bytes());
+
             // Prepare keys for deletion from tx_pool
             let tx_key = TransactionPool::construct_tx_pool_key(&tx.hash);
             tx_keys_to_delete.push(tx_key);
+            tx_keys_to_delete.push(TransactionPool::construct_local_key(&tx.hash));
+            tx_keys_to_delete.push(TransactionPool::construct_admission_key(&tx.hash));
         }
 
         // Mint reward for non-genesis block author.
@@ -382,6 +573,14 @@ impl Block {
                 &db,
             );
             if let Some((author_reward_key, author_reward_value)) = reward_update.storage {
+                if persist_state_diff {
+                    let before = db.get("state", &author_reward_key)?;
+                    state_writes.push(StorageWrite::new(
+                        &author_reward_key,
+                        before.as_deref(),
+                        &author_reward_value,
+                    ));
+                }
                 cf_storage.push("state".to_string());
                 keys_storage.push(author_reward_key);
                 values_storage.push(author_reward_value);
@@ -399,6 +598,13 @@ impl Block {
             }
         }
 
+        if persist_state_diff {
+            let diff = StateDiff::from_writes(block.index, state_writes);
+            cf_storage.push("block".to_string());
+            keys_storage.push(StateDiff::construct_key(block.index));
+            values_storage.push(serde_json::to_string(&diff).unwrap().into_bytes());
+        }
+
         // Prepare operations for database write
         for ((key, value), cf_name) in keys_storage
             .iter()
@@ -421,20 +627,25 @@ impl Block {
                     block.hash, block.index
                 );
 
-                metric::LATEST_BLOCK_INDEX.set(block.index as i64);
-
-                metric::LATEST_BLOCK.clear();
-                metric::LATEST_BLOCK
-                    .get_or_create(&metric::BlockLabels {
-                        block_hash: block.hash.to_string(),
-                    })
-                    .set(block.index as i64);
+                Self::record_latest_block(block);
                 Ok(())
             }
             Err(e) => Err(format!("Failed add_block_to_chain: {}", e)),
         }
     }
 
+    /// Reports `block` as the chain head in the metrics.
+    pub fn record_latest_block(block: &Block) {
+        metric::LATEST_BLOCK_INDEX.set(block.index as i64);
+
+        metric::LATEST_BLOCK.clear();
+        metric::LATEST_BLOCK
+            .get_or_create(&metric::BlockLabels {
+                block_hash: block.hash.to_string(),
+            })
+            .set(block.index as i64);
+    }
+
     pub fn to_block_header(&self) -> BlockHeader {
         BlockHeader {
             index: self.index,
@@ -444,6 +655,103 @@ impl Block {
             signature_s: self.signature_s.clone(),
             signature_v: self.signature_v,
             hash: self.hash.clone(),
+            transactions_root: self.transactions_root.clone(),
+            receipts_root: self.receipts_root.clone(),
+            next_authorities: self.next_authorities.clone(),
+        }
+    }
+}
+
+fn read_block<S: ReadStore>(db: &S, index: usize) -> Result<Option<Block>, String> {
+    let key = format!("block_{}", index);
+    match db.get("block", key.as_bytes()) {
+        Ok(Some(value)) => serde_json::from_slice::<Block>(&value)
+            .map(Some)
+            .map_err(|e| format!("Failed to deserialize block {}: {}", key, e)),
+        Ok(None) => read_archived_block(db, index),
+        Err(e) => Err(format!("Failed to retrieve block {}: {}", key, e)),
+    }
+}
+
+/// A block pruned into the installed era archive. Only served while its header is still
+/// stored and names the same hash, so an archive from another chain or a rolled-back one
+/// never stands in for our blocks.
+fn read_archived_block<S: ReadStore>(db: &S, index: usize) -> Result<Option<Block>, String> {
+    let Some(block) = era::archived_block(index)? else {
+        return Ok(None);
+    };
+    match db.get("block", &BlockHeader::construct_key(index)) {
+        Ok(Some(value)) => {
+            let header = serde_json::from_slice::<BlockHeader>(&value)
+                .map_err(|e| format!("Failed to deserialize header {}: {}", index, e))?;
+            Ok((header.hash == block.hash).then_some(block))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => Err(format!("Failed to retrieve header {}: {}", index, e)),
+    }
+}
+
+/// The stored header, or the header of the full block for blocks imported before headers
+/// were stored on their own.
+fn read_header<S: ReadStore>(db: &S, index: usize) -> Result<Option<BlockHeader>, String> {
+    match db.get("block", &BlockHeader::construct_key(index)) {
+        Ok(Some(value)) => serde_json::from_slice::<BlockHeader>(&value)
+            .map(Some)
+            .map_err(|e| format!("Failed to deserialize header {}: {}", index, e)),
+        Ok(None) => Ok(read_block(db, index)?.map(|block| block.to_block_header())),
+        Err(e) => Err(format!("Failed to retrieve header {}: {}", index, e)),
+    }
+}
+
+/// Blocks (or just their headers) read lazily from storage, so a caller keeping only
+/// part of each never holds the whole range in memory. Ends at the first missing block,
+/// the chain tip for a range running past it, or right after yielding a read error.
+pub struct BlockIter<'a, S: ReadStore, T = Block> {
+    db: &'a S,
+    next: usize,
+    end: usize,
+    read: fn(&S, usize) -> Result<Option<T>, String>,
+}
+
+impl<'a, S: ReadStore, T> BlockIter<'a, S, T> {
+    fn new(
+        db: &'a S,
+        start_index: usize,
+        skip: usize,
+        limit: usize,
+        read: fn(&S, usize) -> Result<Option<T>, String>,
+    ) -> Self {
+        // saturating so a peer-supplied huge skip/limit can't overflow-panic here.
+        let next = start_index.saturating_add(skip);
+        Self {
+            db,
+            next,
+            end: next.saturating_add(limit),
+            read,
+        }
+    }
+}
+
+impl<S: ReadStore, T> Iterator for BlockIter<'_, S, T> {
+    type Item = Result<T, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+        match (self.read)(self.db, self.next) {
+            Ok(Some(item)) => {
+                self.next += 1;
+                Some(Ok(item))
+            }
+            Ok(None) => {
+                self.end = self.next;
+                None
+            }
+            Err(e) => {
+                self.next = self.end;
+                Some(Err(e))
+            }
         }
     }
 }
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
 use super::block::Block;
+use crate::node::rlp_encoding::RlpCodec;
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
 pub struct BlockBodies {
+    #[rlp(list)]
     pub blocks: Vec<Block>,
 }
\ No newline at end of file
@@ -0,0 +1,215 @@
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use super::block::Block;
+use super::block_headers::BlockHeader;
+
+/// In-memory LRU caches for recently read or imported blocks, keyed by block index. Sync
+/// peers and RPC clients mostly ask for the same handful of recent blocks, so these sit in
+/// front of RocksDB and skip the read + JSON decode. Entries are keyed by index alone, so
+/// they are only valid while the block at each index stays the same: whatever rewrites
+/// stored blocks (`Blockchain::rewrite_chain`, behind every rollback and reorg) must `clear`
+/// the cache afterwards, as must a failed write batch whose blocks may have been cached
+/// from buffered reads. Imported blocks are only inserted once their write is committed.
+/// A size of 0 disables that cache.
+#[derive(Debug)]
+pub struct BlockCache {
+    headers: Option<Mutex<LruCache<usize, BlockHeader>>>,
+    bodies: Option<Mutex<LruCache<usize, Block>>>,
+    hashes: Option<Mutex<LruCache<usize, String>>>,
+}
+
+fn lru<V>(capacity: usize) -> Option<Mutex<LruCache<usize, V>>> {
+    NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity)))
+}
+
+fn lookup<V: Clone>(cache: &Option<Mutex<LruCache<usize, V>>>, index: usize) -> Option<V> {
+    cache.as_ref()?.lock().unwrap().get(&index).cloned()
+}
+
+fn store<V>(cache: &Option<Mutex<LruCache<usize, V>>>, index: usize, value: V) {
+    if let Some(cache) = cache {
+        cache.lock().unwrap().put(index, value);
+    }
+}
+
+impl BlockCache {
+    pub fn new(header_capacity: usize, body_capacity: usize, hash_capacity: usize) -> Self {
+        Self {
+            headers: lru(header_capacity),
+            bodies: lru(body_capacity),
+            hashes: lru(hash_capacity),
+        }
+    }
+
+    pub fn insert(&self, block: &Block) {
+        store(&self.headers, block.index, block.to_block_header());
+        store(&self.bodies, block.index, block.clone());
+        store(&self.hashes, block.index, block.hash.clone());
+    }
+
+    /// Caches a header and its hash, for reads that never loaded the body.
+    pub fn insert_header(&self, header: &BlockHeader) {
+        store(&self.hashes, header.index, header.hash.clone());
+        store(&self.headers, header.index, header.clone());
+    }
+
+    pub fn clear(&self) {
+        if let Some(cache) = &self.headers {
+            cache.lock().unwrap().clear();
+        }
+        if let Some(cache) = &self.bodies {
+            cache.lock().unwrap().clear();
+        }
+        if let Some(cache) = &self.hashes {
+            cache.lock().unwrap().clear();
+        }
+    }
+
+    pub fn hash(&self, index: usize) -> Option<String> {
+        lookup(&self.hashes, index)
+    }
+
+    /// Blocks for `indexes`, in order. Misses are fetched with a single `load` call and
+    /// cached; `load` keeps the storage semantics (e.g. erroring on a missing block).
+    pub fn blocks_by_indexes(
+        &self,
+        indexes: &[usize],
+        load: impl FnOnce(Vec<usize>) -> Result<Vec<Block>, String>,
+    ) -> Result<Vec<Block>, String> {
+        let mut found: HashMap<usize, Block> = HashMap::new();
+        let mut misses = Vec::new();
+        for &index in indexes {
+            match lookup(&self.bodies, index) {
+                Some(block) => {
+                    found.insert(index, block);
+                }
+                None => misses.push(index),
+            }
+        }
+
+        if !misses.is_empty() {
+            for block in load(misses)? {
+                self.insert(&block);
+                found.insert(block.index, block);
+            }
+        }
+
+        Ok(indexes
+            .iter()
+            .filter_map(|index| found.get(index).cloned())
+            .collect())
+    }
+
+    /// Headers for the contiguous range `start..end`. Served from the cache up to the
+    /// first miss; from there `load(first_miss, end)` streams the headers of the rest of
+    /// the range, which may come back short at the chain tip.
+    pub fn headers_in_range<I>(
+        &self,
+        start: usize,
+        end: usize,
+        load: impl FnOnce(usize, usize) -> I,
+    ) -> Result<Vec<BlockHeader>, String>
+    where
+        I: IntoIterator<Item = Result<BlockHeader, String>>,
+    {
+        let mut headers = Vec::new();
+        for index in start..end {
+            match lookup(&self.headers, index) {
+                Some(header) => headers.push(header),
+                None => {
+                    for header in load(index, end) {
+                        let header = header?;
+                        self.insert_header(&header);
+                        headers.push(header);
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize) -> Block {
+        Block {
+            index,
+            hash: format!("hash_{}", index),
+            ..Block::new_genesis_block()
+        }
+    }
+
+    #[test]
+    fn misses_are_loaded_once_then_served_from_cache() {
+        let cache = BlockCache::new(8, 8, 8);
+        cache.insert(&block(1));
+
+        let blocks = cache
+            .blocks_by_indexes(&[2, 1], |misses| {
+                assert_eq!(misses, vec![2]);
+                Ok(vec![block(2)])
+            })
+            .unwrap();
+        assert_eq!(blocks.iter().map(|b| b.index).collect::<Vec<_>>(), [2, 1]);
+
+        let blocks = cache
+            .blocks_by_indexes(&[1, 2], |_| panic!("everything is cached"))
+            .unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(cache.hash(2), Some("hash_2".to_string()));
+    }
+
+    #[test]
+    fn header_range_loads_from_first_miss_and_stops_at_tip() {
+        let cache = BlockCache::new(8, 0, 0);
+        cache.insert(&block(3));
+
+        let headers = cache
+            .headers_in_range(3, 10, |from, end| {
+                assert_eq!((from, end), (4, 10));
+                vec![
+                    Ok(block(4).to_block_header()),
+                    Ok(block(5).to_block_header()),
+                ]
+            })
+            .unwrap();
+        assert_eq!(
+            headers.iter().map(|h| h.index).collect::<Vec<_>>(),
+            [3, 4, 5]
+        );
+        assert_eq!(cache.hash(4), None, "hash cache disabled");
+
+        let failed = cache.headers_in_range(6, 10, |_, _| {
+            vec![Ok(block(6).to_block_header()), Err("bad".into())]
+        });
+        assert_eq!(failed.unwrap_err(), "bad");
+        let cached = cache.headers_in_range(6, 7, |_, _| Vec::new()).unwrap();
+        assert_eq!(
+            cached[0].index, 6,
+            "headers read before the error stay cached"
+        );
+    }
+
+    #[test]
+    fn least_recently_used_blocks_are_evicted() {
+        let cache = BlockCache::new(0, 2, 0);
+        for index in 1..=3 {
+            cache.insert(&block(index));
+        }
+
+        let mut loaded = Vec::new();
+        cache
+            .blocks_by_indexes(&[1, 2, 3], |misses| {
+                loaded = misses.clone();
+                Ok(misses.into_iter().map(block).collect())
+            })
+            .unwrap();
+        assert_eq!(loaded, vec![1]);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::node::blocks::block::Block;
+use crate::node::rlp_encoding::encode;
+use crate::node::transactions::transaction::Transaction;
+
+/// Gas every transaction costs before its size is counted.
+pub const TX_BASE_GAS: u64 = 21_000;
+/// Gas per byte of a transaction's RLP encoding.
+pub const GAS_PER_BYTE: u64 = 16;
+/// Room left for the header fields when packing by size; an encoded header with
+/// signature and roots takes well under this.
+const HEADER_RESERVE_BYTES: usize = 1024;
+
+/// Gas a transaction uses: the base cost plus its encoded size. Execution has no
+/// metering, so size is what makes one transaction heavier than another.
+pub fn transaction_gas(tx: &Transaction) -> u64 {
+    TX_BASE_GAS + GAS_PER_BYTE * encode(tx).len() as u64
+}
+
+/// Consensus caps on a block, applied when packing authored blocks and when validating
+/// every imported one, so a producer can't publish blocks other nodes choke on. Every
+/// node of a network needs the same limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockLimits {
+    /// Largest RLP-encoded block; 0 is unlimited.
+    pub max_size_bytes: usize,
+    /// Most gas the transactions of a block may use together; 0 is unlimited.
+    pub gas_limit: u64,
+}
+
+impl BlockLimits {
+    pub fn check(&self, block: &Block) -> Result<(), String> {
+        let size = encode(block).len();
+        if self.max_size_bytes > 0 && size > self.max_size_bytes {
+            return Err(format!(
+                "Invalid block: Block {} is {} bytes, more than the {} allowed.",
+                block.index, size, self.max_size_bytes
+            ));
+        }
+        let gas: u64 = block.transactions.iter().map(transaction_gas).sum();
+        if self.gas_limit > 0 && gas > self.gas_limit {
+            return Err(format!(
+                "Invalid block: Block {} uses {} gas, more than the limit of {}.",
+                block.index, gas, self.gas_limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `tx` could fit in a block at all; larger ones are kept out of the pool.
+    pub fn check_transaction(&self, tx: &Transaction) -> Result<(), String> {
+        let size = encode(tx).len();
+        if self.max_size_bytes > 0 && size + HEADER_RESERVE_BYTES > self.max_size_bytes {
+            return Err(format!(
+                "Transaction {} is {} bytes, too large for a block",
+                tx.hash, size
+            ));
+        }
+        let gas = transaction_gas(tx);
+        if self.gas_limit > 0 && gas > self.gas_limit {
+            return Err(format!(
+                "Transaction {} uses {} gas, more than the block gas limit of {}",
+                tx.hash, gas, self.gas_limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// The longest prefix of `transactions` a block can hold. The rest stay in the pool
+    /// for later blocks.
+    pub fn pack(&self, mut transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let mut size = HEADER_RESERVE_BYTES;
+        let mut gas = 0u64;
+        let fits = transactions
+            .iter()
+            .take_while(|tx| {
+                size += encode(*tx).len();
+                gas += transaction_gas(tx);
+                (self.max_size_bytes == 0 || size <= self.max_size_bytes)
+                    && (self.gas_limit == 0 || gas <= self.gas_limit)
+            })
+            .count();
+        transactions.truncate(fits);
+        transactions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::transactions::function_call::FunctionCall;
+    use crate::node::transactions::transfer::Transfer;
+
+    fn transactions() -> Vec<Transaction> {
+        (0..3)
+            .map(|nonce| {
+                Transaction::new_transaction(
+                    format!("0x{:040x}", nonce),
+                    nonce,
+                    FunctionCall::Transfer(Transfer {
+                        to: "0xdeb4cfb63db134698e1879ea24904df074726cc0".to_string(),
+                        value: 1,
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packing_stops_at_the_gas_limit() {
+        let txs = transactions();
+        let two = transaction_gas(&txs[0]) + transaction_gas(&txs[1]);
+        let limits = BlockLimits {
+            max_size_bytes: 0,
+            gas_limit: two,
+        };
+        let packed = limits.pack(txs.clone());
+        assert_eq!(packed.len(), 2);
+
+        let mut block = Block::new_block(1, "h0".to_string(), packed);
+        assert!(limits.check(&block).is_ok());
+        block.transactions = txs;
+        assert!(limits.check(&block).unwrap_err().contains("gas"));
+        assert!(BlockLimits::default().check(&block).is_ok(), "unlimited");
+    }
+
+    #[test]
+    fn packed_blocks_stay_under_the_size_limit() {
+        let txs = transactions();
+        let limits = BlockLimits {
+            max_size_bytes: HEADER_RESERVE_BYTES + encode(&txs[0]).len(),
+            gas_limit: 0,
+        };
+        let block = Block::new_block(1, "h0".to_string(), limits.pack(txs.clone()));
+        assert_eq!(block.transactions.len(), 1);
+        assert!(limits.check(&block).is_ok());
+
+        let exact = BlockLimits {
+            max_size_bytes: encode(&block).len(),
+            gas_limit: 0,
+        };
+        let oversized = Block::new_block(1, "h0".to_string(), txs);
+        assert!(exact.check(&oversized).unwrap_err().contains("bytes"));
+        assert!(
+            exact.check_transaction(&oversized.transactions[0]).is_err(),
+            "no room left for a header"
+        );
+    }
+}
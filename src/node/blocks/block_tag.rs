@@ -0,0 +1,45 @@
+use serde::Deserialize;
+
+/// Which block an RPC query reads state at: an explicit index, or one of the named
+/// blocks. Accepts `12`, `"latest"` or `"finalized"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum BlockTag {
+    Index(usize),
+    Named(NamedBlock),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedBlock {
+    Latest,
+    /// Newest block built on by more than half of the authorities; see
+    /// `Aura::finalized_index`.
+    Finalized,
+}
+
+impl Default for BlockTag {
+    fn default() -> Self {
+        BlockTag::Named(NamedBlock::Latest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indexes_and_names() {
+        let parse = |value| serde_json::from_value::<BlockTag>(value);
+        assert_eq!(parse(serde_json::json!(12)).unwrap(), BlockTag::Index(12));
+        assert_eq!(
+            parse(serde_json::json!("finalized")).unwrap(),
+            BlockTag::Named(NamedBlock::Finalized)
+        );
+        assert_eq!(
+            parse(serde_json::json!("latest")).unwrap(),
+            BlockTag::default()
+        );
+        assert!(parse(serde_json::json!("pending")).is_err());
+    }
+}
@@ -0,0 +1,571 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::block::Block;
+use super::merkle::merkle_root;
+use crate::node::rlp_encoding::{decode, encode};
+
+/// Blocks per era file: era `n` holds blocks `n * ERA_BLOCKS` up to `(n + 1) * ERA_BLOCKS`.
+/// Every node must use the same size for era files to be interchangeable.
+pub const ERA_BLOCKS: usize = 8192;
+
+const ERA_EXTENSION: &str = "era";
+
+/// Record types. Each record is an 8-byte header — type and data length as little-endian
+/// u16 and u32, then two zero bytes — followed by the data.
+const VERSION_RECORD: u16 = 0x3265;
+const BLOCK_RECORD: u16 = 0x0001;
+const ACCUMULATOR_RECORD: u16 = 0x0007;
+const INDEX_RECORD: u16 = 0x3266;
+const RECORD_HEADER_LEN: u64 = 8;
+/// The accumulator is one 32-byte hash.
+const ACCUMULATOR_LEN: u64 = 32;
+
+/// An era file as the `/era` listing shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EraInfo {
+    pub era: usize,
+    pub file: String,
+    pub start_index: usize,
+    pub count: usize,
+    /// Merkle root of the era's block hashes, in order.
+    pub accumulator: String,
+}
+
+/// One immutable era file: a version record, the RLP-encoded blocks of one era, the
+/// accumulator over their hashes, and an index of the blocks' offsets that ends the file.
+/// Only the index is held in memory; blocks are read from disk as they are asked for.
+#[derive(Debug)]
+pub struct EraFile {
+    path: PathBuf,
+    start_index: usize,
+    offsets: Vec<u64>,
+    accumulator: String,
+}
+
+fn write_record(out: &mut impl Write, kind: u16, data: &[u8]) -> std::io::Result<u64> {
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.write_all(&[0, 0])?;
+    out.write_all(data)?;
+    Ok(RECORD_HEADER_LEN + data.len() as u64)
+}
+
+fn read_record(file: &mut File, offset: u64, kind: u16) -> Result<Vec<u8>, String> {
+    let mut header = [0u8; RECORD_HEADER_LEN as usize];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut header))
+        .map_err(|e| format!("Failed to read era record at {}: {}", offset, e))?;
+    let found = u16::from_le_bytes([header[0], header[1]]);
+    if found != kind {
+        return Err(format!(
+            "Expected era record {:#06x} at {}, found {:#06x}",
+            kind, offset, found
+        ));
+    }
+    let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    let mut data = vec![0u8; len];
+    file.read_exact(&mut data)
+        .map_err(|e| format!("Failed to read era record at {}: {}", offset, e))?;
+    Ok(data)
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// The accumulator of a run of blocks, computed from their hashes alone so it can be
+/// checked against stored headers without the bodies.
+pub fn accumulator<'a>(hashes: impl IntoIterator<Item = &'a str>) -> String {
+    merkle_root(hashes.into_iter().map(str::as_bytes))
+}
+
+impl EraFile {
+    /// Writes `blocks` to a new file at `path` and returns the accumulator. The file is
+    /// written under a temporary name and renamed into place, and an existing file is
+    /// never replaced.
+    pub fn write(path: &Path, blocks: &[Block]) -> Result<String, String> {
+        if path.exists() {
+            return Err(format!("Era file {} already exists", path.display()));
+        }
+        let accumulator = accumulator(blocks.iter().map(|block| block.hash.as_str()));
+        let accumulator_bytes = hex::decode(&accumulator)
+            .map_err(|e| format!("Failed to decode accumulator: {}", e))?;
+
+        if accumulator_bytes.len() as u64 != ACCUMULATOR_LEN {
+            return Err(format!(
+                "Era accumulators are {} bytes, not {}",
+                ACCUMULATOR_LEN,
+                accumulator_bytes.len()
+            ));
+        }
+
+        let temp = path.with_extension("era.tmp");
+        let written = Self::write_records(&temp, blocks, &accumulator_bytes)
+            .and_then(|_| fs::rename(&temp, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(format!(
+                "Failed to write era file {}: {}",
+                path.display(),
+                e
+            ));
+        }
+        Ok(accumulator)
+    }
+
+    fn write_records(path: &Path, blocks: &[Block], accumulator: &[u8]) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut offset = write_record(&mut out, VERSION_RECORD, &[])?;
+        let mut offsets = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            offsets.push(offset);
+            offset += write_record(&mut out, BLOCK_RECORD, &encode(block))?;
+        }
+        write_record(&mut out, ACCUMULATOR_RECORD, accumulator)?;
+
+        let start_index = blocks.first().map_or(0, |block| block.index);
+        let mut index = Vec::with_capacity(16 + 8 * offsets.len());
+        index.extend_from_slice(&(start_index as u64).to_le_bytes());
+        for offset in &offsets {
+            index.extend_from_slice(&offset.to_le_bytes());
+        }
+        index.extend_from_slice(&(offsets.len() as u64).to_le_bytes());
+        write_record(&mut out, INDEX_RECORD, &index)?;
+        out.into_inner()?.sync_all()
+    }
+
+    /// Reads the index and accumulator at the end of the file at `path`.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut file = File::open(path)
+            .map_err(|e| format!("Failed to open era file {}: {}", path.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read era file {}: {}", path.display(), e))?
+            .len();
+        let mut count = [0u8; 8];
+        if len < RECORD_HEADER_LEN + 16 {
+            return Err(format!("Era file {} is truncated", path.display()));
+        }
+        file.seek(SeekFrom::Start(len - 8))
+            .and_then(|_| file.read_exact(&mut count))
+            .map_err(|e| format!("Failed to read era file {}: {}", path.display(), e))?;
+        let count = u64::from_le_bytes(count);
+        let index_len = count
+            .checked_mul(8)
+            .and_then(|offsets| offsets.checked_add(16 + RECORD_HEADER_LEN))
+            .filter(|index_len| *index_len + RECORD_HEADER_LEN + ACCUMULATOR_LEN <= len)
+            .ok_or_else(|| format!("Era file {} has a corrupt index", path.display()))?;
+        let index = read_record(&mut file, len - index_len, INDEX_RECORD)?;
+        let offsets: Vec<u64> = (0..count as usize)
+            .map(|i| read_u64(&index, 8 + 8 * i))
+            .collect();
+
+        let accumulator_offset = len - index_len - RECORD_HEADER_LEN - ACCUMULATOR_LEN;
+        let accumulator = read_record(&mut file, accumulator_offset, ACCUMULATOR_RECORD)?;
+        Ok(EraFile {
+            path: path.to_path_buf(),
+            start_index: read_u64(&index, 0) as usize,
+            offsets,
+            accumulator: hex::encode(accumulator),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn start_index(&self) -> usize {
+        self.start_index
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn accumulator(&self) -> &str {
+        &self.accumulator
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        index >= self.start_index && index - self.start_index < self.offsets.len()
+    }
+
+    pub fn block(&self, index: usize) -> Result<Option<Block>, String> {
+        if !self.contains(index) {
+            return Ok(None);
+        }
+        let mut file = File::open(&self.path)
+            .map_err(|e| format!("Failed to open era file {}: {}", self.path.display(), e))?;
+        let data = read_record(
+            &mut file,
+            self.offsets[index - self.start_index],
+            BLOCK_RECORD,
+        )?;
+        decode::<Block>(&data)
+            .map(Some)
+            .map_err(|e| format!("Failed to decode block {} from era file: {:?}", index, e))
+    }
+
+    /// Reads every block and checks that they are consecutive, that each links to the one
+    /// before, and that their hashes match the accumulator.
+    pub fn verify(&self) -> Result<Vec<Block>, String> {
+        let mut file = File::open(&self.path)
+            .map_err(|e| format!("Failed to open era file {}: {}", self.path.display(), e))?;
+        let mut blocks: Vec<Block> = Vec::with_capacity(self.offsets.len());
+        for (i, offset) in self.offsets.iter().enumerate() {
+            let data = read_record(&mut file, *offset, BLOCK_RECORD)?;
+            let block = decode::<Block>(&data)
+                .map_err(|e| format!("Failed to decode block {}: {:?}", self.start_index + i, e))?;
+            if block.index != self.start_index + i {
+                return Err(format!(
+                    "Era file {} holds block {} where block {} belongs",
+                    self.path.display(),
+                    block.index,
+                    self.start_index + i
+                ));
+            }
+            if let Some(parent) = blocks.last() {
+                if block.previous_hash != parent.hash {
+                    return Err(format!(
+                        "Block {} in era file {} does not link to block {}",
+                        block.index,
+                        self.path.display(),
+                        parent.index
+                    ));
+                }
+            }
+            blocks.push(block);
+        }
+        if accumulator(blocks.iter().map(|block| block.hash.as_str())) != self.accumulator {
+            return Err(format!(
+                "Era file {} does not match its accumulator",
+                self.path.display()
+            ));
+        }
+        Ok(blocks)
+    }
+}
+
+/// A directory of era files of one chain, named `<chain_id>-<era:05>-<accumulator[..8]>.era`
+/// so that files from different chains or with different contents never collide. Files
+/// are only ever added.
+#[derive(Debug)]
+pub struct EraArchive {
+    dir: PathBuf,
+    chain_id: String,
+    blocks_per_era: usize,
+    files: RwLock<BTreeMap<usize, Arc<EraFile>>>,
+}
+
+impl EraArchive {
+    /// Opens the archive in `dir` with `ERA_BLOCKS` per era, creating the directory.
+    pub fn open(dir: impl Into<PathBuf>, chain_id: &str) -> Result<Self, String> {
+        Self::with_blocks_per_era(dir, chain_id, ERA_BLOCKS)
+    }
+
+    /// `open` with another era size, for tests; files of another size are rejected.
+    pub fn with_blocks_per_era(
+        dir: impl Into<PathBuf>,
+        chain_id: &str,
+        blocks_per_era: usize,
+    ) -> Result<Self, String> {
+        let dir = dir.into();
+        let blocks_per_era = blocks_per_era.max(1);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create era directory {}: {}", dir.display(), e))?;
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read era directory {}: {}", dir.display(), e))?;
+        let mut files = BTreeMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read era directory {}: {}", dir.display(), e))?
+                .path();
+            let Some(era) = Self::era_of(&path, chain_id) else {
+                continue;
+            };
+            files.insert(era, Arc::new(EraFile::open(&path)?));
+        }
+        let archive = EraArchive {
+            dir,
+            chain_id: chain_id.to_string(),
+            blocks_per_era,
+            files: RwLock::new(BTreeMap::new()),
+        };
+        for (era, file) in &files {
+            archive.check_holds_era(file, *era)?;
+        }
+        *archive.files.write().unwrap() = files;
+        Ok(archive)
+    }
+
+    fn era_of(path: &Path, chain_id: &str) -> Option<usize> {
+        if path.extension()? != ERA_EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let (era, _accumulator) = stem
+            .strip_prefix(chain_id)?
+            .strip_prefix('-')?
+            .split_once('-')?;
+        era.parse().ok()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn blocks_per_era(&self) -> usize {
+        self.blocks_per_era
+    }
+
+    /// The blocks era `era` holds.
+    pub fn era_range(&self, era: usize) -> std::ops::Range<usize> {
+        era * self.blocks_per_era..(era + 1) * self.blocks_per_era
+    }
+
+    pub fn contains_era(&self, era: usize) -> bool {
+        self.files.read().unwrap().contains_key(&era)
+    }
+
+    pub fn file(&self, era: usize) -> Option<Arc<EraFile>> {
+        self.files.read().unwrap().get(&era).cloned()
+    }
+
+    pub fn eras(&self) -> Vec<EraInfo> {
+        self.files
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(era, file)| Self::info(*era, file))
+            .collect()
+    }
+
+    fn info(era: usize, file: &EraFile) -> EraInfo {
+        EraInfo {
+            era,
+            file: file
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            start_index: file.start_index,
+            count: file.len(),
+            accumulator: file.accumulator.clone(),
+        }
+    }
+
+    /// Writes era `era` from `blocks`, which must be exactly its blocks in order.
+    pub fn freeze(&self, era: usize, blocks: &[Block]) -> Result<EraInfo, String> {
+        let range = self.era_range(era);
+        let indexes_match = blocks.len() == self.blocks_per_era
+            && blocks
+                .iter()
+                .zip(range.clone())
+                .all(|(block, index)| block.index == index);
+        if !indexes_match {
+            return Err(format!(
+                "Era {} needs blocks {} to {}",
+                era,
+                range.start,
+                range.end - 1
+            ));
+        }
+        if self.contains_era(era) {
+            return Err(format!("Era {} is already frozen", era));
+        }
+        let accumulator = accumulator(blocks.iter().map(|block| block.hash.as_str()));
+        let path = self.file_path(era, &accumulator);
+        EraFile::write(&path, blocks)?;
+        let file = EraFile::open(&path)?;
+        let info = Self::info(era, &file);
+        self.files.write().unwrap().insert(era, Arc::new(file));
+        Ok(info)
+    }
+
+    /// Adds era `era` from the bytes of an era file fetched elsewhere, once it verifies
+    /// and its accumulator is `accumulator`.
+    pub fn add_file(&self, era: usize, bytes: &[u8], accumulator: &str) -> Result<EraInfo, String> {
+        if self.contains_era(era) {
+            return Err(format!("Era {} is already archived", era));
+        }
+        let temp = self
+            .dir
+            .join(format!("{}-{:05}.download", self.chain_id, era));
+        fs::write(&temp, bytes)
+            .map_err(|e| format!("Failed to write era file {}: {}", temp.display(), e))?;
+        let added = EraFile::open(&temp).and_then(|file| {
+            file.verify()?;
+            if file.accumulator() != accumulator {
+                return Err(format!(
+                    "Era {} has accumulator {}, not {}",
+                    era,
+                    file.accumulator(),
+                    accumulator
+                ));
+            }
+            self.check_holds_era(&file, era)?;
+            let path = self.file_path(era, accumulator);
+            fs::rename(&temp, &path)
+                .map_err(|e| format!("Failed to write era file {}: {}", path.display(), e))?;
+            EraFile::open(&path)
+        });
+        let file = match added {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        };
+        let info = Self::info(era, &file);
+        self.files.write().unwrap().insert(era, Arc::new(file));
+        Ok(info)
+    }
+
+    fn file_path(&self, era: usize, accumulator: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}-{:05}-{}.{}",
+            self.chain_id,
+            era,
+            &accumulator[..8],
+            ERA_EXTENSION
+        ))
+    }
+
+    fn check_holds_era(&self, file: &EraFile, era: usize) -> Result<(), String> {
+        if file.start_index != era * self.blocks_per_era || file.len() != self.blocks_per_era {
+            return Err(format!(
+                "Era file {} does not hold the {} blocks of era {}",
+                file.path.display(),
+                self.blocks_per_era,
+                era
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn block(&self, index: usize) -> Result<Option<Block>, String> {
+        match self.file(index / self.blocks_per_era) {
+            Some(file) => file.block(index),
+            None => Ok(None),
+        }
+    }
+}
+
+static ARCHIVE: RwLock<Option<Arc<EraArchive>>> = RwLock::new(None);
+
+/// Makes `archive` the place block reads fall back to for blocks pruned from the
+/// database; see `Blockchain::freeze_blocks`.
+pub fn install_archive(archive: Arc<EraArchive>) {
+    *ARCHIVE.write().unwrap() = Some(archive);
+}
+
+pub fn installed_archive() -> Option<Arc<EraArchive>> {
+    ARCHIVE.read().unwrap().clone()
+}
+
+/// Block `index` from the installed archive, if there is one and it holds the block.
+pub fn archived_block(index: usize) -> Result<Option<Block>, String> {
+    match installed_archive() {
+        Some(archive) => archive.block(index),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn chain(len: usize) -> Vec<Block> {
+        let mut blocks = vec![Block::new_genesis_block()];
+        for index in 1..len {
+            let previous_hash = blocks[index - 1].hash.clone();
+            let mut block = Block::new_block(index, previous_hash, Vec::new());
+            block.hash = block.calculate_hash();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn frozen_eras_are_read_back_and_verified() {
+        let dir = TempDir::new("clutch-era-test");
+        let archive = EraArchive::with_blocks_per_era(&dir.0, "clutch-test", 4).unwrap();
+        let blocks = chain(8);
+
+        let info = archive.freeze(1, &blocks[4..8]).unwrap();
+        assert_eq!((info.start_index, info.count), (4, 4));
+        assert!(info.file.starts_with("clutch-test-00001-"));
+        assert!(archive.freeze(1, &blocks[4..8]).is_err());
+        assert!(archive.freeze(0, &blocks[1..5]).is_err());
+
+        let reopened = EraArchive::with_blocks_per_era(&dir.0, "clutch-test", 4).unwrap();
+        assert_eq!(reopened.eras(), vec![info]);
+        assert_eq!(reopened.block(6).unwrap().unwrap().hash, blocks[6].hash);
+        assert!(reopened.block(3).unwrap().is_none());
+
+        let file = reopened.file(1).unwrap();
+        let verified = file.verify().unwrap();
+        assert_eq!(verified.len(), 4);
+        assert_eq!(
+            file.accumulator(),
+            accumulator(blocks[4..8].iter().map(|block| block.hash.as_str()))
+        );
+        assert!(EraArchive::with_blocks_per_era(&dir.0, "clutch-test", 8).is_err());
+    }
+
+    #[test]
+    fn fetched_files_join_the_archive_only_with_the_expected_accumulator() {
+        let source_dir = TempDir::new("clutch-era-source-test");
+        let dir = TempDir::new("clutch-era-fetch-test");
+        let source = EraArchive::with_blocks_per_era(&source_dir.0, "clutch-test", 4).unwrap();
+        let archive = EraArchive::with_blocks_per_era(&dir.0, "clutch-test", 4).unwrap();
+        let info = source.freeze(0, &chain(4)).unwrap();
+        let bytes = fs::read(source_dir.0.join(&info.file)).unwrap();
+
+        assert!(archive.add_file(0, &bytes, &"00".repeat(32)).is_err());
+        assert!(archive.add_file(1, &bytes, &info.accumulator).is_err());
+        assert!(archive.eras().is_empty());
+        assert_eq!(archive.add_file(0, &bytes, &info.accumulator), Ok(info));
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_tampered_era_file_fails_verification() {
+        let dir = TempDir::new("clutch-era-tamper-test");
+        fs::create_dir_all(&dir.0).unwrap();
+        let mut blocks = chain(4);
+        let path = dir.0.join("tampered.era");
+        blocks[2].previous_hash = "00".repeat(32);
+        EraFile::write(&path, &blocks).unwrap();
+
+        let file = EraFile::open(&path).unwrap();
+        assert_eq!(file.start_index(), 0);
+        assert!(file.verify().is_err());
+        assert!(EraFile::write(&path, &blocks).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+use crate::node::crypto;
+
+pub use clutch_core::merkle::MerkleProof;
+
+/// `clutch_core::merkle::merkle_root` with the chain's `hash`.
+pub fn merkle_root<L: AsRef<[u8]>>(leaves: impl IntoIterator<Item = L>) -> String {
+    clutch_core::merkle::merkle_root(crypto::suite().hash, leaves)
+}
+
+/// `clutch_core::merkle::merkle_proof` with the chain's `hash`.
+pub fn merkle_proof<L: AsRef<[u8]>>(
+    leaves: impl IntoIterator<Item = L>,
+    index: usize,
+) -> Option<MerkleProof> {
+    clutch_core::merkle::merkle_proof(crypto::suite().hash, leaves, index)
+}
@@ -1,3 +1,10 @@
+pub mod bad_blocks;
 pub mod block;
 pub mod block_bodies;
+pub mod block_cache;
 pub mod block_headers;
+pub mod block_limits;
+pub mod block_tag;
+pub mod era;
+pub mod merkle;
+pub mod state_diff;
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+use crate::node::database::Database;
+use crate::node::transactions::trace::{BlockTrace, StorageWrite};
+
+/// The state a block changed: each key it wrote, with the value before the block and the
+/// value after it, in the order the keys were first written. Stored under
+/// `state_diff_{index}` in the `block` column family when `persist_state_diffs` is on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateDiff {
+    pub block_index: usize,
+    pub changes: Vec<StorageWrite>,
+}
+
+impl StateDiff {
+    /// Collapses `writes`, in execution order, to one change per key, keeping the first
+    /// `before` and the last `after`. Keys that end up unchanged are left out.
+    pub fn from_writes(block_index: usize, writes: impl IntoIterator<Item = StorageWrite>) -> Self {
+        let mut changes: Vec<StorageWrite> = Vec::new();
+        for write in writes {
+            match changes.iter_mut().find(|change| change.key == write.key) {
+                Some(change) => change.after = write.after,
+                None => changes.push(write),
+            }
+        }
+        changes.retain(|change| change.before.as_ref() != Some(&change.after));
+        StateDiff {
+            block_index,
+            changes,
+        }
+    }
+
+    pub fn from_trace(trace: &BlockTrace) -> Self {
+        let steps = trace
+            .transactions
+            .iter()
+            .flat_map(|tx| &tx.steps)
+            .chain(&trace.reward);
+        Self::from_writes(
+            trace.block_index,
+            steps.filter_map(|step| step.write.clone()),
+        )
+    }
+
+    pub fn construct_key(block_index: usize) -> Vec<u8> {
+        format!("state_diff_{}", block_index).into_bytes()
+    }
+
+    pub fn load(db: &Database, block_index: usize) -> Result<Option<Self>, String> {
+        match db.get("block", &Self::construct_key(block_index))? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to decode state diff: {}", e)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(key: &str, before: Option<&str>, after: &str) -> StorageWrite {
+        StorageWrite::new(key.as_bytes(), before.map(str::as_bytes), after.as_bytes())
+    }
+
+    #[test]
+    fn writes_collapse_to_one_change_per_key() {
+        let diff = StateDiff::from_writes(
+            3,
+            vec![
+                write("a", Some("1"), "2"),
+                write("b", None, "5"),
+                write("a", Some("1"), "4"),
+                write("c", Some("7"), "7"),
+            ],
+        );
+        assert_eq!(diff.block_index, 3);
+        assert_eq!(
+            diff.changes,
+            vec![write("a", Some("1"), "4"), write("b", None, "5")]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::{Arc, RwLock as StdRwLock};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::node::configuration::AppConfig;
+use crate::node::p2p_server::{P2PConfig, P2PLimits, P2PServer, P2PServerCommand};
+use crate::node::tracing::set_log_level;
+
+/// The settings a running node applies again when its configuration is reloaded. The
+/// rest of the configuration is only read at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadableConfig {
+    pub log_level: String,
+    pub p2p: P2PLimits,
+    pub rpc_allowed_methods: Vec<String>,
+}
+
+impl ReloadableConfig {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            log_level: config.log_level.clone(),
+            p2p: P2PConfig::from_app_config(config).limits(),
+            rpc_allowed_methods: config.rpc_allowed_methods.clone(),
+        }
+    }
+
+    /// Names of the settings that differ in `new`, as they appear in the config file.
+    pub fn changes(&self, new: &Self) -> Vec<&'static str> {
+        let (old_p2p, new_p2p) = (&self.p2p, &new.p2p);
+        let (old_bandwidth, new_bandwidth) = (&old_p2p.bandwidth, &new_p2p.bandwidth);
+        [
+            ("log_level", self.log_level != new.log_level),
+            ("max_peers", old_p2p.max_peers != new_p2p.max_peers),
+            (
+                "max_inbound_per_ip",
+                old_p2p.max_inbound_per_ip != new_p2p.max_inbound_per_ip,
+            ),
+            (
+                "max_inbound_per_subnet",
+                old_p2p.max_inbound_per_subnet != new_p2p.max_inbound_per_subnet,
+            ),
+            (
+                "max_upload_kib_per_sec",
+                old_bandwidth.upload != new_bandwidth.upload,
+            ),
+            (
+                "max_download_kib_per_sec",
+                old_bandwidth.download != new_bandwidth.download,
+            ),
+            (
+                "max_peer_upload_kib_per_sec",
+                old_bandwidth.peer_upload != new_bandwidth.peer_upload,
+            ),
+            (
+                "max_peer_download_kib_per_sec",
+                old_bandwidth.peer_download != new_bandwidth.peer_download,
+            ),
+            (
+                "max_in_flight_requests_per_peer",
+                old_p2p.max_in_flight_requests_per_peer != new_p2p.max_in_flight_requests_per_peer,
+            ),
+            (
+                "max_queued_requests_per_peer",
+                old_p2p.max_queued_requests_per_peer != new_p2p.max_queued_requests_per_peer,
+            ),
+            (
+                "rpc_allowed_methods",
+                self.rpc_allowed_methods != new.rpc_allowed_methods,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// JSON-RPC methods the WebSocket server answers; empty allows every method. Cheap to
+/// clone, and shared with the reloader so changes apply to open connections too.
+#[derive(Debug, Clone, Default)]
+pub struct RpcAllowlist {
+    methods: Arc<StdRwLock<Vec<String>>>,
+}
+
+impl RpcAllowlist {
+    pub fn new(methods: Vec<String>) -> Self {
+        Self {
+            methods: Arc::new(StdRwLock::new(methods)),
+        }
+    }
+
+    pub fn allows(&self, method: &str) -> bool {
+        let methods = self.methods.read().unwrap();
+        methods.is_empty() || methods.iter().any(|allowed| allowed == method)
+    }
+
+    pub fn set(&self, methods: Vec<String>) {
+        *self.methods.write().unwrap() = methods;
+    }
+}
+
+/// Re-reads `config/node/<env>.toml` (and `APP_*` variables) on SIGHUP or
+/// `admin_reloadConfig`, and applies the `ReloadableConfig` settings that changed without
+/// restarting the node or interrupting sync.
+#[derive(Debug, Clone)]
+pub struct ConfigReloader {
+    env: String,
+    current: Arc<Mutex<ReloadableConfig>>,
+    allowlist: RpcAllowlist,
+    command_tx_p2p: Sender<P2PServerCommand>,
+}
+
+impl ConfigReloader {
+    pub fn new(config: &AppConfig, command_tx_p2p: Sender<P2PServerCommand>) -> Self {
+        Self::with_current(
+            &config.env,
+            ReloadableConfig::from_config(config),
+            command_tx_p2p,
+        )
+    }
+
+    fn with_current(
+        env: &str,
+        current: ReloadableConfig,
+        command_tx_p2p: Sender<P2PServerCommand>,
+    ) -> Self {
+        Self {
+            env: env.to_string(),
+            allowlist: RpcAllowlist::new(current.rpc_allowed_methods.clone()),
+            current: Arc::new(Mutex::new(current)),
+            command_tx_p2p,
+        }
+    }
+
+    pub fn allowlist(&self) -> &RpcAllowlist {
+        &self.allowlist
+    }
+
+    /// Loads the configuration again and applies it; returns the settings that changed.
+    pub async fn reload(&self) -> Result<Vec<&'static str>, String> {
+        if self.env.is_empty() {
+            return Err("Configuration was not loaded from a config file".to_string());
+        }
+        let config = AppConfig::load_configuration(&self.env)
+            .map_err(|e| format!("Failed to load configuration: {}", e))?;
+        self.apply(ReloadableConfig::from_config(&config)).await
+    }
+
+    /// An invalid log level is rejected before anything else is applied.
+    pub async fn apply(&self, new: ReloadableConfig) -> Result<Vec<&'static str>, String> {
+        let mut current = self.current.lock().await;
+        let changed = current.changes(&new);
+        if current.log_level != new.log_level {
+            set_log_level(&new.log_level)?;
+        }
+        if current.p2p != new.p2p {
+            P2PServer::apply_limits_command(self.command_tx_p2p.clone(), new.p2p)
+                .await
+                .map_err(|e| format!("Failed to apply P2P limits: {}", e))?;
+        }
+        if current.rpc_allowed_methods != new.rpc_allowed_methods {
+            self.allowlist.set(new.rpc_allowed_methods.clone());
+        }
+        *current = new;
+        info!("Configuration reloaded; changed: {:?}", changed);
+        Ok(changed)
+    }
+
+    /// Reloads on every SIGHUP until the node stops. Does nothing off Unix.
+    pub fn listen_for_sighup(&self) {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let reloader = self.clone();
+            tokio::spawn(async move {
+                let mut hangups = match signal(SignalKind::hangup()) {
+                    Ok(hangups) => hangups,
+                    Err(e) => {
+                        error!("Failed to listen for SIGHUP: {}", e);
+                        return;
+                    }
+                };
+                while hangups.recv().await.is_some() {
+                    info!("SIGHUP received, reloading configuration");
+                    if let Err(e) = reloader.reload().await {
+                        error!("{}", e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reloadable() -> ReloadableConfig {
+        ReloadableConfig {
+            log_level: "info".to_string(),
+            p2p: P2PConfig::new("clutch-node", &[], &[]).limits(),
+            rpc_allowed_methods: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn empty_allowlist_allows_every_method() {
+        let allowlist = RpcAllowlist::default();
+        assert!(allowlist.allows("admin_peers"));
+
+        allowlist.set(vec!["clutch_getBalance".to_string()]);
+        assert!(allowlist.allows("clutch_getBalance"));
+        assert!(!allowlist.allows("admin_peers"));
+    }
+
+    #[tokio::test]
+    async fn changed_limits_are_sent_to_the_p2p_server() {
+        let (command_tx_p2p, mut command_rx_p2p) = tokio::sync::mpsc::channel(4);
+        let reloader = ConfigReloader::with_current("test", reloadable(), command_tx_p2p);
+
+        let mut new = reloadable();
+        new.p2p.max_peers = 7;
+        new.p2p.bandwidth.peer_upload = Some(1024);
+        new.rpc_allowed_methods = vec!["admin_reloadConfig".to_string()];
+        let changed = reloader.apply(new.clone()).await.unwrap();
+
+        assert_eq!(
+            changed,
+            [
+                "max_peers",
+                "max_peer_upload_kib_per_sec",
+                "rpc_allowed_methods"
+            ]
+        );
+        match command_rx_p2p.try_recv() {
+            Ok(P2PServerCommand::ApplyLimits { limits }) => assert_eq!(limits, new.p2p),
+            _ => panic!("expected ApplyLimits"),
+        }
+        assert!(!reloader.allowlist().allows("admin_peers"));
+
+        assert!(reloader.apply(new).await.unwrap().is_empty());
+        assert!(command_rx_p2p.try_recv().is_err());
+    }
+}
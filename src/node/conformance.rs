@@ -0,0 +1,345 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::node::blocks::block::Block;
+use crate::node::blocks::block_bodies::BlockBodies;
+use crate::node::blocks::block_headers::BlockHeaders;
+use crate::node::coordinate::Coordinates;
+use crate::node::p2p_server::get_block_bodies::GetBlockBodies;
+use crate::node::p2p_server::get_block_header::GetBlockHeaders;
+use crate::node::p2p_server::handshake::Handshake;
+use crate::node::rlp_encoding::{self, RlpMessage};
+use crate::node::transactions::function_call::FunctionCall;
+use crate::node::transactions::ride_request::RideRequest;
+use crate::node::transactions::transaction::Transaction;
+use crate::node::transactions::transfer::Transfer;
+
+/// Bumped when the file layout changes, not when vectors are added.
+pub const VECTORS_FORMAT_VERSION: u32 = 1;
+
+/// Well-known development keys; signatures are RFC 6979, so vectors come out the same on
+/// every run.
+const SENDER_ADDRESS: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const SENDER_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const RECIPIENT_ADDRESS: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_ADDRESS: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+/// Stands in for the authoring time, which is not hashed but is part of a block's RLP.
+const BLOCK_TIMESTAMP: u64 = 1_700_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorKind {
+    Handshake,
+    GetBlockHeaders,
+    BlockHeaders,
+    GetBlockBodies,
+    BlockBodies,
+    Transaction,
+    Block,
+}
+
+/// One canonical encoding: `value` is the serde (JSON) form, `rlp` its hex RLP payload as
+/// sent on the wire (without the gossip type byte or direct message frame header), and
+/// `hash`, for transactions and blocks, the hash a node computes for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestVector {
+    pub name: String,
+    pub kind: VectorKind,
+    pub value: Value,
+    pub rlp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// The file other implementations check themselves against.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub format_version: u32,
+    pub vectors: Vec<TestVector>,
+}
+
+impl TestVectors {
+    /// Vectors for every direct message that carries chain data, plus transaction, block
+    /// and genesis hashes, built from fixed keys and timestamps.
+    pub fn generate() -> Self {
+        let transfer = signed_transaction(
+            1,
+            FunctionCall::Transfer(Transfer {
+                to: RECIPIENT_ADDRESS.to_string(),
+                value: 10,
+            }),
+        );
+        let ride_request = signed_transaction(
+            2,
+            FunctionCall::RideRequest(RideRequest {
+                pickup_location: Coordinates {
+                    latitude: 35.6892,
+                    longitude: 51.389,
+                },
+                dropoff_location: Coordinates {
+                    latitude: 35.7219,
+                    longitude: 51.3347,
+                },
+                fare: 100,
+                referrer: None,
+            }),
+        );
+
+        let mut genesis = Block::new_genesis_block();
+        genesis.timestamp = 0;
+        let first = signed_block(1, &genesis.hash, vec![transfer.clone()]);
+        let second = signed_block(2, &first.hash, vec![ride_request.clone()]);
+        let empty = signed_block(3, &second.hash, Vec::new());
+
+        let vectors = vec![
+            message_vector(
+                "handshake_rlp_only",
+                VectorKind::Handshake,
+                &Handshake {
+                    genesis_block_hash: genesis.hash.clone(),
+                    latest_block_hash: second.hash.clone(),
+                    latest_block_index: second.index,
+                    capabilities: Vec::new(),
+                    hardforks: Vec::new(),
+                    node_record: Vec::new(),
+                    protocol_version: 0,
+                },
+            ),
+            message_vector(
+                "handshake_with_capabilities",
+                VectorKind::Handshake,
+                &Handshake {
+                    genesis_block_hash: genesis.hash.clone(),
+                    latest_block_hash: second.hash.clone(),
+                    latest_block_index: second.index,
+                    capabilities: vec!["cbor".to_string(), "rlp".to_string()],
+                    hardforks: vec!["monotonic_timestamps@block:100".to_string()],
+                    node_record: Vec::new(),
+                    protocol_version: 0,
+                },
+            ),
+            message_vector(
+                "get_block_headers",
+                VectorKind::GetBlockHeaders,
+                &GetBlockHeaders {
+                    start_block_index: 1,
+                    skip: 0,
+                    limit: 128,
+                    start_block_hash: first.hash.clone(),
+                },
+            ),
+            message_vector(
+                "block_headers",
+                VectorKind::BlockHeaders,
+                &BlockHeaders {
+                    block_headers: vec![first.to_block_header(), second.to_block_header()],
+                },
+            ),
+            message_vector(
+                "get_block_bodies",
+                VectorKind::GetBlockBodies,
+                &GetBlockBodies {
+                    block_indexes: vec![1, 2, 3],
+                },
+            ),
+            message_vector(
+                "block_bodies",
+                VectorKind::BlockBodies,
+                &BlockBodies {
+                    blocks: vec![first.clone(), second.clone(), empty.clone()],
+                },
+            ),
+            hashed_vector(
+                "transaction_transfer",
+                VectorKind::Transaction,
+                &transfer,
+                &transfer.hash,
+            ),
+            hashed_vector(
+                "transaction_ride_request",
+                VectorKind::Transaction,
+                &ride_request,
+                &ride_request.hash,
+            ),
+            hashed_vector("block_genesis", VectorKind::Block, &genesis, &genesis.hash),
+            hashed_vector("block_transfer", VectorKind::Block, &first, &first.hash),
+            hashed_vector(
+                "block_ride_request",
+                VectorKind::Block,
+                &second,
+                &second.hash,
+            ),
+            hashed_vector("block_empty", VectorKind::Block, &empty, &empty.hash),
+        ];
+
+        TestVectors {
+            format_version: VECTORS_FORMAT_VERSION,
+            vectors,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.to_string_lossy(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to encode test vectors: {}", e))?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.to_string_lossy(), e))?;
+        }
+        std::fs::write(path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.to_string_lossy(), e))
+    }
+
+    /// Checks every vector against this node's codecs and returns the failures as
+    /// `"<name>: <reason>"`, empty when the file conforms.
+    pub fn verify(&self) -> Vec<String> {
+        let mut failures = Vec::new();
+        if self.format_version != VECTORS_FORMAT_VERSION {
+            failures.push(format!(
+                "format_version {} is not {}",
+                self.format_version, VECTORS_FORMAT_VERSION
+            ));
+        }
+        for vector in &self.vectors {
+            if let Err(e) = vector.verify() {
+                failures.push(format!("{}: {}", vector.name, e));
+            }
+        }
+        failures
+    }
+}
+
+impl TestVector {
+    /// `value` must encode to `rlp`, `rlp` must decode back to `value`, and a transaction
+    /// or block must hash to `hash`.
+    pub fn verify(&self) -> Result<(), String> {
+        match self.kind {
+            VectorKind::Handshake => self.verify_as::<Handshake>(|_| None),
+            VectorKind::GetBlockHeaders => self.verify_as::<GetBlockHeaders>(|_| None),
+            VectorKind::BlockHeaders => self.verify_as::<BlockHeaders>(|_| None),
+            VectorKind::GetBlockBodies => self.verify_as::<GetBlockBodies>(|_| None),
+            VectorKind::BlockBodies => self.verify_as::<BlockBodies>(|_| None),
+            VectorKind::Transaction => {
+                self.verify_as::<Transaction>(|transaction| Some(transaction.calculate_hash()))
+            }
+            VectorKind::Block => self.verify_as::<Block>(|block| Some(block.calculate_hash())),
+        }
+    }
+
+    fn verify_as<T: RlpMessage>(&self, hash: impl Fn(&T) -> Option<String>) -> Result<(), String> {
+        let message: T = from_value(&self.value)?;
+        let encoded = hex::encode(rlp_encoding::encode(&message));
+        if encoded != self.rlp.to_lowercase() {
+            return Err(format!(
+                "value encodes to {}, expected {}",
+                encoded, self.rlp
+            ));
+        }
+
+        let bytes = hex::decode(&self.rlp).map_err(|e| format!("rlp is not hex: {}", e))?;
+        let decoded: T =
+            rlp_encoding::decode(&bytes).map_err(|e| format!("rlp does not decode: {:?}", e))?;
+        let decoded_value = serde_json::to_value(&decoded)
+            .map_err(|e| format!("Failed to encode decoded message: {}", e))?;
+        if decoded_value != self.value {
+            return Err(format!("rlp decodes to {}", decoded_value));
+        }
+
+        match (hash(&message), &self.hash) {
+            (Some(computed), Some(expected)) if &computed != expected => {
+                Err(format!("hashes to {}, expected {}", computed, expected))
+            }
+            (Some(_), None) => Err("hash is missing".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, String> {
+    serde_json::from_value(value.clone()).map_err(|e| format!("value does not parse: {}", e))
+}
+
+fn message_vector<T: RlpMessage>(name: &str, kind: VectorKind, message: &T) -> TestVector {
+    TestVector {
+        name: name.to_string(),
+        kind,
+        value: serde_json::to_value(message).expect("messages serialize to JSON"),
+        rlp: hex::encode(rlp_encoding::encode(message)),
+        hash: None,
+    }
+}
+
+fn hashed_vector<T: RlpMessage>(
+    name: &str,
+    kind: VectorKind,
+    message: &T,
+    hash: &str,
+) -> TestVector {
+    TestVector {
+        hash: Some(hash.to_string()),
+        ..message_vector(name, kind, message)
+    }
+}
+
+fn signed_transaction(nonce: u64, function_call: FunctionCall) -> Transaction {
+    let mut transaction =
+        Transaction::new_transaction(SENDER_ADDRESS.to_string(), nonce, function_call);
+    transaction.sign(SENDER_SECRET_KEY);
+    transaction
+}
+
+fn signed_block(index: usize, previous_hash: &str, transactions: Vec<Transaction>) -> Block {
+    let mut block = Block::new_block(index, previous_hash.to_string(), transactions);
+    block.timestamp = BLOCK_TIMESTAMP + index as u64;
+    block.sign(AUTHOR_ADDRESS, AUTHOR_SECRET_KEY);
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_vectors_verify_and_are_reproducible() {
+        let vectors = TestVectors::generate();
+        assert_eq!(vectors.verify(), Vec::<String>::new());
+        assert_eq!(TestVectors::generate(), vectors);
+
+        let genesis = vectors
+            .vectors
+            .iter()
+            .find(|vector| vector.name == "block_genesis")
+            .unwrap();
+        assert_eq!(
+            genesis.hash.as_deref(),
+            Some(Block::new_genesis_block().hash.as_str())
+        );
+    }
+
+    #[test]
+    fn tampered_vectors_are_reported() {
+        let mut vectors = TestVectors::generate();
+        let handshake = &mut vectors.vectors[0];
+        handshake.value["latest_block_index"] = Value::from(3);
+        let block = vectors
+            .vectors
+            .iter_mut()
+            .find(|vector| vector.name == "block_transfer")
+            .unwrap();
+        block.hash = Some("00".to_string());
+
+        let failures = vectors.verify();
+        assert_eq!(failures.len(), 2, "{:?}", failures);
+        assert!(failures[0].starts_with("handshake_rlp_only: value encodes to"));
+        assert!(failures[1].starts_with("block_transfer: hashes to"));
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::OnceLock;
+
+pub use clutch_core::crypto::*;
+
+static SUITE: OnceLock<CryptoSuite> = OnceLock::new();
+
+/// The suite of the chain this process runs: the installed one, else the default.
+pub fn suite() -> CryptoSuite {
+    *SUITE.get_or_init(CryptoSuite::default)
+}
+
+/// Selects the chain's suite. Must run before anything is hashed or signed, which uses
+/// the default from then on; fails if the process already uses a different suite.
+pub fn install(suite: CryptoSuite) -> Result<(), String> {
+    let installed = *SUITE.get_or_init(|| suite);
+    if installed != suite {
+        return Err(format!(
+            "This process already uses {} / {} / {}, not {} / {} / {}",
+            installed.hash.name(),
+            installed.block_hash.name(),
+            installed.signature.name(),
+            suite.hash.name(),
+            suite.block_hash.name(),
+            suite.signature.name()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_different_suite_cannot_be_installed() {
+        assert_eq!(install(suite()), Ok(()));
+        let other = CryptoSuite {
+            signature: SignatureAlgorithm::Ed25519,
+            ..suite()
+        };
+        assert!(install(other).is_err());
+    }
+}
@@ -1,22 +1,55 @@
-use rocksdb::{ColumnFamilyDescriptor, DBWithThreadMode, Options, SingleThreaded, WriteBatch, DB};
+use rocksdb::{
+    ColumnFamilyDescriptor, DBWithThreadMode, Direction, IteratorMode, Options, SingleThreaded,
+    SnapshotWithThreadMode, WriteBatch, DB,
+};
+use std::collections::BTreeMap;
 use std::env;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::node::error::StorageError;
+
+/// Point reads shared by the live database and its snapshots, so block lookups can run
+/// against either.
+pub trait ReadStore {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String>;
+}
+
+/// Every column family, in the order backups store them.
+pub const COLUMN_FAMILIES: [&str; 4] = ["block", "state", "blockchain", "tx_pool"];
+
+/// Writes buffered by an open batch, keyed by (column family, key); `None` is a delete.
+type PendingWrites = BTreeMap<(String, Vec<u8>), Option<Vec<u8>>>;
 
 #[derive(Debug)]
 pub struct Database {
-    db: Option<DBWithThreadMode<SingleThreaded>>,
+    db: Option<Arc<DBWithThreadMode<SingleThreaded>>>,
+    path: String,
+    /// `Some` between `begin_batch` and `end_batch`. Reads through `Database` see these
+    /// writes; snapshots from `reader()` only see what has been flushed.
+    pending: Mutex<Option<PendingWrites>>,
 }
 
 impl Database {
-    fn db_path(name: &str) -> String {
+    /// Where the database called `name` lives: under `DB_PATH`, or the working directory.
+    pub fn db_path(name: &str) -> String {
         let db_base_path = env::var("DB_PATH").unwrap_or_else(|_| {
             let current_dir = env::current_dir().expect("Failed to get current directory");
             current_dir.to_str().unwrap_or(".").to_string()
         });
-        format!("{}/{}.db", db_base_path, name)
+        Self::db_path_in(&db_base_path, name)
+    }
+
+    /// Where the database called `name` lives inside `dir`.
+    pub fn db_path_in(dir: &str, name: &str) -> String {
+        format!("{}/{}.db", dir, name)
     }
 
+    /// Opens the database called `name` under `DB_PATH`, or the working directory.
     pub fn new_db(name: &str) -> Self {
-        let db_path = Database::db_path(&name);
+        Self::open(&Database::db_path(name))
+    }
+
+    pub fn open(db_path: &str) -> Self {
         let mut options = Options::default();
         options.create_if_missing(true);
         options.create_missing_column_families(true);
@@ -35,12 +68,148 @@ impl Database {
         )
         .expect("Failed to open database with specified column families");
 
-        Database { db: Some(db) }
+        Database {
+            db: Some(Arc::new(db)),
+            path: db_path.to_string(),
+            pending: Mutex::new(None),
+        }
+    }
+
+    /// Opens the database at `db_path` as a RocksDB secondary instance, keeping its own
+    /// logs in `secondary_path`: a read-only view as of the moment it opens, which works
+    /// while a node holds the database open. Every table file is kept open, so the node's
+    /// compactions can't delete one from under a long read.
+    pub fn open_secondary(db_path: &str, secondary_path: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        options.set_max_open_files(-1);
+        let db = DBWithThreadMode::<SingleThreaded>::open_cf_as_secondary(
+            &options,
+            db_path,
+            secondary_path,
+            COLUMN_FAMILIES,
+        )
+        .map_err(|e| format!("Failed to open database {}: {}", db_path, e))?;
+        Ok(Database {
+            db: Some(Arc::new(db)),
+            path: db_path.to_string(),
+            pending: Mutex::new(None),
+        })
+    }
+
+    /// Lock-free read handle for serving peers. Holds only a weak reference, so it never
+    /// keeps the database open past `close`.
+    pub fn reader(&self) -> Result<DatabaseReader, StorageError> {
+        match &self.db {
+            Some(db) => Ok(DatabaseReader {
+                db: Arc::downgrade(db),
+            }),
+            None => Err(StorageError::Closed),
+        }
+    }
+
+    /// Starts buffering writes in memory instead of committing each one. Used by bulk sync
+    /// so a run of blocks costs one RocksDB write per batch rather than one per block.
+    pub fn begin_batch(&self) {
+        self.pending
+            .lock()
+            .unwrap()
+            .get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Commits everything buffered so far as a single atomic write and keeps the batch
+    /// open. Returns the number of keys written. The writes stay buffered until RocksDB
+    /// has taken them, so a failed flush loses nothing and can be retried.
+    pub fn flush_batch(&self) -> Result<usize, String> {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(writes) = pending.as_mut() else {
+            return Ok(0);
+        };
+        if writes.is_empty() {
+            return Ok(0);
+        }
+        self.write_batch(
+            writes.iter().map(|((cf_name, key), value)| {
+                (cf_name.as_str(), key.as_slice(), value.as_deref())
+            }),
+        )?;
+        let written = writes.len();
+        writes.clear();
+        Ok(written)
+    }
+
+    /// Flushes and closes the batch; later writes go straight to RocksDB again. Writes
+    /// the final flush couldn't commit are dropped with the batch.
+    pub fn end_batch(&self) -> Result<usize, String> {
+        let flushed = self.flush_batch();
+        *self.pending.lock().unwrap() = None;
+        flushed
+    }
+
+    /// Drops everything buffered since the last flush and closes the batch.
+    pub fn discard_batch(&self) {
+        *self.pending.lock().unwrap() = None;
+    }
+
+    /// Buffers the writes if a batch is open. Returns `false` when they should be written
+    /// directly.
+    fn buffer_writes<'a>(
+        &self,
+        operations: impl IntoIterator<Item = (&'a str, &'a [u8], Option<&'a [u8]>)>,
+    ) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(pending) = pending.as_mut() else {
+            return false;
+        };
+        for (cf_name, key, value) in operations {
+            pending.insert(
+                (cf_name.to_string(), key.to_vec()),
+                value.map(<[u8]>::to_vec),
+            );
+        }
+        true
+    }
+
+    fn pending_value(&self, cf_name: &str, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        self.pending
+            .lock()
+            .unwrap()
+            .as_ref()?
+            .get(&(cf_name.to_string(), key.to_vec()))
+            .cloned()
+    }
+
+    /// Overlays buffered writes on entries iterated from RocksDB starting at `from`, so the
+    /// result matches what the iterator would return once the batch is flushed.
+    fn merge_pending(
+        &self,
+        cf_name: &str,
+        from: &[u8],
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let pending = self.pending.lock().unwrap();
+        let Some(pending) = pending.as_ref().filter(|pending| !pending.is_empty()) else {
+            return entries;
+        };
+
+        let mut merged: BTreeMap<Vec<u8>, Vec<u8>> = entries.into_iter().collect();
+        for ((pending_cf, key), value) in pending {
+            if pending_cf != cf_name || key.as_slice() < from {
+                continue;
+            }
+            match value {
+                Some(value) => merged.insert(key.clone(), value.clone()),
+                None => merged.remove(key),
+            };
+        }
+        merged.into_iter().collect()
     }
 
     pub fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
         match &self.db {
             Some(db) => {
+                if let Some(value) = self.pending_value(cf_name, key) {
+                    return Ok(value);
+                }
                 let cf_handle = db.cf_handle(cf_name).ok_or("Column family not found")?;
                 db.get_cf(cf_handle, key).map_err(|e| e.to_string())
             }
@@ -51,6 +220,9 @@ impl Database {
     pub fn put(&self, cf_name: &str, key: &[u8], value: &[u8]) -> Result<(), String> {
         match &self.db {
             Some(db) => {
+                if self.buffer_writes([(cf_name, key, Some(value))]) {
+                    return Ok(());
+                }
                 let cf_handle = db.cf_handle(cf_name).ok_or("Column family not found")?;
                 db.put_cf(cf_handle, key, value).map_err(|e| e.to_string())
             }
@@ -61,6 +233,9 @@ impl Database {
     pub fn delete(&self, cf_name: &str, key: &[u8]) -> Result<(), String> {
         match &self.db {
             Some(db) => {
+                if self.buffer_writes([(cf_name, key, None)]) {
+                    return Ok(());
+                }
                 let cf_handle = db.cf_handle(cf_name).ok_or("Column family not found")?;
                 db.delete_cf(cf_handle, key).map_err(|e| e.to_string())
             }
@@ -69,6 +244,19 @@ impl Database {
     }
 
     pub fn write(&self, operations: Vec<(&str, &[u8], Option<&[u8]>)>) -> Result<(), String> {
+        if self.db.is_none() {
+            return Err("Database connection is closed".to_string());
+        }
+        if self.buffer_writes(operations.iter().copied()) {
+            return Ok(());
+        }
+        self.write_batch(operations)
+    }
+
+    fn write_batch<'a>(
+        &self,
+        operations: impl IntoIterator<Item = (&'a str, &'a [u8], Option<&'a [u8]>)>,
+    ) -> Result<(), String> {
         let mut batch = WriteBatch::default();
     
         match &self.db {
@@ -94,14 +282,43 @@ impl Database {
         let _ = self.db.take(); // Properly drops the database object, closing the connection
     }
 
+    /// Salvages what RocksDB can of a damaged database: rebuilds its manifest from the
+    /// table files and drops what can't be read. Must run before the database is opened.
+    pub fn repair(name: &str) -> Result<(), String> {
+        let db_path = Database::db_path(name);
+        DB::repair(&Options::default(), db_path).map_err(|e| e.to_string())
+    }
+
+    /// Compacts every column family over its full key range, reclaiming the space held by
+    /// deleted and overwritten keys.
+    pub fn compact(&self) -> Result<(), String> {
+        match &self.db {
+            Some(db) => {
+                for cf_name in COLUMN_FAMILIES {
+                    let cf_handle = db
+                        .cf_handle(cf_name)
+                        .ok_or(format!("Column family '{}' not found", cf_name))?;
+                    db.compact_range_cf(cf_handle, None::<&[u8]>, None::<&[u8]>);
+                }
+                Ok(())
+            }
+            None => Err("Database connection is closed".to_string()),
+        }
+    }
+
     pub fn delete_database(&self, name: &str) -> Result<(), String> {
-        let db_path = Database::db_path(&name);
+        Self::destroy(&Database::db_path(name))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
 
-        DB::destroy(&Options::default(), db_path).map_err(|e| e.to_string())?;
-        Ok(())
+    /// Deletes the database at `db_path`, which must not be open.
+    pub fn destroy(db_path: &str) -> Result<(), String> {
+        DB::destroy(&Options::default(), db_path).map_err(|e| e.to_string())
     }
 
-    #[allow(dead_code)]
     pub fn get_keys_by_cf_name(&self, cf_name: &str) -> Result<Vec<Vec<u8>>, String> {
         match &self.db {
             Some(db) => {
@@ -141,7 +358,31 @@ impl Database {
                         Err(e) => return Err(e.to_string()),
                     }
                 }
-                Ok(entries)
+                Ok(self.merge_pending(cf_name, &[], entries))
+            }
+            None => Err("Database connection is closed".to_string()),
+        }
+    }
+
+    /// Calls `visit` with each entry of a column family in key order, starting at `from`,
+    /// without collecting them first. Writes buffered by an open batch are not seen.
+    pub fn for_each_from(
+        &self,
+        cf_name: &str,
+        from: &[u8],
+        mut visit: impl FnMut(&[u8], &[u8]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        match &self.db {
+            Some(db) => {
+                let cf_handle = db
+                    .cf_handle(cf_name)
+                    .ok_or(format!("Column family '{}' not found", cf_name))?;
+                let iter = db.iterator_cf(cf_handle, IteratorMode::From(from, Direction::Forward));
+                for item in iter {
+                    let (key, value) = item.map_err(|e| e.to_string())?;
+                    visit(&key, &value)?;
+                }
+                Ok(())
             }
             None => Err("Database connection is closed".to_string()),
         }
@@ -167,9 +408,54 @@ impl Database {
                         Err(e) => return Err(e.to_string()),
                     }
                 }
-                Ok(entries)
+                Ok(self.merge_pending(cf_name, prefix, entries))
             }
             None => Err("Database connection is closed".to_string()),
         }
     }
 }
+
+impl ReadStore for Database {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        Database::get(self, cf_name, key)
+    }
+}
+
+/// Cloneable handle for reading the chain without going through the `Blockchain` lock.
+#[derive(Debug, Clone)]
+pub struct DatabaseReader {
+    db: Weak<DB>,
+}
+
+impl DatabaseReader {
+    /// Runs `read` against a RocksDB snapshot: every lookup sees the same point in time,
+    /// even if a block is imported concurrently.
+    pub fn with_snapshot<T, E: From<StorageError>>(
+        &self,
+        read: impl FnOnce(&DatabaseSnapshot<'_>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let db = self.db.upgrade().ok_or(StorageError::Closed)?;
+        let snapshot = DatabaseSnapshot {
+            db: &db,
+            snapshot: db.snapshot(),
+        };
+        read(&snapshot)
+    }
+}
+
+pub struct DatabaseSnapshot<'a> {
+    db: &'a DB,
+    snapshot: SnapshotWithThreadMode<'a, DB>,
+}
+
+impl ReadStore for DatabaseSnapshot<'_> {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let cf_handle = self
+            .db
+            .cf_handle(cf_name)
+            .ok_or("Column family not found")?;
+        self.snapshot
+            .get_cf(cf_handle, key)
+            .map_err(|e| e.to_string())
+    }
+}
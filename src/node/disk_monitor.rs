@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::node::configuration::AppConfig;
+use crate::node::database::Database;
+use crate::node::metric::{DISK_FREE_BYTES, DISK_SAFE_MODE};
+use crate::node::webhook::{NodeEvent, WebhookNotifier};
+
+/// How often free space on the data directory is checked.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const MB: u64 = 1024 * 1024;
+
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the node is in disk safe mode: it refuses bulk requests from peers, transactions
+/// gossiped by peers and state diffs, so that importing blocks has the space left.
+pub fn safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+fn set_safe_mode(on: bool) {
+    SAFE_MODE.store(on, Ordering::Relaxed);
+    DISK_SAFE_MODE.set(i64::from(on));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskLevel {
+    Ok,
+    /// Below `disk_warn_free_mb`.
+    Low,
+    /// Below `disk_safe_mode_free_mb`, or not yet back above `disk_warn_free_mb` since.
+    SafeMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskThresholds {
+    pub warn_bytes: u64,
+    pub safe_mode_bytes: u64,
+}
+
+impl DiskThresholds {
+    pub fn from_config(config: &AppConfig) -> Self {
+        DiskThresholds {
+            warn_bytes: config.disk_warn_free_mb.saturating_mul(MB),
+            safe_mode_bytes: config.disk_safe_mode_free_mb.saturating_mul(MB),
+        }
+    }
+
+    /// The level for `free` bytes coming from `previous`. Safe mode is only left once
+    /// space is back above the warning threshold, so it doesn't flap around one value.
+    pub fn level(&self, free: u64, previous: DiskLevel) -> DiskLevel {
+        if free < self.safe_mode_bytes
+            || (previous == DiskLevel::SafeMode && free < self.warn_bytes)
+        {
+            DiskLevel::SafeMode
+        } else if free < self.warn_bytes {
+            DiskLevel::Low
+        } else {
+            DiskLevel::Ok
+        }
+    }
+}
+
+/// Bytes available to the node on the file system holding `path`.
+#[cfg(unix)]
+pub fn free_bytes(path: &str) -> Result<u64, String> {
+    let c_path = std::ffi::CString::new(path).map_err(|e| e.to_string())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[cfg(not(unix))]
+pub fn free_bytes(_path: &str) -> Result<u64, String> {
+    Err("free space is only checked on Unix".to_string())
+}
+
+/// Watches free space where the database lives, started unless `disk_warn_free_mb` is 0.
+/// Publishes it as `disk_free_bytes`, warns and posts to the webhook when it crosses a
+/// threshold, and puts the node in `safe_mode` before a full disk can corrupt the database.
+pub struct DiskMonitor {
+    path: String,
+    thresholds: DiskThresholds,
+    notifier: WebhookNotifier,
+    level: DiskLevel,
+}
+
+impl DiskMonitor {
+    pub fn start(config: &AppConfig) {
+        let monitor = DiskMonitor {
+            path: Database::db_path(&config.blockchain_name),
+            thresholds: DiskThresholds::from_config(config),
+            notifier: WebhookNotifier::from_config(config),
+            level: DiskLevel::Ok,
+        };
+        tokio::spawn(monitor.run());
+    }
+
+    async fn run(mut self) {
+        let mut interval = tokio::time::interval(DISK_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match free_bytes(&self.path) {
+                Ok(free) => self.update(free),
+                Err(e) => error!("Failed to check free space on {}: {}", self.path, e),
+            }
+        }
+    }
+
+    fn update(&mut self, free: u64) {
+        DISK_FREE_BYTES.set(i64::try_from(free).unwrap_or(i64::MAX));
+        let level = self.thresholds.level(free, self.level);
+        if level == self.level {
+            return;
+        }
+        let free_mb = free / MB;
+        match level {
+            DiskLevel::Ok => info!("Free disk space back to {} MB", free_mb),
+            DiskLevel::Low if self.level == DiskLevel::SafeMode => {
+                info!("Free disk space back to {} MB; leaving safe mode", free_mb)
+            }
+            DiskLevel::Low => warn!("Free disk space down to {} MB", free_mb),
+            DiskLevel::SafeMode => error!(
+                "Free disk space down to {} MB; entering safe mode until {} MB are free",
+                free_mb,
+                self.thresholds.warn_bytes / MB
+            ),
+        }
+        if level != DiskLevel::Ok && self.level != DiskLevel::SafeMode {
+            self.notifier.notify(NodeEvent::LowDiskSpace {
+                free_mb,
+                safe_mode: level == DiskLevel::SafeMode,
+            });
+        }
+        set_safe_mode(level == DiskLevel::SafeMode);
+        self.level = level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_mode_holds_until_space_is_back_above_the_warning() {
+        let thresholds = DiskThresholds {
+            warn_bytes: 100,
+            safe_mode_bytes: 40,
+        };
+        let levels: Vec<DiskLevel> = [150, 90, 30, 60, 99, 100, 60]
+            .into_iter()
+            .scan(DiskLevel::Ok, |level, free| {
+                *level = thresholds.level(free, *level);
+                Some(*level)
+            })
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![
+                DiskLevel::Ok,
+                DiskLevel::Low,
+                DiskLevel::SafeMode,
+                DiskLevel::SafeMode,
+                DiskLevel::SafeMode,
+                DiskLevel::Ok,
+                DiskLevel::Low
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn free_space_is_read_for_existing_paths_only() {
+        let temp_dir = std::env::temp_dir();
+        assert!(free_bytes(&temp_dir.to_string_lossy()).is_ok());
+        assert!(free_bytes("/no/such/clutch-node/path").is_err());
+    }
+}
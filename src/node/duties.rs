@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::node::aura::Aura;
+use crate::node::metric::{VALIDATOR_MISSED_PROPOSALS, VALIDATOR_PROPOSED_BLOCKS};
+
+/// Missed slots of ours kept for `clutch_getDuties`.
+const RECENT_MISSES: usize = 32;
+/// Upcoming duties `clutch_getDuties` lists unless asked for another number.
+pub const DEFAULT_UPCOMING_DUTIES: usize = 10;
+/// Most upcoming duties one `clutch_getDuties` call lists.
+pub const MAX_UPCOMING_DUTIES: usize = 100;
+
+/// A slot in which this validator is the authority due to propose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Duty {
+    pub slot: u64,
+    /// Unix seconds the slot starts at and ends before.
+    pub starts_at: u64,
+    pub ends_at: u64,
+}
+
+/// The next `count` slots, from the one running at `now`, in which `validator` is due to
+/// propose. Empty if it isn't an authority.
+pub fn upcoming_duties(aura: &Aura, validator: &str, now: u64, count: usize) -> Vec<Duty> {
+    if !aura
+        .authorities
+        .iter()
+        .any(|authority| authority == validator)
+    {
+        return Vec::new();
+    }
+    (aura.slot_at_time(now)..)
+        .filter(|slot| aura.author_at_slot(*slot) == validator)
+        .take(count)
+        .map(|slot| Duty {
+            slot,
+            starts_at: slot * aura.step_duration,
+            ends_at: (slot + 1) * aura.step_duration,
+        })
+        .collect()
+}
+
+/// What became of this validator's duties since the node started, as its authoring loop
+/// saw them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DutyRecord {
+    pub proposed: u64,
+    pub missed: u64,
+    pub last_proposed_slot: Option<u64>,
+    /// Newest first.
+    pub recent_missed_slots: VecDeque<u64>,
+}
+
+/// Answer to `clutch_getDuties`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DutyReport {
+    pub validator: String,
+    pub current_slot: u64,
+    pub step_duration: u64,
+    pub upcoming: Vec<Duty>,
+    #[serde(flatten)]
+    pub record: DutyRecord,
+}
+
+/// Counts the blocks this validator proposed and the slots it let pass, exported as the
+/// `validator_proposed_blocks` and `validator_missed_proposals` metrics.
+#[derive(Debug, Default)]
+pub struct DutyTracker {
+    record: Mutex<DutyRecord>,
+}
+
+impl DutyTracker {
+    pub fn proposed(&self, slot: u64) {
+        let mut record = self.record.lock().unwrap();
+        record.proposed += 1;
+        record.last_proposed_slot = Some(slot);
+        VALIDATOR_PROPOSED_BLOCKS.inc();
+    }
+
+    /// Counts `slot` as missed unless it already was; returns whether it is new.
+    pub fn missed(&self, slot: u64) -> bool {
+        let mut record = self.record.lock().unwrap();
+        if record.recent_missed_slots.front() == Some(&slot) {
+            return false;
+        }
+        record.missed += 1;
+        record.recent_missed_slots.push_front(slot);
+        record.recent_missed_slots.truncate(RECENT_MISSES);
+        VALIDATOR_MISSED_PROPOSALS.inc();
+        true
+    }
+
+    pub fn record(&self) -> DutyRecord {
+        self.record.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aura() -> Aura {
+        Aura::new(vec!["a".to_string(), "b".to_string(), "c".to_string()], 20)
+    }
+
+    #[test]
+    fn duties_are_the_validators_upcoming_slots() {
+        // Slot 10 runs from 200 to 220 and belongs to "b".
+        let duties = upcoming_duties(&aura(), "b", 205, 3);
+        let slots: Vec<u64> = duties.iter().map(|duty| duty.slot).collect();
+        assert_eq!(slots, vec![10, 13, 16]);
+        assert_eq!((duties[0].starts_at, duties[0].ends_at), (200, 220));
+
+        assert_eq!(upcoming_duties(&aura(), "c", 205, 1)[0].slot, 11);
+        assert!(upcoming_duties(&aura(), "x", 205, 3).is_empty());
+    }
+
+    #[test]
+    fn each_missed_slot_is_counted_once() {
+        let tracker = DutyTracker::default();
+        tracker.proposed(4);
+        assert!(tracker.missed(7));
+        assert!(!tracker.missed(7));
+        assert!(tracker.missed(10));
+
+        let record = tracker.record();
+        assert_eq!(record.proposed, 1);
+        assert_eq!(record.last_proposed_slot, Some(4));
+        assert_eq!(record.missed, 2);
+        assert_eq!(record.recent_missed_slots, VecDeque::from([10, 7]));
+    }
+}
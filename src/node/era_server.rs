@@ -0,0 +1,35 @@
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::{routing::get, Router};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::node::blocks::era::{EraArchive, EraInfo};
+
+/// Serves the era archive for out-of-band sync on `serve_metric_addr`: `/era` lists the
+/// files as JSON, and `/era/<file>` downloads one. Only files the archive lists are
+/// served, so a request can never reach another path.
+pub fn router(archive: Arc<EraArchive>) -> Router {
+    Router::new()
+        .route("/era", get(list_eras))
+        .route("/era/:file", get(era_file))
+        .with_state(archive)
+}
+
+async fn list_eras(State(archive): State<Arc<EraArchive>>) -> Json<Vec<EraInfo>> {
+    Json(archive.eras())
+}
+
+async fn era_file(State(archive): State<Arc<EraArchive>>, Path(file): Path<String>) -> Response {
+    if !archive.eras().iter().any(|info| info.file == file) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match tokio::fs::read(archive.dir().join(&file)).await {
+        Ok(bytes) => ([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response(),
+        Err(e) => {
+            error!("Failed to read era file {}: {}", file, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
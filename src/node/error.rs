@@ -0,0 +1,120 @@
+use std::any::Any;
+use thiserror::Error;
+
+/// Failures of the RocksDB store itself, as opposed to the data in it being invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StorageError {
+    #[error("Database connection is closed")]
+    Closed,
+    #[error("Failed to read {what}: {reason}")]
+    Read { what: &'static str, reason: String },
+}
+
+impl StorageError {
+    pub fn read(what: &'static str) -> impl FnOnce(String) -> Self {
+        move |reason| StorageError::Read { what, reason }
+    }
+}
+
+/// Errors surfaced across the blockchain and p2p modules. Code still returning
+/// `Result<_, String>` converts both ways with `?`, so callers move over one at a time.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NodeError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("Failed to decode {message}: {reason}")]
+    Decode {
+        message: &'static str,
+        reason: String,
+    },
+    #[error("{0}")]
+    Chain(String),
+    #[error("Handler panicked: {0}")]
+    Panicked(String),
+}
+
+impl NodeError {
+    /// Short label for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NodeError::Storage(_) => "storage",
+            NodeError::Decode { .. } => "decode",
+            NodeError::Chain(_) => "chain",
+            NodeError::Panicked(_) => "panic",
+        }
+    }
+
+    /// From the payload `catch_unwind` returns, keeping the panic message when it has one.
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => "unknown panic".to_string(),
+            },
+        };
+        NodeError::Panicked(message)
+    }
+}
+
+impl From<String> for NodeError {
+    fn from(message: String) -> Self {
+        NodeError::Chain(message)
+    }
+}
+
+impl From<NodeError> for String {
+    fn from(error: NodeError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<StorageError> for String {
+    fn from(error: StorageError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_block() -> Result<(), String> {
+        Err("corrupt block_3".to_string())
+    }
+
+    fn serve() -> Result<(), NodeError> {
+        read_block().map_err(StorageError::read("block 3"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn string_errors_convert_both_ways() {
+        let error = serve().unwrap_err();
+        assert_eq!(error.kind(), "storage");
+        assert_eq!(
+            String::from(error),
+            "Failed to read block 3: corrupt block_3"
+        );
+
+        let chain: NodeError = "Invalid block".to_string().into();
+        assert_eq!(chain.kind(), "chain");
+        assert_eq!(chain.to_string(), "Invalid block");
+    }
+
+    #[test]
+    fn panics_keep_their_message() {
+        let panicked = std::panic::catch_unwind(|| panic!("storage hiccup at {}", 3));
+        let error = NodeError::from_panic(panicked.unwrap_err());
+        assert_eq!(
+            error,
+            NodeError::Panicked("storage hiccup at 3".to_string())
+        );
+
+        let panicked = std::panic::catch_unwind(|| panic!("static message"));
+        assert_eq!(
+            NodeError::from_panic(panicked.unwrap_err()).to_string(),
+            "Handler panicked: static message"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! C ABI for programs that embed the node without Rust, e.g. mobile wallets: start a node,
+//! submit transactions, read the head block and hear about imported blocks. Build the
+//! library with `cargo rustc --lib --release --features ffi --crate-type cdylib` (or
+//! `staticlib`); `include/clutch_node.h` declares the functions.
+//!
+//! Functions that can fail return `-1` or null and leave a message for
+//! `clutch_last_error` on the calling thread. Strings returned to the caller are freed
+//! with `clutch_string_free`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+
+use tracing::error;
+
+use crate::node::blocks::block::Block;
+use crate::node::configuration::AppConfig;
+use crate::node::node_builder::NodeBuilder;
+use crate::node::node_services::NodeHandle;
+use crate::node::p2p_server::P2PServer;
+use crate::node::plugin::NodePlugin;
+use crate::node::rlp_encoding::decode;
+use crate::node::runtime;
+use crate::node::transactions::transaction::Transaction;
+
+/// Called with the caller's `user_data`, the block's index and its hash, valid only for
+/// the duration of the call. Runs on the node's thread while it holds the chain lock, so
+/// it must return quickly and must not call back into the node.
+pub type ClutchBlockCallback =
+    extern "C" fn(user_data: *mut c_void, index: u64, hash: *const c_char);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = message.into();
+    error!("{}", message);
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `f`, turning an `Err` or a panic into `failed` and the thread's last error.
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            failed
+        }
+        Err(_) => {
+            set_last_error("Panicked inside the node library");
+            failed
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is null", name));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+struct BlockCallback {
+    callback: ClutchBlockCallback,
+    user_data: *mut c_void,
+}
+
+// The caller promises `user_data` may be used from the node's thread by registering it.
+unsafe impl Send for BlockCallback {}
+
+/// The callbacks `clutch_node_on_block` registered, called as a plugin.
+#[derive(Clone, Default)]
+struct BlockCallbacks(Arc<Mutex<Vec<BlockCallback>>>);
+
+impl NodePlugin for BlockCallbacks {
+    fn name(&self) -> &str {
+        "ffi-block-callbacks"
+    }
+
+    fn on_block_imported(&self, block: &Block) {
+        let Ok(hash) = CString::new(block.hash.as_str()) else {
+            return;
+        };
+        let callbacks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for entry in callbacks.iter() {
+            (entry.callback)(entry.user_data, block.index as u64, hash.as_ptr());
+        }
+    }
+}
+
+/// A node started by `clutch_node_start`, running on a thread of its own.
+pub struct ClutchNode {
+    handle: NodeHandle,
+    runtime: tokio::runtime::Handle,
+    callbacks: BlockCallbacks,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Starts the node configured by `config/node/<env>.toml` (relative to the working
+/// directory), keeping its database in `storage_dir`, or where the binary would when
+/// that is null. Returns null if the node couldn't start; stop it with
+/// `clutch_node_stop`.
+///
+/// # Safety
+/// `env` must be a NUL-terminated string; `storage_dir` one or null.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_node_start(
+    env: *const c_char,
+    storage_dir: *const c_char,
+) -> *mut ClutchNode {
+    guard(std::ptr::null_mut(), || {
+        let env = str_arg(env, "env")?;
+        let storage_dir = if storage_dir.is_null() {
+            None
+        } else {
+            Some(str_arg(storage_dir, "storage_dir")?)
+        };
+        let config = AppConfig::load_configuration(env).map_err(|e| e.to_string())?;
+        runtime::install_cpu_pool(config.cpu_threads);
+        let runtime = runtime::build(&config)
+            .map_err(|e| format!("Failed to start the node's runtime: {}", e))?;
+        let callbacks = BlockCallbacks::default();
+        let mut builder = NodeBuilder::new(config).with_plugin(callbacks.clone());
+        if let Some(dir) = storage_dir {
+            builder = builder.with_storage(dir);
+        }
+        let node = builder.build();
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("clutch-node".to_string())
+            .spawn(move || {
+                runtime.block_on(node.run_with_handle(|handle| {
+                    let _ = started_tx.send((tokio::runtime::Handle::current(), handle));
+                }));
+            })
+            .map_err(|e| format!("Failed to spawn the node's thread: {}", e))?;
+        let (runtime, handle) = started_rx
+            .recv()
+            .map_err(|_| "Node stopped before it started".to_string())?;
+        Ok(Box::into_raw(Box::new(ClutchNode {
+            handle,
+            runtime,
+            callbacks,
+            thread: Some(thread),
+        })))
+    })
+}
+
+/// Stops `node`, waits for its chain to shut down and frees it.
+///
+/// # Safety
+/// `node` must come from `clutch_node_start` and not be used afterwards; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_node_stop(node: *mut ClutchNode) {
+    if node.is_null() {
+        return;
+    }
+    let mut node = Box::from_raw(node);
+    node.handle.shutdown();
+    if let Some(thread) = node.thread.take() {
+        let _ = thread.join();
+    }
+}
+
+/// Adds an RLP-encoded transaction, as `send_raw_transaction` takes it, to the pool and
+/// gossips it. Returns 0, or -1 if it is malformed or rejected.
+///
+/// # Safety
+/// `node` must come from `clutch_node_start`; `rlp` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_node_submit_transaction(
+    node: *const ClutchNode,
+    rlp: *const u8,
+    len: usize,
+) -> c_int {
+    guard(-1, || {
+        let node = node.as_ref().ok_or("node is null")?;
+        if rlp.is_null() {
+            return Err("rlp is null".to_string());
+        }
+        let transaction: Transaction = decode(std::slice::from_raw_parts(rlp, len))
+            .map_err(|e| format!("Failed to decode RLP transaction: {}", e))?;
+        node.runtime.block_on(async {
+            node.handle
+                .blockchain
+                .write()
+                .await
+                .add_local_transaction(&transaction)?;
+            P2PServer::broadcast_transaction_command(node.handle.commands.clone(), transaction)
+                .await;
+            Ok::<_, String>(())
+        })?;
+        Ok(0)
+    })
+}
+
+/// The head block as JSON, as `get_block_by_index` returns blocks, or null.
+///
+/// # Safety
+/// `node` must come from `clutch_node_start`.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_node_head(node: *const ClutchNode) -> *mut c_char {
+    guard(std::ptr::null_mut(), || {
+        let node = node.as_ref().ok_or("node is null")?;
+        let head = node
+            .runtime
+            .block_on(async { node.handle.blockchain.read().await.get_latest_block() })?
+            .ok_or("Chain has no head")?;
+        let json = serde_json::to_string(&head).map_err(|e| e.to_string())?;
+        CString::new(json)
+            .map(CString::into_raw)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Calls `callback` with `user_data` for every block imported from now on, until the
+/// node stops. Returns 0, or -1 if `node` is null.
+///
+/// # Safety
+/// `node` must come from `clutch_node_start`, and `user_data` must stay usable from
+/// another thread until the node is stopped.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_node_on_block(
+    node: *const ClutchNode,
+    callback: ClutchBlockCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(-1, || {
+        let node = node.as_ref().ok_or("node is null")?;
+        node.callbacks
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(BlockCallback {
+                callback,
+                user_data,
+            });
+        Ok(0)
+    })
+}
+
+/// The last error on this thread, or null; free it with `clutch_string_free`.
+#[no_mangle]
+pub extern "C" fn clutch_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .clone()
+            .map_or(std::ptr::null_mut(), CString::into_raw)
+    })
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+/// `s` must come from this library and not be freed twice; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn clutch_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn record(user_data: *mut c_void, index: u64, hash: *const c_char) {
+        let seen = unsafe { &*(user_data as *const Mutex<Vec<(u64, String)>>) };
+        let hash = unsafe { CStr::from_ptr(hash) }
+            .to_string_lossy()
+            .into_owned();
+        seen.lock().unwrap().push((index, hash));
+    }
+
+    #[test]
+    fn registered_callbacks_hear_of_imported_blocks() {
+        let seen: Mutex<Vec<(u64, String)>> = Mutex::new(Vec::new());
+        let callbacks = BlockCallbacks::default();
+        callbacks.0.lock().unwrap().push(BlockCallback {
+            callback: record,
+            user_data: &seen as *const _ as *mut c_void,
+        });
+
+        let block = Block::new_block(7, "parent".to_string(), vec![]);
+        callbacks.on_block_imported(&block);
+        assert_eq!(*seen.lock().unwrap(), vec![(7, block.hash)]);
+    }
+
+    #[test]
+    fn failures_leave_a_last_error() {
+        let node = unsafe { clutch_node_start(std::ptr::null(), std::ptr::null()) };
+        assert!(node.is_null());
+        let error = clutch_last_error();
+        assert_eq!(unsafe { CStr::from_ptr(error) }.to_str(), Ok("env is null"));
+        unsafe { clutch_string_free(error) };
+        assert_eq!(
+            unsafe { clutch_node_submit_transaction(std::ptr::null(), std::ptr::null(), 0) },
+            -1
+        );
+    }
+}
@@ -0,0 +1,328 @@
+pub mod nats;
+pub mod postgres;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::node::balance_effect::StoredBalanceEffect;
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::block::Block;
+use crate::node::configuration::AppConfig;
+use crate::node::transactions::receipt::TransactionReceipt;
+use nats::{NatsAddress, NatsPublisher};
+
+/// Bumped on any incompatible change to the published messages.
+const FORMAT_VERSION: u32 = 1;
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BLOCKS_PER_PUBLISH: usize = 64;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Published blocks remembered to find where a rolled-back chain left them.
+const CURSOR_DEPTH: usize = 256;
+
+/// A firehose message body; published on `<firehose_subject_prefix>.<type>`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FirehoseEvent {
+    Block {
+        block: Block,
+        receipts: Vec<TransactionReceipt>,
+        /// Effects no transaction caused: the author's block reward.
+        block_effects: Vec<StoredBalanceEffect>,
+    },
+    /// Published blocks above `common_index` are no longer in the chain; what replaces
+    /// them is published next. `removed_hashes` is newest first.
+    Reorg {
+        common_index: usize,
+        common_hash: String,
+        removed_hashes: Vec<String>,
+    },
+}
+
+impl FirehoseEvent {
+    fn subject_suffix(&self) -> &'static str {
+        match self {
+            FirehoseEvent::Block { .. } => "block",
+            FirehoseEvent::Reorg { .. } => "reorg",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FirehoseMessage<'a> {
+    version: u32,
+    chain_id: &'a str,
+    #[serde(flatten)]
+    event: &'a FirehoseEvent,
+}
+
+/// The last published blocks, oldest first, persisted so a restart resumes after them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirehoseCursor {
+    published: VecDeque<(usize, String)>,
+}
+
+impl FirehoseCursor {
+    fn next_index(&self) -> usize {
+        self.published.back().map_or(0, |(index, _)| index + 1)
+    }
+
+    fn record(&mut self, index: usize, hash: String) {
+        self.published.push_back((index, hash));
+        if self.published.len() > CURSOR_DEPTH {
+            self.published.pop_front();
+        }
+    }
+
+    /// The reorg to report if the chain no longer holds the last published block. It goes
+    /// back to the newest published block still in the chain, or to genesis if none of the
+    /// remembered ones is.
+    fn divergence(
+        &self,
+        mut hash_at: impl FnMut(usize) -> Result<Option<String>, String>,
+    ) -> Result<Option<FirehoseEvent>, String> {
+        let mut removed_hashes = Vec::new();
+        for (index, hash) in self.published.iter().rev() {
+            if hash_at(*index)?.as_ref() == Some(hash) {
+                if removed_hashes.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(FirehoseEvent::Reorg {
+                    common_index: *index,
+                    common_hash: hash.clone(),
+                    removed_hashes,
+                }));
+            }
+            removed_hashes.push(hash.clone());
+        }
+        if removed_hashes.is_empty() {
+            return Ok(None);
+        }
+        let genesis_hash = hash_at(0)?.ok_or_else(|| "Genesis block is missing".to_string())?;
+        Ok(Some(FirehoseEvent::Reorg {
+            common_index: 0,
+            common_hash: genesis_hash,
+            removed_hashes,
+        }))
+    }
+
+    fn apply(&mut self, event: &FirehoseEvent) {
+        match event {
+            FirehoseEvent::Block { block, .. } => self.record(block.index, block.hash.clone()),
+            FirehoseEvent::Reorg {
+                common_index,
+                common_hash,
+                ..
+            } => {
+                self.published.retain(|(index, _)| index < common_index);
+                self.record(*common_index, common_hash.clone());
+            }
+        }
+    }
+}
+
+/// Publishes imported blocks with their receipts, and reorgs, to NATS. Polls the chain, so
+/// it also reports blocks removed by `rollback` or `db repair` while the node was stopped.
+pub struct Firehose {
+    address: NatsAddress,
+    subject_prefix: String,
+    chain_id: String,
+    blockchain: Arc<RwLock<Blockchain>>,
+}
+
+impl Firehose {
+    pub fn start(config: &AppConfig, blockchain: Arc<RwLock<Blockchain>>) {
+        let address = match NatsAddress::parse(&config.firehose_nats_url) {
+            Ok(address) => address,
+            Err(e) => {
+                error!("Firehose disabled: {}", e);
+                return;
+            }
+        };
+        let subject_prefix = &config.firehose_subject_prefix;
+        if subject_prefix.is_empty() || subject_prefix.contains(char::is_whitespace) {
+            error!(
+                "Firehose disabled: invalid subject prefix {:?}",
+                subject_prefix
+            );
+            return;
+        }
+        let firehose = Firehose {
+            address,
+            subject_prefix: subject_prefix.clone(),
+            chain_id: config.chain_id.clone(),
+            blockchain,
+        };
+        tokio::spawn(firehose.run());
+    }
+
+    async fn run(self) {
+        let mut cursor = None;
+        loop {
+            match NatsPublisher::connect(&self.address, "clutch-node").await {
+                Ok(mut publisher) => {
+                    info!("Firehose connected to NATS at {}", self.address.host_port);
+                    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+                    loop {
+                        interval.tick().await;
+                        if let Err(e) = self.publish_new(&mut publisher, &mut cursor).await {
+                            warn!("Firehose stopped publishing: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => warn!("Firehose could not connect to NATS: {}", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Publishes what the chain gained or lost since `cursor`, which is loaded on first use
+    /// and saved once the server has confirmed the messages. The first run ever starts at
+    /// the current head.
+    async fn publish_new(
+        &self,
+        publisher: &mut NatsPublisher<TcpStream>,
+        cursor: &mut Option<FirehoseCursor>,
+    ) -> Result<(), String> {
+        let events = {
+            let blockchain = self.blockchain.read().await;
+            if cursor.is_none() {
+                *cursor = Some(Self::initial_cursor(&blockchain)?);
+            }
+            let cursor = cursor.as_ref().expect("cursor is loaded above");
+            Self::pending_events(&blockchain, cursor)?
+        };
+        if events.is_empty() {
+            return publisher.flush().await;
+        }
+
+        for event in &events {
+            let message = FirehoseMessage {
+                version: FORMAT_VERSION,
+                chain_id: &self.chain_id,
+                event,
+            };
+            let payload = serde_json::to_vec(&message)
+                .map_err(|e| format!("Failed to encode firehose message: {}", e))?;
+            let subject = format!("{}.{}", self.subject_prefix, event.subject_suffix());
+            publisher.publish(&subject, &payload).await?;
+        }
+        publisher.flush().await?;
+
+        let cursor = cursor.as_mut().expect("cursor is loaded above");
+        for event in &events {
+            cursor.apply(event);
+        }
+        self.blockchain.read().await.save_firehose_cursor(cursor)
+    }
+
+    fn initial_cursor(blockchain: &Blockchain) -> Result<FirehoseCursor, String> {
+        if let Some(cursor) = blockchain.load_firehose_cursor()? {
+            return Ok(cursor);
+        }
+        let head = blockchain
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?;
+        let mut cursor = FirehoseCursor::default();
+        cursor.record(head.index, head.hash);
+        Ok(cursor)
+    }
+
+    fn pending_events(
+        blockchain: &Blockchain,
+        cursor: &FirehoseCursor,
+    ) -> Result<Vec<FirehoseEvent>, String> {
+        let mut events = Vec::new();
+        let mut next = cursor.next_index();
+        if let Some(reorg) = cursor.divergence(|index| blockchain.get_block_hash(index))? {
+            if let FirehoseEvent::Reorg { common_index, .. } = &reorg {
+                next = common_index + 1;
+            }
+            events.push(reorg);
+        }
+
+        let head = blockchain
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        if next > head {
+            return Ok(events);
+        }
+        let last = head.min(next + MAX_BLOCKS_PER_PUBLISH - 1);
+        for block in blockchain.get_blocks_by_indexes((next..=last).collect())? {
+            let receipts = blockchain.get_block_receipts(&block);
+            let block_effects = blockchain.get_block_balance_effects(block.index as u64);
+            events.push(FirehoseEvent::Block {
+                block,
+                receipts,
+                block_effects,
+            });
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(published: &[(usize, &str)]) -> FirehoseCursor {
+        let mut cursor = FirehoseCursor::default();
+        for (index, hash) in published {
+            cursor.record(*index, hash.to_string());
+        }
+        cursor
+    }
+
+    fn chain(
+        hashes: &'static [&'static str],
+    ) -> impl FnMut(usize) -> Result<Option<String>, String> {
+        move |index| Ok(hashes.get(index).map(|hash| hash.to_string()))
+    }
+
+    #[test]
+    fn reorgs_go_back_to_the_newest_block_still_in_the_chain() {
+        let published = cursor(&[(1, "a1"), (2, "a2"), (3, "a3")]);
+        assert_eq!(published.next_index(), 4);
+        assert!(published
+            .divergence(chain(&["g", "a1", "a2", "a3", "a4"]))
+            .unwrap()
+            .is_none());
+
+        let reorg = published
+            .divergence(chain(&["g", "a1", "b2"]))
+            .unwrap()
+            .expect("a2 and a3 were removed");
+        let mut rewound = published.clone();
+        rewound.apply(&reorg);
+        match reorg {
+            FirehoseEvent::Reorg {
+                common_index,
+                common_hash,
+                removed_hashes,
+            } => {
+                assert_eq!((common_index, common_hash.as_str()), (1, "a1"));
+                assert_eq!(removed_hashes, ["a3", "a2"]);
+            }
+            other => panic!("expected a reorg, got {:?}", other),
+        }
+        assert_eq!(rewound, cursor(&[(1, "a1")]));
+    }
+
+    #[test]
+    fn unknown_history_reorgs_to_genesis() {
+        let mut published = cursor(&[(5, "a5")]);
+        let reorg = published
+            .divergence(chain(&["g", "b1"]))
+            .unwrap()
+            .expect("a5 was removed");
+        published.apply(&reorg);
+        assert_eq!(published, cursor(&[(0, "g")]));
+        assert_eq!(published.next_index(), 1);
+    }
+}
@@ -0,0 +1,368 @@
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio_postgres::{Client, NoTls};
+use tracing::{info, warn};
+
+use super::{
+    Firehose, FirehoseCursor, FirehoseEvent, CURSOR_DEPTH, MAX_BLOCKS_PER_PUBLISH,
+    PUBLISH_INTERVAL, RECONNECT_DELAY,
+};
+use crate::node::balance_effect::StoredBalanceEffect;
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::block::Block;
+use crate::node::configuration::AppConfig;
+use crate::node::transactions::receipt::TransactionReceipt;
+
+/// Created on connect if missing. Transactions and logs go with their block, so a reorg
+/// only has to delete blocks.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+    block_index BIGINT PRIMARY KEY,
+    hash TEXT NOT NULL UNIQUE,
+    previous_hash TEXT NOT NULL,
+    author TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    transaction_count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    hash TEXT PRIMARY KEY,
+    block_index BIGINT NOT NULL REFERENCES blocks (block_index) ON DELETE CASCADE,
+    transaction_index INTEGER NOT NULL,
+    from_address TEXT NOT NULL,
+    nonce BIGINT NOT NULL,
+    function_call_type TEXT NOT NULL,
+    data JSONB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transactions_from_address
+    ON transactions (from_address, block_index);
+CREATE TABLE IF NOT EXISTS logs (
+    block_index BIGINT NOT NULL REFERENCES blocks (block_index) ON DELETE CASCADE,
+    log_index INTEGER NOT NULL,
+    transaction_hash TEXT,
+    address TEXT NOT NULL,
+    delta BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    counterparty TEXT,
+    PRIMARY KEY (block_index, log_index)
+);
+CREATE INDEX IF NOT EXISTS logs_address ON logs (address, block_index);
+";
+
+/// A row of the `logs` table. Logs are numbered across the block: each transaction's
+/// effects in order, then the block's own.
+#[derive(Debug)]
+struct LogRow<'a> {
+    log_index: i32,
+    transaction_hash: Option<&'a str>,
+    effect: &'a StoredBalanceEffect,
+}
+
+fn log_rows<'a>(
+    receipts: &'a [TransactionReceipt],
+    block_effects: &'a [StoredBalanceEffect],
+) -> Vec<LogRow<'a>> {
+    let tx_logs = receipts.iter().flat_map(|receipt| {
+        receipt
+            .logs
+            .iter()
+            .map(move |effect| (Some(receipt.transaction_hash.as_str()), effect))
+    });
+    let block_logs = block_effects.iter().map(|effect| (None, effect));
+    tx_logs
+        .chain(block_logs)
+        .enumerate()
+        .map(|(log_index, (transaction_hash, effect))| LogRow {
+            log_index: log_index as i32,
+            transaction_hash,
+            effect,
+        })
+        .collect()
+}
+
+/// The cursor for the blocks already exported, given the newest ones newest first.
+fn cursor_from_rows(rows: impl IntoIterator<Item = (i64, String)>) -> FirehoseCursor {
+    let mut rows: Vec<(i64, String)> = rows.into_iter().collect();
+    rows.reverse();
+    let mut cursor = FirehoseCursor::default();
+    for (index, hash) in rows {
+        cursor.record(index as usize, hash);
+    }
+    cursor
+}
+
+/// Mirrors the chain into PostgreSQL for block explorers. Shares the firehose's change
+/// detection, but the exported rows are the cursor: each poll is written in one database
+/// transaction, an empty database is filled from genesis, and a reorg deletes the blocks
+/// it removed before the replacements are inserted.
+pub struct PostgresExporter {
+    url: String,
+    blockchain: Arc<RwLock<Blockchain>>,
+}
+
+impl PostgresExporter {
+    pub fn start(config: &AppConfig, blockchain: Arc<RwLock<Blockchain>>) {
+        let exporter = PostgresExporter {
+            url: config.explorer_postgres_url.clone(),
+            blockchain,
+        };
+        tokio::spawn(exporter.run());
+    }
+
+    async fn run(self) {
+        loop {
+            match self.connect().await {
+                Ok(mut client) => {
+                    info!("Explorer export connected to PostgreSQL");
+                    let mut cursor = None;
+                    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+                    let mut behind = false;
+                    loop {
+                        // A backfill goes on without waiting until it catches up.
+                        if !behind {
+                            interval.tick().await;
+                        }
+                        match self.export_new(&mut client, &mut cursor).await {
+                            Ok(more) => behind = more,
+                            Err(e) => {
+                                warn!("Explorer export stopped: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => warn!("Explorer export could not connect to PostgreSQL: {}", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn connect(&self) -> Result<Client, String> {
+        // The URL may hold credentials, so it isn't echoed.
+        let (client, connection) = tokio_postgres::connect(&self.url, NoTls)
+            .await
+            .map_err(|e| format!("Failed to connect: {}", e))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("PostgreSQL connection closed: {}", e);
+            }
+        });
+        client
+            .batch_execute(SCHEMA)
+            .await
+            .map_err(|e| format!("Failed to create the explorer schema: {}", e))?;
+        Ok(client)
+    }
+
+    /// Writes what the chain gained or lost since `cursor`, which is read back from the
+    /// `blocks` table on first use. Returns whether more blocks may be waiting.
+    async fn export_new(
+        &self,
+        client: &mut Client,
+        cursor: &mut Option<FirehoseCursor>,
+    ) -> Result<bool, String> {
+        if cursor.is_none() {
+            *cursor = Some(Self::load_cursor(client).await?);
+        }
+        let events = {
+            let blockchain = self.blockchain.read().await;
+            let cursor = cursor.as_ref().expect("cursor is loaded above");
+            Firehose::pending_events(&blockchain, cursor)?
+        };
+        if events.is_empty() {
+            return Ok(false);
+        }
+
+        let transaction = client
+            .transaction()
+            .await
+            .map_err(|e| format!("Failed to begin a transaction: {}", e))?;
+        for event in &events {
+            match event {
+                FirehoseEvent::Block {
+                    block,
+                    receipts,
+                    block_effects,
+                } => Self::insert_block(&transaction, block, receipts, block_effects).await?,
+                FirehoseEvent::Reorg { common_index, .. } => {
+                    transaction
+                        .execute(
+                            "DELETE FROM blocks WHERE block_index > $1",
+                            &[&(*common_index as i64)],
+                        )
+                        .await
+                        .map_err(|e| format!("Failed to delete reorged blocks: {}", e))?;
+                }
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit exported blocks: {}", e))?;
+
+        let cursor = cursor.as_mut().expect("cursor is loaded above");
+        for event in &events {
+            cursor.apply(event);
+        }
+        let blocks = events
+            .iter()
+            .filter(|event| matches!(event, FirehoseEvent::Block { .. }))
+            .count();
+        Ok(blocks == MAX_BLOCKS_PER_PUBLISH)
+    }
+
+    async fn load_cursor(client: &Client) -> Result<FirehoseCursor, String> {
+        let rows = client
+            .query(
+                "SELECT block_index, hash FROM blocks ORDER BY block_index DESC LIMIT $1",
+                &[&(CURSOR_DEPTH as i64)],
+            )
+            .await
+            .map_err(|e| format!("Failed to read exported blocks: {}", e))?;
+        Ok(cursor_from_rows(rows.iter().map(|row| {
+            (row.get::<_, i64>(0), row.get::<_, String>(1))
+        })))
+    }
+
+    async fn insert_block(
+        transaction: &tokio_postgres::Transaction<'_>,
+        block: &Block,
+        receipts: &[TransactionReceipt],
+        block_effects: &[StoredBalanceEffect],
+    ) -> Result<(), String> {
+        let insert_error =
+            |e: tokio_postgres::Error| format!("Failed to export block {}: {}", block.index, e);
+        let block_index = block.index as i64;
+        transaction
+            .execute(
+                "INSERT INTO blocks (block_index, hash, previous_hash, author, timestamp, \
+                 transaction_count) VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &block_index,
+                    &block.hash,
+                    &block.previous_hash,
+                    &block.author,
+                    &(block.timestamp as i64),
+                    &(block.transactions.len() as i32),
+                ],
+            )
+            .await
+            .map_err(insert_error)?;
+
+        for (tx_index, tx) in block.transactions.iter().enumerate() {
+            let data = serde_json::to_value(&tx.data)
+                .map_err(|e| format!("Failed to encode transaction {}: {}", tx.hash, e))?;
+            transaction
+                .execute(
+                    "INSERT INTO transactions (hash, block_index, transaction_index, \
+                     from_address, nonce, function_call_type, data) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    &[
+                        &tx.hash,
+                        &block_index,
+                        &(tx_index as i32),
+                        &tx.from,
+                        &(tx.nonce as i64),
+                        &tx.function_call_type(),
+                        &data,
+                    ],
+                )
+                .await
+                .map_err(insert_error)?;
+        }
+
+        for log in log_rows(receipts, block_effects) {
+            let effect = &log.effect.effect;
+            let kind = serde_json::to_value(&effect.kind)
+                .map_err(|e| format!("Failed to encode log kind: {}", e))?;
+            transaction
+                .execute(
+                    "INSERT INTO logs (block_index, log_index, transaction_hash, address, \
+                     delta, kind, counterparty) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    &[
+                        &block_index,
+                        &log.log_index,
+                        &log.transaction_hash,
+                        &effect.address,
+                        &effect.delta,
+                        &kind.as_str(),
+                        &effect.counterparty,
+                    ],
+                )
+                .await
+                .map_err(insert_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::balance_effect::{BalanceEffect, BalanceEffectKind};
+
+    fn stored(address: &str, delta: i64, kind: BalanceEffectKind) -> StoredBalanceEffect {
+        StoredBalanceEffect {
+            effect: BalanceEffect {
+                address: address.to_string(),
+                delta,
+                kind,
+                counterparty: None,
+            },
+            block_height: 4,
+            tx_hash: None,
+            tx_index: None,
+            function_call_type: None,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn logs_are_numbered_across_the_block() {
+        let receipt = |hash: &str, logs| TransactionReceipt {
+            transaction_hash: hash.to_string(),
+            block_index: 4,
+            block_hash: "0xb4".to_string(),
+            transaction_index: 0,
+            from: "0xa".to_string(),
+            function_call_type: "Transfer".to_string(),
+            status: 1,
+            logs,
+        };
+        let receipts = vec![
+            receipt(
+                "0x01",
+                vec![
+                    stored("0xa", -5, BalanceEffectKind::TransferOut),
+                    stored("0xb", 5, BalanceEffectKind::TransferIn),
+                ],
+            ),
+            receipt(
+                "0x02",
+                vec![stored("0xa", -1, BalanceEffectKind::TransferOut)],
+            ),
+        ];
+        let reward = vec![stored("0xc", 50, BalanceEffectKind::BlockReward)];
+
+        let rows = log_rows(&receipts, &reward);
+        let summary: Vec<(i32, Option<&str>, i64)> = rows
+            .iter()
+            .map(|row| (row.log_index, row.transaction_hash, row.effect.effect.delta))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, Some("0x01"), -5),
+                (1, Some("0x01"), 5),
+                (2, Some("0x02"), -1),
+                (3, None, 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_rows_resume_after_the_newest_block() {
+        let cursor = cursor_from_rows(vec![(7, "h7".to_string()), (6, "h6".to_string())]);
+        assert_eq!(cursor.next_index(), 8);
+        assert_eq!(cursor_from_rows(Vec::new()).next_index(), 0);
+    }
+}
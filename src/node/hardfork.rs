@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::node::blocks::block::Block;
+
+/// A protocol change. Each one is inert until the chain config schedules it, so a network
+/// upgrades by shipping the code first and agreeing on an activation point afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hardfork {
+    /// A block's timestamp may not be earlier than its parent's.
+    MonotonicTimestamps,
+    /// Blocks without transactions no longer mint the block reward.
+    NoEmptyBlockReward,
+    /// Blocks commit to a transactions root and a receipts root, which headers carry.
+    HeaderRoots,
+}
+
+impl Hardfork {
+    pub const ALL: [Hardfork; 3] = [
+        Hardfork::MonotonicTimestamps,
+        Hardfork::NoEmptyBlockReward,
+        Hardfork::HeaderRoots,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hardfork::MonotonicTimestamps => "monotonic_timestamps",
+            Hardfork::NoEmptyBlockReward => "no_empty_block_reward",
+            Hardfork::HeaderRoots => "header_roots",
+        }
+    }
+}
+
+/// Where a fork starts: at a block index, or at the first block whose timestamp (Unix
+/// seconds) reaches a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivationPoint {
+    Block(usize),
+    Timestamp(u64),
+}
+
+impl ActivationPoint {
+    pub fn reached(self, block_index: usize, timestamp: u64) -> bool {
+        match self {
+            ActivationPoint::Block(index) => block_index >= index,
+            ActivationPoint::Timestamp(time) => timestamp >= time,
+        }
+    }
+
+    fn parse(point: &str) -> Option<Self> {
+        match point.split_once(':')? {
+            ("block", index) => index.parse().ok().map(ActivationPoint::Block),
+            ("time", time) => time.parse().ok().map(ActivationPoint::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ActivationPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActivationPoint::Block(index) => write!(f, "block:{}", index),
+            ActivationPoint::Timestamp(time) => write!(f, "time:{}", time),
+        }
+    }
+}
+
+/// One `[[hardforks]]` entry of the chain config: `fork` plus exactly one of `block` or
+/// `timestamp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardforkActivation {
+    pub fork: Hardfork,
+    #[serde(default)]
+    pub block: Option<usize>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+/// The forks this chain activates and when. Validation, execution and the P2P handshake
+/// all ask it which rules apply to a block, so every node switches at the same block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<HardforkActivation>")]
+pub struct HardforkSchedule {
+    activations: Vec<(Hardfork, ActivationPoint)>,
+}
+
+impl TryFrom<Vec<HardforkActivation>> for HardforkSchedule {
+    type Error = String;
+
+    fn try_from(entries: Vec<HardforkActivation>) -> Result<Self, String> {
+        let mut activations: Vec<(Hardfork, ActivationPoint)> = Vec::new();
+        for entry in entries {
+            let point = match (entry.block, entry.timestamp) {
+                (Some(index), None) => ActivationPoint::Block(index),
+                (None, Some(time)) => ActivationPoint::Timestamp(time),
+                _ => {
+                    return Err(format!(
+                        "Hardfork {} needs exactly one of `block` or `timestamp`",
+                        entry.fork.name()
+                    ))
+                }
+            };
+            if activations.iter().any(|(fork, _)| *fork == entry.fork) {
+                return Err(format!(
+                    "Hardfork {} is scheduled more than once",
+                    entry.fork.name()
+                ));
+            }
+            activations.push((entry.fork, point));
+        }
+        Ok(HardforkSchedule { activations })
+    }
+}
+
+impl HardforkSchedule {
+    pub fn activation(&self, fork: Hardfork) -> Option<ActivationPoint> {
+        self.activations
+            .iter()
+            .find(|(scheduled, _)| *scheduled == fork)
+            .map(|(_, point)| *point)
+    }
+
+    /// Whether `fork`'s rules apply to the block at `block_index` stamped `timestamp`.
+    pub fn is_active(&self, fork: Hardfork, block_index: usize, timestamp: u64) -> bool {
+        self.activation(fork)
+            .is_some_and(|point| point.reached(block_index, timestamp))
+    }
+
+    pub fn is_active_for(&self, fork: Hardfork, block: &Block) -> bool {
+        self.is_active(fork, block.index, block.timestamp)
+    }
+
+    /// Validation rules added by forks, for `block` on top of `parent`.
+    pub fn validate_block(&self, block: &Block, parent: &Block) -> Result<(), String> {
+        if self.is_active_for(Hardfork::MonotonicTimestamps, block)
+            && block.timestamp < parent.timestamp
+        {
+            return Err(format!(
+                "Invalid block: The timestamp {} is earlier than the parent's {}.",
+                block.timestamp, parent.timestamp
+            ));
+        }
+        if self.is_active_for(Hardfork::HeaderRoots, block)
+            && (block.transactions_root.is_empty() || block.receipts_root.is_empty())
+        {
+            return Err(format!(
+                "Invalid block: Block {} does not commit to its transactions and receipts roots.",
+                block.index
+            ));
+        }
+        Ok(())
+    }
+
+    /// The reward `block`'s author is minted, given the configured amount.
+    pub fn block_reward(&self, block: &Block, block_reward_amount: u64) -> u64 {
+        if block.transactions.is_empty() && self.is_active_for(Hardfork::NoEmptyBlockReward, block)
+        {
+            0
+        } else {
+            block_reward_amount
+        }
+    }
+
+    /// The schedule as sent in the handshake, one `<fork>@block:<index>` or
+    /// `<fork>@time:<timestamp>` per fork.
+    pub fn advertised(&self) -> Vec<String> {
+        self.activations
+            .iter()
+            .map(|(fork, point)| format!("{}@{}", fork.name(), point))
+            .collect()
+    }
+
+    /// Checks a peer's advertised schedule against ours. Forks may be scheduled on one
+    /// side only while they are still ahead, which lets operators upgrade one node at a
+    /// time; a fork scheduled differently, or active on one side only, splits the chain.
+    /// `head_index` is the higher of both heads and `now` the current time.
+    pub fn check_peer(
+        &self,
+        advertised: &[String],
+        head_index: usize,
+        now: u64,
+    ) -> Result<(), String> {
+        let mut theirs = Vec::new();
+        for entry in advertised {
+            let parsed = entry
+                .split_once('@')
+                .and_then(|(name, point)| Some((name, ActivationPoint::parse(point)?)));
+            match parsed {
+                Some(parsed) => theirs.push(parsed),
+                None => return Err(format!("Malformed hardfork entry {:?}", entry)),
+            }
+        }
+
+        for (name, point) in &theirs {
+            let ours = Hardfork::ALL
+                .into_iter()
+                .find(|fork| fork.name() == *name)
+                .and_then(|fork| self.activation(fork));
+            match ours {
+                Some(ours) if ours != *point => {
+                    return Err(format!(
+                        "Hardfork {} is scheduled at {} here but at {} by the peer",
+                        name, ours, point
+                    ))
+                }
+                Some(_) => {}
+                None if point.reached(head_index, now) => {
+                    return Err(format!(
+                        "Hardfork {} is active for the peer but not scheduled here",
+                        name
+                    ))
+                }
+                None => {}
+            }
+        }
+        for (fork, point) in &self.activations {
+            let scheduled_by_peer = theirs.iter().any(|(name, _)| *name == fork.name());
+            if !scheduled_by_peer && point.reached(head_index, now) {
+                return Err(format!(
+                    "Hardfork {} is active here but not scheduled by the peer",
+                    fork.name()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(entries: Vec<HardforkActivation>) -> Result<HardforkSchedule, String> {
+        HardforkSchedule::try_from(entries)
+    }
+
+    fn at_block(fork: Hardfork, block: usize) -> HardforkActivation {
+        HardforkActivation {
+            fork,
+            block: Some(block),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn forks_activate_at_their_block_or_time() {
+        let schedule = schedule(vec![
+            at_block(Hardfork::MonotonicTimestamps, 10),
+            HardforkActivation {
+                fork: Hardfork::NoEmptyBlockReward,
+                block: None,
+                timestamp: Some(1_700_000_000),
+            },
+        ])
+        .unwrap();
+
+        assert!(!schedule.is_active(Hardfork::MonotonicTimestamps, 9, u64::MAX));
+        assert!(schedule.is_active(Hardfork::MonotonicTimestamps, 10, 0));
+        assert!(!schedule.is_active(Hardfork::NoEmptyBlockReward, 100, 1_699_999_999));
+        assert!(schedule.is_active(Hardfork::NoEmptyBlockReward, 0, 1_700_000_000));
+        assert!(!HardforkSchedule::default().is_active(Hardfork::MonotonicTimestamps, 100, 0));
+        assert_eq!(
+            schedule.advertised(),
+            [
+                "monotonic_timestamps@block:10",
+                "no_empty_block_reward@time:1700000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn schedules_need_one_point_per_fork() {
+        let both = HardforkActivation {
+            timestamp: Some(5),
+            ..at_block(Hardfork::MonotonicTimestamps, 10)
+        };
+        assert!(schedule(vec![both]).is_err());
+        assert!(schedule(vec![HardforkActivation {
+            block: None,
+            ..at_block(Hardfork::MonotonicTimestamps, 10)
+        }])
+        .is_err());
+        assert!(schedule(vec![
+            at_block(Hardfork::MonotonicTimestamps, 10),
+            at_block(Hardfork::MonotonicTimestamps, 20),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn peers_may_differ_only_on_forks_still_ahead() {
+        let ours = schedule(vec![at_block(Hardfork::MonotonicTimestamps, 100)]).unwrap();
+        let same = ["monotonic_timestamps@block:100".to_string()];
+
+        assert!(ours.check_peer(&same, 500, 0).is_ok());
+        // Not yet upgraded peers are fine until the fork activates.
+        assert!(ours.check_peer(&[], 99, 0).is_ok());
+        assert!(ours.check_peer(&[], 100, 0).is_err());
+        assert!(ours
+            .check_peer(&["monotonic_timestamps@block:200".to_string()], 50, 0)
+            .is_err());
+        // A fork we don't know, or didn't schedule, is only a problem once active.
+        let unknown = ["bigger_blocks@time:2000".to_string()];
+        assert!(ours.check_peer(&unknown, 50, 1999).is_ok());
+        assert!(ours.check_peer(&unknown, 50, 2000).is_err());
+        assert!(ours.check_peer(&["garbage".to_string()], 50, 0).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::era::{EraArchive, EraInfo};
+use crate::node::configuration::AppConfig;
+
+/// A trusted era accumulator, from an `[[era_checkpoints]]` table.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct EraCheckpoint {
+    pub era: usize,
+    pub accumulator: String,
+}
+
+/// Downloads the history `era_checkpoints` vouch for from the `/era` endpoints of
+/// `history_urls` into the era archive and bulk-imports it, so that P2P sync only has
+/// the recent tip left to fetch. A file is kept only if it verifies and its accumulator
+/// is the checkpoint's; each URL is tried in turn until one serves a good copy.
+pub struct HistorySync {
+    urls: Vec<String>,
+    checkpoints: BTreeMap<usize, String>,
+    batch_size: usize,
+    client: reqwest::Client,
+}
+
+impl HistorySync {
+    pub fn new(urls: Vec<String>, checkpoints: &[EraCheckpoint], batch_size: usize) -> Self {
+        HistorySync {
+            urls: urls
+                .into_iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect(),
+            checkpoints: checkpoints
+                .iter()
+                .map(|checkpoint| (checkpoint.era, checkpoint.accumulator.clone()))
+                .collect(),
+            batch_size,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(
+            config.history_urls.clone(),
+            &config.era_checkpoints,
+            config.sync_write_batch_size,
+        )
+    }
+
+    /// Fetches the missing checkpointed eras above our head into `archive`, then imports
+    /// every era there is above it. Returns how many blocks were imported; stops fetching
+    /// at the first era no URL serves a good copy of.
+    pub async fn run(
+        &self,
+        blockchain: &RwLock<Blockchain>,
+        archive: &EraArchive,
+    ) -> Result<usize, String> {
+        let head = blockchain
+            .read()
+            .await
+            .get_latest_block()?
+            .ok_or_else(|| "Failed to get latest block".to_string())?
+            .index;
+        for (era, accumulator) in pending_eras(&self.checkpoints, archive.blocks_per_era(), head) {
+            if archive.contains_era(era) {
+                continue;
+            }
+            match self.fetch(archive, era, accumulator).await {
+                Some(info) => info!("Downloaded era {} as {}", era, info.file),
+                None => {
+                    warn!("No history URL served a good copy of era {}", era);
+                    break;
+                }
+            }
+        }
+        blockchain
+            .write()
+            .await
+            .import_era(archive, self.batch_size)
+    }
+
+    async fn fetch(&self, archive: &EraArchive, era: usize, accumulator: &str) -> Option<EraInfo> {
+        for url in &self.urls {
+            match self.fetch_from(url, archive, era, accumulator).await {
+                Ok(info) => return Some(info),
+                Err(e) => warn!("Failed to download era {} from {}: {}", era, url, e),
+            }
+        }
+        None
+    }
+
+    async fn fetch_from(
+        &self,
+        url: &str,
+        archive: &EraArchive,
+        era: usize,
+        accumulator: &str,
+    ) -> Result<EraInfo, String> {
+        let listed: Vec<EraInfo> = self
+            .client
+            .get(format!("{}/era", url))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        let info = listed
+            .into_iter()
+            .find(|info| info.era == era && info.accumulator == accumulator)
+            .ok_or_else(|| "not listed with the checkpoint's accumulator".to_string())?;
+        let bytes = self
+            .client
+            .get(format!("{}/era/{}", url, info.file))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+        archive.add_file(era, &bytes, accumulator)
+    }
+}
+
+/// The checkpointed eras holding blocks above `head`, in order, up to the first era
+/// without a checkpoint: history past a gap couldn't be imported anyway.
+fn pending_eras(
+    checkpoints: &BTreeMap<usize, String>,
+    blocks_per_era: usize,
+    head: usize,
+) -> Vec<(usize, &str)> {
+    let mut pending = Vec::new();
+    let mut era = (head + 1) / blocks_per_era;
+    while let Some(accumulator) = checkpoints.get(&era) {
+        pending.push((era, accumulator.as_str()));
+        era += 1;
+    }
+    pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eras_are_fetched_from_the_head_up_to_the_first_gap() {
+        let checkpoints: BTreeMap<usize, String> = [0, 1, 2, 4]
+            .into_iter()
+            .map(|era| (era, format!("acc{}", era)))
+            .collect();
+
+        assert_eq!(
+            pending_eras(&checkpoints, 4, 0),
+            vec![(0, "acc0"), (1, "acc1"), (2, "acc2")]
+        );
+        assert_eq!(
+            pending_eras(&checkpoints, 4, 6),
+            vec![(1, "acc1"), (2, "acc2")]
+        );
+        assert_eq!(pending_eras(&checkpoints, 4, 7), vec![(2, "acc2")]);
+        assert!(pending_eras(&checkpoints, 4, 11).is_empty());
+    }
+}
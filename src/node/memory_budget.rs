@@ -0,0 +1,195 @@
+use serde::Deserialize;
+use tracing::info;
+
+use crate::node::configuration::AppConfig;
+
+/// Estimated memory of a cached block header, and of a cached block hash with its key.
+const HEADER_BYTES: u64 = 1024;
+const HASH_BYTES: u64 = 128;
+/// Estimated memory of a pool transaction while the pool is loaded to author or evict.
+const TRANSACTION_BYTES: u64 = 2 * 1024;
+/// Blocks one body batch of the sync pipeline holds.
+const BLOCKS_PER_BATCH: u64 = 100;
+const MB: u64 = 1024 * 1024;
+
+/// Relative shares of `memory_budget_mb`, from the `memory_budget_weights` table. A
+/// component with weight 0 is left at its own setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MemoryWeights {
+    /// Header, body and hash LRU caches.
+    pub block_cache: u64,
+    /// Early gossiped blocks held for their parent or slot.
+    pub orphan_pool: u64,
+    pub mempool: u64,
+    /// Body batches the sync pipeline downloads ahead and blocks buffered for a write.
+    pub download_buffers: u64,
+}
+
+impl Default for MemoryWeights {
+    fn default() -> Self {
+        MemoryWeights {
+            block_cache: 50,
+            orphan_pool: 5,
+            mempool: 25,
+            download_buffers: 20,
+        }
+    }
+}
+
+/// Splits `memory_budget_mb` between the node's caches and queues by `MemoryWeights` and
+/// lowers each component's own size setting to what fits its share. Sizes are worst-case
+/// estimates: a block counts as `max_block_size_bytes`, so a budget bounds what a peer
+/// can make the node hold rather than its usual footprint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    total_bytes: u64,
+    weights: MemoryWeights,
+    block_bytes: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(total_bytes: u64, weights: MemoryWeights, block_bytes: u64) -> Self {
+        MemoryBudget {
+            total_bytes,
+            weights,
+            block_bytes: block_bytes.max(1),
+        }
+    }
+
+    /// The budget `config` sets; `None` when `memory_budget_mb` is 0.
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        (config.memory_budget_mb > 0).then(|| {
+            Self::new(
+                config.memory_budget_mb.saturating_mul(MB),
+                config.memory_budget_weights,
+                config.max_block_size_bytes as u64,
+            )
+        })
+    }
+
+    /// Bytes of the budget a component of `weight` gets; `None` for weight 0.
+    fn share(&self, weight: u64) -> Option<u64> {
+        let MemoryWeights {
+            block_cache,
+            orphan_pool,
+            mempool,
+            download_buffers,
+        } = self.weights;
+        let total_weight = block_cache + orphan_pool + mempool + download_buffers;
+        (weight > 0)
+            .then(|| (self.total_bytes as u128 * weight as u128 / total_weight as u128) as u64)
+    }
+
+    /// Lowers the cache, orphan pool, mempool and download buffer sizes of `config` to
+    /// their shares. Settings already below their share are kept.
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(share) = self.share(self.weights.block_cache) {
+            let [headers, bodies, hashes] = scale_to_fit(
+                [
+                    (config.header_cache_size, HEADER_BYTES),
+                    (config.body_cache_size, self.block_bytes),
+                    (config.block_hash_cache_size, HASH_BYTES),
+                ],
+                share,
+                0,
+            );
+            config.header_cache_size = headers;
+            config.body_cache_size = bodies;
+            config.block_hash_cache_size = hashes;
+        }
+        if let Some(share) = self.share(self.weights.orphan_pool) {
+            config.max_future_blocks = config.max_future_blocks.min(fits(share, self.block_bytes));
+        }
+        if let Some(share) = self.share(self.weights.mempool) {
+            let fitting = fits(share, TRANSACTION_BYTES).max(1);
+            config.tx_pool_max_transactions = match config.tx_pool_max_transactions {
+                0 => fitting,
+                max => max.min(fitting),
+            };
+        }
+        if let Some(share) = self.share(self.weights.download_buffers) {
+            // Sync can't make progress with less than one batch and one block per write.
+            let [depth, write_batch] = scale_to_fit(
+                [
+                    (
+                        config.sync_pipeline_depth,
+                        BLOCKS_PER_BATCH * self.block_bytes,
+                    ),
+                    (config.sync_write_batch_size, self.block_bytes),
+                ],
+                share,
+                1,
+            );
+            config.sync_pipeline_depth = depth;
+            config.sync_write_batch_size = write_batch;
+        }
+        info!(
+            "Memory budget of {} MB: {} headers, {} bodies and {} hashes cached, {} future \
+             blocks, {} pool transactions, sync pipeline depth {}, write batch {}",
+            self.total_bytes / MB,
+            config.header_cache_size,
+            config.body_cache_size,
+            config.block_hash_cache_size,
+            config.max_future_blocks,
+            config.tx_pool_max_transactions,
+            config.sync_pipeline_depth,
+            config.sync_write_batch_size
+        );
+    }
+}
+
+/// How many entries of `entry_bytes` fit in `share`.
+fn fits(share: u64, entry_bytes: u64) -> usize {
+    usize::try_from(share / entry_bytes.max(1)).unwrap_or(usize::MAX)
+}
+
+/// Shrinks the `(count, entry_bytes)` sizes by one factor so that together they fit in
+/// `share`, keeping their proportions and at least `min` of each.
+fn scale_to_fit<const N: usize>(sizes: [(usize, u64); N], share: u64, min: usize) -> [usize; N] {
+    let needed: u128 = sizes
+        .iter()
+        .map(|&(count, entry_bytes)| count as u128 * entry_bytes as u128)
+        .sum();
+    sizes.map(|(count, _)| {
+        if needed <= share as u128 {
+            count
+        } else {
+            ((count as u128 * share as u128 / needed) as usize).max(min.min(count))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_are_sized_to_their_weighted_share() {
+        let budget = MemoryBudget::new(100 * MB, MemoryWeights::default(), MB);
+
+        assert_eq!(budget.share(50), Some(50 * MB));
+        assert_eq!(budget.share(0), None);
+        // 50 MB can't hold 256 one-MB bodies plus the headers and hashes; all three
+        // shrink by the same factor.
+        assert_eq!(
+            scale_to_fit(
+                [(1024, HEADER_BYTES), (256, MB), (4096, HASH_BYTES)],
+                50 * MB,
+                0
+            ),
+            [198, 49, 795]
+        );
+        assert_eq!(
+            scale_to_fit([(4, 1000), (8, 10)], 1_000_000, 0),
+            [4, 8],
+            "settings already fitting are kept"
+        );
+        // 20 MB is less than a single 100-block batch, yet sync still gets one.
+        assert_eq!(
+            scale_to_fit([(2, BLOCKS_PER_BATCH * MB), (32, MB)], 20 * MB, 1),
+            [1, 2]
+        );
+        assert_eq!(fits(5 * MB, MB), 5);
+    }
+}
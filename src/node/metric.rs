@@ -1,4 +1,5 @@
 use axum::{routing::get, Router};
+use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::{encoding::text::encode as prometheus_encode, metrics::gauge::Gauge};
 use prometheus_client::registry::Registry;
@@ -11,9 +12,25 @@ pub struct BlockLabels {
     pub block_hash: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, prometheus_client::encoding::EncodeLabelSet)]
+pub struct PeerLabels {
+    pub peer_id: String,
+}
+
 lazy_static::lazy_static! {
     pub static ref LATEST_BLOCK_INDEX: Gauge = Gauge::default();
     pub static ref LATEST_BLOCK: Family<BlockLabels, Gauge> = Family::default();
+    pub static ref CONNECTED_PEERS: Gauge = Gauge::default();
+    pub static ref PEER_LATENCY_MS: Family<PeerLabels, Gauge> = Family::default();
+    pub static ref SYNC_STALLS: Counter = Counter::default();
+    pub static ref SYNC_BAD_BODIES: Counter = Counter::default();
+    pub static ref VALIDATOR_PROPOSED_BLOCKS: Counter = Counter::default();
+    pub static ref VALIDATOR_MISSED_PROPOSALS: Counter = Counter::default();
+    pub static ref FORK_BRANCHES: Gauge = Gauge::default();
+    pub static ref TX_POOL_EVICTED: Counter = Counter::default();
+    pub static ref FORK_MAX_DEPTH: Gauge = Gauge::default();
+    pub static ref DISK_FREE_BYTES: Gauge = Gauge::default();
+    pub static ref DISK_SAFE_MODE: Gauge = Gauge::default();
     
     static ref REGISTRY: Arc<Mutex<Registry>> = {
         let mut registry = Registry::default();
@@ -27,17 +44,75 @@ lazy_static::lazy_static! {
             "Current block of the clutch node",
             LATEST_BLOCK.clone(),
         );
+        registry.register(
+            "connected_peers",
+            "Number of peers connected to the clutch node",
+            CONNECTED_PEERS.clone(),
+        );
+        registry.register(
+            "peer_latency_ms",
+            "Last measured ping round-trip time per peer, in milliseconds",
+            PEER_LATENCY_MS.clone(),
+        );
+        registry.register(
+            "sync_stalls",
+            "Sync runs restarted by the watchdog after making no progress",
+            SYNC_STALLS.clone(),
+        );
+        registry.register(
+            "sync_bad_bodies",
+            "Block body batches dropped for not matching the headers they were requested for",
+            SYNC_BAD_BODIES.clone(),
+        );
+        registry.register(
+            "validator_proposed_blocks",
+            "Blocks this validator proposed in its own slots",
+            VALIDATOR_PROPOSED_BLOCKS.clone(),
+        );
+        registry.register(
+            "validator_missed_proposals",
+            "Slots of this validator that ended without a block from it",
+            VALIDATOR_MISSED_PROPOSALS.clone(),
+        );
+        registry.register(
+            "fork_branches",
+            "Competing branches connected peers are on",
+            FORK_BRANCHES.clone(),
+        );
+        registry.register(
+            "fork_max_depth",
+            "Blocks of our chain the deepest competing branch does not contain",
+            FORK_MAX_DEPTH.clone(),
+        );
+        registry.register(
+            "tx_pool_evicted",
+            "Transactions dropped from the pool after waiting longer than the pool expiry",
+            TX_POOL_EVICTED.clone(),
+        );
+        registry.register(
+            "disk_free_bytes",
+            "Free space on the file system holding the database, in bytes",
+            DISK_FREE_BYTES.clone(),
+        );
+        registry.register(
+            "disk_safe_mode",
+            "1 while low disk space keeps the node in safe mode",
+            DISK_SAFE_MODE.clone(),
+        );
         Arc::new(Mutex::new(registry))
     };
 }
 
-pub fn serve_metrics(config: &AppConfig) {
+/// Serves `/metrics` on `serve_metric_addr`, along with the `routes` of other read-only
+/// pages such as the status page.
+pub fn serve_metrics(config: &AppConfig, routes: Router) {
     let addr = config.serve_metric_addr.clone();
 
     tokio::spawn(async move {
         let app = Router::new()
             .route("/", get(track_and_respond))
-            .route("/metrics", get(metrics_handler));
+            .route("/metrics", get(metrics_handler))
+            .merge(routes);
 
         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
         axum::serve(listener, app).await.unwrap();
@@ -0,0 +1,309 @@
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::node::crypto::{self, SignatureAlgorithm};
+use crate::node::signature_keys::SignatureKeys;
+
+/// The BIP39 English wordlist, sorted, one word per line.
+const WORDLIST: &str = include_str!("bip39_english.txt");
+const PBKDF2_ROUNDS: u32 = 2048;
+const HARDENED: u32 = 1 << 31;
+/// BIP44 path of the first Ethereum account. Addresses are derived the Ethereum way, so
+/// a phrase recovers the same account here as in an Ethereum wallet.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| WORDLIST.lines().collect())
+}
+
+/// A BIP39 mnemonic phrase, whose seed validator keys are derived from with BIP32 so the
+/// phrase alone recovers them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    words: Vec<&'static str>,
+}
+
+impl Mnemonic {
+    /// A new phrase of `word_count` words: 12, 15, 18, 21 or 24.
+    pub fn generate(word_count: usize) -> Result<Self, String> {
+        Self::generate_with(word_count, &mut OsRng)
+    }
+
+    pub fn generate_with<R: RngCore + ?Sized>(
+        word_count: usize,
+        rng: &mut R,
+    ) -> Result<Self, String> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(format!(
+                "A mnemonic has 12, 15, 18, 21 or 24 words, not {}",
+                word_count
+            ));
+        }
+        let mut entropy = vec![0u8; word_count * 4 / 3];
+        rng.fill_bytes(&mut entropy);
+        Self::from_entropy(&entropy)
+    }
+
+    /// The phrase encoding `entropy` (16 to 32 bytes, a multiple of 4) plus its checksum.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, String> {
+        if !(16..=32).contains(&entropy.len()) || entropy.len() % 4 != 0 {
+            return Err(format!(
+                "Mnemonic entropy must be 16 to 32 bytes in steps of 4, not {}",
+                entropy.len()
+            ));
+        }
+        let checksum = Sha256::digest(entropy)[0];
+        let entropy_bits = entropy.len() * 8;
+        let bit = |i: usize| {
+            if i < entropy_bits {
+                (entropy[i / 8] >> (7 - i % 8)) & 1
+            } else {
+                (checksum >> (7 - (i - entropy_bits))) & 1
+            }
+        };
+        let word_count = (entropy_bits + entropy_bits / 32) / 11;
+        let words = (0..word_count)
+            .map(|word| {
+                let index = (0..11).fold(0usize, |index, i| {
+                    (index << 1) | bit(word * 11 + i) as usize
+                });
+                wordlist()[index]
+            })
+            .collect();
+        Ok(Self { words })
+    }
+
+    /// Parses a phrase, rejecting unknown words and a checksum that doesn't match, which
+    /// catches most typos and swapped words.
+    pub fn parse(phrase: &str) -> Result<Self, String> {
+        let mut words = Vec::new();
+        let mut bits = Vec::new();
+        for word in phrase.split_whitespace() {
+            let word = word.to_lowercase();
+            let index = wordlist()
+                .binary_search(&word.as_str())
+                .map_err(|_| format!("{:?} is not a BIP39 word", word))?;
+            words.push(wordlist()[index]);
+            bits.extend((0..11).rev().map(|i| ((index >> i) & 1) as u8));
+        }
+        if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+            return Err(format!(
+                "A mnemonic has 12, 15, 18, 21 or 24 words, not {}",
+                words.len()
+            ));
+        }
+        let entropy: Vec<u8> = bits[..bits.len() * 32 / 33]
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0u8, |value, bit| (value << 1) | bit))
+            .collect();
+        let mnemonic = Self::from_entropy(&entropy)?;
+        if mnemonic.words != words {
+            return Err("Invalid mnemonic checksum; check the words and their order".into());
+        }
+        Ok(mnemonic)
+    }
+
+    pub fn phrase(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// The 64-byte BIP39 seed. The passphrase is used as given, without Unicode
+    /// normalization, which only matters for non-ASCII passphrases.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let salt = format!("mnemonic{}", passphrase);
+        pbkdf2_sha512(self.phrase().as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS)
+    }
+
+    /// The keypair at `path` under this phrase and `passphrase`. BIP32 derives secp256k1
+    /// keys only, so chains signing with another scheme can't use it.
+    pub fn derive_keys(
+        &self,
+        passphrase: &str,
+        path: &DerivationPath,
+    ) -> Result<SignatureKeys, String> {
+        let signature = crypto::suite().signature;
+        if signature != SignatureAlgorithm::Secp256k1 {
+            return Err(format!(
+                "Mnemonic keys are secp256k1, but this chain signs with {}",
+                signature.name()
+            ));
+        }
+        let secret_key = path.derive(&self.to_seed(passphrase))?;
+        Ok(SignatureKeys::from_secret_key(&secret_key))
+    }
+}
+
+/// PBKDF2-HMAC-SHA512 with a single 64-byte output block, all BIP39 needs.
+fn pbkdf2_sha512(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 64] {
+    let mac = HmacSha512::new_from_slice(password).expect("HMAC takes keys of any length");
+    let mut block = mac.clone();
+    block.update(salt);
+    block.update(&1u32.to_be_bytes());
+    let mut u = block.finalize().into_bytes();
+    let mut output = [0u8; 64];
+    output.copy_from_slice(&u);
+    for _ in 1..rounds {
+        let mut block = mac.clone();
+        block.update(&u);
+        u = block.finalize().into_bytes();
+        for (out, byte) in output.iter_mut().zip(u.iter()) {
+            *out ^= byte;
+        }
+    }
+    output
+}
+
+/// A BIP32 path such as `m/44'/60'/0'/0/0`; `'` or `h` marks a hardened index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, String> {
+        let mut parts = path.trim().split('/');
+        if parts.next() != Some("m") {
+            return Err(format!("Derivation path {:?} must start with m/", path));
+        }
+        parts
+            .map(|part| {
+                let (number, hardened) = match part.strip_suffix(['\'', 'h']) {
+                    Some(number) => (number, HARDENED),
+                    None => (part, 0),
+                };
+                match number.parse::<u32>() {
+                    Ok(index) if index < HARDENED => Ok(index | hardened),
+                    _ => Err(format!("Invalid index {:?} in derivation path", part)),
+                }
+            })
+            .collect::<Result<Vec<u32>, String>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DerivationPath {
+    /// The secret key at this path below the master key of `seed`.
+    pub fn derive(&self, seed: &[u8]) -> Result<SecretKey, String> {
+        let secp = Secp256k1::new();
+        let mut mac = HmacSha512::new_from_slice(b"Bitcoin seed").expect("HMAC takes any key");
+        mac.update(seed);
+        let master = mac.finalize().into_bytes();
+        let mut secret_key = SecretKey::from_slice(&master[..32])
+            .map_err(|e| format!("Invalid master key: {}", e))?;
+        let mut chain_code = master[32..].to_vec();
+
+        for index in &self.0 {
+            let mut mac = HmacSha512::new_from_slice(&chain_code).expect("HMAC takes any key");
+            if index & HARDENED != 0 {
+                mac.update(&[0]);
+                mac.update(&secret_key.secret_bytes());
+            } else {
+                mac.update(&PublicKey::from_secret_key(&secp, &secret_key).serialize());
+            }
+            mac.update(&index.to_be_bytes());
+            let child = mac.finalize().into_bytes();
+            let mut tweak = [0u8; 32];
+            tweak.copy_from_slice(&child[..32]);
+            // Out of range with probability below 2^-127; BIP32 says to skip the index.
+            secret_key = Scalar::from_be_bytes(tweak)
+                .map_err(|_| "Derived key out of range; use the next index".to_string())
+                .and_then(|tweak| {
+                    secret_key
+                        .add_tweak(&tweak)
+                        .map_err(|_| "Derived key out of range; use the next index".to_string())
+                })?;
+            chain_code = child[32..].to_vec();
+        }
+        Ok(secret_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                           abandon abandon abandon about";
+
+    #[test]
+    fn phrases_match_the_bip39_vectors() {
+        assert_eq!(wordlist().len(), 2048);
+        assert_eq!(Mnemonic::from_entropy(&[0; 16]).unwrap().phrase(), ABANDON);
+        assert_eq!(
+            Mnemonic::from_entropy(&[0x7f; 16]).unwrap().phrase(),
+            "legal winner thank year wave sausage worth useful legal winner thank yellow"
+        );
+        let zoo = Mnemonic::from_entropy(&[0xff; 32]).unwrap().phrase();
+        assert!(zoo.starts_with("zoo zoo") && zoo.ends_with("zoo vote"));
+
+        assert_eq!(
+            hex::encode(Mnemonic::parse(ABANDON).unwrap().to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d1\
+             8264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+    }
+
+    #[test]
+    fn parsing_rejects_typos_and_bad_checksums() {
+        let mnemonic = Mnemonic::generate(24).unwrap();
+        assert_eq!(
+            Mnemonic::parse(&mnemonic.phrase().to_uppercase()),
+            Ok(mnemonic)
+        );
+
+        assert!(Mnemonic::parse(&ABANDON.replace("about", "abut")).is_err());
+        assert!(Mnemonic::parse(&ABANDON.replace("about", "above")).is_err());
+        assert!(Mnemonic::parse("abandon abandon about").is_err());
+        assert!(Mnemonic::generate(13).is_err());
+    }
+
+    #[test]
+    fn keys_derive_like_an_ethereum_wallet() {
+        let mnemonic = Mnemonic::parse(ABANDON).unwrap();
+        let path: DerivationPath = DEFAULT_DERIVATION_PATH.parse().unwrap();
+        assert_eq!(path.to_string(), DEFAULT_DERIVATION_PATH);
+
+        let keys = mnemonic.derive_keys("", &path).unwrap();
+        assert_eq!(
+            keys.address_key,
+            "0x9858effd232b4033e47d90003d41ec34ecaeda94"
+        );
+        assert_eq!(
+            keys.secret_key,
+            "1ab42cc412b618bdea3a599e3c9bae199ebf030895b039e9db1e30dafb12b727"
+        );
+        let second = mnemonic
+            .derive_keys("", &"m/44h/60h/0h/0/1".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            second.address_key,
+            "0x6fac4d18c912343bf86fa7049364dd4e424ab9c0"
+        );
+
+        assert!("44'/60'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+}
@@ -1,20 +1,53 @@
 pub mod account_state;
+pub mod address;
 pub mod aura;
+pub mod backup;
 pub mod balance_effect;
+pub mod bft;
 pub mod blocks;
 pub mod blockchain;
+pub mod config_reload;
 pub mod configuration;
+pub mod conformance;
 pub mod consensus;
 pub mod coordinate;
+pub mod crypto;
 pub mod database;
+pub mod disk_monitor;
+pub mod duties;
+pub mod era_server;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod file_utils;
+pub mod firehose;
+pub mod hardfork;
+pub mod history_sync;
+pub mod memory_budget;
 pub mod metric;
+pub mod mnemonic;
+pub mod node_builder;
 pub mod node_services;
 pub mod p2p_server;
+pub mod plugin;
+pub mod reorg;
 pub mod rlp_encoding;
+pub mod runtime;
 pub mod seq;
 pub mod signature_keys;
+pub mod signer;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod status_page;
+pub mod systemd;
+pub mod telemetry;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod testnet;
 pub mod time_utils;
 pub mod tracing;
 pub mod transactions;
+pub mod tui;
+pub mod validator_set;
+pub mod webhook;
 pub mod wss;
\ No newline at end of file
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::era::{self, EraArchive};
+use crate::node::configuration::AppConfig;
+use crate::node::crypto;
+use crate::node::database::Database;
+use crate::node::node_services::{NodeHandle, NodeServices};
+use crate::node::plugin::{NodePlugin, PluginRegistry};
+
+/// Assembles a node for crates that embed one instead of running the binary:
+/// `NodeBuilder::new(config).with_storage(dir).build().run().await`.
+pub struct NodeBuilder {
+    config: AppConfig,
+    storage_dir: Option<PathBuf>,
+    plugins: PluginRegistry,
+}
+
+impl NodeBuilder {
+    pub fn new(config: AppConfig) -> Self {
+        NodeBuilder {
+            config,
+            storage_dir: None,
+            plugins: PluginRegistry::default(),
+        }
+    }
+
+    /// Keeps the database, `<blockchain_name>.db`, in `dir` instead of under `DB_PATH` or
+    /// the working directory.
+    pub fn with_storage(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.storage_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_plugin(mut self, plugin: impl NodePlugin + 'static) -> Self {
+        self.plugins.register(Arc::new(plugin));
+        self
+    }
+
+    /// Opens the database and imports genesis if the chain is new. Nothing runs until
+    /// `Node::run`. Panics if this process already hashes with another `CryptoSuite`, or
+    /// if `era_dir` is set and its era files can't be read.
+    pub fn build(self) -> Node {
+        if let Err(e) = crypto::install(self.config.crypto_suite()) {
+            panic!("{}", e);
+        }
+        if !self.config.era_dir.is_empty() {
+            match EraArchive::open(&self.config.era_dir, &self.config.chain_id) {
+                Ok(archive) => era::install_archive(Arc::new(archive)),
+                Err(e) => panic!("Failed to open the era archive: {}", e),
+            }
+        }
+        let db = match &self.storage_dir {
+            Some(dir) => Database::open(&Database::db_path_in(
+                &dir.to_string_lossy(),
+                &self.config.blockchain_name,
+            )),
+            None => Database::new_db(&self.config.blockchain_name),
+        };
+        let blockchain = Blockchain::from_config(&self.config, db).with_plugins(self.plugins);
+        Node {
+            config: self.config,
+            blockchain,
+        }
+    }
+}
+
+/// A built node: its chain is open and can be queried or maintained before `run` starts
+/// the network services.
+pub struct Node {
+    config: AppConfig,
+    blockchain: Blockchain,
+}
+
+impl Node {
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.blockchain
+    }
+
+    /// Starts P2P, RPC, authoring and the optional exporters, and returns after a Ctrl+C or
+    /// once the P2P or WebSocket server stops, with the chain shut down.
+    pub async fn run(self) {
+        NodeServices::start_services(&self.config, self.blockchain).await;
+    }
+
+    /// `run`, handing `on_start` a `NodeHandle` to the running node, e.g. to submit
+    /// transactions or stop it from another thread.
+    pub async fn run_with_handle(self, on_start: impl FnOnce(NodeHandle)) {
+        NodeServices::start_services_with_handle(&self.config, self.blockchain, on_start).await;
+    }
+}
@@ -1,69 +1,215 @@
 use crate::node::blockchain::Blockchain;
+use crate::node::blocks::era;
+use crate::node::config_reload::ConfigReloader;
 use crate::node::configuration::AppConfig;
+use crate::node::disk_monitor::DiskMonitor;
+use crate::node::era_server;
+use crate::node::firehose::postgres::PostgresExporter;
+use crate::node::firehose::Firehose;
+use crate::node::history_sync::HistorySync;
 use crate::node::metric::serve_metrics;
 use crate::node::p2p_server::commands::DirectMessageType;
-use crate::node::p2p_server::{GossipMessageType, P2PServer, P2PServerCommand};
+use crate::node::p2p_server::compact_block::CompactBlock;
+use crate::node::p2p_server::disconnect::DisconnectReason;
+use crate::node::p2p_server::{GossipMessageType, P2PConfig, P2PServer, P2PServerCommand};
 use crate::node::rlp_encoding::encode;
+use crate::node::status_page;
+use crate::node::systemd::{Systemd, AUTHORING_LOOP, P2P_LISTENING, RPC_LISTENING};
+use crate::node::telemetry::Telemetry;
+use crate::node::time_utils::get_current_timespan;
+use crate::node::tui::Dashboard;
 use crate::node::wss::websocket::WebSocket;
 
+use axum::Router;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::signal;
-use tokio::sync::{mpsc, oneshot, Mutex};
-use tracing::{debug, error, info};
+use tokio::sync::{mpsc, oneshot, Notify, RwLock};
+use tracing::{debug, error, info, warn};
+
+const SHUTDOWN_DISCONNECT_GRACE: Duration = Duration::from_millis(500);
+/// How often the pool is swept for expired transactions.
+const POOL_EVICTION_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct NodeServices;
 
+/// What the running services share, for embedders that drive a node from outside it:
+/// the chain, the P2P server's command channel, and `shutdown`.
+#[derive(Clone)]
+pub struct NodeHandle {
+    pub blockchain: Arc<RwLock<Blockchain>>,
+    pub commands: mpsc::Sender<P2PServerCommand>,
+    shutdown: Arc<Notify>,
+}
+
+impl NodeHandle {
+    /// Stops the node as Ctrl+C would.
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+}
+
 impl NodeServices {
     pub async fn start_services(config: &AppConfig, blockchain: Blockchain) {
-        let blockchain_arc = Arc::new(Mutex::new(blockchain));
+        Self::start_services_with_handle(config, blockchain, |_| {}).await;
+    }
 
-        if config.serve_metric_enabled {
-            serve_metrics(config);
-        }
+    /// `start_services`, handing `on_start` the node's `NodeHandle` before anything runs.
+    pub async fn start_services_with_handle(
+        config: &AppConfig,
+        blockchain: Blockchain,
+        on_start: impl FnOnce(NodeHandle),
+    ) {
+        let blockchain_arc = Arc::new(RwLock::new(blockchain));
 
         let (libp2p_shutdown_tx, libp2p_shutdown_rx) = oneshot::channel();
         let (command_tx_p2p, command_rx_p2p) = mpsc::channel(32);
+        let shutdown = Arc::new(Notify::new());
+        on_start(NodeHandle {
+            blockchain: Arc::clone(&blockchain_arc),
+            commands: command_tx_p2p.clone(),
+            shutdown: Arc::clone(&shutdown),
+        });
+        // The database is open by now; readiness waits on the servers listening.
+        let systemd = Systemd::new(&[P2P_LISTENING, RPC_LISTENING]);
+        systemd.start_watchdog();
+
+        if config.serve_metric_enabled {
+            let mut routes = if config.status_page_enabled {
+                status_page::router(
+                    Arc::clone(&blockchain_arc),
+                    command_tx_p2p.clone(),
+                    config.chain_id.clone(),
+                )
+            } else {
+                Router::new()
+            };
+            if let Some(archive) = era::installed_archive().filter(|_| config.serve_era_files) {
+                routes = routes.merge(era_server::router(archive));
+            }
+            serve_metrics(config, routes);
+        }
+
+        if config.disk_warn_free_mb > 0 {
+            DiskMonitor::start(config);
+        }
+
+        if !config.history_urls.is_empty() {
+            Self::sync_history(config, &blockchain_arc).await;
+        }
+
         Self::start_libp2p(
             config,
             Arc::clone(&blockchain_arc),
             libp2p_shutdown_tx,
             command_rx_p2p,
+            systemd.clone(),
         );
 
+        let reloader = ConfigReloader::new(config, command_tx_p2p.clone());
+        reloader.listen_for_sighup();
+
         let (websocket_shutdown_tx, websocket_shutdown_rx) = oneshot::channel();
         Self::start_websocket(
             config,
             Arc::clone(&blockchain_arc),
             command_tx_p2p.clone(),
+            reloader,
+            systemd.clone(),
             websocket_shutdown_tx,
         );
 
         if config.block_authoring_enabled {
-            Self::start_authoring_job(Arc::clone(&blockchain_arc), 1, command_tx_p2p.clone());
+            Self::start_authoring_job(
+                Arc::clone(&blockchain_arc),
+                1,
+                config.compact_block_relay,
+                command_tx_p2p.clone(),
+                systemd.clone(),
+            );
+        }
+
+        if config.bft_enabled {
+            Self::start_bft_job(Arc::clone(&blockchain_arc), command_tx_p2p.clone());
+        }
+
+        if config.local_tx_rebroadcast_secs > 0 {
+            Self::start_local_rebroadcast(
+                Arc::clone(&blockchain_arc),
+                config.local_tx_rebroadcast_secs,
+                command_tx_p2p.clone(),
+            );
+        }
+
+        if config.tx_pool_ttl_secs > 0 || config.tx_pool_ttl_blocks > 0 {
+            Self::start_pool_eviction(Arc::clone(&blockchain_arc));
         }
 
         if config.sync_enabled {
             Self::start_sync(Arc::clone(&blockchain_arc), command_tx_p2p.clone());
         }
 
+        if !config.firehose_nats_url.is_empty() {
+            Firehose::start(config, Arc::clone(&blockchain_arc));
+        }
+
+        if !config.explorer_postgres_url.is_empty() {
+            PostgresExporter::start(config, Arc::clone(&blockchain_arc));
+        }
+
+        if !config.telemetry_url.is_empty() {
+            Telemetry::start(config, Arc::clone(&blockchain_arc), command_tx_p2p.clone());
+        }
+
+        if config.tui_enabled {
+            Dashboard::start(
+                Arc::clone(&blockchain_arc),
+                command_tx_p2p.clone(),
+                config.chain_id.clone(),
+            );
+        }
+
         Self::wait_for_shutdown_signal(
             libp2p_shutdown_rx,
             websocket_shutdown_rx,
+            shutdown,
             Arc::clone(&blockchain_arc),
+            command_tx_p2p,
+            systemd,
         )
         .await;
     }
 
+    /// Imports checkpointed history over HTTP before the P2P server starts syncing.
+    async fn sync_history(config: &AppConfig, blockchain: &RwLock<Blockchain>) {
+        let Some(archive) = era::installed_archive() else {
+            warn!("history_urls needs era_dir to download into; syncing all history from peers");
+            return;
+        };
+        match HistorySync::from_config(config)
+            .run(blockchain, &archive)
+            .await
+        {
+            Ok(imported) => info!("Imported {} blocks of history over HTTP", imported),
+            Err(e) => warn!("History sync stopped: {}; peers sync the rest", e),
+        }
+    }
+
     async fn wait_for_shutdown_signal(
         libp2p_shutdown_rx: oneshot::Receiver<()>,
         websocket_shutdown_rx: oneshot::Receiver<()>,
-        blockchain: Arc<Mutex<Blockchain>>,
+        shutdown: Arc<Notify>,
+        blockchain: Arc<RwLock<Blockchain>>,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        systemd: Systemd,
     ) {
         tokio::select! {
             _ = signal::ctrl_c() => {
                 info!("Received Ctrl+C, shutting down.");
             }
+            _ = shutdown.notified() => {
+                info!("Shutdown requested by the embedding program.");
+            }
             _ = libp2p_shutdown_rx => {
                 info!("Libp2p service completed, shutting down.");
             }
@@ -71,29 +217,36 @@ impl NodeServices {
                 info!("WebSocket service completed, shutting down.");
             }
         }
+        systemd.stopping();
+
+        // Best effort: tell peers why we are going away and give the messages a moment to
+        // leave before the process exits.
+        match P2PServer::disconnect_all_command(command_tx_p2p, DisconnectReason::ShuttingDown)
+            .await
+        {
+            Ok(count) if count > 0 => tokio::time::sleep(SHUTDOWN_DISCONNECT_GRACE).await,
+            Ok(_) => {}
+            Err(e) => debug!("Could not notify peers of shutdown: {}", e),
+        }
 
-        let mut blockchain = blockchain.lock().await;
+        let mut blockchain = blockchain.write().await;
         blockchain.shutdown_blockchain();
     }
 
     fn start_libp2p(
         config: &AppConfig,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         libp2p_shutdown_tx: oneshot::Sender<()>,
         command_rx: tokio::sync::mpsc::Receiver<P2PServerCommand>,
+        systemd: Systemd,
     ) {
-        let listen_addrs: Vec<&str> = config.listen_addrs.iter().map(|s| s.as_str()).collect();
-        let bootstrap_nodes: Vec<&str> =
-            config.bootstrap_nodes.iter().map(|s| s.as_str()).collect();
-
-        let mut p2p_server =
-            match P2PServer::new(&config.libp2p_topic_name, &listen_addrs, &bootstrap_nodes) {
-                Ok(server) => server,
-                Err(e) => {
-                    error!("Failed to create P2PServer: {}", e);
-                    return;
-                }
-            };
+        let mut p2p_server = match P2PServer::with_config(P2PConfig::from_app_config(config)) {
+            Ok(server) => server.with_systemd(systemd),
+            Err(e) => {
+                error!("Failed to create P2PServer: {}", e);
+                return;
+            }
+        };
 
         tokio::spawn(async move {
             {
@@ -107,14 +260,26 @@ impl NodeServices {
 
     fn start_websocket(
         config: &AppConfig,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        reloader: ConfigReloader,
+        systemd: Systemd,
         websocket_shutdown_tx: oneshot::Sender<()>,
     ) {
         let websocket_addr = config.websocket_addr.clone();
+        let debug_rpc_enabled = config.debug_rpc_enabled;
 
         tokio::spawn(async move {
-            if let Err(e) = WebSocket::run(&websocket_addr, blockchain, command_tx_p2p).await {
+            if let Err(e) = WebSocket::run(
+                &websocket_addr,
+                blockchain,
+                command_tx_p2p,
+                debug_rpc_enabled,
+                reloader,
+                systemd,
+            )
+            .await
+            {
                 error!("Error starting WebSocket server: {}", e);
             }
             let _ = websocket_shutdown_tx.send(());
@@ -122,21 +287,33 @@ impl NodeServices {
     }
 
     pub fn start_authoring_job(
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         interval_secs: u64,
+        compact_block_relay: bool,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        systemd: Systemd,
     ) {
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            let mut last_missed = None;
             loop {
                 interval.tick().await;
-                let blockchain = blockchain.lock().await;
+                systemd.beat(AUTHORING_LOOP);
+                let blockchain = blockchain.write().await;
                 match blockchain.author_new_block() {
                     Ok(block) => {
-                        let encoded_block = encode(&block);
+                        blockchain.record_proposal(&block);
+                        let (message_type, encoded_block) = if compact_block_relay {
+                            (
+                                GossipMessageType::CompactBlock,
+                                encode(&CompactBlock::from_block(&block)),
+                            )
+                        } else {
+                            (GossipMessageType::Block, encode(&block))
+                        };
                         P2PServer::gossip_message_command(
                             command_tx_p2p.clone(),
-                            GossipMessageType::Block,
+                            message_type,
                             &encoded_block,
                         )
                         .await;
@@ -145,18 +322,118 @@ impl NodeServices {
                         debug!("Error authoring new block: {:?}", e);
                     }
                 }
+                // Ticks are shorter than slots, so each miss is seen several times.
+                match blockchain.missed_slot(get_current_timespan()) {
+                    Ok(Some(missed)) if last_missed.as_ref() != Some(&missed) => {
+                        warn!("{}", missed.describe(blockchain.chain_id()));
+                        if blockchain.record_missed_slot(&missed) {
+                            error!("This node missed its own slot as an authority");
+                        }
+                        blockchain.notifier().notify(missed.clone());
+                        last_missed = Some(missed);
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to check for a missed slot: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Every second, moves the BFT engine on and gossips the votes it cast.
+    pub fn start_bft_job(
+        blockchain: Arc<RwLock<Blockchain>>,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let votes = blockchain.read().await.bft_tick(get_current_timespan());
+                let votes = match votes {
+                    Ok(votes) => votes,
+                    Err(e) => {
+                        error!("Failed to cast BFT votes: {}", e);
+                        continue;
+                    }
+                };
+                for vote in votes {
+                    P2PServer::gossip_message_command(
+                        command_tx_p2p.clone(),
+                        GossipMessageType::Vote,
+                        &encode(&vote),
+                    )
+                    .await;
+                }
+            }
+        });
+    }
+
+    /// Broadcasts the pool's local transactions again every `interval_secs`, so one that
+    /// a peer missed or dropped still reaches the authorities.
+    pub fn start_local_rebroadcast(
+        blockchain: Arc<RwLock<Blockchain>>,
+        interval_secs: u64,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) {
+        tokio::spawn(async move {
+            let period = Duration::from_secs(interval_secs);
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                let local = match blockchain.read().await.get_local_transactions() {
+                    Ok(local) => local,
+                    Err(e) => {
+                        error!("Failed to read local transactions: {}", e);
+                        continue;
+                    }
+                };
+                if !local.is_empty() {
+                    debug!("Re-broadcasting {} local transactions", local.len());
+                }
+                for transaction in local {
+                    P2PServer::broadcast_transaction_command(command_tx_p2p.clone(), transaction)
+                        .await;
+                }
+            }
+        });
+    }
+
+    /// Drops expired remote transactions from the pool every `POOL_EVICTION_INTERVAL`;
+    /// see `Blockchain::evict_expired_transactions`.
+    pub fn start_pool_eviction(blockchain: Arc<RwLock<Blockchain>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + POOL_EVICTION_INTERVAL,
+                POOL_EVICTION_INTERVAL,
+            );
+            loop {
+                interval.tick().await;
+                match blockchain.write().await.evict_expired_transactions() {
+                    Ok(evicted) if !evicted.is_empty() => {
+                        info!(
+                            "Evicted {} expired transactions from the pool",
+                            evicted.len()
+                        );
+                        for (transaction, reason) in evicted {
+                            debug!("Evicted transaction {} ({:?})", transaction.hash, reason);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to evict expired transactions: {}", e),
+                }
             }
         });
     }
 
     pub fn start_sync(
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
     ) {
         // Initial delay to let libp2p connections establish, then re-sync on an interval.
         // A one-shot handshake at startup meant a node that fell behind later (was down,
         // missed gossip, or rejected a non-chaining future block) never caught up until a
-        // manual restart. Periodically re-handshaking a connected peer reuses the existing
+        // manual restart. Periodically re-handshaking the best-scoring peer reuses the existing
         // handshake -> GetBlockHeaders/GetBlockBodies pull path: a no-op when already at the
         // peer's height, a catch-up when behind.
         // NOTE: this is LIVENESS only. It does not add fork-choice or finality — the node
@@ -172,12 +449,10 @@ impl NodeServices {
             loop {
                 interval.tick().await;
 
-                let peer_id = match P2PServer::get_connected_peers_command(command_tx_p2p.clone())
-                    .await
-                {
-                    Ok(connected_peers) => connected_peers.iter().next().cloned(),
+                let peer_id = match P2PServer::get_sync_peer_command(command_tx_p2p.clone()).await {
+                    Ok(peer_id) => peer_id,
                     Err(e) => {
-                        error!("Failed to get connected peers: {}", e);
+                        error!("Failed to get sync peer: {}", e);
                         continue;
                     }
                 };
@@ -191,9 +466,9 @@ impl NodeServices {
                 };
 
                 // Scope the lock to building the handshake — never hold the global
-                // blockchain Mutex across the network send below.
+                // blockchain lock across the network send below.
                 let encoded_handshake = {
-                    let blockchain = blockchain.lock().await;
+                    let blockchain = blockchain.read().await;
                     match blockchain.handshake() {
                         Ok(handshake) => encode(&handshake),
                         Err(e) => {
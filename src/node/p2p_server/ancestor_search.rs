@@ -0,0 +1,83 @@
+/// Binary search for the highest block we share with a peer whose chain doesn't contain
+/// our tip. Each step asks the peer for one header and compares its hash with ours at
+/// the same index, so it takes about log2(tip) round trips instead of walking back one
+/// header at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AncestorSearch {
+    /// Highest index known to match. Starts at genesis, which the handshake checked.
+    common: usize,
+    /// Lowest index known to differ.
+    diverged: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AncestorStep {
+    /// Ask the peer for its header at this index.
+    Probe(usize),
+    /// The latest common ancestor.
+    Found(usize),
+}
+
+impl AncestorSearch {
+    /// Starts a search below `diverged`, a block where the peer's chain and ours differ.
+    pub fn new(diverged: usize) -> Self {
+        Self {
+            common: 0,
+            diverged: diverged.max(1),
+        }
+    }
+
+    pub fn next_step(&self) -> AncestorStep {
+        if self.diverged - self.common <= 1 {
+            AncestorStep::Found(self.common)
+        } else {
+            AncestorStep::Probe(self.common + (self.diverged - self.common) / 2)
+        }
+    }
+
+    /// Folds in the answer to the current probe: whether the peer's block at that index
+    /// has the same hash as ours.
+    pub fn record(&mut self, matches: bool) -> AncestorStep {
+        if let AncestorStep::Probe(index) = self.next_step() {
+            if matches {
+                self.common = index;
+            } else {
+                self.diverged = index;
+            }
+        }
+        self.next_step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(tip: usize, fork_after: usize) -> (usize, usize) {
+        let mut search = AncestorSearch::new(tip);
+        let mut probes = 0;
+        let mut step = search.next_step();
+        while let AncestorStep::Probe(index) = step {
+            probes += 1;
+            step = search.record(index <= fork_after);
+        }
+        match step {
+            AncestorStep::Found(ancestor) => (ancestor, probes),
+            AncestorStep::Probe(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn finds_the_last_shared_block() {
+        assert_eq!(run(1000, 637).0, 637);
+        assert_eq!(run(1000, 999).0, 999);
+        assert_eq!(run(1000, 0).0, 0);
+        assert_eq!(run(1, 0), (0, 0));
+    }
+
+    #[test]
+    fn probes_logarithmically() {
+        let (_, probes) = run(1 << 20, 12345);
+        assert!(probes <= 20, "took {} probes", probes);
+    }
+}
@@ -0,0 +1,333 @@
+use futures::io::{AsyncRead, AsyncWrite};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// Smallest chunk we wait for once a bucket is empty, so a low limit results in a few
+/// packet-sized transfers per second rather than a wakeup per byte.
+const MIN_CHUNK: f64 = 1500.0;
+
+/// Byte-per-second caps for the p2p transport. `None` means unlimited. The per-peer caps
+/// apply to each connection, which in practice is one per peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BandwidthLimits {
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+    pub peer_upload: Option<u64>,
+    pub peer_download: Option<u64>,
+}
+
+impl BandwidthLimits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Burst capacity is one second worth of traffic.
+    fn new(bytes_per_sec: u64, now: Instant) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        Self {
+            rate,
+            capacity: rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Bytes that may be transferred right now, or how long to wait before trying again.
+    fn allowance(&mut self, now: Instant) -> Result<usize, Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return Ok(self.tokens as usize);
+        }
+        let target = MIN_CHUNK.min(self.capacity);
+        Err(Duration::from_secs_f64((target - self.tokens) / self.rate))
+    }
+
+    /// Tokens may go negative when several connections share a bucket; the debt is repaid
+    /// before anyone transfers again.
+    fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+#[derive(Debug, Default)]
+struct DirectionalBuckets {
+    upload: Option<TokenBucket>,
+    download: Option<TokenBucket>,
+}
+
+impl DirectionalBuckets {
+    fn new(upload: Option<u64>, download: Option<u64>, now: Instant) -> Self {
+        Self {
+            upload: upload.map(|rate| TokenBucket::new(rate, now)),
+            download: download.map(|rate| TokenBucket::new(rate, now)),
+        }
+    }
+
+    fn bucket(&mut self, direction: Direction) -> Option<&mut TokenBucket> {
+        match direction {
+            Direction::Upload => self.upload.as_mut(),
+            Direction::Download => self.download.as_mut(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Upload,
+    Download,
+}
+
+/// Shared state for the global caps plus the per-connection limits to hand out.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    limits: Mutex<BandwidthLimits>,
+    global: Mutex<DirectionalBuckets>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(limits: BandwidthLimits) -> Arc<Self> {
+        Arc::new(Self {
+            limits: Mutex::new(limits),
+            global: Mutex::new(DirectionalBuckets::new(
+                limits.upload,
+                limits.download,
+                Instant::now(),
+            )),
+        })
+    }
+
+    pub fn limits(&self) -> BandwidthLimits {
+        *self.limits.lock().unwrap()
+    }
+
+    /// Replaces the caps on a running node. The global caps apply at once; per-peer caps
+    /// apply to connections established from now on.
+    pub fn set_limits(&self, limits: BandwidthLimits) {
+        *self.global.lock().unwrap() =
+            DirectionalBuckets::new(limits.upload, limits.download, Instant::now());
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    /// Wraps a freshly established connection so its traffic counts against the caps.
+    pub fn throttle<S>(self: &Arc<Self>, inner: S) -> ThrottledStream<S> {
+        let limits = self.limits();
+        ThrottledStream {
+            inner,
+            limiter: Arc::clone(self),
+            connection: DirectionalBuckets::new(
+                limits.peer_upload,
+                limits.peer_download,
+                Instant::now(),
+            ),
+            read_delay: None,
+            write_delay: None,
+        }
+    }
+}
+
+/// Raw connection wrapper that delays reads/writes once a token bucket is exhausted.
+/// Sits below noise/yamux, so it covers gossip and request/response traffic alike.
+pub struct ThrottledStream<S> {
+    inner: S,
+    limiter: Arc<BandwidthLimiter>,
+    connection: DirectionalBuckets,
+    read_delay: Option<Pin<Box<Sleep>>>,
+    write_delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ThrottledStream<S> {
+    fn allowance(&mut self, direction: Direction, wanted: usize) -> Result<usize, Duration> {
+        let now = Instant::now();
+        let mut allowed = wanted;
+        let mut wait = Duration::ZERO;
+
+        let mut global = self.limiter.global.lock().unwrap();
+        for bucket in [global.bucket(direction), self.connection.bucket(direction)]
+            .into_iter()
+            .flatten()
+        {
+            match bucket.allowance(now) {
+                Ok(available) => allowed = allowed.min(available),
+                Err(delay) => wait = wait.max(delay),
+            }
+        }
+
+        if wait > Duration::ZERO {
+            Err(wait)
+        } else {
+            Ok(allowed)
+        }
+    }
+
+    fn consume(&mut self, direction: Direction, bytes: usize) {
+        let mut global = self.limiter.global.lock().unwrap();
+        for bucket in [global.bucket(direction), self.connection.bucket(direction)]
+            .into_iter()
+            .flatten()
+        {
+            bucket.consume(bytes);
+        }
+    }
+}
+
+fn poll_delay(delay: &mut Option<Pin<Box<Sleep>>>, cx: &mut Context<'_>) -> Poll<()> {
+    if let Some(sleep) = delay.as_mut() {
+        ready!(sleep.as_mut().poll(cx));
+        *delay = None;
+    }
+    Poll::Ready(())
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        loop {
+            ready!(poll_delay(&mut this.read_delay, cx));
+            match this.allowance(Direction::Download, buf.len()) {
+                Ok(allowed) => {
+                    let read =
+                        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf[..allowed]))?;
+                    this.consume(Direction::Download, read);
+                    return Poll::Ready(Ok(read));
+                }
+                Err(wait) => this.read_delay = Some(Box::pin(tokio::time::sleep(wait))),
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        loop {
+            ready!(poll_delay(&mut this.write_delay, cx));
+            match this.allowance(Direction::Upload, buf.len()) {
+                Ok(allowed) => {
+                    let written =
+                        ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..allowed]))?;
+                    this.consume(Direction::Upload, written);
+                    return Poll::Ready(Ok(written));
+                }
+                Err(wait) => this.write_delay = Some(Box::pin(tokio::time::sleep(wait))),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::{AsyncReadExt, AsyncWriteExt, Cursor};
+
+    #[test]
+    fn bucket_refills_at_rate_up_to_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000, start);
+
+        assert_eq!(bucket.allowance(start), Ok(1000));
+        bucket.consume(1000);
+        assert_eq!(bucket.allowance(start), Err(Duration::from_secs_f64(1.0)));
+        assert_eq!(
+            bucket.allowance(start + Duration::from_millis(500)),
+            Ok(500)
+        );
+        assert_eq!(bucket.allowance(start + Duration::from_secs(10)), Ok(1000));
+    }
+
+    #[test]
+    fn bucket_debt_is_repaid_before_more_traffic() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(4000, start);
+        bucket.consume(6000);
+
+        // 2000 bytes of debt plus the 1500 byte chunk we wait for.
+        assert_eq!(
+            bucket.allowance(start),
+            Err(Duration::from_secs_f64(3500.0 / 4000.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn unlimited_stream_passes_data_through() {
+        let limiter = BandwidthLimiter::new(BandwidthLimits::default());
+        let mut stream = limiter.throttle(Cursor::new(Vec::new()));
+        stream.write_all(b"clutch").await.unwrap();
+
+        let mut stream = limiter.throttle(Cursor::new(stream.inner.into_inner()));
+        let mut read = String::new();
+        stream.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "clutch");
+    }
+
+    #[tokio::test]
+    async fn writes_are_capped_by_connection_limit() {
+        let limiter = BandwidthLimiter::new(BandwidthLimits {
+            peer_upload: Some(4096),
+            ..BandwidthLimits::default()
+        });
+        let mut stream = limiter.throttle(Cursor::new(Vec::new()));
+
+        let written = stream.write(&[0u8; 10_000]).await.unwrap();
+        assert_eq!(written, 4096);
+    }
+
+    #[tokio::test]
+    async fn new_limits_apply_to_later_connections() {
+        let limiter = BandwidthLimiter::new(BandwidthLimits::default());
+        let mut before = limiter.throttle(Cursor::new(Vec::new()));
+        limiter.set_limits(BandwidthLimits {
+            peer_upload: Some(2048),
+            ..BandwidthLimits::default()
+        });
+        let mut after = limiter.throttle(Cursor::new(Vec::new()));
+
+        assert_eq!(before.write(&[0u8; 10_000]).await.unwrap(), 10_000);
+        assert_eq!(after.write(&[0u8; 10_000]).await.unwrap(), 2048);
+        assert_eq!(limiter.limits().peer_upload, Some(2048));
+    }
+}
@@ -1,23 +1,99 @@
 use libp2p::{
     gossipsub::Behaviour as GossipsubBehaviour, mdns::tokio::Behaviour as MsdnBehaviour,
-    request_response::cbor::Behaviour as RequestResponseBehavior, swarm::NetworkBehaviour,
+    request_response::Behaviour as CodecBehaviour, swarm::NetworkBehaviour,
 };
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::connection_gate::ConnectionGate;
+use super::message_codec::DirectMessageCodec;
+use super::sync_stream::SyncStreamCodec;
 
 #[derive(NetworkBehaviour)]
 pub struct P2PBehaviour {
+    pub gate: ConnectionGate,
     pub gossipsub: GossipsubBehaviour,
     pub mdns: MsdnBehaviour,
-    pub request_response: RequestResponseBehavior<DirectMessageRequest, DirectMessageResponse>,
+    /// Direct messages, over `/agent/message/2.0.0` or, with older nodes, `/1.0.0`.
+    pub request_response: CodecBehaviour<DirectMessageCodec>,
+    /// Block ranges for sync, served over `/clutch/sync/1`.
+    pub sync_stream: CodecBehaviour<SyncStreamCodec>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DirectMessageRequest {
     pub message: Vec<u8>,
+    /// `checksum(message)`. Required on the current protocol; older nodes on the legacy
+    /// one leave it out, and `DirectMessageCodec` fills it in for their messages.
+    #[serde(default)]
+    pub checksum: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DirectMessageResponse {
     pub message: Vec<u8>,
+    /// `checksum(message)`; see `DirectMessageRequest::checksum`.
+    #[serde(default)]
+    pub checksum: Option<u32>,
+}
+
+/// First four bytes of the message's SHA-256. A message that doesn't match its checksum
+/// was truncated or corrupted on the way, and is dropped as a transport error instead of
+/// failing to decode as if the peer had sent garbage.
+pub fn checksum(message: &[u8]) -> u32 {
+    let digest = Sha256::digest(message);
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// Whether `message` matches its checksum. A missing checksum fails, or any sender could
+/// turn the check off by leaving it out.
+fn is_intact(message: &[u8], expected: Option<u32>) -> bool {
+    expected == Some(checksum(message))
+}
+
+impl DirectMessageRequest {
+    pub fn new(message: Vec<u8>) -> Self {
+        DirectMessageRequest {
+            checksum: Some(checksum(&message)),
+            message,
+        }
+    }
+
+    pub fn is_intact(&self) -> bool {
+        is_intact(&self.message, self.checksum)
+    }
+}
+
+impl DirectMessageResponse {
+    pub fn new(message: Vec<u8>) -> Self {
+        DirectMessageResponse {
+            checksum: Some(checksum(&message)),
+            message,
+        }
+    }
+
+    pub fn is_intact(&self) -> bool {
+        is_intact(&self.message, self.checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupted_messages_fail_their_checksum() {
+        let request = DirectMessageRequest::new(vec![0x07, 0xc1, 0x2a]);
+        assert!(request.is_intact());
+        let mut truncated = request.clone();
+        truncated.message.pop();
+        assert!(!truncated.is_intact());
+        let mut flipped = DirectMessageResponse::new(vec![0x08, 0xc1, 0x2a]);
+        flipped.message[2] ^= 0x01;
+        assert!(!flipped.is_intact());
+        let mut unchecked = DirectMessageRequest::new(vec![0x07, 0xc1, 0x2a]);
+        unchecked.checksum = None;
+        assert!(!unchecked.is_intact(), "a checksum is required");
+    }
 }
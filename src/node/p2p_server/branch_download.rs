@@ -0,0 +1,221 @@
+use libp2p::PeerId;
+use std::collections::VecDeque;
+
+use super::get_block_header::GetBlockHeaders;
+use crate::node::blocks::block::Block;
+use crate::node::blocks::block_headers::{BlockHeader, BlockHeaders};
+
+/// A peer's branch above the last block our chains share, downloaded before anything of
+/// ours is unwound. Headers have to chain onto the ancestor and each body has to match
+/// its header; `Blockchain::switch_to_branch` then validates the blocks as it applies
+/// them, and only takes the branch if all of it is valid and heavier than our chain.
+#[derive(Debug, Clone)]
+pub struct BranchDownload {
+    ancestor: usize,
+    /// Index and hash of the last block we hold a header for; the next headers build on it.
+    tip: (usize, String),
+    /// The last block we need: one past our head, so the branch outweighs our chain.
+    target: usize,
+    /// Headers whose bodies haven't arrived, in order.
+    headers: VecDeque<BlockHeader>,
+    blocks: Vec<Block>,
+    max_per_request: usize,
+}
+
+/// What switching to a downloaded branch came to, handed back to the swarm task.
+#[derive(Debug)]
+pub struct BranchSwitch {
+    pub peer_id: PeerId,
+    pub ancestor: usize,
+    /// How many of our blocks were removed, or why the branch wasn't taken.
+    pub result: Result<usize, String>,
+}
+
+impl BranchDownload {
+    pub fn new(
+        ancestor: usize,
+        ancestor_hash: String,
+        target: usize,
+        max_per_request: usize,
+    ) -> Self {
+        Self {
+            ancestor,
+            tip: (ancestor, ancestor_hash),
+            target,
+            headers: VecDeque::new(),
+            blocks: Vec::new(),
+            max_per_request: max_per_request.max(1),
+        }
+    }
+
+    pub fn ancestor(&self) -> usize {
+        self.ancestor
+    }
+
+    /// The next batch of headers to ask for, unless bodies are outstanding or the target
+    /// is reached.
+    pub fn next_headers(&self) -> Option<GetBlockHeaders> {
+        let (index, hash) = &self.tip;
+        if !self.headers.is_empty() || *index >= self.target {
+            return None;
+        }
+        Some(GetBlockHeaders {
+            start_block_index: *index,
+            skip: 1,
+            limit: (self.target - index).min(self.max_per_request),
+            start_block_hash: hash.clone(),
+        })
+    }
+
+    /// Takes the answer to `next_headers`. An empty one means the peer has no more blocks,
+    /// so the branch ends where we are. Returns the blocks to ask bodies for.
+    pub fn on_headers(&mut self, headers: BlockHeaders) -> Result<Vec<usize>, String> {
+        let Some(first) = headers.block_headers.first() else {
+            self.target = self.tip.0;
+            return Ok(Vec::new());
+        };
+        if first.index != self.tip.0 + 1 {
+            return Err(format!(
+                "headers start at block {} instead of {}",
+                first.index,
+                self.tip.0 + 1
+            ));
+        }
+        if headers.block_headers.len() > self.target - self.tip.0 {
+            return Err(format!(
+                "{} headers sent for {} asked",
+                headers.block_headers.len(),
+                self.target - self.tip.0
+            ));
+        }
+        headers.verify_chain(&self.tip.1)?;
+        if let Some(last) = headers.block_headers.last() {
+            self.tip = (last.index, last.hash.clone());
+        }
+        let indexes = headers.to_block_indexes();
+        self.headers.extend(headers.block_headers);
+        Ok(indexes)
+    }
+
+    /// Takes bodies for the outstanding headers, in order; each has to match its header.
+    pub fn on_bodies(&mut self, blocks: Vec<Block>) -> Result<(), String> {
+        for block in blocks {
+            let header = self
+                .headers
+                .pop_front()
+                .ok_or_else(|| format!("body of block {} was not asked for", block.index))?;
+            if header.index != block.index {
+                return Err(format!(
+                    "body of block {} sent for block {}",
+                    block.index, header.index
+                ));
+            }
+            block.verify_against_header(&header)?;
+            self.blocks.push(block);
+        }
+        Ok(())
+    }
+
+    /// Blocks whose headers we hold but whose bodies haven't arrived.
+    pub fn missing_bodies(&self) -> Vec<usize> {
+        self.headers.iter().map(|header| header.index).collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.headers.is_empty() && self.tip.0 >= self.target
+    }
+
+    /// The ancestor and the downloaded blocks on top of it.
+    pub fn into_branch(self) -> (usize, Vec<Block>) {
+        (self.ancestor, self.blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize, previous_hash: &str) -> Block {
+        let mut block = Block::new_block(index, previous_hash.to_string(), Vec::new());
+        block.hash = block.calculate_hash();
+        block
+    }
+
+    fn chain(ancestor: &Block, len: usize) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for index in ancestor.index + 1..=ancestor.index + len {
+            let parent = blocks
+                .last()
+                .map_or(ancestor.hash.as_str(), |parent| parent.hash.as_str());
+            blocks.push(block(index, parent));
+        }
+        blocks
+    }
+
+    fn headers(blocks: &[Block]) -> BlockHeaders {
+        BlockHeaders {
+            block_headers: blocks.iter().map(Block::to_block_header).collect(),
+        }
+    }
+
+    #[test]
+    fn downloads_the_branch_in_batches_up_to_the_target() {
+        let ancestor = block(4, "parent");
+        let branch = chain(&ancestor, 5);
+        let mut download = BranchDownload::new(4, ancestor.hash.clone(), 9, 3);
+
+        let request = download.next_headers().unwrap();
+        assert_eq!((request.start_block_index, request.limit), (4, 3));
+        assert_eq!(request.start_block_hash, ancestor.hash);
+        assert_eq!(
+            download.on_headers(headers(&branch[..3])),
+            Ok(vec![5, 6, 7])
+        );
+        assert!(download.next_headers().is_none(), "bodies come first");
+
+        download.on_bodies(branch[..2].to_vec()).unwrap();
+        assert_eq!(download.missing_bodies(), vec![7]);
+        download.on_bodies(branch[2..3].to_vec()).unwrap();
+
+        let request = download.next_headers().unwrap();
+        assert_eq!((request.start_block_index, request.limit), (7, 2));
+        assert_eq!(download.on_headers(headers(&branch[3..])), Ok(vec![8, 9]));
+        download.on_bodies(branch[3..].to_vec()).unwrap();
+        assert!(download.is_complete());
+        assert!(download.next_headers().is_none());
+
+        let (from, blocks) = download.into_branch();
+        assert_eq!(from, 4);
+        let hashes = |blocks: &[Block]| -> Vec<String> {
+            blocks.iter().map(|block| block.hash.clone()).collect()
+        };
+        assert_eq!(hashes(&blocks), hashes(&branch));
+    }
+
+    #[test]
+    fn a_branch_that_does_not_build_on_the_ancestor_or_its_headers_is_refused() {
+        let ancestor = block(4, "parent");
+        let branch = chain(&ancestor, 2);
+        let elsewhere = chain(&block(4, "other"), 2);
+
+        let mut download = BranchDownload::new(4, ancestor.hash.clone(), 6, 10);
+        assert!(download.on_headers(headers(&elsewhere)).is_err());
+        assert!(download.on_headers(headers(&branch[1..])).is_err());
+
+        assert!(download.on_headers(headers(&branch)).is_ok());
+        assert!(download.on_bodies(elsewhere[..1].to_vec()).is_err());
+    }
+
+    #[test]
+    fn a_peer_out_of_blocks_ends_the_branch_where_it_is() {
+        let ancestor = block(4, "parent");
+        let mut download = BranchDownload::new(4, ancestor.hash.clone(), 9, 10);
+        assert_eq!(
+            download.on_headers(BlockHeaders {
+                block_headers: Vec::new(),
+            }),
+            Ok(Vec::new())
+        );
+        assert!(download.is_complete());
+    }
+}
@@ -0,0 +1,199 @@
+use super::commands::DirectMessageType;
+use super::wire_codec::WireFormat;
+
+const SERVES_BODIES: &str = "serves-bodies";
+const SERVES_RECEIPTS: &str = "serves-receipts";
+const SERVES_SNAPSHOTS: &str = "serves-snapshots";
+const SERVES_SYNC_STREAM: &str = "serves-sync-stream";
+const LIGHT: &str = "light";
+const COMPRESSION_PREFIX: &str = "compression:";
+
+/// What a peer serves, advertised next to its wire formats in the handshake's
+/// `capabilities` list. A list naming none of these comes from a node that predates them,
+/// and such nodes all serve headers and bodies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCapabilities {
+    pub serves_bodies: bool,
+    pub serves_receipts: bool,
+    pub serves_snapshots: bool,
+    /// Answers block range requests on `/clutch/sync/1`.
+    pub serves_sync_stream: bool,
+    /// Serves no chain data at all, headers included.
+    pub light: bool,
+    /// Payload compression algorithms accepted, most preferred first.
+    pub compression: Vec<String>,
+}
+
+impl Default for PeerCapabilities {
+    fn default() -> Self {
+        Self::legacy()
+    }
+}
+
+impl PeerCapabilities {
+    /// What every node offered before capabilities were advertised.
+    pub fn legacy() -> Self {
+        PeerCapabilities {
+            serves_bodies: true,
+            serves_receipts: false,
+            serves_snapshots: false,
+            serves_sync_stream: false,
+            light: false,
+            compression: Vec::new(),
+        }
+    }
+
+    /// A node that answers no chain-data requests.
+    pub fn light() -> Self {
+        PeerCapabilities {
+            serves_bodies: false,
+            light: true,
+            ..Self::legacy()
+        }
+    }
+
+    pub fn from_advertised(capabilities: &[String]) -> Self {
+        let mut parsed = PeerCapabilities {
+            serves_bodies: false,
+            ..Self::legacy()
+        };
+        let mut any = false;
+        for name in capabilities {
+            let known = match name.as_str() {
+                SERVES_BODIES => {
+                    parsed.serves_bodies = true;
+                    true
+                }
+                SERVES_RECEIPTS => {
+                    parsed.serves_receipts = true;
+                    true
+                }
+                SERVES_SNAPSHOTS => {
+                    parsed.serves_snapshots = true;
+                    true
+                }
+                SERVES_SYNC_STREAM => {
+                    parsed.serves_sync_stream = true;
+                    true
+                }
+                LIGHT => {
+                    parsed.light = true;
+                    true
+                }
+                _ => match name.strip_prefix(COMPRESSION_PREFIX) {
+                    Some(algorithm) => {
+                        parsed.compression.push(algorithm.to_string());
+                        true
+                    }
+                    None => false,
+                },
+            };
+            any |= known;
+        }
+        if any {
+            parsed
+        } else {
+            Self::legacy()
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for (offered, name) in [
+            (self.serves_bodies, SERVES_BODIES),
+            (self.serves_receipts, SERVES_RECEIPTS),
+            (self.serves_snapshots, SERVES_SNAPSHOTS),
+            (self.serves_sync_stream, SERVES_SYNC_STREAM),
+            (self.light, LIGHT),
+        ] {
+            if offered {
+                names.push(name.to_string());
+            }
+        }
+        names.extend(
+            self.compression
+                .iter()
+                .map(|algorithm| format!("{}{}", COMPRESSION_PREFIX, algorithm)),
+        );
+        names
+    }
+
+    /// The handshake capability list for a node speaking `formats`. Stays empty for an RLP
+    /// only node with the legacy capabilities, so its handshake encodes as it always did.
+    pub fn advertise(&self, formats: &[WireFormat]) -> Vec<String> {
+        if *self == Self::legacy() {
+            return WireFormat::capabilities(formats);
+        }
+        formats
+            .iter()
+            .map(|format| format.name().to_string())
+            .chain(self.names())
+            .collect()
+    }
+
+    /// Whether the peer answers `request`. Requests that carry no chain data are always
+    /// answered.
+    pub fn serves(&self, request: DirectMessageType) -> bool {
+        match request {
+            DirectMessageType::GetBlockHeaders => !self.light,
+            DirectMessageType::GetBlockBodies | DirectMessageType::GetBlockTransactions => {
+                !self.light && self.serves_bodies
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_that_advertise_no_capabilities_serve_headers_and_bodies() {
+        for advertised in [vec![], vec!["cbor".to_string(), "rlp".to_string()]] {
+            let capabilities = PeerCapabilities::from_advertised(&advertised);
+            assert_eq!(capabilities, PeerCapabilities::legacy());
+            assert!(capabilities.serves(DirectMessageType::GetBlockBodies));
+        }
+        assert!(PeerCapabilities::legacy()
+            .advertise(&[WireFormat::Rlp])
+            .is_empty());
+    }
+
+    #[test]
+    fn advertised_capabilities_round_trip() {
+        let light = PeerCapabilities::light();
+        let advertised = light.advertise(&[WireFormat::Rlp]);
+        assert_eq!(advertised, ["rlp", "light"]);
+        let parsed = PeerCapabilities::from_advertised(&advertised);
+        assert_eq!(parsed, light);
+        assert!(!parsed.serves(DirectMessageType::GetBlockHeaders));
+        assert!(!parsed.serves(DirectMessageType::GetBlockBodies));
+        assert!(parsed.serves(DirectMessageType::GetPooledTransactions));
+
+        let full = PeerCapabilities {
+            serves_receipts: true,
+            serves_sync_stream: true,
+            compression: vec!["zstd".to_string()],
+            ..PeerCapabilities::legacy()
+        };
+        let advertised = full.advertise(&[WireFormat::Cbor, WireFormat::Rlp]);
+        assert_eq!(
+            advertised,
+            [
+                "cbor",
+                "rlp",
+                "serves-bodies",
+                "serves-receipts",
+                "serves-sync-stream",
+                "compression:zstd"
+            ]
+        );
+        assert_eq!(PeerCapabilities::from_advertised(&advertised), full);
+        // Wire format negotiation skips the capability names.
+        assert_eq!(
+            WireFormat::negotiate(&[WireFormat::Rlp, WireFormat::Cbor], &advertised),
+            WireFormat::Cbor
+        );
+    }
+}
@@ -1,12 +1,18 @@
 use libp2p::{
     gossipsub::{self, MessageId},
     request_response::OutboundRequestId,
-    PeerId,
+    Multiaddr, PeerId,
 };
 use std::collections::HashSet;
 use tokio::sync::oneshot;
 
 use super::behaviour::DirectMessageRequest;
+use super::config::P2PLimits;
+use super::disconnect::DisconnectReason;
+use super::fork_monitor::ForksSnapshot;
+use super::peer_table::PeersSnapshot;
+use super::response_router::Reply;
+use crate::node::transactions::transaction::Transaction;
 
 #[allow(dead_code)]
 pub enum P2PServerCommand {
@@ -22,15 +28,66 @@ pub enum P2PServerCommand {
         message: DirectMessageRequest,
         response_tx: oneshot::Sender<OutboundRequestId>,
     },
+    /// Sends a direct request and routes the peer's response, or the request's failure,
+    /// back through `response_tx` instead of the inline response handlers.
+    Request {
+        peer_id: PeerId,
+        message: DirectMessageRequest,
+        response_tx: oneshot::Sender<Reply>,
+    },
     GetLocalPeerId {
         response_tx: oneshot::Sender<PeerId>,
     },
+    GetPeers {
+        response_tx: oneshot::Sender<PeersSnapshot>,
+    },
+    /// Competing branches as of the last peer check.
+    GetForks {
+        response_tx: oneshot::Sender<ForksSnapshot>,
+    },
+    /// Best-scoring handshaked peer to sync from, or any connected peer if none has
+    /// handshaked yet.
+    GetSyncPeer {
+        response_tx: oneshot::Sender<Option<PeerId>>,
+    },
+    /// Spreads a transaction that entered our pool: gossiped whole, or queued for the next
+    /// hash announcement when announcements are enabled.
+    BroadcastTransaction {
+        transaction: Transaction,
+    },
+    /// Dials `address`; replies once the dial has started, or why it couldn't. The peer
+    /// handshakes like any other once connected.
+    DialPeer {
+        address: Multiaddr,
+        response_tx: oneshot::Sender<Result<(), String>>,
+    },
+    /// Sends `Disconnect { reason }` to one peer; replies whether it was connected.
+    DisconnectPeer {
+        peer_id: PeerId,
+        reason: DisconnectReason,
+        response_tx: oneshot::Sender<bool>,
+    },
+    /// Sends `Disconnect { reason }` to every connected peer; replies with how many.
+    DisconnectAll {
+        reason: DisconnectReason,
+        response_tx: oneshot::Sender<usize>,
+    },
+    /// Changes peer, request and bandwidth limits without restarting the swarm.
+    ApplyLimits {
+        limits: P2PLimits,
+    },
 }
 
 #[derive(Debug)]
 pub enum GossipMessageType {
     Transaction,
     Block,
+    /// Header plus transaction hashes; see `compact_block.rs`.
+    CompactBlock,
+    /// Batch of transaction hashes; see `tx_announce.rs`.
+    TransactionHashes,
+    /// A validator's BFT vote; see `bft.rs`.
+    Vote,
 }
 
 impl GossipMessageType {
@@ -38,6 +95,9 @@ impl GossipMessageType {
         match self {
             GossipMessageType::Transaction => 0x01,
             GossipMessageType::Block => 0x02,
+            GossipMessageType::CompactBlock => 0x03,
+            GossipMessageType::TransactionHashes => 0x04,
+            GossipMessageType::Vote => 0x05,
         }
     }
 
@@ -45,18 +105,28 @@ impl GossipMessageType {
         match byte {
             0x01 => Some(GossipMessageType::Transaction),
             0x02 => Some(GossipMessageType::Block),
+            0x03 => Some(GossipMessageType::CompactBlock),
+            0x04 => Some(GossipMessageType::TransactionHashes),
+            0x05 => Some(GossipMessageType::Vote),
             _ => None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DirectMessageType {
     Handshake,
     GetBlockHeaders,
     BlockHeaders,
     GetBlockBodies,
-    BlockBodies 
+    BlockBodies,
+    Disconnect,
+    Ping,
+    Pong,
+    GetBlockTransactions,
+    BlockTransactions,
+    GetPooledTransactions,
+    PooledTransactions,
 }
 
 impl DirectMessageType {
@@ -67,6 +137,13 @@ impl DirectMessageType {
             DirectMessageType::BlockHeaders => 0x03,
             DirectMessageType::GetBlockBodies => 0x04,
             DirectMessageType::BlockBodies => 0x05,
+            DirectMessageType::Disconnect => 0x06,
+            DirectMessageType::Ping => 0x07,
+            DirectMessageType::Pong => 0x08,
+            DirectMessageType::GetBlockTransactions => 0x09,
+            DirectMessageType::BlockTransactions => 0x0a,
+            DirectMessageType::GetPooledTransactions => 0x0b,
+            DirectMessageType::PooledTransactions => 0x0c,
         }
     }
 
@@ -77,7 +154,34 @@ impl DirectMessageType {
             0x03 => Some(DirectMessageType::BlockHeaders),
             0x04 => Some(DirectMessageType::GetBlockBodies),
             0x05 => Some(DirectMessageType::BlockBodies),
+            0x06 => Some(DirectMessageType::Disconnect),
+            0x07 => Some(DirectMessageType::Ping),
+            0x08 => Some(DirectMessageType::Pong),
+            0x09 => Some(DirectMessageType::GetBlockTransactions),
+            0x0a => Some(DirectMessageType::BlockTransactions),
+            0x0b => Some(DirectMessageType::GetPooledTransactions),
+            0x0c => Some(DirectMessageType::PooledTransactions),
             _ => None,
         }
     }
+
+    /// The message a peer answers this request with; `None` for messages only sent as
+    /// responses. Handshakes are answered with a handshake, and a `Disconnect` with a
+    /// bare `Disconnect` acknowledgement.
+    pub fn response_type(&self) -> Option<DirectMessageType> {
+        match self {
+            DirectMessageType::Handshake => Some(DirectMessageType::Handshake),
+            DirectMessageType::GetBlockHeaders => Some(DirectMessageType::BlockHeaders),
+            DirectMessageType::GetBlockBodies => Some(DirectMessageType::BlockBodies),
+            DirectMessageType::Disconnect => Some(DirectMessageType::Disconnect),
+            DirectMessageType::Ping => Some(DirectMessageType::Pong),
+            DirectMessageType::GetBlockTransactions => Some(DirectMessageType::BlockTransactions),
+            DirectMessageType::GetPooledTransactions => Some(DirectMessageType::PooledTransactions),
+            DirectMessageType::BlockHeaders
+            | DirectMessageType::BlockBodies
+            | DirectMessageType::Pong
+            | DirectMessageType::BlockTransactions
+            | DirectMessageType::PooledTransactions => None,
+        }
+    }
 }
@@ -0,0 +1,301 @@
+use libp2p::{gossipsub::MessageId, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::node::blocks::block::Block;
+use crate::node::rlp_encoding::RlpCodec;
+use crate::node::transactions::transaction::Transaction;
+
+/// Blocks waiting on missing transactions at once; further compact blocks that can't be
+/// rebuilt from the mempool are ignored and left to sync.
+const MAX_PENDING_BLOCKS: usize = 16;
+/// How long the propagation source has to send the missing transactions.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A block as gossiped in compact mode: the header plus the hashes of its transactions.
+/// Receivers take the transactions from their mempool and fetch only the ones they lack.
+#[derive(Debug, Clone, Serialize, Deserialize, RlpCodec)]
+pub struct CompactBlock {
+    pub index: usize,
+    pub timestamp: u64,
+    pub previous_hash: String,
+    pub author: String,
+    pub signature_r: String,
+    pub signature_s: String,
+    #[rlp(as = "u64")]
+    pub signature_v: i32,
+    pub hash: String,
+    #[rlp(list)]
+    pub tx_hashes: Vec<String>,
+    #[serde(default)]
+    #[rlp(default)]
+    pub transactions_root: String,
+    #[serde(default)]
+    #[rlp(default)]
+    pub receipts_root: String,
+    #[serde(default)]
+    #[rlp(list, default)]
+    pub next_authorities: Vec<String>,
+}
+
+impl CompactBlock {
+    pub fn from_block(block: &Block) -> Self {
+        Self {
+            index: block.index,
+            timestamp: block.timestamp,
+            previous_hash: block.previous_hash.clone(),
+            author: block.author.clone(),
+            signature_r: block.signature_r.clone(),
+            signature_s: block.signature_s.clone(),
+            signature_v: block.signature_v,
+            hash: block.hash.clone(),
+            tx_hashes: block
+                .transactions
+                .iter()
+                .map(|tx| tx.hash.clone())
+                .collect(),
+            transactions_root: block.transactions_root.clone(),
+            receipts_root: block.receipts_root.clone(),
+            next_authorities: block.next_authorities.clone(),
+        }
+    }
+
+    /// The full block, given its transactions in `tx_hashes` order.
+    pub fn into_block(self, transactions: Vec<Transaction>) -> Block {
+        Block {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            author: self.author,
+            signature_r: self.signature_r,
+            signature_s: self.signature_s,
+            signature_v: self.signature_v,
+            hash: self.hash,
+            transactions,
+            transactions_root: self.transactions_root,
+            receipts_root: self.receipts_root,
+            next_authorities: self.next_authorities,
+        }
+    }
+}
+
+/// Asks the peer that relayed a compact block for the transactions at `tx_indexes` in it.
+/// The peer answers from its chain, so only a peer that already imported the block can.
+#[derive(Debug, Clone, Serialize, Deserialize, RlpCodec)]
+pub struct GetBlockTransactions {
+    pub block_index: usize,
+    pub block_hash: String,
+    #[rlp(list)]
+    pub tx_indexes: Vec<usize>,
+}
+
+/// Answer to `GetBlockTransactions`, in the requested order; empty if the block is unknown.
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct BlockTransactions {
+    pub block_hash: String,
+    #[rlp(list)]
+    pub transactions: Vec<Transaction>,
+}
+
+/// A compact block whose gossip verdict is held until its missing transactions arrive.
+#[derive(Debug)]
+pub struct PendingBlock {
+    compact: CompactBlock,
+    transactions: Vec<Option<Transaction>>,
+    pub source: PeerId,
+    pub message_id: MessageId,
+    received_at: Instant,
+}
+
+impl PendingBlock {
+    pub fn new(
+        compact: CompactBlock,
+        transactions: Vec<Option<Transaction>>,
+        source: PeerId,
+        message_id: MessageId,
+        now: Instant,
+    ) -> Self {
+        Self {
+            compact,
+            transactions,
+            source,
+            message_id,
+            received_at: now,
+        }
+    }
+
+    /// The request for every transaction the mempool didn't have.
+    pub fn request(&self) -> GetBlockTransactions {
+        GetBlockTransactions {
+            block_index: self.compact.index,
+            block_hash: self.compact.hash.clone(),
+            tx_indexes: self
+                .transactions
+                .iter()
+                .enumerate()
+                .filter(|(_, tx)| tx.is_none())
+                .map(|(index, _)| index)
+                .collect(),
+        }
+    }
+
+    /// Fills the gaps with `fetched`, which must be exactly the requested transactions in
+    /// order, and rebuilds the block.
+    pub fn complete(self, fetched: Vec<Transaction>) -> Result<Block, String> {
+        let missing = self.transactions.iter().filter(|tx| tx.is_none()).count();
+        if fetched.len() != missing {
+            return Err(format!(
+                "expected {} transactions for block {}, got {}",
+                missing,
+                self.compact.index,
+                fetched.len()
+            ));
+        }
+        let mut fetched = fetched.into_iter();
+        let mut transactions = Vec::with_capacity(self.transactions.len());
+        for (slot, expected) in self.transactions.into_iter().zip(&self.compact.tx_hashes) {
+            let tx = match slot {
+                Some(tx) => tx,
+                None => fetched.next().expect("one fetched transaction per gap"),
+            };
+            if tx.hash != *expected {
+                return Err(format!(
+                    "transaction {} does not match {} in block {}",
+                    tx.hash, expected, self.compact.index
+                ));
+            }
+            transactions.push(tx);
+        }
+        Ok(self.compact.into_block(transactions))
+    }
+}
+
+/// Compact blocks waiting on transactions, by block hash.
+#[derive(Debug, Default)]
+pub struct PendingBlocks {
+    blocks: HashMap<String, PendingBlock>,
+}
+
+impl PendingBlocks {
+    pub fn contains(&self, block_hash: &str) -> bool {
+        self.blocks.contains_key(block_hash)
+    }
+
+    /// Holds `pending` and returns the request for its missing transactions, or `None`
+    /// when too many blocks are already waiting.
+    pub fn insert(&mut self, pending: PendingBlock) -> Option<GetBlockTransactions> {
+        if self.blocks.len() >= MAX_PENDING_BLOCKS {
+            return None;
+        }
+        let request = pending.request();
+        self.blocks.insert(pending.compact.hash.clone(), pending);
+        Some(request)
+    }
+
+    /// Removes the block `from` was asked for. Answers from other peers are ignored.
+    pub fn take(&mut self, block_hash: &str, from: &PeerId) -> Option<PendingBlock> {
+        if self.blocks.get(block_hash)?.source != *from {
+            return None;
+        }
+        self.blocks.remove(block_hash)
+    }
+
+    /// Drops blocks whose transactions didn't arrive in time, so their verdicts can be
+    /// reported.
+    pub fn expire(&mut self, now: Instant) -> Vec<PendingBlock> {
+        let expired: Vec<String> = self
+            .blocks
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.received_at) >= PENDING_TIMEOUT)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|hash| self.blocks.remove(hash))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(hash: &str) -> Transaction {
+        let mut tx = Transaction::new_genesis_transactions().remove(0);
+        tx.hash = hash.to_string();
+        tx
+    }
+
+    fn compact(tx_hashes: &[&str]) -> CompactBlock {
+        CompactBlock {
+            index: 7,
+            timestamp: 0,
+            previous_hash: "h6".to_string(),
+            author: String::new(),
+            signature_r: String::new(),
+            signature_s: String::new(),
+            signature_v: 0,
+            hash: "h7".to_string(),
+            tx_hashes: tx_hashes.iter().map(|hash| hash.to_string()).collect(),
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
+        }
+    }
+
+    fn pending(known: Vec<Option<Transaction>>, now: Instant) -> PendingBlock {
+        PendingBlock::new(
+            compact(&["a", "b", "c"]),
+            known,
+            PeerId::random(),
+            MessageId::from("id"),
+            now,
+        )
+    }
+
+    #[test]
+    fn missing_transactions_are_requested_and_filled_in_order() {
+        let pending = pending(vec![None, Some(transaction("b")), None], Instant::now());
+        let request = pending.request();
+        assert_eq!(request.tx_indexes, vec![0, 2]);
+        assert_eq!(request.block_hash, "h7");
+
+        let block = pending
+            .complete(vec![transaction("a"), transaction("c")])
+            .unwrap();
+        let hashes: Vec<&str> = block
+            .transactions
+            .iter()
+            .map(|tx| tx.hash.as_str())
+            .collect();
+        assert_eq!(hashes, ["a", "b", "c"]);
+        assert_eq!(block.hash, "h7");
+    }
+
+    #[test]
+    fn rejects_transactions_that_do_not_match_the_block() {
+        let now = Instant::now();
+        let known = || vec![None, Some(transaction("b")), None];
+        assert!(pending(known(), now)
+            .complete(vec![transaction("c"), transaction("a")])
+            .is_err());
+        assert!(pending(known(), now)
+            .complete(vec![transaction("a")])
+            .is_err());
+    }
+
+    #[test]
+    fn only_the_source_completes_and_stale_blocks_expire() {
+        let now = Instant::now();
+        let mut blocks = PendingBlocks::default();
+        let block = pending(vec![None, None, None], now);
+        let source = block.source;
+        assert!(blocks.insert(block).is_some());
+
+        assert!(blocks.take("h7", &PeerId::random()).is_none());
+        assert!(blocks.expire(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(blocks.expire(now + PENDING_TIMEOUT).len(), 1);
+        assert!(blocks.take("h7", &source).is_none());
+    }
+}
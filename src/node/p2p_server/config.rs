@@ -0,0 +1,254 @@
+use super::bandwidth::BandwidthLimits;
+use super::capability::PeerCapabilities;
+use super::wire_codec::WireFormat;
+use crate::node::configuration::AppConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::warn;
+
+const DEFAULT_CHAIN_ID: &str = "clutch-devnet";
+
+/// Networking settings for a `P2PServer`. Split out of `AppConfig` so tests (and other
+/// embedders) can build a server without a full node configuration.
+#[derive(Debug, Clone)]
+pub struct P2PConfig {
+    pub chain_id: String,
+    pub topic_name: String,
+    pub listen_addrs: Vec<String>,
+    /// `/ws` listen addresses; the WebSocket transport is only built when there are some.
+    pub websocket_listen_addrs: Vec<String>,
+    /// Addresses advertised as reachable in addition to the listen addresses.
+    pub external_addrs: Vec<String>,
+    /// Swarm key of a private network; `None` joins the public network.
+    pub swarm_key_path: Option<PathBuf>,
+    pub bootstrap_nodes: Vec<String>,
+    pub peer_scoring_enabled: bool,
+    pub seen_cache_ttl: Duration,
+    pub seen_cache_capacity: usize,
+    pub handshake_timeout: Duration,
+    pub max_peers: usize,
+    /// CIDR ranges enforced by the `ConnectionGate`; an empty allow list allows any address.
+    pub allowed_cidrs: Vec<String>,
+    pub denied_cidrs: Vec<String>,
+    /// How long a peer's IP is banned after a protocol violation; zero disables bans.
+    pub ban_duration: Duration,
+    /// Concurrent inbound connections from one IP and one subnet; zero is unlimited.
+    pub max_inbound_per_ip: usize,
+    pub max_inbound_per_subnet: usize,
+    pub ping_interval: Duration,
+    pub max_missed_pings: u32,
+    pub bandwidth: BandwidthLimits,
+    pub max_in_flight_requests_per_peer: usize,
+    pub max_queued_requests_per_peer: usize,
+    pub max_concurrent_inbound_requests: usize,
+    /// Blocks from a `BlockBodies` response committed per RocksDB write.
+    pub sync_write_batch_size: usize,
+    /// Body batches in flight before header downloads pause.
+    pub sync_pipeline_depth: usize,
+    /// Early gossiped blocks held for their parent or slot at once.
+    pub max_future_blocks: usize,
+    /// How long a sync may go without progress before the watchdog restarts it.
+    pub sync_stall_timeout: Duration,
+    /// Formats advertised in our handshake, most preferred first. Always includes RLP.
+    pub wire_formats: Vec<WireFormat>,
+    /// What we serve, advertised in our handshake next to `wire_formats`.
+    pub capabilities: PeerCapabilities,
+    /// Where known peers are saved; `None` keeps them in memory only.
+    pub known_peers_path: Option<PathBuf>,
+    /// Remembered peers dialed at startup.
+    pub reconnect_known_peers: usize,
+    /// How often transactions new to our pool are announced by hash; zero gossips each
+    /// transaction whole instead.
+    pub tx_announce_interval: Duration,
+    /// Most hashes in one announcement (and in one request for the transactions).
+    pub tx_announce_batch_size: usize,
+    /// Whether a handshake showing a peer ahead of us starts a header/body download.
+    pub sync_enabled: bool,
+    /// Whether bodies are fetched as `/clutch/sync/1` ranges from peers serving them.
+    /// Serving ranges ourselves is `capabilities.serves_sync_stream`.
+    pub sync_stream_enabled: bool,
+    /// A disconnect leaving fewer peers than this is posted to the webhook; 0 never is.
+    pub min_peers: usize,
+    /// How long peers may be on competing branches before the split is warned about;
+    /// zero never warns.
+    pub fork_warn_after: Duration,
+}
+
+/// The part of `P2PConfig` a running server can change; see
+/// `P2PServerCommand::ApplyLimits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P2PLimits {
+    pub max_peers: usize,
+    pub max_inbound_per_ip: usize,
+    pub max_inbound_per_subnet: usize,
+    pub bandwidth: BandwidthLimits,
+    pub max_in_flight_requests_per_peer: usize,
+    pub max_queued_requests_per_peer: usize,
+}
+
+impl P2PConfig {
+    pub fn new(topic_name: &str, listen_addrs: &[&str], bootstrap_nodes: &[&str]) -> Self {
+        Self {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            topic_name: topic_name.to_string(),
+            listen_addrs: listen_addrs.iter().map(|s| s.to_string()).collect(),
+            websocket_listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            swarm_key_path: None,
+            bootstrap_nodes: bootstrap_nodes.iter().map(|s| s.to_string()).collect(),
+            peer_scoring_enabled: true,
+            seen_cache_ttl: Duration::from_secs(120),
+            seen_cache_capacity: 4096,
+            handshake_timeout: Duration::from_secs(30),
+            max_peers: 50,
+            allowed_cidrs: Vec::new(),
+            denied_cidrs: Vec::new(),
+            ban_duration: Duration::from_secs(600),
+            max_inbound_per_ip: 4,
+            max_inbound_per_subnet: 16,
+            ping_interval: Duration::from_secs(15),
+            max_missed_pings: 3,
+            bandwidth: BandwidthLimits::default(),
+            max_in_flight_requests_per_peer: 4,
+            max_queued_requests_per_peer: 16,
+            max_concurrent_inbound_requests: 8,
+            sync_write_batch_size: 32,
+            sync_pipeline_depth: 2,
+            max_future_blocks: 32,
+            sync_stall_timeout: Duration::from_secs(30),
+            wire_formats: vec![WireFormat::Rlp],
+            capabilities: PeerCapabilities::legacy(),
+            known_peers_path: None,
+            reconnect_known_peers: 8,
+            tx_announce_interval: Duration::from_millis(500),
+            tx_announce_batch_size: 256,
+            sync_enabled: true,
+            sync_stream_enabled: true,
+            min_peers: 1,
+            fork_warn_after: Duration::from_secs(300),
+        }
+    }
+
+    pub fn from_app_config(config: &AppConfig) -> Self {
+        Self {
+            chain_id: config.chain_id.clone(),
+            topic_name: config.libp2p_topic_name.clone(),
+            listen_addrs: config.listen_addrs.clone(),
+            websocket_listen_addrs: config.p2p_websocket_listen_addrs.clone(),
+            external_addrs: config.external_addrs.clone(),
+            swarm_key_path: Some(&config.swarm_key_file)
+                .filter(|file| !file.is_empty())
+                .map(PathBuf::from),
+            bootstrap_nodes: config.bootstrap_nodes.clone(),
+            peer_scoring_enabled: config.gossipsub_peer_scoring_enabled,
+            seen_cache_ttl: Duration::from_secs(config.seen_cache_ttl_secs),
+            seen_cache_capacity: config.seen_cache_capacity,
+            handshake_timeout: Duration::from_secs(config.handshake_timeout_secs),
+            max_peers: config.max_peers,
+            allowed_cidrs: config.allowed_cidrs.clone(),
+            denied_cidrs: config.denied_cidrs.clone(),
+            ban_duration: Duration::from_secs(config.ban_duration_secs),
+            max_inbound_per_ip: config.max_inbound_per_ip,
+            max_inbound_per_subnet: config.max_inbound_per_subnet,
+            ping_interval: Duration::from_secs(config.ping_interval_secs),
+            max_missed_pings: config.max_missed_pings,
+            bandwidth: BandwidthLimits {
+                upload: kib_per_sec(config.max_upload_kib_per_sec),
+                download: kib_per_sec(config.max_download_kib_per_sec),
+                peer_upload: kib_per_sec(config.max_peer_upload_kib_per_sec),
+                peer_download: kib_per_sec(config.max_peer_download_kib_per_sec),
+            },
+            max_in_flight_requests_per_peer: config.max_in_flight_requests_per_peer,
+            max_queued_requests_per_peer: config.max_queued_requests_per_peer,
+            max_concurrent_inbound_requests: config.max_concurrent_inbound_requests,
+            sync_write_batch_size: config.sync_write_batch_size,
+            sync_pipeline_depth: config.sync_pipeline_depth,
+            max_future_blocks: config.max_future_blocks,
+            sync_stall_timeout: Duration::from_secs(config.sync_stall_timeout_secs),
+            wire_formats: wire_formats(&config.wire_codecs),
+            capabilities: if config.serve_chain_data {
+                PeerCapabilities {
+                    serves_sync_stream: config.sync_stream_enabled,
+                    ..PeerCapabilities::legacy()
+                }
+            } else {
+                PeerCapabilities::light()
+            },
+            known_peers_path: Some(&config.known_peers_file)
+                .filter(|file| !file.is_empty())
+                .map(PathBuf::from),
+            reconnect_known_peers: config.reconnect_known_peers,
+            tx_announce_interval: Duration::from_millis(config.tx_announce_interval_ms),
+            tx_announce_batch_size: config.tx_announce_batch_size,
+            sync_enabled: config.sync_enabled,
+            sync_stream_enabled: config.sync_stream_enabled,
+            min_peers: config.webhook_min_peers,
+            fork_warn_after: Duration::from_secs(config.fork_warn_after_secs),
+        }
+    }
+
+    pub fn limits(&self) -> P2PLimits {
+        P2PLimits {
+            max_peers: self.max_peers,
+            max_inbound_per_ip: self.max_inbound_per_ip,
+            max_inbound_per_subnet: self.max_inbound_per_subnet,
+            bandwidth: self.bandwidth,
+            max_in_flight_requests_per_peer: self.max_in_flight_requests_per_peer,
+            max_queued_requests_per_peer: self.max_queued_requests_per_peer,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: P2PLimits) {
+        self.max_peers = limits.max_peers;
+        self.max_inbound_per_ip = limits.max_inbound_per_ip;
+        self.max_inbound_per_subnet = limits.max_inbound_per_subnet;
+        self.bandwidth = limits.bandwidth;
+        self.max_in_flight_requests_per_peer = limits.max_in_flight_requests_per_peer;
+        self.max_queued_requests_per_peer = limits.max_queued_requests_per_peer;
+    }
+
+    /// Gossip topic scoped by chain id: `/clutch/{chain_id}/{topic_name}`. Two networks
+    /// that share a topic name still end up on different topic hashes.
+    pub fn scoped_topic_name(&self) -> String {
+        format!("/clutch/{}/{}", self.chain_id, self.topic_name)
+    }
+}
+
+/// Config uses KiB/s with 0 for "unlimited"; the limiter wants bytes/s or `None`.
+fn kib_per_sec(value: u64) -> Option<u64> {
+    (value > 0).then(|| value * 1024)
+}
+
+/// Parses the configured codec names, skipping unknown ones. RLP is appended if missing
+/// since peers that advertise nothing can only be reached with it.
+fn wire_formats(names: &[String]) -> Vec<WireFormat> {
+    let mut formats = Vec::new();
+    for name in names {
+        match WireFormat::from_name(name) {
+            Some(format) if !formats.contains(&format) => formats.push(format),
+            Some(_) => {}
+            None => warn!("Ignoring unknown wire codec {:?}", name),
+        }
+    }
+    if !formats.contains(&WireFormat::Rlp) {
+        formats.push(WireFormat::Rlp);
+    }
+    formats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_name_is_scoped_by_chain_id() {
+        let mut config = P2PConfig::new("test-net", &[], &[]);
+        assert_eq!(config.scoped_topic_name(), "/clutch/clutch-devnet/test-net");
+
+        config.chain_id = "clutch-mainnet".to_string();
+        assert_eq!(
+            config.scoped_topic_name(),
+            "/clutch/clutch-mainnet/test-net"
+        );
+    }
+}
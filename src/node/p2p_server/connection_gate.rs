@@ -0,0 +1,356 @@
+use ipnet::IpNet;
+use libp2p::core::{multiaddr::Protocol, transport::PortUse, Endpoint};
+use libp2p::swarm::{
+    dummy, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, ListenFailure,
+    NetworkBehaviour, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::net::IpAddr;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Prefix lengths grouping inbound connections into one subnet for
+/// `max_inbound_per_subnet`: what a single host or small provider typically controls.
+const IPV4_SUBNET_PREFIX: u8 = 24;
+const IPV6_SUBNET_PREFIX: u8 = 64;
+
+/// Why the gate refused a connection, reported in the swarm's dial and listen errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GateDenied {
+    NotAllowed(IpAddr),
+    Denied(IpAddr, IpNet),
+    Banned(IpAddr),
+    TooManyFromIp(IpAddr),
+    TooManyFromSubnet(IpNet),
+}
+
+impl fmt::Display for GateDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GateDenied::NotAllowed(ip) => write!(f, "{} is not in an allowed range", ip),
+            GateDenied::Denied(ip, net) => write!(f, "{} is in denied range {}", ip, net),
+            GateDenied::Banned(ip) => write!(f, "{} is banned", ip),
+            GateDenied::TooManyFromIp(ip) => {
+                write!(f, "too many inbound connections from {}", ip)
+            }
+            GateDenied::TooManyFromSubnet(net) => {
+                write!(f, "too many inbound connections from {}", net)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GateDenied {}
+
+/// Refuses connections by IP before any handshake runs: inbound ones as they are accepted,
+/// outbound ones before dialing. An address must fall in `allow` (when it isn't empty),
+/// outside `deny`, and not be banned. Addresses without an IP (`/dns4/...`) are checked
+/// once the connection is established, against the address the swarm reports.
+///
+/// Inbound connections are also capped per IP and per subnet, so one host can't take every
+/// peer slot by opening connections under fresh peer ids.
+#[derive(Debug, Default)]
+pub struct ConnectionGate {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    /// Banned IPs and when their ban ends.
+    banned: HashMap<IpAddr, Instant>,
+    /// Zero means unlimited.
+    max_inbound_per_ip: usize,
+    max_inbound_per_subnet: usize,
+    /// Remote IP of every pending or established inbound connection.
+    inbound: HashMap<ConnectionId, IpAddr>,
+}
+
+impl ConnectionGate {
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>) -> Self {
+        Self {
+            allow,
+            deny,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_inbound_limits(mut self, per_ip: usize, per_subnet: usize) -> Self {
+        self.set_inbound_limits(per_ip, per_subnet);
+        self
+    }
+
+    /// Changes the caps for connections accepted from now on; existing ones are kept.
+    pub fn set_inbound_limits(&mut self, per_ip: usize, per_subnet: usize) {
+        self.max_inbound_per_ip = per_ip;
+        self.max_inbound_per_subnet = per_subnet;
+    }
+
+    /// Refuses new connections from and to `ip` until `until`.
+    pub fn ban(&mut self, ip: IpAddr, until: Instant) {
+        let entry = self.banned.entry(ip).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr, now: Instant) -> bool {
+        self.banned.get(ip).is_some_and(|until| now < *until)
+    }
+
+    pub fn check_ip(&self, ip: IpAddr, now: Instant) -> Result<(), GateDenied> {
+        if let Some(net) = self.deny.iter().find(|net| net.contains(&ip)) {
+            return Err(GateDenied::Denied(ip, *net));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|net| net.contains(&ip)) {
+            return Err(GateDenied::NotAllowed(ip));
+        }
+        if self.is_banned(&ip, now) {
+            return Err(GateDenied::Banned(ip));
+        }
+        Ok(())
+    }
+
+    /// Checks the IP of `addr`; an address without one passes.
+    pub fn check(&mut self, addr: &Multiaddr, now: Instant) -> Result<(), GateDenied> {
+        self.banned.retain(|_, until| now < *until);
+        match ip_of(addr) {
+            Some(ip) => self.check_ip(ip, now),
+            None => Ok(()),
+        }
+    }
+
+    /// Admits an inbound connection from `remote_addr`, counting it against its IP and
+    /// subnet until `on_inbound_closed`.
+    fn on_inbound(
+        &mut self,
+        connection_id: ConnectionId,
+        remote_addr: &Multiaddr,
+        now: Instant,
+    ) -> Result<(), GateDenied> {
+        self.check(remote_addr, now)?;
+        let Some(ip) = ip_of(remote_addr) else {
+            return Ok(());
+        };
+        let subnet = subnet_of(ip);
+        let from_ip = self.inbound.values().filter(|other| **other == ip).count();
+        let from_subnet = self
+            .inbound
+            .values()
+            .filter(|other| subnet.contains(*other))
+            .count();
+        if self.max_inbound_per_ip > 0 && from_ip >= self.max_inbound_per_ip {
+            return Err(GateDenied::TooManyFromIp(ip));
+        }
+        if self.max_inbound_per_subnet > 0 && from_subnet >= self.max_inbound_per_subnet {
+            return Err(GateDenied::TooManyFromSubnet(subnet));
+        }
+        self.inbound.insert(connection_id, ip);
+        Ok(())
+    }
+
+    fn on_inbound_closed(&mut self, connection_id: ConnectionId) {
+        self.inbound.remove(&connection_id);
+    }
+}
+
+fn subnet_of(ip: IpAddr) -> IpNet {
+    let prefix = match ip {
+        IpAddr::V4(_) => IPV4_SUBNET_PREFIX,
+        IpAddr::V6(_) => IPV6_SUBNET_PREFIX,
+    };
+    IpNet::new(ip, prefix)
+        .expect("prefix is within the address length")
+        .trunc()
+}
+
+/// The IP an address starts with, if any.
+pub fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    }
+}
+
+/// Parses CIDR ranges from the config; a bare IP is taken as a single address.
+pub fn parse_cidrs(ranges: &[String], kind: &str) -> Result<Vec<IpNet>, String> {
+    ranges
+        .iter()
+        .map(|range| {
+            range
+                .parse::<IpNet>()
+                .or_else(|_| range.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("Invalid {} CIDR range {:?}", kind, range))
+        })
+        .collect()
+}
+
+impl NetworkBehaviour for ConnectionGate {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.on_inbound(connection_id, remote_addr, Instant::now())
+            .map_err(ConnectionDenied::new)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    /// Refuses the dial only when every candidate address is refused, so one bad address
+    /// doesn't stop us reaching a peer on the others.
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: Option<PeerId>,
+        addresses: &[Multiaddr],
+        _: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        let now = Instant::now();
+        let mut denied = None;
+        for addr in addresses {
+            match self.check(addr, now) {
+                Ok(()) => return Ok(Vec::new()),
+                Err(e) => denied = Some(e),
+            }
+        }
+        match denied {
+            Some(e) => Err(ConnectionDenied::new(e)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(addr, Instant::now())
+            .map_err(ConnectionDenied::new)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. })
+            | FromSwarm::ListenFailure(ListenFailure { connection_id, .. }) => {
+                self.on_inbound_closed(connection_id)
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn gate(allow: &[&str], deny: &[&str]) -> ConnectionGate {
+        let parse = |ranges: &[&str]| {
+            let ranges: Vec<String> = ranges.iter().map(|r| r.to_string()).collect();
+            parse_cidrs(&ranges, "test").unwrap()
+        };
+        ConnectionGate::new(parse(allow), parse(deny))
+    }
+
+    fn addr(addr: &str) -> Multiaddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let mut gate = gate(&["10.0.0.0/8", "::1"], &["10.6.0.0/16"]);
+        let now = Instant::now();
+        assert!(gate.check(&addr("/ip4/10.1.2.3/tcp/4001"), now).is_ok());
+        assert!(gate.check(&addr("/ip6/::1/tcp/4001"), now).is_ok());
+        assert!(matches!(
+            gate.check(&addr("/ip4/10.6.0.9/tcp/4001"), now),
+            Err(GateDenied::Denied(..))
+        ));
+        assert!(matches!(
+            gate.check(&addr("/ip4/8.8.8.8/tcp/4001"), now),
+            Err(GateDenied::NotAllowed(..))
+        ));
+        assert!(gate.check(&addr("/dns4/node1/tcp/4001"), now).is_ok());
+    }
+
+    #[test]
+    fn bans_expire() {
+        let mut gate = gate(&[], &[]);
+        let now = Instant::now();
+        let peer = addr("/ip4/203.0.113.7/udp/4001/quic-v1");
+        gate.ban(ip_of(&peer).unwrap(), now + Duration::from_secs(60));
+
+        assert_eq!(
+            gate.check(&peer, now),
+            Err(GateDenied::Banned(ip_of(&peer).unwrap()))
+        );
+        assert!(gate.check(&peer, now + Duration::from_secs(60)).is_ok());
+        assert!(gate.banned.is_empty());
+    }
+
+    #[test]
+    fn inbound_connections_are_capped_per_ip_and_subnet() {
+        let mut gate = gate(&[], &[]).with_inbound_limits(2, 3);
+        let now = Instant::now();
+        let mut next_id = 0;
+        let mut connect = |gate: &mut ConnectionGate, ip: &str| {
+            next_id += 1;
+            let id = ConnectionId::new_unchecked(next_id);
+            gate.on_inbound(id, &addr(&format!("/ip4/{}/tcp/4001", ip)), now)
+                .map(|()| id)
+        };
+
+        let first = connect(&mut gate, "198.51.100.1").unwrap();
+        connect(&mut gate, "198.51.100.1").unwrap();
+        assert!(matches!(
+            connect(&mut gate, "198.51.100.1"),
+            Err(GateDenied::TooManyFromIp(_))
+        ));
+
+        connect(&mut gate, "198.51.100.2").unwrap();
+        assert_eq!(
+            connect(&mut gate, "198.51.100.3"),
+            Err(GateDenied::TooManyFromSubnet(
+                "198.51.100.0/24".parse().unwrap()
+            ))
+        );
+        connect(&mut gate, "203.0.113.1").unwrap();
+
+        gate.on_inbound_closed(first);
+        connect(&mut gate, "198.51.100.1").unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        let ranges = vec!["10.0.0.0/33".to_string()];
+        assert!(parse_cidrs(&ranges, "allowed").is_err());
+    }
+}
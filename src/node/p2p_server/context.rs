@@ -0,0 +1,209 @@
+use crate::node::blockchain::ChainReader;
+use crate::node::hardfork::HardforkSchedule;
+use crate::node::plugin::PluginRegistry;
+use crate::node::systemd::Systemd;
+use crate::node::time_utils::get_current_timespan;
+use crate::node::webhook::WebhookNotifier;
+use libp2p::{
+    identity::Keypair,
+    request_response::{Event as RequestResponseEvent, ResponseChannel},
+    Multiaddr, PeerId,
+};
+use std::collections::VecDeque;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{
+    behaviour::{DirectMessageRequest, DirectMessageResponse},
+    branch_download::BranchSwitch,
+    commands::DirectMessageType,
+    compact_block::PendingBlocks,
+    fork_monitor::ForkMonitor,
+    future_blocks::FutureBlocks,
+    handshake::HandshakeSnapshot,
+    in_flight::InFlightRequests,
+    inbound_pool::InboundPool,
+    known_peers::KnownPeers,
+    message_handler::HandlerRegistry,
+    node_record::{NodeRecord, MAX_RECORD_ADDRESSES},
+    peer_table::PeerTable,
+    request_queue::OutboundQueue,
+    request_response_handler::builtin_handlers,
+    response_router::ResponseRouter,
+    seen_cache::SeenCache,
+    sync_pipeline::SyncPipeline,
+    sync_stream::SyncBlocks,
+    tx_announce::TxAnnouncer,
+    P2PConfig,
+};
+
+/// Per-swarm state handed to the gossip and request/response handlers. Owned by the
+/// `P2PServer` task, so handlers get `&mut` access without any locking.
+#[derive(Debug)]
+pub struct P2PContext {
+    pub config: P2PConfig,
+    pub seen: SeenCache,
+    pub peers: PeerTable,
+    pub requests: OutboundQueue,
+    /// Every outstanding direct request, which responses are checked against.
+    pub in_flight: InFlightRequests,
+    /// Handlers direct requests and responses are dispatched to by message type.
+    pub handlers: HandlerRegistry,
+    /// Callers awaiting the response to a request they sent through the command channel.
+    pub responses: ResponseRouter,
+    pub inbound: InboundPool,
+    /// Serves `/clutch/sync/1` ranges, apart from `inbound` so a syncing peer's large
+    /// ranges don't take the workers header and body requests need.
+    pub sync_streams: InboundPool<ResponseChannel<SyncBlocks>, SyncBlocks>,
+    pub sync: SyncPipeline,
+    pub known_peers: KnownPeers,
+    pub tx_announcer: TxAnnouncer,
+    /// Gossiped compact blocks waiting on transactions fetched from their relayer.
+    pub compact_blocks: PendingBlocks,
+    /// Gossiped blocks that arrived before their parent or their slot.
+    pub future_blocks: FutureBlocks,
+    /// Branches peers are on that compete with ours.
+    pub forks: ForkMonitor,
+    /// Where a switch to a downloaded branch, run off the swarm task, reports back.
+    pub branch_switches: mpsc::Sender<BranchSwitch>,
+    /// `Bulk` request/response events, handled one at a time once nothing more urgent is
+    /// ready.
+    pub bulk_backlog: VecDeque<RequestResponseEvent<DirectMessageRequest, DirectMessageResponse>>,
+    /// Set once the server starts running; header/body requests read through it instead
+    /// of the `Blockchain` lock.
+    pub chain_reader: Option<ChainReader>,
+    /// Shared with the `Blockchain` when the server starts running; handshakes are
+    /// answered from it instead of the `Blockchain` lock.
+    pub handshake: HandshakeSnapshot,
+    /// Copied from the `Blockchain` when the server starts running.
+    pub hardforks: HardforkSchedule,
+    /// Copied from the `Blockchain` when the server starts running.
+    pub plugins: PluginRegistry,
+    /// Copied from the `Blockchain` when the server starts running.
+    pub notifier: WebhookNotifier,
+    /// Told when the swarm first listens, and beaten by the event loop for the watchdog.
+    pub systemd: Systemd,
+    /// The swarm's identity, which our `NodeRecord` is signed with.
+    pub identity: Keypair,
+    /// Our signed `NodeRecord`, sent in every handshake; empty until the first refresh.
+    pub node_record: Vec<u8>,
+}
+
+impl P2PContext {
+    pub fn new(
+        config: P2PConfig,
+        identity: Keypair,
+        inbound: InboundPool,
+        sync_streams: InboundPool<ResponseChannel<SyncBlocks>, SyncBlocks>,
+        branch_switches: mpsc::Sender<BranchSwitch>,
+    ) -> Self {
+        let seen = SeenCache::new(config.seen_cache_ttl, config.seen_cache_capacity);
+        let requests = OutboundQueue::new(
+            config.max_in_flight_requests_per_peer,
+            config.max_queued_requests_per_peer,
+        );
+        let sync = SyncPipeline::new(config.sync_pipeline_depth)
+            .with_stall_timeout(config.sync_stall_timeout);
+        let known_peers = KnownPeers::load(config.known_peers_path.clone());
+        let tx_announcer = TxAnnouncer::new(config.tx_announce_batch_size);
+        let forks = ForkMonitor::new(config.fork_warn_after);
+        let future_blocks = FutureBlocks::new(config.max_future_blocks);
+        Self {
+            config,
+            seen,
+            peers: PeerTable::new(),
+            requests,
+            in_flight: InFlightRequests::default(),
+            handlers: builtin_handlers(),
+            responses: ResponseRouter::default(),
+            inbound,
+            sync_streams,
+            sync,
+            known_peers,
+            tx_announcer,
+            compact_blocks: PendingBlocks::default(),
+            future_blocks,
+            forks,
+            branch_switches,
+            bulk_backlog: VecDeque::new(),
+            chain_reader: None,
+            handshake: HandshakeSnapshot::default(),
+            hardforks: HardforkSchedule::default(),
+            plugins: PluginRegistry::default(),
+            notifier: WebhookNotifier::default(),
+            systemd: Systemd::default(),
+            identity,
+            node_record: Vec::new(),
+        }
+    }
+
+    /// Signs a new `NodeRecord` listing `addresses`, replacing the one handshakes carry.
+    pub fn refresh_node_record<'a>(&mut self, addresses: impl Iterator<Item = &'a Multiaddr>) {
+        let mut listed: Vec<String> = Vec::new();
+        for address in addresses.map(|address| address.to_string()) {
+            if !listed.contains(&address) && listed.len() < MAX_RECORD_ADDRESSES {
+                listed.push(address);
+            }
+        }
+        let record = NodeRecord {
+            seq: get_current_timespan(),
+            chain_id: self.config.chain_id.clone(),
+            addresses: listed,
+            capabilities: self
+                .config
+                .capabilities
+                .advertise(&self.config.wire_formats),
+        };
+        match record.sign(&self.identity) {
+            Ok(signed) => self.node_record = signed,
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    /// Records a peer we dialed in the known-peers file. Saves when the address is new or
+    /// `save` is set, so periodic re-handshakes don't rewrite the file every time.
+    pub fn remember_peer(&mut self, peer_id: &PeerId, save: bool) {
+        let Some(entry) = self.peers.get(peer_id) else {
+            return;
+        };
+        // An inbound peer is remembered at the first address its signed record lists.
+        let recorded = entry
+            .node_record
+            .as_ref()
+            .and_then(|record| record.multiaddrs().ok())
+            .and_then(|addresses| addresses.into_iter().next());
+        let Some(address) = entry.dialed_address.as_ref().or(recorded.as_ref()) else {
+            return;
+        };
+        let added =
+            self.known_peers
+                .record(peer_id, address, entry.sync_score(), get_current_timespan());
+        if added || save {
+            if let Err(e) = self.known_peers.save() {
+                warn!("Failed to save known peers: {}", e);
+            }
+        }
+    }
+
+    /// Best sync peer at `min_height` that answers `request` and still has a free request
+    /// slot.
+    pub fn select_sync_peer(
+        &self,
+        min_height: usize,
+        request: DirectMessageType,
+    ) -> Option<PeerId> {
+        self.peers.select_sync_peer(min_height, request, |peer_id| {
+            self.requests.has_capacity(peer_id)
+        })
+    }
+
+    /// Records that `peer_id` relayed block `index`, letting a running sync continue to it
+    /// when it moves the peer's head forward.
+    pub fn on_peer_block(&mut self, peer_id: &PeerId, index: usize, hash: &str) {
+        let now = Instant::now();
+        if self.peers.on_new_block(peer_id, index, hash, now) && self.sync.is_running(now) {
+            self.sync.extend_target(index);
+        }
+    }
+}
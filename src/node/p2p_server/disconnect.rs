@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::node::rlp_encoding::RlpCodec;
+
+/// Why a node is closing the connection. Sent in a `Disconnect` direct message so the
+/// other side can log something more useful than "connection closed".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    IncompatibleGenesis,
+    ProtocolViolation,
+    TooManyPeers,
+    ShuttingDown,
+    HandshakeTimeout,
+    PingTimeout,
+    /// The peer's hardfork schedule disagrees with ours on a fork that has activated.
+    IncompatibleHardforks,
+    /// The node's operator asked to drop the peer, e.g. through `admin_removePeer`.
+    Requested,
+    /// A code this version doesn't know about, kept so it can still be logged.
+    Unknown(u8),
+}
+
+impl DisconnectReason {
+    pub fn as_code(&self) -> u8 {
+        match self {
+            DisconnectReason::IncompatibleGenesis => 0x01,
+            DisconnectReason::ProtocolViolation => 0x02,
+            DisconnectReason::TooManyPeers => 0x03,
+            DisconnectReason::ShuttingDown => 0x04,
+            DisconnectReason::HandshakeTimeout => 0x05,
+            DisconnectReason::PingTimeout => 0x06,
+            DisconnectReason::IncompatibleHardforks => 0x07,
+            DisconnectReason::Requested => 0x08,
+            DisconnectReason::Unknown(code) => *code,
+        }
+    }
+
+    /// Whether the peer is at fault in a way that gets its IP banned.
+    pub fn is_misbehaviour(&self) -> bool {
+        matches!(self, DisconnectReason::ProtocolViolation)
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x01 => DisconnectReason::IncompatibleGenesis,
+            0x02 => DisconnectReason::ProtocolViolation,
+            0x03 => DisconnectReason::TooManyPeers,
+            0x04 => DisconnectReason::ShuttingDown,
+            0x05 => DisconnectReason::HandshakeTimeout,
+            0x06 => DisconnectReason::PingTimeout,
+            0x07 => DisconnectReason::IncompatibleHardforks,
+            0x08 => DisconnectReason::Requested,
+            other => DisconnectReason::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::IncompatibleGenesis => write!(f, "incompatible genesis"),
+            DisconnectReason::ProtocolViolation => write!(f, "protocol violation"),
+            DisconnectReason::TooManyPeers => write!(f, "too many peers"),
+            DisconnectReason::ShuttingDown => write!(f, "shutting down"),
+            DisconnectReason::HandshakeTimeout => write!(f, "handshake timeout"),
+            DisconnectReason::PingTimeout => write!(f, "ping timeout"),
+            DisconnectReason::IncompatibleHardforks => write!(f, "incompatible hardforks"),
+            DisconnectReason::Requested => write!(f, "requested by the operator"),
+            DisconnectReason::Unknown(code) => write!(f, "unknown reason 0x{:02x}", code),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct Disconnect {
+    pub reason: DisconnectReason,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reason_codes_round_trip() {
+        for code in 0x00..=0xff {
+            assert_eq!(DisconnectReason::from_code(code).as_code(), code);
+        }
+        assert_eq!(
+            DisconnectReason::from_code(0x03),
+            DisconnectReason::TooManyPeers
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use libp2p::PeerId;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::peer_table::PeerHead;
+use crate::node::metric::{FORK_BRANCHES, FORK_MAX_DEPTH};
+use crate::node::webhook::NodeEvent;
+
+/// A chain tip that handshaked peers are on and our chain doesn't contain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Branch {
+    pub tip_index: usize,
+    pub tip_hash: String,
+    pub peers: Vec<String>,
+    /// The last block the branch shares with our chain, once an ancestor search found it.
+    pub common_ancestor: Option<usize>,
+    /// Blocks of ours past the common ancestor; while that is unknown, the fewest there can
+    /// be.
+    pub depth: usize,
+}
+
+/// Serializable view of the competing branches, returned by `P2PServerCommand::GetForks`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ForksSnapshot {
+    pub head_index: usize,
+    pub head_hash: String,
+    /// Deepest first.
+    pub branches: Vec<Branch>,
+    /// How long some peer has been on a competing branch without a break.
+    pub split_secs: Option<u64>,
+}
+
+/// Watches the heads peers advertise in handshakes and relayed blocks for ones our chain
+/// doesn't contain, grouped into branches by tip. Refreshed on the peer check tick; the
+/// `fork_branches` and `fork_max_depth` metrics follow each refresh, and a split lasting
+/// `warn_after` is logged and posted as a `network_split` webhook event once.
+#[derive(Debug)]
+pub struct ForkMonitor {
+    warn_after: Duration,
+    /// Fork points ancestor searches found, until the peer is back on our chain.
+    common_ancestors: HashMap<PeerId, usize>,
+    split_since: Option<Instant>,
+    warned: bool,
+    last: ForksSnapshot,
+}
+
+impl ForkMonitor {
+    /// `warn_after` of zero never warns.
+    pub fn new(warn_after: Duration) -> Self {
+        ForkMonitor {
+            warn_after,
+            common_ancestors: HashMap::new(),
+            split_since: None,
+            warned: false,
+            last: ForksSnapshot::default(),
+        }
+    }
+
+    /// Records that `peer_id`'s chain left ours after block `ancestor`.
+    pub fn on_common_ancestor(&mut self, peer_id: &PeerId, ancestor: usize) {
+        self.common_ancestors.insert(*peer_id, ancestor);
+    }
+
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) {
+        self.common_ancestors.remove(peer_id);
+    }
+
+    /// Classifies `peers`' heads against our chain, whose head is `head_index`/`head_hash`
+    /// and whose other hashes `our_hash_at` looks up. A head at or below ours with another
+    /// hash is a branch; one above ours only is once an ancestor search put its fork below
+    /// our head, since it may just be ahead of us. Returns the split event to post when the
+    /// network has now been split for `warn_after`.
+    pub fn observe<'a>(
+        &mut self,
+        head_index: usize,
+        head_hash: &str,
+        peers: impl Iterator<Item = (&'a PeerId, &'a PeerHead)>,
+        our_hash_at: impl Fn(usize) -> Option<String>,
+        now: Instant,
+    ) -> Option<NodeEvent> {
+        let mut branches: BTreeMap<String, Branch> = BTreeMap::new();
+        for (peer_id, head) in peers {
+            let ancestor = self.common_ancestors.get(peer_id).copied();
+            let forked = if head.index > head_index {
+                ancestor.is_some_and(|ancestor| ancestor < head_index)
+            } else {
+                let ours = if head.index == head_index {
+                    Some(head_hash.to_string())
+                } else {
+                    our_hash_at(head.index)
+                };
+                match ours {
+                    Some(ours) if ours == head.hash => {
+                        self.common_ancestors.remove(peer_id);
+                        false
+                    }
+                    Some(_) => true,
+                    None => false,
+                }
+            };
+            if !forked {
+                continue;
+            }
+            let branch = branches.entry(head.hash.clone()).or_insert_with(|| Branch {
+                tip_index: head.index,
+                tip_hash: head.hash.clone(),
+                peers: Vec::new(),
+                common_ancestor: None,
+                depth: 0,
+            });
+            branch.peers.push(peer_id.to_string());
+            branch.common_ancestor = branch.common_ancestor.max(ancestor);
+        }
+
+        let mut branches: Vec<Branch> = branches.into_values().collect();
+        for branch in &mut branches {
+            branch.peers.sort();
+            branch.depth = match branch.common_ancestor {
+                Some(ancestor) if ancestor < branch.tip_index => head_index - ancestor,
+                _ => head_index + 1 - branch.tip_index,
+            };
+        }
+        branches.sort_by(|a, b| {
+            b.depth
+                .cmp(&a.depth)
+                .then_with(|| a.tip_hash.cmp(&b.tip_hash))
+        });
+
+        let split_for = if branches.is_empty() {
+            if self.warned {
+                info!("Peers are back on our chain; the network split is over");
+            }
+            self.split_since = None;
+            self.warned = false;
+            None
+        } else {
+            Some(now.saturating_duration_since(*self.split_since.get_or_insert(now)))
+        };
+        let peers: usize = branches.iter().map(|branch| branch.peers.len()).sum();
+        let max_depth = branches.first().map_or(0, |branch| branch.depth);
+        FORK_BRANCHES.set(branches.len() as i64);
+        FORK_MAX_DEPTH.set(max_depth as i64);
+        self.last = ForksSnapshot {
+            head_index,
+            head_hash: head_hash.to_string(),
+            branches,
+            split_secs: split_for.map(|split_for| split_for.as_secs()),
+        };
+
+        let split_for = split_for?;
+        if self.warned || self.warn_after.is_zero() || split_for < self.warn_after {
+            return None;
+        }
+        self.warned = true;
+        warn!(
+            "The network appears split: {} peers on {} competing branches for {}s, the deepest {} blocks deep",
+            peers,
+            self.last.branches.len(),
+            split_for.as_secs(),
+            max_depth
+        );
+        Some(NodeEvent::NetworkSplit {
+            branches: self.last.branches.len(),
+            peers,
+            max_depth,
+            split_secs: split_for.as_secs(),
+        })
+    }
+
+    pub fn snapshot(&self) -> ForksSnapshot {
+        self.last.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn our_hash(index: usize) -> Option<String> {
+        (index <= 10).then(|| format!("h{}", index))
+    }
+
+    #[test]
+    fn peers_off_our_chain_are_grouped_into_branches() {
+        let now = Instant::now();
+        let mut monitor = ForkMonitor::new(Duration::ZERO);
+        let (synced, behind, also_behind, ahead, forked_ahead) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        let heads = [
+            (synced, PeerHead::new(8, "h8".to_string(), now)),
+            (behind, PeerHead::new(9, "x9".to_string(), now)),
+            (also_behind, PeerHead::new(9, "x9".to_string(), now)),
+            (ahead, PeerHead::new(12, "y12".to_string(), now)),
+            (forked_ahead, PeerHead::new(12, "z12".to_string(), now)),
+        ];
+        monitor.on_common_ancestor(&forked_ahead, 6);
+
+        let event = monitor.observe(
+            10,
+            "h10",
+            heads.iter().map(|(peer_id, head)| (peer_id, head)),
+            our_hash,
+            now,
+        );
+        assert!(event.is_none());
+
+        let snapshot = monitor.snapshot();
+        let tips: Vec<(&str, usize, Option<usize>)> = snapshot
+            .branches
+            .iter()
+            .map(|branch| {
+                (
+                    branch.tip_hash.as_str(),
+                    branch.depth,
+                    branch.common_ancestor,
+                )
+            })
+            .collect();
+        assert_eq!(tips, vec![("z12", 4, Some(6)), ("x9", 2, None)]);
+        assert_eq!(snapshot.branches[1].peers.len(), 2);
+        assert_eq!(snapshot.split_secs, Some(0));
+    }
+
+    #[test]
+    fn a_persistent_split_is_reported_once() {
+        let start = Instant::now();
+        let mut monitor = ForkMonitor::new(Duration::from_secs(60));
+        let peer_id = PeerId::random();
+        let forked = [(peer_id, PeerHead::new(10, "x10".to_string(), start))];
+        let observe = |monitor: &mut ForkMonitor, heads: &[(PeerId, PeerHead)], secs| {
+            monitor.observe(
+                10,
+                "h10",
+                heads.iter().map(|(peer_id, head)| (peer_id, head)),
+                our_hash,
+                start + Duration::from_secs(secs),
+            )
+        };
+
+        assert!(observe(&mut monitor, &forked, 0).is_none());
+        assert!(observe(&mut monitor, &forked, 30).is_none());
+        assert_eq!(
+            observe(&mut monitor, &forked, 61),
+            Some(NodeEvent::NetworkSplit {
+                branches: 1,
+                peers: 1,
+                max_depth: 1,
+                split_secs: 61,
+            })
+        );
+        assert!(observe(&mut monitor, &forked, 90).is_none());
+
+        // Once the peer is back on our chain, a new split is timed from scratch.
+        assert!(observe(&mut monitor, &[], 100).is_none());
+        assert_eq!(monitor.snapshot().split_secs, None);
+        assert!(observe(&mut monitor, &forked, 110).is_none());
+        assert!(observe(&mut monitor, &forked, 171).is_some());
+    }
+}
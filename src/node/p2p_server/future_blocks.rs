@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::node::blocks::block::Block;
+
+/// Blocks held at once unless `max_future_blocks` says otherwise; further early blocks
+/// are ignored and left to sync.
+const MAX_FUTURE_BLOCKS: usize = 32;
+/// How far past our head a block may be and still be held for its parents.
+const MAX_BLOCKS_AHEAD: usize = 16;
+/// How long a block is held, and how far ahead of our clock its timestamp may be.
+const FUTURE_BLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A gossiped block that arrived before it could be imported.
+#[derive(Debug, Clone)]
+pub struct FutureBlock {
+    pub block: Block,
+    pub received_at: Instant,
+}
+
+/// Gossiped blocks that were valid but early: their parent hadn't arrived yet, or their
+/// slot hadn't started on our clock. They are retried as the chain grows and on the peer
+/// check tick instead of being rejected for good.
+#[derive(Debug)]
+pub struct FutureBlocks {
+    blocks: HashMap<String, FutureBlock>,
+    capacity: usize,
+}
+
+impl Default for FutureBlocks {
+    fn default() -> Self {
+        Self::new(MAX_FUTURE_BLOCKS)
+    }
+}
+
+impl FutureBlocks {
+    /// Holds at most `capacity` blocks at once.
+    pub fn new(capacity: usize) -> Self {
+        FutureBlocks {
+            blocks: HashMap::new(),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, block_hash: &str) -> bool {
+        self.blocks.contains_key(block_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Holds `block`, unless it is more than a short wait ahead of our head at `tip_index`
+    /// or of our clock at `now_secs`, or too many blocks are already held.
+    pub fn insert(&mut self, block: Block, tip_index: usize, now_secs: u64, now: Instant) -> bool {
+        let too_far = block.index > tip_index + MAX_BLOCKS_AHEAD
+            || block.timestamp > now_secs + FUTURE_BLOCK_TIMEOUT.as_secs();
+        if too_far || block.index <= tip_index || self.blocks.len() >= self.capacity {
+            return false;
+        }
+        self.put_back(FutureBlock {
+            block,
+            received_at: now,
+        });
+        true
+    }
+
+    /// Holds a block taken out by `take_next` again, keeping its original arrival time.
+    pub fn put_back(&mut self, held: FutureBlock) {
+        self.blocks.insert(held.block.hash.clone(), held);
+    }
+
+    /// Removes the held blocks that build on block `tip_index`, oldest first.
+    pub fn take_next(&mut self, tip_index: usize) -> Vec<FutureBlock> {
+        let hashes: Vec<String> = self
+            .blocks
+            .values()
+            .filter(|held| held.block.index == tip_index + 1)
+            .map(|held| held.block.hash.clone())
+            .collect();
+        let mut next: Vec<FutureBlock> = hashes
+            .iter()
+            .filter_map(|hash| self.blocks.remove(hash))
+            .collect();
+        next.sort_by_key(|held| held.received_at);
+        next
+    }
+
+    /// Drops blocks held too long and those the chain has already passed. Returns how
+    /// many were dropped.
+    pub fn expire(&mut self, tip_index: usize, now: Instant) -> usize {
+        let before = self.blocks.len();
+        self.blocks.retain(|_, held| {
+            held.block.index > tip_index
+                && now.duration_since(held.received_at) < FUTURE_BLOCK_TIMEOUT
+        });
+        before - self.blocks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize, hash: &str, timestamp: u64) -> Block {
+        Block {
+            index,
+            timestamp,
+            hash: hash.to_string(),
+            ..Block::new_genesis_block()
+        }
+    }
+
+    #[test]
+    fn only_slightly_early_blocks_are_held() {
+        let now = Instant::now();
+        let mut future = FutureBlocks::default();
+
+        assert!(future.insert(block(11, "a", 1000), 10, 1000, now));
+        assert!(future.insert(block(12, "b", 1030), 10, 1000, now));
+        assert!(
+            !future.insert(block(10, "c", 1000), 10, 1000, now),
+            "already passed"
+        );
+        assert!(
+            !future.insert(block(40, "d", 1000), 10, 1000, now),
+            "left to sync"
+        );
+        assert!(
+            !future.insert(block(11, "e", 2000), 10, 1000, now),
+            "too far ahead"
+        );
+        assert!(future.contains("a"));
+        assert_eq!(future.len(), 2);
+
+        for index in 0..MAX_FUTURE_BLOCKS {
+            future.insert(block(11, &index.to_string(), 1000), 10, 1000, now);
+        }
+        assert_eq!(future.len(), MAX_FUTURE_BLOCKS);
+    }
+
+    #[test]
+    fn held_blocks_are_released_in_height_order_and_expire() {
+        let now = Instant::now();
+        let mut future = FutureBlocks::default();
+        future.insert(block(12, "b", 0), 10, 0, now);
+        future.insert(block(11, "a", 0), 10, 0, now + Duration::from_secs(1));
+        future.insert(block(11, "a2", 0), 10, 0, now);
+
+        assert!(future.take_next(9).is_empty());
+        let next: Vec<String> = future
+            .take_next(10)
+            .into_iter()
+            .map(|held| held.block.hash)
+            .collect();
+        assert_eq!(next, ["a2", "a"]);
+
+        let held = future.take_next(11).remove(0);
+        future.put_back(held);
+        assert_eq!(future.expire(11, now + Duration::from_secs(1)), 0);
+        assert_eq!(future.expire(11, now + FUTURE_BLOCK_TIMEOUT), 1);
+        assert!(future.is_empty());
+    }
+}
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::node::rlp_encoding::RlpCodec;
+
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
 pub struct GetBlockBodies {
+    #[rlp(list)]
     pub block_indexes: Vec<usize>,        
 }
\ No newline at end of file
@@ -1,8 +1,16 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::node::rlp_encoding::RlpCodec;
+
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
 pub struct GetBlockHeaders {
     pub start_block_index: usize,
     pub skip : usize,
     pub limit : usize,
-}
\ No newline at end of file
+    /// Hash the requester has at `start_block_index`. The responder serves nothing if its
+    /// block there differs, so a peer on another fork never hands out headers that only
+    /// line up by index. Empty skips the check.
+    #[serde(default)]
+    #[rlp(default)]
+    pub start_block_hash: String,
+}
@@ -0,0 +1,62 @@
+use libp2p::gossipsub::{PeerScoreParams, PeerScoreThresholds, TopicHash, TopicScoreParams};
+use std::time::Duration;
+
+/// Peer-score parameters for the single Clutch gossip topic.
+///
+/// Traffic is low (one block per Aura step plus occasional transactions), so the mesh
+/// delivery expectation (P3) is deliberately small and slow to activate; otherwise honest
+/// peers would be penalised simply because nothing happened. Invalid messages (P4) are
+/// what we really want to punish: anything we `Reject` in validation costs the sender.
+pub fn peer_score_params(topic: TopicHash) -> PeerScoreParams {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        // P1: reward staying in the mesh.
+        time_in_mesh_weight: 0.1,
+        time_in_mesh_quantum: Duration::from_secs(1),
+        time_in_mesh_cap: 3600.0,
+        // P2: reward being first to deliver a message.
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_decay: 0.9,
+        first_message_deliveries_cap: 100.0,
+        // P3: expect at least one delivery per window once the peer has been meshed a while.
+        mesh_message_deliveries_weight: -0.5,
+        mesh_message_deliveries_decay: 0.97,
+        mesh_message_deliveries_cap: 20.0,
+        mesh_message_deliveries_threshold: 1.0,
+        mesh_message_deliveries_window: Duration::from_millis(100),
+        mesh_message_deliveries_activation: Duration::from_secs(120),
+        // P3b: sticky penalty for peers pruned while under-delivering.
+        mesh_failure_penalty_weight: -0.5,
+        mesh_failure_penalty_decay: 0.97,
+        // P4: undecodable / malformed messages.
+        invalid_message_deliveries_weight: -20.0,
+        invalid_message_deliveries_decay: 0.5,
+    };
+
+    let mut params = PeerScoreParams::default();
+    params.topics.insert(topic, topic_params);
+    params
+}
+
+pub fn peer_score_thresholds() -> PeerScoreThresholds {
+    PeerScoreThresholds {
+        gossip_threshold: -10.0,
+        publish_threshold: -50.0,
+        graylist_threshold: -80.0,
+        accept_px_threshold: 10.0,
+        opportunistic_graft_threshold: 5.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::gossipsub::IdentTopic;
+
+    #[test]
+    fn score_params_pass_gossipsub_validation() {
+        let topic = IdentTopic::new("/clutch/clutch-devnet/test-net");
+        assert!(peer_score_params(topic.hash()).validate().is_ok());
+        assert!(peer_score_thresholds().validate().is_ok());
+    }
+}
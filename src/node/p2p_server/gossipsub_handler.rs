@@ -1,22 +1,50 @@
 use crate::node::{blockchain::Blockchain, blocks::block::Block};
+use crate::node::bft::Vote;
+use crate::node::disk_monitor;
 use crate::node::rlp_encoding::decode;
 use crate::node::transactions::transaction::Transaction;
+use crate::node::p2p_server::compact_block::{CompactBlock, GetBlockTransactions, PendingBlock};
+use crate::node::p2p_server::context::P2PContext;
+use crate::node::p2p_server::seen_cache::SeenCache;
+use crate::node::time_utils::get_current_timespan;
+use crate::node::p2p_server::tx_announce::{GetPooledTransactions, TransactionHashes};
 use crate::node::p2p_server::GossipMessageType;
 
 use libp2p::{
-    gossipsub::{self, MessageId},
+    gossipsub::{self, MessageAcceptance, MessageId},
     PeerId,
 };
-use tracing::{error, info};
+use tracing::{debug, error, info};
+use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::RwLock;
 
+/// What to do with a gossip message once it has been handled.
+pub enum GossipVerdict {
+    /// Report this to gossipsub now.
+    Report(MessageAcceptance),
+    /// A compact block needs transactions our mempool lacks. Ask the propagation source
+    /// for them; the verdict is reported once they arrive or the wait times out.
+    FetchTransactions(GetBlockTransactions),
+    /// A transaction announcement listed hashes we don't know. The announcement itself is
+    /// `Ignore`d (announcements aren't forwarded; every node announces its own pool
+    /// additions) and the transactions are requested from the announcer.
+    FetchPooledTransactions(GetPooledTransactions),
+}
+
+/// Processes one gossip message and returns the verdict reported back to gossipsub.
+/// Only messages that are malformed on the wire are `Reject`ed (penalising the sender);
+/// well-formed transactions/blocks that fail against local state are `Ignore`d, since an
+/// honest peer that is ahead of or behind us can legitimately send those. Hashes already
+/// in `seen` are `Ignore`d without touching the blockchain.
 pub async fn handle_gossipsub_message(
     peer_id: PeerId,
     id: MessageId,
     message: gossipsub::Message,
-    blockchain: &Arc<Mutex<Blockchain>>,
-) {
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> GossipVerdict {
     info!(
         "Received gossip message from peer: {} with id:'{}': {} ",
         peer_id,
@@ -26,59 +54,311 @@ pub async fn handle_gossipsub_message(
 
     if message.data.is_empty() {
         error!("Received empty gossip message from peer: {}", peer_id);
-        return;
+        return GossipVerdict::Report(MessageAcceptance::Reject);
     }
     let message_type = GossipMessageType::from_byte(message.data[0]);
     let payload = &message.data[1..];
 
-    match message_type {
+    let acceptance = match message_type {
         Some(GossipMessageType::Transaction) => match decode::<Transaction>(payload) {
             Ok(transaction) => {
                 info!("Decoded transaction: {:?}", &transaction);
-                handle_received_transaction(&transaction, blockchain).await;
+                handle_received_transaction(&transaction, blockchain, &mut ctx.seen).await
             }
             Err(e) => {
                 error!("Failed to decode transaction: {:?}", e);
+                MessageAcceptance::Reject
             }
         },
         Some(GossipMessageType::Block) => match decode::<Block>(payload) {
             Ok(block) => {
                 info!("Decoded block: {:?}", &block);
-                handle_received_block(&block, blockchain).await;
+                ctx.on_peer_block(&peer_id, block.index, &block.hash);
+                handle_received_block(&block, blockchain, ctx).await
             }
             Err(e) => {
                 error!("Failed to decode block: {:?}", e);
+                MessageAcceptance::Reject
+            }
+        },
+        Some(GossipMessageType::CompactBlock) => match decode::<CompactBlock>(payload) {
+            Ok(compact) => {
+                info!("Decoded compact block: {:?}", &compact);
+                return handle_received_compact_block(compact, peer_id, id, blockchain, ctx).await;
+            }
+            Err(e) => {
+                error!("Failed to decode compact block: {:?}", e);
+                MessageAcceptance::Reject
+            }
+        },
+        Some(GossipMessageType::TransactionHashes) => match decode::<TransactionHashes>(payload) {
+            Ok(announcement) => {
+                debug!(
+                    "Decoded {} announced transactions",
+                    announcement.hashes.len()
+                );
+                return handle_transaction_announcement(announcement, blockchain, ctx).await;
+            }
+            Err(e) => {
+                error!("Failed to decode transaction announcement: {:?}", e);
+                MessageAcceptance::Reject
+            }
+        },
+        Some(GossipMessageType::Vote) => match decode::<Vote>(payload) {
+            Ok(vote) => {
+                debug!("Decoded {:?} vote from {}", vote.kind, vote.voter);
+                handle_received_vote(&vote, blockchain).await
+            }
+            Err(e) => {
+                error!("Failed to decode vote: {:?}", e);
+                MessageAcceptance::Reject
             }
         },
         _ => {
             error!("Unknown message type: {:?}", message_type);
+            MessageAcceptance::Reject
+        }
+    };
+    GossipVerdict::Report(acceptance)
+}
+
+/// Picks the announced transactions that are neither in our pool nor already being fetched.
+async fn handle_transaction_announcement(
+    announcement: TransactionHashes,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> GossipVerdict {
+    let in_pool: HashSet<String> = {
+        let blockchain = blockchain.read().await;
+        announcement
+            .hashes
+            .iter()
+            .filter(|hash| matches!(blockchain.get_transaction_from_pool(hash), Ok(Some(_))))
+            .cloned()
+            .collect()
+    };
+    let seen = &mut ctx.seen;
+    let unknown = ctx
+        .tx_announcer
+        .claim_unknown(announcement.hashes, Instant::now(), |hash| {
+            in_pool.contains(hash) || seen.has_transaction(hash)
+        });
+    if unknown.is_empty() {
+        return GossipVerdict::Report(MessageAcceptance::Ignore);
+    }
+    GossipVerdict::FetchPooledTransactions(GetPooledTransactions { hashes: unknown })
+}
+
+/// Counts a BFT vote. Only new votes for heights still being decided are relayed.
+async fn handle_received_vote(
+    vote: &Vote,
+    blockchain: &Arc<RwLock<Blockchain>>,
+) -> MessageAcceptance {
+    let result = blockchain
+        .read()
+        .await
+        .add_vote(vote, get_current_timespan());
+    match result {
+        Ok(true) => MessageAcceptance::Accept,
+        Ok(false) => MessageAcceptance::Ignore,
+        Err(e) => {
+            debug!("Ignoring vote from {}: {}", vote.voter, e);
+            MessageAcceptance::Ignore
         }
     }
 }
 
-async fn handle_received_transaction(
+pub async fn handle_received_transaction(
     transaction: &Transaction,
-    blockchain: &Arc<Mutex<Blockchain>>,
-) {
+    blockchain: &Arc<RwLock<Blockchain>>,
+    seen: &mut SeenCache,
+) -> MessageAcceptance {
+    if seen.has_transaction(&transaction.hash) {
+        debug!("Ignoring already seen transaction {}", transaction.hash);
+        return MessageAcceptance::Ignore;
+    }
+    if disk_monitor::safe_mode() {
+        debug!(
+            "Disk safe mode; not pooling transaction {}",
+            transaction.hash
+        );
+        return MessageAcceptance::Ignore;
+    }
+
     let result = {
-        let blockchain = blockchain.lock().await;
+        let blockchain = blockchain.write().await;
         blockchain.add_transaction_to_pool(&transaction)
     };
 
     match result {
-        Ok(_) => info!("Transaction added to mempool from P2P"),
-        Err(e) => error!("Failed to add transaction to pool: {:?}", e),
+        Ok(_) => {
+            seen.mark_transaction(&transaction.hash);
+            info!("Transaction added to mempool from P2P");
+            MessageAcceptance::Accept
+        }
+        Err(e) => {
+            error!("Failed to add transaction to pool: {:?}", e);
+            MessageAcceptance::Ignore
+        }
     }
 }
 
-async fn handle_received_block(block: &Block, blockchain: &Arc<Mutex<Blockchain>>) {
-    let result = {
-        let blockchain = blockchain.lock().await;
-        blockchain.import_block(&block)
+/// Rebuilds a compact block from the mempool and imports it, or holds it until the
+/// transactions we don't have are fetched from `peer_id`.
+async fn handle_received_compact_block(
+    compact: CompactBlock,
+    peer_id: PeerId,
+    id: MessageId,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> GossipVerdict {
+    ctx.on_peer_block(&peer_id, compact.index, &compact.hash);
+    if ctx.seen.has_block(&compact.hash) || ctx.compact_blocks.contains(&compact.hash) {
+        debug!("Ignoring already seen compact block {}", compact.hash);
+        return GossipVerdict::Report(MessageAcceptance::Ignore);
+    }
+
+    let transactions = {
+        let blockchain = blockchain.read().await;
+        compact
+            .tx_hashes
+            .iter()
+            .map(|hash| blockchain.get_transaction_from_pool(hash))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let transactions = match transactions {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            error!("Failed to read transaction pool: {}", e);
+            return GossipVerdict::Report(MessageAcceptance::Ignore);
+        }
     };
 
-    match result {
-        Ok(_) => info!("Block added to blockchain from P2P"),
-        Err(e) => error!("Failed to add block to blockchain: {:?}", e),
+    if transactions.iter().all(Option::is_some) {
+        let block = compact.into_block(transactions.into_iter().flatten().collect());
+        return GossipVerdict::Report(handle_received_block(&block, blockchain, ctx).await);
+    }
+
+    let pending = PendingBlock::new(compact, transactions, peer_id, id, Instant::now());
+    match ctx.compact_blocks.insert(pending) {
+        Some(request) => {
+            debug!(
+                "Fetching {} missing transactions of block {} from {}",
+                request.tx_indexes.len(),
+                request.block_index,
+                peer_id
+            );
+            GossipVerdict::FetchTransactions(request)
+        }
+        None => {
+            debug!("Too many compact blocks waiting on transactions; leaving this one to sync");
+            GossipVerdict::Report(MessageAcceptance::Ignore)
+        }
+    }
+}
+
+/// Imports a gossiped block. One that fails only for arriving before its parent or its
+/// slot is held in `ctx.future_blocks` and retried, and held blocks it unblocks follow it.
+pub async fn handle_received_block(
+    block: &Block,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> MessageAcceptance {
+    if ctx.seen.has_block(&block.hash) || ctx.future_blocks.contains(&block.hash) {
+        debug!("Ignoring already imported block {}", block.hash);
+        return MessageAcceptance::Ignore;
+    }
+
+    let blockchain = blockchain.write().await;
+    match blockchain.import_block(&block) {
+        Ok(_) => {
+            ctx.seen.mark_block(&block.hash);
+            info!("Block added to blockchain from P2P");
+            import_future_blocks(&blockchain, ctx);
+            MessageAcceptance::Accept
+        }
+        Err(e) if hold_future_block(block, &blockchain, ctx) => {
+            debug!(
+                "Holding block {} until it can be imported: {}",
+                block.index, e
+            );
+            MessageAcceptance::Ignore
+        }
+        Err(e) => {
+            error!("Failed to add block to blockchain: {:?}", e);
+            MessageAcceptance::Ignore
+        }
+    }
+}
+
+/// Holds `block` if it can become importable soon. Returns whether it was held.
+fn hold_future_block(block: &Block, blockchain: &Blockchain, ctx: &mut P2PContext) -> bool {
+    let Ok(Some(tip)) = blockchain.get_latest_block() else {
+        return false;
+    };
+    matches!(blockchain.is_future_block(block), Ok(true))
+        && ctx.future_blocks.insert(
+            block.clone(),
+            tip.index,
+            get_current_timespan(),
+            Instant::now(),
+        )
+}
+
+/// Imports held blocks that now build on our head, one height at a time. Blocks whose slot
+/// still hasn't started go back in the queue; those that fail otherwise are dropped.
+pub fn import_future_blocks(blockchain: &Blockchain, ctx: &mut P2PContext) {
+    loop {
+        let tip_index = match blockchain.get_latest_block() {
+            Ok(Some(tip)) => tip.index,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to read latest block: {}", e);
+                return;
+            }
+        };
+        let mut imported = false;
+        for held in ctx.future_blocks.take_next(tip_index) {
+            if imported {
+                debug!(
+                    "Dropping held block {}; its height is taken",
+                    held.block.hash
+                );
+                continue;
+            }
+            match blockchain.import_block(&held.block) {
+                Ok(()) => {
+                    ctx.seen.mark_block(&held.block.hash);
+                    info!("Imported held block {}", held.block.index);
+                    imported = true;
+                }
+                Err(_) if matches!(blockchain.is_future_block(&held.block), Ok(true)) => {
+                    ctx.future_blocks.put_back(held);
+                }
+                Err(e) => debug!("Dropping held block {}: {}", held.block.index, e),
+            }
+        }
+        if !imported {
+            return;
+        }
+    }
+}
+
+/// Called on the peer check tick: drops held blocks that waited too long and imports the
+/// ones whose slot has now started.
+pub async fn retry_future_blocks(blockchain: &Arc<RwLock<Blockchain>>, ctx: &mut P2PContext) {
+    if ctx.future_blocks.is_empty() {
+        return;
+    }
+    let blockchain = blockchain.write().await;
+    if let Ok(Some(tip)) = blockchain.get_latest_block() {
+        let expired = ctx.future_blocks.expire(tip.index, Instant::now());
+        if expired > 0 {
+            debug!(
+                "Dropped {} held blocks that never became importable",
+                expired
+            );
+        }
     }
+    import_future_blocks(&blockchain, ctx);
 }
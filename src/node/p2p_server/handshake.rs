@@ -1,8 +1,47 @@
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::node::rlp_encoding::RlpCodec;
+
+#[derive(Debug, Clone, Serialize, Deserialize, RlpCodec)]
 pub struct Handshake {
     pub genesis_block_hash: String,
     pub latest_block_hash: String,
     pub latest_block_index: usize,
+    /// Wire formats (`WireFormat` names) this node accepts, most preferred first. Empty
+    /// means RLP only, and is left off the wire so older nodes can still decode it.
+    #[serde(default)]
+    #[rlp(list, default)]
+    pub capabilities: Vec<String>,
+    /// The sender's hardfork schedule (`HardforkSchedule::advertised`). Peers whose
+    /// schedules disagree on a fork that has activated are disconnected.
+    #[serde(default)]
+    #[rlp(list, default)]
+    pub hardforks: Vec<String>,
+    /// The sender's signed `NodeRecord`; empty from nodes that predate records.
+    #[serde(default)]
+    #[rlp(default)]
+    pub node_record: Vec<u8>,
+    /// The newest direct message protocol version the sender speaks
+    /// (`DIRECT_MESSAGE_VERSION`); 0 from nodes that predate versions, which speak 1.
+    #[serde(default)]
+    #[rlp(default)]
+    pub protocol_version: u32,
+}
+
+/// Our handshake as of the last head change. The `Blockchain` refreshes it after every
+/// import, bulk-import flush and rollback, and the P2P server answers handshakes from it
+/// without taking the chain lock. Clones share the same snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct HandshakeSnapshot(Arc<RwLock<Option<Handshake>>>);
+
+impl HandshakeSnapshot {
+    /// `None` until the first refresh.
+    pub fn get(&self) -> Option<Handshake> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, handshake: Handshake) {
+        *self.0.write().unwrap() = Some(handshake);
+    }
 }
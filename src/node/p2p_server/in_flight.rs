@@ -0,0 +1,108 @@
+use libp2p::{request_response::OutboundRequestId, PeerId};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::commands::DirectMessageType;
+
+/// Every direct request we have outstanding, with the peer it went to and its type. A
+/// response is only handled once it matches an entry and `answers` its request, so a
+/// peer can't get blocks imported by answering a `Ping` with `BlockBodies`. Generic over
+/// the request id only so tests don't need a live swarm to mint `OutboundRequestId`s.
+#[derive(Debug)]
+pub struct InFlightRequests<R = OutboundRequestId> {
+    requests: HashMap<R, (PeerId, DirectMessageType)>,
+}
+
+impl<R> Default for InFlightRequests<R> {
+    fn default() -> Self {
+        Self {
+            requests: HashMap::new(),
+        }
+    }
+}
+
+impl<R: Eq + Hash> InFlightRequests<R> {
+    pub fn on_sent(&mut self, request_id: R, peer_id: PeerId, request: DirectMessageType) {
+        self.requests.insert(request_id, (peer_id, request));
+    }
+
+    /// Forgets a request that failed or timed out.
+    pub fn on_failed(&mut self, request_id: &R) {
+        self.requests.remove(request_id);
+    }
+
+    /// The type of the request `request_id` answers, taken out of the table. `None` when
+    /// we have no such request to `peer_id` outstanding, or it was already answered.
+    pub fn take(&mut self, request_id: &R, peer_id: &PeerId) -> Option<DirectMessageType> {
+        match self.requests.get(request_id) {
+            Some((sent_to, _)) if sent_to == peer_id => {
+                self.requests.remove(request_id).map(|(_, request)| request)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+/// Whether a response of type `response` is what `request` is answered with.
+pub fn answers(request: DirectMessageType, response: Option<DirectMessageType>) -> bool {
+    response.is_some() && request.response_type() == response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_our_own_requests_are_answered_once_by_the_peer_asked() {
+        let peer = PeerId::random();
+        let mut in_flight: InFlightRequests<u64> = InFlightRequests::default();
+        in_flight.on_sent(1, peer, DirectMessageType::Ping);
+        in_flight.on_sent(2, peer, DirectMessageType::GetBlockBodies);
+        in_flight.on_sent(3, peer, DirectMessageType::Handshake);
+
+        assert_eq!(in_flight.take(&1, &peer), Some(DirectMessageType::Ping));
+        assert_eq!(
+            in_flight.take(&1, &peer),
+            None,
+            "a request is answered once"
+        );
+        assert_eq!(
+            in_flight.take(&2, &PeerId::random()),
+            None,
+            "only the peer asked can answer"
+        );
+        assert_eq!(
+            in_flight.take(&2, &peer),
+            Some(DirectMessageType::GetBlockBodies)
+        );
+        in_flight.on_failed(&3);
+        assert_eq!(in_flight.take(&3, &peer), None);
+        assert!(in_flight.is_empty());
+
+        assert!(answers(
+            DirectMessageType::Ping,
+            Some(DirectMessageType::Pong)
+        ));
+        assert!(!answers(
+            DirectMessageType::Ping,
+            Some(DirectMessageType::BlockBodies)
+        ));
+        assert!(answers(
+            DirectMessageType::Disconnect,
+            Some(DirectMessageType::Disconnect)
+        ));
+        assert!(!answers(
+            DirectMessageType::Pong,
+            Some(DirectMessageType::Pong)
+        ));
+        assert!(!answers(DirectMessageType::GetBlockHeaders, None));
+    }
+}
@@ -0,0 +1,172 @@
+use futures::FutureExt;
+use libp2p::{request_response::ResponseChannel, PeerId};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::error;
+
+use super::behaviour::DirectMessageResponse;
+use super::priority::Priority;
+use crate::node::error::NodeError;
+
+/// Workers bulk jobs can never take, so a peer syncing from us can't crowd out the requests
+/// a new block's propagation waits on.
+const RESERVED_WORKERS: usize = 1;
+
+/// A finished inbound request, handed back to the swarm task to be sent on `channel`.
+#[derive(Debug)]
+pub struct InboundResponse<C = ResponseChannel<DirectMessageResponse>, M = Vec<u8>> {
+    pub peer_id: PeerId,
+    pub channel: C,
+    pub message: M,
+    /// The job panicked; `message` is then empty, which the peer sees as a failed request.
+    pub panicked: bool,
+}
+
+/// Runs chain-data requests (`GetBlockHeaders`/`GetBlockBodies`) off the swarm task, at
+/// most `max_concurrent` at a time. Waiting on the blockchain lock inside the swarm loop
+/// stalled every other event, so the loop only dispatches and later sends the result.
+/// Generic over the response channel so tests don't need a live swarm, and over the
+/// message so `/clutch/sync/1` ranges are served the same way.
+#[derive(Debug)]
+pub struct InboundPool<C = ResponseChannel<DirectMessageResponse>, M = Vec<u8>> {
+    permits: Arc<Semaphore>,
+    /// Held alongside a worker permit by `Bulk` jobs; `RESERVED_WORKERS` fewer than the
+    /// workers, when there is more than one.
+    bulk_permits: Arc<Semaphore>,
+    responses: mpsc::Sender<InboundResponse<C, M>>,
+}
+
+impl<C: Send + 'static, M: Default + Send + 'static> InboundPool<C, M> {
+    pub fn new(max_concurrent: usize) -> (Self, mpsc::Receiver<InboundResponse<C, M>>) {
+        let max_concurrent = max_concurrent.max(1);
+        let (responses, response_rx) = mpsc::channel(max_concurrent);
+        let max_bulk = max_concurrent.saturating_sub(RESERVED_WORKERS).max(1);
+        let pool = Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            bulk_permits: Arc::new(Semaphore::new(max_bulk)),
+            responses,
+        };
+        (pool, response_rx)
+    }
+
+    /// Spawns `job` if a worker slot is free for its `priority`. When the pool is saturated
+    /// the request is dropped and `false` returned; the peer sees a failed request and can
+    /// retry elsewhere.
+    pub fn spawn<F>(&self, peer_id: PeerId, channel: C, priority: Priority, job: F) -> bool
+    where
+        F: Future<Output = M> + Send + 'static,
+    {
+        let bulk_permit = match priority {
+            Priority::Bulk => match Arc::clone(&self.bulk_permits).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => return false,
+            },
+            _ => None,
+        };
+        let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() else {
+            return false;
+        };
+        let responses = self.responses.clone();
+
+        tokio::spawn(async move {
+            let (message, panicked) = match AssertUnwindSafe(job).catch_unwind().await {
+                Ok(message) => (message, false),
+                Err(panic) => {
+                    error!(
+                        "Inbound request from peer {} failed: {}",
+                        peer_id,
+                        NodeError::from_panic(panic)
+                    );
+                    (M::default(), true)
+                }
+            };
+            let _ = responses
+                .send(InboundResponse {
+                    peer_id,
+                    channel,
+                    message,
+                    panicked,
+                })
+                .await;
+            drop(permit);
+            drop(bulk_permit);
+        });
+        true
+    }
+
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn requests_beyond_limit_are_dropped_until_a_worker_frees_up() {
+        let (pool, mut response_rx) = InboundPool::<u32>::new(1);
+        let peer = PeerId::random();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+
+        assert!(pool.spawn(peer, 1, Priority::Bulk, async move {
+            let _ = release_rx.await;
+            vec![0x03]
+        }));
+        assert!(!pool.spawn(peer, 2, Priority::Bulk, async { vec![0x05] }));
+        assert_eq!(pool.available(), 0);
+
+        release_tx.send(()).unwrap();
+        let response = response_rx.recv().await.unwrap();
+        assert_eq!((response.channel, response.message), (1, vec![0x03]));
+
+        while pool.available() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(pool.spawn(peer, 3, Priority::Bulk, async { vec![0x05] }));
+        assert_eq!(response_rx.recv().await.unwrap().channel, 3);
+    }
+
+    #[tokio::test]
+    async fn bulk_jobs_leave_a_worker_for_urgent_requests() {
+        let (pool, _response_rx) = InboundPool::<u32>::new(2);
+        let peer = PeerId::random();
+        let (_release_tx, release_rx) = oneshot::channel::<()>();
+        let (_release_urgent_tx, release_urgent_rx) = oneshot::channel::<()>();
+
+        assert!(pool.spawn(peer, 1, Priority::Bulk, async move {
+            let _ = release_rx.await;
+            Vec::new()
+        }));
+        assert!(!pool.spawn(peer, 2, Priority::Bulk, async { Vec::new() }));
+        assert!(pool.spawn(peer, 3, Priority::Consensus, async move {
+            let _ = release_urgent_rx.await;
+            Vec::new()
+        }));
+        assert!(!pool.spawn(peer, 4, Priority::Consensus, async { Vec::new() }));
+    }
+
+    #[tokio::test]
+    async fn panicking_jobs_are_answered_and_free_their_worker() {
+        fn read_block() -> Vec<u8> {
+            panic!("storage hiccup")
+        }
+        let (pool, mut response_rx) = InboundPool::<u32>::new(1);
+        let peer = PeerId::random();
+
+        assert!(pool.spawn(peer, 1, Priority::Bulk, async { read_block() }));
+        let response = response_rx.recv().await.unwrap();
+        assert_eq!(response.channel, 1);
+        assert!(response.panicked);
+        assert!(response.message.is_empty());
+
+        while pool.available() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(pool.spawn(peer, 2, Priority::Bulk, async { vec![0x03] }));
+        assert!(!response_rx.recv().await.unwrap().panicked);
+    }
+}
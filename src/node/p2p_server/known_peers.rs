@@ -0,0 +1,160 @@
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Entries kept in the file; the lowest ranked are dropped beyond this.
+const MAX_KNOWN_PEERS: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownPeer {
+    /// For diagnostics only: a peer gets a new id every time it restarts.
+    pub peer_id: String,
+    pub address: String,
+    /// Unix seconds.
+    pub last_seen: u64,
+    /// The peer's sync score when last seen; higher is better.
+    pub score: f64,
+}
+
+/// Peers we have dialed and handshaked with, saved as JSON so a restarted node can
+/// reconnect to them instead of relying only on its bootstrap nodes. Entries are keyed by
+/// address, and only addresses we dialed are kept, since the remote address of an inbound
+/// connection is an ephemeral port.
+#[derive(Debug, Default)]
+pub struct KnownPeers {
+    path: Option<PathBuf>,
+    peers: HashMap<String, KnownPeer>,
+}
+
+impl KnownPeers {
+    /// Reads the file at `path`; a missing or unreadable file starts an empty list. With no
+    /// path, peers are tracked in memory only.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let peers = path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                let read = fs::read(path).map_err(|e| e.to_string()).and_then(|bytes| {
+                    serde_json::from_slice::<Vec<KnownPeer>>(&bytes).map_err(|e| e.to_string())
+                });
+                read.map_err(|e| warn!("Ignoring known peers file {}: {}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|peer| (peer.address.clone(), peer))
+            .collect();
+        Self { path, peers }
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Adds or refreshes the peer at `address`. Returns true if the address is new.
+    pub fn record(&mut self, peer_id: &PeerId, address: &Multiaddr, score: f64, now: u64) -> bool {
+        let address = address.to_string();
+        let added = !self.peers.contains_key(&address);
+        self.peers.insert(
+            address.clone(),
+            KnownPeer {
+                peer_id: peer_id.to_string(),
+                address,
+                last_seen: now,
+                score,
+            },
+        );
+        if self.peers.len() > MAX_KNOWN_PEERS {
+            let ranked = self.ranked();
+            let dropped: Vec<String> = ranked[MAX_KNOWN_PEERS..]
+                .iter()
+                .map(|peer| peer.address.clone())
+                .collect();
+            for address in dropped {
+                self.peers.remove(&address);
+            }
+        }
+        added
+    }
+
+    /// Best ranked addresses to dial at startup.
+    pub fn best(&self, count: usize) -> Vec<Multiaddr> {
+        self.ranked()
+            .into_iter()
+            .filter_map(|peer| peer.address.parse().ok())
+            .take(count)
+            .collect()
+    }
+
+    /// Highest score first, most recently seen among equals.
+    fn ranked(&self) -> Vec<&KnownPeer> {
+        let mut peers: Vec<&KnownPeer> = self.peers.values().collect();
+        peers.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        peers
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(&self.ranked()).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/10.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn best_peers_rank_by_score_then_recency() {
+        let mut known = KnownPeers::default();
+        let peer = PeerId::random();
+        assert!(known.record(&peer, &addr(1), 5.0, 100));
+        assert!(known.record(&peer, &addr(2), 9.0, 50));
+        assert!(known.record(&peer, &addr(3), 5.0, 200));
+        assert!(!known.record(&peer, &addr(1), 5.0, 150), "same address");
+
+        assert_eq!(known.best(2), [addr(2), addr(3)]);
+        assert_eq!(known.len(), 3);
+    }
+
+    #[test]
+    fn saved_peers_are_loaded_back() {
+        let path = std::env::temp_dir().join(format!("known-peers-{}.json", PeerId::random()));
+        let mut known = KnownPeers::load(Some(path.clone()));
+        assert!(known.is_empty());
+        known.record(&PeerId::random(), &addr(4001), 1.0, 10);
+        known.save().unwrap();
+
+        let reloaded = KnownPeers::load(Some(path.clone()));
+        fs::remove_file(&path).ok();
+        assert_eq!(reloaded.best(8), [addr(4001)]);
+    }
+
+    #[test]
+    fn list_is_capped_by_dropping_the_worst() {
+        let mut known = KnownPeers::default();
+        let peer = PeerId::random();
+        for port in 0..=MAX_KNOWN_PEERS as u16 {
+            known.record(&peer, &addr(port), port as f64, 0);
+        }
+        assert_eq!(known.len(), MAX_KNOWN_PEERS);
+        assert!(!known.best(MAX_KNOWN_PEERS).contains(&addr(0)));
+    }
+}
@@ -0,0 +1,224 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{
+    request_response::{cbor, Codec},
+    StreamProtocol,
+};
+use std::io;
+
+use super::behaviour::{checksum, DirectMessageRequest, DirectMessageResponse};
+use super::wire_codec::{from_legacy_frame, to_legacy_frame};
+
+/// The direct message protocol: frames with a message type and a format byte.
+pub const DIRECT_MESSAGE_PROTOCOL: StreamProtocol = StreamProtocol::new("/agent/message/2.0.0");
+/// The protocol nodes spoke before wire formats: the message type, then RLP.
+pub const LEGACY_DIRECT_MESSAGE_PROTOCOL: StreamProtocol =
+    StreamProtocol::new("/agent/message/1.0.0");
+/// Both protocols, in the order we ask for them, so two current nodes never fall back.
+pub const DIRECT_MESSAGE_PROTOCOLS: [StreamProtocol; 2] =
+    [DIRECT_MESSAGE_PROTOCOL, LEGACY_DIRECT_MESSAGE_PROTOCOL];
+
+type CborCodec = cbor::codec::Codec<DirectMessageRequest, DirectMessageResponse>;
+
+/// Wire format of the direct message protocols: libp2p's CBOR envelope on both. Frames
+/// are built and handled in the current layout; on the legacy protocol this codec
+/// rewrites them to the legacy layout on the way out and back on the way in, so nothing
+/// above it needs to know which protocol a peer negotiated.
+#[derive(Clone, Default)]
+pub struct DirectMessageCodec(CborCodec);
+
+#[async_trait]
+impl Codec for DirectMessageCodec {
+    type Protocol = StreamProtocol;
+    type Request = DirectMessageRequest;
+    type Response = DirectMessageResponse;
+
+    async fn read_request<T>(
+        &mut self,
+        protocol: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<DirectMessageRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut request = self.0.read_request(protocol, io).await?;
+        if *protocol == LEGACY_DIRECT_MESSAGE_PROTOCOL {
+            read_legacy(&mut request.message, &mut request.checksum)?;
+        }
+        Ok(request)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        protocol: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<DirectMessageResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut response = self.0.read_response(protocol, io).await?;
+        if *protocol == LEGACY_DIRECT_MESSAGE_PROTOCOL {
+            read_legacy(&mut response.message, &mut response.checksum)?;
+        }
+        Ok(response)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        protocol: &StreamProtocol,
+        io: &mut T,
+        mut request: DirectMessageRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        if *protocol == LEGACY_DIRECT_MESSAGE_PROTOCOL {
+            write_legacy(&mut request.message, &mut request.checksum)?;
+        }
+        self.0.write_request(protocol, io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        protocol: &StreamProtocol,
+        io: &mut T,
+        mut response: DirectMessageResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        if *protocol == LEGACY_DIRECT_MESSAGE_PROTOCOL {
+            write_legacy(&mut response.message, &mut response.checksum)?;
+        }
+        self.0.write_response(protocol, io, response).await
+    }
+}
+
+/// Rewrites a message read over the legacy protocol to the current layout. A checksum,
+/// if the sender sent one, covers the legacy bytes, so it is checked before the rewrite.
+fn read_legacy(message: &mut Vec<u8>, sum: &mut Option<u32>) -> io::Result<()> {
+    if sum.is_some_and(|expected| checksum(message) != expected) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "corrupted direct message",
+        ));
+    }
+    *message = from_legacy_frame(message);
+    *sum = Some(checksum(message));
+    Ok(())
+}
+
+fn write_legacy(message: &mut Vec<u8>, sum: &mut Option<u32>) -> io::Result<()> {
+    *message =
+        to_legacy_frame(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    *sum = Some(checksum(message));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::p2p_server::commands::DirectMessageType;
+    use crate::node::p2p_server::ping::Ping;
+    use crate::node::p2p_server::wire_codec::{decode_frame, encode_frame, WireFormat};
+    use crate::node::rlp_encoding;
+    use futures::io::Cursor;
+    use serde::{Deserialize, Serialize};
+
+    /// A direct message as nodes that predate wire formats and checksums send and read it.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct LegacyMessage {
+        message: Vec<u8>,
+    }
+
+    async fn send(protocol: &StreamProtocol, request: DirectMessageRequest) -> io::Result<Vec<u8>> {
+        let mut written = Cursor::new(Vec::new());
+        DirectMessageCodec::default()
+            .write_request(protocol, &mut written, request)
+            .await?;
+        Ok(written.into_inner())
+    }
+
+    async fn receive(
+        protocol: &StreamProtocol,
+        bytes: Vec<u8>,
+    ) -> io::Result<DirectMessageRequest> {
+        DirectMessageCodec::default()
+            .read_request(protocol, &mut Cursor::new(bytes))
+            .await
+    }
+
+    #[tokio::test]
+    async fn legacy_peers_get_legacy_frames_and_ours_are_read_back() {
+        let frame =
+            encode_frame(DirectMessageType::Ping, WireFormat::Rlp, &Ping { nonce: 7 }).unwrap();
+        for protocol in DIRECT_MESSAGE_PROTOCOLS {
+            let bytes = send(&protocol, DirectMessageRequest::new(frame.clone()))
+                .await
+                .unwrap();
+            let sent: DirectMessageRequest = cbor4ii::serde::from_slice(&bytes).unwrap();
+            let legacy = protocol == LEGACY_DIRECT_MESSAGE_PROTOCOL;
+            assert_eq!(sent.message.len(), frame.len() - usize::from(legacy));
+            assert_eq!(sent.message[0], DirectMessageType::Ping.as_byte());
+
+            let read = receive(&protocol, bytes).await.unwrap();
+            assert_eq!(read.message, frame);
+            assert!(read.is_intact());
+            let (message_type, format, _) = decode_frame(&read.message).unwrap();
+            assert_eq!(message_type, Some(DirectMessageType::Ping));
+            assert_eq!(format, Some(WireFormat::Rlp));
+        }
+
+        let cbor = encode_frame(
+            DirectMessageType::Ping,
+            WireFormat::Cbor,
+            &Ping { nonce: 7 },
+        )
+        .unwrap();
+        let refused = send(
+            &LEGACY_DIRECT_MESSAGE_PROTOCOL,
+            DirectMessageRequest::new(cbor),
+        )
+        .await;
+        assert!(refused.is_err(), "legacy peers only read RLP");
+    }
+
+    #[tokio::test]
+    async fn legacy_frames_without_checksums_are_only_accepted_on_the_legacy_protocol() {
+        let mut legacy_frame = vec![DirectMessageType::Ping.as_byte()];
+        legacy_frame.extend(rlp_encoding::encode(&Ping { nonce: 7 }));
+        let legacy = LegacyMessage {
+            message: legacy_frame.clone(),
+        };
+        let bytes = cbor4ii::serde::to_vec(Vec::new(), &legacy).unwrap();
+
+        let read = receive(&LEGACY_DIRECT_MESSAGE_PROTOCOL, bytes.clone())
+            .await
+            .unwrap();
+        assert!(read.is_intact());
+        let (message_type, format, payload) = decode_frame(&read.message).unwrap();
+        assert_eq!(message_type, Some(DirectMessageType::Ping));
+        let ping: Ping = format.unwrap().decode(payload).unwrap();
+        assert_eq!(ping.nonce, 7);
+
+        let unchecked = receive(&DIRECT_MESSAGE_PROTOCOL, bytes).await.unwrap();
+        assert!(
+            !unchecked.is_intact(),
+            "the current protocol requires a checksum"
+        );
+
+        let written = send(&LEGACY_DIRECT_MESSAGE_PROTOCOL, read).await.unwrap();
+        let echoed: LegacyMessage = cbor4ii::serde::from_slice(&written).unwrap();
+        assert_eq!(
+            echoed.message, legacy_frame,
+            "a legacy node reads what we send"
+        );
+
+        let mut corrupted = DirectMessageRequest::new(legacy_frame);
+        corrupted.message[1] ^= 0x01;
+        let bytes = cbor4ii::serde::to_vec(Vec::new(), &corrupted).unwrap();
+        assert!(receive(&LEGACY_DIRECT_MESSAGE_PROTOCOL, bytes)
+            .await
+            .is_err());
+    }
+}
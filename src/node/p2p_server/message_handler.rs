@@ -0,0 +1,213 @@
+use async_trait::async_trait;
+use libp2p::{swarm::Swarm, PeerId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::commands::DirectMessageType;
+use super::context::P2PContext;
+use super::priority::Priority;
+use super::wire_codec::WireFormat;
+use super::P2PBehaviour;
+use crate::node::blockchain::{Blockchain, ChainReader};
+use crate::node::error::NodeError;
+
+/// Newest version of the direct message protocol we speak (`/agent/message/2.0.0`),
+/// advertised in our handshake.
+pub const DIRECT_MESSAGE_VERSION: u32 = 2;
+/// The version of peers whose handshake carries none (`/agent/message/1.0.0`), and of
+/// every peer until its handshake arrives.
+pub const LEGACY_DIRECT_MESSAGE_VERSION: u32 = 1;
+
+/// The version we handle a peer's messages under: the older of ours and the one its
+/// handshake advertised.
+pub fn negotiate_version(advertised: u32) -> u32 {
+    advertised
+        .max(LEGACY_DIRECT_MESSAGE_VERSION)
+        .min(DIRECT_MESSAGE_VERSION)
+}
+
+/// A decoded direct message, with the swarm-task state its handler may use.
+pub struct Inbound<'a> {
+    pub peer: PeerId,
+    pub format: WireFormat,
+    pub payload: &'a [u8],
+    pub swarm: &'a mut Swarm<P2PBehaviour>,
+    pub blockchain: &'a Arc<RwLock<Blockchain>>,
+    pub ctx: &'a mut P2PContext,
+}
+
+/// Reads a response from the chain on the inbound pool, off the swarm task.
+pub type ChainJob = Box<dyn FnOnce(ChainReader) -> Result<Vec<u8>, NodeError> + Send>;
+
+/// How a request handler answers.
+pub enum Answer {
+    /// With this frame, sent right away; an error is answered with an empty message.
+    Now(Result<Vec<u8>, NodeError>),
+    /// With what the job reads, once an inbound worker at `Priority` is free.
+    FromChain(Priority, ChainJob),
+}
+
+/// Answers requests of one message type.
+#[async_trait]
+pub trait RequestHandler: Send + Sync {
+    /// Whether the peer must have completed a handshake first. Peers that haven't are
+    /// disconnected for `ProtocolViolation`.
+    fn requires_handshake(&self) -> bool {
+        true
+    }
+
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer;
+}
+
+/// Handles responses of one message type to our own requests, once they passed the
+/// in-flight check and no caller awaits them.
+#[async_trait]
+pub trait ResponseHandler: Send + Sync {
+    async fn handle_response(&self, message: Inbound<'_>);
+}
+
+/// Direct message handlers by message type and protocol version. A message is handled by
+/// the newest version registered at or below the one negotiated with its peer, so a new
+/// protocol version only registers the handlers that changed.
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    requests: HashMap<DirectMessageType, BTreeMap<u32, Arc<dyn RequestHandler>>>,
+    responses: HashMap<DirectMessageType, BTreeMap<u32, Arc<dyn ResponseHandler>>>,
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandlerRegistry")
+            .field("requests", &self.requests.keys().collect::<Vec<_>>())
+            .field("responses", &self.responses.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl HandlerRegistry {
+    /// Registers `handler` for `request` messages and for the responses they get.
+    pub fn with_handler<H>(self, request: DirectMessageType, version: u32, handler: H) -> Self
+    where
+        H: RequestHandler + ResponseHandler + 'static,
+    {
+        let handler = Arc::new(handler);
+        let registry = self.with_request(request, version, handler.clone());
+        match request.response_type() {
+            Some(response) => registry.with_response(response, version, handler),
+            None => registry,
+        }
+    }
+
+    pub fn with_request(
+        mut self,
+        message_type: DirectMessageType,
+        version: u32,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Self {
+        self.requests
+            .entry(message_type)
+            .or_default()
+            .insert(version, handler);
+        self
+    }
+
+    pub fn with_response(
+        mut self,
+        message_type: DirectMessageType,
+        version: u32,
+        handler: Arc<dyn ResponseHandler>,
+    ) -> Self {
+        self.responses
+            .entry(message_type)
+            .or_default()
+            .insert(version, handler);
+        self
+    }
+
+    pub fn request(
+        &self,
+        message_type: DirectMessageType,
+        version: u32,
+    ) -> Option<Arc<dyn RequestHandler>> {
+        newest_at_or_below(self.requests.get(&message_type)?, version)
+    }
+
+    pub fn response(
+        &self,
+        message_type: DirectMessageType,
+        version: u32,
+    ) -> Option<Arc<dyn ResponseHandler>> {
+        newest_at_or_below(self.responses.get(&message_type)?, version)
+    }
+}
+
+fn newest_at_or_below<H: ?Sized>(versions: &BTreeMap<u32, Arc<H>>, version: u32) -> Option<Arc<H>> {
+    versions
+        .range(..=version)
+        .next_back()
+        .map(|(_, handler)| Arc::clone(handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ignore;
+
+    #[async_trait]
+    impl ResponseHandler for Ignore {
+        async fn handle_response(&self, _message: Inbound<'_>) {}
+    }
+
+    #[test]
+    fn messages_go_to_the_newest_handler_at_or_below_their_version() {
+        let versions: BTreeMap<u32, Arc<&str>> =
+            BTreeMap::from([(1, Arc::new("v1")), (3, Arc::new("v3"))]);
+        assert_eq!(newest_at_or_below(&versions, 1).as_deref(), Some(&"v1"));
+        assert_eq!(newest_at_or_below(&versions, 2).as_deref(), Some(&"v1"));
+        assert_eq!(newest_at_or_below(&versions, 4).as_deref(), Some(&"v3"));
+        assert_eq!(newest_at_or_below(&versions, 0), None);
+
+        let registry =
+            HandlerRegistry::default().with_response(DirectMessageType::Pong, 1, Arc::new(Ignore));
+        assert!(registry.response(DirectMessageType::Pong, 2).is_some());
+        assert!(registry
+            .response(DirectMessageType::BlockBodies, 2)
+            .is_none());
+        assert!(registry.request(DirectMessageType::Pong, 2).is_none());
+    }
+
+    #[test]
+    fn peers_on_another_version_get_that_versions_handlers() {
+        let v1: Arc<dyn ResponseHandler> = Arc::new(Ignore);
+        let v2: Arc<dyn ResponseHandler> = Arc::new(Ignore);
+        let registry = HandlerRegistry::default()
+            .with_response(
+                DirectMessageType::Pong,
+                LEGACY_DIRECT_MESSAGE_VERSION,
+                v1.clone(),
+            )
+            .with_response(DirectMessageType::Pong, DIRECT_MESSAGE_VERSION, v2.clone());
+
+        let legacy_peer = negotiate_version(0);
+        assert_eq!(legacy_peer, LEGACY_DIRECT_MESSAGE_VERSION);
+        let handler = registry
+            .response(DirectMessageType::Pong, legacy_peer)
+            .unwrap();
+        assert!(Arc::ptr_eq(&handler, &v1));
+
+        let current_peer = negotiate_version(DIRECT_MESSAGE_VERSION);
+        let handler = registry
+            .response(DirectMessageType::Pong, current_peer)
+            .unwrap();
+        assert!(Arc::ptr_eq(&handler, &v2));
+
+        // A newer peer is handled under our newest version, not one we don't know.
+        assert_eq!(
+            negotiate_version(DIRECT_MESSAGE_VERSION + 1),
+            DIRECT_MESSAGE_VERSION
+        );
+    }
+}
@@ -1,11 +1,40 @@
+pub mod ancestor_search;
+pub mod bandwidth;
 pub mod behaviour;
+pub mod branch_download;
+pub mod capability;
 pub mod commands;
+pub mod compact_block;
+pub mod config;
+pub mod connection_gate;
+pub mod context;
+pub mod disconnect;
+pub mod fork_monitor;
+pub mod future_blocks;
 pub mod get_block_bodies;
 pub mod get_block_header;
+pub mod gossip_scoring;
 pub mod gossipsub_handler;
 pub mod handshake;
+pub mod in_flight;
+pub mod inbound_pool;
+pub mod known_peers;
+pub mod message_codec;
+pub mod message_handler;
+pub mod node_record;
+pub mod peer_table;
+pub mod ping;
+pub mod priority;
+pub mod request_queue;
 pub mod request_response_handler;
+pub mod response_router;
+pub mod seen_cache;
 pub mod server;
+pub mod sync_pipeline;
+pub mod sync_stream;
+pub mod tx_announce;
+pub mod wire_codec;
 pub use behaviour::P2PBehaviour;
 pub use commands::{GossipMessageType, P2PServerCommand};
+pub use config::{P2PConfig, P2PLimits};
 pub use server::P2PServer;
@@ -0,0 +1,133 @@
+use libp2p::{core::SignedEnvelope, identity::Keypair, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::node::rlp_encoding::{decode, encode, RlpCodec};
+
+/// What the envelope signature covers besides the record, so a record can't be passed
+/// off as another kind of libp2p signed payload, or one as a record.
+const DOMAIN: &str = "clutch-node-record";
+const PAYLOAD_TYPE: &[u8] = b"/clutch/node-record/1";
+/// Most addresses a record may list.
+pub const MAX_RECORD_ADDRESSES: usize = 16;
+
+/// Where and how a node can be reached, signed with its libp2p identity key (ENR-style)
+/// and carried in the handshake. A record only verifies against the peer id of the key
+/// that signed it, so addresses learned from one can't point us at someone else's node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpCodec)]
+pub struct NodeRecord {
+    /// When the record was signed; a newer record replaces an older one.
+    pub seq: u64,
+    pub chain_id: String,
+    /// Multiaddrs, ports included, the node listens on or advertises.
+    #[rlp(list)]
+    pub addresses: Vec<String>,
+    /// The handshake capability list (`PeerCapabilities::advertise`).
+    #[rlp(list)]
+    pub capabilities: Vec<String>,
+}
+
+impl NodeRecord {
+    /// The record as a libp2p signed envelope, protobuf encoded.
+    pub fn sign(&self, key: &Keypair) -> Result<Vec<u8>, String> {
+        SignedEnvelope::new(key, DOMAIN.to_string(), PAYLOAD_TYPE.to_vec(), encode(self))
+            .map(SignedEnvelope::into_protobuf_encoding)
+            .map_err(|e| format!("Failed to sign node record: {}", e))
+    }
+
+    /// The record in `signed`, once it is signed by `peer_id`'s key, is for our
+    /// `chain_id`, and lists only valid addresses.
+    pub fn verify(signed: &[u8], peer_id: &PeerId, chain_id: &str) -> Result<Self, String> {
+        let envelope = SignedEnvelope::from_protobuf_encoding(signed)
+            .map_err(|e| format!("Malformed node record: {}", e))?;
+        let (payload, key) = envelope
+            .payload_and_signing_key(DOMAIN.to_string(), PAYLOAD_TYPE)
+            .map_err(|e| format!("Invalid node record: {}", e))?;
+        let signer = key.to_peer_id();
+        if signer != *peer_id {
+            return Err(format!("Node record signed by {} instead", signer));
+        }
+        let record: NodeRecord =
+            decode(payload).map_err(|e| format!("Malformed node record: {:?}", e))?;
+        if record.chain_id != chain_id {
+            return Err(format!("Node record is for chain {}", record.chain_id));
+        }
+        if record.addresses.len() > MAX_RECORD_ADDRESSES {
+            return Err(format!(
+                "Node record lists {} addresses, more than {}",
+                record.addresses.len(),
+                MAX_RECORD_ADDRESSES
+            ));
+        }
+        record.multiaddrs()?;
+        Ok(record)
+    }
+
+    pub fn multiaddrs(&self) -> Result<Vec<Multiaddr>, String> {
+        self.addresses
+            .iter()
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|e| format!("Invalid address {:?} in node record: {}", address, e))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> NodeRecord {
+        NodeRecord {
+            seq: 1_700_000_000,
+            chain_id: "clutch-devnet".to_string(),
+            addresses: vec!["/ip4/10.0.0.1/tcp/4001".to_string()],
+            capabilities: vec!["rlp".to_string(), "serves-bodies".to_string()],
+        }
+    }
+
+    #[test]
+    fn records_verify_against_their_signer_only() {
+        let key = Keypair::generate_ed25519();
+        let signed = record().sign(&key).unwrap();
+        let peer_id = key.public().to_peer_id();
+
+        assert_eq!(
+            NodeRecord::verify(&signed, &peer_id, "clutch-devnet"),
+            Ok(record())
+        );
+        assert!(NodeRecord::verify(&signed, &PeerId::random(), "clutch-devnet").is_err());
+        assert!(NodeRecord::verify(&signed, &peer_id, "clutch-mainnet").is_err());
+    }
+
+    #[test]
+    fn tampered_records_are_rejected() {
+        let key = Keypair::generate_ed25519();
+        let peer_id = key.public().to_peer_id();
+        let signed = record().sign(&key).unwrap();
+
+        let mut redirected = record();
+        redirected.addresses = vec!["/ip4/6.6.6.6/tcp/4001".to_string()];
+        let forged = redirected.sign(&Keypair::generate_ed25519()).unwrap();
+        assert!(NodeRecord::verify(&forged, &peer_id, "clutch-devnet").is_err());
+
+        let mut flipped = signed.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0x01;
+        assert!(NodeRecord::verify(&flipped, &peer_id, "clutch-devnet").is_err());
+        assert!(
+            NodeRecord::verify(&signed[..signed.len() / 2], &peer_id, "clutch-devnet").is_err()
+        );
+    }
+
+    #[test]
+    fn records_with_invalid_addresses_are_rejected() {
+        let key = Keypair::generate_ed25519();
+        let peer_id = key.public().to_peer_id();
+        let mut bad = record();
+        bad.addresses.push("not an address".to_string());
+        let signed = bad.sign(&key).unwrap();
+        assert!(NodeRecord::verify(&signed, &peer_id, "clutch-devnet").is_err());
+    }
+}
@@ -0,0 +1,814 @@
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use super::ancestor_search::AncestorSearch;
+use super::branch_download::BranchDownload;
+use super::capability::PeerCapabilities;
+use super::commands::DirectMessageType;
+use super::disconnect::DisconnectReason;
+use super::handshake::Handshake;
+use super::message_handler::LEGACY_DIRECT_MESSAGE_VERSION;
+use super::node_record::NodeRecord;
+use super::wire_codec::WireFormat;
+use crate::node::time_utils::get_current_timespan;
+
+/// How many recent disconnects (sent or received) are kept for the peers API.
+const RECENT_DISCONNECTS: usize = 32;
+
+/// Assumed for peers we haven't measured yet, so fresh peers still get picked sometimes
+/// instead of losing to any peer with a measurement.
+const DEFAULT_LATENCY: Duration = Duration::from_millis(250);
+const DEFAULT_BLOCKS_PER_SEC: f64 = 20.0;
+/// Weight of the newest sample in the body-throughput moving average.
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+
+/// The chain head a peer last advertised, from its handshake or a block it relayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerHead {
+    pub index: usize,
+    pub hash: String,
+    /// Chain length. Aura has no difficulty, so the heavier chain is the longer one.
+    pub weight: u64,
+    pub updated_at: Instant,
+}
+
+impl PeerHead {
+    pub fn new(index: usize, hash: String, now: Instant) -> Self {
+        PeerHead {
+            index,
+            hash,
+            weight: index as u64 + 1,
+            updated_at: now,
+        }
+    }
+}
+
+/// What this node knows about a connected peer.
+#[derive(Debug, Clone)]
+pub struct PeerEntry {
+    pub connected_at: Instant,
+    /// Set once the peer has exchanged a handshake with a matching genesis block.
+    pub handshaked_at: Option<Instant>,
+    /// Kept current from handshakes and relayed blocks; `None` until the first handshake.
+    pub head: Option<PeerHead>,
+    /// Set when we have sent a `Disconnect` and are waiting for it to be delivered.
+    pub disconnecting: Option<(DisconnectReason, Instant)>,
+    /// Nonce and send time of the ping we are still waiting a pong for.
+    pub pending_ping: Option<(u64, Instant)>,
+    pub missed_pings: u32,
+    /// Round-trip time of the most recently answered ping.
+    pub latency: Option<Duration>,
+    /// When our outstanding `GetBlockBodies` to this peer was sent.
+    pub bodies_requested_at: Option<Instant>,
+    /// Moving average of blocks per second delivered in `BlockBodies` responses.
+    pub blocks_per_sec: Option<f64>,
+    /// When we last sent the peer a header or body request.
+    pub sync_requested_at: Option<Instant>,
+    /// When a sync run last stalled waiting on this peer. Such peers are picked last.
+    pub sync_stalled_at: Option<Instant>,
+    /// Format our requests to this peer are encoded in, negotiated from its handshake.
+    pub wire_format: WireFormat,
+    /// Direct message protocol version its messages are handled under, negotiated from
+    /// its handshake.
+    pub protocol_version: u32,
+    /// What the peer serves, from its handshake.
+    pub capabilities: PeerCapabilities,
+    /// Address we dialed the peer on; `None` for inbound connections.
+    pub dialed_address: Option<Multiaddr>,
+    /// The peer's verified `NodeRecord`, from its handshake.
+    pub node_record: Option<NodeRecord>,
+    /// IP the connection came from or went to, which a ban applies to.
+    pub remote_ip: Option<IpAddr>,
+    /// Set while we are looking for the block our chain forked from this peer's.
+    pub ancestor_search: Option<AncestorSearch>,
+    /// The peer's branch, being downloaded once the ancestor search found where we forked.
+    pub branch_download: Option<BranchDownload>,
+    /// Requests from this peer whose handler panicked.
+    pub panicked_requests: u32,
+}
+
+impl PeerEntry {
+    /// Higher is better: expected delivery rate discounted by round-trip time.
+    pub fn sync_score(&self) -> f64 {
+        let latency = self.latency.unwrap_or(DEFAULT_LATENCY).as_secs_f64();
+        let throughput = self.blocks_per_sec.unwrap_or(DEFAULT_BLOCKS_PER_SEC);
+        throughput / (1.0 + latency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DisconnectRecord {
+    pub peer_id: String,
+    pub reason: DisconnectReason,
+    pub direction: DisconnectDirection,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerSummary {
+    pub peer_id: String,
+    pub connected_secs: u64,
+    pub handshaked: bool,
+    pub latest_block_index: Option<usize>,
+    pub latest_block_hash: Option<String>,
+    pub head_weight: Option<u64>,
+    /// Seconds since the head was last advertised.
+    pub head_updated_secs: Option<u64>,
+    pub latency_ms: Option<u64>,
+    pub blocks_per_sec: Option<f64>,
+    pub wire_format: &'static str,
+    pub protocol_version: u32,
+    pub capabilities: Vec<String>,
+    /// Addresses listed in the peer's verified `NodeRecord`.
+    pub record_addresses: Vec<String>,
+}
+
+/// Pings to send this round, and peers that have now missed too many in a row.
+#[derive(Debug, Default)]
+pub struct PingRound {
+    pub pings: Vec<(PeerId, u64)>,
+    pub unresponsive: Vec<PeerId>,
+}
+
+/// Serializable view of the peer table, returned by `P2PServerCommand::GetPeers`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeersSnapshot {
+    pub peers: Vec<PeerSummary>,
+    pub recent_disconnects: Vec<DisconnectRecord>,
+}
+
+/// Connected peers keyed by `PeerId`. Only the swarm task mutates it.
+#[derive(Debug, Default)]
+pub struct PeerTable {
+    peers: HashMap<PeerId, PeerEntry>,
+    recent_disconnects: VecDeque<DisconnectRecord>,
+    next_ping_nonce: u64,
+}
+
+impl PeerTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_connected(&mut self, peer_id: PeerId, now: Instant) {
+        self.peers.entry(peer_id).or_insert(PeerEntry {
+            connected_at: now,
+            handshaked_at: None,
+            head: None,
+            disconnecting: None,
+            pending_ping: None,
+            missed_pings: 0,
+            latency: None,
+            bodies_requested_at: None,
+            blocks_per_sec: None,
+            sync_requested_at: None,
+            sync_stalled_at: None,
+            wire_format: WireFormat::default(),
+            protocol_version: LEGACY_DIRECT_MESSAGE_VERSION,
+            capabilities: PeerCapabilities::legacy(),
+            dialed_address: None,
+            remote_ip: None,
+            ancestor_search: None,
+            branch_download: None,
+            panicked_requests: 0,
+            node_record: None,
+        });
+    }
+
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    pub fn mark_handshaked(&mut self, peer_id: &PeerId, handshake: &Handshake, now: Instant) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.handshaked_at.get_or_insert(now);
+            entry.head = Some(PeerHead::new(
+                handshake.latest_block_index,
+                handshake.latest_block_hash.clone(),
+                now,
+            ));
+        }
+    }
+
+    /// Records that a handshaked peer has block `index`, which moves its head forward
+    /// when the block is past it. Returns whether the head moved.
+    pub fn on_new_block(
+        &mut self,
+        peer_id: &PeerId,
+        index: usize,
+        hash: &str,
+        now: Instant,
+    ) -> bool {
+        let Some(entry) = self.peers.get_mut(peer_id) else {
+            return false;
+        };
+        match &entry.head {
+            Some(head) if head.index < index => {
+                entry.head = Some(PeerHead::new(index, hash.to_string(), now));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The heaviest head advertised by a handshaked peer we aren't disconnecting.
+    pub fn best_head(&self) -> Option<(PeerId, &PeerHead)> {
+        self.peers
+            .iter()
+            .filter(|(_, entry)| entry.handshaked_at.is_some() && entry.disconnecting.is_none())
+            .filter_map(|(peer_id, entry)| Some((*peer_id, entry.head.as_ref()?)))
+            .max_by_key(|(_, head)| head.weight)
+    }
+
+    pub fn set_wire_format(&mut self, peer_id: &PeerId, format: WireFormat) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.wire_format = format;
+        }
+    }
+
+    pub fn set_protocol_version(&mut self, peer_id: &PeerId, version: u32) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.protocol_version = version;
+        }
+    }
+
+    pub fn set_capabilities(&mut self, peer_id: &PeerId, capabilities: PeerCapabilities) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.capabilities = capabilities;
+        }
+    }
+
+    /// Whether `peer_id` is connected and answers `request`.
+    pub fn serves(&self, peer_id: &PeerId, request: DirectMessageType) -> bool {
+        self.peers
+            .get(peer_id)
+            .is_some_and(|entry| entry.capabilities.serves(request))
+    }
+
+    /// Whether `peer_id` is connected and answers block ranges on `/clutch/sync/1`.
+    pub fn serves_sync_stream(&self, peer_id: &PeerId) -> bool {
+        self.peers.get(peer_id).is_some_and(|entry| {
+            entry.capabilities.serves_sync_stream && entry.capabilities.serves_bodies
+        })
+    }
+
+    /// RLP until the peer's handshake says otherwise.
+    pub fn wire_format(&self, peer_id: &PeerId) -> WireFormat {
+        self.peers
+            .get(peer_id)
+            .map(|entry| entry.wire_format)
+            .unwrap_or_default()
+    }
+
+    /// The legacy version until the peer's handshake says otherwise.
+    pub fn protocol_version(&self, peer_id: &PeerId) -> u32 {
+        self.peers
+            .get(peer_id)
+            .map(|entry| entry.protocol_version)
+            .unwrap_or(LEGACY_DIRECT_MESSAGE_VERSION)
+    }
+
+    pub fn set_dialed_address(&mut self, peer_id: &PeerId, address: Multiaddr) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.dialed_address = Some(address);
+        }
+    }
+
+    /// Keeps `record` unless we already hold a newer one.
+    pub fn set_node_record(&mut self, peer_id: &PeerId, record: NodeRecord) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            if entry
+                .node_record
+                .as_ref()
+                .is_none_or(|known| known.seq <= record.seq)
+            {
+                entry.node_record = Some(record);
+            }
+        }
+    }
+
+    pub fn set_remote_ip(&mut self, peer_id: &PeerId, ip: Option<IpAddr>) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.remote_ip = ip;
+        }
+    }
+
+    pub fn ancestor_search(&self, peer_id: &PeerId) -> Option<AncestorSearch> {
+        self.peers.get(peer_id)?.ancestor_search
+    }
+
+    pub fn set_ancestor_search(&mut self, peer_id: &PeerId, search: Option<AncestorSearch>) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.ancestor_search = search;
+        }
+    }
+
+    pub fn is_downloading_branch(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .is_some_and(|entry| entry.branch_download.is_some())
+    }
+
+    /// Takes the branch being downloaded from `peer_id`; `set_branch_download` puts it back
+    /// while more of it is to come.
+    pub fn take_branch_download(&mut self, peer_id: &PeerId) -> Option<BranchDownload> {
+        self.peers.get_mut(peer_id)?.branch_download.take()
+    }
+
+    pub fn set_branch_download(&mut self, peer_id: &PeerId, download: BranchDownload) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.branch_download = Some(download);
+        }
+    }
+
+    pub fn is_handshaked(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .is_some_and(|entry| entry.handshaked_at.is_some())
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&PeerEntry> {
+        self.peers.get(peer_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &PeerEntry)> {
+        self.peers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Peers that have been connected for at least `timeout` without completing a handshake.
+    pub fn handshake_expired(&self, now: Instant, timeout: Duration) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, entry)| {
+                entry.handshaked_at.is_none()
+                    && entry.disconnecting.is_none()
+                    && now.duration_since(entry.connected_at) >= timeout
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Records that we sent `Disconnect { reason }` to `peer_id`. Returns false if a
+    /// disconnect is already in flight, so callers don't send a second one.
+    pub fn mark_disconnecting(
+        &mut self,
+        peer_id: &PeerId,
+        reason: DisconnectReason,
+        now: Instant,
+    ) -> bool {
+        let Some(entry) = self.peers.get_mut(peer_id) else {
+            return false;
+        };
+        if entry.disconnecting.is_some() {
+            return false;
+        }
+        entry.disconnecting = Some((reason, now));
+        self.record_disconnect(peer_id, reason, DisconnectDirection::Sent);
+        true
+    }
+
+    /// Peers we sent a `Disconnect` to more than `grace` ago that are still connected.
+    pub fn disconnect_overdue(&self, now: Instant, grace: Duration) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .disconnecting
+                    .is_some_and(|(_, since)| now.duration_since(since) >= grace)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Starts a ping round over handshaked peers. A ping still unanswered from the previous
+    /// round counts as missed; peers reaching `max_missed` are reported instead of pinged.
+    pub fn ping_round(&mut self, now: Instant, max_missed: u32) -> PingRound {
+        let mut round = PingRound::default();
+
+        for (peer_id, entry) in self.peers.iter_mut() {
+            if entry.handshaked_at.is_none() || entry.disconnecting.is_some() {
+                continue;
+            }
+            if entry.pending_ping.take().is_some() {
+                entry.missed_pings += 1;
+            }
+            if entry.missed_pings >= max_missed {
+                round.unresponsive.push(*peer_id);
+                continue;
+            }
+
+            self.next_ping_nonce = self.next_ping_nonce.wrapping_add(1);
+            entry.pending_ping = Some((self.next_ping_nonce, now));
+            round.pings.push((*peer_id, self.next_ping_nonce));
+        }
+
+        round
+    }
+
+    /// Matches a pong against the outstanding ping and returns the measured round-trip time.
+    /// Pongs for an older or unknown nonce are ignored.
+    pub fn on_pong(&mut self, peer_id: &PeerId, nonce: u64, now: Instant) -> Option<Duration> {
+        let entry = self.peers.get_mut(peer_id)?;
+        match entry.pending_ping {
+            Some((pending_nonce, sent_at)) if pending_nonce == nonce => {
+                let rtt = now.duration_since(sent_at);
+                entry.pending_ping = None;
+                entry.missed_pings = 0;
+                entry.latency = Some(rtt);
+                Some(rtt)
+            }
+            _ => None,
+        }
+    }
+
+    /// Counts a request from the peer whose handler panicked; returns how many it has sent.
+    pub fn on_request_panicked(&mut self, peer_id: &PeerId) -> u32 {
+        match self.peers.get_mut(peer_id) {
+            Some(entry) => {
+                entry.panicked_requests += 1;
+                entry.panicked_requests
+            }
+            None => 0,
+        }
+    }
+
+    pub fn on_sync_requested(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.sync_requested_at = Some(now);
+        }
+    }
+
+    /// Marks the peers a stalled sync run was waiting on: those asked for headers or bodies
+    /// since `since`, its last progress, and those `waiting` says still owe a response.
+    /// Returns them.
+    pub fn mark_sync_stalled(
+        &mut self,
+        since: Instant,
+        waiting: impl Fn(&PeerId) -> bool,
+        now: Instant,
+    ) -> Vec<PeerId> {
+        let mut stalled = Vec::new();
+        for (peer_id, entry) in self.peers.iter_mut() {
+            if waiting(peer_id) || entry.sync_requested_at.is_some_and(|at| at >= since) {
+                entry.sync_stalled_at = Some(now);
+                stalled.push(*peer_id);
+            }
+        }
+        stalled
+    }
+
+    pub fn on_bodies_requested(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.bodies_requested_at = Some(now);
+        }
+    }
+
+    /// Folds a `BlockBodies` delivery of `blocks` into the peer's throughput average.
+    pub fn on_bodies_received(&mut self, peer_id: &PeerId, blocks: usize, now: Instant) {
+        let Some(entry) = self.peers.get_mut(peer_id) else {
+            return;
+        };
+        let Some(requested_at) = entry.bodies_requested_at.take() else {
+            return;
+        };
+
+        let elapsed = now.duration_since(requested_at).as_secs_f64().max(0.001);
+        let sample = blocks as f64 / elapsed;
+        entry.blocks_per_sec = Some(match entry.blocks_per_sec {
+            Some(average) => average + THROUGHPUT_SMOOTHING * (sample - average),
+            None => sample,
+        });
+    }
+
+    /// Highest-scoring handshaked peer at `min_height` for which `available` holds. Peers a
+    /// sync stalled on come last, the least recently stalled first, so repeated stalls
+    /// rotate through them.
+    pub fn select_sync_peer(
+        &self,
+        min_height: usize,
+        request: DirectMessageType,
+        available: impl Fn(&PeerId) -> bool,
+    ) -> Option<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(peer_id, entry)| {
+                available(peer_id)
+                    && entry.capabilities.serves(request)
+                    && entry.handshaked_at.is_some()
+                    && entry.disconnecting.is_none()
+                    && entry
+                        .head
+                        .as_ref()
+                        .is_some_and(|head| head.index >= min_height)
+            })
+            .max_by(|(_, a), (_, b)| {
+                b.sync_stalled_at
+                    .cmp(&a.sync_stalled_at)
+                    .then(a.sync_score().total_cmp(&b.sync_score()))
+            })
+            .map(|(peer_id, _)| *peer_id)
+    }
+
+    pub fn record_disconnect(
+        &mut self,
+        peer_id: &PeerId,
+        reason: DisconnectReason,
+        direction: DisconnectDirection,
+    ) {
+        if self.recent_disconnects.len() == RECENT_DISCONNECTS {
+            self.recent_disconnects.pop_front();
+        }
+        self.recent_disconnects.push_back(DisconnectRecord {
+            peer_id: peer_id.to_string(),
+            reason,
+            direction,
+            timestamp: get_current_timespan(),
+        });
+    }
+
+    pub fn snapshot(&self, now: Instant) -> PeersSnapshot {
+        let mut peers: Vec<PeerSummary> = self
+            .peers
+            .iter()
+            .map(|(peer_id, entry)| PeerSummary {
+                peer_id: peer_id.to_string(),
+                connected_secs: now.duration_since(entry.connected_at).as_secs(),
+                handshaked: entry.handshaked_at.is_some(),
+                latest_block_index: entry.head.as_ref().map(|head| head.index),
+                latest_block_hash: entry.head.as_ref().map(|head| head.hash.clone()),
+                head_weight: entry.head.as_ref().map(|head| head.weight),
+                head_updated_secs: entry
+                    .head
+                    .as_ref()
+                    .map(|head| now.saturating_duration_since(head.updated_at).as_secs()),
+                latency_ms: entry.latency.map(|latency| latency.as_millis() as u64),
+                blocks_per_sec: entry.blocks_per_sec,
+                wire_format: entry.wire_format.name(),
+                protocol_version: entry.protocol_version,
+                capabilities: entry.capabilities.names(),
+                record_addresses: entry
+                    .node_record
+                    .as_ref()
+                    .map(|record| record.addresses.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+        PeersSnapshot {
+            peers,
+            recent_disconnects: self.recent_disconnects.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake() -> Handshake {
+        Handshake {
+            genesis_block_hash: "genesis".to_string(),
+            latest_block_hash: "latest".to_string(),
+            latest_block_index: 7,
+            capabilities: Vec::new(),
+            hardforks: Vec::new(),
+            node_record: Vec::new(),
+            protocol_version: 0,
+        }
+    }
+
+    #[test]
+    fn only_unhandshaked_peers_past_timeout_expire() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let slow = PeerId::random();
+        let done = PeerId::random();
+        let fresh = PeerId::random();
+
+        let mut table = PeerTable::new();
+        table.on_connected(slow, start);
+        table.on_connected(done, start);
+        table.on_connected(fresh, start + Duration::from_secs(20));
+        table.mark_handshaked(&done, &handshake(), start + Duration::from_secs(1));
+
+        let expired = table.handshake_expired(start + timeout, timeout);
+        assert_eq!(expired, vec![slow]);
+        assert!(table.is_handshaked(&done));
+        assert_eq!(table.get(&done).unwrap().head.as_ref().unwrap().index, 7);
+    }
+
+    #[test]
+    fn handshake_from_unknown_peer_is_ignored() {
+        let peer = PeerId::random();
+        let mut table = PeerTable::new();
+        table.mark_handshaked(&peer, &handshake(), Instant::now());
+
+        assert!(!table.is_handshaked(&peer));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn peers_are_legacy_until_their_handshake_negotiates_a_version() {
+        let peer = PeerId::random();
+        let mut table = PeerTable::new();
+        assert_eq!(table.protocol_version(&peer), LEGACY_DIRECT_MESSAGE_VERSION);
+        table.on_connected(peer, Instant::now());
+        assert_eq!(table.protocol_version(&peer), LEGACY_DIRECT_MESSAGE_VERSION);
+
+        table.set_protocol_version(&peer, 2);
+        assert_eq!(table.protocol_version(&peer), 2);
+        assert_eq!(table.snapshot(Instant::now()).peers[0].protocol_version, 2);
+    }
+
+    #[test]
+    fn disconnect_is_sent_once_and_becomes_overdue_after_grace() {
+        let start = Instant::now();
+        let grace = Duration::from_secs(2);
+        let peer = PeerId::random();
+
+        let mut table = PeerTable::new();
+        table.on_connected(peer, start);
+        assert!(table.mark_disconnecting(&peer, DisconnectReason::TooManyPeers, start));
+        assert!(!table.mark_disconnecting(&peer, DisconnectReason::ShuttingDown, start));
+
+        assert!(table
+            .disconnect_overdue(start + Duration::from_secs(1), grace)
+            .is_empty());
+        assert_eq!(table.disconnect_overdue(start + grace, grace), vec![peer]);
+
+        let snapshot = table.snapshot(start);
+        assert_eq!(snapshot.recent_disconnects.len(), 1);
+        assert_eq!(
+            snapshot.recent_disconnects[0].reason,
+            DisconnectReason::TooManyPeers
+        );
+        assert_eq!(
+            snapshot.recent_disconnects[0].direction,
+            DisconnectDirection::Sent
+        );
+    }
+
+    #[test]
+    fn missed_pings_accumulate_until_peer_is_unresponsive() {
+        let start = Instant::now();
+        let peer = PeerId::random();
+        let mut table = PeerTable::new();
+        table.on_connected(peer, start);
+        table.mark_handshaked(&peer, &handshake(), start);
+
+        let round = table.ping_round(start, 2);
+        let (_, first_nonce) = round.pings[0];
+        assert!(table.ping_round(start, 2).unresponsive.is_empty());
+        // The first ping has been superseded, so its late pong is not a measurement.
+        assert_eq!(table.on_pong(&peer, first_nonce, start), None);
+
+        let round = table.ping_round(start, 2);
+        assert_eq!(round.unresponsive, vec![peer]);
+        assert!(round.pings.is_empty());
+    }
+
+    #[test]
+    fn pong_records_latency_and_resets_missed_count() {
+        let start = Instant::now();
+        let peer = PeerId::random();
+        let mut table = PeerTable::new();
+        table.on_connected(peer, start);
+        table.mark_handshaked(&peer, &handshake(), start);
+
+        table.ping_round(start, 3);
+        let (_, nonce) = table.ping_round(start, 3).pings[0];
+        let rtt = table.on_pong(&peer, nonce, start + Duration::from_millis(40));
+
+        assert_eq!(rtt, Some(Duration::from_millis(40)));
+        assert_eq!(table.get(&peer).unwrap().missed_pings, 0);
+        assert_eq!(table.snapshot(start).peers[0].latency_ms, Some(40));
+    }
+
+    #[test]
+    fn sync_peer_prefers_fast_low_latency_peers_at_required_height() {
+        let start = Instant::now();
+        let slow = PeerId::random();
+        let fast = PeerId::random();
+        let behind = PeerId::random();
+
+        let mut table = PeerTable::new();
+        for peer in [slow, fast, behind] {
+            table.on_connected(peer, start);
+        }
+        table.mark_handshaked(&slow, &handshake(), start);
+        table.mark_handshaked(&fast, &handshake(), start);
+        let mut behind_handshake = handshake();
+        behind_handshake.latest_block_index = 3;
+        table.mark_handshaked(&behind, &behind_handshake, start);
+
+        table.on_bodies_requested(&slow, start);
+        table.on_bodies_received(&slow, 10, start + Duration::from_secs(5));
+        table.on_bodies_requested(&fast, start);
+        table.on_bodies_received(&fast, 100, start + Duration::from_secs(1));
+        table.on_bodies_requested(&behind, start);
+        table.on_bodies_received(&behind, 1000, start + Duration::from_secs(1));
+
+        let bodies = DirectMessageType::GetBlockBodies;
+        assert_eq!(table.select_sync_peer(5, bodies, |_| true), Some(fast));
+        assert_eq!(
+            table.select_sync_peer(5, bodies, |peer| *peer != fast),
+            Some(slow)
+        );
+        assert_eq!(table.select_sync_peer(8, bodies, |_| true), None);
+
+        // A peer that doesn't serve bodies is never asked for them.
+        table.set_capabilities(&fast, PeerCapabilities::light());
+        assert_eq!(table.select_sync_peer(5, bodies, |_| true), Some(slow));
+        assert!(!table.serves(&fast, DirectMessageType::GetBlockHeaders));
+    }
+
+    #[test]
+    fn relayed_blocks_move_peer_heads_forward() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(4);
+        let ahead = PeerId::random();
+        let behind = PeerId::random();
+        let stranger = PeerId::random();
+
+        let mut table = PeerTable::new();
+        for peer in [ahead, behind] {
+            table.on_connected(peer, start);
+        }
+        table.mark_handshaked(&ahead, &handshake(), start);
+        let mut behind_handshake = handshake();
+        behind_handshake.latest_block_index = 3;
+        table.mark_handshaked(&behind, &behind_handshake, start);
+        assert_eq!(table.best_head().map(|(peer, _)| peer), Some(ahead));
+
+        assert!(table.on_new_block(&behind, 9, "h9", later));
+        // Stale relays and peers we haven't handshaked leave heads alone.
+        assert!(!table.on_new_block(&behind, 8, "h8", later));
+        assert!(!table.on_new_block(&stranger, 20, "h20", later));
+
+        let (best, head) = table.best_head().unwrap();
+        assert_eq!(best, behind);
+        assert_eq!((head.index, head.hash.as_str(), head.weight), (9, "h9", 10));
+        assert_eq!(
+            table.select_sync_peer(8, DirectMessageType::GetBlockHeaders, |_| true),
+            Some(behind)
+        );
+
+        let summary = table
+            .snapshot(later + Duration::from_secs(1))
+            .peers
+            .into_iter()
+            .find(|summary| summary.peer_id == behind.to_string())
+            .unwrap();
+        assert_eq!(summary.latest_block_index, Some(9));
+        assert_eq!(summary.head_weight, Some(10));
+        assert_eq!(summary.head_updated_secs, Some(1));
+    }
+
+    #[test]
+    fn sync_rotates_away_from_peers_it_stalled_on() {
+        let start = Instant::now();
+        let first = PeerId::random();
+        let second = PeerId::random();
+        let headers = DirectMessageType::GetBlockHeaders;
+
+        let mut table = PeerTable::new();
+        for peer in [first, second] {
+            table.on_connected(peer, start);
+            table.mark_handshaked(&peer, &handshake(), start);
+        }
+        table.on_bodies_requested(&first, start);
+        table.on_bodies_received(&first, 100, start + Duration::from_secs(1));
+        assert_eq!(table.select_sync_peer(5, headers, |_| true), Some(first));
+
+        let stall = start + Duration::from_secs(30);
+        table.on_sync_requested(&first, start + Duration::from_secs(2));
+        let stalled = table.mark_sync_stalled(start + Duration::from_secs(1), |_| false, stall);
+        assert_eq!(stalled, vec![first]);
+        assert_eq!(table.select_sync_peer(5, headers, |_| true), Some(second));
+
+        // Once every peer has stalled, the one that stalled longest ago gets another go.
+        let later = stall + Duration::from_secs(30);
+        let stalled = table.mark_sync_stalled(later, |peer| *peer == second, later);
+        assert_eq!(stalled, vec![second]);
+        assert_eq!(table.select_sync_peer(5, headers, |_| true), Some(first));
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+use crate::node::rlp_encoding::RlpCodec;
+
+/// Keepalive probe sent periodically to every handshaked peer. The peer echoes `nonce`
+/// back in a `Pong`, which lets us measure round-trip time per peer.
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct Ping {
+    pub nonce: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct Pong {
+    pub nonce: u64,
+}
@@ -0,0 +1,79 @@
+use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
+
+use super::behaviour::{DirectMessageRequest, DirectMessageResponse};
+use super::commands::DirectMessageType;
+use super::wire_codec::decode_frame;
+
+/// Scheduling class of a direct message, most urgent first. Gossip (blocks, transactions)
+/// is always handled as it arrives; among direct messages, what it takes to rebuild a new
+/// block goes ahead of everything else, and bulk sync traffic waits until nothing more
+/// urgent is ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Consensus,
+    Control,
+    Bulk,
+}
+
+impl Priority {
+    pub fn of_direct(message_type: DirectMessageType) -> Self {
+        match message_type {
+            DirectMessageType::GetBlockTransactions | DirectMessageType::BlockTransactions => {
+                Priority::Consensus
+            }
+            DirectMessageType::GetBlockHeaders
+            | DirectMessageType::BlockHeaders
+            | DirectMessageType::GetBlockBodies
+            | DirectMessageType::BlockBodies => Priority::Bulk,
+            DirectMessageType::Handshake
+            | DirectMessageType::Disconnect
+            | DirectMessageType::Ping
+            | DirectMessageType::Pong
+            | DirectMessageType::GetPooledTransactions
+            | DirectMessageType::PooledTransactions => Priority::Control,
+        }
+    }
+
+    /// Class of a request-response event, by the direct message it carries. Failures and
+    /// acknowledgements are `Control`: they free request slots and are cheap to handle.
+    pub fn of_event(
+        event: &RequestResponseEvent<DirectMessageRequest, DirectMessageResponse>,
+    ) -> Self {
+        let frame = match event {
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { request, .. },
+                ..
+            } => &request.message,
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { response, .. },
+                ..
+            } => &response.message,
+            _ => return Priority::Control,
+        };
+        match decode_frame(frame) {
+            Some((Some(message_type), _, _)) => Priority::of_direct(message_type),
+            _ => Priority::Control,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_propagation_outranks_sync_traffic() {
+        assert!(
+            Priority::of_direct(DirectMessageType::GetBlockTransactions)
+                < Priority::of_direct(DirectMessageType::Ping)
+        );
+        assert!(
+            Priority::of_direct(DirectMessageType::BlockTransactions)
+                < Priority::of_direct(DirectMessageType::BlockBodies)
+        );
+        assert_eq!(
+            Priority::of_direct(DirectMessageType::GetBlockHeaders),
+            Priority::Bulk
+        );
+    }
+}
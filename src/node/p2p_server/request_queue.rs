@@ -0,0 +1,152 @@
+use libp2p::{request_response::OutboundRequestId, PeerId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Outcome of handing a sync request to the queue.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Enqueued {
+    /// The peer has a free slot; the caller should send the message now and report the
+    /// request id via `on_sent`.
+    SendNow(Vec<u8>),
+    /// Waiting behind the peer's in-flight requests.
+    Queued,
+    /// The peer's queue is full and the message was dropped.
+    Rejected,
+}
+
+#[derive(Debug)]
+struct PeerRequests<R> {
+    in_flight: HashSet<R>,
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl<R> Default for PeerRequests<R> {
+    fn default() -> Self {
+        Self {
+            in_flight: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// Per-peer cap on outstanding sync requests (`GetBlockHeaders`/`GetBlockBodies`). A peer
+/// only gets a new request when an earlier one completes, so slow peers hold their slots
+/// longer and are passed over by sync peer selection in the meantime. Generic over the
+/// request id only so tests don't need a live swarm to mint `OutboundRequestId`s.
+#[derive(Debug)]
+pub struct OutboundQueue<R = OutboundRequestId> {
+    max_in_flight: usize,
+    max_queued: usize,
+    peers: HashMap<PeerId, PeerRequests<R>>,
+}
+
+impl<R: Eq + Hash> OutboundQueue<R> {
+    pub fn new(max_in_flight: usize, max_queued: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            max_queued,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Changes the caps on a running queue. Requests already in flight or queued past a
+    /// lowered cap are kept; new ones wait until the peer is back under it.
+    pub fn set_limits(&mut self, max_in_flight: usize, max_queued: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+        self.max_queued = max_queued;
+    }
+
+    /// Whether `peer_id` can take another request without queueing.
+    pub fn has_capacity(&self, peer_id: &PeerId) -> bool {
+        self.in_flight(peer_id) < self.max_in_flight
+    }
+
+    pub fn in_flight(&self, peer_id: &PeerId) -> usize {
+        self.peers
+            .get(peer_id)
+            .map_or(0, |requests| requests.in_flight.len())
+    }
+
+    pub fn enqueue(&mut self, peer_id: &PeerId, message: Vec<u8>) -> Enqueued {
+        let max_in_flight = self.max_in_flight;
+        let requests = self.peers.entry(*peer_id).or_default();
+
+        if requests.in_flight.len() < max_in_flight && requests.pending.is_empty() {
+            Enqueued::SendNow(message)
+        } else if requests.pending.len() < self.max_queued {
+            requests.pending.push_back(message);
+            Enqueued::Queued
+        } else {
+            Enqueued::Rejected
+        }
+    }
+
+    pub fn on_sent(&mut self, peer_id: &PeerId, request_id: R) {
+        self.peers
+            .entry(*peer_id)
+            .or_default()
+            .in_flight
+            .insert(request_id);
+    }
+
+    /// Frees the slot held by `request_id` (answered or failed) and returns the next queued
+    /// message for that peer, if any. Ids we never tracked (handshakes, pings) are ignored.
+    pub fn on_completed(&mut self, peer_id: &PeerId, request_id: &R) -> Option<Vec<u8>> {
+        let requests = self.peers.get_mut(peer_id)?;
+        if !requests.in_flight.remove(request_id) {
+            return None;
+        }
+        if requests.in_flight.len() < self.max_in_flight {
+            requests.pending.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// Drops everything outstanding for a peer that went away.
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) -> usize {
+        self.peers
+            .remove(peer_id)
+            .map_or(0, |requests| requests.pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_beyond_in_flight_limit_are_queued_then_rejected() {
+        let peer = PeerId::random();
+        let mut queue: OutboundQueue<u64> = OutboundQueue::new(2, 1);
+
+        assert_eq!(queue.enqueue(&peer, vec![1]), Enqueued::SendNow(vec![1]));
+        queue.on_sent(&peer, 1);
+        assert_eq!(queue.enqueue(&peer, vec![2]), Enqueued::SendNow(vec![2]));
+        queue.on_sent(&peer, 2);
+        assert!(!queue.has_capacity(&peer));
+
+        assert_eq!(queue.enqueue(&peer, vec![3]), Enqueued::Queued);
+        assert_eq!(queue.enqueue(&peer, vec![4]), Enqueued::Rejected);
+        assert!(queue.has_capacity(&PeerId::random()));
+    }
+
+    #[test]
+    fn completion_releases_slot_to_next_queued_request() {
+        let peer = PeerId::random();
+        let mut queue: OutboundQueue<u64> = OutboundQueue::new(1, 4);
+
+        assert_eq!(queue.enqueue(&peer, vec![1]), Enqueued::SendNow(vec![1]));
+        queue.on_sent(&peer, 1);
+        assert_eq!(queue.enqueue(&peer, vec![2]), Enqueued::Queued);
+
+        // Untracked ids (handshakes, pings) don't free anything.
+        assert_eq!(queue.on_completed(&peer, &99), None);
+        assert_eq!(queue.on_completed(&peer, &1), Some(vec![2]));
+        assert_eq!(queue.in_flight(&peer), 0);
+        queue.on_sent(&peer, 2);
+
+        assert_eq!(queue.on_disconnected(&peer), 0);
+        assert_eq!(queue.in_flight(&peer), 0);
+    }
+}
@@ -1,42 +1,104 @@
+use super::ancestor_search::{AncestorSearch, AncestorStep};
 use super::behaviour::{DirectMessageRequest, DirectMessageResponse};
+use super::branch_download::{BranchDownload, BranchSwitch};
+use super::capability::PeerCapabilities;
+use super::compact_block::{BlockTransactions, GetBlockTransactions};
 use super::handshake::Handshake;
+use super::context::P2PContext;
+use super::disconnect::{Disconnect, DisconnectReason};
+use super::gossipsub_handler::{
+    handle_received_block, handle_received_transaction, import_future_blocks,
+};
+use super::in_flight::answers;
+use super::message_handler::{
+    negotiate_version, Answer, HandlerRegistry, Inbound, RequestHandler, ResponseHandler,
+    DIRECT_MESSAGE_VERSION, LEGACY_DIRECT_MESSAGE_VERSION,
+};
+use super::node_record::NodeRecord;
+use super::peer_table::DisconnectDirection;
+use super::ping::{Ping, Pong};
+use super::priority::Priority;
+use super::request_queue::Enqueued;
+use super::sync_stream::{serve_range, SyncBlocks, SyncRange, MAX_STREAM_BLOCKS};
+use super::tx_announce::{GetPooledTransactions, PooledTransactions};
+use super::wire_codec::{decode_frame, encode_frame, frame_header, WireFormat};
 use super::P2PBehaviour;
-use crate::node::blockchain::Blockchain;
+use crate::node::blockchain::{Blockchain, BulkImport, ChainReader};
+use crate::node::blocks::block::Block;
 use crate::node::blocks::block_bodies::BlockBodies;
-use crate::node::blocks::block_headers::{BlockHeader, BlockHeaders};
+use crate::node::blocks::block_headers::BlockHeaders;
+use crate::node::disk_monitor;
+use crate::node::error::NodeError;
+use crate::node::metric;
 use crate::node::p2p_server::commands::DirectMessageType;
 use crate::node::p2p_server::get_block_bodies::GetBlockBodies;
 use crate::node::p2p_server::get_block_header::GetBlockHeaders;
-use crate::node::rlp_encoding::{decode, encode};
+use crate::node::rlp_encoding::{decode, decode_list_field, RlpMessage};
+use crate::node::time_utils::get_current_timespan;
+use crate::node::webhook::NodeEvent;
+use async_trait::async_trait;
+use futures::FutureExt;
 use libp2p::request_response::OutboundRequestId;
 use libp2p::{
-    request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage},
+    gossipsub::MessageAcceptance,
+    request_response::{
+        Event as RequestResponseEvent, Message as RequestResponseMessage, ResponseChannel,
+    },
     swarm::Swarm,
     PeerId,
 };
-use rlp::Encodable;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::RwLock;
 
 pub async fn handle_request_response(
     event: RequestResponseEvent<DirectMessageRequest, DirectMessageResponse>,
     swarm: &mut Swarm<P2PBehaviour>,
-    blockchain: &Arc<Mutex<Blockchain>>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
     match event {
         RequestResponseEvent::Message { peer, message, .. } => match message {
+            RequestResponseMessage::Request { request, .. } if !request.is_intact() => {
+                // Dropping the channel fails the request on the peer's side, which can retry.
+                warn!(
+                    "Dropping corrupted direct message request from peer {:?}",
+                    peer
+                );
+            }
             RequestResponseMessage::Request {
                 request_id,
                 request,
                 channel,
             } => {
-                handle_request_message(peer, request_id, request, channel, swarm, blockchain).await
+                let span = request_span("inbound", &peer, request_id, &request.message);
+                handle_request_message(peer, request_id, request, channel, swarm, blockchain, ctx)
+                    .instrument(span)
+                    .await
+            }
+            RequestResponseMessage::Response {
+                request_id,
+                response,
+            } if !response.is_intact() => {
+                warn!(
+                    "Corrupted direct message response from peer {:?} to request_id {:?}",
+                    peer, request_id
+                );
+                fail_outbound_request(&peer, &request_id, "corrupted response", swarm, ctx);
             }
             RequestResponseMessage::Response {
                 request_id,
                 response,
-            } => handle_response_message(peer, request_id, response, swarm, blockchain).await,
+            } => {
+                let span = request_span("outbound", &peer, request_id, &response.message);
+                handle_response_message(peer, request_id, response, swarm, blockchain, ctx)
+                    .instrument(span)
+                    .await
+            }
         },
         RequestResponseEvent::OutboundFailure {
             peer,
@@ -48,6 +110,8 @@ pub async fn handle_request_response(
                 "Failed to send request to peer {:?} with request_id {:?}: {:?}",
                 peer, request_id, outbound_failure
             );
+            let error = outbound_failure.to_string();
+            fail_outbound_request(&peer, &request_id, &error, swarm, ctx);
         }
         RequestResponseEvent::InboundFailure {
             peer,
@@ -66,44 +130,304 @@ pub async fn handle_request_response(
     }
 }
 
+/// Forgets a request that got no usable response, tells a caller awaiting it, and frees
+/// its slot for the peer's next queued sync request.
+fn fail_outbound_request(
+    peer_id: &PeerId,
+    request_id: &OutboundRequestId,
+    error: &str,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    ctx.in_flight.on_failed(request_id);
+    ctx.responses.fail(request_id, error.to_string());
+    abandon_branch_download(peer_id, error, ctx);
+    release_request_slot(peer_id, request_id, swarm, ctx);
+}
+
+/// Handles `/clutch/sync/1`: ranges peers ask for are read on the sync stream pool, and
+/// ranges we asked for go through the same checks and import as a `BlockBodies` response.
+/// A failed range is left to the stall watchdog, which restarts sync on other peers.
+pub async fn handle_sync_stream(
+    event: RequestResponseEvent<SyncRange, SyncBlocks>,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    match event {
+        RequestResponseEvent::Message { peer, message, .. } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => serve_sync_range(peer, request, channel, swarm, ctx),
+            RequestResponseMessage::Response { response, .. } => {
+                handle_sync_blocks(response, &peer, swarm, blockchain, ctx).await
+            }
+        },
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            warn!("Sync stream from peer {:?} failed: {:?}", peer, error);
+        }
+        RequestResponseEvent::InboundFailure { peer, error, .. } => {
+            debug!("Sync stream to peer {:?} failed: {:?}", peer, error);
+        }
+        RequestResponseEvent::ResponseSent { .. } => {}
+    }
+}
+
+fn serve_sync_range(
+    peer: PeerId,
+    range: SyncRange,
+    channel: ResponseChannel<SyncBlocks>,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    if !ctx.peers.is_handshaked(&peer) {
+        warn!(
+            "Refusing sync stream from peer {:?} that has not completed a handshake",
+            peer
+        );
+        send_disconnect(&peer, DisconnectReason::ProtocolViolation, swarm, ctx);
+        return;
+    }
+    let capabilities = &ctx.config.capabilities;
+    if capabilities.light || !capabilities.serves_sync_stream {
+        debug!("Not serving sync stream to peer {:?}", peer);
+        return;
+    }
+    if disk_monitor::safe_mode() {
+        warn!("Disk safe mode; dropping sync stream from peer {:?}", peer);
+        return;
+    }
+    let Some(reader) = ctx.chain_reader.clone() else {
+        error!(
+            "No chain reader available; dropping sync stream from peer {:?}",
+            peer
+        );
+        return;
+    };
+    let job = async move {
+        serve_range(&reader, range).unwrap_or_else(|e| {
+            error!(error_kind = e.kind(), "Failed to serve sync range: {}", e);
+            SyncBlocks::default()
+        })
+    }
+    .instrument(Span::current());
+    if !ctx.sync_streams.spawn(peer, channel, Priority::Bulk, job) {
+        warn!(
+            "Sync stream pool saturated; dropping range from peer {:?}",
+            peer
+        );
+    }
+}
+
+async fn handle_sync_blocks(
+    response: SyncBlocks,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    if response.blocks.is_empty() {
+        error!("Received an empty sync stream from {:?}", peer_id);
+        return;
+    }
+    let blocks = response
+        .blocks
+        .iter()
+        .map(|block| decode::<Block>(block).map_err(|e| format!("{:?}", e)));
+    import_bodies(blocks, MAX_STREAM_BLOCKS, peer_id, swarm, blockchain, ctx).await
+}
+
+/// Span entered while a direct message is handled, including the validation and import
+/// of what it carries and any job it hands to the inbound pool, so one slow request can
+/// be followed through the logs. Inbound and outbound request ids are counted
+/// separately, hence `direction`; an outbound id matches the one logged when it was sent.
+fn request_span(
+    direction: &'static str,
+    peer: &PeerId,
+    request_id: impl fmt::Display,
+    message: &[u8],
+) -> Span {
+    let message_type = match decode_frame(message) {
+        Some((Some(message_type), _, _)) => format!("{:?}", message_type),
+        _ => "unknown".to_string(),
+    };
+    info_span!(
+        "direct_message",
+        direction,
+        peer_id = %peer,
+        request_id = %request_id,
+        message_type = %message_type
+    )
+}
+
 async fn handle_request_message(
     peer: libp2p::PeerId,
     request_id: libp2p::request_response::InboundRequestId,
     request: DirectMessageRequest,
     channel: libp2p::request_response::ResponseChannel<DirectMessageResponse>,
     swarm: &mut Swarm<P2PBehaviour>,
-    blockchain: &Arc<Mutex<Blockchain>>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
     debug!(
         "Send direct message from peer:{:?} with id {:?}",
         peer, request_id,
     );
 
-    if request.message.is_empty() {
+    let Some((message_type, format, payload)) = decode_frame(&request.message) else {
         error!("Received empty direct message from peer {:?}", peer);
         return;
+    };
+    let Some(format) = accepted_format(format, &peer, ctx) else {
+        return;
+    };
+
+    let version = ctx.peers.protocol_version(&peer);
+    let Some((message_type, handler)) = message_type.and_then(|message_type| {
+        let handler = ctx.handlers.request(message_type, version)?;
+        Some((message_type, handler))
+    }) else {
+        error!(
+            "Received unknown DirectMessageType from peer {:?}: {:?}",
+            peer, message_type
+        );
+        return;
+    };
+    // Chain data is only served to peers that proved they share our genesis block.
+    if handler.requires_handshake() && !ctx.peers.is_handshaked(&peer) {
+        warn!(
+            "Refusing {:?} from peer {:?} that has not completed a handshake",
+            message_type, peer
+        );
+        send_disconnect(&peer, DisconnectReason::ProtocolViolation, swarm, ctx);
+        return;
+    }
+    // A light node turns chain-data requests away; dropping the channel fails them on the
+    // peer's side, which advertised capabilities should have prevented anyway.
+    if !ctx.config.capabilities.serves(message_type) {
+        debug!("Not serving {:?} to peer {:?}", message_type, peer);
+        return;
     }
-    let message_type = DirectMessageType::from_byte(request.message[0]);
-    let payload = &request.message[1..];
 
-    let response_message = match message_type {
-        Some(DirectMessageType::Handshake) => handle_handshake_request(payload, blockchain).await,
-        Some(DirectMessageType::GetBlockHeaders) => {
-            handle_get_block_headers_request(payload, blockchain).await
-        }
-        Some(DirectMessageType::GetBlockBodies) => {
-            handle_get_block_bodies_request(payload, blockchain).await
+    let message = Inbound {
+        peer,
+        format,
+        payload,
+        swarm,
+        blockchain,
+        ctx,
+    };
+    let response_message = match isolate(handler.handle_request(message)).await {
+        Answer::Now(response_message) => response_message,
+        Answer::FromChain(priority, job) => {
+            dispatch_chain_request(peer, channel, priority, ctx, job);
+            return;
         }
+    };
+
+    if matches!(response_message, Err(NodeError::Panicked(_))) {
+        penalize_panicked_request(&peer, swarm, ctx);
+    }
+    send_response(response_or_empty(response_message), swarm, channel);
+}
+
+/// Runs a request handler on the swarm task, turning a panic into an error so a bug in
+/// one handler fails that request instead of the whole p2p server. State the handler
+/// left half-updated stays as it is; the peer table and caches tolerate that.
+async fn isolate(handler: impl Future<Output = Answer>) -> Answer {
+    AssertUnwindSafe(handler)
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| Answer::Now(Err(NodeError::from_panic(panic))))
+}
+
+/// Requests a peer may crash our handlers with before it is dropped as misbehaving.
+/// One could be our own bug; a peer that keeps hitting it is likely probing for it.
+const MAX_PANICKED_REQUESTS: u32 = 3;
+
+/// Counts a request from `peer_id` whose handler panicked, and disconnects and bans the
+/// peer once it reaches `MAX_PANICKED_REQUESTS`.
+pub fn penalize_panicked_request(
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    if ctx.peers.on_request_panicked(peer_id) >= MAX_PANICKED_REQUESTS {
+        send_disconnect(peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+    }
+}
+
+/// The encoded response, or an empty message when the request couldn't be answered. The
+/// requester treats an empty message as a failed request, so a storage hiccup costs the
+/// peer one request rather than taking down the swarm task.
+fn response_or_empty(response: Result<Vec<u8>, NodeError>) -> Vec<u8> {
+    response.unwrap_or_else(|e| {
+        error!(error_kind = e.kind(), "Failed to answer request: {}", e);
+        Vec::new()
+    })
+}
+
+/// Decodes the payload of a `message` request.
+fn decode_request<T: RlpMessage>(
+    payload: &[u8],
+    format: WireFormat,
+    message: &'static str,
+) -> Result<T, NodeError> {
+    format
+        .decode(payload)
+        .map_err(|reason| NodeError::Decode { message, reason })
+}
+
+/// The frame's format if we accept it. Requests are answered in the format they arrived
+/// in, so only formats we advertised (or RLP) are taken.
+fn accepted_format(
+    format: Option<WireFormat>,
+    peer_id: &PeerId,
+    ctx: &P2PContext,
+) -> Option<WireFormat> {
+    match format {
+        Some(format) if ctx.config.wire_formats.contains(&format) => Some(format),
         _ => {
             error!(
-                "Received unknown DirectMessageType from peer {:?}: {:?}",
-                peer, message_type
+                "Peer {:?} sent a direct message in a wire format we did not offer: {:?}",
+                peer_id, format
             );
-            return;
+            None
         }
-    };
+    }
+}
 
-    send_response(response_message, swarm, channel);
+/// Hands a chain-data request to the inbound worker pool. Dropping `channel` when the pool
+/// is saturated, or for bulk requests in disk safe mode, fails the request on the peer's
+/// side instead of stalling the swarm loop.
+/// Jobs read through the snapshot reader, so they never wait on the `Blockchain` lock.
+fn dispatch_chain_request<F>(
+    peer: PeerId,
+    channel: libp2p::request_response::ResponseChannel<DirectMessageResponse>,
+    priority: Priority,
+    ctx: &mut P2PContext,
+    job: F,
+) where
+    F: FnOnce(ChainReader) -> Result<Vec<u8>, NodeError> + Send + 'static,
+{
+    if priority == Priority::Bulk && disk_monitor::safe_mode() {
+        warn!("Disk safe mode; dropping bulk request from peer {:?}", peer);
+        return;
+    }
+    let Some(reader) = ctx.chain_reader.clone() else {
+        error!(
+            "No chain reader available; dropping request from peer {:?}",
+            peer
+        );
+        return;
+    };
+    let job = async move { response_or_empty(job(reader)) }.instrument(Span::current());
+    if !ctx.inbound.spawn(peer, channel, priority, job) {
+        warn!(
+            "Inbound request pool saturated; dropping request from peer {:?}",
+            peer
+        );
+    }
 }
 
 async fn handle_response_message(
@@ -111,62 +435,360 @@ async fn handle_response_message(
     request_id: libp2p::request_response::OutboundRequestId,
     response: DirectMessageResponse,
     swarm: &mut Swarm<P2PBehaviour>,
-    blockchain: &Arc<Mutex<Blockchain>>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
     debug!(
         "Received direct message response from {:?} with request_id {:?}",
         peer_id, request_id,
     );
+    release_request_slot(&peer_id, &request_id, swarm, ctx);
+    let Some(request_type) = ctx.in_flight.take(&request_id, &peer_id) else {
+        warn!(
+            "Peer {:?} sent a response to no request of ours: {:?}",
+            peer_id, request_id
+        );
+        send_disconnect(&peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+        return;
+    };
+    // An empty response is a request the peer couldn't answer, not a violation.
+    if let Some((message_type, _, _)) = decode_frame(&response.message) {
+        if !answers(request_type, message_type) {
+            warn!(
+                "Peer {:?} answered {:?} with {:?}",
+                peer_id, request_type, message_type
+            );
+            ctx.responses
+                .fail(&request_id, format!("answered with {:?}", message_type));
+            send_disconnect(&peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+            return;
+        }
+    }
+    let Some(response) = ctx.responses.resolve(&request_id, response) else {
+        return;
+    };
 
-    if response.message.is_empty() {
+    let Some((message_type, format, payload)) = decode_frame(&response.message) else {
         error!("Received empty direct message response from {:?}", peer_id);
+        abandon_branch_download(&peer_id, "the peer could not answer", ctx);
+        return;
+    };
+    let Some(format) = accepted_format(format, &peer_id, ctx) else {
+        return;
+    };
+
+    let version = ctx.peers.protocol_version(&peer_id);
+    let Some(handler) =
+        message_type.and_then(|message_type| ctx.handlers.response(message_type, version))
+    else {
+        error!(
+            "Unknown DirectMessageType in response from peer {:?}: {:?}",
+            peer_id, message_type
+        );
         return;
+    };
+    let message = Inbound {
+        peer: peer_id,
+        format,
+        payload,
+        swarm,
+        blockchain,
+        ctx,
+    };
+    handler.handle_response(message).await;
+}
+
+/// Handlers for every direct message this node speaks, one per request and its response.
+/// A new message type implements `RequestHandler` and `ResponseHandler` and is registered
+/// here. The codec hands every handler the current frame layout, so these serve both
+/// versions; one that changes registers again at `DIRECT_MESSAGE_VERSION`.
+pub fn builtin_handlers() -> HandlerRegistry {
+    let version = LEGACY_DIRECT_MESSAGE_VERSION;
+    HandlerRegistry::default()
+        .with_handler(DirectMessageType::Handshake, version, HandshakeMessages)
+        .with_handler(DirectMessageType::GetBlockHeaders, version, HeaderMessages)
+        .with_handler(DirectMessageType::GetBlockBodies, version, BodyMessages)
+        .with_handler(DirectMessageType::Disconnect, version, DisconnectMessages)
+        .with_handler(DirectMessageType::Ping, version, PingMessages)
+        .with_handler(
+            DirectMessageType::GetBlockTransactions,
+            version,
+            CompactBlockMessages,
+        )
+        .with_handler(
+            DirectMessageType::GetPooledTransactions,
+            version,
+            TxAnnounceMessages,
+        )
+}
+
+struct HandshakeMessages;
+
+#[async_trait]
+impl RequestHandler for HandshakeMessages {
+    fn requires_handshake(&self) -> bool {
+        false
     }
-    let message_type = DirectMessageType::from_byte(response.message[0]);
-    let payload = &response.message[1..];
 
-    match message_type {
-        Some(DirectMessageType::Handshake) => {
-            handle_handshake_response(payload, &peer_id, swarm, blockchain).await
-        }
-        Some(DirectMessageType::BlockHeaders) => {
-            handle_block_headers_response(payload, &peer_id, swarm, blockchain).await
-        }
-        Some(DirectMessageType::BlockBodies) => {
-            handle_block_bodies_response(payload, &peer_id, swarm, blockchain).await
-        }
-        _ => {
-            error!(
-                "Unknown DirectMessageType in response from peer {:?}: {:?}",
-                peer_id, message_type
-            );
-        }
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            swarm,
+            blockchain,
+            ctx,
+        } = message;
+        Answer::Now(handle_handshake_request(payload, format, &peer, swarm, blockchain, ctx).await)
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for HandshakeMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            swarm,
+            blockchain,
+            ctx,
+        } = message;
+        handle_handshake_response(payload, format, &peer, swarm, blockchain, ctx).await
+    }
+}
+
+struct HeaderMessages;
+
+#[async_trait]
+impl RequestHandler for HeaderMessages {
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let (payload, format) = (message.payload.to_vec(), message.format);
+        Answer::FromChain(
+            Priority::Bulk,
+            Box::new(move |reader| handle_get_block_headers_request(&payload, format, &reader)),
+        )
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for HeaderMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            swarm,
+            blockchain,
+            ctx,
+        } = message;
+        handle_block_headers_response(payload, format, &peer, swarm, blockchain, ctx).await
+    }
+}
+
+struct BodyMessages;
+
+#[async_trait]
+impl RequestHandler for BodyMessages {
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let (payload, format) = (message.payload.to_vec(), message.format);
+        Answer::FromChain(
+            Priority::Bulk,
+            Box::new(move |reader| handle_get_block_bodies_request(&payload, format, &reader)),
+        )
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for BodyMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            swarm,
+            blockchain,
+            ctx,
+        } = message;
+        handle_block_bodies_response(payload, format, &peer, swarm, blockchain, ctx).await
+    }
+}
+
+struct DisconnectMessages;
+
+#[async_trait]
+impl RequestHandler for DisconnectMessages {
+    fn requires_handshake(&self) -> bool {
+        false
+    }
+
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            ctx,
+            ..
+        } = message;
+        Answer::Now(Ok(handle_disconnect_request(payload, format, &peer, ctx)))
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for DisconnectMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        // Our Disconnect was delivered; now it is safe to close the connection.
+        let _ = message.swarm.disconnect_peer_id(message.peer);
+    }
+}
+
+struct PingMessages;
+
+#[async_trait]
+impl RequestHandler for PingMessages {
+    fn requires_handshake(&self) -> bool {
+        false
+    }
+
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        Answer::Now(handle_ping_request(message.payload, message.format))
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for PingMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        handle_pong_response(message.payload, message.format, &message.peer, message.ctx)
+    }
+}
+
+struct CompactBlockMessages;
+
+#[async_trait]
+impl RequestHandler for CompactBlockMessages {
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let (payload, format) = (message.payload.to_vec(), message.format);
+        Answer::FromChain(
+            Priority::Consensus,
+            Box::new(move |reader| {
+                handle_get_block_transactions_request(&payload, format, &reader)
+            }),
+        )
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for CompactBlockMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            swarm,
+            blockchain,
+            ctx,
+        } = message;
+        handle_block_transactions_response(payload, format, &peer, swarm, blockchain, ctx).await
+    }
+}
+
+struct TxAnnounceMessages;
+
+#[async_trait]
+impl RequestHandler for TxAnnounceMessages {
+    async fn handle_request(&self, message: Inbound<'_>) -> Answer {
+        let Inbound {
+            format,
+            payload,
+            blockchain,
+            ctx,
+            ..
+        } = message;
+        Answer::Now(handle_get_pooled_transactions_request(payload, format, blockchain, ctx).await)
+    }
+}
+
+#[async_trait]
+impl ResponseHandler for TxAnnounceMessages {
+    async fn handle_response(&self, message: Inbound<'_>) {
+        let Inbound {
+            peer,
+            format,
+            payload,
+            blockchain,
+            ctx,
+            ..
+        } = message;
+        handle_pooled_transactions_response(payload, format, &peer, blockchain, ctx).await
     }
 }
 
-fn send_request(
+/// Sends a direct request and records it in `P2PContext::in_flight`, so its response is
+/// handled once it arrives.
+pub fn send_request(
     peer_id: &PeerId,
     request_message: Vec<u8>,
     swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
 ) -> OutboundRequestId {
-    let request: DirectMessageRequest = DirectMessageRequest {
-        message: request_message,
-    };
+    let request = DirectMessageRequest::new(request_message);
 
-    swarm
+    let message_type = decode_frame(&request.message).and_then(|(message_type, _, _)| message_type);
+    let request_id = swarm
         .behaviour_mut()
         .request_response
-        .send_request(&peer_id, request)
+        .send_request(&peer_id, request);
+    if let Some(message_type) = message_type {
+        ctx.in_flight.on_sent(request_id, *peer_id, message_type);
+    }
+    debug!(
+        peer_id = %peer_id,
+        request_id = %request_id,
+        message_type = ?message_type,
+        "Sent direct message request"
+    );
+    request_id
+}
+
+/// Sends a sync request through the peer's bounded queue instead of straight to the
+/// swarm, so one peer never has more than `max_in_flight_requests_per_peer` outstanding.
+fn send_sync_request(
+    peer_id: &PeerId,
+    request_message: Vec<u8>,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    ctx.peers.on_sync_requested(peer_id, Instant::now());
+    match ctx.requests.enqueue(peer_id, request_message) {
+        Enqueued::SendNow(message) => {
+            let request_id = send_request(peer_id, message, swarm, ctx);
+            ctx.requests.on_sent(peer_id, request_id);
+        }
+        Enqueued::Queued => debug!("Queued sync request for busy peer {:?}", peer_id),
+        Enqueued::Rejected => warn!("Sync request queue full for peer {:?}; dropping", peer_id),
+    }
 }
 
-fn send_response(
+/// Called once an outbound request is answered or failed; hands the freed slot to the
+/// next queued sync request for that peer.
+fn release_request_slot(
+    peer_id: &PeerId,
+    request_id: &OutboundRequestId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    if let Some(message) = ctx.requests.on_completed(peer_id, request_id) {
+        let next_id = send_request(peer_id, message, swarm, ctx);
+        ctx.requests.on_sent(peer_id, next_id);
+    }
+}
+
+pub fn send_response(
     response_message: Vec<u8>,
     swarm: &mut Swarm<P2PBehaviour>,
     channel: libp2p::request_response::ResponseChannel<DirectMessageResponse>,
 ) {
-    let response = DirectMessageResponse {
-        message: response_message,
-    };
+    let response = DirectMessageResponse::new(response_message);
 
     if let Err(e) = swarm
         .behaviour_mut()
@@ -177,222 +799,1445 @@ fn send_response(
     }
 }
 
-async fn handle_handshake_request(payload: &[u8], blockchain: &Arc<Mutex<Blockchain>>) -> Vec<u8> {
-    match decode::<Handshake>(payload) {
+/// Our handshake, advertising the wire formats we accept and what we serve.
+/// Our handshake, from the snapshot the `Blockchain` keeps current; the chain lock is
+/// only taken before the server has one.
+async fn local_handshake(
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &P2PContext,
+) -> Result<Handshake, NodeError> {
+    let mut handshake = match ctx.handshake.get() {
+        Some(handshake) => handshake,
+        None => blockchain.read().await.handshake()?,
+    };
+    handshake.capabilities = ctx.config.capabilities.advertise(&ctx.config.wire_formats);
+    handshake.node_record = ctx.node_record.clone();
+    handshake.protocol_version = DIRECT_MESSAGE_VERSION;
+    Ok(handshake)
+}
+
+/// Sends our handshake to `peer_id`. Done once per newly connected peer so both sides
+/// can mark each other handshaked before the timeout. Handshakes are always RLP: the
+/// peer's formats are only known once its handshake arrives.
+pub async fn send_handshake(
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    match local_handshake(blockchain, ctx).await {
         Ok(handshake) => {
-            debug!("Received and decoded handshake: {:?}", handshake);
-            handshake_response(&handshake, blockchain).await
-        }
-        Err(e) => {
-            error!("Failed to decode handshake: {:?}", e);
-            Vec::new()
+            let encoded_handshake =
+                encode_message(DirectMessageType::Handshake, WireFormat::Rlp, &handshake);
+            send_request(peer_id, encoded_handshake, swarm, ctx);
         }
+        Err(e) => error!("Failed to build handshake for peer {}: {}", peer_id, e),
     }
 }
 
-async fn handle_get_block_headers_request(
-    payload: &[u8],
-    blockchain: &Arc<Mutex<Blockchain>>,
-) -> Vec<u8> {
-    match decode::<GetBlockHeaders>(payload) {
-        Ok(get_block_header) => {
-            debug!(
-                "Received and decoded getBlockHeader: {:?}",
-                get_block_header
-            );
-            get_block_headers_response(&get_block_header, blockchain).await
-        }
-        Err(e) => {
-            error!("Failed to decode getBlockHeader: {:?}", e);
-            Vec::new()
-        }
+/// Tells `peer_id` why we are about to drop it. The connection is closed once the peer
+/// acknowledges, or by the server's peer check if it doesn't within the grace period.
+pub fn send_disconnect(
+    peer_id: &PeerId,
+    reason: DisconnectReason,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    if !ctx
+        .peers
+        .mark_disconnecting(peer_id, reason, Instant::now())
+    {
+        return;
+    }
+
+    warn!("Disconnecting peer {}: {}", peer_id, reason);
+    if reason.is_misbehaviour() {
+        ban_peer(peer_id, swarm, ctx);
     }
+    let encoded_disconnect = encode_message(
+        DirectMessageType::Disconnect,
+        ctx.peers.wire_format(peer_id),
+        &Disconnect { reason },
+    );
+    send_request(peer_id, encoded_disconnect, swarm, ctx);
 }
 
-async fn handle_get_block_bodies_request(
-    payload: &[u8],
-    blockchain: &Arc<Mutex<Blockchain>>,
-) -> Vec<u8> {
-    match decode::<GetBlockBodies>(payload) {
-        Ok(get_block_bodies) => {
-            debug!(
-                "Received and decoded GetBlockBodies: {:?}",
-                get_block_bodies
-            );
-            get_block_bodies_response(&get_block_bodies, blockchain).await
-        }
-        Err(e) => {
-            error!("Failed to decode GetBlockBodies: {:?}", e);
-            Vec::new()
-        }
+/// Refuses new connections from the peer's IP for `ban_duration`.
+fn ban_peer(peer_id: &PeerId, swarm: &mut Swarm<P2PBehaviour>, ctx: &P2PContext) {
+    if ctx.config.ban_duration.is_zero() {
+        return;
     }
+    let Some(ip) = ctx.peers.get(peer_id).and_then(|entry| entry.remote_ip) else {
+        return;
+    };
+    warn!(
+        "Banning {} (peer {}) for {:?}",
+        ip, peer_id, ctx.config.ban_duration
+    );
+    swarm
+        .behaviour_mut()
+        .gate
+        .ban(ip, Instant::now() + ctx.config.ban_duration);
 }
 
-async fn handle_handshake_response(
-    payload: &[u8],
+pub fn send_ping(
     peer_id: &PeerId,
+    nonce: u64,
     swarm: &mut Swarm<P2PBehaviour>,
-    blockchain: &Arc<Mutex<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
-    match decode::<Handshake>(payload) {
-        Ok(handshake) => {
-            debug!("Decoded Handshake: {:?}", handshake);
-            let blockchain = blockchain.lock().await;
-            let current_block_index = match blockchain.handshake() {
-                Ok(handshake) => handshake.latest_block_index,
-                Err(e) => {
-                    error!("Failed to read local handshake state: {}", e);
-                    return;
-                }
-            };
-            let received_block_index = handshake.latest_block_index;
-
-            if current_block_index < received_block_index {
-                warn!("this node is needed to syncing!");
-
-                let get_block_headers = GetBlockHeaders {
-                    start_block_index: current_block_index,
-                    skip: 1,
-                    limit: 100,
-                };
-
-                let encoded_headers =
-                    encode_message(DirectMessageType::GetBlockHeaders, &get_block_headers);
-                send_request(peer_id, encoded_headers, swarm);
-            }
-        }
-        Err(e) => {
-            error!("Failed to decode Handshake: {:?}", e);
-        }
-    }
+    let encoded_ping = encode_message(
+        DirectMessageType::Ping,
+        ctx.peers.wire_format(peer_id),
+        &Ping { nonce },
+    );
+    send_request(peer_id, encoded_ping, swarm, ctx);
 }
 
-async fn handle_block_headers_response(
-    payload: &[u8],
+/// Asks the peer that relayed a compact block for the transactions our mempool lacks.
+/// Sent outside the sync queue, since the block's gossip verdict waits on the answer.
+pub fn request_block_transactions(
     peer_id: &PeerId,
+    request: &GetBlockTransactions,
     swarm: &mut Swarm<P2PBehaviour>,
-    _blockchain: &Arc<Mutex<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
-    match decode::<BlockHeaders>(payload) {
-        Ok(block_headers) => {
-            debug!("Decoded BlockHeaders: {:?}", block_headers);
+    let encoded_request = encode_message(
+        DirectMessageType::GetBlockTransactions,
+        ctx.peers.wire_format(peer_id),
+        request,
+    );
+    send_request(peer_id, encoded_request, swarm, ctx);
+}
+
+/// Asks a peer that announced transactions for the ones we lack.
+pub fn request_pooled_transactions(
+    peer_id: &PeerId,
+    request: &GetPooledTransactions,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    let encoded_request = encode_message(
+        DirectMessageType::GetPooledTransactions,
+        ctx.peers.wire_format(peer_id),
+        request,
+    );
+    send_request(peer_id, encoded_request, swarm, ctx);
+}
+
+/// Answers with the requested transactions still in our pool; ones that were mined or
+/// dropped meanwhile are left out.
+async fn handle_get_pooled_transactions_request(
+    payload: &[u8],
+    format: WireFormat,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &P2PContext,
+) -> Result<Vec<u8>, NodeError> {
+    let request: GetPooledTransactions = decode_request(payload, format, "GetPooledTransactions")?;
+    let transactions = {
+        let blockchain = blockchain.read().await;
+        request
+            .hashes
+            .iter()
+            .take(ctx.config.tx_announce_batch_size)
+            .filter_map(|hash| blockchain.get_transaction_from_pool(hash).ok().flatten())
+            .collect()
+    };
+    Ok(encode_message(
+        DirectMessageType::PooledTransactions,
+        format,
+        &PooledTransactions { transactions },
+    ))
+}
+
+/// Adds the fetched transactions to our pool. Each one accepted is announced to our own
+/// peers in the next batch.
+async fn handle_pooled_transactions_response(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    let pooled = match format.decode::<PooledTransactions>(payload) {
+        Ok(pooled) => pooled,
+        Err(e) => {
+            error!("Failed to decode PooledTransactions: {:?}", e);
+            return;
+        }
+    };
+    for transaction in pooled.transactions {
+        if !ctx.tx_announcer.on_received(&transaction.hash) {
+            debug!(
+                "Dropping transaction {} from {:?} that we did not ask for",
+                transaction.hash, peer_id
+            );
+            continue;
+        }
+        let acceptance = handle_received_transaction(&transaction, blockchain, &mut ctx.seen).await;
+        if matches!(acceptance, MessageAcceptance::Accept) {
+            ctx.tx_announcer.queue(transaction.hash);
+        }
+    }
+}
 
-            let block_indexes = block_headers.to_block_indexes();
-            let get_block_bodies = GetBlockBodies { block_indexes };
+fn handle_ping_request(payload: &[u8], format: WireFormat) -> Result<Vec<u8>, NodeError> {
+    let ping: Ping = decode_request(payload, format, "Ping")?;
+    Ok(encode_message(
+        DirectMessageType::Pong,
+        format,
+        &Pong { nonce: ping.nonce },
+    ))
+}
 
-            let encoded_bodies =
-                encode_message(DirectMessageType::GetBlockBodies, &get_block_bodies);
-            send_request(peer_id, encoded_bodies, swarm);
+fn handle_pong_response(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    ctx: &mut P2PContext,
+) {
+    match format.decode::<Pong>(payload) {
+        Ok(pong) => {
+            if let Some(rtt) = ctx.peers.on_pong(peer_id, pong.nonce, Instant::now()) {
+                debug!("Peer {} round-trip time {:?}", peer_id, rtt);
+                metric::PEER_LATENCY_MS
+                    .get_or_create(&metric::PeerLabels {
+                        peer_id: peer_id.to_string(),
+                    })
+                    .set(rtt.as_millis() as i64);
+            }
         }
         Err(e) => {
-            error!("Failed to decode BlockHeaders: {:?}", e);
+            error!("Failed to decode Pong from peer {}: {:?}", peer_id, e);
         }
     }
 }
 
-async fn handle_block_bodies_response(
+fn handle_disconnect_request(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    ctx: &mut P2PContext,
+) -> Vec<u8> {
+    match format.decode::<Disconnect>(payload) {
+        Ok(disconnect) => {
+            warn!("Peer {} is disconnecting: {}", peer_id, disconnect.reason);
+            ctx.peers
+                .record_disconnect(peer_id, disconnect.reason, DisconnectDirection::Received);
+        }
+        Err(e) => {
+            error!("Failed to decode Disconnect from peer {}: {:?}", peer_id, e);
+        }
+    }
+
+    // Bare acknowledgement so the sender knows it can close the connection.
+    frame_header(DirectMessageType::Disconnect, format).to_vec()
+}
+
+/// Records `handshake` in the peer table if it is for the same genesis block as ours and
+/// its hardfork schedule is compatible, otherwise disconnects the peer. Returns our own
+/// handshake so callers can compare chain heights.
+async fn accept_handshake(
+    handshake: &Handshake,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> Option<Handshake> {
+    let local_handshake = match local_handshake(blockchain, ctx).await {
+        Ok(handshake) => handshake,
+        Err(e) => {
+            error!("Failed to read local handshake state: {}", e);
+            return None;
+        }
+    };
+
+    if local_handshake.genesis_block_hash != handshake.genesis_block_hash {
+        warn!(
+            "Peer {} has a different genesis block ({}); not accepting handshake",
+            peer_id, handshake.genesis_block_hash
+        );
+        send_disconnect(peer_id, DisconnectReason::IncompatibleGenesis, swarm, ctx);
+        return None;
+    }
+    let head_index = local_handshake
+        .latest_block_index
+        .max(handshake.latest_block_index);
+    if let Err(e) =
+        ctx.hardforks
+            .check_peer(&handshake.hardforks, head_index, get_current_timespan())
+    {
+        warn!(
+            "Peer {} has an incompatible hardfork schedule: {}; not accepting handshake",
+            peer_id, e
+        );
+        send_disconnect(peer_id, DisconnectReason::IncompatibleHardforks, swarm, ctx);
+        return None;
+    }
+    // Nodes that predate records send none; a record that is sent has to check out.
+    if !handshake.node_record.is_empty() {
+        match NodeRecord::verify(&handshake.node_record, peer_id, &ctx.config.chain_id) {
+            Ok(record) => ctx.peers.set_node_record(peer_id, record),
+            Err(e) => {
+                warn!(
+                    "Peer {} sent a node record that doesn't verify: {}; not accepting handshake",
+                    peer_id, e
+                );
+                send_disconnect(peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+                return None;
+            }
+        }
+    }
+
+    ctx.peers
+        .mark_handshaked(peer_id, handshake, Instant::now());
+    ctx.remember_peer(peer_id, false);
+    let format = WireFormat::negotiate(&ctx.config.wire_formats, &handshake.capabilities);
+    ctx.peers.set_wire_format(peer_id, format);
+    ctx.peers
+        .set_protocol_version(peer_id, negotiate_version(handshake.protocol_version));
+    ctx.peers.set_capabilities(
+        peer_id,
+        PeerCapabilities::from_advertised(&handshake.capabilities),
+    );
+    Some(local_handshake)
+}
+
+async fn handle_handshake_request(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) -> Result<Vec<u8>, NodeError> {
+    let handshake: Handshake = decode_request(payload, format, "Handshake")?;
+    debug!("Received and decoded handshake: {:?}", handshake);
+    accept_handshake(&handshake, peer_id, swarm, blockchain, ctx).await;
+    handshake_response(&handshake, blockchain, ctx).await
+}
+
+fn handle_get_block_headers_request(
+    payload: &[u8],
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
+    let get_block_header: GetBlockHeaders = decode_request(payload, format, "GetBlockHeaders")?;
+    debug!(
+        "Received and decoded getBlockHeader: {:?}",
+        get_block_header
+    );
+    get_block_headers_response(&get_block_header, format, reader)
+}
+
+fn handle_get_block_bodies_request(
+    payload: &[u8],
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
+    let get_block_bodies: GetBlockBodies = decode_request(payload, format, "GetBlockBodies")?;
+    debug!(
+        "Received and decoded GetBlockBodies: {:?}",
+        get_block_bodies
+    );
+    get_block_bodies_response(&get_block_bodies, format, reader)
+}
+
+fn handle_get_block_transactions_request(
+    payload: &[u8],
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
+    let get_block_transactions: GetBlockTransactions =
+        decode_request(payload, format, "GetBlockTransactions")?;
+    debug!(
+        "Received and decoded GetBlockTransactions: {:?}",
+        get_block_transactions
+    );
+    get_block_transactions_response(&get_block_transactions, format, reader)
+}
+
+async fn handle_handshake_response(
     payload: &[u8],
-    _peer_id: &PeerId,
-    _swarm: &mut Swarm<P2PBehaviour>,
-    blockchain: &Arc<Mutex<Blockchain>>,
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
 ) {
-    match decode::<BlockBodies>(payload) {
-        Ok(block_bodies) => {
-            debug!("Decoded BlockBodies: {:?}", block_bodies);
+    match format.decode::<Handshake>(payload) {
+        Ok(handshake) => {
+            debug!("Decoded Handshake: {:?}", handshake);
+            let Some(local_handshake) =
+                accept_handshake(&handshake, peer_id, swarm, blockchain, ctx).await
+            else {
+                return;
+            };
+            let current_block_index = local_handshake.latest_block_index;
+            let received_block_index = handshake.latest_block_index;
 
-            let blockchain = blockchain.lock().await;
+            if current_block_index == received_block_index
+                && local_handshake.latest_block_hash != handshake.latest_block_hash
+            {
+                warn!(
+                    "Peer {:?} is at block {} like us but on a different fork ({} vs {})",
+                    peer_id,
+                    received_block_index,
+                    handshake.latest_block_hash,
+                    local_handshake.latest_block_hash
+                );
+            }
 
-            for block in block_bodies.blocks {
-                match blockchain.import_block(&block) {
-                    Ok(_) => {
-                        debug!("Successfully imported block with index: {}", block.index);
+            // Sync towards the best head any peer has advertised, not just this one's.
+            let target = ctx
+                .peers
+                .best_head()
+                .map_or(received_block_index, |(_, head)| head.index);
+            if ctx.config.sync_enabled && current_block_index < target {
+                let now = Instant::now();
+                if ctx.sync.is_running(now) {
+                    ctx.sync.extend_target(target);
+                } else if let Some(checkpoint) = ctx.sync.take_restored().filter(|checkpoint| {
+                    checkpoint.head_index > current_block_index
+                        && checkpoint.can_resume(current_block_index)
+                }) {
+                    info!(
+                        "Resuming sync from block {} to {}",
+                        current_block_index, checkpoint.head_index
+                    );
+                    let missing = ctx
+                        .sync
+                        .resume(checkpoint, current_block_index, target, now);
+                    for block_indexes in missing.chunks(MAX_BLOCKS_PER_REQUEST) {
+                        request_bodies(block_indexes.to_vec(), peer_id, swarm, ctx);
                     }
-                    Err(e) => {
-                        error!("Failed to import block with index {}: {:?}", block.index, e);
+                    // A saved batch may already follow the tip.
+                    import_synced_blocks(std::iter::empty(), &*blockchain.write().await, ctx);
+                } else {
+                    warn!("this node is needed to syncing!");
+                    ctx.sync.start(
+                        current_block_index,
+                        local_handshake.latest_block_hash,
+                        target,
+                        now,
+                    );
+                }
+                request_next_headers(peer_id, swarm, ctx);
+            }
+        }
+        Err(e) => {
+            error!("Failed to decode Handshake: {:?}", e);
+        }
+    }
+}
+
+async fn handle_block_headers_response(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    match format.decode::<BlockHeaders>(payload) {
+        Ok(block_headers) => {
+            debug!("Decoded BlockHeaders: {:?}", block_headers);
+
+            let Some(reader) = ctx.chain_reader.clone() else {
+                return;
+            };
+            if let Some(search) = ctx.peers.ancestor_search(peer_id) {
+                handle_ancestor_probe(
+                    search,
+                    &block_headers,
+                    &reader,
+                    peer_id,
+                    swarm,
+                    blockchain,
+                    ctx,
+                )
+                .await;
+                return;
+            }
+            if let Some(download) = ctx.peers.take_branch_download(peer_id) {
+                download_branch_headers(download, block_headers, peer_id, swarm, blockchain, ctx);
+                return;
+            }
+
+            if ctx.sync.head().is_none() {
+                debug!("Ignoring BlockHeaders from {:?}; not syncing", peer_id);
+                return;
+            }
+
+            let Some(first_header) = block_headers.block_headers.first() else {
+                ctx.sync.on_headers_exhausted();
+                // Our request was anchored on the pipeline head. If that is our tip, an
+                // empty answer from a peer that is ahead means the tip isn't on its chain;
+                // past the tip the peer has simply run out of blocks.
+                match reader.get_latest_block() {
+                    Ok(Some(tip)) if ctx.sync.head_hash(tip.index).is_some() => {
+                        ctx.sync.stop();
+                        start_ancestor_search(tip.index, peer_id, swarm, blockchain, ctx).await;
                     }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to read latest block: {}", e),
                 }
+                return;
+            };
+            let parent_index = first_header.index.saturating_sub(1);
+            let (parent_hash, parent_imported) = match ctx.sync.head_hash(parent_index) {
+                Some(hash) => (Ok(Some(hash.to_string())), false),
+                None => (reader.get_block_hash(parent_index), true),
+            };
+            let parent_hash = match parent_hash {
+                Ok(Some(hash)) => hash,
+                Ok(None) => {
+                    warn!(
+                        "Peer {:?} sent headers from {} which we can't attach to",
+                        peer_id, first_header.index
+                    );
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to read local block hash: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = block_headers.verify_chain(&parent_hash) {
+                warn!(
+                    "BlockHeaders from {:?} don't extend our chain: {}",
+                    peer_id, e
+                );
+                ctx.sync.stop();
+                if parent_imported {
+                    start_ancestor_search(parent_index, peer_id, swarm, blockchain, ctx).await;
+                }
+                return;
+            }
+            if let Some(last_header) = block_headers.block_headers.last() {
+                ctx.sync
+                    .on_headers(last_header.index, last_header.hash.clone(), Instant::now());
             }
+            ctx.sync.expect_bodies(&block_headers.block_headers);
+
+            request_bodies(block_headers.to_block_indexes(), peer_id, swarm, ctx);
+
+            // Fetch the next headers while these bodies download and import.
+            request_next_headers(peer_id, swarm, ctx);
+        }
+        Err(e) => {
+            error!("Failed to decode BlockHeaders: {:?}", e);
         }
+    }
+}
+
+fn request_bodies(
+    block_indexes: Vec<usize>,
+    fallback_peer: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    let highest_index = block_indexes.iter().copied().max().unwrap_or_default();
+    let request = DirectMessageType::GetBlockBodies;
+    let Some(sync_peer) = ctx.select_sync_peer(highest_index, request).or_else(|| {
+        ctx.peers
+            .serves(fallback_peer, request)
+            .then_some(*fallback_peer)
+    }) else {
+        debug!("No peer serves block bodies up to {}", highest_index);
+        return;
+    };
+    ctx.peers.on_bodies_requested(&sync_peer, Instant::now());
+    ctx.sync.on_bodies_requested();
+
+    // A header batch is one contiguous range, which a peer serving `/clutch/sync/1` sends
+    // as a single stream. The sync pipeline depth bounds these like queued requests.
+    let range = SyncRange::covering(&block_indexes)
+        .filter(|_| ctx.config.sync_stream_enabled && ctx.peers.serves_sync_stream(&sync_peer));
+    if let Some(range) = range {
+        ctx.peers.on_sync_requested(&sync_peer, Instant::now());
+        let request_id = swarm
+            .behaviour_mut()
+            .sync_stream
+            .send_request(&sync_peer, range);
+        debug!(
+            peer_id = %sync_peer,
+            request_id = %request_id,
+            "Requested {} blocks from {} over the sync stream",
+            range.count,
+            range.start_index
+        );
+        return;
+    }
+
+    let get_block_bodies = GetBlockBodies { block_indexes };
+
+    let encoded_bodies = encode_message(
+        DirectMessageType::GetBlockBodies,
+        ctx.peers.wire_format(&sync_peer),
+        &get_block_bodies,
+    );
+    send_sync_request(&sync_peer, encoded_bodies, swarm, ctx);
+}
+
+/// Asks for the header batch after the sync pipeline head, if the pipeline has room.
+fn request_next_headers(
+    fallback_peer: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    if !ctx.sync.wants_headers() {
+        return;
+    }
+    let Some((head_index, head_hash)) = ctx
+        .sync
+        .head()
+        .map(|(index, hash)| (index, hash.to_string()))
+    else {
+        return;
+    };
+
+    // Download from whichever peer at that height has served us best so far,
+    // not necessarily the one that happened to answer this handshake.
+    let request = DirectMessageType::GetBlockHeaders;
+    let Some(sync_peer) = ctx.select_sync_peer(head_index + 1, request).or_else(|| {
+        ctx.peers
+            .serves(fallback_peer, request)
+            .then_some(*fallback_peer)
+    }) else {
+        debug!("No peer serves headers past {}", head_index);
+        return;
+    };
+    if ctx.peers.ancestor_search(&sync_peer).is_some() {
+        debug!("Common ancestor search with {:?} still running", sync_peer);
+        return;
+    }
+    if ctx.peers.is_downloading_branch(&sync_peer) {
+        debug!("Still downloading the branch of {:?}", sync_peer);
+        return;
+    }
+
+    let get_block_headers = GetBlockHeaders {
+        start_block_index: head_index,
+        skip: 1,
+        limit: MAX_BLOCKS_PER_REQUEST,
+        start_block_hash: head_hash,
+    };
+    let encoded_headers = encode_message(
+        DirectMessageType::GetBlockHeaders,
+        ctx.peers.wire_format(&sync_peer),
+        &get_block_headers,
+    );
+    ctx.sync.on_headers_requested();
+    send_sync_request(&sync_peer, encoded_headers, swarm, ctx);
+}
+
+/// Called on the peer check tick. A run still behind its target that has made no progress
+/// for the stall timeout is logged with what it was waiting on, counted in the
+/// `sync_stalls` metric, and restarted from our tip towards the best advertised head on
+/// peers other than the ones it stalled on.
+pub fn check_sync_stall(swarm: &mut Swarm<P2PBehaviour>, ctx: &mut P2PContext) {
+    let now = Instant::now();
+    let Some(reader) = ctx.chain_reader.clone() else {
+        return;
+    };
+    let tip = match reader.get_latest_block() {
+        Ok(Some(tip)) => tip,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to read latest block: {}", e);
+            return;
+        }
+    };
+    if !ctx.sync.is_stalled(tip.index, now) {
+        return;
+    }
+
+    metric::SYNC_STALLS.inc();
+    let status = ctx.sync.status(now);
+    ctx.notifier.notify(NodeEvent::SyncStalled {
+        tip_index: tip.index,
+        target: status.target,
+        idle_secs: status.idle.unwrap_or_default().as_secs(),
+    });
+    let peers: Vec<String> = ctx
+        .peers
+        .iter()
+        .map(|(peer_id, entry)| {
+            format!(
+                "{} head={:?} in_flight={} latency={:?} blocks_per_sec={:?}",
+                peer_id,
+                entry.head.as_ref().map(|head| head.index),
+                ctx.requests.in_flight(peer_id),
+                entry.latency,
+                entry.blocks_per_sec
+            )
+        })
+        .collect();
+    warn!(
+        "Sync stalled at block {}: {:?}; peers: [{}]",
+        tip.index,
+        status,
+        peers.join(", ")
+    );
+
+    let since = ctx.sync.last_progress().unwrap_or(now);
+    let requests = &ctx.requests;
+    let waiting = |peer_id: &PeerId| requests.in_flight(peer_id) > 0;
+    let stalled = ctx.peers.mark_sync_stalled(since, waiting, now);
+    debug!("Rotating sync away from {:?}", stalled);
+
+    let target = ctx.peers.best_head().map_or(0, |(_, head)| head.index);
+    if target <= tip.index {
+        info!(
+            "No peer is ahead of block {} any more; ending sync",
+            tip.index
+        );
+        ctx.sync.stop();
+        return;
+    }
+    ctx.sync.start(tip.index, tip.hash, target, now);
+    match ctx.select_sync_peer(tip.index + 1, DirectMessageType::GetBlockHeaders) {
+        Some(sync_peer) => request_next_headers(&sync_peer, swarm, ctx),
+        None => warn!("No peer to restart sync from block {} with", tip.index),
+    }
+}
+
+/// Our chain and `peer_id`'s differ at block `diverged`; starts looking for where they split.
+async fn start_ancestor_search(
+    diverged: usize,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    // A different genesis never gets past the handshake.
+    if diverged == 0 {
+        return;
+    }
+    warn!(
+        "Chain of {:?} differs from ours at block {}; searching for the common ancestor",
+        peer_id, diverged
+    );
+    let search = AncestorSearch::new(diverged);
+    continue_ancestor_search(search, peer_id, swarm, blockchain, ctx).await;
+}
+
+async fn continue_ancestor_search(
+    search: AncestorSearch,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    match search.next_step() {
+        AncestorStep::Probe(index) => {
+            ctx.peers.set_ancestor_search(peer_id, Some(search));
+            let probe = GetBlockHeaders {
+                start_block_index: index,
+                skip: 0,
+                limit: 1,
+                start_block_hash: String::new(),
+            };
+            let encoded_probe = encode_message(
+                DirectMessageType::GetBlockHeaders,
+                ctx.peers.wire_format(peer_id),
+                &probe,
+            );
+            send_sync_request(peer_id, encoded_probe, swarm, ctx);
+        }
+        AncestorStep::Found(ancestor) => {
+            ctx.peers.set_ancestor_search(peer_id, None);
+            ctx.forks.on_common_ancestor(peer_id, ancestor);
+            reorg_onto_peer(ancestor, peer_id, swarm, blockchain, ctx).await;
+        }
+    }
+}
+
+/// Our chain forked from `peer_id`'s after block `ancestor`. If the peer's chain is the
+/// longer one and ours may be unwound to the ancestor (`Blockchain::check_reorg`: not
+/// below the finalized block, nor deeper than the reorg policy's `max_depth`), downloads
+/// the peer's branch up to one block past our head. Nothing of ours is unwound until all
+/// of it is here; see `continue_branch_download`.
+async fn reorg_onto_peer(
+    ancestor: usize,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    let Some(peer_head) = ctx.peers.get(peer_id).and_then(|entry| entry.head.clone()) else {
+        return;
+    };
+    let Some(reader) = ctx.chain_reader.clone() else {
+        return;
+    };
+    let tip = match reader.get_latest_block() {
+        Ok(Some(tip)) => tip,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to read latest block: {}", e);
+            return;
+        }
+    };
+    if peer_head.index <= tip.index {
+        info!(
+            "Our chain forked from {:?}'s after block {} and is at least as long; keeping it",
+            peer_id, ancestor
+        );
+        return;
+    }
+    let ancestor_hash = match reader.get_block_hash(ancestor) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to read local block hash: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = blockchain.read().await.check_reorg(ancestor) {
+        warn!(
+            "Not following the longer chain of {:?} past block {}: {}",
+            peer_id, ancestor, e
+        );
+        return;
+    }
+    info!(
+        "Downloading the longer chain of {:?} above block {} before switching to it",
+        peer_id, ancestor
+    );
+    let download = BranchDownload::new(
+        ancestor,
+        ancestor_hash,
+        tip.index + 1,
+        MAX_BLOCKS_PER_REQUEST,
+    );
+    continue_branch_download(download, peer_id, swarm, blockchain, ctx);
+}
+
+/// Asks `peer_id` for the next part of the branch `download` is after: bodies for the
+/// headers we hold, otherwise more headers. Once all of it is here, the switch runs off
+/// the swarm task, since it replays the chain under the `Blockchain` write lock, and
+/// reports back to `on_branch_switch`.
+fn continue_branch_download(
+    download: BranchDownload,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    if download.is_complete() {
+        let (ancestor, branch) = download.into_branch();
+        let blockchain = Arc::clone(blockchain);
+        let switches = ctx.branch_switches.clone();
+        let peer_id = *peer_id;
+        tokio::spawn(async move {
+            let result = tokio::task::spawn_blocking(move || {
+                blockchain
+                    .blocking_write()
+                    .switch_to_branch(ancestor, &branch)
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Branch switch failed: {}", e)));
+            let switch = BranchSwitch {
+                peer_id,
+                ancestor,
+                result,
+            };
+            if switches.send(switch).await.is_err() {
+                debug!("P2P server stopped before the branch switch finished");
+            }
+        });
+        return;
+    }
+
+    let format = ctx.peers.wire_format(peer_id);
+    let missing = download.missing_bodies();
+    let request = if !missing.is_empty() {
+        let get_block_bodies = GetBlockBodies {
+            block_indexes: missing,
+        };
+        encode_message(DirectMessageType::GetBlockBodies, format, &get_block_bodies)
+    } else if let Some(get_block_headers) = download.next_headers() {
+        encode_message(
+            DirectMessageType::GetBlockHeaders,
+            format,
+            &get_block_headers,
+        )
+    } else {
+        return;
+    };
+    ctx.peers.set_branch_download(peer_id, download);
+    send_sync_request(peer_id, request, swarm, ctx);
+}
+
+/// Takes headers of the branch being downloaded from `peer_id`. A batch that doesn't
+/// chain onto what we have ends the download.
+fn download_branch_headers(
+    mut download: BranchDownload,
+    block_headers: BlockHeaders,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    if let Err(e) = download.on_headers(block_headers) {
+        warn!(
+            "Headers of the branch of {:?} above block {} don't check out: {}",
+            peer_id,
+            download.ancestor(),
+            e
+        );
+        return;
+    }
+    continue_branch_download(download, peer_id, swarm, blockchain, ctx);
+}
+
+/// Takes bodies of the branch being downloaded from `peer_id`. Like in sync, a body that
+/// doesn't match its header disconnects the peer.
+fn download_branch_bodies(
+    mut download: BranchDownload,
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    let blocks = match decode_block_bodies(payload, format)
+        .and_then(|blocks| blocks.collect::<Result<Vec<_>, _>>())
+    {
+        Ok(blocks) => blocks,
         Err(e) => {
             error!("Failed to decode BlockBodies: {:?}", e);
+            return;
         }
+    };
+    ctx.peers
+        .on_bodies_received(peer_id, blocks.len(), Instant::now());
+    if blocks.is_empty() {
+        warn!(
+            "Peer {:?} sent none of the bodies of its branch above block {}; giving up",
+            peer_id,
+            download.ancestor()
+        );
+        return;
+    }
+    if let Err(e) = download.on_bodies(blocks) {
+        warn!(
+            "Bodies of the branch of {:?} don't match their headers: {}",
+            peer_id, e
+        );
+        metric::SYNC_BAD_BODIES.inc();
+        send_disconnect(peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+        return;
     }
+    continue_branch_download(download, peer_id, swarm, blockchain, ctx);
 }
 
-async fn handshake_response(
-    _handshake: &Handshake,
-    blockchain: &Arc<Mutex<Blockchain>>,
-) -> Vec<u8> {
-    let blockchain = blockchain.lock().await;
-    match blockchain.handshake() {
-        Ok(response_handshake) => {
-            encode_message(DirectMessageType::Handshake, &response_handshake)
+/// Drops the branch being downloaded from `peer_id` after a request to it failed; the
+/// next fork check searches again.
+fn abandon_branch_download(peer_id: &PeerId, reason: &str, ctx: &mut P2PContext) {
+    if let Some(download) = ctx.peers.take_branch_download(peer_id) {
+        warn!(
+            "Giving up on the branch of {:?} above block {}: {}",
+            peer_id,
+            download.ancestor(),
+            reason
+        );
+    }
+}
+
+/// Picks sync back up from our new head once the chain switched to a peer's branch.
+/// Other peers may still be on the branch we left, so the first batch comes from the
+/// peer whose branch it was.
+pub fn on_branch_switch(
+    switch: BranchSwitch,
+    swarm: &mut Swarm<P2PBehaviour>,
+    ctx: &mut P2PContext,
+) {
+    let BranchSwitch {
+        peer_id,
+        ancestor,
+        result,
+    } = switch;
+    let removed = match result {
+        Ok(removed) => removed,
+        Err(e) => {
+            warn!(
+                "Not following the longer chain of {:?} past block {}: {}",
+                peer_id, ancestor, e
+            );
+            return;
         }
+    };
+    warn!(
+        "Replaced our {} blocks above block {} with the longer chain of {:?}",
+        removed, ancestor, peer_id
+    );
+
+    let Some(reader) = ctx.chain_reader.clone() else {
+        return;
+    };
+    let tip = match reader.get_latest_block() {
+        Ok(Some(tip)) => tip,
+        Ok(None) => return,
         Err(e) => {
-            error!("Failed to build handshake response: {}", e);
-            Vec::new()
+            error!("Failed to read latest block: {}", e);
+            return;
+        }
+    };
+    let target = ctx.peers.best_head().map_or(0, |(_, head)| head.index);
+    if !ctx.config.sync_enabled || target <= tip.index {
+        return;
+    }
+    let now = Instant::now();
+    if ctx.sync.is_running(now) {
+        ctx.sync.extend_target(target);
+        return;
+    }
+    ctx.sync.start(tip.index, tip.hash.clone(), target, now);
+    let get_block_headers = GetBlockHeaders {
+        start_block_index: tip.index,
+        skip: 1,
+        limit: MAX_BLOCKS_PER_REQUEST,
+        start_block_hash: tip.hash,
+    };
+    let encoded_headers = encode_message(
+        DirectMessageType::GetBlockHeaders,
+        ctx.peers.wire_format(&peer_id),
+        &get_block_headers,
+    );
+    ctx.sync.on_headers_requested();
+    send_sync_request(&peer_id, encoded_headers, swarm, ctx);
+}
+
+/// Handles the single-header answer to an ancestor search probe.
+async fn handle_ancestor_probe(
+    mut search: AncestorSearch,
+    block_headers: &BlockHeaders,
+    reader: &ChainReader,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    let AncestorStep::Probe(index) = search.next_step() else {
+        return;
+    };
+    let peer_hash = block_headers
+        .block_headers
+        .iter()
+        .find(|header| header.index == index)
+        .map(|header| header.hash.as_str());
+    let local_hash = reader.get_block_hash(index);
+    match (peer_hash, local_hash) {
+        (Some(peer_hash), Ok(Some(local_hash))) => {
+            search.record(peer_hash == local_hash);
+            continue_ancestor_search(search, peer_id, swarm, blockchain, ctx).await;
+        }
+        (_, Err(e)) => {
+            error!("Failed to read local block hash: {}", e);
+            ctx.peers.set_ancestor_search(peer_id, None);
+        }
+        _ => {
+            warn!(
+                "No header {} from {:?} for the common ancestor search; giving up",
+                index, peer_id
+            );
+            ctx.peers.set_ancestor_search(peer_id, None);
         }
     }
 }
 
+async fn handle_block_bodies_response(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    if let Some(download) = ctx.peers.take_branch_download(peer_id) {
+        download_branch_bodies(download, payload, format, peer_id, swarm, blockchain, ctx);
+        return;
+    }
+    match decode_block_bodies(payload, format) {
+        Ok(blocks) => {
+            import_bodies(
+                blocks,
+                MAX_BLOCKS_PER_REQUEST,
+                peer_id,
+                swarm,
+                blockchain,
+                ctx,
+            )
+            .await
+        }
+        Err(e) => {
+            error!("Failed to decode BlockBodies: {:?}", e);
+        }
+    }
+}
+
+/// Checks downloaded bodies against their verified headers and imports them, then asks for
+/// the next headers. More than `max_blocks` means the peer ignored what we asked for.
+async fn import_bodies(
+    blocks: impl ExactSizeIterator<Item = Result<Block, String>>,
+    max_blocks: usize,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    debug!("Received {} block bodies", blocks.len());
+    if blocks.len() > max_blocks {
+        warn!(
+            "Peer {:?} sent {} block bodies, more than the {} we ask for",
+            peer_id,
+            blocks.len(),
+            max_blocks
+        );
+        return;
+    }
+    ctx.peers
+        .on_bodies_received(peer_id, blocks.len(), Instant::now());
+
+    ctx.sync.on_bodies(Instant::now());
+
+    // Bodies without a verified header were not asked for by this run: they are left over
+    // from one that was stopped or restarted, and the batch is dropped.
+    let blocks: Vec<_> = blocks.collect();
+    let unexpected = blocks
+        .iter()
+        .flatten()
+        .find(|block| !ctx.sync.expects_body(block.index));
+    if let Some(block) = unexpected {
+        debug!(
+            "Dropping BlockBodies from {:?}: no header was verified for block {}",
+            peer_id, block.index
+        );
+        return;
+    }
+
+    // A body that doesn't match the header it was requested for means the peer
+    // advertised one chain and serves another; nothing of the batch is imported.
+    let mut checked = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        if let Ok(block) = &block {
+            if let Err(e) = ctx.sync.verify_body(block) {
+                warn!(
+                    "BlockBodies from {:?} don't match their headers: {}",
+                    peer_id, e
+                );
+                metric::SYNC_BAD_BODIES.inc();
+                ctx.sync.stop();
+                send_disconnect(peer_id, DisconnectReason::ProtocolViolation, swarm, ctx);
+                return;
+            }
+        }
+        checked.push(block);
+    }
+
+    let blockchain = blockchain.write().await;
+    import_synced_blocks(checked.into_iter(), &blockchain, ctx);
+    drop(blockchain);
+    request_next_headers(peer_id, swarm, ctx);
+}
+
+/// Completes the compact block the transactions were fetched for, imports it, and reports
+/// the gossip verdict that was held back until now.
+async fn handle_block_transactions_response(
+    payload: &[u8],
+    format: WireFormat,
+    peer_id: &PeerId,
+    swarm: &mut Swarm<P2PBehaviour>,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &mut P2PContext,
+) {
+    let block_transactions = match format.decode::<BlockTransactions>(payload) {
+        Ok(block_transactions) => block_transactions,
+        Err(e) => {
+            error!("Failed to decode BlockTransactions: {:?}", e);
+            return;
+        }
+    };
+    let Some(pending) = ctx
+        .compact_blocks
+        .take(&block_transactions.block_hash, peer_id)
+    else {
+        debug!(
+            "Ignoring BlockTransactions from {:?} for block {} we are not waiting on",
+            peer_id, block_transactions.block_hash
+        );
+        return;
+    };
+
+    let message_id = pending.message_id.clone();
+    let acceptance = match pending.complete(block_transactions.transactions) {
+        Ok(block) => {
+            debug!(
+                "Rebuilt compact block {} with transactions from {:?}",
+                block.index, peer_id
+            );
+            handle_received_block(&block, blockchain, ctx).await
+        }
+        Err(e) => {
+            warn!("Can't rebuild compact block from {:?}: {}", peer_id, e);
+            MessageAcceptance::Ignore
+        }
+    };
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .report_message_validation_result(&message_id, peer_id, acceptance);
+}
+
+/// Imports a `BlockBodies` batch, then any stashed batches that now follow the tip. With
+/// headers fetched ahead, a batch can arrive before the one below it; that one is held in
+/// the sync pipeline instead. Either way the pipeline's checkpoint is saved afterwards.
+fn import_synced_blocks(
+    blocks: impl Iterator<Item = Result<Block, String>>,
+    blockchain: &Blockchain,
+    ctx: &mut P2PContext,
+) {
+    let mut next_index = match blockchain.get_latest_block() {
+        Ok(Some(tip)) => tip.index + 1,
+        Ok(None) => 0,
+        Err(e) => {
+            error!("Failed to read latest block: {}", e);
+            return;
+        }
+    };
+
+    let mut blocks = blocks.peekable();
+    let first_index = match blocks.peek() {
+        Some(Ok(first)) => Some(first.index),
+        _ => None,
+    };
+    match first_index {
+        Some(first_index) if first_index > next_index => {
+            match blocks.collect::<Result<Vec<_>, _>>() {
+                Ok(batch) => {
+                    debug!(
+                        "Holding {} blocks from {} until block {} is imported",
+                        batch.len(),
+                        first_index,
+                        next_index
+                    );
+                    ctx.sync.stash(first_index, batch);
+                }
+                Err(e) => error!("Failed to decode block in BlockBodies: {:?}", e),
+            }
+        }
+        _ => {
+            let mut import = blockchain.bulk_import(ctx.config.sync_write_batch_size);
+            next_index = import_blocks(&mut import, blocks, next_index, ctx);
+            while let Some(batch) = ctx.sync.take_pending(next_index) {
+                next_index = import_blocks(&mut import, batch.into_iter().map(Ok), next_index, ctx);
+            }
+            if let Err(e) = import.finish() {
+                error!("{}", e);
+            }
+        }
+    }
+
+    let tip_index = next_index.saturating_sub(1);
+    let checkpoint = ctx.sync.checkpoint(tip_index);
+    if let Err(e) = blockchain.save_sync_checkpoint(checkpoint.as_ref()) {
+        error!("Failed to save sync checkpoint: {}", e);
+    }
+    if let Some(took) = ctx.sync.finish(tip_index, Instant::now()) {
+        info!("Sync completed at block {} in {:?}", tip_index, took);
+        ctx.notifier.notify(NodeEvent::SyncCompleted {
+            head_index: tip_index,
+            duration_secs: took.as_secs(),
+        });
+    }
+    // Gossiped blocks held for a parent may build on what sync just imported.
+    import_future_blocks(blockchain, ctx);
+}
+
+/// Imports `blocks` in order and returns the index after the last one imported.
+fn import_blocks(
+    import: &mut BulkImport<'_>,
+    blocks: impl Iterator<Item = Result<Block, String>>,
+    mut next_index: usize,
+    ctx: &mut P2PContext,
+) -> usize {
+    for block in blocks {
+        let block = match block {
+            Ok(block) => block,
+            Err(e) => {
+                error!("Failed to decode block in BlockBodies: {:?}", e);
+                break;
+            }
+        };
+        if ctx.seen.has_block(&block.hash) {
+            debug!(
+                "Skipping already imported block with index: {}",
+                block.index
+            );
+            next_index = next_index.max(block.index + 1);
+            continue;
+        }
+        match import.import_block(&block) {
+            Ok(_) => {
+                ctx.seen.mark_block(&block.hash);
+                next_index = block.index + 1;
+                debug!("Successfully imported block with index: {}", block.index);
+            }
+            Err(e) => {
+                error!("Failed to import block with index {}: {:?}", block.index, e);
+            }
+        }
+    }
+    next_index
+}
+
+/// Decodes a `BlockBodies` payload into its blocks. RLP is decoded block by block straight
+/// from the payload so an oversized or malformed response is rejected without
+/// materializing every block first; other formats are decoded whole.
+fn decode_block_bodies(
+    payload: &[u8],
+    format: WireFormat,
+) -> Result<Box<dyn ExactSizeIterator<Item = Result<Block, String>> + Send + '_>, String> {
+    match format {
+        WireFormat::Rlp => {
+            let blocks =
+                decode_list_field::<Block>(payload, 1, 0).map_err(|e| format!("{:?}", e))?;
+            Ok(Box::new(
+                blocks.map(|block| block.map_err(|e| format!("{:?}", e))),
+            ))
+        }
+        _ => {
+            let bodies = format.decode::<BlockBodies>(payload)?;
+            Ok(Box::new(bodies.blocks.into_iter().map(Ok)))
+        }
+    }
+}
+
+async fn handshake_response(
+    _handshake: &Handshake,
+    blockchain: &Arc<RwLock<Blockchain>>,
+    ctx: &P2PContext,
+) -> Result<Vec<u8>, NodeError> {
+    let response_handshake = local_handshake(blockchain, ctx).await?;
+    Ok(encode_message(
+        DirectMessageType::Handshake,
+        WireFormat::Rlp,
+        &response_handshake,
+    ))
+}
+
 /// Cap how many blocks a single peer request can pull, bounding CPU/memory/DB work.
 const MAX_BLOCKS_PER_REQUEST: usize = 100;
 
-async fn get_block_headers_response(
+fn get_block_headers_response(
     get_block_header: &GetBlockHeaders,
-    blockchain: &Arc<Mutex<Blockchain>>,
-) -> Vec<u8> {
-    let blockchain = blockchain.lock().await;
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
     let limit = get_block_header.limit.min(MAX_BLOCKS_PER_REQUEST);
-    let blocks = match blockchain.get_blocks_with_limit_and_skip(
+    if !get_block_header.start_block_hash.is_empty() {
+        match reader.get_block_hash(get_block_header.start_block_index)? {
+            Some(hash) if hash == get_block_header.start_block_hash => {}
+            _ => {
+                // The requester's tip isn't on our chain, so our next blocks don't extend it.
+                warn!(
+                    "GetBlockHeaders anchor {} at block {} is not on our chain",
+                    get_block_header.start_block_hash, get_block_header.start_block_index
+                );
+                return Ok(encode_message(
+                    DirectMessageType::BlockHeaders,
+                    format,
+                    &BlockHeaders {
+                        block_headers: Vec::new(),
+                    },
+                ));
+            }
+        }
+    }
+    let block_headers = reader.get_block_headers(
         get_block_header.start_block_index,
         get_block_header.skip,
         limit,
-    ) {
-        Ok(blocks) => blocks,
-        Err(e) => {
-            error!("Failed to get blocks for headers response: {}", e);
-            return Vec::new();
-        }
-    };
-
-    let block_headers: Vec<BlockHeader> =
-        blocks.iter().map(|block| block.to_block_header()).collect();
+    )?;
 
     let response_block_headers = BlockHeaders { block_headers };
-    encode_message(DirectMessageType::BlockHeaders, &response_block_headers)
+    Ok(encode_message(
+        DirectMessageType::BlockHeaders,
+        format,
+        &response_block_headers,
+    ))
 }
 
-async fn get_block_bodies_response(
+fn get_block_bodies_response(
     get_block_bodies: &GetBlockBodies,
-    blockchain: &Arc<Mutex<Blockchain>>,
-) -> Vec<u8> {
-    let blockchain = blockchain.lock().await;
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
     let indexes: Vec<usize> = get_block_bodies
         .block_indexes
         .iter()
         .copied()
         .take(MAX_BLOCKS_PER_REQUEST)
         .collect();
-    let blocks = match blockchain.get_blocks_by_indexes(indexes) {
-        Ok(blocks) => blocks,
+    let blocks = reader.get_blocks_by_indexes(&indexes)?;
+
+    let response_block_bodies = BlockBodies { blocks };
+    Ok(encode_message(
+        DirectMessageType::BlockBodies,
+        format,
+        &response_block_bodies,
+    ))
+}
+
+fn get_block_transactions_response(
+    get_block_transactions: &GetBlockTransactions,
+    format: WireFormat,
+    reader: &ChainReader,
+) -> Result<Vec<u8>, NodeError> {
+    let mut response = BlockTransactions {
+        block_hash: get_block_transactions.block_hash.clone(),
+        transactions: Vec::new(),
+    };
+    let block = match reader.get_blocks_by_indexes(&[get_block_transactions.block_index]) {
+        Ok(mut blocks) => blocks.pop(),
+        // Not imported (yet); the empty answer tells the requester to fall back to sync.
         Err(e) => {
-            error!("Failed to get blocks for bodies response: {}", e);
-            return Vec::new();
+            debug!("No block for transactions response: {}", e);
+            None
         }
     };
-
-    let response_block_bodies = BlockBodies { blocks };
-    encode_message(DirectMessageType::BlockBodies, &response_block_bodies)
+    if let Some(block) = block.filter(|block| block.hash == get_block_transactions.block_hash) {
+        response.transactions = get_block_transactions
+            .tx_indexes
+            .iter()
+            .filter_map(|index| block.transactions.get(*index).cloned())
+            .collect();
+    }
+    Ok(encode_message(
+        DirectMessageType::BlockTransactions,
+        format,
+        &response,
+    ))
 }
 
-fn encode_message<T: serde::Serialize + Encodable>(
+/// Frames `message` in `format`. An encoding failure is logged and yields an empty
+/// message, which the receiver already treats as a failed request.
+fn encode_message<T: RlpMessage>(
     message_type: DirectMessageType,
+    format: WireFormat,
     message: &T,
 ) -> Vec<u8> {
-    let encoded_message = encode(message);
-    let mut message_with_type = Vec::with_capacity(1 + encoded_message.len());
-    message_with_type.push(message_type.as_byte());
-    message_with_type.extend(encoded_message);
-    message_with_type
+    encode_frame(message_type, format, message).unwrap_or_else(|e| {
+        error!(
+            "Failed to encode {:?} as {}: {}",
+            message_type,
+            format.name(),
+            e
+        );
+        Vec::new()
+    })
 }
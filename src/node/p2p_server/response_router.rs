@@ -0,0 +1,123 @@
+use libp2p::request_response::OutboundRequestId;
+use std::collections::HashMap;
+use std::hash::Hash;
+use tokio::sync::oneshot;
+
+use super::behaviour::DirectMessageResponse;
+use super::commands::DirectMessageType;
+use super::wire_codec::decode_frame;
+use crate::node::rlp_encoding::RlpMessage;
+
+/// What a caller awaiting a direct request gets: the peer's response, or why none came.
+pub type Reply = Result<DirectMessageResponse, String>;
+
+/// Direct requests sent through `P2PServerCommand::Request`, keyed by request id, whose
+/// callers await the response instead of leaving it to the inline response handlers.
+/// Generic over the request id only so tests don't need a live swarm to mint
+/// `OutboundRequestId`s.
+#[derive(Debug)]
+pub struct ResponseRouter<R = OutboundRequestId> {
+    waiting: HashMap<R, oneshot::Sender<Reply>>,
+}
+
+impl<R> Default for ResponseRouter<R> {
+    fn default() -> Self {
+        Self {
+            waiting: HashMap::new(),
+        }
+    }
+}
+
+impl<R: Eq + Hash> ResponseRouter<R> {
+    pub fn register(&mut self, request_id: R, reply_tx: oneshot::Sender<Reply>) {
+        self.waiting.insert(request_id, reply_tx);
+    }
+
+    /// Hands `response` to the caller awaiting `request_id`. Gives it back when nobody
+    /// is, so it goes to the inline handlers. A caller that gave up drops the response.
+    pub fn resolve(
+        &mut self,
+        request_id: &R,
+        response: DirectMessageResponse,
+    ) -> Option<DirectMessageResponse> {
+        match self.waiting.remove(request_id) {
+            Some(reply_tx) => {
+                let _ = reply_tx.send(Ok(response));
+                None
+            }
+            None => Some(response),
+        }
+    }
+
+    /// Tells the caller awaiting `request_id`, if any, that the request failed.
+    pub fn fail(&mut self, request_id: &R, error: String) {
+        if let Some(reply_tx) = self.waiting.remove(request_id) {
+            let _ = reply_tx.send(Err(error));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+/// Decodes a routed response as `T`, in whichever format the peer answered with.
+/// Errors when the peer answered with some other message type than `expected`.
+pub fn decode_response<T: RlpMessage>(
+    response: &DirectMessageResponse,
+    expected: DirectMessageType,
+) -> Result<T, String> {
+    let Some((message_type, format, payload)) = decode_frame(&response.message) else {
+        return Err("empty response".to_string());
+    };
+    if message_type != Some(expected) {
+        return Err(format!("expected {:?}, got {:?}", expected, message_type));
+    }
+    let format = format.ok_or_else(|| "response in an unknown wire format".to_string())?;
+    format.decode(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::p2p_server::ping::Pong;
+    use crate::node::p2p_server::wire_codec::{encode_frame, WireFormat};
+
+    #[test]
+    fn responses_go_to_their_awaiting_caller_or_back_to_the_handlers() {
+        let mut router: ResponseRouter<u64> = ResponseRouter::default();
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+        let (failed_tx, mut failed_rx) = oneshot::channel();
+        router.register(1, reply_tx);
+        router.register(2, failed_tx);
+        let pong = DirectMessageResponse::new(
+            encode_frame(
+                DirectMessageType::Pong,
+                WireFormat::Cbor,
+                &Pong { nonce: 7 },
+            )
+            .unwrap(),
+        );
+
+        assert!(router.resolve(&1, pong.clone()).is_none());
+        assert!(
+            router.resolve(&3, pong).is_some(),
+            "nobody awaits request 3"
+        );
+        router.fail(&2, "connection closed".to_string());
+        assert!(router.is_empty());
+
+        let response = reply_rx.try_recv().unwrap().unwrap();
+        let decoded: Pong = decode_response(&response, DirectMessageType::Pong).unwrap();
+        assert_eq!(decoded.nonce, 7);
+        assert!(decode_response::<Pong>(&response, DirectMessageType::BlockHeaders).is_err());
+        assert_eq!(
+            failed_rx.try_recv().unwrap().unwrap_err(),
+            "connection closed"
+        );
+    }
+}
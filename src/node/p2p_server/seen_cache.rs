@@ -0,0 +1,130 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Hashes of blocks and transactions this node has already processed, kept for a bounded
+/// time. Gossipsub only deduplicates identical payloads, so the same block can still reach
+/// us from several peers (or from gossip and sync at once); this cache lets us skip the
+/// repeated validation and import.
+#[derive(Debug)]
+pub struct SeenCache {
+    blocks: TimedSet,
+    transactions: TimedSet,
+}
+
+impl SeenCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            blocks: TimedSet::new(ttl, capacity),
+            transactions: TimedSet::new(ttl, capacity),
+        }
+    }
+
+    pub fn has_block(&mut self, hash: &str) -> bool {
+        self.blocks.contains(hash, Instant::now())
+    }
+
+    pub fn mark_block(&mut self, hash: &str) {
+        self.blocks.insert(hash, Instant::now());
+    }
+
+    pub fn has_transaction(&mut self, hash: &str) -> bool {
+        self.transactions.contains(hash, Instant::now())
+    }
+
+    pub fn mark_transaction(&mut self, hash: &str) {
+        self.transactions.insert(hash, Instant::now());
+    }
+}
+
+#[derive(Debug)]
+struct TimedSet {
+    ttl: Duration,
+    capacity: usize,
+    entries: HashMap<String, Instant>,
+    order: VecDeque<(String, Instant)>,
+}
+
+impl TimedSet {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn contains(&mut self, key: &str, now: Instant) -> bool {
+        self.prune(now);
+        self.entries.contains_key(key)
+    }
+
+    fn insert(&mut self, key: &str, now: Instant) {
+        self.prune(now);
+        if self.capacity == 0 {
+            return;
+        }
+        // Re-marking refreshes the entry; the stale queue slot is skipped in `prune`.
+        self.entries.insert(key.to_string(), now);
+        self.order.push_back((key.to_string(), now));
+        while self.entries.len() > self.capacity {
+            self.pop_oldest();
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some((_, inserted)) = self.order.front() {
+            if now.duration_since(*inserted) < self.ttl {
+                break;
+            }
+            self.pop_oldest();
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some((key, inserted)) = self.order.pop_front() {
+            if self.entries.get(&key) == Some(&inserted) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let start = Instant::now();
+        let mut set = TimedSet::new(Duration::from_secs(10), 16);
+        set.insert("a", start);
+
+        assert!(set.contains("a", start + Duration::from_secs(9)));
+        assert!(!set.contains("a", start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn oldest_entry_is_evicted_at_capacity() {
+        let start = Instant::now();
+        let mut set = TimedSet::new(Duration::from_secs(60), 2);
+        set.insert("a", start);
+        set.insert("b", start);
+        set.insert("a", start + Duration::from_secs(1));
+        set.insert("c", start + Duration::from_secs(2));
+
+        let now = start + Duration::from_secs(3);
+        assert!(set.contains("a", now));
+        assert!(!set.contains("b", now));
+        assert!(set.contains("c", now));
+    }
+
+    #[test]
+    fn blocks_and_transactions_are_tracked_separately() {
+        let mut cache = SeenCache::new(Duration::from_secs(60), 16);
+        cache.mark_block("0xabc");
+
+        assert!(cache.has_block("0xabc"));
+        assert!(!cache.has_transaction("0xabc"));
+    }
+}
@@ -1,42 +1,84 @@
 use crate::node::blockchain::Blockchain;
+use crate::node::metric;
+use crate::node::rlp_encoding::{encode, RlpMessage};
+use crate::node::systemd::{Systemd, P2P_LISTENING, P2P_LOOP};
+use crate::node::transactions::transaction::Transaction;
+use crate::node::webhook::NodeEvent;
 use futures::stream::StreamExt;
-use futures::FutureExt;
+use futures::{AsyncRead, AsyncWrite, FutureExt};
 use libp2p::{
-    gossipsub::{self, Event as GossipsubEvent, IdentTopic, MessageId},
+    core::{muxing::StreamMuxerBox, transport::Boxed, upgrade, ConnectedPoint},
+    gossipsub::{self, Event as GossipsubEvent, IdentTopic, MessageAcceptance, MessageId},
+    identity::Keypair,
     mdns::{self, Event as MdnsEvent},
     noise,
+    pnet::{PnetConfig, PreSharedKey},
+    quic,
     request_response::{
-        cbor::Behaviour as RequestResponseBehavior, Config as RequestResponseConfig,
-        OutboundRequestId, ProtocolSupport as RequestResponseProtocolSupport,
+        Behaviour as CodecBehaviour, Config as RequestResponseConfig, OutboundRequestId,
+        ProtocolSupport as RequestResponseProtocolSupport, ResponseChannel,
     },
     swarm::{Swarm, SwarmEvent},
-    tcp, yamux, Multiaddr, PeerId, StreamProtocol,
+    tcp, websocket, yamux, Multiaddr, PeerId, Transport,
 };
-use tracing::{info,error};
+use tracing::{debug, error, info, warn};
 
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
 use std::error::Error as StdError;
 use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
 use tokio::{
     io, select,
-    sync::{oneshot, Mutex},
+    sync::{oneshot, RwLock},
 };
 
 use super::{
-    behaviour::{DirectMessageRequest, DirectMessageResponse, P2PBehaviourEvent},
+    bandwidth::BandwidthLimiter,
+    behaviour::{DirectMessageRequest, P2PBehaviourEvent},
+    branch_download::BranchSwitch,
     commands::DirectMessageType,
-    gossipsub_handler::handle_gossipsub_message,
-    request_response_handler::handle_request_response,
-    GossipMessageType, P2PBehaviour, P2PServerCommand,
+    config::P2PLimits,
+    connection_gate::{ip_of, parse_cidrs, ConnectionGate},
+    context::P2PContext,
+    disconnect::DisconnectReason,
+    fork_monitor::ForksSnapshot,
+    gossip_scoring::{peer_score_params, peer_score_thresholds},
+    gossipsub_handler::{handle_gossipsub_message, retry_future_blocks, GossipVerdict},
+    inbound_pool::{InboundPool, InboundResponse},
+    message_codec::{DirectMessageCodec, DIRECT_MESSAGE_PROTOCOLS},
+    peer_table::PeersSnapshot,
+    priority::Priority,
+    request_response_handler::{
+        check_sync_stall, handle_request_response, handle_sync_stream, on_branch_switch,
+        penalize_panicked_request, request_block_transactions, request_pooled_transactions,
+        send_disconnect, send_handshake, send_ping, send_request, send_response,
+    },
+    response_router::decode_response,
+    sync_stream::{SyncBlocks, SyncStreamCodec, SYNC_STREAM_PROTOCOL, SYNC_STREAM_TIMEOUT},
+    wire_codec::{encode_frame, frame_header, WireFormat},
+    GossipMessageType, P2PBehaviour, P2PConfig, P2PServerCommand,
 };
 
+const PEER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Ticks the announcement timer when announcements are disabled, to keep the select loop
+/// uniform; nothing is queued then, so the ticks do nothing.
+const IDLE_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a peer has to acknowledge our `Disconnect` before we close the connection anyway.
+const DISCONNECT_GRACE: Duration = Duration::from_secs(2);
+
 pub struct P2PServer {
     pub behaviour: Swarm<P2PBehaviour>,
     pub topic: IdentTopic,
+    ctx: P2PContext,
+    inbound_rx: tokio::sync::mpsc::Receiver<InboundResponse>,
+    sync_stream_rx:
+        tokio::sync::mpsc::Receiver<InboundResponse<ResponseChannel<SyncBlocks>, SyncBlocks>>,
+    branch_switch_rx: tokio::sync::mpsc::Receiver<BranchSwitch>,
+    bandwidth: Arc<BandwidthLimiter>,
 }
 
 impl P2PServer {
@@ -45,20 +87,55 @@ impl P2PServer {
         listen_addrs: &[&str],
         peer_addrs: &[&str],
     ) -> Result<Self, Box<dyn StdError>> {
-        let mut swarm = Self::build_swarm(listen_addrs)?;
-        let topic = Self::setup_gossipsub_topic(&mut swarm, topic_name)?;
+        Self::with_config(P2PConfig::new(topic_name, listen_addrs, peer_addrs))
+    }
+
+    pub fn with_config(config: P2PConfig) -> Result<Self, Box<dyn StdError>> {
+        let bandwidth = BandwidthLimiter::new(config.bandwidth);
+        let identity = Keypair::generate_ed25519();
+        let mut swarm = Self::build_swarm(&config, identity.clone(), Arc::clone(&bandwidth))?;
+        let topic = Self::setup_gossipsub_topic(&mut swarm, &config)?;
+
+        let mut bootstrap_addrs = Vec::new();
+        for peer in &config.bootstrap_nodes {
+            let addr = parse_multiaddr(peer, "bootstrap")?;
+            Swarm::dial(&mut swarm, addr.clone())?;
+            bootstrap_addrs.push(addr);
+        }
 
-        for peer in peer_addrs {
-            let addr: Multiaddr = peer.parse()?;
-            Swarm::dial(&mut swarm, addr)?;
+        let (inbound, inbound_rx) = InboundPool::new(config.max_concurrent_inbound_requests);
+        let (sync_streams, sync_stream_rx) =
+            InboundPool::new(config.max_concurrent_inbound_requests);
+        // A finished branch switch waits here until the swarm task takes its outcome.
+        let (branch_switches, branch_switch_rx) = tokio::sync::mpsc::channel(1);
+        let mut ctx = P2PContext::new(config, identity, inbound, sync_streams, branch_switches);
+        ctx.refresh_node_record(swarm.external_addresses());
+        // Known peers are a best effort on top of the bootnodes: a stale address just fails.
+        for addr in ctx.known_peers.best(ctx.config.reconnect_known_peers) {
+            if bootstrap_addrs.contains(&addr) {
+                continue;
+            }
+            if let Err(e) = Swarm::dial(&mut swarm, addr.clone()) {
+                debug!("Failed to dial known peer {}: {}", addr, e);
+            }
         }
 
         Ok(Self {
             behaviour: swarm,
             topic,
+            ctx,
+            inbound_rx,
+            sync_stream_rx,
+            branch_switch_rx,
+            bandwidth,
         })
     }
 
+    pub fn with_systemd(mut self, systemd: Systemd) -> Self {
+        self.ctx.systemd = systemd;
+        self
+    }
+
     pub async fn gossip_message_command(
         command_tx_p2p: Sender<P2PServerCommand>,
         message_type: GossipMessageType,
@@ -94,12 +171,10 @@ impl P2PServer {
     ) -> Result<OutboundRequestId, Box<dyn StdError>> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        let mut message_with_type = vec![message_type.as_byte()];
+        let mut message_with_type = frame_header(message_type, WireFormat::Rlp).to_vec();
         message_with_type.extend(message);
 
-        let direct_message = DirectMessageRequest {
-            message: message_with_type,
-        };
+        let direct_message = DirectMessageRequest::new(message_with_type);
 
         command_tx_p2p
             .send(P2PServerCommand::SendDirectMessage {
@@ -116,6 +191,30 @@ impl P2PServer {
         }
     }
 
+    /// Sends `request` to `peer_id` as a `request_type` message and awaits the peer's
+    /// `response_type` answer, decoded. Fails when the request fails, the connection
+    /// closes first, or the peer answers with anything else.
+    pub async fn request_command<Req: RlpMessage, Resp: RlpMessage>(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        peer_id: PeerId,
+        request_type: DirectMessageType,
+        request: &Req,
+        response_type: DirectMessageType,
+    ) -> Result<Resp, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let message = encode_frame(request_type, WireFormat::Rlp, request)?;
+        command_tx_p2p
+            .send(P2PServerCommand::Request {
+                peer_id,
+                message: DirectMessageRequest::new(message),
+                response_tx,
+            })
+            .await?;
+
+        let response = response_rx.await??;
+        Ok(decode_response(&response, response_type)?)
+    }
+
     #[allow(dead_code)]
     pub async fn get_local_peer_id_command(command_tx_p2p: Sender<P2PServerCommand>) -> PeerId {
         let (response_tx, response_rx) = oneshot::channel();
@@ -141,23 +240,185 @@ impl P2PServer {
         Ok(peers)
     }
 
+    pub async fn get_sync_peer_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+    ) -> Result<Option<PeerId>, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::GetSyncPeer { response_tx })
+            .await?;
+
+        Ok(response_rx.await?)
+    }
+
+    pub async fn get_peers_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+    ) -> Result<PeersSnapshot, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::GetPeers { response_tx })
+            .await?;
+
+        Ok(response_rx.await?)
+    }
+
+    pub async fn get_forks_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+    ) -> Result<ForksSnapshot, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::GetForks { response_tx })
+            .await?;
+
+        Ok(response_rx.await?)
+    }
+
+    /// Hands a transaction that entered our pool to the swarm to spread.
+    pub async fn broadcast_transaction_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        transaction: Transaction,
+    ) {
+        if let Err(e) = command_tx_p2p
+            .send(P2PServerCommand::BroadcastTransaction { transaction })
+            .await
+        {
+            error!("Failed to broadcast transaction: {}", e);
+        }
+    }
+
+    pub async fn dial_peer_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        address: Multiaddr,
+    ) -> Result<(), Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::DialPeer {
+                address,
+                response_tx,
+            })
+            .await?;
+
+        Ok(response_rx.await??)
+    }
+
+    pub async fn disconnect_peer_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        peer_id: PeerId,
+        reason: DisconnectReason,
+    ) -> Result<bool, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::DisconnectPeer {
+                peer_id,
+                reason,
+                response_tx,
+            })
+            .await?;
+
+        Ok(response_rx.await?)
+    }
+
+    pub async fn disconnect_all_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        reason: DisconnectReason,
+    ) -> Result<usize, Box<dyn StdError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        command_tx_p2p
+            .send(P2PServerCommand::DisconnectAll {
+                reason,
+                response_tx,
+            })
+            .await?;
+
+        Ok(response_rx.await?)
+    }
+
+    pub async fn apply_limits_command(
+        command_tx_p2p: Sender<P2PServerCommand>,
+        limits: P2PLimits,
+    ) -> Result<(), Box<dyn StdError>> {
+        command_tx_p2p
+            .send(P2PServerCommand::ApplyLimits { limits })
+            .await?;
+        Ok(())
+    }
+
     pub async fn run(
         &mut self,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         mut command_rx: tokio::sync::mpsc::Receiver<P2PServerCommand>,
-    ) -> Result<(), Box<dyn StdError>> {      
+    ) -> Result<(), Box<dyn StdError>> {
+        self.ctx.chain_reader = Some(blockchain.read().await.reader()?);
+        self.ctx.handshake = blockchain.read().await.handshake_snapshot();
+        self.ctx.hardforks = blockchain.read().await.hardforks().clone();
+        self.ctx.plugins = blockchain.read().await.plugins().clone();
+        self.ctx.notifier = blockchain.read().await.notifier().clone();
+        match blockchain.read().await.load_sync_checkpoint() {
+            Ok(Some(checkpoint)) => {
+                info!(
+                    "Found sync checkpoint at block {}; resuming on the next sync",
+                    checkpoint.head_index
+                );
+                self.ctx.sync.restore(checkpoint);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring sync checkpoint: {}", e),
+        }
         self.process_messages(blockchain, &mut command_rx).await
     }
 
-    fn build_swarm(listen_addrs: &[&str]) -> Result<Swarm<P2PBehaviour>, Box<dyn StdError>> {
-        let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+    fn build_swarm(
+        config: &P2PConfig,
+        identity: Keypair,
+        limiter: Arc<BandwidthLimiter>,
+    ) -> Result<Swarm<P2PBehaviour>, Box<dyn StdError>> {
+        if !config.bandwidth.is_unlimited() {
+            info!("P2P bandwidth limits (bytes/s): {:?}", config.bandwidth);
+        }
+
+        let swarm_key = config
+            .swarm_key_path
+            .as_deref()
+            .map(read_swarm_key)
+            .transpose()?;
+        if let Some(swarm_key) = &swarm_key {
+            info!(
+                "Private network enabled, swarm key fingerprint {}",
+                swarm_key.fingerprint()
+            );
+            if let Some(addr) = config
+                .listen_addrs
+                .iter()
+                .find(|addr| addr.contains("/quic"))
+            {
+                return Err(format!(
+                    "QUIC listen address {} can't be used on a private network",
+                    addr
+                )
+                .into());
+            }
+            if let Some(addr) = config.websocket_listen_addrs.first() {
+                return Err(format!(
+                    "WebSocket listen address {} can't be used on a private network",
+                    addr
+                )
+                .into());
+            }
+        }
+
+        let gate = ConnectionGate::new(
+            parse_cidrs(&config.allowed_cidrs, "allowed")?,
+            parse_cidrs(&config.denied_cidrs, "denied")?,
+        )
+        .with_inbound_limits(config.max_inbound_per_ip, config.max_inbound_per_subnet);
+
+        let mut swarm = libp2p::SwarmBuilder::with_existing_identity(identity)
             .with_tokio()
-            .with_tcp(
-                tcp::Config::default(),
-                noise::Config::new,
-                yamux::Config::default,
-            )?
-            .with_quic()
+            .with_other_transport(|key| {
+                let websocket = !config.websocket_listen_addrs.is_empty();
+                build_transport(key, limiter, swarm_key, websocket)
+            })?
+            .with_dns()?
             .with_behaviour(|key| {
                 let message_id_fn = |message: &gossipsub::Message| {
                     let mut s = DefaultHasher::new();
@@ -165,9 +426,13 @@ impl P2PServer {
                     gossipsub::MessageId::from(s.finish().to_string())
                 };
 
+                // Messages are only forwarded once `handle_gossipsub_message` reports them
+                // valid, so malformed gossip stops at the first honest hop and its sender
+                // pays the invalid-delivery penalty.
                 let gossipsub_config = gossipsub::ConfigBuilder::default()
                     .heartbeat_interval(Duration::from_secs(10))
                     .validation_mode(gossipsub::ValidationMode::Strict)
+                    .validate_messages()
                     .message_id_fn(message_id_fn)
                     .build()
                     .map_err(|msg| io::Error::new(io::ErrorKind::Other, msg))?;
@@ -182,25 +447,42 @@ impl P2PServer {
                     key.public().to_peer_id(),
                 )?;
 
-                let rr_config = RequestResponseConfig::default();
-                let rr_protocol = StreamProtocol::new("/agent/message/1.0.0");
-                let rr_behavior =
-                    RequestResponseBehavior::<DirectMessageRequest, DirectMessageResponse>::new(
-                        [(rr_protocol, RequestResponseProtocolSupport::Full)],
-                        rr_config,
-                    );
+                let rr_behavior = CodecBehaviour::with_codec(
+                    DirectMessageCodec::default(),
+                    DIRECT_MESSAGE_PROTOCOLS
+                        .map(|protocol| (protocol, RequestResponseProtocolSupport::Full)),
+                    RequestResponseConfig::default(),
+                );
+
+                let sync_stream = CodecBehaviour::with_codec(
+                    SyncStreamCodec,
+                    [(SYNC_STREAM_PROTOCOL, RequestResponseProtocolSupport::Full)],
+                    RequestResponseConfig::default().with_request_timeout(SYNC_STREAM_TIMEOUT),
+                );
 
                 Ok(P2PBehaviour {
+                    gate,
                     gossipsub,
                     mdns,
                     request_response: rr_behavior,
+                    sync_stream,
                 })
             })?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
-        for addr in listen_addrs {
-            swarm.listen_on(addr.parse()?)?;
+        for addr in &config.listen_addrs {
+            swarm.listen_on(parse_multiaddr(addr, "listen")?)?;
+        }
+        for addr in &config.websocket_listen_addrs {
+            let addr = parse_multiaddr(addr, "WebSocket listen")?;
+            info!("Accepting browser light clients on {addr}");
+            swarm.listen_on(addr)?;
+        }
+        for addr in &config.external_addrs {
+            let addr = parse_multiaddr(addr, "external")?;
+            info!("Advertising external address {addr}");
+            swarm.add_external_address(addr);
         }
 
         Ok(swarm)
@@ -208,43 +490,152 @@ impl P2PServer {
 
     fn setup_gossipsub_topic(
         swarm: &mut Swarm<P2PBehaviour>,
-        topic_name: &str,
+        config: &P2PConfig,
     ) -> Result<IdentTopic, Box<dyn StdError>> {
-        let topic = IdentTopic::new(topic_name);
+        let topic = IdentTopic::new(config.scoped_topic_name());
+
+        if config.peer_scoring_enabled {
+            swarm
+                .behaviour_mut()
+                .gossipsub
+                .with_peer_score(peer_score_params(topic.hash()), peer_score_thresholds())?;
+        }
+
         swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        info!("Subscribed to gossip topic {}", topic);
         Ok(topic)
     }
 
     async fn process_messages(
         &mut self,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         command_rx: &mut tokio::sync::mpsc::Receiver<P2PServerCommand>,
     ) -> Result<(), Box<dyn StdError>> {
+        let mut peer_check = tokio::time::interval(PEER_CHECK_INTERVAL);
+        let mut ping = tokio::time::interval(self.ctx.config.ping_interval);
+        let announce_interval = Some(self.ctx.config.tx_announce_interval)
+            .filter(|interval| !interval.is_zero())
+            .unwrap_or(IDLE_ANNOUNCE_INTERVAL);
+        let mut announce = tokio::time::interval(announce_interval);
+
         loop {
+            self.ctx.systemd.beat(P2P_LOOP);
+            // Biased, so swarm events and commands (new blocks, votes) always go first and
+            // bulk sync traffic backed up behind them waits for a quiet moment.
             select! {
+                biased;
                 event = self.behaviour.select_next_some().fuse() => {
-                    Self::handle_swarm_event(event, &mut self.behaviour, &blockchain).await;
+                    Self::handle_swarm_event(event, &mut self.behaviour, &blockchain, &mut self.ctx)
+                        .await;
+                },
+                Some(command) = command_rx.recv() => {
+                    match command {
+                        P2PServerCommand::SendGossipMessage { message, response_tx } => {
+                            let result = self.send_gossip_message(message);
+                            let _ = response_tx.send(result);
+                        },
+                        P2PServerCommand::GetConnectedPeers { response_tx } => {
+                            let peers = self.get_connected_peers();
+                            let _ = response_tx.send(peers);
+                        },
+                        P2PServerCommand::SendDirectMessage { peer_id, message, response_tx } => {
+                            let result = self.send_direct_message(&peer_id, message);
+                            let _ = response_tx.send(result);
+                        },
+                        P2PServerCommand::Request { peer_id, message, response_tx } => {
+                            let request_id = self.send_direct_message(&peer_id, message);
+                            self.ctx.responses.register(request_id, response_tx);
+                        },
+                        P2PServerCommand::GetLocalPeerId { response_tx } => {
+                            let peer_id = self.get_local_peer_id();
+                            let _ = response_tx.send(peer_id);
+                        },
+                        P2PServerCommand::GetSyncPeer { response_tx } => {
+                            let peer_id = self
+                                .ctx
+                                .select_sync_peer(0, DirectMessageType::GetBlockHeaders)
+                                .or_else(|| self.behaviour.connected_peers().next().cloned());
+                            let _ = response_tx.send(peer_id);
+                        },
+                        P2PServerCommand::GetPeers { response_tx } => {
+                            let _ = response_tx.send(self.ctx.peers.snapshot(Instant::now()));
+                        },
+                        P2PServerCommand::GetForks { response_tx } => {
+                            let _ = response_tx.send(self.ctx.forks.snapshot());
+                        },
+                        P2PServerCommand::BroadcastTransaction { transaction } => {
+                            self.broadcast_transaction(transaction);
+                        },
+                        P2PServerCommand::DialPeer { address, response_tx } => {
+                            let result = self.dial_peer(address);
+                            let _ = response_tx.send(result);
+                        },
+                        P2PServerCommand::DisconnectPeer { peer_id, reason, response_tx } => {
+                            let connected = self.disconnect_peer(&peer_id, reason);
+                            let _ = response_tx.send(connected);
+                        },
+                        P2PServerCommand::DisconnectAll { reason, response_tx } => {
+                            let count = self.disconnect_all(reason);
+                            let _ = response_tx.send(count);
+                        },
+                        P2PServerCommand::ApplyLimits { limits } => {
+                            self.apply_limits(limits);
+                        },
+                    }
+                },
+                Some(response) = self.inbound_rx.recv() => {
+                    debug!("Sending pooled response to peer {}", response.peer_id);
+                    if response.panicked {
+                        penalize_panicked_request(
+                            &response.peer_id,
+                            &mut self.behaviour,
+                            &mut self.ctx,
+                        );
+                    }
+                    send_response(response.message, &mut self.behaviour, response.channel);
                 },
-                command = command_rx.recv() => {
-                    if let Some(command) = command {
-                        match command {
-                            P2PServerCommand::SendGossipMessage { message, response_tx } => {
-                                let result = self.send_gossip_message(message);
-                                let _ = response_tx.send(result);
-                            },
-                            P2PServerCommand::GetConnectedPeers { response_tx } => {
-                                let peers = self.get_connected_peers();
-                                let _ = response_tx.send(peers);
-                            },
-                            P2PServerCommand::SendDirectMessage { peer_id, message, response_tx } => {
-                                let result = self.send_direct_message(&peer_id, message);
-                                let _ = response_tx.send(result);
-                            },
-                            P2PServerCommand::GetLocalPeerId { response_tx } => {
-                                let peer_id = self.get_local_peer_id();
-                                let _ = response_tx.send(peer_id);
-                            },
-                        }
+                Some(response) = self.sync_stream_rx.recv() => {
+                    debug!("Sending sync stream blocks to peer {}", response.peer_id);
+                    if response.panicked {
+                        penalize_panicked_request(
+                            &response.peer_id,
+                            &mut self.behaviour,
+                            &mut self.ctx,
+                        );
+                    }
+                    if self
+                        .behaviour
+                        .behaviour_mut()
+                        .sync_stream
+                        .send_response(response.channel, response.message)
+                        .is_err()
+                    {
+                        debug!("Peer {} gave up on its sync stream", response.peer_id);
+                    }
+                },
+                Some(switch) = self.branch_switch_rx.recv() => {
+                    on_branch_switch(switch, &mut self.behaviour, &mut self.ctx);
+                },
+                _ = peer_check.tick() => {
+                    self.check_peers();
+                    self.check_forks();
+                    retry_future_blocks(&blockchain, &mut self.ctx).await;
+                },
+                _ = ping.tick() => {
+                    self.ping_peers();
+                },
+                _ = announce.tick() => {
+                    self.announce_transactions();
+                },
+                _ = std::future::ready(()), if !self.ctx.bulk_backlog.is_empty() => {
+                    if let Some(event) = self.ctx.bulk_backlog.pop_front() {
+                        handle_request_response(
+                            event,
+                            &mut self.behaviour,
+                            &blockchain,
+                            &mut self.ctx,
+                        )
+                        .await;
                     }
                 },
             }
@@ -261,6 +652,159 @@ impl P2PServer {
             .publish(self.topic.clone(), message)
     }
 
+    /// Gossips `transaction` whole, or queues its hash for the next announcement.
+    fn broadcast_transaction(&mut self, transaction: Transaction) {
+        self.ctx.seen.mark_transaction(&transaction.hash);
+        if !self.ctx.config.tx_announce_interval.is_zero() {
+            self.ctx.tx_announcer.queue(transaction.hash);
+            return;
+        }
+        let mut message = vec![GossipMessageType::Transaction.as_byte()];
+        message.extend(encode(&transaction));
+        if let Err(e) = self.send_gossip_message(message) {
+            error!("Failed to gossip transaction {}: {:?}", transaction.hash, e);
+        }
+    }
+
+    /// Gossips the hashes of transactions added to our pool since the last tick.
+    fn announce_transactions(&mut self) {
+        for announcement in self.ctx.tx_announcer.drain_batches() {
+            debug!("Announcing {} transactions", announcement.hashes.len());
+            let mut message = vec![GossipMessageType::TransactionHashes.as_byte()];
+            message.extend(encode(&announcement));
+            if let Err(e) = self.send_gossip_message(message) {
+                debug!("Failed to announce transactions: {:?}", e);
+            }
+        }
+    }
+
+    /// Drops peers that connected but never completed a handshake within the configured
+    /// window, closes connections whose `Disconnect` was never acknowledged, gives up on
+    /// compact blocks whose missing transactions never arrived, and restarts a stalled sync.
+    fn check_peers(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .ctx
+            .peers
+            .handshake_expired(now, self.ctx.config.handshake_timeout);
+
+        for peer_id in expired {
+            send_disconnect(
+                &peer_id,
+                DisconnectReason::HandshakeTimeout,
+                &mut self.behaviour,
+                &mut self.ctx,
+            );
+        }
+
+        for peer_id in self.ctx.peers.disconnect_overdue(now, DISCONNECT_GRACE) {
+            let _ = self.behaviour.disconnect_peer_id(peer_id);
+        }
+
+        for pending in self.ctx.compact_blocks.expire(now) {
+            debug!(
+                "Transactions for a compact block from {} never arrived; leaving it to sync",
+                pending.source
+            );
+            self.behaviour
+                .behaviour_mut()
+                .gossipsub
+                .report_message_validation_result(
+                    &pending.message_id,
+                    &pending.source,
+                    MessageAcceptance::Ignore,
+                );
+        }
+
+        check_sync_stall(&mut self.behaviour, &mut self.ctx);
+    }
+
+    /// Refreshes the competing branches from handshaked peers' heads, posting a split
+    /// that has lasted `fork_warn_after`.
+    fn check_forks(&mut self) {
+        let Some(reader) = self.ctx.chain_reader.as_ref() else {
+            return;
+        };
+        let head = match reader.get_latest_block() {
+            Ok(Some(head)) => head,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to read our head to check for forks: {}", e);
+                return;
+            }
+        };
+        let peers = self.ctx.peers.iter().filter_map(|(peer_id, entry)| {
+            entry.handshaked_at?;
+            Some((peer_id, entry.head.as_ref()?))
+        });
+        let split = self.ctx.forks.observe(
+            head.index,
+            &head.hash,
+            peers,
+            |index| reader.get_block_hash(index).ok().flatten(),
+            Instant::now(),
+        );
+        if let Some(event) = split {
+            self.ctx.notifier.notify(event);
+        }
+    }
+
+    fn ping_peers(&mut self) {
+        let round = self
+            .ctx
+            .peers
+            .ping_round(Instant::now(), self.ctx.config.max_missed_pings);
+
+        for peer_id in round.unresponsive {
+            send_disconnect(
+                &peer_id,
+                DisconnectReason::PingTimeout,
+                &mut self.behaviour,
+                &mut self.ctx,
+            );
+        }
+        for (peer_id, nonce) in round.pings {
+            send_ping(&peer_id, nonce, &mut self.behaviour, &mut self.ctx);
+        }
+    }
+
+    /// Lowered limits turn away new peers and requests; connected peers are kept, along
+    /// with their per-connection bandwidth caps.
+    fn apply_limits(&mut self, limits: P2PLimits) {
+        info!("Applying P2P limits: {:?}", limits);
+        self.behaviour
+            .behaviour_mut()
+            .gate
+            .set_inbound_limits(limits.max_inbound_per_ip, limits.max_inbound_per_subnet);
+        self.bandwidth.set_limits(limits.bandwidth);
+        self.ctx.requests.set_limits(
+            limits.max_in_flight_requests_per_peer,
+            limits.max_queued_requests_per_peer,
+        );
+        self.ctx.config.set_limits(limits);
+    }
+
+    fn dial_peer(&mut self, address: Multiaddr) -> Result<(), String> {
+        info!("Dialing {}", address);
+        Swarm::dial(&mut self.behaviour, address).map_err(|e| e.to_string())
+    }
+
+    fn disconnect_peer(&mut self, peer_id: &PeerId, reason: DisconnectReason) -> bool {
+        if !self.behaviour.is_connected(peer_id) {
+            return false;
+        }
+        send_disconnect(peer_id, reason, &mut self.behaviour, &mut self.ctx);
+        true
+    }
+
+    fn disconnect_all(&mut self, reason: DisconnectReason) -> usize {
+        let peers: Vec<PeerId> = self.behaviour.connected_peers().cloned().collect();
+        for peer_id in &peers {
+            send_disconnect(peer_id, reason, &mut self.behaviour, &mut self.ctx);
+        }
+        peers.len()
+    }
+
     fn get_connected_peers(&self) -> HashSet<PeerId> {
         self.behaviour.connected_peers().cloned().collect()
     }
@@ -270,10 +814,7 @@ impl P2PServer {
         peer_id: &PeerId,
         message: DirectMessageRequest,
     ) -> libp2p::request_response::OutboundRequestId {
-        self.behaviour
-            .behaviour_mut()
-            .request_response
-            .send_request(&peer_id, message)
+        send_request(peer_id, message.message, &mut self.behaviour, &mut self.ctx)
     }
 
     fn get_local_peer_id(&self) -> PeerId {
@@ -283,9 +824,58 @@ impl P2PServer {
     async fn handle_swarm_event(
         event: SwarmEvent<P2PBehaviourEvent>,
         swarm: &mut Swarm<P2PBehaviour>,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
+        ctx: &mut P2PContext,
     ) {
         match event {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                num_established,
+                endpoint,
+                ..
+            } => {
+                ctx.peers.on_connected(peer_id, Instant::now());
+                ctx.peers
+                    .set_remote_ip(&peer_id, ip_of(endpoint.get_remote_address()));
+                if let ConnectedPoint::Dialer { address, .. } = endpoint {
+                    ctx.peers.set_dialed_address(&peer_id, address);
+                }
+                metric::CONNECTED_PEERS.set(ctx.peers.len() as i64);
+                if num_established.get() == 1 {
+                    if ctx.peers.len() > ctx.config.max_peers {
+                        send_disconnect(&peer_id, DisconnectReason::TooManyPeers, swarm, ctx);
+                    } else {
+                        ctx.plugins.peer_connected(&peer_id);
+                        send_handshake(&peer_id, swarm, blockchain, ctx).await;
+                    }
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => {
+                if ctx.peers.is_handshaked(&peer_id) {
+                    ctx.remember_peer(&peer_id, true);
+                }
+                let connected = ctx.peers.len();
+                ctx.peers.on_disconnected(&peer_id);
+                ctx.forks.on_disconnected(&peer_id);
+                let dropped = ctx.requests.on_disconnected(&peer_id);
+                if dropped > 0 {
+                    debug!("Dropped {} queued sync requests for {}", dropped, peer_id);
+                }
+                metric::CONNECTED_PEERS.set(ctx.peers.len() as i64);
+                if ctx.peers.len() < ctx.config.min_peers && connected >= ctx.config.min_peers {
+                    ctx.notifier.notify(NodeEvent::LowPeerCount {
+                        peers: ctx.peers.len(),
+                        min_peers: ctx.config.min_peers,
+                    });
+                }
+                metric::PEER_LATENCY_MS.remove(&metric::PeerLabels {
+                    peer_id: peer_id.to_string(),
+                });
+            }
             SwarmEvent::Behaviour(P2PBehaviourEvent::Mdns(MdnsEvent::Discovered(list))) => {
                 Self::handle_mdns_discovered(swarm, list);
             }
@@ -297,18 +887,63 @@ impl P2PServer {
                 message_id: id,
                 message,
             })) => {
-                handle_gossipsub_message(peer_id, id, message, blockchain).await;
+                match handle_gossipsub_message(peer_id, id.clone(), message, blockchain, ctx).await
+                {
+                    GossipVerdict::Report(acceptance) => {
+                        swarm
+                            .behaviour_mut()
+                            .gossipsub
+                            .report_message_validation_result(&id, &peer_id, acceptance);
+                    }
+                    GossipVerdict::FetchTransactions(request) => {
+                        request_block_transactions(&peer_id, &request, swarm, ctx);
+                    }
+                    GossipVerdict::FetchPooledTransactions(request) => {
+                        swarm
+                            .behaviour_mut()
+                            .gossipsub
+                            .report_message_validation_result(
+                                &id,
+                                &peer_id,
+                                MessageAcceptance::Ignore,
+                            );
+                        request_pooled_transactions(&peer_id, &request, swarm, ctx);
+                    }
+                }
             }
             SwarmEvent::Behaviour(P2PBehaviourEvent::RequestResponse(event)) => {
-                handle_request_response(event, swarm, blockchain).await;
+                if Priority::of_event(&event) == Priority::Bulk {
+                    ctx.bulk_backlog.push_back(event);
+                } else {
+                    handle_request_response(event, swarm, blockchain, ctx).await;
+                }
+            }
+            SwarmEvent::Behaviour(P2PBehaviourEvent::SyncStream(event)) => {
+                handle_sync_stream(event, swarm, blockchain, ctx).await;
             }
             SwarmEvent::NewListenAddr { address, .. } => {
                 info!("Local node is listening on {address}");
+                ctx.systemd.ready(P2P_LISTENING);
+                Self::refresh_node_record(swarm, ctx);
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                info!("Local node stopped listening on {address}");
+                Self::refresh_node_record(swarm, ctx);
             }
             _ => {}
         }
     }
 
+    /// Re-signs our `NodeRecord` with the addresses we advertise and listen on. Wildcard
+    /// addresses are left out; a peer can't dial them.
+    fn refresh_node_record(swarm: &Swarm<P2PBehaviour>, ctx: &mut P2PContext) {
+        let addresses = swarm
+            .external_addresses()
+            .chain(swarm.listeners())
+            .filter(|address| !ip_of(address).is_some_and(|ip| ip.is_unspecified()));
+        ctx.refresh_node_record(addresses);
+    }
+
     fn handle_mdns_discovered(swarm: &mut Swarm<P2PBehaviour>, list: Vec<(PeerId, Multiaddr)>) {
         for (peer_id, _multiaddr) in list {
             info!("mDNS discovered a new peer: {peer_id}");
@@ -326,3 +961,100 @@ impl P2PServer {
         }
     }
 }
+
+/// Reads a private network's pre-shared key from a file in the IPFS `swarm.key` layout
+/// (`/key/swarm/psk/1.0.0/`, `/base16/`, 64 hex digits), so go-ipfs and kubo keys work.
+fn read_swarm_key(path: &Path) -> Result<PreSharedKey, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read swarm key {}: {}", path.display(), e))?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid swarm key {}: {}", path.display(), e))
+}
+
+fn parse_multiaddr(addr: &str, kind: &str) -> Result<Multiaddr, Box<dyn StdError>> {
+    addr.parse()
+        .map_err(|e| format!("Invalid {} address {:?}: {}", kind, addr, e).into())
+}
+
+/// The network transport, plus `/ws` addresses when `websocket` is set, and `/memory/<port>`
+/// addresses when built with `test-utils`, so test nodes in one process can connect
+/// without sockets.
+fn build_transport(
+    key: &Keypair,
+    limiter: Arc<BandwidthLimiter>,
+    swarm_key: Option<PreSharedKey>,
+    websocket: bool,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn StdError + Send + Sync>> {
+    let transport = network_transport(key, Arc::clone(&limiter), swarm_key)?;
+    let transport = if websocket {
+        websocket_transport(key, limiter)?
+            .or_transport(transport)
+            .map(|either, _| either.into_inner())
+            .boxed()
+    } else {
+        transport
+    };
+    #[cfg(feature = "test-utils")]
+    let transport = secure_and_multiplex(libp2p::core::transport::MemoryTransport::default(), key)?
+        .or_transport(transport)
+        .map(|either, _| either.into_inner())
+        .boxed();
+    Ok(transport)
+}
+
+/// TCP + noise + yamux, with every raw connection wrapped in the bandwidth limiter and, on
+/// a private network, in libp2p's pnet XSalsa20 layer keyed by the swarm key. QUIC manages
+/// its own UDP sockets, so it is neither throttled nor available on a private network.
+fn network_transport(
+    key: &Keypair,
+    limiter: Arc<BandwidthLimiter>,
+    swarm_key: Option<PreSharedKey>,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn StdError + Send + Sync>> {
+    let tcp = tcp::tokio::Transport::new(tcp::Config::default())
+        .map(move |stream, _| limiter.throttle(stream));
+    let Some(swarm_key) = swarm_key else {
+        let quic = quic::tokio::Transport::new(quic::Config::new(key))
+            .map(|(peer_id, connection), _| (peer_id, StreamMuxerBox::new(connection)));
+        return Ok(secure_and_multiplex(tcp, key)?
+            .or_transport(quic)
+            .map(|either, _| either.into_inner())
+            .boxed());
+    };
+    secure_and_multiplex(
+        tcp.and_then(move |stream, _| PnetConfig::new(swarm_key).handshake(stream)),
+        key,
+    )
+}
+
+/// WebSocket over throttled TCP, for browsers: js-libp2p speaks noise and yamux over it
+/// as over plain TCP. Browsers can't open WebTransport or QUIC to a rust-libp2p node, and
+/// can't hold a swarm key, so this is their only way in.
+fn websocket_transport(
+    key: &Keypair,
+    limiter: Arc<BandwidthLimiter>,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn StdError + Send + Sync>> {
+    let tcp = tcp::tokio::Transport::new(tcp::Config::default())
+        .map(move |stream, _| limiter.throttle(stream));
+    secure_and_multiplex(websocket::Config::new(tcp), key)
+}
+
+fn secure_and_multiplex<T>(
+    transport: T,
+    key: &Keypair,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn StdError + Send + Sync>>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    Ok(transport
+        .upgrade(upgrade::Version::V1Lazy)
+        .authenticate(noise::Config::new(key)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+        .boxed())
+}
@@ -0,0 +1,479 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::node::blocks::block::Block;
+use crate::node::blocks::block_headers::BlockHeader;
+
+/// A run that has accepted no headers and imported no bodies for this long is treated as
+/// dead (a request failed or the peer left), and the next handshake or the stall watchdog
+/// starts a new one. Overridden by `with_stall_timeout`.
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a sync run persists so a restarted node resumes where it left off. The chain tip
+/// is the durable record of the last fully imported block (bulk imports commit
+/// atomically), so this only holds what lies past it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncCheckpoint {
+    pub head_index: usize,
+    pub head_hash: String,
+    pub target: usize,
+    /// Downloaded body batches that were waiting on the batches below them.
+    pub pending: Vec<Vec<Block>>,
+    /// Verified headers whose bodies hadn't arrived, so resumed downloads are checked
+    /// against them too. Checkpoints from before this field have none.
+    #[serde(default)]
+    pub headers: Vec<BlockHeader>,
+}
+
+impl SyncCheckpoint {
+    /// Whether every block past `tip_index` up to the head has its body held or a header
+    /// to check the re-downloaded body against. A checkpoint from before headers were
+    /// saved doesn't, and is dropped for a fresh run from the tip.
+    pub fn can_resume(&self, tip_index: usize) -> bool {
+        let headers: HashSet<usize> = self.headers.iter().map(|header| header.index).collect();
+        (tip_index + 1..=self.head_index).all(|index| {
+            headers.contains(&index)
+                || self.pending.iter().any(|batch| {
+                    batch.first().is_some_and(|first| {
+                        (first.index..first.index + batch.len()).contains(&index)
+                    })
+                })
+        })
+    }
+}
+
+/// Progress of a header/body download. The next batch of headers is requested as soon as
+/// a batch is verified, while its bodies are still downloading and importing, up to
+/// `depth` body batches ahead of the chain.
+#[derive(Debug)]
+pub struct SyncPipeline {
+    depth: usize,
+    /// Index and hash of the last verified header. Ahead of the chain tip while bodies
+    /// are outstanding.
+    head: Option<(usize, String)>,
+    /// Height we are catching up to, from the peers' handshakes.
+    target: usize,
+    headers_in_flight: bool,
+    bodies_in_flight: usize,
+    /// Body batches that arrived before the batch below them was imported, by first index.
+    pending: BTreeMap<usize, Vec<Block>>,
+    /// Verified headers whose bodies haven't arrived yet, by index.
+    headers: BTreeMap<usize, BlockHeader>,
+    last_progress: Option<Instant>,
+    /// When the run started; taken by `finish` so a run completes once.
+    started_at: Option<Instant>,
+    stall_timeout: Duration,
+    /// Checkpoint loaded at startup, picked up by the first run.
+    restored: Option<SyncCheckpoint>,
+}
+
+/// Where a run stands, logged by the stall watchdog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncStatus {
+    pub head_index: Option<usize>,
+    pub target: usize,
+    pub headers_in_flight: bool,
+    pub bodies_in_flight: usize,
+    pub pending_batches: usize,
+    pub idle: Option<Duration>,
+}
+
+impl SyncPipeline {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            head: None,
+            target: 0,
+            headers_in_flight: false,
+            bodies_in_flight: 0,
+            pending: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            last_progress: None,
+            started_at: None,
+            stall_timeout: STALL_TIMEOUT,
+            restored: None,
+        }
+    }
+
+    pub fn with_stall_timeout(mut self, stall_timeout: Duration) -> Self {
+        self.stall_timeout = stall_timeout;
+        self
+    }
+
+    pub fn is_running(&self, now: Instant) -> bool {
+        self.last_progress
+            .is_some_and(|at| now.duration_since(at) < self.stall_timeout)
+    }
+
+    /// A run that was started, is still short of its target with our tip at `tip_index`,
+    /// and has made no progress for the stall timeout.
+    pub fn is_stalled(&self, tip_index: usize, now: Instant) -> bool {
+        self.head.is_some() && self.target > tip_index && !self.is_running(now)
+    }
+
+    /// When the run last accepted headers or imported bodies.
+    pub fn last_progress(&self) -> Option<Instant> {
+        self.last_progress
+    }
+
+    pub fn status(&self, now: Instant) -> SyncStatus {
+        SyncStatus {
+            head_index: self.head.as_ref().map(|(index, _)| *index),
+            target: self.target,
+            headers_in_flight: self.headers_in_flight,
+            bodies_in_flight: self.bodies_in_flight,
+            pending_batches: self.pending.len(),
+            idle: self.last_progress.map(|at| now.duration_since(at)),
+        }
+    }
+
+    /// Starts a run from our tip towards `target`, dropping whatever a stalled run left.
+    pub fn start(&mut self, tip_index: usize, tip_hash: String, target: usize, now: Instant) {
+        self.stop();
+        self.head = Some((tip_index, tip_hash));
+        self.target = target;
+        self.last_progress = Some(now);
+        self.started_at = Some(now);
+    }
+
+    /// How long the run took, the first time our tip at `tip_index` reaches its target
+    /// with nothing left to download or import.
+    pub fn finish(&mut self, tip_index: usize, now: Instant) -> Option<Duration> {
+        let done = self.head.is_some()
+            && tip_index >= self.target
+            && self.bodies_in_flight == 0
+            && self.pending.is_empty();
+        if !done {
+            return None;
+        }
+        self.started_at.take().map(|at| now.duration_since(at))
+    }
+
+    /// Picks a run back up from a saved checkpoint, keeping the bodies it had downloaded.
+    /// Returns the blocks between our tip and the checkpoint head that still need bodies.
+    pub fn resume(
+        &mut self,
+        checkpoint: SyncCheckpoint,
+        tip_index: usize,
+        target: usize,
+        now: Instant,
+    ) -> Vec<usize> {
+        self.start(
+            checkpoint.head_index,
+            checkpoint.head_hash,
+            target.max(checkpoint.target),
+            now,
+        );
+        for header in checkpoint.headers {
+            if header.index > tip_index {
+                self.headers.insert(header.index, header);
+            }
+        }
+        for batch in checkpoint.pending {
+            if let Some(first_index) = batch.first().map(|block| block.index) {
+                if first_index > tip_index {
+                    self.pending.insert(first_index, batch);
+                }
+            }
+        }
+        (tip_index + 1..=checkpoint.head_index)
+            .filter(|index| !self.is_pending(*index))
+            .collect()
+    }
+
+    fn is_pending(&self, index: usize) -> bool {
+        self.pending
+            .range(..=index)
+            .next_back()
+            .is_some_and(|(first, batch)| index < first + batch.len())
+    }
+
+    /// What to persist, or `None` once nothing is left past `tip_index`.
+    pub fn checkpoint(&self, tip_index: usize) -> Option<SyncCheckpoint> {
+        let (head_index, head_hash) = self.head.clone()?;
+        if head_index <= tip_index && self.pending.is_empty() {
+            return None;
+        }
+        Some(SyncCheckpoint {
+            head_index,
+            head_hash,
+            target: self.target,
+            pending: self.pending.values().cloned().collect(),
+            headers: self.headers.values().cloned().collect(),
+        })
+    }
+
+    pub fn restore(&mut self, checkpoint: SyncCheckpoint) {
+        self.restored = Some(checkpoint);
+    }
+
+    pub fn take_restored(&mut self) -> Option<SyncCheckpoint> {
+        self.restored.take()
+    }
+
+    pub fn stop(&mut self) {
+        self.head = None;
+        self.headers_in_flight = false;
+        self.bodies_in_flight = 0;
+        self.pending.clear();
+        self.headers.clear();
+        self.last_progress = None;
+        self.started_at = None;
+    }
+
+    pub fn extend_target(&mut self, target: usize) {
+        self.target = self.target.max(target);
+    }
+
+    pub fn head(&self) -> Option<(usize, &str)> {
+        self.head
+            .as_ref()
+            .map(|(index, hash)| (*index, hash.as_str()))
+    }
+
+    /// Hash of block `index` if it is the head, which the chain may not have imported yet.
+    pub fn head_hash(&self, index: usize) -> Option<&str> {
+        self.head()
+            .filter(|(head_index, _)| *head_index == index)
+            .map(|(_, hash)| hash)
+    }
+
+    /// Whether to ask for the next header batch: the target is further on, no header
+    /// request is outstanding, and fewer than `depth` body batches are.
+    pub fn wants_headers(&self) -> bool {
+        self.head
+            .as_ref()
+            .is_some_and(|(index, _)| *index < self.target)
+            && !self.headers_in_flight
+            && self.bodies_in_flight < self.depth
+    }
+
+    pub fn on_headers_requested(&mut self) {
+        self.headers_in_flight = true;
+    }
+
+    /// A verified header batch ending at `last_index`.
+    pub fn on_headers(&mut self, last_index: usize, last_hash: String, now: Instant) {
+        self.head = Some((last_index, last_hash));
+        self.headers_in_flight = false;
+        self.last_progress = Some(now);
+    }
+
+    /// Keeps a verified header batch, so the bodies requested for it can be checked
+    /// against it.
+    pub fn expect_bodies(&mut self, headers: &[BlockHeader]) {
+        for header in headers {
+            self.headers.insert(header.index, header.clone());
+        }
+    }
+
+    /// Whether a header was verified for block `index` whose body hasn't arrived yet.
+    pub fn expects_body(&self, index: usize) -> bool {
+        self.headers.contains_key(&index)
+    }
+
+    /// Checks a downloaded body against the header verified for its index. A body with
+    /// no such header, or a second one for the same index, is rejected.
+    pub fn verify_body(&mut self, block: &Block) -> Result<(), String> {
+        match self.headers.remove(&block.index) {
+            Some(header) => block.verify_against_header(&header),
+            None => Err(format!("no header was verified for block {}", block.index)),
+        }
+    }
+
+    pub fn on_bodies_requested(&mut self) {
+        self.bodies_in_flight += 1;
+    }
+
+    /// The peer had no headers past the head.
+    pub fn on_headers_exhausted(&mut self) {
+        self.headers_in_flight = false;
+        if let Some((index, _)) = self.head {
+            self.target = index;
+        }
+    }
+
+    pub fn on_bodies(&mut self, now: Instant) {
+        self.bodies_in_flight = self.bodies_in_flight.saturating_sub(1);
+        self.last_progress = Some(now);
+    }
+
+    /// Holds a body batch starting at `first_index` until the chain reaches it.
+    pub fn stash(&mut self, first_index: usize, blocks: Vec<Block>) {
+        self.pending.insert(first_index, blocks);
+    }
+
+    /// The stashed batch starting at `next_index`, once the chain has caught up to it.
+    pub fn take_pending(&mut self, next_index: usize) -> Option<Vec<Block>> {
+        self.pending.remove(&next_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::transactions::transaction::Transaction;
+
+    #[test]
+    fn headers_run_ahead_of_bodies_up_to_depth() {
+        let now = Instant::now();
+        let mut sync = SyncPipeline::new(2);
+        sync.start(10, "h10".to_string(), 400, now);
+        assert!(sync.wants_headers());
+
+        sync.on_headers_requested();
+        assert!(!sync.wants_headers(), "one header request at a time");
+
+        sync.on_headers(110, "h110".to_string(), now);
+        sync.on_bodies_requested();
+        assert!(sync.wants_headers(), "next batch while bodies download");
+        assert_eq!(sync.head_hash(110), Some("h110"));
+        assert_eq!(sync.head_hash(10), None);
+
+        sync.on_headers_requested();
+        sync.on_headers(210, "h210".to_string(), now);
+        sync.on_bodies_requested();
+        assert!(!sync.wants_headers(), "two body batches outstanding");
+
+        sync.on_bodies(now);
+        assert!(sync.wants_headers());
+    }
+
+    #[test]
+    fn run_ends_at_target_or_when_stalled() {
+        let now = Instant::now();
+        let mut sync = SyncPipeline::new(2);
+        assert!(!sync.is_running(now));
+
+        sync.start(0, "genesis".to_string(), 100, now);
+        sync.on_headers_requested();
+        sync.on_headers(100, "h100".to_string(), now);
+        assert!(!sync.wants_headers(), "target reached");
+
+        sync.extend_target(150);
+        assert!(sync.wants_headers());
+        sync.on_headers_requested();
+        sync.on_headers_exhausted();
+        assert!(!sync.wants_headers());
+
+        assert!(sync.is_running(now + Duration::from_secs(1)));
+        assert!(!sync.is_running(now + STALL_TIMEOUT));
+    }
+
+    #[test]
+    fn stalled_only_while_behind_the_target() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(5);
+        let mut sync = SyncPipeline::new(2).with_stall_timeout(timeout);
+        assert!(!sync.is_stalled(0, now + timeout), "never started");
+
+        sync.start(0, "genesis".to_string(), 100, now);
+        sync.on_headers_requested();
+        assert!(!sync.is_stalled(0, now + Duration::from_secs(4)));
+        assert!(sync.is_stalled(0, now + timeout));
+        assert!(!sync.is_stalled(100, now + timeout), "caught up");
+
+        let status = sync.status(now + timeout);
+        assert_eq!(status.head_index, Some(0));
+        assert!(status.headers_in_flight);
+        assert_eq!(status.idle, Some(timeout));
+    }
+
+    #[test]
+    fn a_run_finishes_once_its_target_is_imported() {
+        let now = Instant::now();
+        let mut sync = SyncPipeline::new(2);
+        assert_eq!(sync.finish(0, now), None, "never started");
+
+        sync.start(0, "genesis".to_string(), 100, now);
+        sync.on_headers(100, "h100".to_string(), now);
+        sync.on_bodies_requested();
+        assert_eq!(sync.finish(100, now), None, "bodies still in flight");
+
+        sync.on_bodies(now);
+        assert_eq!(sync.finish(99, now), None);
+        let later = now + Duration::from_secs(7);
+        assert_eq!(sync.finish(100, later), Some(Duration::from_secs(7)));
+        assert_eq!(sync.finish(100, later), None, "already reported");
+    }
+
+    #[test]
+    fn early_batches_wait_for_the_chain() {
+        let mut sync = SyncPipeline::new(2);
+        sync.stash(101, vec![Block::new_genesis_block()]);
+        assert!(sync.take_pending(1).is_none());
+        assert_eq!(sync.take_pending(101).map(|blocks| blocks.len()), Some(1));
+        assert!(sync.take_pending(101).is_none());
+    }
+
+    #[test]
+    fn bodies_must_match_their_headers() {
+        let mut sync = SyncPipeline::new(2);
+        let body = Block::new_block(1, "genesis".to_string(), Vec::new());
+        sync.expect_bodies(&[body.to_block_header()]);
+        assert!(sync.verify_body(&body).is_ok());
+
+        let mut forged = body.clone();
+        forged.transactions = Transaction::new_genesis_transactions();
+        sync.expect_bodies(&[body.to_block_header()]);
+        assert!(
+            sync.verify_body(&forged).is_err(),
+            "hash doesn't cover them"
+        );
+
+        forged.hash = forged.calculate_hash();
+        sync.expect_bodies(&[body.to_block_header()]);
+        assert!(
+            sync.verify_body(&forged).is_err(),
+            "hash differs from the header's"
+        );
+
+        assert!(!sync.expects_body(1));
+        assert!(
+            sync.verify_body(&forged).is_err(),
+            "no header was verified for it"
+        );
+    }
+
+    fn block(index: usize) -> Block {
+        Block {
+            index,
+            ..Block::new_genesis_block()
+        }
+    }
+
+    #[test]
+    fn resume_keeps_downloaded_bodies_and_refetches_the_rest() {
+        let now = Instant::now();
+        let mut sync = SyncPipeline::new(2);
+        sync.start(10, "h10".to_string(), 30, now);
+        sync.on_headers(20, "h20".to_string(), now);
+        let body = Block::new_block(19, "h18".to_string(), Vec::new());
+        sync.expect_bodies(&[body.to_block_header()]);
+        sync.stash(16, (16..=18).map(block).collect());
+        sync.stash(4, vec![block(4)]);
+
+        let checkpoint = sync.checkpoint(12).unwrap();
+        assert_eq!(checkpoint.pending.len(), 2);
+        assert!(
+            !checkpoint.can_resume(12),
+            "only block 19 has a header to check its body against"
+        );
+
+        let mut restarted = SyncPipeline::new(2);
+        let missing = restarted.resume(checkpoint, 12, 25, now);
+        assert_eq!(missing, [13, 14, 15, 19, 20]);
+        assert_eq!(restarted.head(), Some((20, "h20")));
+        assert!(restarted.take_pending(4).is_none(), "already below the tip");
+        assert!(restarted.take_pending(16).is_some());
+        let mut forged = body.clone();
+        forged.previous_hash = "other".to_string();
+        assert!(
+            restarted.verify_body(&forged).is_err(),
+            "header kept across restarts"
+        );
+
+        assert!(restarted.checkpoint(20).is_none(), "caught up");
+    }
+}
@@ -0,0 +1,239 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{request_response::Codec, StreamProtocol};
+use std::io;
+use std::time::Duration;
+
+use crate::node::blockchain::ChainReader;
+use crate::node::error::NodeError;
+use crate::node::rlp_encoding::encode;
+
+pub const SYNC_STREAM_PROTOCOL: StreamProtocol = StreamProtocol::new("/clutch/sync/1");
+/// Most blocks one range request is answered with.
+pub const MAX_STREAM_BLOCKS: usize = 1024;
+/// Most encoded block bytes in one response; a range is cut short before passing it.
+pub const MAX_STREAM_BYTES: usize = 64 * 1024 * 1024;
+/// A full range takes far longer to arrive than the default request timeout allows.
+pub const SYNC_STREAM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// `count` consecutive blocks from `start_index`, asked for over `/clutch/sync/1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncRange {
+    pub start_index: u64,
+    pub count: u64,
+}
+
+impl SyncRange {
+    /// The range spanning `indexes` when they are consecutive and ascending, as the indexes
+    /// of a verified header batch are.
+    pub fn covering(indexes: &[usize]) -> Option<Self> {
+        let first = *indexes.first()?;
+        let consecutive = indexes
+            .iter()
+            .enumerate()
+            .all(|(offset, index)| *index == first + offset);
+        consecutive.then_some(SyncRange {
+            start_index: first as u64,
+            count: indexes.len() as u64,
+        })
+    }
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.start_index.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.count.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        let mut start_index = [0u8; 8];
+        let mut count = [0u8; 8];
+        start_index.copy_from_slice(&bytes[..8]);
+        count.copy_from_slice(&bytes[8..]);
+        SyncRange {
+            start_index: u64::from_be_bytes(start_index),
+            count: u64::from_be_bytes(count),
+        }
+    }
+}
+
+/// RLP-encoded blocks answering a `SyncRange`, in order. Empty when the range couldn't be
+/// served, which the requester treats as a failed request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncBlocks {
+    pub blocks: Vec<Vec<u8>>,
+}
+
+/// Wire format of `/clutch/sync/1`. The request is the range as two big-endian `u64`s; the
+/// response is each block prefixed with its big-endian `u32` length, ended by a zero
+/// length. Unlike a `BlockBodies` message the blocks are never framed as one value, so a
+/// range is bounded by `MAX_STREAM_BLOCKS` and `MAX_STREAM_BYTES` rather than by the
+/// request-response message size limit.
+#[derive(Debug, Clone, Default)]
+pub struct SyncStreamCodec;
+
+#[async_trait]
+impl Codec for SyncStreamCodec {
+    type Protocol = StreamProtocol;
+    type Request = SyncRange;
+    type Response = SyncBlocks;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<SyncRange>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut bytes = [0u8; 16];
+        io.read_exact(&mut bytes).await?;
+        Ok(SyncRange::from_bytes(bytes))
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<SyncBlocks>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut blocks = Vec::new();
+        let mut total = 0usize;
+        loop {
+            let mut length = [0u8; 4];
+            io.read_exact(&mut length).await?;
+            let length = u32::from_be_bytes(length) as usize;
+            if length == 0 {
+                return Ok(SyncBlocks { blocks });
+            }
+            total = total.saturating_add(length);
+            if blocks.len() >= MAX_STREAM_BLOCKS || total > MAX_STREAM_BYTES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "sync stream response exceeds its limits",
+                ));
+            }
+            let mut block = vec![0u8; length];
+            io.read_exact(&mut block).await?;
+            blocks.push(block);
+        }
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        range: SyncRange,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&range.to_bytes()).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: SyncBlocks,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        for block in &response.blocks {
+            io.write_all(&(block.len() as u32).to_be_bytes()).await?;
+            io.write_all(block).await?;
+        }
+        io.write_all(&0u32.to_be_bytes()).await
+    }
+}
+
+/// The blocks of `range` we have, stopping at our head, at `MAX_STREAM_BLOCKS`, or before
+/// the block that would pass `MAX_STREAM_BYTES`.
+pub fn serve_range(reader: &ChainReader, range: SyncRange) -> Result<SyncBlocks, NodeError> {
+    let count = range.count.min(MAX_STREAM_BLOCKS as u64) as usize;
+    let start_index = usize::try_from(range.start_index).unwrap_or(usize::MAX);
+    let mut response = SyncBlocks::default();
+    let mut total = 0;
+    for block in reader.get_block_range(start_index, count)? {
+        let encoded = encode(&block);
+        total += encoded.len();
+        if total > MAX_STREAM_BYTES {
+            break;
+        }
+        response.blocks.push(encoded);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+
+    async fn round_trip(response: SyncBlocks) -> io::Result<SyncBlocks> {
+        let mut written = Cursor::new(Vec::new());
+        SyncStreamCodec
+            .write_response(&SYNC_STREAM_PROTOCOL, &mut written, response)
+            .await?;
+        let mut read = Cursor::new(written.into_inner());
+        SyncStreamCodec
+            .read_response(&SYNC_STREAM_PROTOCOL, &mut read)
+            .await
+    }
+
+    #[test]
+    fn only_consecutive_indexes_make_a_range() {
+        assert_eq!(
+            SyncRange::covering(&[7, 8, 9]),
+            Some(SyncRange {
+                start_index: 7,
+                count: 3
+            })
+        );
+        assert_eq!(SyncRange::covering(&[7, 9]), None);
+        assert_eq!(SyncRange::covering(&[9, 8]), None);
+        assert_eq!(SyncRange::covering(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn ranges_and_blocks_round_trip() {
+        let range = SyncRange {
+            start_index: 1 << 40,
+            count: 100,
+        };
+        let mut written = Cursor::new(Vec::new());
+        SyncStreamCodec
+            .write_request(&SYNC_STREAM_PROTOCOL, &mut written, range)
+            .await
+            .unwrap();
+        let mut read = Cursor::new(written.into_inner());
+        let decoded = SyncStreamCodec
+            .read_request(&SYNC_STREAM_PROTOCOL, &mut read)
+            .await
+            .unwrap();
+        assert_eq!(decoded, range);
+
+        let blocks = SyncBlocks {
+            blocks: vec![vec![0xc1, 0x01], vec![0xc0], vec![0x05; 300]],
+        };
+        assert_eq!(round_trip(blocks.clone()).await.unwrap(), blocks);
+        assert_eq!(
+            round_trip(SyncBlocks::default()).await.unwrap(),
+            SyncBlocks::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn responses_past_the_block_limit_are_rejected() {
+        let blocks = SyncBlocks {
+            blocks: vec![vec![0xc0]; MAX_STREAM_BLOCKS + 1],
+        };
+        let error = round_trip(blocks).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn truncated_responses_fail() {
+        let mut read = Cursor::new(vec![0, 0, 0, 4, 0xc0]);
+        let error = SyncStreamCodec
+            .read_response(&SYNC_STREAM_PROTOCOL, &mut read)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::node::rlp_encoding::RlpCodec;
+use crate::node::transactions::transaction::Transaction;
+
+/// How long a hash we asked a peer for stays claimed. After that, another peer announcing
+/// it is asked instead.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hashes of transactions that entered our pool since the last announcement. Gossiped in
+/// place of the transactions themselves when `tx_announce_interval` is set.
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct TransactionHashes {
+    #[rlp(list)]
+    pub hashes: Vec<String>,
+}
+
+/// Asks a peer that announced `hashes` for the transactions.
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct GetPooledTransactions {
+    #[rlp(list)]
+    pub hashes: Vec<String>,
+}
+
+/// Answer to `GetPooledTransactions`: the requested transactions still in the peer's pool.
+#[derive(Debug, Serialize, Deserialize, RlpCodec)]
+pub struct PooledTransactions {
+    #[rlp(list)]
+    pub transactions: Vec<Transaction>,
+}
+
+/// Announcements are per hop: a node announces the transactions it added to its pool,
+/// whether submitted locally or fetched after another peer's announcement, and doesn't
+/// forward the announcements it receives.
+#[derive(Debug)]
+pub struct TxAnnouncer {
+    batch_size: usize,
+    queued: VecDeque<String>,
+    queued_set: HashSet<String>,
+    /// Hashes asked for and not yet received, with when they were asked for.
+    requested: HashMap<String, Instant>,
+}
+
+impl TxAnnouncer {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            queued: VecDeque::new(),
+            queued_set: HashSet::new(),
+            requested: HashMap::new(),
+        }
+    }
+
+    /// Queues `hash` for the next announcement.
+    pub fn queue(&mut self, hash: String) {
+        if self.queued_set.insert(hash.clone()) {
+            self.queued.push_back(hash);
+        }
+    }
+
+    /// Takes everything queued, in announcements of at most `batch_size` hashes.
+    pub fn drain_batches(&mut self) -> Vec<TransactionHashes> {
+        self.queued_set.clear();
+        let hashes: Vec<String> = self.queued.drain(..).collect();
+        hashes
+            .chunks(self.batch_size)
+            .map(|batch| TransactionHashes {
+                hashes: batch.to_vec(),
+            })
+            .collect()
+    }
+
+    /// The announced hashes worth asking for: not `known` and not already asked of another
+    /// peer. They are claimed until received or `REQUEST_TIMEOUT` passes.
+    pub fn claim_unknown(
+        &mut self,
+        hashes: Vec<String>,
+        now: Instant,
+        mut known: impl FnMut(&str) -> bool,
+    ) -> Vec<String> {
+        self.requested
+            .retain(|_, asked| now.duration_since(*asked) < REQUEST_TIMEOUT);
+        let mut unknown = Vec::new();
+        for hash in hashes.into_iter().take(self.batch_size) {
+            if self.requested.contains_key(&hash) || known(&hash) {
+                continue;
+            }
+            self.requested.insert(hash.clone(), now);
+            unknown.push(hash);
+        }
+        unknown
+    }
+
+    /// Whether `hash` was asked for. Unsolicited transactions are dropped.
+    pub fn on_received(&mut self, hash: &str) -> bool {
+        self.requested.remove(hash).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn queued_hashes_are_announced_once_in_batches() {
+        let mut announcer = TxAnnouncer::new(2);
+        for hash in ["a", "b", "a", "c"] {
+            announcer.queue(hash.to_string());
+        }
+
+        let batches = announcer.drain_batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].hashes, hashes(&["a", "b"]));
+        assert_eq!(batches[1].hashes, hashes(&["c"]));
+        assert!(announcer.drain_batches().is_empty());
+    }
+
+    #[test]
+    fn only_unknown_unclaimed_hashes_are_requested() {
+        let mut announcer = TxAnnouncer::new(16);
+        let now = Instant::now();
+
+        let first = announcer.claim_unknown(hashes(&["a", "b", "c"]), now, |hash| hash == "b");
+        assert_eq!(first, hashes(&["a", "c"]));
+        // Another peer announcing the same hashes doesn't get asked while we wait.
+        assert!(announcer
+            .claim_unknown(hashes(&["a", "c"]), now, |_| false)
+            .is_empty());
+
+        assert!(announcer.on_received("a"));
+        assert!(!announcer.on_received("b"));
+
+        let later = now + REQUEST_TIMEOUT;
+        assert_eq!(
+            announcer.claim_unknown(hashes(&["c"]), later, |_| false),
+            hashes(&["c"])
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::node::rlp_encoding::{self, RlpMessage};
+
+use super::commands::DirectMessageType;
+
+/// Serialization of direct-message payloads. Implementations are selected per peer: each
+/// node lists the formats it accepts in its handshake, and a sender encodes for the first
+/// one in the receiving peer's list that it also supports.
+pub trait WireCodec {
+    /// Name advertised in the handshake capability list.
+    const NAME: &'static str;
+    /// Sent in every frame's header so the receiver can decode it without tracking
+    /// negotiation state.
+    const ID: u8;
+
+    fn encode<T: RlpMessage>(message: &T) -> Result<Vec<u8>, String>;
+    fn decode<T: RlpMessage>(payload: &[u8]) -> Result<T, String>;
+}
+
+/// The original encoding. Always supported, and implied when a peer advertises nothing.
+pub struct Rlp;
+
+impl WireCodec for Rlp {
+    const NAME: &'static str = "rlp";
+    const ID: u8 = 0;
+
+    fn encode<T: RlpMessage>(message: &T) -> Result<Vec<u8>, String> {
+        Ok(rlp_encoding::encode(message))
+    }
+
+    fn decode<T: RlpMessage>(payload: &[u8]) -> Result<T, String> {
+        rlp_encoding::decode(payload).map_err(|e| format!("{:?}", e))
+    }
+}
+
+/// CBOR of the serde form of a message, the same encoding libp2p uses for the
+/// request-response envelope.
+pub struct Cbor;
+
+impl WireCodec for Cbor {
+    const NAME: &'static str = "cbor";
+    const ID: u8 = 1;
+
+    fn encode<T: RlpMessage>(message: &T) -> Result<Vec<u8>, String> {
+        cbor4ii::serde::to_vec(Vec::new(), message).map_err(|e| e.to_string())
+    }
+
+    fn decode<T: RlpMessage>(payload: &[u8]) -> Result<T, String> {
+        cbor4ii::serde::from_slice(payload).map_err(|e| e.to_string())
+    }
+}
+
+/// Runtime choice of `WireCodec`, as stored per peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Rlp,
+    Cbor,
+}
+
+impl WireFormat {
+    pub const ALL: [WireFormat; 2] = [WireFormat::Rlp, WireFormat::Cbor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WireFormat::Rlp => Rlp::NAME,
+            WireFormat::Cbor => Cbor::NAME,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            WireFormat::Rlp => Rlp::ID,
+            WireFormat::Cbor => Cbor::ID,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.id() == id)
+    }
+
+    pub fn encode<T: RlpMessage>(self, message: &T) -> Result<Vec<u8>, String> {
+        match self {
+            WireFormat::Rlp => Rlp::encode(message),
+            WireFormat::Cbor => Cbor::encode(message),
+        }
+    }
+
+    pub fn decode<T: RlpMessage>(self, payload: &[u8]) -> Result<T, String> {
+        match self {
+            WireFormat::Rlp => Rlp::decode(payload),
+            WireFormat::Cbor => Cbor::decode(payload),
+        }
+    }
+
+    /// Capability list for our handshake. Empty when we only speak RLP, so the handshake
+    /// encodes exactly as it did before negotiation existed.
+    pub fn capabilities(formats: &[WireFormat]) -> Vec<String> {
+        if formats.iter().all(|format| *format == WireFormat::Rlp) {
+            return Vec::new();
+        }
+        formats
+            .iter()
+            .map(|format| format.name().to_string())
+            .collect()
+    }
+
+    /// Format to send to a peer that advertised `remote` (in its order of preference):
+    /// the first one we also support, falling back to RLP.
+    pub fn negotiate(local: &[WireFormat], remote: &[String]) -> WireFormat {
+        remote
+            .iter()
+            .filter_map(|name| WireFormat::from_name(name))
+            .find(|format| local.contains(format))
+            .unwrap_or_default()
+    }
+}
+
+/// First two bytes of a direct message: the message type, then the format id.
+pub fn frame_header(message_type: DirectMessageType, format: WireFormat) -> [u8; 2] {
+    [message_type.as_byte(), format.id()]
+}
+
+/// Builds a direct message: `frame_header` followed by the encoded payload.
+pub fn encode_frame<T: RlpMessage>(
+    message_type: DirectMessageType,
+    format: WireFormat,
+    message: &T,
+) -> Result<Vec<u8>, String> {
+    let payload = format.encode(message)?;
+    let header = frame_header(message_type, format);
+    let mut frame = Vec::with_capacity(header.len() + payload.len());
+    frame.extend(header);
+    frame.extend(payload);
+    Ok(frame)
+}
+
+/// Splits a direct message into its type, format and payload. The type or format is
+/// `None` when the sender used one this build doesn't know.
+pub fn decode_frame(
+    frame: &[u8],
+) -> Option<(Option<DirectMessageType>, Option<WireFormat>, &[u8])> {
+    let [message_type, format, payload @ ..] = frame else {
+        return None;
+    };
+    Some((
+        DirectMessageType::from_byte(*message_type),
+        WireFormat::from_id(*format),
+        payload,
+    ))
+}
+
+/// Rewrites a legacy frame, the message type then an RLP payload, to the current layout.
+/// An empty message, how a request that couldn't be answered is answered, stays empty.
+pub fn from_legacy_frame(frame: &[u8]) -> Vec<u8> {
+    let Some((message_type, payload)) = frame.split_first() else {
+        return Vec::new();
+    };
+    let mut current = Vec::with_capacity(frame.len() + 1);
+    current.extend([*message_type, Rlp::ID]);
+    current.extend(payload);
+    current
+}
+
+/// Rewrites a frame to the legacy layout. Legacy nodes only read RLP, and never advertise
+/// anything else to be sent to them.
+pub fn to_legacy_frame(frame: &[u8]) -> Result<Vec<u8>, String> {
+    match frame {
+        [] => Ok(Vec::new()),
+        [message_type, format, payload @ ..] if *format == Rlp::ID => {
+            let mut legacy = Vec::with_capacity(frame.len() - 1);
+            legacy.push(*message_type);
+            legacy.extend(payload);
+            Ok(legacy)
+        }
+        _ => Err("legacy peers only read RLP frames".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::p2p_server::handshake::Handshake;
+
+    fn handshake(capabilities: Vec<String>) -> Handshake {
+        Handshake {
+            genesis_block_hash: "genesis".to_string(),
+            latest_block_hash: "tip".to_string(),
+            latest_block_index: 7,
+            capabilities,
+            hardforks: Vec::new(),
+            node_record: Vec::new(),
+            protocol_version: 0,
+        }
+    }
+
+    #[test]
+    fn sender_picks_first_remote_format_it_supports() {
+        let both = [WireFormat::Rlp, WireFormat::Cbor];
+        let remote = vec!["ssz".to_string(), "cbor".to_string(), "rlp".to_string()];
+        assert_eq!(WireFormat::negotiate(&both, &remote), WireFormat::Cbor);
+        assert_eq!(
+            WireFormat::negotiate(&[WireFormat::Rlp], &remote),
+            WireFormat::Rlp
+        );
+        assert_eq!(WireFormat::negotiate(&both, &[]), WireFormat::Rlp);
+    }
+
+    #[test]
+    fn rlp_only_handshake_keeps_legacy_encoding() {
+        let capabilities = WireFormat::capabilities(&[WireFormat::Rlp]);
+        let legacy = rlp_encoding::encode(&handshake(capabilities));
+
+        let mut stream = rlp::RlpStream::new_list(3);
+        stream.append(&"genesis");
+        stream.append(&"tip");
+        stream.append(&7usize);
+        assert_eq!(legacy, stream.out().to_vec());
+
+        let advertised = WireFormat::capabilities(&[WireFormat::Cbor, WireFormat::Rlp]);
+        let decoded: Handshake =
+            rlp_encoding::decode(&rlp_encoding::encode(&handshake(advertised.clone()))).unwrap();
+        assert_eq!(decoded.capabilities, advertised);
+
+        let versioned = Handshake {
+            protocol_version: 2,
+            ..handshake(Vec::new())
+        };
+        let decoded: Handshake = rlp_encoding::decode(&rlp_encoding::encode(&versioned)).unwrap();
+        assert_eq!(decoded.protocol_version, 2);
+        assert!(decoded.node_record.is_empty());
+    }
+
+    #[test]
+    fn frames_round_trip_in_every_format() {
+        for format in WireFormat::ALL {
+            let frame = encode_frame(
+                DirectMessageType::Handshake,
+                format,
+                &handshake(vec!["cbor".to_string()]),
+            )
+            .unwrap();
+            let (message_type, decoded_format, payload) = decode_frame(&frame).unwrap();
+            assert_eq!(message_type, Some(DirectMessageType::Handshake));
+            assert_eq!(decoded_format, Some(format));
+
+            let decoded: Handshake = format.decode(payload).unwrap();
+            assert_eq!(decoded.latest_block_index, 7);
+            assert_eq!(decoded.capabilities, ["cbor"]);
+        }
+    }
+
+    #[test]
+    fn frame_headers_keep_the_whole_message_type_byte() {
+        let header = frame_header(DirectMessageType::GetPooledTransactions, WireFormat::Cbor);
+        assert_eq!(
+            header,
+            [DirectMessageType::GetPooledTransactions.as_byte(), Cbor::ID]
+        );
+        let (message_type, format, payload) = decode_frame(&header).unwrap();
+        assert_eq!(message_type, Some(DirectMessageType::GetPooledTransactions));
+        assert_eq!(format, Some(WireFormat::Cbor));
+        assert!(payload.is_empty());
+
+        assert_eq!(
+            decode_frame(&[0x10, 0x07]).unwrap().1,
+            None,
+            "unknown format"
+        );
+        assert!(decode_frame(&[header[0]]).is_none(), "no format byte");
+    }
+
+    #[test]
+    fn rlp_frames_convert_to_and_from_the_legacy_layout() {
+        let frame = encode_frame(
+            DirectMessageType::Handshake,
+            WireFormat::Rlp,
+            &handshake(vec![]),
+        )
+        .unwrap();
+        let legacy = to_legacy_frame(&frame).unwrap();
+        assert_eq!(legacy[0], DirectMessageType::Handshake.as_byte());
+        assert_eq!(legacy[1..], frame[2..]);
+        assert_eq!(from_legacy_frame(&legacy), frame);
+        assert!(to_legacy_frame(&[]).unwrap().is_empty());
+        assert!(from_legacy_frame(&[]).is_empty());
+
+        let cbor = encode_frame(
+            DirectMessageType::Handshake,
+            WireFormat::Cbor,
+            &handshake(vec![]),
+        )
+        .unwrap();
+        assert!(to_legacy_frame(&cbor).is_err());
+    }
+}
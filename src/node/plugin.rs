@@ -0,0 +1,143 @@
+use libp2p::PeerId;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::node::blocks::block::Block;
+use crate::node::transactions::transaction::Transaction;
+use crate::node::transactions::transaction_pool::EvictionReason;
+
+/// Extension point for crates that embed the node. Every hook has an empty default, so a
+/// plugin implements only the events it needs. Hooks run synchronously on the task that
+/// caused the event, often while it holds the chain lock, so anything slow should be
+/// handed off to a task or channel of the plugin's own. A hook that panics is logged and
+/// the node carries on.
+pub trait NodePlugin: Send + Sync {
+    /// Shown in logs.
+    fn name(&self) -> &str;
+
+    /// `block` is committed to the chain, whether authored, gossiped or synced.
+    fn on_block_imported(&self, _block: &Block) {}
+
+    /// Blocks above `common_index` were removed from the chain; `removed_hashes` is newest
+    /// first. Reported by `Blockchain::rollback_to`, for an operator's rollback or sync
+    /// switching to a peer's longer chain.
+    fn on_reorg(&self, _common_index: usize, _removed_hashes: &[String]) {}
+
+    /// `transaction` was validated and added to the pool, from RPC or gossip.
+    fn on_tx_pool_insert(&self, _transaction: &Transaction) {}
+
+    /// `transaction` expired and was dropped from the pool without being included.
+    fn on_tx_pool_evict(&self, _transaction: &Transaction, _reason: EvictionReason) {}
+
+    /// The first connection to `peer_id` was established, before the handshake.
+    fn on_peer_connected(&self, _peer_id: &PeerId) {}
+}
+
+/// The plugins registered with `Blockchain::with_plugin`, called in registration order.
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Arc<dyn NodePlugin>>,
+}
+
+impl fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.plugins.iter().map(|plugin| plugin.name()))
+            .finish()
+    }
+}
+
+impl PluginRegistry {
+    pub fn register(&mut self, plugin: Arc<dyn NodePlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn block_imported(&self, block: &Block) {
+        self.each("on_block_imported", |plugin| {
+            plugin.on_block_imported(block)
+        });
+    }
+
+    pub fn reorg(&self, common_index: usize, removed_hashes: &[String]) {
+        self.each("on_reorg", |plugin| {
+            plugin.on_reorg(common_index, removed_hashes)
+        });
+    }
+
+    pub fn tx_pool_insert(&self, transaction: &Transaction) {
+        self.each("on_tx_pool_insert", |plugin| {
+            plugin.on_tx_pool_insert(transaction)
+        });
+    }
+
+    pub fn tx_pool_evict(&self, transaction: &Transaction, reason: EvictionReason) {
+        self.each("on_tx_pool_evict", |plugin| {
+            plugin.on_tx_pool_evict(transaction, reason)
+        });
+    }
+
+    pub fn peer_connected(&self, peer_id: &PeerId) {
+        self.each("on_peer_connected", |plugin| {
+            plugin.on_peer_connected(peer_id)
+        });
+    }
+
+    fn each(&self, hook: &str, call: impl Fn(&dyn NodePlugin)) {
+        for plugin in &self.plugins {
+            if catch_unwind(AssertUnwindSafe(|| call(plugin.as_ref()))).is_err() {
+                error!("Plugin {} panicked in {}", plugin.name(), hook);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Recorder {
+        calls: Arc<Mutex<Vec<String>>>,
+        panics: bool,
+    }
+
+    impl NodePlugin for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn on_reorg(&self, common_index: usize, removed_hashes: &[String]) {
+            if self.panics {
+                panic!("plugin failure");
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("reorg {} {:?}", common_index, removed_hashes));
+        }
+    }
+
+    #[test]
+    fn hooks_run_in_order_past_a_panicking_plugin() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = PluginRegistry::default();
+        for panics in [true, false] {
+            registry.register(Arc::new(Recorder {
+                calls: Arc::clone(&calls),
+                panics,
+            }));
+        }
+
+        registry.reorg(3, &["0x05".to_string(), "0x04".to_string()]);
+        registry.peer_connected(&PeerId::random());
+
+        assert_eq!(*calls.lock().unwrap(), ["reorg 3 [\"0x05\", \"0x04\"]"]);
+        assert_eq!(format!("{:?}", registry), "[\"recorder\", \"recorder\"]");
+    }
+}
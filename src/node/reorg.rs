@@ -0,0 +1,130 @@
+use serde::Serialize;
+
+use crate::node::blocks::block::Block;
+
+/// Limits on how much of the chain may be replaced at once. Reorgs go through
+/// `Blockchain::rollback_to`, from the operator's `rollback` or from sync switching to a
+/// peer's longer chain, so these guard both against unwinding further than intended.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReorgPolicy {
+    /// Deepest reorg carried out without being forced; 0 is unlimited.
+    pub max_depth: usize,
+    /// Reorgs at least this deep are logged as warnings and posted as a `deep_reorg` event
+    /// through the chain's `WebhookNotifier`; 0 never alerts.
+    pub alert_depth: usize,
+}
+
+/// One side of a reorg: the blocks above the common ancestor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BranchSummary {
+    pub head_index: usize,
+    pub head_hash: String,
+    pub blocks: usize,
+    pub transactions: usize,
+    /// Authors of the branch's blocks, each once, in block order.
+    pub authors: Vec<String>,
+}
+
+impl BranchSummary {
+    /// The branch of `blocks` (oldest first) on top of `common`.
+    pub fn new(common: &Block, blocks: &[Block]) -> Self {
+        let head = blocks.last().unwrap_or(common);
+        let mut authors: Vec<String> = Vec::new();
+        for block in blocks {
+            if !authors.contains(&block.author) {
+                authors.push(block.author.clone());
+            }
+        }
+        BranchSummary {
+            head_index: head.index,
+            head_hash: head.hash.clone(),
+            blocks: blocks.len(),
+            transactions: blocks.iter().map(|block| block.transactions.len()).sum(),
+            authors,
+        }
+    }
+}
+
+/// What a reorg replaced, as sent to the webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReorgSummary {
+    pub common_index: usize,
+    pub common_hash: String,
+    pub depth: usize,
+    pub old_branch: BranchSummary,
+    pub new_branch: BranchSummary,
+}
+
+impl ReorgSummary {
+    pub fn new(common: &Block, old_branch: &[Block], new_branch: &[Block]) -> Self {
+        ReorgSummary {
+            common_index: common.index,
+            common_hash: common.hash.clone(),
+            depth: old_branch.len(),
+            old_branch: BranchSummary::new(common, old_branch),
+            new_branch: BranchSummary::new(common, new_branch),
+        }
+    }
+}
+
+impl ReorgPolicy {
+    /// Refuses a reorg removing `depth` blocks beyond `max_depth`, unless `force`d.
+    pub fn check(&self, depth: usize, force: bool) -> Result<(), String> {
+        if force || self.max_depth == 0 || depth <= self.max_depth {
+            return Ok(());
+        }
+        Err(format!(
+            "Refusing to remove {} blocks, more than max_reorg_depth {}; rerun with --force",
+            depth, self.max_depth
+        ))
+    }
+
+    pub fn alerts(&self, depth: usize) -> bool {
+        self.alert_depth != 0 && depth >= self.alert_depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize, author: &str) -> Block {
+        Block {
+            index,
+            hash: format!("h{}", index),
+            author: author.to_string(),
+            transactions: Vec::new(),
+            ..Block::new_genesis_block()
+        }
+    }
+
+    #[test]
+    fn deep_reorgs_need_force_and_alert_past_the_threshold() {
+        let policy = ReorgPolicy {
+            max_depth: 10,
+            alert_depth: 3,
+        };
+        assert!(policy.check(10, false).is_ok());
+        assert!(policy.check(11, false).is_err());
+        assert!(policy.check(11, true).is_ok());
+        assert!(!policy.alerts(2));
+        assert!(policy.alerts(3));
+
+        let unlimited = ReorgPolicy::default();
+        assert!(unlimited.check(usize::MAX, false).is_ok());
+        assert!(!unlimited.alerts(usize::MAX));
+    }
+
+    #[test]
+    fn summaries_describe_both_branches() {
+        let common = block(4, "a");
+        let old = [block(5, "a"), block(6, "b"), block(7, "a")];
+        let summary = ReorgSummary::new(&common, &old, &[]);
+
+        assert_eq!(summary.depth, 3);
+        assert_eq!(summary.old_branch.head_index, 7);
+        assert_eq!(summary.old_branch.authors, ["a", "b"]);
+        assert_eq!(summary.new_branch.head_hash, "h4");
+        assert_eq!(summary.new_branch.blocks, 0);
+    }
+}
@@ -5,104 +5,12 @@ use crate::node::transactions::transaction::Transaction;
 use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use hex;
 
-use super::blocks::block::Block;
-use super::blocks::block_bodies::BlockBodies;
-use super::blocks::block_headers::{BlockHeader, BlockHeaders};
-use super::p2p_server::get_block_bodies::GetBlockBodies;
-use super::p2p_server::get_block_header::GetBlockHeaders;
-use super::p2p_server::handshake::Handshake;
-use super::transactions::function_call::FunctionCall;
-use super::transactions::ride_acceptance::RideAcceptance;
-use super::transactions::ride_cancel::RideCancel;
-use super::transactions::ride_offer::RideOffer;
-use super::transactions::ride_pay::RidePay;
-use super::transactions::ride_request::RideRequest;
-use super::transactions::ride_request_cancel::RideRequestCancel;
-use super::transactions::transfer::Transfer;
-
-impl Encodable for FunctionCall {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        match self {
-            FunctionCall::Transfer(args) => {
-                stream.begin_list(2);
-                stream.append(&0u8); // Tag for Transfer
-                stream.append(args);
-            }
-            FunctionCall::RideRequest(args) => {
-                stream.begin_list(2);
-                stream.append(&1u8); // Tag for RideRequest
-                stream.append(args);
-            }
-            FunctionCall::RideOffer(args) => {
-                stream.begin_list(2);
-                stream.append(&2u8); // Tag for RideOffer
-                stream.append(args);
-            }
-            FunctionCall::RideAcceptance(args) => {
-                stream.begin_list(2);
-                stream.append(&3u8); // Tag for RideAcceptance
-                stream.append(args);
-            }
-            FunctionCall::RidePay(args) => {
-                stream.begin_list(2);
-                stream.append(&4u8); // Tag for RidePay
-                stream.append(args);
-            }
-            FunctionCall::RideCancel(args) => {
-                stream.begin_list(2);
-                stream.append(&5u8); // Tag for RideCancel
-                stream.append(args);
-            }
-            FunctionCall::RideRequestCancel(args) => {
-                stream.begin_list(2);
-                stream.append(&8u8); // Tag for RideRequestCancel
-                stream.append(args);
-            }
-        }
-    }
-}
-
-impl Decodable for FunctionCall {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        // Expecting a list of two items: tag and arguments
-        if !rlp.is_list() || rlp.item_count()? != 2 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
+use super::bft::VoteKind;
+use super::p2p_server::disconnect::DisconnectReason;
 
-        let tag: u8 = rlp.val_at(0)?;
-        match tag {
-            0 => {
-                let args: Transfer = rlp.val_at(1)?;
-                Ok(FunctionCall::Transfer(args))
-            }
-            1 => {
-                let args: RideRequest = rlp.val_at(1)?;
-                Ok(FunctionCall::RideRequest(args))
-            }
-            2 => {
-                let args: RideOffer = rlp.val_at(1)?;
-                Ok(FunctionCall::RideOffer(args))
-            }
-            3 => {
-                let args: RideAcceptance = rlp.val_at(1)?;
-                Ok(FunctionCall::RideAcceptance(args))
-            }
-            4 => {
-                let args: RidePay = rlp.val_at(1)?;
-                Ok(FunctionCall::RidePay(args))
-            }
-            5 => {
-                let args: RideCancel = rlp.val_at(1)?;
-                Ok(FunctionCall::RideCancel(args))
-            }
-            8 => {
-                let args: RideRequestCancel = rlp.val_at(1)?;
-                Ok(FunctionCall::RideRequestCancel(args))
-            }
-            _ => Err(DecoderError::Custom("Unknown FunctionCall variant")),
-        }
-    }
-}
+pub use clutch_core::rlp_encoding::{
+    decode, decode_list_field, encode, ListDecoder, RlpCodec, RlpMessage,
+};
 
 impl Encodable for Transaction {
     fn rlp_append(&self, stream: &mut RlpStream) {
@@ -158,195 +66,46 @@ impl Decodable for Transaction {
     }
 }
 
-impl Encodable for Block {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(9);
-
-        stream.append(&self.index);
-        stream.append(&self.timestamp);
-        stream.append(&self.previous_hash);
-        stream.append(&self.author);
-        stream.append(&self.signature_r);
-        stream.append(&self.signature_s);
-        let signature_v_as_u64 = self.signature_v as u64;
-        stream.append(&signature_v_as_u64);
-        stream.append(&self.hash);
-        stream.append_list(&self.transactions);
-    }
-}
-
-impl Decodable for Block {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 9 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(Block {
-            index: rlp.val_at(0)?,
-            timestamp: rlp.val_at(1)?,
-            previous_hash: rlp.val_at(2)?,
-            author: rlp.val_at(3)?,
-            signature_r: rlp.val_at(4)?,
-            signature_s: rlp.val_at(5)?,
-            signature_v: rlp.val_at::<u64>(6)? as i32,
-            hash: rlp.val_at(7)?,
-            transactions: rlp.list_at(8)?,
-        })
-    }
-}
+impl RlpMessage for Transaction {}
 
-impl Encodable for Handshake {
+impl Encodable for DisconnectReason {
     fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(3);
-        stream.append(&self.genesis_block_hash);
-        stream.append(&self.latest_block_hash);
-        stream.append(&self.latest_block_index);
+        stream.append(&self.as_code());
     }
 }
 
-impl Decodable for Handshake {
+impl Decodable for DisconnectReason {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 3 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(Handshake {
-            genesis_block_hash: rlp.val_at(0)?,
-            latest_block_hash: rlp.val_at(1)?,
-            latest_block_index: rlp.val_at(2)?,
-        })
+        Ok(DisconnectReason::from_code(rlp.as_val()?))
     }
 }
 
-impl Encodable for GetBlockHeaders {
+impl Encodable for VoteKind {
     fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(3);
-        stream.append(&self.start_block_index);
-        stream.append(&self.skip);
-        stream.append(&self.limit);
+        stream.append(&self.as_code());
     }
 }
 
-impl Decodable for GetBlockHeaders {
+impl Decodable for VoteKind {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 3 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(GetBlockHeaders {
-            start_block_index: rlp.val_at(0)?,
-            skip: rlp.val_at(1)?,
-            limit: rlp.val_at(2)?,
-        })
-    }
-}
-
-impl Encodable for BlockHeader {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(7);
-        stream.append(&self.index);
-        stream.append(&self.previous_hash);
-        stream.append(&self.author);
-        stream.append(&self.signature_r);
-        stream.append(&self.signature_s);
-        let signature_v_as_u64 = self.signature_v as u64;
-        stream.append(&signature_v_as_u64);
-        stream.append(&self.hash);
+        VoteKind::from_code(rlp.as_val()?).ok_or(DecoderError::Custom("Unknown vote kind"))
     }
 }
 
-impl Decodable for BlockHeader {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 7 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(BlockHeader {
-            index: rlp.val_at(0)?,
-            previous_hash: rlp.val_at(1)?,
-            author: rlp.val_at(2)?,
-            signature_r: rlp.val_at(3)?,
-            signature_s: rlp.val_at(4)?,
-            signature_v: rlp.val_at::<u64>(5)? as i32,
-            hash: rlp.val_at(6)?,
-        })
-    }
-}
-
-impl Encodable for BlockHeaders {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append_list(&self.block_headers);
-    }
-}
-
-impl Decodable for BlockHeaders {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(BlockHeaders {
-            block_headers: rlp.list_at(0)?,
-        })
-    }
-}
-
-impl Encodable for GetBlockBodies {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append_list(&self.block_indexes);
-    }
-}
-
-impl Decodable for GetBlockBodies {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(GetBlockBodies {
-            block_indexes: rlp.list_at(0)?,
-        })
-    }
-}
-
-impl Encodable for BlockBodies {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append_list(&self.blocks);
-    }
-}
-
-impl Decodable for BlockBodies {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(BlockBodies {
-            blocks: rlp.list_at(0)?,
-        })
-    }
-}
-
-pub fn encode<T: Encodable>(data: &T) -> Vec<u8> {
-    let mut stream = RlpStream::new();
-    data.rlp_append(&mut stream);
-    stream.out().to_vec()
-}
-
-pub fn decode<T: Decodable>(bytes: &[u8]) -> Result<T, DecoderError> {
-    let rlp = Rlp::new(bytes);
-    T::decode(&rlp)
-}
-
 #[cfg(test)]
 mod tests {
 
     use tracing::{error, info};
 
+    use crate::node::blocks::block::Block;
+    use crate::node::blocks::block_bodies::BlockBodies;
+    use crate::node::blocks::block_headers::{BlockHeader, BlockHeaders};
+    use crate::node::p2p_server::disconnect::Disconnect;
+    use crate::node::p2p_server::get_block_header::GetBlockHeaders;
     use crate::node::time_utils::get_current_timespan;
+    use crate::node::transactions::function_call::FunctionCall;
+    use crate::node::transactions::ride_offer::RideOffer;
+    use crate::node::transactions::transfer::Transfer;
 
     use super::*;
 
@@ -424,6 +183,9 @@ mod tests {
             signature_v: 27,
             hash: "2086095648e3160d0dfa5d40bdf4693d8a00d77ed3fb3b607156465b3e0de2dc".to_string(),
             transactions: vec![tx1, tx2],
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         let encoded = encode(&block);
@@ -436,12 +198,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_decode_disconnect() {
+        let disconnect = Disconnect {
+            reason: DisconnectReason::IncompatibleGenesis,
+        };
+
+        let decoded = decode::<Disconnect>(&encode(&disconnect)).unwrap();
+        assert_eq!(decoded.reason, DisconnectReason::IncompatibleGenesis);
+
+        let unknown = Disconnect {
+            reason: DisconnectReason::Unknown(0x7f),
+        };
+        let decoded = decode::<Disconnect>(&encode(&unknown)).unwrap();
+        assert_eq!(decoded.reason, DisconnectReason::Unknown(0x7f));
+    }
+
     #[test]
     fn test_encode_decode_get_block_headers() {
         let get_block_headers = GetBlockHeaders {
             start_block_index: 0,
             skip: 0,
             limit: 100,
+            start_block_hash: String::new(),
         };
 
         let encoded = encode(&get_block_headers);
@@ -467,6 +246,9 @@ mod tests {
                 .to_string(),
             signature_v: 27,
             hash: "2086095648e3160d0dfa5d40bdf4693d8a00d77ed3fb3b607156465b3e0de2dc".to_string(),
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         let block_header_2 = BlockHeader {
@@ -480,6 +262,9 @@ mod tests {
                 .to_string(),
             signature_v: 27,
             hash: "2086095648e3160d0dfa5d40bdf4693d8a00d77ed3fb3b607156465b3e0de2dc".to_string(),
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         let block_headers = BlockHeaders {
@@ -511,6 +296,9 @@ mod tests {
             signature_v: 27,
             hash: "2086095648e3160d0dfa5d40bdf4693d8a00d77ed3fb3b607156465b3e0de2dc".to_string(),
             transactions: vec![],
+            transactions_root: String::new(),
+            receipts_root: String::new(),
+            next_authorities: Vec::new(),
         };
 
         let block_boodies = BlockBodies {
@@ -526,4 +314,60 @@ mod tests {
             Err(e) => error!("Failed to decode BlockBodies: {:?}", e),
         }
     }
+
+    #[test]
+    fn test_stream_decode_block_bodies_stops_at_bad_item() {
+        let mut stream = RlpStream::new_list(1);
+        stream.begin_list(3);
+        stream.append(&Block::new_genesis_block());
+        stream.append(&"not a block");
+        stream.append(&Block::new_genesis_block());
+        let encoded = stream.out().to_vec();
+
+        let mut blocks = decode_list_field::<Block>(&encoded, 1, 0).unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks.next().unwrap().unwrap().index, 0);
+        assert!(blocks.next().unwrap().is_err());
+
+        let bodies = encode(&BlockBodies { blocks: vec![] });
+        assert!(decode_list_field::<Block>(&bodies, 1, 0)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            decode_list_field::<Block>(&bodies, 2, 0).err(),
+            Some(DecoderError::RlpIncorrectListLen)
+        );
+    }
+
+    #[test]
+    fn test_derived_codec_keeps_wire_layout() {
+        let offer = FunctionCall::RideOffer(RideOffer {
+            ride_request_transaction_hash: "0xabc".to_string(),
+            fare: 42,
+            referrer: None,
+        });
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&2u8);
+        stream.begin_list(3);
+        stream.append(&"0xabc");
+        stream.append(&42u64);
+        stream.append(&"");
+        assert_eq!(encode(&offer), stream.out().to_vec());
+
+        let mut unknown_tag = RlpStream::new_list(2);
+        unknown_tag.append(&6u8);
+        unknown_tag.append(&"");
+        assert_eq!(
+            decode::<FunctionCall>(&unknown_tag.out()).err(),
+            Some(DecoderError::Custom("Unknown FunctionCall variant"))
+        );
+
+        let header = Block::new_genesis_block().to_block_header();
+        let decoded = decode::<BlockHeader>(&encode(&header)).unwrap();
+        assert_eq!(decoded.signature_v, header.signature_v);
+        assert_eq!(
+            decode::<GetBlockHeaders>(&encode(&header)).err(),
+            Some(DecoderError::RlpIncorrectListLen)
+        );
+    }
 }
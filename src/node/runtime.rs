@@ -0,0 +1,83 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::node::configuration::AppConfig;
+
+/// Batches smaller than this are processed on the calling thread.
+pub(crate) const PARALLEL_THRESHOLD: usize = 16;
+
+/// Threads of the CPU pool; 0 is one per core.
+static CPU_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+fn cores() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// The Tokio runtime the node runs on, with `runtime_worker_threads` workers (0 is one
+/// per core). CPU-bound batches go to the CPU pool instead, so they don't hold up the
+/// workers serving the network and RPC.
+pub fn build(config: &AppConfig) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if config.runtime_worker_threads > 0 {
+        builder.worker_threads(config.runtime_worker_threads);
+    }
+    builder.thread_name("clutch-worker").enable_all().build()
+}
+
+/// Sizes the CPU pool that signature recovery and hash checks of whole blocks run on;
+/// 0 is one thread per core.
+pub fn install_cpu_pool(threads: usize) {
+    CPU_THREADS.store(threads, Ordering::Relaxed);
+}
+
+pub fn cpu_threads() -> usize {
+    match CPU_THREADS.load(Ordering::Relaxed) {
+        0 => cores(),
+        threads => threads,
+    }
+}
+
+/// `items.iter().filter_map(f)`, with the items split over the CPU pool's threads.
+/// Results keep the order of `items`.
+pub fn par_filter_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Option<R> + Sync,
+{
+    let threads = cpu_threads();
+    if items.len() < PARALLEL_THRESHOLD || threads == 1 {
+        return items.iter().filter_map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().filter_map(f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(std::panic::resume_unwind))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_results_keep_their_order() {
+        let items: Vec<u32> = (0..1000).collect();
+        let odd_squares = par_filter_map(&items, |item| (item % 2 == 1).then_some(item * item));
+
+        let expected: Vec<u32> = (0..1000)
+            .filter(|item| item % 2 == 1)
+            .map(|item| item * item)
+            .collect();
+        assert_eq!(odd_squares, expected);
+        assert!(cpu_threads() >= 1);
+    }
+}
@@ -5,8 +5,10 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::span::{Attributes, Id};
 use tracing::{Event, Subscriber};
 use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
 
 pub struct SeqLogger {
     seq_url: String,
@@ -61,6 +63,10 @@ impl SeqLogger {
     }
 }
 
+/// Fields a span was created with, kept in its extensions so the events logged inside
+/// it carry them to Seq too (e.g. the peer and request id of a direct message).
+struct SpanFields(HashMap<String, String>);
+
 pub struct SeqLayer {
     logger: Arc<Mutex<SeqLogger>>,
 }
@@ -73,14 +79,35 @@ impl SeqLayer {
 
 impl<S> Layer<S> for SeqLayer
 where
-    S: Subscriber,
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
 {
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = HashMap::new();
+        attrs.record(
+            &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
+                fields.insert(field.name().to_string(), format!("{:?}", value));
+            },
+        );
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
         let logger = self.logger.clone();
 
         // Create a JSON object to hold the fields
         let mut fields_map = HashMap::new();
 
+        // Fields of the enclosing spans, outermost first; the event's own fields win
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    fields_map.extend(fields.clone());
+                }
+            }
+        }
+
         // Record the fields from the event using the correct closure signature
         event.record(
             &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
@@ -1,10 +1,10 @@
-use hex::FromHex;
 use rand::rngs::OsRng;
-use secp256k1::{
-    ecdsa::RecoverableSignature, ecdsa::RecoveryId, Message, PublicKey, Secp256k1, SecretKey,
-};
-use sha3::{Digest, Keccak256};
+use secp256k1::SecretKey;
 
+use crate::node::crypto;
+
+/// Keys, signatures and addresses use the chain's `crypto::suite`: secp256k1 and
+/// Keccak-256 unless the config selects other primitives.
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct SignatureKeys {
@@ -17,26 +17,30 @@ impl SignatureKeys {
 
     #[allow(dead_code)]
     pub fn generate_new_keypair() -> Self {
-        let secp = Secp256k1::new();
-        let mut rng = OsRng::default();
-        let (secret_key, public_key) = secp.generate_keypair(&mut rng);
-        let address_key = Self::derive_address(&public_key);
-
-        SignatureKeys {
-            secret_key: hex::encode(secret_key.as_ref()),
-            public_key: hex::encode(public_key.serialize_uncompressed()),
-            address_key: address_key,
-        }
+        Self::generate_with(&mut OsRng::default())
     }
 
-    fn derive_address(public_key: &PublicKey) -> String {
-        let serialized_pubkey = public_key.serialize_uncompressed();
-        let mut hasher = Keccak256::new();
-        hasher.update(&serialized_pubkey[1..]);
-        let hash = hasher.finalize();
+    /// A keypair drawn from `rng`, e.g. a seeded one for reproducible keys.
+    pub fn generate_with<R: rand::Rng + ?Sized>(rng: &mut R) -> Self {
+        let secret_key = crypto::suite().signature.generate_secret_key(rng);
+        Self::from_secret_bytes(&secret_key).expect("Generated secret key is valid")
+    }
 
-        let address_key = format!("0x{}", hex::encode(&hash[12..32]));
-        address_key
+    /// The public key and address of an existing secret key, e.g. one derived from a
+    /// mnemonic.
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self::from_secret_bytes(&secret_key.secret_bytes()).expect("Secret key is valid")
+    }
+
+    pub fn from_secret_bytes(secret_key: &[u8]) -> Result<Self, String> {
+        let suite = crypto::suite();
+        let public_key = suite.signature.public_key(secret_key)?;
+
+        Ok(SignatureKeys {
+            secret_key: hex::encode(secret_key),
+            public_key: hex::encode(&public_key),
+            address_key: suite.address(&public_key),
+        })
     }
 
     /// Strip 0x/0X prefix for hex parsing (Rust hex crate does not accept it)
@@ -45,32 +49,10 @@ impl SignatureKeys {
     }
 
     pub fn sign(secret_key: &str, data: &[u8]) -> (String, String, i32) {
-        let secp = Secp256k1::new();
-
         let secret_key_bytes = hex::decode(Self::strip_hex_prefix(secret_key)).unwrap();
-        let secret_key = SecretKey::from_slice(&secret_key_bytes).unwrap();
-
-        // Create a message hash (Keccak-256 of the data)
-        let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let message_hash = hasher.finalize();
-
-        // Create a message object for secp256k1
-        let message = Message::from_digest_slice(&message_hash)
-            .expect("Message could not be created from hash");
-
-        // Sign the message
-        let recoverable_sig = secp.sign_ecdsa_recoverable(&message, &secret_key);
-
-        // Serialize the signature to compact format
-        let (recid, sig) = recoverable_sig.serialize_compact();
-
-        // Convert signature and recovery ID to appropriate formats
-        let r = hex::encode(&sig[0..32]); // r component
-        let s = hex::encode(&sig[32..64]); // s component
-        let v = recid.to_i32() + 27; // recovery ID, adjusted for Ethereum (v = 27 or 28)
-
-        (r, s, v)
+        crypto::suite()
+            .sign(&secret_key_bytes, data)
+            .expect("Invalid secret key")
     }
 
     pub fn verify(
@@ -80,28 +62,7 @@ impl SignatureKeys {
         s: &str,
         v: i32,
     ) -> Result<bool, String> {
-        let secp = Secp256k1::new();
-        let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let message_hash = hasher.finalize();
-        let message = Message::from_digest_slice(&message_hash)
-            .map_err(|_| "Message could not be created from hash".to_string())?;
-
-        let sig_r = Vec::from_hex(Self::strip_hex_prefix(r)).map_err(|_| "Invalid hex in r".to_string())?;
-        let sig_s = Vec::from_hex(Self::strip_hex_prefix(s)).map_err(|_| "Invalid hex in s".to_string())?;
-        let signature_data = [&sig_r[..], &sig_s[..]].concat();
-        let recovery_id =
-            RecoveryId::from_i32(v - 27).map_err(|_| "Invalid recovery ID".to_string())?;
-        let recoverable_sig = RecoverableSignature::from_compact(&signature_data, recovery_id)
-            .map_err(|_| "Valid signature could not be created".to_string())?;
-
-        match secp.recover_ecdsa(&message, &recoverable_sig) {
-            Ok(recovered_public_key) => {
-                let derived_address = Self::derive_address(&recovered_public_key);
-                Ok(derived_address == derive_address)
-            }
-            Err(_) => Err("Public key could not be recovered".to_string()),
-        }
+        crypto::suite().verify(derive_address, data, r, s, v)
     }
 }
 
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::info;
+
+use crate::node::configuration::AppConfig;
+use crate::node::signature_keys::SignatureKeys;
+
+pub use crate::node::crypto::Signature;
+
+/// Body of `POST <remote_signer_url>/sign`: the address whose key should sign, and the
+/// hex-encoded bytes to sign (the signer applies the chain's hash, Keccak-256 by default,
+/// as `SignatureKeys::sign` does).
+#[derive(Debug, Serialize)]
+struct SignRequest<'a> {
+    address: &'a str,
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignResponse {
+    r: String,
+    s: String,
+    v: i32,
+}
+
+/// Signs authored blocks, either with the node's own key or through a signer service.
+#[derive(Debug, Clone)]
+pub enum BlockSigner {
+    /// `author_secret_key`, held in this node's memory.
+    Local { secret_key: String },
+    /// A signer service holding the key, so it never lives on the internet-facing node.
+    Remote(RemoteSigner),
+}
+
+impl BlockSigner {
+    pub fn from_config(config: &AppConfig) -> Self {
+        if config.remote_signer_url.is_empty() {
+            return BlockSigner::Local {
+                secret_key: config.author_secret_key.clone(),
+            };
+        }
+        info!(
+            "Signing blocks with the remote signer at {}",
+            config.remote_signer_url
+        );
+        BlockSigner::Remote(RemoteSigner::new(
+            &config.remote_signer_url,
+            &config.remote_signer_token,
+            Duration::from_millis(config.remote_signer_timeout_ms),
+        ))
+    }
+
+    pub fn sign(&self, address: &str, data: &[u8]) -> Result<Signature, String> {
+        match self {
+            BlockSigner::Local { secret_key } => Ok(SignatureKeys::sign(secret_key, data)),
+            BlockSigner::Remote(remote) => remote.sign(address, data),
+        }
+    }
+}
+
+/// Client for a signer service answering `POST <url>/sign` with `{address, data}` and
+/// replying `{r, s, v}`. Every signature is checked to recover to `address` before use,
+/// so a misconfigured signer can't make the node publish blocks it can't author.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    url: String,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl RemoteSigner {
+    /// An empty `token` sends no `Authorization` header.
+    pub fn new(url: &str, token: &str, timeout: Duration) -> Self {
+        Self {
+            url: format!("{}/sign", url.trim_end_matches('/')),
+            token: Some(token.to_string()).filter(|token| !token.is_empty()),
+            timeout,
+        }
+    }
+
+    /// Blocks until the signer answers or `timeout` passes. Block authoring is
+    /// synchronous, so the request runs on its own thread and runtime, which also works
+    /// when the caller is on a runtime thread.
+    pub fn sign(&self, address: &str, data: &[u8]) -> Result<Signature, String> {
+        let signer = self.clone();
+        let body = SignRequest {
+            address,
+            data: hex::encode(data),
+        };
+        let body = serde_json::to_value(body).map_err(|e| e.to_string())?;
+        let response = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            runtime.block_on(signer.request(body))
+        })
+        .join()
+        .map_err(|_| "Remote signer thread panicked".to_string())??;
+        Self::check(address, data, response)
+    }
+
+    async fn request(self, body: serde_json::Value) -> Result<SignResponse, String> {
+        let mut request = reqwest::Client::new()
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Remote signer request failed: {}", e))?
+            .json::<SignResponse>()
+            .await
+            .map_err(|e| format!("Invalid remote signer response: {}", e))
+    }
+
+    fn check(address: &str, data: &[u8], response: SignResponse) -> Result<Signature, String> {
+        let SignResponse { r, s, v } = response;
+        match SignatureKeys::verify(address, data, &r, &s, v) {
+            Ok(true) => Ok((r, s, v)),
+            Ok(false) => Err(format!("Remote signer did not sign as {}", address)),
+            Err(e) => Err(format!("Invalid remote signature: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_signatures_must_recover_to_the_author() {
+        let author = SignatureKeys::generate_new_keypair();
+        let other = SignatureKeys::generate_new_keypair();
+        let data = b"block hash";
+        let response = |secret_key: &str| {
+            let (r, s, v) = SignatureKeys::sign(secret_key, data);
+            SignResponse { r, s, v }
+        };
+
+        let signature =
+            RemoteSigner::check(&author.address_key, data, response(&author.secret_key)).unwrap();
+        assert!(SignatureKeys::verify(
+            &author.address_key,
+            data,
+            &signature.0,
+            &signature.1,
+            signature.2
+        )
+        .unwrap());
+        assert!(
+            RemoteSigner::check(&author.address_key, data, response(&other.secret_key)).is_err()
+        );
+
+        let local = BlockSigner::Local {
+            secret_key: author.secret_key.clone(),
+        };
+        assert_eq!(local.sign(&author.address_key, data).unwrap(), signature);
+    }
+
+    #[test]
+    fn sign_url_and_token() {
+        let signer = RemoteSigner::new("http://signer:9000/", "", Duration::from_secs(1));
+        assert_eq!(signer.url, "http://signer:9000/sign");
+        assert_eq!(signer.token, None);
+    }
+}
@@ -0,0 +1,352 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::block::Block;
+use crate::node::signature_keys::SignatureKeys;
+use crate::node::time_utils::set_simulated_time;
+use crate::node::transactions::transaction::Transaction;
+
+/// The authoring loop in `NodeServices` tries once a second.
+const TICK_MS: u64 = 1000;
+/// Like `NodeServices::start_sync`, each node asks a peer for missing blocks this often.
+const SYNC_INTERVAL_MS: u64 = 10_000;
+/// Blocks per `GetBlocks` reply.
+const MAX_BLOCKS_PER_REPLY: usize = 64;
+
+/// Names each simulation's databases apart, so simulations can run in parallel.
+static SIMULATION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    /// The first `authorities` nodes are Aura authorities; the rest only follow.
+    pub authorities: usize,
+    /// Seeds the node keys, message latencies and drops, so a run can be repeated.
+    pub seed: u64,
+    pub latency: Duration,
+    /// Up to this much is added to each message's latency.
+    pub jitter: Duration,
+    /// Chance that a message is lost, from 0 to 1.
+    pub drop_rate: f64,
+    pub block_reward_amount: u64,
+    /// Virtual Unix time, in seconds, the simulation starts at.
+    pub start_time: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            nodes: 3,
+            authorities: 3,
+            seed: 0,
+            latency: Duration::from_millis(50),
+            jitter: Duration::ZERO,
+            drop_rate: 0.0,
+            block_reward_amount: 50,
+            start_time: 1_700_000_000,
+        }
+    }
+}
+
+/// What the simulated nodes say to each other: gossip, and the block pull sync uses.
+#[derive(Debug)]
+enum Message {
+    Block(Block),
+    Transaction(Transaction),
+    GetBlocks { from: usize },
+    Blocks(Vec<Block>),
+}
+
+#[derive(Debug)]
+struct Envelope {
+    from: usize,
+    to: usize,
+    message: Message,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SimStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+}
+
+pub struct SimNode {
+    pub blockchain: Blockchain,
+    pub address: String,
+}
+
+/// Runs several nodes' chains in one thread against a virtual clock, joined by a
+/// simulated network with latency, drops and partitions. Nodes author, gossip and sync
+/// the way `NodeServices` drives them, without libp2p: gossip reaches every peer directly
+/// and sync asks a random peer for the blocks after its head. Everything random is drawn
+/// from `SimConfig::seed`, so the same config and calls give the same run.
+pub struct Simulation {
+    nodes: Vec<SimNode>,
+    rng: StdRng,
+    latency_ms: u64,
+    jitter_ms: u64,
+    drop_rate: f64,
+    now_ms: u64,
+    next_tick_ms: u64,
+    /// Messages by delivery time, then send order.
+    in_flight: BTreeMap<(u64, u64), Envelope>,
+    sent: u64,
+    /// Partition group of each node; messages only pass within a group.
+    groups: Vec<usize>,
+    stats: SimStats,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        assert!(
+            (1..=config.nodes).contains(&config.authorities),
+            "a simulation needs 1 to `nodes` authorities"
+        );
+        let now_ms = config.start_time * 1000;
+        set_simulated_time(Some(config.start_time));
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let keys: Vec<SignatureKeys> = (0..config.nodes)
+            .map(|_| SignatureKeys::generate_with(&mut rng))
+            .collect();
+        let authorities: Vec<String> = keys[..config.authorities]
+            .iter()
+            .map(|keys| keys.address_key.clone())
+            .collect();
+        let simulation = SIMULATION_COUNT.fetch_add(1, Ordering::Relaxed);
+        let nodes = keys
+            .into_iter()
+            .enumerate()
+            .map(|(i, keys)| SimNode {
+                blockchain: Blockchain::new(
+                    format!(
+                        "clutch-sim-{}-{}-node-{}",
+                        std::process::id(),
+                        simulation,
+                        i
+                    ),
+                    keys.address_key.clone(),
+                    keys.secret_key,
+                    true,
+                    authorities.clone(),
+                    config.block_reward_amount,
+                    2,
+                    2,
+                ),
+                address: keys.address_key,
+            })
+            .collect();
+
+        Simulation {
+            nodes,
+            rng,
+            latency_ms: config.latency.as_millis() as u64,
+            jitter_ms: config.jitter.as_millis() as u64,
+            drop_rate: config.drop_rate.clamp(0.0, 1.0),
+            now_ms,
+            next_tick_ms: now_ms + TICK_MS,
+            in_flight: BTreeMap::new(),
+            sent: 0,
+            groups: vec![0; config.nodes],
+            stats: SimStats::default(),
+        }
+    }
+
+    pub fn node(&self, index: usize) -> &SimNode {
+        &self.nodes[index]
+    }
+
+    /// Virtual Unix time in seconds.
+    pub fn now(&self) -> u64 {
+        self.now_ms / 1000
+    }
+
+    pub fn stats(&self) -> SimStats {
+        self.stats
+    }
+
+    /// `(index, hash)` of every node's head block.
+    pub fn heads(&self) -> Vec<(usize, String)> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let head = node
+                    .blockchain
+                    .get_latest_block()
+                    .expect("db read failed")
+                    .expect("chain has a head");
+                (head.index, head.hash)
+            })
+            .collect()
+    }
+
+    /// Adds `transaction` to `node`'s pool and gossips it, as the RPC server does.
+    pub fn submit_transaction(
+        &mut self,
+        node: usize,
+        transaction: &Transaction,
+    ) -> Result<(), String> {
+        self.nodes[node]
+            .blockchain
+            .add_transaction_to_pool(transaction)?;
+        self.broadcast(node, || Message::Transaction(transaction.clone()));
+        Ok(())
+    }
+
+    /// Cuts the network into `groups`; nodes left out form one more group. Messages
+    /// between groups are dropped, including those already in flight.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        self.groups = vec![groups.len(); self.nodes.len()];
+        for (group, nodes) in groups.iter().enumerate() {
+            for node in *nodes {
+                self.groups[*node] = group;
+            }
+        }
+    }
+
+    pub fn heal(&mut self) {
+        self.groups = vec![0; self.nodes.len()];
+    }
+
+    pub fn set_drop_rate(&mut self, drop_rate: f64) {
+        self.drop_rate = drop_rate.clamp(0.0, 1.0);
+    }
+
+    /// Advances virtual time by `duration`, delivering messages and running each node's
+    /// authoring and sync as they fall due.
+    pub fn run_for(&mut self, duration: Duration) {
+        let end_ms = self.now_ms + duration.as_millis() as u64;
+        loop {
+            let delivery = self
+                .in_flight
+                .keys()
+                .next()
+                .copied()
+                .filter(|(at, _)| *at <= self.next_tick_ms);
+            let at = delivery.map_or(self.next_tick_ms, |(at, _)| at);
+            if at > end_ms {
+                break;
+            }
+            self.set_now(at);
+            match delivery {
+                Some(key) => {
+                    let envelope = self.in_flight.remove(&key).expect("key was just read");
+                    self.deliver(envelope);
+                }
+                None => {
+                    self.tick();
+                    self.next_tick_ms += TICK_MS;
+                }
+            }
+        }
+        self.set_now(end_ms);
+    }
+
+    fn set_now(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+        set_simulated_time(Some(now_ms / 1000));
+    }
+
+    fn tick(&mut self) {
+        for node in 0..self.nodes.len() {
+            if self.now_ms % SYNC_INTERVAL_MS == 0 && self.nodes.len() > 1 {
+                let mut peer = self.rng.gen_range(0..self.nodes.len() - 1);
+                if peer >= node {
+                    peer += 1;
+                }
+                let from = self.head_index(node) + 1;
+                self.send(node, peer, Message::GetBlocks { from });
+            }
+            if let Ok(block) = self.nodes[node].blockchain.author_new_block() {
+                self.broadcast(node, || Message::Block(block.clone()));
+            }
+        }
+    }
+
+    fn deliver(&mut self, envelope: Envelope) {
+        let Envelope { from, to, message } = envelope;
+        if self.groups[from] != self.groups[to] {
+            self.stats.dropped += 1;
+            return;
+        }
+        self.stats.delivered += 1;
+        match message {
+            Message::Transaction(transaction) => {
+                let _ = self.nodes[to]
+                    .blockchain
+                    .add_transaction_to_pool(&transaction);
+            }
+            Message::Block(block) => {
+                let next = self.head_index(to) + 1;
+                if block.index == next {
+                    let _ = self.nodes[to].blockchain.import_block(&block);
+                } else if block.index > next {
+                    self.send(to, from, Message::GetBlocks { from: next });
+                }
+            }
+            Message::GetBlocks { from: start } => {
+                let blocks = self.nodes[to]
+                    .blockchain
+                    .get_blocks_with_limit_and_skip(start, 0, MAX_BLOCKS_PER_REPLY)
+                    .unwrap_or_default();
+                if !blocks.is_empty() {
+                    self.send(to, from, Message::Blocks(blocks));
+                }
+            }
+            Message::Blocks(blocks) => {
+                for block in blocks {
+                    if block.index != self.head_index(to) + 1
+                        || self.nodes[to].blockchain.import_block(&block).is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, message: impl Fn() -> Message) {
+        for to in 0..self.nodes.len() {
+            if to != from {
+                self.send(from, to, message());
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message) {
+        self.stats.sent += 1;
+        if self.groups[from] != self.groups[to] || self.rng.gen_bool(self.drop_rate) {
+            self.stats.dropped += 1;
+            return;
+        }
+        let latency = self.latency_ms + self.rng.gen_range(0..=self.jitter_ms);
+        self.sent += 1;
+        self.in_flight.insert(
+            (self.now_ms + latency, self.sent),
+            Envelope { from, to, message },
+        );
+    }
+
+    fn head_index(&self, node: usize) -> usize {
+        self.nodes[node]
+            .blockchain
+            .get_latest_block()
+            .ok()
+            .flatten()
+            .map_or(0, |block| block.index)
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        for node in &mut self.nodes {
+            node.blockchain.shutdown_blockchain();
+        }
+        set_simulated_time(None);
+    }
+}
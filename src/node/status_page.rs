@@ -0,0 +1,246 @@
+use axum::extract::State;
+use axum::response::{Html, Json};
+use axum::{routing::get, Router};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::RwLock;
+use tracing::error;
+
+use crate::node::blockchain::Blockchain;
+use crate::node::p2p_server::P2PServerCommand;
+use crate::node::time_utils::get_current_timespan;
+use crate::node::tui::DashboardState;
+
+/// Newest blocks listed on the page.
+const RECENT_BLOCKS: usize = 10;
+/// Seconds between the page's own reloads.
+const REFRESH_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentBlock {
+    pub index: usize,
+    pub hash: String,
+    pub author: String,
+    pub timestamp: u64,
+    pub transactions: usize,
+}
+
+/// Everything the status page shows, also served as JSON at `/status.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    #[serde(flatten)]
+    pub node: DashboardState,
+    pub recent_blocks: Vec<RecentBlock>,
+}
+
+#[derive(Clone)]
+struct StatusSource {
+    blockchain: Arc<RwLock<Blockchain>>,
+    command_tx_p2p: Sender<P2PServerCommand>,
+    chain_id: String,
+}
+
+/// Read-only `/status` page and `/status.json`, merged into the metrics server so an
+/// operator can check a node from a browser.
+pub fn router(
+    blockchain: Arc<RwLock<Blockchain>>,
+    command_tx_p2p: Sender<P2PServerCommand>,
+    chain_id: String,
+) -> Router {
+    Router::new()
+        .route("/status", get(status_html))
+        .route("/status.json", get(status_json))
+        .with_state(StatusSource {
+            blockchain,
+            command_tx_p2p,
+            chain_id,
+        })
+}
+
+async fn collect(source: &StatusSource) -> NodeStatus {
+    let StatusSource {
+        blockchain,
+        command_tx_p2p,
+        chain_id,
+    } = source;
+    let node = DashboardState::collect(blockchain, command_tx_p2p, chain_id).await;
+    let first = (node.head_index + 1).saturating_sub(RECENT_BLOCKS);
+    let indexes: Vec<usize> = (first..=node.head_index).rev().collect();
+    let blocks = blockchain.read().await.get_blocks_by_indexes(indexes);
+    let recent_blocks = match blocks {
+        Ok(blocks) => blocks
+            .into_iter()
+            .map(|block| RecentBlock {
+                index: block.index,
+                hash: block.hash,
+                author: block.author,
+                timestamp: block.timestamp,
+                transactions: block.transactions.len(),
+            })
+            .collect(),
+        Err(e) => {
+            error!("Failed to read recent blocks: {}", e);
+            Vec::new()
+        }
+    };
+    NodeStatus {
+        node,
+        recent_blocks,
+    }
+}
+
+async fn status_json(State(source): State<StatusSource>) -> Json<NodeStatus> {
+    Json(collect(&source).await)
+}
+
+async fn status_html(State(source): State<StatusSource>) -> Html<String> {
+    Html(render(&collect(&source).await, get_current_timespan()))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| format!("<td>{}</td>", escape(cell)))
+        .collect();
+    format!("<tr>{}</tr>", cells.join(""))
+}
+
+fn table(headers: &[&str], rows: Vec<String>) -> String {
+    if rows.is_empty() {
+        return "<p>None</p>".to_string();
+    }
+    let headers: Vec<String> = headers
+        .iter()
+        .map(|header| format!("<th>{}</th>", header))
+        .collect();
+    format!(
+        "<table><tr>{}</tr>{}</table>",
+        headers.join(""),
+        rows.join("")
+    )
+}
+
+/// The page at unix time `now`. Plain HTML that reloads itself; no scripts.
+pub fn render(status: &NodeStatus, now: u64) -> String {
+    let node = &status.node;
+    let sync = match node.best_peer_head() {
+        Some(target) if target > node.head_index => format!(
+            "Syncing: block {} of {} ({}%)",
+            node.head_index,
+            target,
+            node.head_index * 100 / target
+        ),
+        Some(_) => "In sync with peers".to_string(),
+        None => "No peer heads yet".to_string(),
+    };
+    let summary = [
+        ("Chain", node.chain_id.clone()),
+        (
+            "Head",
+            format!(
+                "#{} {} ({}s ago)",
+                node.head_index, node.head_hash, node.head_age_secs
+            ),
+        ),
+        ("Sync", sync),
+        ("Peers", node.peers.len().to_string()),
+        (
+            "Mempool",
+            format!(
+                "{} pending, {} queued, {} stale",
+                node.pool.pending, node.pool.queued, node.pool.stale
+            ),
+        ),
+    ];
+    let summary: Vec<String> = summary
+        .iter()
+        .map(|(name, value)| format!("<tr><th>{}</th><td>{}</td></tr>", name, escape(value)))
+        .collect();
+
+    let peers = node
+        .peers
+        .iter()
+        .map(|peer| {
+            row(&[
+                peer.peer_id.clone(),
+                peer.latest_block_index
+                    .map_or("-".to_string(), |index| index.to_string()),
+                peer.latency_ms
+                    .map_or("-".to_string(), |ms| format!("{} ms", ms)),
+                format!("{}s", peer.connected_secs),
+                peer.wire_format.to_string(),
+            ])
+        })
+        .collect();
+    let blocks = status
+        .recent_blocks
+        .iter()
+        .map(|block| {
+            row(&[
+                block.index.to_string(),
+                block.hash.clone(),
+                block.author.clone(),
+                format!("{}s ago", now.saturating_sub(block.timestamp)),
+                block.transactions.to_string(),
+            ])
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{refresh}\">\
+         <title>Clutch node status</title>\
+         <style>body{{font-family:monospace;margin:2em}}table{{border-collapse:collapse;\
+         margin-bottom:1.5em}}th,td{{text-align:left;padding:2px 12px 2px 0}}</style>\
+         </head><body><h1>Clutch node status</h1><table>{summary}</table>\
+         <h2>Peers</h2>{peers}<h2>Recent blocks</h2>{blocks}</body></html>",
+        refresh = REFRESH_SECS,
+        summary = summary.join(""),
+        peers = table(&["Peer", "Head", "Latency", "Connected", "Format"], peers),
+        blocks = table(&["Index", "Hash", "Author", "Age", "Txs"], blocks),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_shows_the_head_sync_and_escaped_values() {
+        let status = NodeStatus {
+            node: DashboardState {
+                chain_id: "<dev>".to_string(),
+                head_index: 9,
+                head_hash: "0xhead".to_string(),
+                ..DashboardState::default()
+            },
+            recent_blocks: vec![RecentBlock {
+                index: 9,
+                hash: "0xhead".to_string(),
+                author: "0xauthor".to_string(),
+                timestamp: 100,
+                transactions: 2,
+            }],
+        };
+        let page = render(&status, 130);
+
+        assert!(page.contains("<td>&lt;dev&gt;</td>"));
+        assert!(page.contains("#9 0xhead (0s ago)"));
+        assert!(page.contains("No peer heads yet"));
+        assert!(page.contains("<h2>Peers</h2><p>None</p>"));
+        assert!(page.contains("<td>0xauthor</td><td>30s ago</td><td>2</td>"));
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["head_index"], 9);
+        assert_eq!(json["recent_blocks"][0]["transactions"], 2);
+        assert!(json.get("blocks_per_sec").is_none());
+    }
+}
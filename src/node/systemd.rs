@@ -0,0 +1,234 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Main loops the watchdog waits on, by the name they beat under.
+pub const P2P_LOOP: &str = "p2p";
+pub const AUTHORING_LOOP: &str = "authoring";
+/// Components that must be up before systemd is told the node is ready.
+pub const P2P_LISTENING: &str = "p2p";
+pub const RPC_LISTENING: &str = "rpc";
+
+/// Sends `state` (e.g. `READY=1`) to systemd's `NOTIFY_SOCKET`. Returns whether a message
+/// was sent; false when the node wasn't started by systemd with `Type=notify`.
+pub fn notify(state: &str) -> bool {
+    let Ok(socket) = std::env::var("NOTIFY_SOCKET") else {
+        return false;
+    };
+    match send(&socket, state) {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Failed to notify systemd ({}): {}", state, e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &str, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &str, _state: &str) -> std::io::Result<()> {
+    Err(std::io::Error::other(
+        "systemd notification needs Unix sockets",
+    ))
+}
+
+/// The interval systemd expects watchdog pings within, from `WATCHDOG_USEC`; `None` when
+/// the watchdog is off or meant for another process (`WATCHDOG_PID`).
+pub fn watchdog_timeout(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse::<u32>().ok() != Some(own_pid)) {
+        return None;
+    }
+    usec?
+        .parse::<u64>()
+        .ok()
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Readiness components not up yet; `READY=1` is sent once this empties.
+    pending: HashSet<&'static str>,
+    ready: bool,
+    heartbeats: HashMap<&'static str, Instant>,
+}
+
+/// systemd integration for `Type=notify` units: `READY=1` once the database is open and
+/// the P2P and RPC servers listen, and `WATCHDOG=1` pings while every main loop keeps
+/// beating, so `WatchdogSec=` restarts a node whose swarm task has deadlocked. Cheap to
+/// clone; does nothing when the node wasn't started by systemd.
+#[derive(Debug, Clone, Default)]
+pub struct Systemd {
+    state: Arc<Mutex<State>>,
+}
+
+impl Systemd {
+    /// Ready once every one of `components` has called `ready`.
+    pub fn new(components: &[&'static str]) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                pending: components.iter().copied().collect(),
+                ..State::default()
+            })),
+        }
+    }
+
+    /// Marks `component` up; returns true for the call that made the node ready, which
+    /// also notifies systemd.
+    pub fn ready(&self, component: &'static str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(component);
+        if state.ready || !state.pending.is_empty() {
+            return false;
+        }
+        state.ready = true;
+        drop(state);
+        if notify("READY=1\nSTATUS=Listening") {
+            info!("Notified systemd that the node is ready");
+        }
+        true
+    }
+
+    /// Called by each main loop on every iteration; a loop that stops beating for the
+    /// watchdog timeout stops the pings.
+    pub fn beat(&self, main_loop: &'static str) {
+        self.beat_at(main_loop, Instant::now());
+    }
+
+    fn beat_at(&self, main_loop: &'static str, now: Instant) {
+        self.state.lock().unwrap().heartbeats.insert(main_loop, now);
+    }
+
+    /// Main loops that haven't beaten within `timeout` of `now`.
+    pub fn stalled(&self, now: Instant, timeout: Duration) -> Vec<&'static str> {
+        let state = self.state.lock().unwrap();
+        let mut stalled: Vec<&'static str> = state
+            .heartbeats
+            .iter()
+            .filter(|(_, beat)| now.saturating_duration_since(**beat) > timeout)
+            .map(|(main_loop, _)| *main_loop)
+            .collect();
+        stalled.sort();
+        stalled
+    }
+
+    /// Pings systemd at half its watchdog timeout while no main loop has stalled. Does
+    /// nothing unless the unit sets `WatchdogSec=`.
+    pub fn start_watchdog(&self) {
+        let usec = std::env::var("WATCHDOG_USEC").ok();
+        let pid = std::env::var("WATCHDOG_PID").ok();
+        let Some(timeout) = watchdog_timeout(usec.as_deref(), pid.as_deref(), std::process::id())
+        else {
+            return;
+        };
+        info!("systemd watchdog enabled, timeout {:?}", timeout);
+        let systemd = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(timeout / 2);
+            let mut was_stalled = false;
+            loop {
+                interval.tick().await;
+                let stalled = systemd.stalled(Instant::now(), timeout);
+                if stalled.is_empty() {
+                    was_stalled = false;
+                    debug!("Pinging systemd watchdog");
+                    notify("WATCHDOG=1");
+                } else if !was_stalled {
+                    was_stalled = true;
+                    warn!(
+                        "Main loops {:?} stopped responding; withholding watchdog pings",
+                        stalled
+                    );
+                }
+            }
+        });
+    }
+
+    pub fn stopping(&self) {
+        notify("STOPPING=1");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_once_every_component_is_up() {
+        let systemd = Systemd::new(&[P2P_LISTENING, RPC_LISTENING]);
+        assert!(!systemd.ready(P2P_LISTENING));
+        assert!(!systemd.ready(P2P_LISTENING));
+        assert!(systemd.ready(RPC_LISTENING));
+        assert!(!systemd.ready(RPC_LISTENING));
+    }
+
+    #[test]
+    fn loops_that_stop_beating_are_stalled() {
+        let systemd = Systemd::default();
+        let start = Instant::now();
+        systemd.beat_at(P2P_LOOP, start);
+        systemd.beat_at(AUTHORING_LOOP, start + Duration::from_secs(20));
+
+        let timeout = Duration::from_secs(15);
+        assert!(systemd
+            .stalled(start + Duration::from_secs(10), timeout)
+            .is_empty());
+        assert_eq!(
+            systemd.stalled(start + Duration::from_secs(30), timeout),
+            [P2P_LOOP]
+        );
+    }
+
+    #[test]
+    fn watchdog_timeout_is_for_this_process_only() {
+        let own = Some("42");
+        assert_eq!(
+            watchdog_timeout(Some("30000000"), own, 42),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            watchdog_timeout(Some("30000000"), None, 42),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(watchdog_timeout(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_timeout(Some("0"), own, 42), None);
+        assert_eq!(watchdog_timeout(None, own, 42), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn messages_reach_the_notify_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!("clutch-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send(&path.to_string_lossy(), "WATCHDOG=1").unwrap();
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+        let _ = std::fs::remove_file(&path);
+    }
+}
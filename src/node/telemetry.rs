@@ -0,0 +1,202 @@
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::RwLock;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::node::blockchain::Blockchain;
+use crate::node::configuration::AppConfig;
+use crate::node::p2p_server::P2PServerCommand;
+use crate::node::tui::DashboardState;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// One telemetry message. Nothing in it identifies the operator: no keys, addresses,
+/// peer IDs or IPs, and `node_id` is random for each run of the node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TelemetryReport {
+    pub node_id: String,
+    /// `telemetry_name`, if the operator chose to show one.
+    pub name: Option<String>,
+    pub version: String,
+    pub chain_id: String,
+    pub head_index: usize,
+    pub head_hash: String,
+    pub peers: usize,
+    /// Some peer has advertised a head above ours.
+    pub syncing: bool,
+    pub os: String,
+    pub arch: String,
+    pub uptime_secs: u64,
+}
+
+impl TelemetryReport {
+    pub fn new(node_id: &str, name: &str, state: &DashboardState, uptime: Duration) -> Self {
+        TelemetryReport {
+            node_id: node_id.to_string(),
+            name: Some(name.to_string()).filter(|name| !name.is_empty()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            chain_id: state.chain_id.clone(),
+            head_index: state.head_index,
+            head_hash: state.head_hash.clone(),
+            peers: state.peers.len(),
+            syncing: state
+                .best_peer_head()
+                .is_some_and(|best| best > state.head_index),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            uptime_secs: uptime.as_secs(),
+        }
+    }
+}
+
+/// Opt-in reporter started when `telemetry_url` is set: keeps a WebSocket open to the
+/// telemetry server and sends a `TelemetryReport` as a JSON text message every
+/// `telemetry_interval_secs`, reconnecting after failures. Reports are best effort and
+/// never hold up the node.
+pub struct Telemetry {
+    url: String,
+    name: String,
+    interval: Duration,
+    node_id: String,
+    started: Instant,
+    blockchain: Arc<RwLock<Blockchain>>,
+    command_tx_p2p: Sender<P2PServerCommand>,
+    chain_id: String,
+}
+
+impl Telemetry {
+    pub fn start(
+        config: &AppConfig,
+        blockchain: Arc<RwLock<Blockchain>>,
+        command_tx_p2p: Sender<P2PServerCommand>,
+    ) {
+        let telemetry = Telemetry {
+            url: config.telemetry_url.clone(),
+            name: config.telemetry_name.clone(),
+            interval: Duration::from_secs(config.telemetry_interval_secs.max(1)),
+            node_id: hex::encode(rand::random::<[u8; 8]>()),
+            started: Instant::now(),
+            blockchain,
+            command_tx_p2p,
+            chain_id: config.chain_id.clone(),
+        };
+        tokio::spawn(telemetry.run());
+    }
+
+    async fn run(self) {
+        loop {
+            match tokio_tungstenite::connect_async(self.url.as_str()).await {
+                Ok((stream, _)) => {
+                    info!("Reporting telemetry to {}", self.url);
+                    if let Err(e) = self.report(stream).await {
+                        warn!("Telemetry connection lost: {}", e);
+                    }
+                }
+                Err(e) => warn!("Could not connect to telemetry server {}: {}", self.url, e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn collect(&self) -> TelemetryReport {
+        let state =
+            DashboardState::collect(&self.blockchain, &self.command_tx_p2p, &self.chain_id).await;
+        TelemetryReport::new(&self.node_id, &self.name, &state, self.started.elapsed())
+    }
+
+    async fn report(
+        &self,
+        mut stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    ) -> Result<(), String> {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let json = serde_json::to_string(&self.collect().await)
+                        .map_err(|e| e.to_string())?;
+                    stream
+                        .send(Message::Text(json))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                // Read what the server sends so pings are answered and a close is noticed.
+                message = stream.next() => match message {
+                    Some(Ok(Message::Close(_))) | None => {
+                        return Err("closed by the server".to_string());
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.to_string()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::p2p_server::peer_table::PeerSummary;
+
+    #[test]
+    fn reports_carry_no_identifying_details() {
+        let state = DashboardState {
+            chain_id: "clutch-devnet".to_string(),
+            head_index: 10,
+            head_hash: "h10".to_string(),
+            peers: vec![PeerSummary {
+                peer_id: "12D3KooWPeer".to_string(),
+                connected_secs: 0,
+                handshaked: true,
+                latest_block_index: Some(12),
+                latest_block_hash: None,
+                head_weight: None,
+                head_updated_secs: None,
+                latency_ms: None,
+                blocks_per_sec: None,
+                wire_format: "rlp",
+                capabilities: Vec::new(),
+                record_addresses: vec!["/ip4/203.0.113.7/tcp/4001".to_string()],
+            }],
+            ..DashboardState::default()
+        };
+
+        let report = TelemetryReport::new("0011", "", &state, Duration::from_secs(90));
+        assert_eq!(report.name, None);
+        assert_eq!(report.peers, 1);
+        assert!(report.syncing);
+        assert_eq!(report.uptime_secs, 90);
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(!json.contains("12D3KooWPeer") && !json.contains("203.0.113.7"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "arch",
+                "chain_id",
+                "head_hash",
+                "head_index",
+                "name",
+                "node_id",
+                "os",
+                "peers",
+                "syncing",
+                "uptime_secs",
+                "version"
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tracing::error;
+
+use crate::node::blockchain::Blockchain;
+use crate::node::blocks::block::Block;
+use crate::node::p2p_server::{GossipMessageType, P2PConfig, P2PServer, P2PServerCommand};
+use crate::node::rlp_encoding::encode;
+use crate::node::signature_keys::SignatureKeys;
+
+const TOPIC: &str = "clutch-test-utils";
+/// How often `await_synced` and `await_connected` look again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Names each pair's databases and memory ports apart, so tests can run in parallel.
+static PAIR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A node wired the way `NodeServices` wires one: a `Blockchain` shared with its running
+/// `P2PServer`, driven through the server's command channel.
+pub struct TestNode {
+    pub blockchain: Arc<RwLock<Blockchain>>,
+    pub commands: mpsc::Sender<P2PServerCommand>,
+    pub address: String,
+    server: JoinHandle<()>,
+}
+
+impl TestNode {
+    fn start(name: String, keys: &SignatureKeys, authority: &str, config: P2PConfig) -> Self {
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(
+            name,
+            keys.address_key.clone(),
+            keys.secret_key.clone(),
+            true,
+            vec![authority.to_string()],
+            50,
+            2,
+            2,
+        )));
+        let (commands, command_rx) = mpsc::channel(32);
+        let server_blockchain = Arc::clone(&blockchain);
+        let server = tokio::spawn(async move {
+            let mut server = match P2PServer::with_config(config) {
+                Ok(server) => server,
+                Err(e) => {
+                    error!("Failed to start test node's p2p server: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = server.run(server_blockchain, command_rx).await {
+                error!("Test node's p2p server stopped: {}", e);
+            }
+        });
+        TestNode {
+            blockchain,
+            commands,
+            address: keys.address_key.clone(),
+            server,
+        }
+    }
+
+    /// `(index, hash)` of the head block.
+    pub async fn head(&self) -> Result<(usize, String), String> {
+        let head = self
+            .blockchain
+            .read()
+            .await
+            .get_latest_block()?
+            .ok_or("Chain has no head")?;
+        Ok((head.index, head.hash))
+    }
+
+    async fn shutdown(self) {
+        self.server.abort();
+        let _ = self.server.await;
+        self.blockchain.write().await.shutdown_blockchain();
+    }
+}
+
+/// Two nodes in one tokio test, connected over libp2p's in-memory transport: `author` is
+/// the chain's only authority and `follower` dials it at startup, so end-to-end sync runs
+/// without spawning processes. Each node has its own throwaway RocksDB; call `shutdown`
+/// at the end of the test to stop the servers and delete them.
+pub struct TwoNodes {
+    pub author: TestNode,
+    pub follower: TestNode,
+}
+
+impl TwoNodes {
+    pub fn start() -> Self {
+        Self::start_with(|_| {})
+    }
+
+    /// Like `start`, with `configure` applied to both nodes' p2p settings.
+    pub fn start_with(configure: impl Fn(&mut P2PConfig)) -> Self {
+        let pair = PAIR_COUNT.fetch_add(1, Ordering::Relaxed);
+        // Memory ports are per process; 0 would pick one we couldn't tell the follower.
+        let author_addr = format!("/memory/{}", pair + 1);
+        let name = |role: &str| format!("clutch-test-{}-{}-{}", std::process::id(), pair, role);
+        let author_keys = SignatureKeys::generate_new_keypair();
+        let follower_keys = SignatureKeys::generate_new_keypair();
+
+        let mut author_config = P2PConfig::new(TOPIC, &[author_addr.as_str()], &[]);
+        configure(&mut author_config);
+        let mut follower_config = P2PConfig::new(TOPIC, &[], &[author_addr.as_str()]);
+        configure(&mut follower_config);
+
+        let author = TestNode::start(
+            name("author"),
+            &author_keys,
+            &author_keys.address_key,
+            author_config,
+        );
+        let follower = TestNode::start(
+            name("follower"),
+            &follower_keys,
+            &author_keys.address_key,
+            follower_config,
+        );
+        TwoNodes { author, follower }
+    }
+
+    /// Waits until the follower's server reports the author as connected.
+    pub async fn await_connected(&self, timeout: Duration) -> Result<(), String> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let peers = P2PServer::get_connected_peers_command(self.follower.commands.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+            if !peers.is_empty() {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(format!("Nodes did not connect within {:?}", timeout));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Authors `n` blocks on `author` and gossips each, as the authoring loop does.
+    pub async fn mine_blocks(&self, n: usize) -> Result<Vec<Block>, String> {
+        let mut blocks = Vec::with_capacity(n);
+        for _ in 0..n {
+            let block = self.author.blockchain.write().await.author_new_block()?;
+            P2PServer::gossip_message_command(
+                self.author.commands.clone(),
+                GossipMessageType::Block,
+                &encode(&block),
+            )
+            .await;
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// Waits until the follower's head is the author's, and returns its index.
+    pub async fn await_synced(&self, timeout: Duration) -> Result<usize, String> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let author = self.author.head().await?;
+            let follower = self.follower.head().await?;
+            if author == follower {
+                return Ok(author.0);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(format!(
+                    "Follower at block {} did not reach the author's block {} within {:?}",
+                    follower.0, author.0, timeout
+                ));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn shutdown(self) {
+        self.author.shutdown().await;
+        self.follower.shutdown().await;
+    }
+}
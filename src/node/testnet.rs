@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::info;
+
+use crate::node::configuration::AppConfig;
+use crate::node::database::Database;
+use crate::node::node_builder::NodeBuilder;
+use crate::node::signature_keys::SignatureKeys;
+
+/// Aura's step is `60 / authorities` seconds, so more validators would make it zero.
+pub const MAX_TESTNET_NODES: usize = 60;
+/// Lets each node start listening before the next one dials it.
+const NODE_START_STAGGER: Duration = Duration::from_millis(500);
+
+/// One validator of a local testnet.
+#[derive(Debug)]
+pub struct TestnetNode {
+    pub config: AppConfig,
+    pub storage_dir: PathBuf,
+}
+
+/// Configs for `nodes` validators on this machine, derived from `base`. Each gets a new
+/// keypair and every key is an authority, so they take turns authoring. Node `i` serves
+/// RPC on `base_port + 2i` and P2P on `base_port + 2i + 1`, stores its chain in
+/// `dir/node-{i}` and bootstraps from every node before it. Metrics, whose registry is
+/// shared by the process, and the exporters are off.
+pub fn testnet_nodes(
+    base: &AppConfig,
+    nodes: usize,
+    base_port: u16,
+    dir: &Path,
+) -> Result<Vec<TestnetNode>, String> {
+    if nodes == 0 || nodes > MAX_TESTNET_NODES {
+        return Err(format!("A testnet has 1 to {} nodes", MAX_TESTNET_NODES));
+    }
+    let port = |offset: usize| base_port as usize + offset;
+    if port(2 * nodes - 1) > u16::MAX as usize {
+        return Err(format!(
+            "{} nodes need ports {} to {}",
+            nodes,
+            base_port,
+            port(2 * nodes - 1)
+        ));
+    }
+    let p2p_addr = |i: usize| format!("/ip4/127.0.0.1/tcp/{}", port(2 * i + 1));
+
+    let keys: Vec<SignatureKeys> = (0..nodes)
+        .map(|_| SignatureKeys::generate_new_keypair())
+        .collect();
+    let authorities: Vec<String> = keys.iter().map(|keys| keys.address_key.clone()).collect();
+    Ok(keys
+        .into_iter()
+        .enumerate()
+        .map(|(i, keys)| {
+            let storage_dir = dir.join(format!("node-{}", i));
+            let mut config = base.clone();
+            config.blockchain_name = format!("testnet-node-{}", i);
+            config.author_public_key = keys.address_key;
+            config.author_secret_key = keys.secret_key;
+            config.authorities = authorities.clone();
+            config.block_authoring_enabled = true;
+            config.developer_mode = false;
+            config.websocket_addr = format!("127.0.0.1:{}", port(2 * i));
+            config.listen_addrs = vec![p2p_addr(i)];
+            config.external_addrs = Vec::new();
+            config.bootstrap_nodes = (0..i).map(p2p_addr).collect();
+            config.known_peers_file = storage_dir
+                .join("known-peers.json")
+                .to_string_lossy()
+                .into_owned();
+            config.serve_metric_enabled = false;
+            config.firehose_nats_url = String::new();
+            config.explorer_postgres_url = String::new();
+            TestnetNode {
+                config,
+                storage_dir,
+            }
+        })
+        .collect())
+}
+
+/// Runs every node in this process until Ctrl+C. Each launch is a new chain: a database
+/// left by an earlier launch is deleted first, since other keys signed its blocks.
+pub async fn run_testnet(nodes: Vec<TestnetNode>) -> Result<(), String> {
+    let mut running = JoinSet::new();
+    for node in nodes {
+        std::fs::create_dir_all(&node.storage_dir).map_err(|e| {
+            format!(
+                "Failed to create {}: {}",
+                node.storage_dir.to_string_lossy(),
+                e
+            )
+        })?;
+        let db_path = Database::db_path_in(
+            &node.storage_dir.to_string_lossy(),
+            &node.config.blockchain_name,
+        );
+        if Path::new(&db_path).exists() {
+            Database::destroy(&db_path)?;
+        }
+
+        info!(
+            "Starting {} (author {}): RPC ws://{}, P2P {}",
+            node.config.blockchain_name,
+            node.config.author_public_key,
+            node.config.websocket_addr,
+            node.config.listen_addrs.join(", ")
+        );
+        let node = NodeBuilder::new(node.config)
+            .with_storage(node.storage_dir)
+            .build();
+        running.spawn(node.run());
+        tokio::time::sleep(NODE_START_STAGGER).await;
+    }
+    while running.join_next().await.is_some() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_share_authorities_and_chain_their_bootnodes() {
+        let base = AppConfig::load_configuration("default").expect("default config loads");
+        let nodes = testnet_nodes(&base, 3, 18000, Path::new("testnet")).unwrap();
+
+        assert_eq!(nodes.len(), 3);
+        let authorities = &nodes[0].config.authorities;
+        assert_eq!(authorities.len(), 3);
+        for (i, node) in nodes.iter().enumerate() {
+            assert_eq!(&node.config.authorities, authorities);
+            assert_eq!(node.config.author_public_key, authorities[i]);
+            assert_eq!(
+                node.storage_dir,
+                Path::new("testnet").join(format!("node-{}", i))
+            );
+        }
+        assert_eq!(nodes[2].config.websocket_addr, "127.0.0.1:18004");
+        assert_eq!(nodes[2].config.listen_addrs, ["/ip4/127.0.0.1/tcp/18005"]);
+        assert_eq!(
+            nodes[2].config.bootstrap_nodes,
+            ["/ip4/127.0.0.1/tcp/18001", "/ip4/127.0.0.1/tcp/18003"]
+        );
+
+        assert!(testnet_nodes(&base, 0, 18000, Path::new("testnet")).is_err());
+        assert!(testnet_nodes(&base, 2, u16::MAX - 2, Path::new("testnet")).is_err());
+    }
+}
@@ -1,8 +1,25 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "simulation")]
+thread_local! {
+    /// Set by `Simulation` so slots and block timestamps follow its virtual clock.
+    static SIMULATED_TIME: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
 pub fn get_current_timespan() -> u64 {
+    #[cfg(feature = "simulation")]
+    if let Some(now) = SIMULATED_TIME.with(|time| time.get()) {
+        return now;
+    }
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs()
-}
\ No newline at end of file
+}
+
+/// Makes `get_current_timespan` on this thread return `now`, or the system time again
+/// for `None`.
+#[cfg(feature = "simulation")]
+pub fn set_simulated_time(now: Option<u64>) {
+    SIMULATED_TIME.with(|time| time.set(now));
+}
@@ -1,26 +1,57 @@
-use std::sync::Arc;
+use std::fs::OpenOptions;
+use std::sync::{Arc, OnceLock};
 use tokio::sync::Mutex;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
 
 use super::seq::{SeqLayer, SeqLogger};
 
+/// Swaps the filter of the subscriber `setup_tracing` installed.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Logs to stdout, or appends them to `log_file` when given (e.g. while the terminal
+/// dashboard owns stdout), and to Seq.
 pub fn setup_tracing(
     log_level: &str,
     seq_url: &str,
     seq_api_key: &str,
+    log_file: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let seq_logger = Arc::new(Mutex::new(SeqLogger::new(seq_url, seq_api_key)));
     let seq_layer = SeqLayer::new(seq_logger);
 
-    tracing_subscriber::registry()
-        .with(fmt::layer())
+    let (stdout_layer, file_layer) = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (None, Some(fmt::layer().with_ansi(false).with_writer(file)))
+        }
+        None => (Some(fmt::layer()), None),
+    };
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(log_level));
+    let initialized = tracing_subscriber::registry()
+        .with(filter)
+        .with(stdout_layer)
+        .with(file_layer)
         .with(seq_layer)
-        .with(EnvFilter::new(log_level))
-        .try_init()
-        .or_else(|_| {
-            println!("Global default trace dispatcher has already been set");
-            Ok::<(), Box<dyn std::error::Error>>(())
-        })?;
+        .try_init();
+    match initialized {
+        Ok(()) => {
+            let _ = LOG_FILTER.set(filter_handle);
+        }
+        Err(_) => println!("Global default trace dispatcher has already been set"),
+    }
 
     Ok(())
 }
+
+/// Applies a new `log_level` directive to the running subscriber.
+pub fn set_log_level(log_level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(log_level)
+        .map_err(|e| format!("Invalid log level {:?}: {}", log_level, e))?;
+    LOG_FILTER
+        .get()
+        .ok_or_else(|| "Tracing was not set up by this process".to_string())?
+        .reload(filter)
+        .map_err(|e| format!("Failed to change the log level: {}", e))
+}
@@ -24,6 +24,19 @@ pub fn optional_canonical_referrer(s: String) -> Option<String> {
     }
 }
 
+/// `#[rlp(with = ..)]` codec for optional referrers: written as an empty string when absent.
+pub mod referrer_rlp {
+    use rlp::{DecoderError, Rlp, RlpStream};
+
+    pub fn rlp_append(referrer: &Option<String>, stream: &mut RlpStream) {
+        stream.append(&referrer.as_deref().unwrap_or_default());
+    }
+
+    pub fn decode(rlp: &Rlp) -> Result<Option<String>, DecoderError> {
+        Ok(super::optional_canonical_referrer(rlp.as_val()?))
+    }
+}
+
 /// Legacy on-chain referrer keys stored without the `0x` prefix (pre-canonicalization).
 pub fn legacy_account_address_hex(canonical: &str) -> String {
     canonical
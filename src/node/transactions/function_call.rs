@@ -1,22 +1,37 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::node::rlp_encoding::RlpCodec;
+
+use super::stake::{Stake, Unstake};
 use super::{
     ride_acceptance::RideAcceptance, ride_cancel::RideCancel, ride_offer::RideOffer,
     ride_pay::RidePay, ride_request::RideRequest, ride_request_cancel::RideRequestCancel,
     transfer::Transfer,
 };
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// RLP tags are part of the signed transaction encoding: never renumber a variant.
+#[derive(Debug, Serialize, Deserialize, Clone, RlpCodec)]
 #[serde(tag = "function_call_type", content = "arguments")]
 pub enum FunctionCall {
+    #[rlp(tag = 0)]
     Transfer(Transfer),
+    #[rlp(tag = 1)]
     RideRequest(RideRequest),
+    #[rlp(tag = 2)]
     RideOffer(RideOffer),
+    #[rlp(tag = 3)]
     RideAcceptance(RideAcceptance),
+    #[rlp(tag = 4)]
     RidePay(RidePay),
+    #[rlp(tag = 5)]
     RideCancel(RideCancel),
+    #[rlp(tag = 8)]
     RideRequestCancel(RideRequestCancel),
+    #[rlp(tag = 9)]
+    Stake(Stake),
+    #[rlp(tag = 10)]
+    Unstake(Unstake),
 }
 
 impl fmt::Display for FunctionCall {
@@ -29,6 +44,8 @@ impl fmt::Display for FunctionCall {
             FunctionCall::RidePay(args) => write!(f, "RidePay: {:?}", args),
             FunctionCall::RideCancel(args) => write!(f, "RideCancel: {:?}", args),
             FunctionCall::RideRequestCancel(args) => write!(f, "RideRequestCancel: {:?}", args),
+            FunctionCall::Stake(args) => write!(f, "Stake: {:?}", args),
+            FunctionCall::Unstake(args) => write!(f, "Unstake: {:?}", args),
         }
     }
 }
@@ -1,12 +1,16 @@
 pub mod address;
 pub mod function_call;
 pub mod passenger_concurrent;
+pub mod receipt;
 pub mod ride_acceptance;
 pub mod ride_cancel;
 pub mod ride_offer;
 pub mod ride_pay;
 pub mod ride_request;
 pub mod ride_request_cancel;
+pub mod sender_recovery;
+pub mod stake;
+pub mod trace;
 pub mod transaction;
 pub mod transaction_pool;
 pub mod transfer;
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::node::balance_effect::{BalanceEffect, StoredBalanceEffect};
+use crate::node::blocks::block::Block;
+use crate::node::database::ReadStore;
+
+/// Where an imported transaction sits in the chain, stored under `tx_location_{hash}` in
+/// the `block` column family. Written in the same batch as its block, so the index never
+/// points at a block that isn't stored.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block_index: usize,
+    pub tx_index: usize,
+}
+
+impl TxLocation {
+    pub fn construct_key(tx_hash: &str) -> Vec<u8> {
+        format!("tx_location_{}", tx_hash).into_bytes()
+    }
+
+    pub fn load(db: &impl ReadStore, tx_hash: &str) -> Result<Option<TxLocation>, String> {
+        match db.get("block", &Self::construct_key(tx_hash))? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to decode location of {}: {}", tx_hash, e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Outcome of an imported transaction. Its logs are the balance effects it caused.
+#[derive(Serialize, Debug, Clone)]
+pub struct TransactionReceipt {
+    pub transaction_hash: String,
+    pub block_index: usize,
+    pub block_hash: String,
+    pub transaction_index: usize,
+    pub from: String,
+    pub function_call_type: String,
+    /// Always 1: a block with a transaction that fails is rejected whole, so every
+    /// transaction in the chain succeeded.
+    pub status: u8,
+    pub logs: Vec<StoredBalanceEffect>,
+}
+
+impl TransactionReceipt {
+    /// Receipt of the transaction at `tx_index` in `block`, with the effects it caused.
+    pub fn new(block: &Block, tx_index: usize, logs: Vec<StoredBalanceEffect>) -> Self {
+        let tx = &block.transactions[tx_index];
+        TransactionReceipt {
+            transaction_hash: tx.hash.clone(),
+            block_index: block.index,
+            block_hash: block.hash.clone(),
+            transaction_index: tx_index,
+            from: tx.from.clone(),
+            function_call_type: tx.function_call_type().to_string(),
+            status: 1,
+            logs,
+        }
+    }
+}
+
+/// The part of a receipt a block commits to in `receipts_root`, one Merkle leaf (as JSON)
+/// per transaction. The rest of a receipt follows from the block itself.
+#[derive(Serialize, Debug)]
+pub struct ReceiptCommitment<'a> {
+    pub transaction_hash: &'a str,
+    pub status: u8,
+    pub logs: &'a [BalanceEffect],
+}
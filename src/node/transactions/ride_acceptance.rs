@@ -1,3 +1,4 @@
+use crate::node::rlp_encoding::RlpCodec;
 use crate::node::{
     account_state::AccountState,
     balance_effect::{BalanceEffectKind, StateUpdate},
@@ -6,10 +7,9 @@ use crate::node::{
     transactions::ride_offer::RideOffer,
     transactions::ride_request::RideRequest,
 };
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct RideAcceptance {
     pub ride_offer_transaction_hash: String,
 }
@@ -533,21 +533,3 @@ impl RideAcceptance {
         Ok(result)
     }
 }
-
-impl Encodable for RideAcceptance {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append(&self.ride_offer_transaction_hash);
-    }
-}
-
-impl Decodable for RideAcceptance {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        Ok(RideAcceptance {
-            ride_offer_transaction_hash: rlp.val_at(0)?,
-        })
-    }
-}
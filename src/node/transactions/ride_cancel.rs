@@ -1,4 +1,4 @@
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
@@ -10,7 +10,7 @@ use crate::node::{
 
 use super::{ride_acceptance::RideAcceptance, ride_offer::RideOffer, ride_request::RideRequest};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct RideCancel {
     pub ride_acceptance_transaction_hash: String,
 }
@@ -154,22 +154,3 @@ impl RideCancel {
         format!("ride_pay_{}", tx_hash).into_bytes()
     }
 }
-
-impl Encodable for RideCancel {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append(&self.ride_acceptance_transaction_hash);
-    }
-}
-
-impl Decodable for RideCancel {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-
-        Ok(RideCancel {
-            ride_acceptance_transaction_hash: rlp.val_at(0)?,
-        })
-    }
-}
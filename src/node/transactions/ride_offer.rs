@@ -1,15 +1,16 @@
 use super::{ride_request::RideRequest, tx_hash_pointer::decode_acceptance_pointer_value};
 use crate::node::balance_effect::StateUpdate;
 use crate::node::database::Database;
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct RideOffer {
     pub ride_request_transaction_hash: String,
     pub fare: u64,
     #[serde(default)]
+    #[rlp(with = "super::address::referrer_rlp")]
     pub referrer: Option<String>,
 }
 
@@ -182,32 +183,3 @@ impl RideOffer {
         format!("ride_offer_{}:ride_acceptance", ride_offer_tx_hash).into_bytes()
     }
 }
-
-impl Encodable for RideOffer {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        // Begin an RLP list with three elements: ride_request_transaction_hash, fare, referrer (empty if none)
-        stream.begin_list(3);
-        // Append the ride_request_transaction_hash field
-        stream.append(&self.ride_request_transaction_hash);
-        // Append the fare field
-        stream.append(&self.fare);
-        stream.append(&self.referrer.clone().unwrap_or_default());
-    }
-}
-
-impl Decodable for RideOffer {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        // Ensure the RLP data is a list of exactly three items
-        if !rlp.is_list() || rlp.item_count()? != 3 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-
-        Ok(RideOffer {
-            // Extract the ride_request_transaction_hash field from the first element
-            ride_request_transaction_hash: rlp.val_at(0)?,
-            // Extract the fare field from the second element
-            fare: rlp.val_at(1)?,
-            referrer: super::address::optional_canonical_referrer(rlp.val_at(2)?),
-        })
-    }
-}
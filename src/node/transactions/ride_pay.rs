@@ -1,4 +1,4 @@
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
@@ -33,7 +33,7 @@ fn split_fare(fare: u64, request_fee: u64, offer_fee: u64) -> (u64, u64, u64) {
     (request, offer, driver)
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct RidePay {
     pub ride_acceptance_transaction_hash: String,
     pub fare: u64,
@@ -220,27 +220,6 @@ impl RidePay {
     }
 }
 
-impl Encodable for RidePay {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(2);
-        stream.append(&self.ride_acceptance_transaction_hash);
-        stream.append(&self.fare);
-    }
-}
-
-impl Decodable for RidePay {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 2 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-
-        Ok(RidePay {
-            ride_acceptance_transaction_hash: rlp.val_at(0)?,
-            fare: rlp.val_at(1)?,
-        })
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::{referrer_fee_ceiling, split_fare};
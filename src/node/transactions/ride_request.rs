@@ -4,15 +4,16 @@ use crate::node::balance_effect::StateUpdate;
 use crate::node::coordinate::Coordinates;
 use crate::node::database::Database;
 
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct RideRequest {
     pub pickup_location: Coordinates,
     pub dropoff_location: Coordinates,
     pub fare: u64,
     #[serde(default)]
+    #[rlp(with = "super::address::referrer_rlp")]
     pub referrer: Option<String>,
 }
 
@@ -217,29 +218,3 @@ impl RideRequest {
         format!("ride_request_{}:cancelled", ride_request_tx_hash).into_bytes()
     }
 }
-
-impl Encodable for RideRequest {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        // Begin an RLP list with four elements: pickup_location, dropoff_location, fare, referrer (empty string if none)
-        stream.begin_list(4);
-        stream.append(&self.pickup_location);
-        stream.append(&self.dropoff_location);
-        stream.append(&self.fare);
-        stream.append(&self.referrer.clone().unwrap_or_default());
-    }
-}
-
-impl Decodable for RideRequest {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 4 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-        
-        Ok(RideRequest {
-            pickup_location: rlp.val_at(0)?,
-            dropoff_location: rlp.val_at(1)?,
-            fare: rlp.val_at(2)?,
-            referrer: super::address::optional_canonical_referrer(rlp.val_at(3)?),
-        })
-    }
-}
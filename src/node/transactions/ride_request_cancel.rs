@@ -1,11 +1,11 @@
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 
 use crate::node::balance_effect::StateUpdate;
 use crate::node::database::Database;
 
 use super::ride_request::RideRequest;
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, RlpCodec)]
 pub struct RideRequestCancel {
     pub ride_request_transaction_hash: String,
 }
@@ -71,22 +71,3 @@ impl RideRequestCancel {
         vec![StateUpdate::storage_only(cancel_key, cancel_value)]
     }
 }
-
-impl Encodable for RideRequestCancel {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(1);
-        stream.append(&self.ride_request_transaction_hash);
-    }
-}
-
-impl Decodable for RideRequestCancel {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 1 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-
-        Ok(RideRequestCancel {
-            ride_request_transaction_hash: rlp.val_at(0)?,
-        })
-    }
-}
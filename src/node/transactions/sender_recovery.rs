@@ -0,0 +1,129 @@
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, OnceLock};
+
+use crate::node::crypto;
+use crate::node::runtime;
+use crate::node::transactions::transaction::Transaction;
+
+/// Recovered senders kept; a few blocks' worth on top of a busy pool.
+const CACHE_CAPACITY: usize = 16_384;
+
+/// The signature a sender was recovered from, so a transaction reusing a cached hash
+/// with another signature is recovered again instead of inheriting the entry.
+type Signature = (String, String, i32);
+
+/// Senders recovered from transaction signatures, keyed by transaction hash. A
+/// transaction is checked when it enters the pool and again in the block that includes
+/// it, and recovery is the expensive part of both.
+fn cache() -> &'static Mutex<LruCache<String, (Signature, String)>> {
+    static CACHE: OnceLock<Mutex<LruCache<String, (Signature, String)>>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        Mutex::new(LruCache::new(
+            NonZeroUsize::new(CACHE_CAPACITY).expect("capacity is not zero"),
+        ))
+    })
+}
+
+fn signature(tx: &Transaction) -> Signature {
+    (
+        tx.signature_r.clone(),
+        tx.signature_s.clone(),
+        tx.signature_v,
+    )
+}
+
+fn cached(tx: &Transaction) -> Option<String> {
+    let mut cache = cache().lock().unwrap();
+    match cache.get(&tx.hash) {
+        Some((signature_of, sender)) if *signature_of == signature(tx) => Some(sender.clone()),
+        _ => None,
+    }
+}
+
+fn recover(tx: &Transaction) -> Result<String, String> {
+    crypto::suite().recover_address(
+        tx.hash.as_bytes(),
+        &tx.signature_r,
+        &tx.signature_s,
+        tx.signature_v,
+    )
+}
+
+/// Address whose key signed `tx`'s hash. Only successful recoveries are cached.
+pub fn recover_sender(tx: &Transaction) -> Result<String, String> {
+    if let Some(sender) = cached(tx) {
+        return Ok(sender);
+    }
+    let sender = recover(tx)?;
+    cache()
+        .lock()
+        .unwrap()
+        .put(tx.hash.clone(), (signature(tx), sender.clone()));
+    Ok(sender)
+}
+
+/// Recovers the senders of `transactions` missing from the cache on the CPU pool, so
+/// the checks that follow find them cached. Failures are left for those checks to
+/// report.
+pub fn prefetch_senders(transactions: &[Transaction]) {
+    let misses: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|tx| cached(tx).is_none())
+        .collect();
+    let recovered =
+        runtime::par_filter_map(&misses, |tx| recover(tx).ok().map(|sender| (*tx, sender)));
+    let mut cache = cache().lock().unwrap();
+    for (tx, sender) in recovered {
+        cache.put(tx.hash.clone(), (signature(tx), sender));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::signature_keys::SignatureKeys;
+    use crate::node::transactions::function_call::FunctionCall;
+    use crate::node::transactions::transfer::Transfer;
+
+    fn signed(keys: &SignatureKeys, nonce: u64) -> Transaction {
+        let mut tx = Transaction::new_transaction(
+            keys.address_key.clone(),
+            nonce,
+            FunctionCall::Transfer(Transfer {
+                to: "0xdeb4cfb63db134698e1879ea24904df074726cc0".to_string(),
+                value: 1,
+            }),
+        );
+        tx.sign(&keys.secret_key);
+        tx
+    }
+
+    #[test]
+    fn cached_senders_are_tied_to_their_signature() {
+        let keys = SignatureKeys::generate_new_keypair();
+        let other = SignatureKeys::generate_new_keypair();
+        let tx = signed(&keys, 1);
+        assert_eq!(recover_sender(&tx).unwrap(), keys.address_key);
+        assert!(cached(&tx).is_some());
+
+        let mut forged = tx.clone();
+        let (r, s, v) = SignatureKeys::sign(&other.secret_key, tx.hash.as_bytes());
+        (forged.signature_r, forged.signature_s, forged.signature_v) = (r, s, v);
+        assert!(cached(&forged).is_none());
+        assert_eq!(recover_sender(&forged).unwrap(), other.address_key);
+    }
+
+    #[test]
+    fn prefetching_recovers_every_sender() {
+        let keys: Vec<SignatureKeys> = (0..runtime::PARALLEL_THRESHOLD + 3)
+            .map(|_| SignatureKeys::generate_new_keypair())
+            .collect();
+        let transactions: Vec<Transaction> = keys.iter().map(|keys| signed(keys, 1)).collect();
+
+        prefetch_senders(&transactions);
+        for (tx, keys) in transactions.iter().zip(&keys) {
+            assert_eq!(cached(tx), Some(keys.address_key.clone()));
+        }
+    }
+}
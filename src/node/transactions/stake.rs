@@ -0,0 +1,203 @@
+use crate::node::account_state::AccountState;
+use crate::node::balance_effect::{BalanceEffectKind, StateUpdate};
+use crate::node::database::Database;
+use crate::node::transactions::address::canonical_account_address;
+
+use crate::node::rlp_encoding::RlpCodec;
+use serde::{Deserialize, Serialize};
+
+const VALIDATOR_STAKE_PREFIX: &str = "validator_stake_";
+
+/// Locks `value` of the sender's balance as validator stake. Stakes add up; once the
+/// total reaches `min_validator_stake` the sender is a candidate at the next epoch.
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
+pub struct Stake {
+    pub value: u64,
+}
+
+/// Returns `value` of the sender's stake to its balance.
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
+pub struct Unstake {
+    pub value: u64,
+}
+
+/// An account's entry in the validator registry.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ValidatorStake {
+    pub address: String,
+    pub stake: u64,
+}
+
+impl Stake {
+    pub fn verify_state(&self, from: &String, db: &Database) -> Result<(), String> {
+        if self.value == 0 {
+            return Err("Stake value must be greater than zero.".to_string());
+        }
+
+        let account_state = AccountState::get_current_state(from, db);
+        if account_state.balance < self.value {
+            return Err(format!(
+                "Error: Insufficient balance to stake. From: {} Required: {}, Available: {}",
+                from, self.value, account_state.balance
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn state_transaction(&self, from: &String, db: &Database) -> Vec<StateUpdate> {
+        let stake = get_stake(from, db).unwrap_or(0);
+        vec![
+            AccountState::apply_balance_change(
+                from,
+                -(self.value as i64),
+                BalanceEffectKind::Stake,
+                None,
+                db,
+            ),
+            StateUpdate::storage_only(
+                construct_validator_stake_key(from),
+                (stake + self.value).to_string().into_bytes(),
+            ),
+        ]
+    }
+}
+
+impl Unstake {
+    pub fn verify_state(&self, from: &String, db: &Database) -> Result<(), String> {
+        if self.value == 0 {
+            return Err("Unstake value must be greater than zero.".to_string());
+        }
+
+        let stake = get_stake(from, db)?;
+        if stake < self.value {
+            return Err(format!(
+                "Error: Insufficient stake. From: {} Required: {}, Staked: {}",
+                from, self.value, stake
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn state_transaction(&self, from: &String, db: &Database) -> Vec<StateUpdate> {
+        let stake = get_stake(from, db).unwrap_or(0);
+        vec![
+            StateUpdate::storage_only(
+                construct_validator_stake_key(from),
+                stake.saturating_sub(self.value).to_string().into_bytes(),
+            ),
+            AccountState::apply_balance_change(
+                from,
+                self.value as i64,
+                BalanceEffectKind::Unstake,
+                None,
+                db,
+            ),
+        ]
+    }
+}
+
+pub fn construct_validator_stake_key(address: &str) -> Vec<u8> {
+    format!(
+        "{}{}",
+        VALIDATOR_STAKE_PREFIX,
+        canonical_account_address(address)
+    )
+    .into_bytes()
+}
+
+fn parse_stake(value: Vec<u8>) -> Result<u64, String> {
+    String::from_utf8(value)
+        .ok()
+        .and_then(|stake| stake.parse().ok())
+        .ok_or_else(|| "Failed to decode validator stake".to_string())
+}
+
+/// What `address` has staked; 0 if nothing.
+pub fn get_stake(address: &str, db: &Database) -> Result<u64, String> {
+    match db.get("state", &construct_validator_stake_key(address)) {
+        Ok(Some(value)) => parse_stake(value),
+        Ok(None) => Ok(0),
+        Err(_) => Err("Database error occurred".to_string()),
+    }
+}
+
+/// Every account with stake, most staked first (ties by address).
+pub fn validator_registry(db: &Database) -> Result<Vec<ValidatorStake>, String> {
+    let entries = db.prefix_scan("state", VALIDATOR_STAKE_PREFIX.as_bytes())?;
+    let mut registry = Vec::new();
+    for (key, value) in entries {
+        let Some(address) = String::from_utf8(key)
+            .ok()
+            .and_then(|key| key.strip_prefix(VALIDATOR_STAKE_PREFIX).map(str::to_string))
+        else {
+            continue;
+        };
+        let stake = parse_stake(value)?;
+        if stake > 0 {
+            registry.push(ValidatorStake { address, stake });
+        }
+    }
+    sort_registry(&mut registry);
+    Ok(registry)
+}
+
+fn sort_registry(registry: &mut [ValidatorStake]) {
+    registry.sort_by(|a, b| {
+        b.stake
+            .cmp(&a.stake)
+            .then_with(|| a.address.cmp(&b.address))
+    });
+}
+
+/// The validator set the registry elects: the `max` most staked accounts holding at least
+/// `min_stake`. Empty if none does.
+pub fn elect_validators(db: &Database, min_stake: u64, max: usize) -> Result<Vec<String>, String> {
+    Ok(elect(validator_registry(db)?, min_stake, max))
+}
+
+fn elect(registry: Vec<ValidatorStake>, min_stake: u64, max: usize) -> Vec<String> {
+    registry
+        .into_iter()
+        .filter(|entry| entry.stake >= min_stake)
+        .take(max)
+        .map(|entry| entry.address)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(address: &str, stake: u64) -> ValidatorStake {
+        ValidatorStake {
+            address: address.to_string(),
+            stake,
+        }
+    }
+
+    #[test]
+    fn the_most_staked_accounts_above_the_minimum_are_elected() {
+        let mut registry = vec![
+            entry("0xc", 50),
+            entry("0xa", 200),
+            entry("0xd", 500),
+            entry("0xb", 200),
+        ];
+        sort_registry(&mut registry);
+        assert_eq!(
+            registry,
+            vec![
+                entry("0xd", 500),
+                entry("0xa", 200),
+                entry("0xb", 200),
+                entry("0xc", 50)
+            ]
+        );
+
+        assert_eq!(elect(registry.clone(), 100, 60), vec!["0xd", "0xa", "0xb"]);
+        assert_eq!(elect(registry.clone(), 100, 2), vec!["0xd", "0xa"]);
+        assert!(elect(registry, 1000, 60).is_empty());
+    }
+}
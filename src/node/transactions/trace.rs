@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+use crate::node::account_state::AccountState;
+use crate::node::balance_effect::{BalanceEffect, BalanceEffectKind, StateUpdate};
+use crate::node::blocks::block::Block;
+use crate::node::database::Database;
+
+/// A state write, with the value it replaces. Values are shown as JSON when they parse as
+/// JSON and as `0x`-prefixed hex otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageWrite {
+    pub key: String,
+    pub before: Option<serde_json::Value>,
+    pub after: serde_json::Value,
+}
+
+impl StorageWrite {
+    pub fn new(key: &[u8], before: Option<&[u8]>, after: &[u8]) -> Self {
+        Self {
+            key: String::from_utf8_lossy(key).into_owned(),
+            before: before.map(display_value),
+            after: display_value(after),
+        }
+    }
+}
+
+/// One update produced by the executor, in order.
+#[derive(Debug, Serialize)]
+pub struct TraceStep {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write: Option<StorageWrite>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<BalanceEffect>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionTrace {
+    pub transaction_hash: String,
+    pub transaction_index: usize,
+    pub from: String,
+    pub function_call_type: String,
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockTrace {
+    pub block_index: usize,
+    pub block_hash: String,
+    pub transactions: Vec<TransactionTrace>,
+    /// The author's block reward, applied after the transactions.
+    pub reward: Vec<TraceStep>,
+}
+
+impl BlockTrace {
+    /// Executes `block` against `db`, which must hold the state before it, without writing
+    /// anything. As in `Block::add_block_to_chain`, every transaction sees the state before
+    /// the block.
+    pub fn execute(
+        db: &Database,
+        block: &Block,
+        block_reward_amount: u64,
+        ride_request_referrer_fee_percent: u8,
+        ride_offer_referrer_fee_percent: u8,
+    ) -> Result<Self, String> {
+        let mut transactions = Vec::with_capacity(block.transactions.len());
+        for (tx_index, tx) in block.transactions.iter().enumerate() {
+            let updates = tx.state_transaction(
+                db,
+                ride_request_referrer_fee_percent,
+                ride_offer_referrer_fee_percent,
+            );
+            transactions.push(TransactionTrace {
+                transaction_hash: tx.hash.clone(),
+                transaction_index: tx_index,
+                from: tx.from.clone(),
+                function_call_type: tx.function_call_type().to_string(),
+                steps: trace_steps(db, updates)?,
+            });
+        }
+
+        let mut reward = Vec::new();
+        if block.index > 0 && block_reward_amount > 0 {
+            let update = AccountState::apply_balance_change(
+                &block.author,
+                block_reward_amount as i64,
+                BalanceEffectKind::BlockReward,
+                None,
+                db,
+            );
+            reward = trace_steps(db, vec![update])?;
+        }
+
+        Ok(BlockTrace {
+            block_index: block.index,
+            block_hash: block.hash.clone(),
+            transactions,
+            reward,
+        })
+    }
+}
+
+fn trace_steps(db: &Database, updates: Vec<StateUpdate>) -> Result<Vec<TraceStep>, String> {
+    let mut steps = Vec::with_capacity(updates.len());
+    for update in updates {
+        let write = match update.storage {
+            Some((key, value)) => Some(StorageWrite::new(
+                &key,
+                db.get("state", &key)?.as_deref(),
+                &value,
+            )),
+            None => None,
+        };
+        steps.push(TraceStep {
+            write,
+            effect: update.effect,
+        });
+    }
+    Ok(steps)
+}
+
+fn display_value(bytes: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| serde_json::Value::String(format!("0x{}", hex::encode(bytes))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_shown_as_json_or_hex() {
+        assert_eq!(
+            display_value(br#"{"balance":5}"#),
+            serde_json::json!({ "balance": 5 })
+        );
+        assert_eq!(display_value(&[0xff, 0x01]), serde_json::json!("0xff01"));
+    }
+}
@@ -1,16 +1,15 @@
 use crate::node::{
-    account_state::AccountState,
-    balance_effect::StateUpdate,
-    database::Database,
-    signature_keys::{self, SignatureKeys},
+    account_state::AccountState, balance_effect::StateUpdate, crypto, database::Database, runtime,
+    signature_keys,
 };
 
 use rlp::RlpStream;
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Keccak256};
 use std::vec;
 
-use super::{function_call::FunctionCall, passenger_concurrent, transfer::Transfer};
+use super::{
+    function_call::FunctionCall, passenger_concurrent, sender_recovery, transfer::Transfer,
+};
 
 const FROM_GENESIS: &str = "0xGENESIS";
 
@@ -59,10 +58,11 @@ impl Transaction {
 
     /// Canonical transaction hash. MUST stay byte-for-byte in agreement with the client
     /// hashing in clutch-hub-sdk-js (`signTransaction`) and clutch-hub-api's faucet:
-    /// Keccak-256 over RLP `[from (no 0x prefix), nonce, data]`. `from` is stripped of any
-    /// `0x` because the SDK RLP-encodes it without the prefix; the node's decoder re-adds the
-    /// prefix, so it must be removed again here for the hash to match.
-    fn calculate_hash(&self) -> String {
+    /// Keccak-256 over RLP `[from (no 0x prefix), nonce, data]`, or the hash the chain's
+    /// `crypto::suite` selects instead. `from` is stripped of any `0x` because the SDK
+    /// RLP-encodes it without the prefix; the node's decoder re-adds the prefix, so it must
+    /// be removed again here for the hash to match.
+    pub fn calculate_hash(&self) -> String {
         let from_no_prefix = self.from.strip_prefix("0x").unwrap_or(&self.from);
         let mut stream = RlpStream::new();
         stream.begin_list(3);
@@ -71,9 +71,7 @@ impl Transaction {
         stream.append(&self.data);
         let rlp_bytes = stream.out();
 
-        let mut hasher = Keccak256::new();
-        hasher.update(&rlp_bytes);
-        format!("0x{}", hex::encode(hasher.finalize()))
+        format!("0x{}", hex::encode(crypto::suite().hash(&rlp_bytes)))
     }
 
     /// Rejects a transaction whose `hash` field was not honestly derived from
@@ -105,20 +103,13 @@ impl Transaction {
     }
 
     fn verify_signature(&self) -> Result<(), String> {
-        let from_address = &self.from;
-        let data = self.hash.as_bytes();
-        let r = &self.signature_r;
-        let s = &self.signature_s;
-        let v = self.signature_v;
-
-        match SignatureKeys::verify(from_address, data, r, s, v) {
-            Ok(true) => Ok(()),
-            Ok(false) => Err(
+        if sender_recovery::recover_sender(self)? != self.from {
+            return Err(
                 "Verification failed: transaction signature does not match the from address"
                     .to_string(),
-            ),
-            Err(e) => Err(e),
+            );
         }
+        Ok(())
     }
 
     pub fn validate_transactions(
@@ -146,8 +137,16 @@ impl Transaction {
             ));
         }
 
+        // The state-free checks of the whole block run on the CPU pool first.
+        let bad_hashes = runtime::par_filter_map(transactions, |tx| tx.verify_hash().err());
+        if let Some(e) = bad_hashes.into_iter().next() {
+            return Err(e);
+        }
+        sender_recovery::prefetch_senders(transactions);
         for tx in transactions.iter() {
-            tx.validate_transaction(&db)?;
+            tx.verify_signature()?;
+            tx.verify_nonce(&db)?;
+            tx.verify_state(&db)?;
         }
 
         Ok(())
@@ -174,6 +173,20 @@ impl Transaction {
         Ok(())
     }
 
+    /// Only the checks that don't depend on state: the hash and the signature. The pool
+    /// uses these for a queued transaction, whose state check has to wait for the
+    /// transactions before it.
+    pub fn validate_signed(&self) -> Result<(), String> {
+        self.verify_hash()?;
+        self.verify_signature()
+    }
+
+    /// Receipt status of executing the transaction on `db`'s state: 1 when its nonce and
+    /// state checks pass, 0 when they fail.
+    pub fn execution_status(&self, db: &Database) -> u8 {
+        u8::from(self.verify_nonce(db).is_ok() && self.verify_state(db).is_ok())
+    }
+
     fn verify_nonce(&self, db: &Database) -> Result<bool, String> {
         match AccountState::get_current_nonce(&self.from, db) {
             Ok(last_nonce) => {
@@ -215,6 +228,8 @@ impl Transaction {
             FunctionCall::RideRequestCancel(ride_request_cancel) => {
                 ride_request_cancel.verify_state(&self.from, db)
             }
+            FunctionCall::Stake(stake) => stake.verify_state(&self.from, db),
+            FunctionCall::Unstake(unstake) => unstake.verify_state(&self.from, db),
         }
     }
 
@@ -227,6 +242,8 @@ impl Transaction {
             FunctionCall::RidePay(_) => "RidePay",
             FunctionCall::RideCancel(_) => "RideCancel",
             FunctionCall::RideRequestCancel(_) => "RideRequestCancel",
+            FunctionCall::Stake(_) => "Stake",
+            FunctionCall::Unstake(_) => "Unstake",
         }
     }
 
@@ -258,6 +275,8 @@ impl Transaction {
             FunctionCall::RideRequestCancel(ride_request_cancel) => {
                 ride_request_cancel.state_transaction(&self.hash, db)
             }
+            FunctionCall::Stake(stake) => stake.state_transaction(&self.from, db),
+            FunctionCall::Unstake(unstake) => unstake.state_transaction(&self.from, db),
         };
 
         match AccountState::increase_account_nonce_key(&self.from, db) {
@@ -274,6 +293,7 @@ impl Transaction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sha3::{Digest, Keccak256};
 
     fn tf(from: &str, nonce: u64, to: &str) -> Transaction {
         Transaction::new_transaction(
@@ -1,37 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::node::account_state::AccountState;
 use crate::node::database::Database;
+use crate::node::transactions::address::canonical_account_address;
 use crate::node::transactions::transaction::Transaction;
 
+/// How far past a sender's next nonce the pool still queues a transaction.
+pub const MAX_NONCE_GAP: u64 = 64;
+
+/// How many queued transactions the pool holds per sender. Queued ones aren't checked
+/// against state, so without a cap one funded key could fill `MAX_NONCE_GAP` slots.
+pub const MAX_QUEUED_PER_SENDER: usize = 16;
+
+/// When a transaction entered the pool: unix seconds, and our head at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolAdmission {
+    pub timestamp: u64,
+    pub block_index: usize,
+}
+
+/// Why a transaction left the pool without a block including it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    /// It waited `max_age_secs`.
+    MaxAge,
+    /// It waited `max_blocks` blocks.
+    MaxBlocks,
+}
+
+/// How long a remote transaction may wait in the pool; zero disables either limit. Local
+/// transactions never expire, they are re-broadcast instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolExpiry {
+    pub max_age_secs: u64,
+    pub max_blocks: usize,
+}
+
+impl PoolExpiry {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_secs > 0 || self.max_blocks > 0
+    }
+
+    /// Whether a transaction admitted at `admission` has expired at `now` with our head
+    /// at `head_index`.
+    pub fn check(
+        &self,
+        admission: &PoolAdmission,
+        now: u64,
+        head_index: usize,
+    ) -> Option<EvictionReason> {
+        if self.max_age_secs > 0 && now.saturating_sub(admission.timestamp) >= self.max_age_secs {
+            Some(EvictionReason::MaxAge)
+        } else if self.max_blocks > 0
+            && head_index.saturating_sub(admission.block_index) >= self.max_blocks
+        {
+            Some(EvictionReason::MaxBlocks)
+        } else {
+            None
+        }
+    }
+}
+
 pub struct TransactionPool {}
 
 impl TransactionPool {
     pub fn add_transaction(db: &Database, transaction: &Transaction) -> Result<(), String> {
-        transaction.validate_transaction(&db)?;
+        Self::validate(db, transaction)?;
         let key = Self::construct_tx_pool_key(&transaction.hash);
         let value = serde_json::to_string(&transaction).unwrap().into_bytes();
 
         db.put("tx_pool", &key, &value)
     }
 
-    #[allow(dead_code)]
+    /// Removes a transaction with its local and admission markers.
     pub fn remove_transaction(db: &Database, tx_hash: &str) -> Result<(), String> {
-        let key = Self::construct_tx_pool_key(tx_hash);
-        match db.delete("tx_pool", &key) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to remove transaction: {}", e)),
+        for key in [
+            Self::construct_tx_pool_key(tx_hash),
+            Self::construct_local_key(tx_hash),
+            Self::construct_admission_key(tx_hash),
+        ] {
+            db.delete("tx_pool", &key)
+                .map_err(|e| format!("Failed to remove transaction: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Admission check. A transaction for its sender's next nonce must be executable now;
+    /// one up to `MAX_NONCE_GAP` further ahead is queued with only its hash and signature
+    /// checked, and becomes pending once the nonces before it are included. Only a sender
+    /// holding a balance may queue, at most `MAX_QUEUED_PER_SENDER` transactions, so fresh
+    /// keys can't fill the pool for free.
+    pub fn validate(db: &Database, transaction: &Transaction) -> Result<(), String> {
+        let next = AccountState::get_current_nonce(&transaction.from, db)? + 1;
+        if transaction.nonce <= next {
+            return transaction.validate_transaction(db);
+        }
+        if transaction.nonce - next > MAX_NONCE_GAP {
+            return Err(format!(
+                "Nonce {} of transaction from '{}' is more than {} ahead of the next nonce {}",
+                transaction.nonce, transaction.from, MAX_NONCE_GAP, next
+            ));
+        }
+        transaction.validate_signed()?;
+        if AccountState::get_current_state(&transaction.from, db).balance == 0 {
+            return Err(format!(
+                "Sender '{}' holds no balance, so nonce {} can't be queued ahead of nonce {}",
+                transaction.from, transaction.nonce, next
+            ));
         }
+        let sender = canonical_account_address(&transaction.from);
+        let queued = Self::get_transactions(db)?
+            .iter()
+            .filter(|tx| tx.nonce > next && tx.hash != transaction.hash)
+            .filter(|tx| canonical_account_address(&tx.from) == sender)
+            .count();
+        if queued >= MAX_QUEUED_PER_SENDER {
+            return Err(format!(
+                "Sender '{}' already has {} queued transactions, the most the pool holds",
+                transaction.from, queued
+            ));
+        }
+        Ok(())
     }
 
     pub fn construct_tx_pool_key(tx_hash: &str) -> Vec<u8> {
         format!("tx_pool_{}", tx_hash).into_bytes()
     }
 
+    pub fn construct_local_key(tx_hash: &str) -> Vec<u8> {
+        format!("local_tx_{}", tx_hash).into_bytes()
+    }
+
+    pub fn construct_admission_key(tx_hash: &str) -> Vec<u8> {
+        format!("pool_since_{}", tx_hash).into_bytes()
+    }
+
+    /// Records when a transaction entered the pool, for `PoolExpiry`. Deleted with the pool
+    /// entry.
+    pub fn mark_admitted(
+        db: &Database,
+        tx_hash: &str,
+        admission: &PoolAdmission,
+    ) -> Result<(), String> {
+        let value = serde_json::to_string(admission).unwrap().into_bytes();
+        db.put("tx_pool", &Self::construct_admission_key(tx_hash), &value)
+    }
+
+    pub fn get_admission(db: &Database, tx_hash: &str) -> Result<Option<PoolAdmission>, String> {
+        match db.get("tx_pool", &Self::construct_admission_key(tx_hash))? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to decode pool admission: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Marks a pool transaction as submitted through this node's RPC. The marker is
+    /// deleted with the pool entry when a block includes the transaction, and keeps the
+    /// transaction from expiring.
+    pub fn mark_local(db: &Database, tx_hash: &str) -> Result<(), String> {
+        db.put("tx_pool", &Self::construct_local_key(tx_hash), b"1")
+    }
+
+    /// Hashes of the local transactions still waiting in the pool.
+    pub fn get_local_hashes(db: &Database) -> Result<HashSet<String>, String> {
+        let entries = db
+            .get_keys_values_by_cf_name("tx_pool")
+            .map_err(|e| format!("Failed to retrieve local transactions: {}", e))?;
+        Ok(entries
+            .iter()
+            .filter_map(|(key, _)| key.strip_prefix(b"local_tx_"))
+            .map(|hash| String::from_utf8_lossy(hash).into_owned())
+            .collect())
+    }
+
+    pub fn get_local_transactions(db: &Database) -> Result<Vec<Transaction>, String> {
+        let local = Self::get_local_hashes(db)?;
+        let mut transactions = Self::get_transactions(db)?;
+        transactions.retain(|tx| local.contains(&tx.hash));
+        Ok(transactions)
+    }
+
+    pub fn get_transaction(db: &Database, tx_hash: &str) -> Result<Option<Transaction>, String> {
+        match db.get("tx_pool", &Self::construct_tx_pool_key(tx_hash))? {
+            Some(value) => serde_json::from_slice::<Transaction>(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to deserialize transaction: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Transactions in the pool, counted from the keys without decoding them.
+    pub fn count(db: &Database) -> Result<usize, String> {
+        Ok(db
+            .get_keys_by_cf_name("tx_pool")?
+            .iter()
+            .filter(|key| key.starts_with(b"tx_pool_"))
+            .count())
+    }
+
     pub fn get_transactions(db: &Database) -> Result<Vec<Transaction>, String> {
         match db.get_keys_values_by_cf_name("tx_pool") {
             Ok(entries) => {
                 let mut transactions = Vec::new();
 
-                for (_key, value) in entries {
-                    match serde_json::from_slice::<Transaction>(&value) {
+                // The column family also holds the `local_tx_*` markers.
+                for (_key, value) in entries
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(b"tx_pool_"))
+                {
+                    match serde_json::from_slice::<Transaction>(value) {
                         Ok(transaction) => {
                             transactions.push(transaction);
                         }
@@ -47,3 +227,153 @@ impl TransactionPool {
         }
     }
 }
+
+/// Pool transactions by sender, each sender's sorted by nonce then hash. Relative to the
+/// sender's next nonce on chain, a transaction is `pending` (includable in the next block),
+/// `queued` (waiting on a lower nonce) or `stale` (its nonce was spent, so it will never be
+/// included; these accumulate when several transactions share a nonce).
+#[derive(Debug, Serialize)]
+pub struct PoolContent<T> {
+    pub pending: BTreeMap<String, Vec<T>>,
+    pub queued: BTreeMap<String, Vec<T>>,
+    pub stale: BTreeMap<String, Vec<T>>,
+}
+
+/// Transaction counts of a `PoolContent`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PoolStatus {
+    pub pending: usize,
+    pub queued: usize,
+    pub stale: usize,
+}
+
+impl PoolContent<Transaction> {
+    /// Groups `transactions` against each sender's current nonce, looked up once per sender.
+    pub fn classify(
+        mut transactions: Vec<Transaction>,
+        mut current_nonce: impl FnMut(&String) -> Result<u64, String>,
+    ) -> Result<Self, String> {
+        transactions.sort_by(|a, b| a.nonce.cmp(&b.nonce).then_with(|| a.hash.cmp(&b.hash)));
+        let mut content = PoolContent {
+            pending: BTreeMap::new(),
+            queued: BTreeMap::new(),
+            stale: BTreeMap::new(),
+        };
+        let mut nonces: BTreeMap<String, u64> = BTreeMap::new();
+        for tx in transactions {
+            let current = match nonces.get(&tx.from) {
+                Some(nonce) => *nonce,
+                None => {
+                    let nonce = current_nonce(&tx.from)?;
+                    nonces.insert(tx.from.clone(), nonce);
+                    nonce
+                }
+            };
+            let bucket = if tx.nonce <= current {
+                &mut content.stale
+            } else if tx.nonce == current + 1 {
+                &mut content.pending
+            } else {
+                &mut content.queued
+            };
+            bucket.entry(tx.from.clone()).or_default().push(tx);
+        }
+        Ok(content)
+    }
+}
+
+impl<T> PoolContent<T> {
+    pub fn status(&self) -> PoolStatus {
+        let count = |bucket: &BTreeMap<String, Vec<T>>| bucket.values().map(Vec::len).sum();
+        PoolStatus {
+            pending: count(&self.pending),
+            queued: count(&self.queued),
+            stale: count(&self.stale),
+        }
+    }
+
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> PoolContent<U> {
+        let mut map_bucket = |bucket: BTreeMap<String, Vec<T>>| -> BTreeMap<String, Vec<U>> {
+            bucket
+                .into_iter()
+                .map(|(sender, txs)| (sender, txs.into_iter().map(&mut f).collect()))
+                .collect()
+        };
+        PoolContent {
+            pending: map_bucket(self.pending),
+            queued: map_bucket(self.queued),
+            stale: map_bucket(self.stale),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::transactions::function_call::FunctionCall;
+    use crate::node::transactions::transfer::Transfer;
+
+    fn tx(from: &str, nonce: u64, to: &str) -> Transaction {
+        Transaction::new_transaction(
+            from.to_string(),
+            nonce,
+            FunctionCall::Transfer(Transfer {
+                to: to.to_string(),
+                value: 1,
+            }),
+        )
+    }
+
+    #[test]
+    fn transactions_are_grouped_by_their_senders_next_nonce() {
+        let transactions = vec![
+            tx("0xA", 3, "0xB"),
+            tx("0xA", 5, "0xB"),
+            tx("0xA", 2, "0xB"),
+            tx("0xB", 1, "0xA"),
+        ];
+        let mut lookups = 0;
+        let content = PoolContent::classify(transactions, |sender| {
+            lookups += 1;
+            Ok(if sender == "0xA" { 2 } else { 0 })
+        })
+        .unwrap();
+        assert_eq!(lookups, 2);
+
+        let nonces = |bucket: &BTreeMap<String, Vec<Transaction>>, sender: &str| {
+            bucket[sender].iter().map(|tx| tx.nonce).collect::<Vec<_>>()
+        };
+        assert_eq!(nonces(&content.pending, "0xA"), [3]);
+        assert_eq!(nonces(&content.pending, "0xB"), [1]);
+        assert_eq!(nonces(&content.queued, "0xA"), [5]);
+        assert_eq!(nonces(&content.stale, "0xA"), [2]);
+
+        let status = content.map(|tx| tx.nonce).status();
+        assert_eq!((status.pending, status.queued, status.stale), (2, 1, 1));
+    }
+
+    #[test]
+    fn transactions_expire_by_age_or_by_blocks() {
+        let admission = PoolAdmission {
+            timestamp: 1_000,
+            block_index: 10,
+        };
+        let expiry = PoolExpiry {
+            max_age_secs: 60,
+            max_blocks: 5,
+        };
+        assert_eq!(expiry.check(&admission, 1_059, 14), None);
+        assert_eq!(
+            expiry.check(&admission, 1_060, 14),
+            Some(EvictionReason::MaxAge)
+        );
+        assert_eq!(
+            expiry.check(&admission, 1_059, 15),
+            Some(EvictionReason::MaxBlocks)
+        );
+
+        let never = PoolExpiry::default();
+        assert!(!never.is_enabled());
+        assert_eq!(never.check(&admission, u64::MAX, usize::MAX), None);
+    }
+}
@@ -2,10 +2,10 @@ use crate::node::account_state::AccountState;
 use crate::node::balance_effect::{BalanceEffectKind, StateUpdate};
 use crate::node::database::Database;
 
-use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use crate::node::rlp_encoding::RlpCodec;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, RlpCodec)]
 pub struct Transfer {
     pub to: String,
     pub value: u64,
@@ -47,24 +47,3 @@ impl Transfer {
         ]
     }
 }
-
-impl Encodable for Transfer {
-    fn rlp_append(&self, stream: &mut RlpStream) {
-        stream.begin_list(2);
-        stream.append(&self.to);
-        stream.append(&self.value);
-    }
-}
-
-impl Decodable for Transfer {
-    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        if !rlp.is_list() || rlp.item_count()? != 2 {
-            return Err(DecoderError::RlpIncorrectListLen);
-        }
-
-        Ok(Transfer {
-            to: rlp.val_at(0)?,
-            value: rlp.val_at(1)?,
-        })
-    }
-}
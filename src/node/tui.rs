@@ -0,0 +1,324 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::RwLock;
+use tracing::error;
+
+use crate::node::blockchain::Blockchain;
+use crate::node::p2p_server::peer_table::PeerSummary;
+use crate::node::p2p_server::{P2PServer, P2PServerCommand};
+use crate::node::time_utils::get_current_timespan;
+use crate::node::transactions::transaction_pool::PoolStatus;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Window the import rate is averaged over.
+const IMPORT_RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Columns a frame is cut to, so long peer lists don't wrap on narrow terminals.
+const SCREEN_WIDTH: usize = 80;
+const PROGRESS_BAR_WIDTH: usize = 40;
+const MAX_PEER_ROWS: usize = 20;
+
+/// What one frame of the dashboard shows; the status page serves it too.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DashboardState {
+    pub chain_id: String,
+    pub head_index: usize,
+    pub head_hash: String,
+    /// Seconds since the head block's timestamp.
+    pub head_age_secs: u64,
+    pub peers: Vec<PeerSummary>,
+    pub pool: PoolStatus,
+    /// Sampled across frames by the terminal dashboard only.
+    #[serde(skip)]
+    pub blocks_per_sec: f64,
+}
+
+impl DashboardState {
+    /// Reads the head and the pool, and asks the P2P server for its peers. Whatever
+    /// can't be read is logged and left empty.
+    pub async fn collect(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        command_tx_p2p: &Sender<P2PServerCommand>,
+        chain_id: &str,
+    ) -> Self {
+        let mut state = DashboardState {
+            chain_id: chain_id.to_string(),
+            ..DashboardState::default()
+        };
+        {
+            let blockchain = blockchain.read().await;
+            match blockchain.get_latest_block() {
+                Ok(Some(head)) => {
+                    state.head_index = head.index;
+                    state.head_age_secs = get_current_timespan().saturating_sub(head.timestamp);
+                    state.head_hash = head.hash;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to read latest block: {}", e),
+            }
+            match blockchain.get_pool_content() {
+                Ok(content) => state.pool = content.status(),
+                Err(e) => error!("Failed to read transaction pool: {}", e),
+            }
+        }
+        match P2PServer::get_peers_command(command_tx_p2p.clone()).await {
+            Ok(snapshot) => state.peers = snapshot.peers,
+            Err(e) => error!("Failed to get peers: {}", e),
+        }
+        state
+    }
+
+    /// The highest head any peer has advertised, which sync is heading for.
+    pub fn best_peer_head(&self) -> Option<usize> {
+        self.peers
+            .iter()
+            .filter_map(|peer| peer.latest_block_index)
+            .max()
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        let sync = match self.best_peer_head() {
+            Some(target) if target > self.head_index => format!(
+                "{} {:>3}%  {} / {}",
+                progress_bar(self.head_index, target, PROGRESS_BAR_WIDTH),
+                self.head_index * 100 / target,
+                self.head_index,
+                target
+            ),
+            Some(_) => "in sync with peers".to_string(),
+            None => "no peer heads yet".to_string(),
+        };
+        let mut lines = vec![
+            format!("Clutch node - chain {}", self.chain_id),
+            String::new(),
+            format!(
+                "Head     #{}  {}  {}s ago",
+                self.head_index,
+                abbreviate(&self.head_hash),
+                self.head_age_secs
+            ),
+            format!("Sync     {}", sync),
+            format!("Import   {:.1} blocks/s", self.blocks_per_sec),
+            format!(
+                "Mempool  {} pending, {} queued, {} stale",
+                self.pool.pending, self.pool.queued, self.pool.stale
+            ),
+            format!("Peers    {} connected", self.peers.len()),
+            String::new(),
+            format!(
+                "{:<20}  {:>10}  {:>10}  {:>8}",
+                "PEER", "HEAD", "LATENCY", "FORMAT"
+            ),
+        ];
+
+        let mut peers: Vec<&PeerSummary> = self.peers.iter().collect();
+        peers.sort_by_key(|peer| {
+            (
+                std::cmp::Reverse(peer.latest_block_index),
+                peer.latency_ms.unwrap_or(u64::MAX),
+            )
+        });
+        for peer in peers.iter().take(MAX_PEER_ROWS) {
+            let head = peer
+                .latest_block_index
+                .map_or("-".to_string(), |index| index.to_string());
+            let latency = peer
+                .latency_ms
+                .map_or("-".to_string(), |ms| format!("{} ms", ms));
+            lines.push(format!(
+                "{:<20}  {:>10}  {:>10}  {:>8}",
+                abbreviate(&peer.peer_id),
+                head,
+                latency,
+                peer.wire_format
+            ));
+        }
+        if peers.len() > MAX_PEER_ROWS {
+            lines.push(format!("... {} more", peers.len() - MAX_PEER_ROWS));
+        }
+        lines
+            .into_iter()
+            .map(|line| line.chars().take(SCREEN_WIDTH).collect())
+            .collect()
+    }
+}
+
+/// `[####----]` filled in proportion to `done` of `total`.
+pub fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let filled = if total == 0 {
+        width
+    } else {
+        done.min(total) * width / total
+    };
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// The first 8 and last 6 characters of a hash or peer id.
+fn abbreviate(id: &str) -> String {
+    let chars: Vec<char> = id.chars().collect();
+    if chars.len() <= 17 {
+        return id.to_string();
+    }
+    let head: String = chars[..8].iter().collect();
+    let tail: String = chars[chars.len() - 6..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+/// Blocks imported per second over the last `IMPORT_RATE_WINDOW`, from head samples.
+#[derive(Debug, Default)]
+pub struct ImportRate {
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl ImportRate {
+    pub fn record(&mut self, head_index: usize, now: Instant) -> f64 {
+        self.samples.push_back((now, head_index));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > IMPORT_RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        let Some((first_at, first_index)) = self.samples.front() else {
+            return 0.0;
+        };
+        let secs = now.duration_since(*first_at).as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        head_index.saturating_sub(*first_index) as f64 / secs
+    }
+}
+
+/// The terminal's alternate screen with the cursor hidden, restored when dropped.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Self {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[?1049h\x1b[?25l");
+        let _ = stdout.flush();
+        Screen
+    }
+
+    /// Redraws in place rather than clearing first, which flickers.
+    fn draw(&self, lines: &[String]) {
+        let mut frame = String::from("\x1b[H");
+        for line in lines {
+            frame.push_str(line);
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame.push_str("\x1b[J");
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(frame.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+    }
+}
+
+/// Live dashboard for operators running the node in a terminal (`--tui`). Drawn with
+/// plain ANSI escapes every second; logs go to a file meanwhile so they don't scroll over
+/// it. Ctrl+C still stops the node, and the screen is restored as the task is dropped.
+pub struct Dashboard;
+
+impl Dashboard {
+    pub fn start(
+        blockchain: Arc<RwLock<Blockchain>>,
+        command_tx_p2p: Sender<P2PServerCommand>,
+        chain_id: String,
+    ) {
+        tokio::spawn(async move {
+            let screen = Screen::enter();
+            let mut import_rate = ImportRate::default();
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let mut state =
+                    DashboardState::collect(&blockchain, &command_tx_p2p, &chain_id).await;
+                state.blocks_per_sec = import_rate.record(state.head_index, Instant::now());
+                screen.draw(&state.render());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(id: &str, head: Option<usize>, latency_ms: Option<u64>) -> PeerSummary {
+        PeerSummary {
+            peer_id: id.to_string(),
+            connected_secs: 0,
+            handshaked: true,
+            latest_block_index: head,
+            latest_block_hash: None,
+            head_weight: None,
+            head_updated_secs: None,
+            latency_ms,
+            blocks_per_sec: None,
+            wire_format: "rlp",
+            capabilities: Vec::new(),
+            record_addresses: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn frames_show_sync_progress_towards_the_best_peer() {
+        let state = DashboardState {
+            chain_id: "clutch-dev".to_string(),
+            head_index: 50,
+            head_hash: "0x0123456789abcdef0123".to_string(),
+            peers: vec![
+                peer("slow", Some(80), Some(90)),
+                peer("best", Some(200), None),
+                peer("new", None, Some(5)),
+            ],
+            blocks_per_sec: 2.5,
+            ..DashboardState::default()
+        };
+        let lines = state.render();
+
+        assert!(lines[2].starts_with("Head     #50  0x012345...ef0123  "));
+        assert!(lines[3].ends_with(" 25%  50 / 200"), "{}", lines[3]);
+        assert_eq!(lines[4], "Import   2.5 blocks/s");
+        let rows: Vec<&str> = lines[9..]
+            .iter()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(rows, ["best", "slow", "new"]);
+        assert!(lines
+            .iter()
+            .all(|line| line.chars().count() <= SCREEN_WIDTH));
+
+        let synced = DashboardState {
+            head_index: 200,
+            ..state
+        };
+        assert_eq!(synced.render()[3], "Sync     in sync with peers");
+    }
+
+    #[test]
+    fn progress_and_import_rate() {
+        assert_eq!(progress_bar(1, 4, 8), "[##------]");
+        assert_eq!(progress_bar(9, 4, 4), "[####]");
+        assert_eq!(progress_bar(0, 0, 2), "[##]");
+
+        let now = Instant::now();
+        let mut rate = ImportRate::default();
+        assert_eq!(rate.record(10, now), 0.0);
+        assert_eq!(rate.record(30, now + Duration::from_secs(5)), 4.0);
+        assert_eq!(rate.record(30, now + Duration::from_secs(20)), 0.0);
+    }
+}
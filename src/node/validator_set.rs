@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use crate::node::aura::Aura;
+use crate::node::blocks::block::Block;
+use crate::node::database::Database;
+use crate::node::transactions::stake::{elect_validators, ValidatorStake};
+
+/// Aura's step is `60 / authorities` seconds, so a larger set would make it zero.
+pub const MAX_AUTHORITIES: usize = 60;
+
+/// One `[[validator_sets]]` entry of the chain config: the authorities that author blocks
+/// from the first block of `epoch` on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorSetChange {
+    pub epoch: u64,
+    pub authorities: Vec<String>,
+}
+
+/// The validator-set changes a chain schedules, in epoch order. Like the hardfork
+/// schedule they are part of the chain spec: every node must list the same changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<ValidatorSetChange>")]
+pub struct ValidatorSetChanges {
+    changes: Vec<ValidatorSetChange>,
+}
+
+impl TryFrom<Vec<ValidatorSetChange>> for ValidatorSetChanges {
+    type Error = String;
+
+    fn try_from(changes: Vec<ValidatorSetChange>) -> Result<Self, String> {
+        let mut previous = 0;
+        for change in &changes {
+            if change.epoch == 0 {
+                return Err("Epoch 0 is authored by `authorities`".to_string());
+            }
+            if change.epoch <= previous {
+                return Err(format!(
+                    "Validator set for epoch {} is listed after epoch {}",
+                    change.epoch, previous
+                ));
+            }
+            check_authorities(&change.authorities)
+                .map_err(|e| format!("Validator set for epoch {}: {}", change.epoch, e))?;
+            previous = change.epoch;
+        }
+        Ok(ValidatorSetChanges { changes })
+    }
+}
+
+impl ValidatorSetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+fn check_authorities(authorities: &[String]) -> Result<(), String> {
+    if authorities.is_empty() {
+        return Err("no authorities".to_string());
+    }
+    if authorities.len() > MAX_AUTHORITIES {
+        return Err(format!(
+            "{} authorities, more than {}",
+            authorities.len(),
+            MAX_AUTHORITIES
+        ));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = authorities.iter().find(|a| !seen.insert(a.as_str())) {
+        return Err(format!("{} is listed twice", duplicate));
+    }
+    Ok(())
+}
+
+/// Which authorities author which blocks. The chain is cut into epochs of `epoch_length`
+/// blocks; the genesis `authorities` author until the first scheduled change, and each
+/// change's set from the first block of its epoch. The last block of every epoch commits
+/// to the next epoch's set in its `next_authorities`, so a node or light client reading
+/// headers learns each set from a block signed by the set before it.
+///
+/// With a `min_stake` the sets come from the validator registry instead: the last block
+/// of an epoch commits to the accounts the registry elects at that point, and the schedule
+/// learns each set as those blocks are applied.
+#[derive(Debug)]
+pub struct ValidatorSchedule {
+    epoch_length: usize,
+    min_stake: u64,
+    /// Aura over each set, keyed by the epoch it starts at; the first is epoch 0's.
+    sets: RwLock<Vec<(u64, Arc<Aura>)>>,
+}
+
+fn aura_over(authorities: Vec<String>) -> Arc<Aura> {
+    let step_duration = 60 / authorities.len() as u64;
+    Arc::new(Aura::new(authorities, step_duration))
+}
+
+impl ValidatorSchedule {
+    /// `epoch_length` 0 keeps `genesis` for good, and then `changes` must be empty and
+    /// `min_stake` 0. A `min_stake` above 0 elects sets from the registry, which rules out
+    /// scheduled `changes`.
+    pub fn new(
+        genesis: Vec<String>,
+        epoch_length: usize,
+        changes: &ValidatorSetChanges,
+        min_stake: u64,
+    ) -> Result<Self, String> {
+        check_authorities(&genesis).map_err(|e| format!("Genesis validator set: {}", e))?;
+        if epoch_length == 0 && (!changes.is_empty() || min_stake > 0) {
+            return Err("Validator set changes need an epoch_length".to_string());
+        }
+        if min_stake > 0 && !changes.is_empty() {
+            return Err(
+                "Validator sets are either scheduled or elected by stake, not both".to_string(),
+            );
+        }
+        let sets = std::iter::once((0, genesis))
+            .chain(
+                changes
+                    .changes
+                    .iter()
+                    .map(|change| (change.epoch, change.authorities.clone())),
+            )
+            .map(|(epoch, authorities)| (epoch, aura_over(authorities)))
+            .collect();
+        Ok(ValidatorSchedule {
+            epoch_length,
+            min_stake,
+            sets: RwLock::new(sets),
+        })
+    }
+
+    /// `authorities` for every block.
+    pub fn fixed(authorities: Vec<String>) -> Self {
+        ValidatorSchedule {
+            epoch_length: 0,
+            min_stake: 0,
+            sets: RwLock::new(vec![(0, aura_over(authorities))]),
+        }
+    }
+
+    pub fn epoch_length(&self) -> usize {
+        self.epoch_length
+    }
+
+    /// Stake an account needs to be elected; 0 when the sets are not elected.
+    pub fn min_stake(&self) -> u64 {
+        self.min_stake
+    }
+
+    pub fn epoch_of(&self, index: usize) -> u64 {
+        match self.epoch_length {
+            0 => 0,
+            length => (index / length) as u64,
+        }
+    }
+
+    /// Whether the block at `index` is the last of its epoch, and so commits to the next
+    /// epoch's set.
+    pub fn is_epoch_boundary(&self, index: usize) -> bool {
+        self.epoch_length != 0 && (index + 1) % self.epoch_length == 0
+    }
+
+    /// Aura over the set authoring `epoch`.
+    pub fn aura_for_epoch(&self, epoch: u64) -> Arc<Aura> {
+        let sets = self.sets.read().unwrap();
+        sets.iter()
+            .rev()
+            .find(|(start, _)| *start <= epoch)
+            .map(|(_, aura)| aura)
+            .unwrap_or(&sets[0].1)
+            .clone()
+    }
+
+    /// Aura over the set authoring the block at `index`.
+    pub fn aura_at(&self, index: usize) -> Arc<Aura> {
+        self.aura_for_epoch(self.epoch_of(index))
+    }
+
+    /// What the block at `index` commits to in `next_authorities`, given the state it is
+    /// built on: the next epoch's set on the last block of an epoch, nothing on others.
+    /// An elected set is the registry's choice, or the current set while nobody holds
+    /// `min_stake`.
+    pub fn next_authorities_at(&self, index: usize, db: &Database) -> Result<Vec<String>, String> {
+        if !self.is_epoch_boundary(index) {
+            return Ok(Vec::new());
+        }
+        if self.min_stake > 0 {
+            let elected = elect_validators(db, self.min_stake, MAX_AUTHORITIES)?;
+            if !elected.is_empty() {
+                return Ok(elected);
+            }
+            return Ok(self.aura_at(index).authorities.clone());
+        }
+        Ok(self
+            .aura_for_epoch(self.epoch_of(index) + 1)
+            .authorities
+            .clone())
+    }
+
+    /// Checks that `block` commits to the set the schedule says it must, on top of the
+    /// state in `db`.
+    pub fn validate_block(&self, block: &Block, db: &Database) -> Result<(), String> {
+        let expected = self.next_authorities_at(block.index, db)?;
+        if block.next_authorities != expected {
+            return Err(format!(
+                "Invalid block: Block {} commits to next authorities {:?} instead of {:?}.",
+                block.index, block.next_authorities, expected
+            ));
+        }
+        Ok(())
+    }
+
+    /// Takes the next epoch's elected set from `block`, just applied. Sets learned from
+    /// blocks since unwound are replaced, as the epochs they start at are reached again.
+    pub fn learn(&self, block: &Block) {
+        if self.min_stake == 0 || block.next_authorities.is_empty() {
+            return;
+        }
+        let epoch = self.epoch_of(block.index) + 1;
+        let mut sets = self.sets.write().unwrap();
+        sets.retain(|(start, _)| *start < epoch);
+        sets.push((epoch, aura_over(block.next_authorities.clone())));
+    }
+}
+
+/// Answer to `clutch_getValidators`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatorsReport {
+    /// Epoch of the next block, and the set authoring it.
+    pub epoch: u64,
+    pub epoch_length: usize,
+    pub authorities: Vec<String>,
+    pub min_stake: u64,
+    pub registry: Vec<ValidatorStake>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::transactions::stake::construct_validator_stake_key;
+
+    fn set(authorities: &[&str]) -> Vec<String> {
+        authorities.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn changes(entries: &[(u64, &[&str])]) -> Result<ValidatorSetChanges, String> {
+        entries
+            .iter()
+            .map(|(epoch, authorities)| ValidatorSetChange {
+                epoch: *epoch,
+                authorities: set(authorities),
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+    }
+
+    /// An empty database of its own under the temp directory, destroyed on drop.
+    struct TempDb(Database);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            TempDb(Database::open(path.to_str().unwrap()))
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let path = self.0.path().to_string();
+            self.0.close();
+            let _ = Database::destroy(&path);
+        }
+    }
+
+    #[test]
+    fn each_epoch_is_authored_by_the_latest_set_scheduled() {
+        let changes = changes(&[(2, &["c"]), (4, &["d", "e"])]).unwrap();
+        let schedule = ValidatorSchedule::new(set(&["a", "b"]), 10, &changes, 0).unwrap();
+
+        assert_eq!(schedule.aura_at(0).authorities, set(&["a", "b"]));
+        assert_eq!(schedule.aura_at(19).authorities, set(&["a", "b"]));
+        assert_eq!(schedule.aura_at(20).authorities, set(&["c"]));
+        assert_eq!(schedule.aura_at(39).authorities, set(&["c"]));
+        assert_eq!(schedule.aura_at(45).authorities, set(&["d", "e"]));
+        assert_eq!(schedule.aura_at(45).step_duration, 30);
+    }
+
+    #[test]
+    fn the_last_block_of_an_epoch_commits_to_the_next_set() {
+        let db = TempDb::new("validator-set-scheduled");
+        let changes = changes(&[(2, &["c"])]).unwrap();
+        let schedule = ValidatorSchedule::new(set(&["a", "b"]), 10, &changes, 0).unwrap();
+
+        assert_eq!(schedule.next_authorities_at(8, &db.0), Ok(Vec::new()));
+        assert_eq!(schedule.next_authorities_at(9, &db.0), Ok(set(&["a", "b"])));
+        assert_eq!(schedule.next_authorities_at(19, &db.0), Ok(set(&["c"])));
+        assert_eq!(schedule.next_authorities_at(29, &db.0), Ok(set(&["c"])));
+
+        let mut block = Block::new_block(19, "h18".to_string(), vec![]);
+        assert!(schedule.validate_block(&block, &db.0).is_err());
+        block.next_authorities = set(&["c"]);
+        assert_eq!(schedule.validate_block(&block, &db.0), Ok(()));
+        block.index = 18;
+        assert!(schedule.validate_block(&block, &db.0).is_err());
+
+        let fixed = ValidatorSchedule::fixed(set(&["a"]));
+        assert_eq!(fixed.next_authorities_at(9, &db.0), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn elected_sets_follow_the_registry_from_the_committing_block() {
+        let db = TempDb::new("validator-set-elected");
+        let schedule =
+            ValidatorSchedule::new(set(&["a"]), 10, &ValidatorSetChanges::default(), 100).unwrap();
+
+        // Nobody holds the minimum yet, so the current set carries on.
+        assert_eq!(schedule.next_authorities_at(9, &db.0), Ok(set(&["a"])));
+
+        for (address, stake) in [("0xb", "500"), ("0xc", "50")] {
+            let key = construct_validator_stake_key(address);
+            db.0.put("state", &key, stake.as_bytes()).unwrap();
+        }
+        assert_eq!(schedule.next_authorities_at(19, &db.0), Ok(set(&["0xb"])));
+
+        let mut block = Block::new_block(19, "h18".to_string(), vec![]);
+        block.next_authorities = set(&["0xb"]);
+        schedule.learn(&block);
+        assert_eq!(schedule.aura_at(19).authorities, set(&["a"]));
+        assert_eq!(schedule.aura_at(20).authorities, set(&["0xb"]));
+
+        // The block committing to the set is replaced, and with it the set.
+        block.next_authorities = set(&["a"]);
+        schedule.learn(&block);
+        assert_eq!(schedule.aura_at(20).authorities, set(&["a"]));
+    }
+
+    #[test]
+    fn malformed_schedules_are_refused() {
+        assert!(changes(&[(0, &["a"])]).is_err());
+        assert!(changes(&[(3, &["a"]), (3, &["b"])]).is_err());
+        assert!(changes(&[(3, &[])]).is_err());
+        assert!(changes(&[(3, &["a", "a"])]).is_err());
+        let changes = changes(&[(3, &["a"])]).unwrap();
+        assert!(ValidatorSchedule::new(set(&["a"]), 0, &changes, 0).is_err());
+        assert!(ValidatorSchedule::new(set(&["a"]), 10, &changes, 100).is_err());
+        let none = ValidatorSetChanges::default();
+        assert!(ValidatorSchedule::new(set(&["a"]), 0, &none, 100).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, error};
+
+use crate::node::address::display_address;
+use crate::node::configuration::AppConfig;
+use crate::node::reorg::ReorgSummary;
+use crate::node::time_utils::get_current_timespan;
+
+/// How long the webhook gets to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something an operator wants paged about, POSTed to `webhook_url`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NodeEvent {
+    /// A rollback at least `reorg_alert_depth` deep.
+    DeepReorg(ReorgSummary),
+    SyncCompleted {
+        head_index: usize,
+        duration_secs: u64,
+    },
+    SyncStalled {
+        tip_index: usize,
+        target: usize,
+        idle_secs: u64,
+    },
+    /// No block was imported for a slot that has ended; `author` was the authority due.
+    MissedSlot {
+        slot: u64,
+        author: String,
+        head_index: usize,
+    },
+    /// A peer disconnected and left fewer than `min_peers` connected.
+    LowPeerCount { peers: usize, min_peers: usize },
+    /// Peers have been on chains competing with ours for `fork_warn_after_secs`.
+    NetworkSplit {
+        branches: usize,
+        peers: usize,
+        max_depth: usize,
+        split_secs: u64,
+    },
+    /// Free space on the database's file system fell below `disk_warn_free_mb`, or below
+    /// `disk_safe_mode_free_mb` with `safe_mode` on.
+    LowDiskSpace { free_mb: u64, safe_mode: bool },
+}
+
+impl NodeEvent {
+    /// One line for chat integrations, sent as `text` next to the event's fields, with
+    /// addresses in the human-facing form of network `chain_id`.
+    pub fn describe(&self, chain_id: &str) -> String {
+        match self {
+            NodeEvent::DeepReorg(summary) => format!(
+                "Reorg removed {} blocks above block {}",
+                summary.depth, summary.common_index
+            ),
+            NodeEvent::SyncCompleted {
+                head_index,
+                duration_secs,
+            } => format!(
+                "Sync completed at block {} after {}s",
+                head_index, duration_secs
+            ),
+            NodeEvent::SyncStalled {
+                tip_index,
+                target,
+                idle_secs,
+            } => format!(
+                "Sync stalled at block {} of {} for {}s",
+                tip_index, target, idle_secs
+            ),
+            NodeEvent::MissedSlot {
+                slot,
+                author,
+                head_index,
+            } => format!(
+                "Validator {} missed slot {}; head is block {}",
+                display_address(author, chain_id),
+                slot,
+                head_index
+            ),
+            NodeEvent::LowPeerCount { peers, min_peers } => {
+                format!("Only {} peers connected, below {}", peers, min_peers)
+            }
+            NodeEvent::NetworkSplit {
+                branches,
+                peers,
+                max_depth,
+                split_secs,
+            } => format!(
+                "Network split for {}s: {} peers on {} competing branches, up to {} blocks deep",
+                split_secs, peers, branches, max_depth
+            ),
+            NodeEvent::LowDiskSpace {
+                free_mb,
+                safe_mode: true,
+            } => format!("Only {} MB of disk space left; entered safe mode", free_mb),
+            NodeEvent::LowDiskSpace { free_mb, .. } => {
+                format!("Only {} MB of disk space left", free_mb)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    node: &'a str,
+    chain_id: &'a str,
+    timestamp: u64,
+    text: String,
+    #[serde(flatten)]
+    event: &'a NodeEvent,
+}
+
+/// Posts `NodeEvent`s as JSON to the configured URL, e.g. a Slack incoming webhook or a
+/// relay into PagerDuty. Cheap to clone; the default posts nothing. Delivery is best
+/// effort: failures are logged and not retried.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookNotifier {
+    url: Option<String>,
+    node: String,
+    chain_id: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// Posts to `url`, naming this node `node` on chain `chain_id` in every payload.
+    pub fn new(url: &str, node: &str, chain_id: &str) -> Self {
+        Self {
+            url: Some(url.to_string()).filter(|url| !url.is_empty()),
+            node: node.to_string(),
+            chain_id: chain_id.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(
+            &config.webhook_url,
+            &config.author_public_key,
+            &config.chain_id,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some()
+    }
+
+    fn payload(&self, event: &NodeEvent) -> serde_json::Value {
+        let payload = Payload {
+            node: &self.node,
+            chain_id: &self.chain_id,
+            timestamp: get_current_timespan(),
+            text: event.describe(&self.chain_id),
+            event,
+        };
+        serde_json::to_value(payload).unwrap_or_default()
+    }
+
+    async fn post(
+        client: reqwest::Client,
+        url: String,
+        body: serde_json::Value,
+    ) -> Result<(), String> {
+        client
+            .post(&url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Posts `event` in the background. Needs a Tokio runtime; without one the event is
+    /// only logged.
+    pub fn notify(&self, event: NodeEvent) {
+        let Some(url) = self.url.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            error!(
+                "No runtime to post webhook event: {}",
+                event.describe(&self.chain_id)
+            );
+            return;
+        };
+        debug!("Posting webhook event: {}", event.describe(&self.chain_id));
+        let body = self.payload(&event);
+        let client = self.client.clone();
+        runtime.spawn(async move {
+            if let Err(e) = Self::post(client, url, body).await {
+                error!("Failed to post webhook event: {}", e);
+            }
+        });
+    }
+
+    /// Posts `event` and waits for the answer, so a short-lived process such as
+    /// `rollback` doesn't exit before it is delivered.
+    pub fn notify_and_wait(&self, event: NodeEvent) {
+        let Some(url) = self.url.clone() else {
+            return;
+        };
+        let body = self.payload(&event);
+        // The caller may be on a runtime thread, which can't block on a future itself.
+        let posted = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            runtime.block_on(Self::post(reqwest::Client::new(), url, body))
+        })
+        .join();
+        match posted {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to post webhook event: {}", e),
+            Err(_) => error!("Webhook thread panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_carry_the_node_the_event_and_a_text_line() {
+        let notifier = WebhookNotifier::new("http://localhost:1/hook", "0xabc", "clutch-dev");
+        assert!(notifier.is_enabled());
+        assert!(!WebhookNotifier::new("", "0xabc", "clutch-dev").is_enabled());
+
+        let payload = notifier.payload(&NodeEvent::LowPeerCount {
+            peers: 1,
+            min_peers: 3,
+        });
+        assert_eq!(payload["node"], "0xabc");
+        assert_eq!(payload["chain_id"], "clutch-dev");
+        assert_eq!(payload["event"], "low_peer_count");
+        assert_eq!(payload["peers"], 1);
+        assert_eq!(payload["min_peers"], 3);
+        assert_eq!(payload["text"], "Only 1 peers connected, below 3");
+
+        let missed = notifier.payload(&NodeEvent::MissedSlot {
+            slot: 42,
+            author: "0xdef".to_string(),
+            head_index: 7,
+        });
+        assert_eq!(missed["event"], "missed_slot");
+        assert_eq!(missed["author"], "0xdef");
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// A filter not polled for this long is dropped.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(300);
+/// Installed filters across all connections; `install` fails beyond this.
+const MAX_FILTERS: usize = 1024;
+/// Blocks reported per `clutch_getFilterChanges` call. A client further behind catches
+/// up over several polls.
+const MAX_BLOCKS_PER_POLL: usize = 256;
+
+/// What a filter reports for each new block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Block hashes.
+    Blocks,
+    /// Balance effects, optionally only those of one account.
+    Logs { address: Option<String> },
+}
+
+#[derive(Debug)]
+struct Filter {
+    kind: FilterKind,
+    /// First block not yet reported.
+    next_block: usize,
+    last_polled: Instant,
+}
+
+/// Server-side state of the polling filters. Shared by every WebSocket connection, so a
+/// client can reconnect between polls; filters are only removed by `uninstall` or by
+/// going `FILTER_TIMEOUT` without a poll.
+#[derive(Debug, Default)]
+pub struct Filters {
+    filters: HashMap<String, Filter>,
+}
+
+impl Filters {
+    /// Installs a filter reporting blocks from `next_block` on, and returns its id.
+    pub fn install(
+        &mut self,
+        kind: FilterKind,
+        next_block: usize,
+        now: Instant,
+    ) -> Result<String, String> {
+        self.expire(now);
+        if self.filters.len() >= MAX_FILTERS {
+            return Err(format!("Too many installed filters (max {})", MAX_FILTERS));
+        }
+        let id = format!("0x{:032x}", rand::random::<u128>());
+        self.filters.insert(
+            id.clone(),
+            Filter {
+                kind,
+                next_block,
+                last_polled: now,
+            },
+        );
+        Ok(id)
+    }
+
+    /// The filter's kind and the blocks up to `head` it hasn't reported yet, marking them
+    /// reported. The range is empty when there is nothing new; `None` if the filter is
+    /// unknown or expired.
+    pub fn poll(
+        &mut self,
+        id: &str,
+        head: usize,
+        now: Instant,
+    ) -> Option<(FilterKind, RangeInclusive<usize>)> {
+        self.expire(now);
+        let filter = self.filters.get_mut(id)?;
+        filter.last_polled = now;
+        let from = filter.next_block;
+        let to = head.min(from.saturating_add(MAX_BLOCKS_PER_POLL - 1));
+        if from <= to {
+            filter.next_block = to + 1;
+        }
+        Some((filter.kind.clone(), from..=to))
+    }
+
+    pub fn uninstall(&mut self, id: &str) -> bool {
+        self.filters.remove(id).is_some()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.filters
+            .retain(|_, filter| now.duration_since(filter.last_polled) < FILTER_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_block_is_reported_once() {
+        let mut filters = Filters::default();
+        let now = Instant::now();
+        let id = filters.install(FilterKind::Blocks, 5, now).unwrap();
+
+        let (_, range) = filters.poll(&id, 4, now).unwrap();
+        assert!(range.is_empty());
+        let (kind, range) = filters.poll(&id, 7, now).unwrap();
+        assert_eq!((kind, range), (FilterKind::Blocks, 5..=7));
+        let (_, range) = filters.poll(&id, 7, now).unwrap();
+        assert!(range.is_empty());
+
+        let (_, range) = filters.poll(&id, 5000, now).unwrap();
+        assert_eq!(range, 8..=8 + MAX_BLOCKS_PER_POLL - 1);
+    }
+
+    #[test]
+    fn filters_expire_without_polls_and_can_be_uninstalled() {
+        let mut filters = Filters::default();
+        let now = Instant::now();
+        let idle = filters.install(FilterKind::Blocks, 0, now).unwrap();
+        let kind = FilterKind::Logs { address: None };
+        let removed = filters.install(kind, 0, now).unwrap();
+
+        assert!(filters.uninstall(&removed));
+        assert!(!filters.uninstall(&removed));
+        assert!(filters.poll(&idle, 0, now + FILTER_TIMEOUT).is_none());
+    }
+}
@@ -1 +1,2 @@
+pub mod filters;
 pub mod websocket;
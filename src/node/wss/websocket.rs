@@ -1,17 +1,28 @@
+use crate::node::account_state::AccountSnapshot;
+use crate::node::address::Address;
 use crate::node::blockchain::Blockchain;
+use crate::node::blocks::block_tag::BlockTag;
+use crate::node::blocks::state_diff::StateDiff;
+use crate::node::config_reload::ConfigReloader;
+use crate::node::duties::{DEFAULT_UPCOMING_DUTIES, MAX_UPCOMING_DUTIES};
+use crate::node::systemd::{Systemd, RPC_LISTENING};
+use crate::node::time_utils::get_current_timespan;
+use crate::node::wss::filters::{FilterKind, Filters};
 use crate::node::transactions::ride_request::MapBounds;
 use crate::node::transactions::transaction::Transaction;
-use crate::node::p2p_server::{GossipMessageType, P2PServer, P2PServerCommand};
-use crate::node::rlp_encoding::encode;
+use crate::node::p2p_server::disconnect::DisconnectReason;
+use crate::node::p2p_server::{P2PServer, P2PServerCommand};
 use futures::{stream::StreamExt, SinkExt};
 use tracing::{error, info, warn};
 use std::error::Error;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{Mutex, Semaphore};
+use tokio::sync::{RwLock, Semaphore};
 use tokio_tungstenite::accept_async_with_config;
 use tokio_tungstenite::tungstenite::protocol::{Message, WebSocketConfig};
 use hex;
+use libp2p::{Multiaddr, PeerId};
 
 // Bound per-connection message size and total concurrent connections so an
 // unauthenticated peer can't exhaust memory or tasks (default frame cap is 64 MiB).
@@ -23,13 +34,18 @@ pub struct WebSocket;
 impl WebSocket {
     pub async fn run(
         addr: &str,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        debug_rpc_enabled: bool,
+        reloader: ConfigReloader,
+        systemd: Systemd,
     ) -> Result<(), Box<dyn Error>> {
         let listener = TcpListener::bind(addr).await?;
         info!("WebSocket server started on {}", addr);
+        systemd.ready(RPC_LISTENING);
 
         let connections = Arc::new(Semaphore::new(MAX_WS_CONNECTIONS));
+        let filters = Arc::new(Mutex::new(Filters::default()));
 
         while let Ok((stream, _)) = listener.accept().await {
             let permit = match Arc::clone(&connections).try_acquire_owned() {
@@ -44,9 +60,20 @@ impl WebSocket {
             };
             let blockchain = Arc::clone(&blockchain);
             let command_tx_p2p = command_tx_p2p.clone();
+            let filters = Arc::clone(&filters);
+            let reloader = reloader.clone();
             tokio::spawn(async move {
                 let _permit = permit; // released when the connection ends
-                if let Err(e) = Self::handle_connection(stream, blockchain, command_tx_p2p).await {
+                if let Err(e) = Self::handle_connection(
+                    stream,
+                    blockchain,
+                    command_tx_p2p,
+                    filters,
+                    debug_rpc_enabled,
+                    reloader,
+                )
+                .await
+                {
                     error!("Error handling connection: {}", e);
                 }
             });
@@ -57,8 +84,11 @@ impl WebSocket {
 
     async fn handle_connection(
         stream: TcpStream,
-        blockchain: Arc<Mutex<Blockchain>>,
+        blockchain: Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        filters: Arc<Mutex<Filters>>,
+        debug_rpc_enabled: bool,
+        reloader: ConfigReloader,
     ) -> Result<(), Box<dyn Error>> {
         let mut config = WebSocketConfig::default();
         config.max_message_size = Some(MAX_WS_MESSAGE_BYTES);
@@ -70,7 +100,16 @@ impl WebSocket {
             match message {
                 Ok(Message::Text(text)) => {
                     info!("Received from websocket: {}", text);
-                    if let Some(response) = Self::handle_json_rpc_request(&text, &blockchain, command_tx_p2p.clone()).await {
+                    if let Some(response) = Self::handle_json_rpc_request(
+                        &text,
+                        &blockchain,
+                        command_tx_p2p.clone(),
+                        &filters,
+                        debug_rpc_enabled,
+                        &reloader,
+                    )
+                    .await
+                    {
                         if let Err(e) = ws_sender.send(Message::Text(response)).await {
                             error!("Error sending message: {}", e);
                             return Err(Box::new(e));
@@ -90,8 +129,11 @@ impl WebSocket {
 
     async fn handle_json_rpc_request(
         request_str: &str,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+        filters: &Mutex<Filters>,
+        debug_rpc_enabled: bool,
+        reloader: &ConfigReloader,
     ) -> Option<String> {
         let request_value: serde_json::Value = match serde_json::from_str(request_str) {
             Ok(val) => val,
@@ -113,6 +155,11 @@ impl WebSocket {
         let params = request_value.get("params").cloned().unwrap_or(serde_json::Value::Null);
         let id = request_value.get("id").cloned().unwrap_or(serde_json::Value::Null);
 
+        if !reloader.allowlist().allows(method) {
+            warn!("Method '{}' is not in rpc_allowed_methods", method);
+            return Some(json_rpc_error_response(-32601, "Method not allowed", id));
+        }
+
         match method {
             "send_transaction" => {
                 Self::handle_send_transaction(params, id, blockchain, command_tx_p2p).await
@@ -126,6 +173,42 @@ impl WebSocket {
             "get_account_balance" => {
                 Self::handle_get_account_balance(params, id, blockchain).await
             }
+            "clutch_getBalance" => {
+                Self::handle_clutch_get_balance(params, id, blockchain).await
+            }
+            "clutch_getTransactionCount" => {
+                Self::handle_clutch_get_transaction_count(params, id, blockchain).await
+            }
+            "clutch_getAccount" => {
+                Self::handle_clutch_get_account(params, id, blockchain).await
+            }
+            "clutch_getTransactionByHash" => {
+                Self::handle_clutch_get_transaction_by_hash(params, id, blockchain).await
+            }
+            "clutch_getTransactionReceipt" => {
+                Self::handle_clutch_get_transaction_receipt(params, id, blockchain).await
+            }
+            "clutch_newFilter" => {
+                Self::handle_clutch_new_filter(params, id, blockchain, filters).await
+            }
+            "clutch_newBlockFilter" => {
+                Self::handle_clutch_new_block_filter(id, blockchain, filters).await
+            }
+            "clutch_getFilterChanges" => {
+                Self::handle_clutch_get_filter_changes(params, id, blockchain, filters).await
+            }
+            "clutch_uninstallFilter" => {
+                Self::handle_clutch_uninstall_filter(params, id, filters)
+            }
+            "txpool_content" => {
+                Self::handle_txpool_content(id, blockchain).await
+            }
+            "txpool_status" => {
+                Self::handle_txpool_status(id, blockchain).await
+            }
+            "txpool_inspect" => {
+                Self::handle_txpool_inspect(id, blockchain).await
+            }
             "get_account_balance_effects" => {
                 Self::handle_get_account_balance_effects(params, id, blockchain).await
             }
@@ -147,6 +230,33 @@ impl WebSocket {
             "list_recent_trips" => {
                 Self::handle_list_recent_trips(params, id, blockchain).await
             }
+            "admin_peers" => {
+                Self::handle_admin_peers(id, command_tx_p2p).await
+            }
+            "admin_addPeer" => Self::handle_admin_add_peer(params, id, command_tx_p2p).await,
+            "admin_removePeer" => Self::handle_admin_remove_peer(params, id, command_tx_p2p).await,
+            "admin_reloadConfig" => {
+                Self::handle_admin_reload_config(id, reloader).await
+            }
+            "clutch_getDuties" => {
+                Self::handle_clutch_get_duties(params, id, blockchain).await
+            }
+            "clutch_getValidators" => Self::handle_clutch_get_validators(id, blockchain).await,
+            "clutch_getStateDiff" => {
+                Self::handle_clutch_get_state_diff(params, id, blockchain, debug_rpc_enabled).await
+            }
+            "debug_traceBlockByIndex" if debug_rpc_enabled => {
+                Self::handle_debug_trace_block_by_index(params, id, blockchain).await
+            }
+            "debug_traceTransaction" if debug_rpc_enabled => {
+                Self::handle_debug_trace_transaction(params, id, blockchain).await
+            }
+            "debug_getBadBlocks" if debug_rpc_enabled => {
+                Self::handle_debug_get_bad_blocks(id, blockchain).await
+            }
+            "debug_forks" if debug_rpc_enabled => {
+                Self::handle_debug_forks(id, command_tx_p2p).await
+            }
             _ => {
                 warn!("Unknown method '{}' in request: {}", method, request_str);
                 Some(json_rpc_error_response(-32601, "Method not found", id))
@@ -157,7 +267,7 @@ impl WebSocket {
     async fn handle_send_transaction(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
     ) -> Option<String> {
         let transaction: Transaction = match serde_json::from_value(params) {
@@ -169,8 +279,8 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
-        if let Err(e) = blockchain.add_transaction_to_pool(&transaction) {
+        let blockchain = blockchain.write().await;
+        if let Err(e) = blockchain.add_local_transaction(&transaction) {
             let error_msg = format!("Failed to add transaction: {}", e);
             error!("{}", error_msg);
             return Some(json_rpc_error_response(-32000, &error_msg, id));
@@ -178,9 +288,7 @@ impl WebSocket {
 
         info!("Transaction added to pool from WebSocket.");
 
-        // Gossip transaction
-        let encoded_tx = encode(&transaction);
-        P2PServer::gossip_message_command(command_tx_p2p, GossipMessageType::Transaction, &encoded_tx).await;
+        P2PServer::broadcast_transaction_command(command_tx_p2p, transaction).await;
 
         Some(json_rpc_success_response(serde_json::json!("Transaction imported"), id))
     }
@@ -188,7 +296,7 @@ impl WebSocket {
     async fn handle_send_raw_transaction(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
         command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
     ) -> Option<String> {
         // Expect params to be a hex string (RLP encoded)
@@ -217,23 +325,21 @@ impl WebSocket {
                 return Some(json_rpc_error_response(-32602, &error_msg, id));
             }
         };
-        let blockchain = blockchain.lock().await;
-        if let Err(e) = blockchain.add_transaction_to_pool(&transaction) {
+        let blockchain = blockchain.write().await;
+        if let Err(e) = blockchain.add_local_transaction(&transaction) {
             let error_msg = format!("Failed to add transaction: {}", e);
             error!("{}", error_msg);
             return Some(json_rpc_error_response(-32000, &error_msg, id));
         }
         info!("Transaction added to pool from WebSocket.");
-        // Gossip transaction
-        let encoded_tx = encode(&transaction);
-        P2PServer::gossip_message_command(command_tx_p2p, GossipMessageType::Transaction, &encoded_tx).await;
+        P2PServer::broadcast_transaction_command(command_tx_p2p, transaction).await;
         Some(json_rpc_success_response(serde_json::json!("Transaction imported"), id))
     }
 
     async fn handle_get_next_nonce(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
 
         #[derive(serde::Deserialize)]
@@ -251,9 +357,13 @@ impl WebSocket {
         };
 
         // Get the blockchain lock
-        let blockchain = blockchain.lock().await;
-        
-        match blockchain.get_current_nonce(&params.address) {
+        let blockchain = blockchain.read().await;
+        let address = match Self::address_param(&params.address, &id, &blockchain) {
+            Ok(address) => address,
+            Err(response) => return Some(response),
+        };
+
+        match blockchain.get_current_nonce(&address) {
             Ok(nonce) => {
                 let next_nonce = nonce + 1;
                 Some(json_rpc_success_response(serde_json::json!({ "nonce": next_nonce }), id))
@@ -269,7 +379,7 @@ impl WebSocket {
     async fn handle_get_account_balance(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct GetBalanceParams {
@@ -285,15 +395,468 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
-        let balance = blockchain.get_account_balance(&params.address);
+        let blockchain = blockchain.read().await;
+        let address = match Self::address_param(&params.address, &id, &blockchain) {
+            Ok(address) => address,
+            Err(response) => return Some(response),
+        };
+        let balance = blockchain.get_account_balance(&address);
         Some(json_rpc_success_response(serde_json::json!({ "balance": balance }), id))
     }
 
+    async fn handle_clutch_get_balance(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        match Self::account_at(params, &id, blockchain).await {
+            Ok(account) => Some(json_rpc_success_response(
+                serde_json::json!({ "balance": account.balance, "block_index": account.block_index }),
+                id,
+            )),
+            Err(response) => Some(response),
+        }
+    }
+
+    async fn handle_clutch_get_transaction_count(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        match Self::account_at(params, &id, blockchain).await {
+            Ok(account) => Some(json_rpc_success_response(
+                serde_json::json!({ "nonce": account.nonce, "block_index": account.block_index }),
+                id,
+            )),
+            Err(response) => Some(response),
+        }
+    }
+
+    async fn handle_clutch_get_account(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        match Self::account_at(params, &id, blockchain).await {
+            Ok(account) => Some(json_rpc_success_response(
+                serde_json::to_value(&account).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(response) => Some(response),
+        }
+    }
+
+    /// Shared by the `clutch_*` account queries: params `{ address, block }`, where `block`
+    /// is an index, `"latest"` (the default) or `"finalized"`. Errors come back as the
+    /// JSON-RPC error response to send.
+    async fn account_at(
+        params: serde_json::Value,
+        id: &serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Result<AccountSnapshot, String> {
+        #[derive(serde::Deserialize)]
+        struct AccountAtParams {
+            address: String,
+            #[serde(default)]
+            block: BlockTag,
+        }
+
+        let params: AccountAtParams = serde_json::from_value(params).map_err(|e| {
+            let error_msg = format!(
+                "Invalid params: expected object with 'address' and optional 'block' fields: {}",
+                e
+            );
+            warn!("{}", error_msg);
+            json_rpc_error_response(-32602, &error_msg, id.clone())
+        })?;
+
+        let blockchain = blockchain.read().await;
+        let address = Self::address_param(&params.address, id, &blockchain)?;
+        blockchain
+            .resolve_block_tag(params.block)
+            .and_then(|index| blockchain.get_account_at(&address, index))
+            .map_err(|e| {
+                let error_msg = format!("Failed to get account {}: {}", params.address, e);
+                error!("{}", error_msg);
+                json_rpc_error_response(-32000, &error_msg, id.clone())
+            })
+    }
+
+    /// An `address` param in this network's `<chain_id>:0x…` form or plain hex, as the
+    /// lowercase hex account state is keyed by; addresses of other networks are rejected.
+    fn address_param(
+        address: &str,
+        id: &serde_json::Value,
+        blockchain: &Blockchain,
+    ) -> Result<String, String> {
+        Address::parse(address, blockchain.chain_id())
+            .map(|address| address.to_hex())
+            .map_err(|e| {
+                let error_msg = format!("Invalid params: {}", e);
+                warn!("{}", error_msg);
+                json_rpc_error_response(-32602, &error_msg, id.clone())
+            })
+    }
+
+    /// The optional `driver_address` and `passenger_address` trip filters, parsed like
+    /// `address_param`.
+    fn trip_address_params(
+        driver_address: Option<String>,
+        passenger_address: Option<String>,
+        id: &serde_json::Value,
+        blockchain: &Blockchain,
+    ) -> Result<(Option<String>, Option<String>), String> {
+        let parse = |address: Option<String>| {
+            address
+                .map(|address| Self::address_param(&address, id, blockchain))
+                .transpose()
+        };
+        Ok((parse(driver_address)?, parse(passenger_address)?))
+    }
+
+    /// An imported transaction with `block_index`, `block_hash` and `transaction_index`
+    /// added, or a pooled one with those set to null; null if the hash is unknown.
+    async fn handle_clutch_get_transaction_by_hash(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let hash = match Self::transaction_hash_param(params, &id) {
+            Ok(hash) => hash,
+            Err(response) => return Some(response),
+        };
+
+        let blockchain = blockchain.read().await;
+        let found = match blockchain.find_transaction(&hash) {
+            Ok(Some((mut block, tx_index))) => Ok(Some((
+                block.transactions.swap_remove(tx_index),
+                Some((block.index, block.hash, tx_index)),
+            ))),
+            Ok(None) => blockchain
+                .get_transaction_from_pool(&hash)
+                .map(|pooled| pooled.map(|tx| (tx, None))),
+            Err(e) => Err(e),
+        };
+
+        match found {
+            Ok(Some((tx, location))) => {
+                let mut tx_value = serde_json::to_value(&tx).unwrap_or(serde_json::Value::Null);
+                if let Some(obj) = tx_value.as_object_mut() {
+                    let (block_index, block_hash, transaction_index) = match location {
+                        Some((index, hash, position)) => (Some(index), Some(hash), Some(position)),
+                        None => (None, None, None),
+                    };
+                    obj.insert("block_index".to_string(), serde_json::json!(block_index));
+                    obj.insert("block_hash".to_string(), serde_json::json!(block_hash));
+                    obj.insert(
+                        "transaction_index".to_string(),
+                        serde_json::json!(transaction_index),
+                    );
+                }
+                Some(json_rpc_success_response(tx_value, id))
+            }
+            Ok(None) => Some(json_rpc_success_response(serde_json::Value::Null, id)),
+            Err(e) => {
+                let error_msg = format!("Failed to get transaction {}: {}", hash, e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    async fn handle_clutch_get_transaction_receipt(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let hash = match Self::transaction_hash_param(params, &id) {
+            Ok(hash) => hash,
+            Err(response) => return Some(response),
+        };
+
+        let blockchain = blockchain.read().await;
+        match blockchain.get_transaction_receipt(&hash) {
+            Ok(receipt) => Some(json_rpc_success_response(
+                serde_json::to_value(&receipt).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => {
+                let error_msg = format!("Failed to get receipt for {}: {}", hash, e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Params `{ hash }` of the transaction lookups, lowercased to match stored hashes.
+    fn transaction_hash_param(
+        params: serde_json::Value,
+        id: &serde_json::Value,
+    ) -> Result<String, String> {
+        #[derive(serde::Deserialize)]
+        struct TransactionHashParams {
+            hash: String,
+        }
+
+        match serde_json::from_value::<TransactionHashParams>(params) {
+            Ok(p) => Ok(p.hash.to_lowercase()),
+            Err(e) => {
+                let error_msg = format!("Invalid params: expected object with 'hash' field: {}", e);
+                warn!("{}", error_msg);
+                Err(json_rpc_error_response(-32602, &error_msg, id.clone()))
+            }
+        }
+    }
+
+    /// Every pool transaction, by bucket and sender.
+    async fn handle_txpool_content(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let blockchain = blockchain.read().await;
+        match blockchain.get_pool_content() {
+            Ok(content) => Some(json_rpc_success_response(
+                serde_json::to_value(&content).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => Some(Self::txpool_error_response(e, id)),
+        }
+    }
+
+    /// Transaction counts per bucket.
+    async fn handle_txpool_status(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let blockchain = blockchain.read().await;
+        match blockchain.get_pool_content() {
+            Ok(content) => Some(json_rpc_success_response(
+                serde_json::to_value(content.status()).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => Some(Self::txpool_error_response(e, id)),
+        }
+    }
+
+    /// Like `txpool_content`, with each transaction summarised as `"<nonce>: <type> <hash>"`.
+    async fn handle_txpool_inspect(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let blockchain = blockchain.read().await;
+        match blockchain.get_pool_content() {
+            Ok(content) => {
+                let summary = content
+                    .map(|tx| format!("{}: {} {}", tx.nonce, tx.function_call_type(), tx.hash));
+                Some(json_rpc_success_response(
+                    serde_json::to_value(&summary).unwrap_or(serde_json::Value::Null),
+                    id,
+                ))
+            }
+            Err(e) => Some(Self::txpool_error_response(e, id)),
+        }
+    }
+
+    fn txpool_error_response(e: String, id: serde_json::Value) -> String {
+        let error_msg = format!("Failed to read transaction pool: {}", e);
+        error!("{}", error_msg);
+        json_rpc_error_response(-32000, &error_msg, id)
+    }
+
+    /// Installs a log filter reporting balance effects, optionally of one `address`, from
+    /// `from_block` on (default: the next block). Params may be omitted.
+    async fn handle_clutch_new_filter(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+        filters: &Mutex<Filters>,
+    ) -> Option<String> {
+        #[derive(serde::Deserialize, Default)]
+        #[serde(default)]
+        struct NewFilterParams {
+            address: Option<String>,
+            from_block: Option<BlockTag>,
+        }
+
+        let params: NewFilterParams = if params.is_null() {
+            NewFilterParams::default()
+        } else {
+            match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    let error_msg = format!(
+                        "Invalid params: expected object with optional 'address' and 'from_block' fields: {}",
+                        e
+                    );
+                    warn!("{}", error_msg);
+                    return Some(json_rpc_error_response(-32602, &error_msg, id));
+                }
+            }
+        };
+
+        let (next_block, address) = {
+            let blockchain = blockchain.read().await;
+            let address = match params.address {
+                Some(address) => match Self::address_param(&address, &id, &blockchain) {
+                    Ok(address) => Some(address),
+                    Err(response) => return Some(response),
+                },
+                None => None,
+            };
+            let tag = params.from_block.unwrap_or_default();
+            let resolved = blockchain.resolve_block_tag(tag);
+            match (params.from_block, resolved) {
+                (Some(_), Ok(index)) => (index, address),
+                (None, Ok(head)) => (head + 1, address),
+                (_, Err(e)) => {
+                    let error_msg = format!("Failed to install filter: {}", e);
+                    error!("{}", error_msg);
+                    return Some(json_rpc_error_response(-32000, &error_msg, id));
+                }
+            }
+        };
+
+        let kind = FilterKind::Logs { address };
+        Self::install_filter(kind, next_block, id, filters)
+    }
+
+    async fn handle_clutch_new_block_filter(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+        filters: &Mutex<Filters>,
+    ) -> Option<String> {
+        let head = match blockchain.read().await.get_latest_block() {
+            Ok(Some(block)) => block.index,
+            Ok(None) => {
+                return Some(json_rpc_error_response(
+                    -32000,
+                    "Failed to get latest block",
+                    id,
+                ))
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to install filter: {}", e);
+                error!("{}", error_msg);
+                return Some(json_rpc_error_response(-32000, &error_msg, id));
+            }
+        };
+        Self::install_filter(FilterKind::Blocks, head + 1, id, filters)
+    }
+
+    fn install_filter(
+        kind: FilterKind,
+        next_block: usize,
+        id: serde_json::Value,
+        filters: &Mutex<Filters>,
+    ) -> Option<String> {
+        match filters
+            .lock()
+            .unwrap()
+            .install(kind, next_block, Instant::now())
+        {
+            Ok(filter_id) => Some(json_rpc_success_response(serde_json::json!(filter_id), id)),
+            Err(e) => {
+                warn!("{}", e);
+                Some(json_rpc_error_response(-32000, &e, id))
+            }
+        }
+    }
+
+    /// Block hashes or balance effects since the filter's last poll.
+    async fn handle_clutch_get_filter_changes(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+        filters: &Mutex<Filters>,
+    ) -> Option<String> {
+        let filter_id = match Self::filter_id_param(params, &id) {
+            Ok(filter_id) => filter_id,
+            Err(response) => return Some(response),
+        };
+
+        let blockchain = blockchain.read().await;
+        let head = match blockchain.get_latest_block() {
+            Ok(Some(block)) => block.index,
+            Ok(None) => {
+                return Some(json_rpc_error_response(
+                    -32000,
+                    "Failed to get latest block",
+                    id,
+                ))
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to get filter changes: {}", e);
+                error!("{}", error_msg);
+                return Some(json_rpc_error_response(-32000, &error_msg, id));
+            }
+        };
+        let polled = filters
+            .lock()
+            .unwrap()
+            .poll(&filter_id, head, Instant::now());
+        let Some((kind, blocks)) = polled else {
+            return Some(json_rpc_error_response(-32004, "Filter not found", id));
+        };
+
+        let changes = match kind {
+            FilterKind::Blocks => blockchain
+                .iter_blocks(*blocks.start(), blocks.count())
+                .map(|block| block.map(|block| block.hash))
+                .collect::<Result<Vec<String>, String>>()
+                .map(|hashes| serde_json::json!(hashes)),
+            FilterKind::Logs { address } => blockchain
+                .get_logs(*blocks.start(), *blocks.end(), address.as_deref())
+                .map(|logs| {
+                    serde_json::to_value(&logs).unwrap_or(serde_json::Value::Array(vec![]))
+                }),
+        };
+        match changes {
+            Ok(changes) => Some(json_rpc_success_response(changes, id)),
+            Err(e) => {
+                let error_msg = format!("Failed to get filter changes: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    fn handle_clutch_uninstall_filter(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        filters: &Mutex<Filters>,
+    ) -> Option<String> {
+        match Self::filter_id_param(params, &id) {
+            Ok(filter_id) => {
+                let removed = filters.lock().unwrap().uninstall(&filter_id);
+                Some(json_rpc_success_response(serde_json::json!(removed), id))
+            }
+            Err(response) => Some(response),
+        }
+    }
+
+    fn filter_id_param(
+        params: serde_json::Value,
+        id: &serde_json::Value,
+    ) -> Result<String, String> {
+        #[derive(serde::Deserialize)]
+        struct FilterIdParams {
+            id: String,
+        }
+
+        match serde_json::from_value::<FilterIdParams>(params) {
+            Ok(p) => Ok(p.id),
+            Err(e) => {
+                let error_msg = format!("Invalid params: expected object with 'id' field: {}", e);
+                warn!("{}", error_msg);
+                Err(json_rpc_error_response(-32602, &error_msg, id.clone()))
+            }
+        }
+    }
+
     async fn handle_get_account_balance_effects(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct GetAccountBalanceEffectsParams {
@@ -320,9 +883,13 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
+        let blockchain = blockchain.read().await;
+        let address = match Self::address_param(&params.address, &id, &blockchain) {
+            Ok(address) => address,
+            Err(response) => return Some(response),
+        };
         let effects = blockchain.get_account_balance_effects(
-            &params.address,
+            &address,
             params.limit,
             params.offset,
         );
@@ -335,7 +902,7 @@ impl WebSocket {
     async fn handle_get_block_by_index(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct GetBlockByIndexParams {
@@ -351,14 +918,14 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
+        let blockchain = blockchain.read().await;
         match blockchain.get_blocks_by_indexes(vec![params.index]) {
             Ok(blocks) => {
                 if let Some(block) = blocks.into_iter().next() {
                     let block_reward = if block.index == 0 {
                         0
                     } else {
-                        blockchain.block_reward_amount()
+                        blockchain.block_reward_for(&block)
                     };
                     let reward_recipient = block.author.clone();
                     let mut block_value =
@@ -426,7 +993,7 @@ impl WebSocket {
     async fn handle_list_ride_requests(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         // Optional bounds: { minLat, maxLat, minLng, maxLng } - all optional, omit for no filter
         let bounds: Option<MapBounds> = if params.is_object() && !params.as_object().unwrap().is_empty() {
@@ -442,7 +1009,7 @@ impl WebSocket {
             None
         };
 
-        let blockchain = blockchain.lock().await;
+        let blockchain = blockchain.read().await;
         match blockchain.list_available_ride_requests(bounds) {
             Ok(requests) => {
                 let result = serde_json::to_value(requests).unwrap_or(serde_json::Value::Array(vec![]));
@@ -459,7 +1026,7 @@ impl WebSocket {
     async fn handle_list_ride_offers(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct GetRideOffersParams {
@@ -481,7 +1048,7 @@ impl WebSocket {
 
         let ride_request_tx_hash = parsed_params.and_then(|p| p.ride_request_tx_hash);
 
-        let blockchain = blockchain.lock().await;
+        let blockchain = blockchain.read().await;
         match blockchain.list_ride_offers_for_request(ride_request_tx_hash.as_deref()) {
             Ok(offers) => {
                 let result = serde_json::to_value(offers).unwrap_or(serde_json::Value::Array(vec![]));
@@ -498,7 +1065,7 @@ impl WebSocket {
     async fn handle_list_active_trips(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct ListActiveTripsParams {
@@ -518,11 +1085,17 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
-        match blockchain.list_active_trips(
-            parsed.driver_address.as_deref(),
-            parsed.passenger_address.as_deref(),
+        let blockchain = blockchain.read().await;
+        let (driver_address, passenger_address) = match Self::trip_address_params(
+            parsed.driver_address,
+            parsed.passenger_address,
+            &id,
+            &blockchain,
         ) {
+            Ok(addresses) => addresses,
+            Err(response) => return Some(response),
+        };
+        match blockchain.list_active_trips(driver_address.as_deref(), passenger_address.as_deref()) {
             Ok(trips) => {
                 let result = serde_json::to_value(trips).unwrap_or(serde_json::Value::Array(vec![]));
                 Some(json_rpc_success_response(result, id))
@@ -538,7 +1111,7 @@ impl WebSocket {
     async fn handle_list_completed_trips(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct ListCompletedTripsParams {
@@ -558,11 +1131,17 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
-        match blockchain.list_completed_trips(
-            parsed.driver_address.as_deref(),
-            parsed.passenger_address.as_deref(),
+        let blockchain = blockchain.read().await;
+        let (driver_address, passenger_address) = match Self::trip_address_params(
+            parsed.driver_address,
+            parsed.passenger_address,
+            &id,
+            &blockchain,
         ) {
+            Ok(addresses) => addresses,
+            Err(response) => return Some(response),
+        };
+        match blockchain.list_completed_trips(driver_address.as_deref(), passenger_address.as_deref()) {
             Ok(trips) => {
                 let result = serde_json::to_value(trips).unwrap_or(serde_json::Value::Array(vec![]));
                 Some(json_rpc_success_response(result, id))
@@ -578,7 +1157,7 @@ impl WebSocket {
     async fn handle_list_recent_trips(
         params: serde_json::Value,
         id: serde_json::Value,
-        blockchain: &Arc<Mutex<Blockchain>>,
+        blockchain: &Arc<RwLock<Blockchain>>,
     ) -> Option<String> {
         #[derive(serde::Deserialize)]
         struct ListRecentTripsParams {
@@ -598,11 +1177,17 @@ impl WebSocket {
             }
         };
 
-        let blockchain = blockchain.lock().await;
-        match blockchain.list_recent_trips(
-            parsed.driver_address.as_deref(),
-            parsed.passenger_address.as_deref(),
+        let blockchain = blockchain.read().await;
+        let (driver_address, passenger_address) = match Self::trip_address_params(
+            parsed.driver_address,
+            parsed.passenger_address,
+            &id,
+            &blockchain,
         ) {
+            Ok(addresses) => addresses,
+            Err(response) => return Some(response),
+        };
+        match blockchain.list_recent_trips(driver_address.as_deref(), passenger_address.as_deref()) {
             Ok(trips) => {
                 let result = serde_json::to_value(trips).unwrap_or(serde_json::Value::Array(vec![]));
                 Some(json_rpc_success_response(result, id))
@@ -614,6 +1199,313 @@ impl WebSocket {
             }
         }
     }
+
+    async fn handle_admin_peers(
+        id: serde_json::Value,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) -> Option<String> {
+        match P2PServer::get_peers_command(command_tx_p2p).await {
+            Ok(snapshot) => {
+                let result = serde_json::to_value(snapshot).unwrap_or(serde_json::Value::Null);
+                Some(json_rpc_success_response(result, id))
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to get peers: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Params `{ address }`, a multiaddr ending in `/p2p/<peer id>` or not; true once the
+    /// dial has started.
+    async fn handle_admin_add_peer(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) -> Option<String> {
+        #[derive(serde::Deserialize)]
+        struct AddPeerParams {
+            address: String,
+        }
+
+        let address = match serde_json::from_value::<AddPeerParams>(params)
+            .map_err(|e| e.to_string())
+            .and_then(|p| p.address.parse::<Multiaddr>().map_err(|e| e.to_string()))
+        {
+            Ok(address) => address,
+            Err(e) => {
+                let error_msg = format!("Invalid params: expected a multiaddr 'address': {}", e);
+                warn!("{}", error_msg);
+                return Some(json_rpc_error_response(-32602, &error_msg, id));
+            }
+        };
+        match P2PServer::dial_peer_command(command_tx_p2p, address).await {
+            Ok(()) => Some(json_rpc_success_response(serde_json::Value::Bool(true), id)),
+            Err(e) => {
+                let error_msg = format!("Failed to dial peer: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Params `{ peer_id }`; whether the peer was connected. It is told the operator asked
+    /// for the disconnect, and may connect again.
+    async fn handle_admin_remove_peer(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) -> Option<String> {
+        #[derive(serde::Deserialize)]
+        struct RemovePeerParams {
+            peer_id: String,
+        }
+
+        let peer_id = match serde_json::from_value::<RemovePeerParams>(params)
+            .map_err(|e| e.to_string())
+            .and_then(|p| p.peer_id.parse::<PeerId>().map_err(|e| e.to_string()))
+        {
+            Ok(peer_id) => peer_id,
+            Err(e) => {
+                let error_msg = format!("Invalid params: expected a 'peer_id': {}", e);
+                warn!("{}", error_msg);
+                return Some(json_rpc_error_response(-32602, &error_msg, id));
+            }
+        };
+        match P2PServer::disconnect_peer_command(
+            command_tx_p2p,
+            peer_id,
+            DisconnectReason::Requested,
+        )
+        .await
+        {
+            Ok(connected) => Some(json_rpc_success_response(
+                serde_json::Value::Bool(connected),
+                id,
+            )),
+            Err(e) => {
+                let error_msg = format!("Failed to disconnect peer: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Branches connected peers are on that compete with our chain, deepest first.
+    async fn handle_debug_forks(
+        id: serde_json::Value,
+        command_tx_p2p: tokio::sync::mpsc::Sender<P2PServerCommand>,
+    ) -> Option<String> {
+        match P2PServer::get_forks_command(command_tx_p2p).await {
+            Ok(snapshot) => {
+                let result = serde_json::to_value(snapshot).unwrap_or(serde_json::Value::Null);
+                Some(json_rpc_success_response(result, id))
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to get forks: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Re-reads the node's configuration and applies the reloadable settings; replies with
+    /// the names of those that changed.
+    async fn handle_admin_reload_config(
+        id: serde_json::Value,
+        reloader: &ConfigReloader,
+    ) -> Option<String> {
+        match reloader.reload().await {
+            Ok(changed) => Some(json_rpc_success_response(serde_json::json!(changed), id)),
+            Err(e) => {
+                error!("{}", e);
+                Some(json_rpc_error_response(-32000, &e, id))
+            }
+        }
+    }
+
+    /// Params `{ index }`. Takes the chain lock for writing while the block is re-executed.
+    async fn handle_debug_trace_block_by_index(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let index = match Self::block_index_param(params, &id) {
+            Ok(index) => index,
+            Err(response) => return Some(response),
+        };
+
+        let blockchain = blockchain.write().await;
+        match blockchain.trace_block(index) {
+            Ok(trace) => Some(json_rpc_success_response(
+                serde_json::to_value(&trace).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => {
+                let error_msg = format!("Failed to trace block {}: {}", index, e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Blocks rejected on top of their parent, with the reason and how often they were
+    /// received again; most recently received first.
+    async fn handle_debug_get_bad_blocks(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let bad_blocks = blockchain.read().await.bad_blocks();
+        Some(json_rpc_success_response(
+            serde_json::to_value(&bad_blocks).unwrap_or(serde_json::Value::Null),
+            id,
+        ))
+    }
+
+    /// Params `{ count }`, optional: this node's next `count` slots as an authority, and
+    /// the proposals it made and missed since it started.
+    async fn handle_clutch_get_duties(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        #[derive(serde::Deserialize)]
+        struct DutiesParams {
+            count: Option<usize>,
+        }
+
+        let count = if params.is_null() {
+            None
+        } else {
+            match serde_json::from_value::<DutiesParams>(params) {
+                Ok(p) => p.count,
+                Err(e) => {
+                    let error_msg =
+                        format!("Invalid params: expected object with 'count' field: {}", e);
+                    warn!("{}", error_msg);
+                    return Some(json_rpc_error_response(-32602, &error_msg, id));
+                }
+            }
+        };
+        let count = count
+            .unwrap_or(DEFAULT_UPCOMING_DUTIES)
+            .min(MAX_UPCOMING_DUTIES);
+
+        let duties = blockchain
+            .read()
+            .await
+            .duties(get_current_timespan(), count);
+        Some(json_rpc_success_response(
+            serde_json::to_value(&duties).unwrap_or(serde_json::Value::Null),
+            id,
+        ))
+    }
+
+    /// No params: the validator set authoring the next block, its epoch, and the stakes in
+    /// the validator registry.
+    async fn handle_clutch_get_validators(
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        match blockchain.read().await.validators() {
+            Ok(validators) => Some(json_rpc_success_response(
+                serde_json::to_value(&validators).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => {
+                let error_msg = format!("Failed to read the validator registry: {}", e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Params `{ index }`. Blocks imported without `persist_state_diffs` are re-executed
+    /// when the debug RPCs are enabled.
+    async fn handle_clutch_get_state_diff(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+        debug_rpc_enabled: bool,
+    ) -> Option<String> {
+        let index = match Self::block_index_param(params, &id) {
+            Ok(index) => index,
+            Err(response) => return Some(response),
+        };
+
+        let stored = blockchain.read().await.get_state_diff(index);
+        let diff = match stored {
+            Ok(None) if debug_rpc_enabled => blockchain
+                .write()
+                .await
+                .trace_block(index)
+                .map(|trace| Some(StateDiff::from_trace(&trace))),
+            other => other,
+        };
+        match diff {
+            Ok(Some(diff)) => Some(json_rpc_success_response(
+                serde_json::to_value(&diff).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Ok(None) => {
+                let error_msg = format!("State diff of block {} is not stored", index);
+                warn!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to get state diff of block {}: {}", index, e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
+
+    /// Params `{ index }` of the per-block methods.
+    fn block_index_param(
+        params: serde_json::Value,
+        id: &serde_json::Value,
+    ) -> Result<usize, String> {
+        #[derive(serde::Deserialize)]
+        struct BlockIndexParams {
+            index: usize,
+        }
+
+        match serde_json::from_value::<BlockIndexParams>(params) {
+            Ok(p) => Ok(p.index),
+            Err(e) => {
+                let error_msg =
+                    format!("Invalid params: expected object with 'index' field: {}", e);
+                warn!("{}", error_msg);
+                Err(json_rpc_error_response(-32602, &error_msg, id.clone()))
+            }
+        }
+    }
+
+    /// Params `{ hash }`; null for transactions not in the chain.
+    async fn handle_debug_trace_transaction(
+        params: serde_json::Value,
+        id: serde_json::Value,
+        blockchain: &Arc<RwLock<Blockchain>>,
+    ) -> Option<String> {
+        let hash = match Self::transaction_hash_param(params, &id) {
+            Ok(hash) => hash,
+            Err(response) => return Some(response),
+        };
+
+        let blockchain = blockchain.write().await;
+        match blockchain.trace_transaction(&hash) {
+            Ok(trace) => Some(json_rpc_success_response(
+                serde_json::to_value(&trace).unwrap_or(serde_json::Value::Null),
+                id,
+            )),
+            Err(e) => {
+                let error_msg = format!("Failed to trace transaction {}: {}", hash, e);
+                error!("{}", error_msg);
+                Some(json_rpc_error_response(-32000, &error_msg, id))
+            }
+        }
+    }
 }
 
 fn json_rpc_error_response(code: i32, message: &str, id: serde_json::Value) -> String {
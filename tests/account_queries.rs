@@ -0,0 +1,76 @@
+// Account queries at an older block derive balance and nonce from the head state by
+// undoing what happened above that block.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    blocks::block_tag::{BlockTag, NamedBlock},
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+
+fn author_transfer(blockchain: &Blockchain, nonce: u64, value: u64) {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    blockchain
+        .add_transaction_to_pool(&transaction)
+        .expect("Failed to add transaction to pool");
+    blockchain
+        .author_new_block()
+        .expect("failed to author new block");
+}
+
+#[test]
+#[serial]
+fn account_at_older_block_matches_state_then() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-account-queries-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    let from = FROM_ADDRESS_KEY.to_string();
+    let to = TO_ADDRESS_KEY.to_string();
+
+    author_transfer(&blockchain, 1, 20);
+    let from_balance = blockchain.get_account_balance(&from);
+    author_transfer(&blockchain, 2, 30);
+
+    let sender_then = blockchain.get_account_at(&from, 1);
+    let recipient_then = blockchain.get_account_at(&to, 1);
+    let author_at_genesis = blockchain.get_account_at(&AUTHOR_PUBLIC_KEY.to_string(), 0);
+    let sender_now = blockchain.get_account_at(&from, 2);
+    let finalized = blockchain.resolve_block_tag(BlockTag::Named(NamedBlock::Finalized));
+    let beyond_head = blockchain.resolve_block_tag(BlockTag::Index(3));
+    let streamed: Result<Vec<usize>, String> = blockchain
+        .iter_blocks(1, 10)
+        .map(|block| block.map(|block| block.index))
+        .collect();
+    blockchain.shutdown_blockchain();
+
+    let sender_then = sender_then.expect("account query failed");
+    assert_eq!((sender_then.balance, sender_then.nonce), (from_balance, 1));
+    assert_eq!(recipient_then.expect("account query failed").balance, 20);
+    assert_eq!(author_at_genesis.expect("account query failed").balance, 0);
+    assert_eq!(sender_now.expect("account query failed").nonce, 2);
+    // A sole authority finalizes its own blocks.
+    assert_eq!(finalized, Ok(2));
+    assert!(beyond_head.is_err());
+    assert_eq!(streamed, Ok(vec![1, 2]), "the stream ends at the head");
+}
@@ -0,0 +1,62 @@
+// A backup taken while the chain is open restores to the same database.
+use clutch_node::node::{backup, blockchain::Blockchain, blocks::block::Block, database::Database};
+use serial_test::serial;
+
+const BLOCKCHAIN_NAME: &str = "clutch-node-backup-test";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+#[test]
+#[serial]
+fn an_online_backup_restores_the_chain() {
+    let dir = std::env::temp_dir().join(format!("clutch-node-backup-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("chain.backup").to_string_lossy().into_owned();
+    let restored_path = Database::db_path_in(&dir.to_string_lossy(), "restored");
+
+    let mut blockchain = Blockchain::new(
+        BLOCKCHAIN_NAME.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    for _ in 0..3 {
+        blockchain
+            .author_new_block()
+            .expect("failed to author block");
+    }
+    let head = blockchain.get_latest_block().unwrap().unwrap();
+
+    // The chain stays open: the backup reads through a secondary instance.
+    let summary = backup::backup(&Database::db_path(BLOCKCHAIN_NAME), &output, false);
+    let overwrite = backup::backup(&Database::db_path(BLOCKCHAIN_NAME), &output, false);
+    let resumed = backup::backup(&Database::db_path(BLOCKCHAIN_NAME), &output, true);
+    blockchain.shutdown_blockchain();
+
+    let restore = backup::restore(&restored_path, &output, false);
+    let restored_head = {
+        let mut db = Database::open(&restored_path);
+        let head = Block::get_latest_block(&db);
+        db.close();
+        head
+    };
+    let again = backup::restore(&restored_path, &output, false);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let summary = summary.unwrap();
+    assert_eq!(summary.head_index, Some(head.index));
+    assert!(!summary.resumed);
+    assert!(overwrite.is_err());
+    // A complete backup has nothing left to resume.
+    assert_eq!(resumed, Ok(summary.clone()));
+    assert_eq!(restore, Ok(summary));
+    assert_eq!(
+        restored_head.unwrap().map(|block| block.hash),
+        Some(head.hash)
+    );
+    assert!(again.is_err());
+}
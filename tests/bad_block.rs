@@ -0,0 +1,41 @@
+use clutch_node::node::{blockchain::Blockchain, blocks::block::Block};
+use serial_test::serial;
+
+const BLOCKCHAIN_NAME: &str = "clutch-node-bad-block-test";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const OTHER_PUBLIC_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const OTHER_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+
+#[test]
+#[serial]
+fn rejected_blocks_are_remembered_without_blocking_the_honest_one() {
+    let mut blockchain = Blockchain::new(
+        BLOCKCHAIN_NAME.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    let head = blockchain.get_latest_block().unwrap().unwrap();
+    let mut honest = Block::new_block(head.index + 1, head.hash.clone(), Vec::new());
+    let mut forged = honest.clone();
+    honest.sign(AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY);
+    forged.sign(OTHER_PUBLIC_KEY, OTHER_SECRET_KEY);
+
+    let first = blockchain.import_block(&forged);
+    let second = blockchain.import_block(&forged);
+    let bad_blocks = blockchain.bad_blocks();
+    let imported = blockchain.import_block(&honest);
+    blockchain.shutdown_blockchain();
+
+    assert!(first.unwrap_err().contains("author verification failed"));
+    assert!(second.unwrap_err().contains("already rejected"));
+    assert_eq!(bad_blocks.len(), 1);
+    assert_eq!(bad_blocks[0].author, OTHER_PUBLIC_KEY);
+    assert_eq!(bad_blocks[0].repeats, 1);
+    assert_eq!(imported, Ok(()));
+}
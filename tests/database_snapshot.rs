@@ -0,0 +1,100 @@
+// Peers are served block data from RocksDB snapshots so imports never block them; a
+// snapshot must keep seeing the state it was taken at, and a reader must not outlive close.
+// Bulk sync buffers writes in a batch that reads see immediately, snapshots only once flushed,
+// and that a failed flush leaves buffered.
+use clutch_node::node::database::{Database, ReadStore};
+
+const DB_NAME: &str = "clutch-node-test-db-snapshot";
+
+#[test]
+fn snapshot_reads_are_isolated_from_later_writes() {
+    let mut db = Database::new_db(DB_NAME);
+    db.put("block", b"block_1", b"before")
+        .expect("put should succeed");
+    let reader = db.reader().expect("reader on open database");
+
+    let seen = reader.with_snapshot(|snapshot| {
+        db.put("block", b"block_1", b"after")
+            .expect("put should succeed");
+        db.put("block", b"block_2", b"new")
+            .expect("put should succeed");
+        Ok::<_, String>((
+            snapshot.get("block", b"block_1")?,
+            snapshot.get("block", b"block_2")?,
+        ))
+    });
+    let live = ReadStore::get(&db, "block", b"block_1");
+
+    db.close();
+    let after_close = reader.with_snapshot(|snapshot| snapshot.get("block", b"block_1"));
+    db.delete_database(DB_NAME).ok();
+
+    assert_eq!(seen, Ok((Some(b"before".to_vec()), None)));
+    assert_eq!(live, Ok(Some(b"after".to_vec())));
+    assert!(
+        after_close.is_err(),
+        "reader must not reopen a closed database"
+    );
+}
+
+#[test]
+fn batched_writes_are_readable_but_only_snapshotted_once_flushed() {
+    const BATCH_DB_NAME: &str = "clutch-node-test-db-batch";
+    let mut db = Database::new_db(BATCH_DB_NAME);
+    db.put("state", b"ride_request_a", b"old")
+        .expect("put should succeed");
+    let reader = db.reader().expect("reader on open database");
+
+    db.begin_batch();
+    db.write(vec![
+        ("state", b"ride_request_a".as_slice(), None),
+        (
+            "state",
+            b"ride_request_b".as_slice(),
+            Some(b"new".as_slice()),
+        ),
+    ])
+    .expect("buffered write should succeed");
+
+    let buffered_get = db.get("state", b"ride_request_b");
+    let buffered_scan = db.prefix_scan("state", b"ride_request_");
+    let snapshot_before = reader.with_snapshot(|snapshot| snapshot.get("state", b"ride_request_a"));
+    let flushed = db.end_batch();
+    let snapshot_after = reader.with_snapshot(|snapshot| snapshot.get("state", b"ride_request_b"));
+
+    db.close();
+    db.delete_database(BATCH_DB_NAME).ok();
+
+    assert_eq!(buffered_get, Ok(Some(b"new".to_vec())));
+    assert_eq!(
+        buffered_scan,
+        Ok(vec![(b"ride_request_b".to_vec(), b"new".to_vec())])
+    );
+    assert_eq!(snapshot_before, Ok(Some(b"old".to_vec())));
+    assert_eq!(flushed, Ok(2));
+    assert_eq!(snapshot_after, Ok(Some(b"new".to_vec())));
+}
+
+#[test]
+fn a_failed_flush_keeps_the_batch_buffered() {
+    const FAILED_DB_NAME: &str = "clutch-node-test-db-failed-flush";
+    let mut db = Database::new_db(FAILED_DB_NAME);
+
+    db.begin_batch();
+    db.put("state", b"ride_request_a", b"new")
+        .expect("buffered put should succeed");
+    db.put("no_such_column_family", b"key", b"value")
+        .expect("buffered put should succeed");
+
+    let flushed = db.flush_batch();
+    let after_failure = db.get("state", b"ride_request_a");
+    db.discard_batch();
+    let after_discard = db.get("state", b"ride_request_a");
+
+    db.close();
+    db.delete_database(FAILED_DB_NAME).ok();
+
+    assert!(flushed.is_err());
+    assert_eq!(after_failure, Ok(Some(b"new".to_vec())));
+    assert_eq!(after_discard, Ok(None));
+}
@@ -0,0 +1,108 @@
+// Finalized eras frozen into era files can be pruned from the database and still read,
+// replayed by a rollback, and imported by another node instead of syncing the blocks
+// from peers.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    blocks::era::{self, EraArchive},
+};
+use serial_test::serial;
+use std::sync::Arc;
+
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+fn blockchain(name: &str) -> Blockchain {
+    Blockchain::new(
+        name.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    )
+}
+
+#[test]
+#[serial]
+fn pruned_eras_are_read_from_the_archive_and_imported_elsewhere() {
+    let dir = std::env::temp_dir().join(format!("clutch-node-era-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let archive = Arc::new(EraArchive::with_blocks_per_era(&dir, "clutch-devnet", 4).unwrap());
+
+    let mut source = blockchain("clutch-node-era-source-test");
+    for _ in 0..9 {
+        source.author_new_block().expect("failed to author block");
+    }
+    let hashes: Vec<String> = (0..=9)
+        .map(|index| source.get_block_hash(index).unwrap().unwrap())
+        .collect();
+
+    // Blocks 8 and 9 don't fill era 2 yet.
+    let report = source.freeze_blocks(&archive, true).unwrap();
+    era::install_archive(Arc::clone(&archive));
+    let again = source.freeze_blocks(&archive, true).unwrap();
+    let read_back: Vec<String> = source
+        .iter_blocks(0, 10)
+        .map(|block| block.unwrap().hash)
+        .collect();
+    let by_index = source.get_blocks_by_indexes(vec![5, 9]).unwrap();
+    source.shutdown_blockchain();
+
+    let mut target = blockchain("clutch-node-era-target-test");
+    let imported = target.import_era(&archive, 3);
+    let target_head = target.get_latest_block().unwrap().unwrap();
+    target.shutdown_blockchain();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let eras: Vec<usize> = report.frozen.iter().map(|info| info.era).collect();
+    assert_eq!(eras, vec![0, 1]);
+    assert_eq!(report.pruned, 7);
+    assert!(again.frozen.is_empty());
+    assert_eq!(again.pruned, 0);
+    assert_eq!(read_back, hashes);
+    assert_eq!(by_index[0].hash, hashes[5]);
+    assert_eq!(by_index[1].hash, hashes[9]);
+    assert_eq!(imported, Ok(7));
+    assert_eq!(target_head.hash, hashes[7]);
+}
+
+#[test]
+#[serial]
+fn rollback_and_rebuild_replay_pruned_blocks_from_the_archive() {
+    let dir = std::env::temp_dir().join(format!(
+        "clutch-node-era-rollback-test-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let archive = Arc::new(EraArchive::with_blocks_per_era(&dir, "clutch-devnet", 4).unwrap());
+
+    let mut blockchain = blockchain("clutch-node-era-rollback-test");
+    for _ in 0..9 {
+        blockchain
+            .author_new_block()
+            .expect("failed to author block");
+    }
+    let hashes: Vec<String> = (0..=9)
+        .map(|index| blockchain.get_block_hash(index).unwrap().unwrap())
+        .collect();
+    let report = blockchain.freeze_blocks(&archive, true).unwrap();
+    era::install_archive(Arc::clone(&archive));
+
+    let removed = blockchain.rollback_to(8);
+    let rebuilt = blockchain.rebuild_indexes();
+    let read_back: Vec<String> = blockchain
+        .iter_blocks(0, 10)
+        .map(|block| block.unwrap().hash)
+        .collect();
+    let again = blockchain.freeze_blocks(&archive, true).unwrap();
+    blockchain.shutdown_blockchain();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(report.pruned, 7);
+    assert_eq!(removed, Ok(1));
+    assert_eq!(rebuilt, Ok(8));
+    assert_eq!(read_back, hashes[..=8]);
+    assert_eq!(again.pruned, 0, "replayed blocks stay pruned");
+}
@@ -0,0 +1,35 @@
+use clutch_node::node::{blockchain::Blockchain, blocks::block::Block};
+use serial_test::serial;
+
+const BLOCKCHAIN_NAME: &str = "clutch-node-future-block-test";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+#[test]
+#[serial]
+fn blocks_ahead_of_the_head_or_the_clock_are_future_blocks() {
+    let mut blockchain = Blockchain::new(
+        BLOCKCHAIN_NAME.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    let head = blockchain.get_latest_block().unwrap().unwrap();
+
+    let mut next = Block::new_block(head.index + 1, head.hash.clone(), Vec::new());
+    next.sign(AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY);
+    let orphan = Block::new_block(head.index + 2, "unknown".to_string(), Vec::new());
+    let mut early = next.clone();
+    early.timestamp += 600;
+
+    let results = [&next, &orphan, &early].map(|block| blockchain.is_future_block(block));
+    let rejected = blockchain.import_block(&early);
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(results, [Ok(false), Ok(true), Ok(true)]);
+    assert!(rejected.is_err());
+}
@@ -0,0 +1,110 @@
+use clutch_node::node::{
+    blockchain::Blockchain,
+    blocks::block::Block,
+    hardfork::{Hardfork, HardforkActivation, HardforkSchedule},
+};
+use serial_test::serial;
+
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+fn new_blockchain(name: &str, fork: Hardfork, block: usize) -> Blockchain {
+    let hardforks = HardforkSchedule::try_from(vec![HardforkActivation {
+        fork,
+        block: Some(block),
+        timestamp: None,
+    }])
+    .expect("valid schedule");
+    Blockchain::new(
+        name.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    )
+    .with_hardforks(hardforks)
+}
+
+/// An empty block on the head, stamped a second before it.
+fn backdated_block(blockchain: &Blockchain) -> Block {
+    let head = blockchain.get_latest_block().unwrap().unwrap();
+    let mut block = Block::new_block(head.index + 1, head.hash, Vec::new());
+    block.timestamp = head.timestamp - 1;
+    block.sign(AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY);
+    block
+}
+
+#[test]
+#[serial]
+fn backdated_blocks_are_rejected_once_monotonic_timestamps_activates() {
+    let mut blockchain = new_blockchain(
+        "clutch-node-hardfork-timestamps-test",
+        Hardfork::MonotonicTimestamps,
+        2,
+    );
+
+    let before_fork = blockchain.import_block(&backdated_block(&blockchain));
+    let after_fork = blockchain.import_block(&backdated_block(&blockchain));
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(before_fork, Ok(()));
+    assert!(after_fork
+        .unwrap_err()
+        .contains("earlier than the parent's"));
+}
+
+#[test]
+#[serial]
+fn empty_blocks_stop_minting_rewards_once_the_fork_activates() {
+    let mut blockchain = new_blockchain(
+        "clutch-node-hardfork-reward-test",
+        Hardfork::NoEmptyBlockReward,
+        2,
+    );
+
+    for _ in 0..3 {
+        blockchain
+            .author_new_block()
+            .expect("failed to author new block");
+    }
+    let author_balance = blockchain.get_account_balance(&AUTHOR_PUBLIC_KEY.to_string());
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(author_balance, BLOCK_REWARD_AMOUNT);
+}
+
+#[test]
+#[serial]
+fn blocks_commit_to_their_roots_once_header_roots_activates() {
+    let mut blockchain =
+        new_blockchain("clutch-node-hardfork-roots-test", Hardfork::HeaderRoots, 2);
+
+    let before_fork = blockchain
+        .author_new_block()
+        .expect("failed to author block 1");
+    let after_fork = blockchain
+        .author_new_block()
+        .expect("failed to author block 2");
+
+    let mut without_roots = Block::new_block(3, after_fork.hash.clone(), Vec::new());
+    without_roots.sign(AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY);
+    let rootless = blockchain.import_block(&without_roots);
+
+    let mut forged = without_roots.clone();
+    forged.transactions_root = after_fork.transactions_root.clone();
+    forged.receipts_root = "00".repeat(32);
+    forged.hash = forged.calculate_hash();
+    forged.sign(AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY);
+    let forged_receipts = blockchain.import_block(&forged);
+    blockchain.shutdown_blockchain();
+
+    assert!(before_fork.transactions_root.is_empty());
+    assert!(!after_fork.transactions_root.is_empty() && !after_fork.receipts_root.is_empty());
+    assert_eq!(after_fork.hash, after_fork.calculate_hash());
+    assert!(rootless.unwrap_err().contains("does not commit"));
+    assert!(forged_receipts.unwrap_err().contains("receipts root"));
+}
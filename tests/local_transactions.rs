@@ -0,0 +1,51 @@
+use clutch_node::node::{
+    blockchain::Blockchain,
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+#[test]
+#[serial]
+fn local_transactions_are_tracked_until_included() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-local-tx-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        1,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value: 20,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+
+    let added = blockchain.add_local_transaction(&transaction);
+    let local = blockchain.get_local_transactions();
+    let pool = blockchain.get_transactions_from_pool();
+    let block = blockchain.author_new_block();
+    let local_after = blockchain.get_local_transactions();
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(added, Ok(()));
+    assert_eq!(local.expect("local transactions").len(), 1);
+    assert_eq!(pool.expect("pool holds only transactions").len(), 1);
+    assert_eq!(
+        block.expect("failed to author block").transactions[0].hash,
+        transaction.hash
+    );
+    assert!(local_after.expect("local transactions").is_empty());
+}
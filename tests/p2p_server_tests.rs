@@ -2,19 +2,19 @@ use clutch_node::node::blockchain::Blockchain;
 use clutch_node::node::p2p_server::commands::DirectMessageType;
 use clutch_node::node::p2p_server::get_block_bodies::GetBlockBodies;
 use clutch_node::node::p2p_server::get_block_header::GetBlockHeaders;
-use clutch_node::node::p2p_server::{GossipMessageType, P2PServer, P2PServerCommand};
+use clutch_node::node::p2p_server::{GossipMessageType, P2PConfig, P2PServer, P2PServerCommand};
 use clutch_node::node::rlp_encoding::encode;
 use tracing::info;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 
 async fn setup_p2p_server(
     topic_name: &str,
     listen_addrs: &[&str],
     peer_addrs: &[&str],
-    blockchain: Arc<Mutex<Blockchain>>,
+    blockchain: Arc<RwLock<Blockchain>>,
 ) -> (
     Arc<Mutex<P2PServer>>,
     tokio::sync::mpsc::Sender<P2PServerCommand>,
@@ -60,7 +60,7 @@ async fn test_p2p_server_gossip_message() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -95,7 +95,7 @@ async fn test_p2p_server_gossip_message() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
 }
 
 #[tokio::test]
@@ -104,7 +104,7 @@ async fn test_p2p_server_connected_peers() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -177,7 +177,7 @@ async fn test_p2p_server_connected_peers() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
 }
 
 #[tokio::test]
@@ -185,7 +185,7 @@ async fn test_p2p_server_get_local_peer_id() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -218,7 +218,7 @@ async fn test_p2p_server_get_local_peer_id() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
 }
 
 #[tokio::test]
@@ -227,7 +227,7 @@ async fn test_p2p_server_handshake_direct_message() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -256,7 +256,7 @@ async fn test_p2p_server_handshake_direct_message() {
     info!("peer_id server 1: {:?}", peer_id_server1);
     info!("peer_id server 2: {:?}", peer_id_server2);
 
-    let handshake =  blockchain.lock().await.handshake().unwrap();
+    let handshake = blockchain.read().await.handshake().unwrap();
     let encoded_handshake = encode(&handshake);
 
     // Send a direct message from server2 to server1
@@ -277,7 +277,7 @@ async fn test_p2p_server_handshake_direct_message() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
 }
 
 #[tokio::test]
@@ -286,7 +286,7 @@ async fn test_p2p_server_get_block_headers_direct_message() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -319,6 +319,7 @@ async fn test_p2p_server_get_block_headers_direct_message() {
         start_block_index: 0,
         skip: 0,
         limit: 100,
+        start_block_hash: String::new(),
     };
 
     let encoded_get_block_headers = encode(&get_block_headers);
@@ -341,7 +342,7 @@ async fn test_p2p_server_get_block_headers_direct_message() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
 }
 
 #[tokio::test]
@@ -350,7 +351,7 @@ async fn test_p2p_server_get_block_boodies_direct_message() {
     let topic_name = "test-topic";
 
     // Initialize blockchain
-    let blockchain = Arc::new(Mutex::new(initialize_blockchain(
+    let blockchain = Arc::new(RwLock::new(initialize_blockchain(
         "clutch-node-test-1".to_string(),
     )));
 
@@ -403,5 +404,53 @@ async fn test_p2p_server_get_block_boodies_direct_message() {
     // Shut down the servers
     drop(command_tx1);
     drop(command_tx2);
-    blockchain.lock().await.shutdown_blockchain();
+    blockchain.write().await.shutdown_blockchain();
+}
+
+#[tokio::test]
+async fn test_p2p_server_accepts_several_listeners_and_dns_addresses() {
+    let mut config = P2PConfig::new(
+        "test-topic",
+        &["/ip4/127.0.0.1/tcp/0", "/ip4/127.0.0.1/udp/0/quic-v1"],
+        &["/dns4/localhost/tcp/4999"],
+    );
+    config.external_addrs = vec!["/dns4/node.example.com/tcp/4001".to_string()];
+    config.websocket_listen_addrs = vec!["/ip4/127.0.0.1/tcp/0/ws".to_string()];
+    assert!(P2PServer::with_config(config.clone()).is_ok());
+
+    config.external_addrs = vec!["not-a-multiaddr".to_string()];
+    let error = P2PServer::with_config(config).err().unwrap();
+    assert!(error.to_string().contains("external"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_p2p_server_private_network_requires_a_valid_swarm_key() {
+    let key_path = std::env::temp_dir().join("clutch-test-swarm.key");
+    std::fs::write(
+        &key_path,
+        "/key/swarm/psk/1.0.0/\n/base16/\n\
+         6189c5cf0b87fb800c1a9feeda73c6ab5e998db48fb9e6a978575c770ceef683\n",
+    )
+    .unwrap();
+
+    let mut config = P2PConfig::new("test-topic", &["/ip4/127.0.0.1/tcp/0"], &[]);
+    config.swarm_key_path = Some(key_path.clone());
+    assert!(P2PServer::with_config(config.clone()).is_ok());
+
+    config
+        .listen_addrs
+        .push("/ip4/127.0.0.1/udp/0/quic-v1".to_string());
+    let error = P2PServer::with_config(config.clone()).err().unwrap();
+    assert!(error.to_string().contains("QUIC"), "{}", error);
+
+    config.listen_addrs.pop();
+    config.websocket_listen_addrs = vec!["/ip4/127.0.0.1/tcp/0/ws".to_string()];
+    let error = P2PServer::with_config(config.clone()).err().unwrap();
+    assert!(error.to_string().contains("WebSocket"), "{}", error);
+
+    config.websocket_listen_addrs.clear();
+    std::fs::write(&key_path, "not a swarm key").unwrap();
+    let error = P2PServer::with_config(config).err().unwrap();
+    std::fs::remove_file(&key_path).ok();
+    assert!(error.to_string().contains("Invalid swarm key"), "{}", error);
 }
@@ -0,0 +1,105 @@
+// Registered plugins hear of pooled transactions, imported blocks and rollbacks, in the
+// order they happen.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    blocks::block::Block,
+    plugin::NodePlugin,
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+
+struct EventLog(Arc<Mutex<Vec<String>>>);
+
+impl NodePlugin for EventLog {
+    fn name(&self) -> &str {
+        "event-log"
+    }
+
+    fn on_block_imported(&self, block: &Block) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("block {}", block.index));
+    }
+
+    fn on_reorg(&self, common_index: usize, removed_hashes: &[String]) {
+        self.0.lock().unwrap().push(format!(
+            "reorg to {} removing {}",
+            common_index,
+            removed_hashes.len()
+        ));
+    }
+
+    fn on_tx_pool_insert(&self, transaction: &Transaction) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("pooled nonce {}", transaction.nonce));
+    }
+}
+
+fn transfer_transaction(nonce: u64, value: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    transaction
+}
+
+#[test]
+#[serial]
+fn plugins_hear_of_chain_events() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut blockchain = Blockchain::new(
+        "clutch-node-plugin-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    )
+    .with_plugin(EventLog(Arc::clone(&events)));
+
+    for nonce in 1..=2 {
+        let transaction = transfer_transaction(nonce, 10);
+        blockchain
+            .add_transaction_to_pool(&transaction)
+            .expect("Failed to add transaction to pool");
+        // Re-announcing a pooled transaction isn't a new insert.
+        blockchain
+            .add_transaction_to_pool(&transaction)
+            .expect("Failed to add transaction to pool");
+        blockchain
+            .author_new_block()
+            .expect("failed to author new block");
+    }
+    let removed = blockchain.rollback_to(0);
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(removed, Ok(2));
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "pooled nonce 1",
+            "block 1",
+            "pooled nonce 2",
+            "block 2",
+            "reorg to 0 removing 2",
+        ]
+    );
+}
@@ -0,0 +1,125 @@
+// Remote transactions that wait in the pool past the `PoolExpiry` are evicted and
+// reported to plugins; local ones stay. A pool at its capacity refuses new ones.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    plugin::NodePlugin,
+    transactions::{
+        function_call::FunctionCall,
+        transaction::Transaction,
+        transaction_pool::{EvictionReason, PoolExpiry},
+        transfer::Transfer,
+    },
+};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+struct Evictions(Arc<Mutex<Vec<(u64, EvictionReason)>>>);
+
+impl NodePlugin for Evictions {
+    fn name(&self) -> &str {
+        "evictions"
+    }
+
+    fn on_tx_pool_evict(&self, transaction: &Transaction, reason: EvictionReason) {
+        self.0.lock().unwrap().push((transaction.nonce, reason));
+    }
+}
+
+fn queued_transaction(nonce: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value: 10,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    transaction
+}
+
+#[test]
+#[serial]
+fn remote_transactions_expire_after_the_configured_blocks() {
+    let evictions = Arc::new(Mutex::new(Vec::new()));
+    let mut blockchain = Blockchain::new(
+        "clutch-node-pool-expiry-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    )
+    .with_pool_expiry(PoolExpiry {
+        max_age_secs: 0,
+        max_blocks: 2,
+    })
+    .with_plugin(Evictions(Arc::clone(&evictions)));
+
+    // Nonce gaps keep both queued, so no block includes them.
+    blockchain
+        .add_transaction_to_pool(&queued_transaction(3))
+        .expect("Failed to add remote transaction");
+    blockchain
+        .add_local_transaction(&queued_transaction(5))
+        .expect("Failed to add local transaction");
+
+    blockchain
+        .author_new_block()
+        .expect("failed to author block 1");
+    let after_one_block = blockchain.evict_expired_transactions();
+    blockchain
+        .author_new_block()
+        .expect("failed to author block 2");
+    let after_two_blocks = blockchain.evict_expired_transactions();
+    let pool = blockchain.get_transactions_from_pool();
+    blockchain.shutdown_blockchain();
+
+    assert!(after_one_block.expect("eviction failed").is_empty());
+    let evicted = after_two_blocks.expect("eviction failed");
+    assert_eq!(evicted.len(), 1);
+    assert_eq!(evicted[0].0.nonce, 3);
+    assert_eq!(
+        *evictions.lock().unwrap(),
+        vec![(3, EvictionReason::MaxBlocks)]
+    );
+    let pool = pool.expect("failed to read pool");
+    assert_eq!(pool.len(), 1);
+    assert_eq!(pool[0].nonce, 5);
+}
+
+#[test]
+#[serial]
+fn a_full_pool_refuses_new_transactions() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-pool-capacity-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    )
+    .with_pool_capacity(2);
+
+    let first = blockchain.add_transaction_to_pool(&queued_transaction(3));
+    let second = blockchain.add_transaction_to_pool(&queued_transaction(4));
+    let third = blockchain.add_transaction_to_pool(&queued_transaction(5));
+    // Pooling a transaction again doesn't take more room.
+    let again = blockchain.add_transaction_to_pool(&queued_transaction(3));
+    let pool = blockchain.get_transactions_from_pool();
+    blockchain.shutdown_blockchain();
+
+    assert!(first.is_ok() && second.is_ok() && again.is_ok());
+    assert!(third.unwrap_err().contains("pool is full"));
+    assert_eq!(pool.expect("failed to read pool").len(), 2);
+}
@@ -0,0 +1,112 @@
+use clutch_node::node::{
+    blockchain::Blockchain,
+    blocks::block::Block,
+    signature_keys::SignatureKeys,
+    transactions::{
+        function_call::FunctionCall,
+        transaction::Transaction,
+        transaction_pool::{MAX_NONCE_GAP, MAX_QUEUED_PER_SENDER},
+        transfer::Transfer,
+    },
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+fn transfer(nonce: u64) -> Transaction {
+    transfer_from(FROM_ADDRESS_KEY, FROM_SECRET_KEY, nonce)
+}
+
+fn transfer_from(from: &str, secret_key: &str, nonce: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        from.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value: 20,
+        }),
+    );
+    transaction.sign(secret_key);
+    transaction
+}
+
+fn nonces(block: Result<Block, String>) -> Vec<u64> {
+    let block = block.expect("failed to author block");
+    block.transactions.iter().map(|tx| tx.nonce).collect()
+}
+
+#[test]
+#[serial]
+fn queued_transactions_are_promoted_when_the_gap_closes() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-queued-tx-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+
+    let queued = blockchain.add_transaction_to_pool(&transfer(2));
+    let too_far = blockchain.add_transaction_to_pool(&transfer(2 + MAX_NONCE_GAP));
+    let waiting = blockchain.author_new_block();
+    let queued_status = blockchain
+        .get_pool_content()
+        .map(|content| content.status());
+    let gap_closed = blockchain.add_transaction_to_pool(&transfer(1));
+    let first = blockchain.author_new_block();
+    let second = blockchain.author_new_block();
+    let spent = blockchain.add_transaction_to_pool(&transfer(1));
+    let pool = blockchain.get_transactions_from_pool();
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(queued, Ok(()));
+    assert!(too_far.unwrap_err().contains("ahead of the next nonce"));
+    assert!(nonces(waiting).is_empty(), "nonce 2 waits for nonce 1");
+    let queued_status = queued_status.expect("pool content");
+    assert_eq!((queued_status.pending, queued_status.queued), (0, 1));
+    assert_eq!(gap_closed, Ok(()));
+    assert_eq!(nonces(first), [1]);
+    assert_eq!(nonces(second), [2]);
+    assert!(spent.unwrap_err().contains("Incorrect nonce"));
+    assert!(pool.expect("pool").is_empty());
+}
+
+#[test]
+#[serial]
+fn only_funded_senders_queue_and_each_only_so_many() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-queued-tx-limit-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+
+    let fresh = SignatureKeys::generate_new_keypair();
+    let unfunded = blockchain.add_transaction_to_pool(&transfer_from(
+        &fresh.address_key,
+        &fresh.secret_key,
+        2,
+    ));
+    let admitted: Vec<Result<(), String>> = (0..MAX_QUEUED_PER_SENDER as u64)
+        .map(|gap| blockchain.add_transaction_to_pool(&transfer(2 + gap)))
+        .collect();
+    let over_cap = blockchain.add_transaction_to_pool(&transfer(2 + MAX_QUEUED_PER_SENDER as u64));
+    let pending = blockchain.add_transaction_to_pool(&transfer(1));
+    blockchain.shutdown_blockchain();
+
+    assert!(unfunded.unwrap_err().contains("holds no balance"));
+    assert!(admitted.iter().all(Result::is_ok), "{:?}", admitted);
+    assert!(over_cap.unwrap_err().contains("queued transactions"));
+    assert_eq!(pending, Ok(()), "the cap only counts queued transactions");
+}
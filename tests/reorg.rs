@@ -0,0 +1,105 @@
+// Switching to a peer's branch: the whole branch is validated on top of the common
+// ancestor before anything of ours is unwound, it has to be heavier than our chain, and
+// blocks a majority of the authorities built on are never given up.
+use clutch_node::node::{
+    blockchain::Blockchain, blocks::block::Block, time_utils::get_current_timespan,
+};
+use serial_test::serial;
+
+const BLOCKCHAIN_NAME: &str = "clutch-node-reorg-test";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const OTHER_PUBLIC_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const OTHER_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+/// Two authorities take turns in 30 second slots, the author in even ones.
+const STEP_DURATION: u64 = 30;
+
+/// A block on `parent` in the past slot `slot`, signed with the given keys.
+fn block_in_slot(parent: &Block, slot: u64, (public_key, secret_key): (&str, &str)) -> Block {
+    let mut block = Block::new_block(parent.index + 1, parent.hash.clone(), Vec::new());
+    block.timestamp = slot * STEP_DURATION;
+    block.hash = block.calculate_hash();
+    block.sign(public_key, secret_key);
+    block
+}
+
+fn slot_author(slot: u64) -> (&'static str, &'static str) {
+    [
+        (AUTHOR_PUBLIC_KEY, AUTHOR_SECRET_KEY),
+        (OTHER_PUBLIC_KEY, OTHER_SECRET_KEY),
+    ][slot as usize % 2]
+}
+
+fn head(blockchain: &Blockchain) -> (usize, String) {
+    let head = blockchain.get_latest_block().unwrap().unwrap();
+    (head.index, head.hash)
+}
+
+#[test]
+#[serial]
+fn only_a_valid_heavier_branch_above_the_finalized_block_replaces_ours() {
+    let mut blockchain = Blockchain::new(
+        BLOCKCHAIN_NAME.to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string(), OTHER_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    let first_slot = get_current_timespan() / STEP_DURATION - 100;
+    let genesis = blockchain.get_latest_block().unwrap().unwrap();
+
+    // Our chain: two blocks by one authority alone, so neither is finalized.
+    let ours_1 = block_in_slot(&genesis, first_slot, slot_author(first_slot));
+    let ours_2 = block_in_slot(&ours_1, first_slot + 2, slot_author(first_slot + 2));
+    let imported = [&ours_1, &ours_2]
+        .iter()
+        .map(|block| blockchain.import_block(block))
+        .collect::<Result<Vec<_>, _>>();
+    let ours = head(&blockchain);
+
+    // As long as ours, built by the other authority.
+    let level_1 = block_in_slot(&genesis, first_slot + 1, slot_author(first_slot + 1));
+    let level_2 = block_in_slot(&level_1, first_slot + 3, slot_author(first_slot + 3));
+    let level = blockchain.switch_to_branch(0, &[level_1, level_2]);
+    let after_level = head(&blockchain);
+
+    // Heavier, but its last block is signed out of turn.
+    let forged_2 = block_in_slot(&ours_1, first_slot + 3, slot_author(first_slot + 3));
+    let forged_3 = block_in_slot(&forged_2, first_slot + 5, slot_author(first_slot + 4));
+    let forged = blockchain.switch_to_branch(1, &[forged_2, forged_3]);
+    let after_forged = head(&blockchain);
+
+    // Heavier and valid: the other authority takes over from block 1.
+    let branch_2 = block_in_slot(&ours_1, first_slot + 3, slot_author(first_slot + 3));
+    let branch_3 = block_in_slot(&branch_2, first_slot + 4, slot_author(first_slot + 4));
+    let branch_head = (3, branch_3.hash.clone());
+    let switched = blockchain.switch_to_branch(1, &[branch_2, branch_3]);
+    let after_switch = head(&blockchain);
+
+    // Blocks 2 and 3 were built by both authorities, so block 2 is now final.
+    let late_2 = block_in_slot(&ours_1, first_slot + 5, slot_author(first_slot + 5));
+    let late_3 = block_in_slot(&late_2, first_slot + 6, slot_author(first_slot + 6));
+    let late_4 = block_in_slot(&late_3, first_slot + 7, slot_author(first_slot + 7));
+    let below_finalized = blockchain.switch_to_branch(1, &[late_2, late_3, late_4]);
+    let check_below = blockchain.check_reorg(1);
+    let check_at = blockchain.check_reorg(2);
+    let after_refused = head(&blockchain);
+    blockchain.shutdown_blockchain();
+
+    assert!(imported.is_ok());
+    assert!(level.unwrap_err().contains("no heavier"));
+    assert_eq!(after_level, ours);
+    assert!(forged
+        .unwrap_err()
+        .contains("Not switching to the branch above block 1"));
+    assert_eq!(after_forged, ours);
+    assert_eq!(switched, Ok(1));
+    assert_eq!(after_switch, branch_head);
+    assert!(below_finalized.unwrap_err().contains("finalized"));
+    assert!(check_below.is_err());
+    assert_eq!(check_at, Ok(()));
+    assert_eq!(after_refused, branch_head);
+}
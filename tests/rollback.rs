@@ -0,0 +1,214 @@
+// Rolling back removes the blocks above the target and rebuilds state as it was at the
+// target; rebuilding indexes replays the whole stored chain the same way. Either way the
+// handshake snapshot peers are answered from moves with the head.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    reorg::ReorgPolicy,
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+
+fn transfer_transaction(nonce: u64, value: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    transaction
+}
+
+fn author_transfer(blockchain: &Blockchain, nonce: u64, value: u64) {
+    blockchain
+        .add_transaction_to_pool(&transfer_transaction(nonce, value))
+        .expect("Failed to add transaction to pool");
+    blockchain
+        .author_new_block()
+        .expect("failed to author new block");
+}
+
+#[test]
+#[serial]
+fn rollback_restores_state_at_target_block() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-rollback-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    let from = FROM_ADDRESS_KEY.to_string();
+    let to = TO_ADDRESS_KEY.to_string();
+
+    author_transfer(&blockchain, 1, 20);
+    let from_balance = blockchain.get_account_balance(&from);
+    author_transfer(&blockchain, 2, 30);
+    author_transfer(&blockchain, 3, 40);
+
+    let beyond_head = blockchain.rollback_to(4);
+    let removed = blockchain.rollback_to(1);
+    let head = blockchain.get_latest_block().expect("db read failed");
+    let to_balance = blockchain.get_account_balance(&to);
+    let author_balance = blockchain.get_account_balance(&AUTHOR_PUBLIC_KEY.to_string());
+    let nonce = blockchain.get_current_nonce(&from);
+    let removed_block = blockchain.get_blocks_with_limit_and_skip(2, 0, 1);
+    let rolled_back_effects = blockchain.get_block_balance_effects(2);
+    let rolled_back_from_balance = blockchain.get_account_balance(&from);
+
+    // The next nonce after the target block is accepted again.
+    author_transfer(&blockchain, 2, 5);
+    let new_head = blockchain.get_latest_block().expect("db read failed");
+    blockchain.shutdown_blockchain();
+
+    assert!(beyond_head.is_err());
+    assert_eq!(removed, Ok(2));
+    assert_eq!(head.expect("chain has a head").index, 1);
+    assert_eq!(rolled_back_from_balance, from_balance);
+    assert_eq!(to_balance, 20);
+    assert_eq!(author_balance, BLOCK_REWARD_AMOUNT);
+    assert_eq!(nonce, Ok(1));
+    assert!(removed_block.expect("db read failed").is_empty());
+    assert!(rolled_back_effects.is_empty());
+    assert_eq!(new_head.expect("chain has a head").index, 2);
+}
+
+#[test]
+#[serial]
+fn rollbacks_deeper_than_the_reorg_limit_need_force() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-reorg-limit-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    )
+    .with_reorg_policy(ReorgPolicy {
+        max_depth: 1,
+        alert_depth: 1,
+    });
+
+    author_transfer(&blockchain, 1, 20);
+    author_transfer(&blockchain, 2, 30);
+    author_transfer(&blockchain, 3, 40);
+
+    let within_limit = blockchain.rollback_to(2);
+    let too_deep = blockchain.rollback_to(0);
+    let forced = blockchain.force_rollback_to(0);
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(within_limit, Ok(1));
+    assert!(too_deep.unwrap_err().contains("max_reorg_depth"));
+    assert_eq!(forced, Ok(2));
+}
+
+#[test]
+#[serial]
+fn rebuilt_indexes_match_the_imported_chain() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-rebuild-indexes-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    let from = FROM_ADDRESS_KEY.to_string();
+
+    author_transfer(&blockchain, 1, 20);
+    author_transfer(&blockchain, 2, 30);
+    let from_balance = blockchain.get_account_balance(&from);
+    let effects = blockchain.get_account_balance_effects(&from, 10, 0).len();
+
+    let head = blockchain.rebuild_indexes();
+    let rebuilt_balance = blockchain.get_account_balance(&from);
+    let rebuilt_effects = blockchain.get_account_balance_effects(&from, 10, 0).len();
+    let nonce = blockchain.get_current_nonce(&from);
+    let compacted = blockchain.compact_database();
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(head, Ok(2));
+    assert_eq!(rebuilt_balance, from_balance);
+    assert_eq!(rebuilt_effects, effects);
+    assert_eq!(nonce, Ok(2));
+    assert!(compacted.is_ok());
+}
+
+#[test]
+#[serial]
+fn handshake_snapshot_follows_the_head() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-handshake-snapshot-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    let snapshot = blockchain.handshake_snapshot();
+    let at_genesis = snapshot.get().map(|handshake| handshake.latest_block_index);
+
+    author_transfer(&blockchain, 1, 20);
+    author_transfer(&blockchain, 2, 30);
+    let after_imports = snapshot.get().map(|handshake| handshake.latest_block_hash);
+    let head_hash = blockchain.get_block_hash(2);
+    let rolled_back = blockchain.rollback_to(1);
+    let after_rollback = snapshot.get().map(|handshake| handshake.latest_block_index);
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(at_genesis, Some(0));
+    assert_eq!(after_imports, head_hash.expect("block 2 hash"));
+    assert_eq!(rolled_back, Ok(1));
+    assert_eq!(after_rollback, Some(1));
+}
+
+#[test]
+#[serial]
+fn stored_headers_match_their_blocks_after_a_rollback() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-stored-headers-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    author_transfer(&blockchain, 1, 20);
+    author_transfer(&blockchain, 2, 30);
+    let rolled_back = blockchain.rollback_to(1);
+    let headers = blockchain
+        .reader()
+        .and_then(|reader| reader.get_block_headers(0, 0, 10));
+    let blocks = blockchain.get_blocks_with_limit_and_skip(0, 0, 10);
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(rolled_back, Ok(1));
+    let expected: Vec<_> = blocks
+        .expect("blocks")
+        .iter()
+        .map(|block| block.to_block_header())
+        .collect();
+    assert_eq!(expected.len(), 2);
+    assert_eq!(headers.expect("headers"), expected);
+}
@@ -0,0 +1,138 @@
+// Multi-node runs of the deterministic network simulation: run with
+// `cargo test --features simulation`.
+#![cfg(feature = "simulation")]
+
+use clutch_node::node::{
+    simulation::{SimConfig, Simulation},
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use std::time::Duration;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+
+fn transfer_transaction(nonce: u64, value: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    transaction
+}
+
+/// Hash of block `index` on `node`.
+fn block_hash(simulation: &Simulation, node: usize, index: usize) -> String {
+    simulation
+        .node(node)
+        .blockchain
+        .get_blocks_with_limit_and_skip(index, 0, 1)
+        .expect("db read failed")
+        .pop()
+        .expect("node has the block")
+        .hash
+}
+
+/// Asserts every node holds the same block at the lowest head index.
+fn assert_converged(simulation: &Simulation) {
+    let heads = simulation.heads();
+    let common = heads.iter().map(|(index, _)| *index).min().unwrap();
+    assert!(common > 0, "no blocks were authored: {:?}", heads);
+    let hash = block_hash(simulation, 0, common);
+    for node in 1..heads.len() {
+        assert_eq!(block_hash(simulation, node, common), hash, "{:?}", heads);
+    }
+}
+
+fn noisy_run(seed: u64) -> Simulation {
+    let mut simulation = Simulation::new(SimConfig {
+        seed,
+        latency: Duration::from_millis(40),
+        jitter: Duration::from_millis(200),
+        ..SimConfig::default()
+    });
+    simulation.run_for(Duration::from_secs(5));
+    simulation
+        .submit_transaction(1, &transfer_transaction(1, 10))
+        .expect("Failed to submit transaction");
+    simulation.run_for(Duration::from_secs(120));
+    simulation
+}
+
+#[test]
+fn authorities_converge_and_include_gossiped_transactions() {
+    let simulation = noisy_run(7);
+
+    assert_converged(&simulation);
+    for node in 0..3 {
+        assert_eq!(
+            simulation
+                .node(node)
+                .blockchain
+                .get_account_balance(&TO_ADDRESS_KEY.to_string()),
+            10
+        );
+    }
+}
+
+#[test]
+fn same_seed_gives_the_same_run() {
+    let first = noisy_run(11);
+    let (first_heads, first_stats) = (first.heads(), first.stats());
+    drop(first);
+    let second = noisy_run(11);
+
+    assert_eq!(second.heads(), first_heads);
+    assert_eq!(second.stats(), first_stats);
+}
+
+#[test]
+fn partitioned_follower_catches_up_after_heal() {
+    let mut simulation = Simulation::new(SimConfig {
+        nodes: 4,
+        authorities: 3,
+        ..SimConfig::default()
+    });
+    simulation.partition(&[&[0, 1, 2]]);
+    simulation.run_for(Duration::from_secs(90));
+    let heads = simulation.heads();
+    assert_eq!(heads[3].0, 0, "the follower was cut off from the start");
+
+    simulation.heal();
+    simulation.run_for(Duration::from_secs(30));
+
+    assert!(simulation.heads()[3].0 > heads[0].0);
+    assert_converged(&simulation);
+    assert!(simulation.stats().dropped > 0);
+}
+
+#[test]
+fn partitioned_authorities_stay_forked_without_fork_choice() {
+    let mut simulation = Simulation::new(SimConfig::default());
+    simulation.partition(&[&[0], &[1, 2]]);
+    // The same nonce spent differently on each side, so their chains diverge.
+    simulation
+        .submit_transaction(0, &transfer_transaction(1, 10))
+        .expect("Failed to submit transaction");
+    simulation
+        .submit_transaction(1, &transfer_transaction(1, 20))
+        .expect("Failed to submit transaction");
+    simulation.run_for(Duration::from_secs(90));
+    simulation.heal();
+    simulation.run_for(Duration::from_secs(30));
+
+    // Sync only extends the head, so neither side takes the other's blocks.
+    let balances: Vec<u64> = (0..3)
+        .map(|node| {
+            simulation
+                .node(node)
+                .blockchain
+                .get_account_balance(&TO_ADDRESS_KEY.to_string())
+        })
+        .collect();
+    assert_eq!(balances, [10, 20, 20]);
+}
@@ -0,0 +1,94 @@
+use clutch_node::node::{
+    blockchain::Blockchain,
+    transactions::{
+        function_call::FunctionCall,
+        stake::{Stake, Unstake, ValidatorStake},
+        transaction::Transaction,
+    },
+    validator_set::{ValidatorSchedule, ValidatorSetChanges},
+};
+use serial_test::serial;
+
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+const MIN_STAKE: u64 = 100;
+const STAKER_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const STAKER_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+fn staking_transaction(nonce: u64, function_call: FunctionCall) -> Transaction {
+    let mut transaction =
+        Transaction::new_transaction(STAKER_ADDRESS_KEY.to_string(), nonce, function_call);
+    transaction.sign(STAKER_SECRET_KEY);
+    transaction
+}
+
+#[test]
+#[serial]
+fn staked_accounts_are_elected_at_the_next_epoch_boundary() {
+    let schedule = ValidatorSchedule::new(
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        2,
+        &ValidatorSetChanges::default(),
+        MIN_STAKE,
+    )
+    .expect("valid schedule");
+    let mut blockchain = Blockchain::new(
+        "clutch-node-staking-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    )
+    .with_validator_schedule(schedule);
+    let balance_before = blockchain.get_account_balance(&STAKER_ADDRESS_KEY.to_string());
+
+    // Block 1 ends epoch 0, but commits to the set the registry held before the stake.
+    blockchain
+        .add_transaction_to_pool(&staking_transaction(
+            1,
+            FunctionCall::Stake(Stake { value: 500 }),
+        ))
+        .expect("Failed to add stake to pool");
+    let block_1 = blockchain
+        .author_new_block()
+        .expect("failed to author block 1");
+
+    blockchain
+        .add_transaction_to_pool(&staking_transaction(
+            2,
+            FunctionCall::Unstake(Unstake { value: 100 }),
+        ))
+        .expect("Failed to add unstake to pool");
+    blockchain
+        .author_new_block()
+        .expect("failed to author block 2");
+    let block_3 = blockchain
+        .author_new_block()
+        .expect("failed to author block 3");
+
+    let validators = blockchain.validators().expect("failed to read validators");
+    let balance_after = blockchain.get_account_balance(&STAKER_ADDRESS_KEY.to_string());
+    let current_author = blockchain.current_author();
+    let block_4 = blockchain.author_new_block();
+    blockchain.shutdown_blockchain();
+
+    assert_eq!(block_1.next_authorities, vec![AUTHOR_PUBLIC_KEY]);
+    assert_eq!(block_3.next_authorities, vec![STAKER_ADDRESS_KEY]);
+    assert_eq!(
+        validators.registry,
+        vec![ValidatorStake {
+            address: STAKER_ADDRESS_KEY.to_string(),
+            stake: 400,
+        }]
+    );
+    assert_eq!(validators.epoch, 2);
+    assert_eq!(validators.authorities, vec![STAKER_ADDRESS_KEY]);
+    assert_eq!(balance_before - balance_after, 400);
+    assert_eq!(current_author, STAKER_ADDRESS_KEY);
+    // The old authority is out of the set and can no longer author.
+    assert!(block_4.is_err());
+}
@@ -0,0 +1,56 @@
+// A node restarted mid-sync picks the download back up from the checkpoint saved in the
+// blockchain column family, including bodies it had downloaded but not yet imported.
+use clutch_node::node::{
+    blockchain::Blockchain, blocks::block::Block, p2p_server::sync_pipeline::SyncCheckpoint,
+};
+use serial_test::serial;
+
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+#[test]
+#[serial]
+fn sync_checkpoint_round_trips_and_clears() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-sync-checkpoint-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+    let empty = blockchain.load_sync_checkpoint();
+
+    let pending_block = Block {
+        index: 7,
+        ..Block::new_genesis_block()
+    };
+    let checkpoint = SyncCheckpoint {
+        head_index: 9,
+        head_hash: "h9".to_string(),
+        target: 120,
+        pending: vec![vec![pending_block]],
+        headers: Vec::new(),
+    };
+    blockchain
+        .save_sync_checkpoint(Some(&checkpoint))
+        .expect("save should succeed");
+    let loaded = blockchain.load_sync_checkpoint();
+
+    blockchain
+        .save_sync_checkpoint(None)
+        .expect("clear should succeed");
+    let cleared = blockchain.load_sync_checkpoint();
+    blockchain.shutdown_blockchain();
+
+    assert!(matches!(empty, Ok(None)));
+    let loaded = loaded
+        .expect("load should succeed")
+        .expect("checkpoint saved");
+    assert_eq!((loaded.head_index, loaded.head_hash.as_str()), (9, "h9"));
+    assert_eq!(loaded.target, 120);
+    assert_eq!(loaded.pending[0][0].index, 7);
+    assert!(matches!(cleared, Ok(None)));
+}
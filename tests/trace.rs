@@ -0,0 +1,145 @@
+// Tracing re-executes a block against the state before it and leaves the chain as it was;
+// a stored state diff matches what the trace reports.
+use clutch_node::node::{
+    balance_effect::BalanceEffectKind,
+    blockchain::Blockchain,
+    blocks::state_diff::StateDiff,
+    transactions::{
+        function_call::FunctionCall,
+        trace::{TraceStep, TransactionTrace},
+        transaction::Transaction,
+        transfer::Transfer,
+    },
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+const BLOCK_REWARD_AMOUNT: u64 = 50;
+
+fn transfer_transaction(nonce: u64, value: u64) -> Transaction {
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        nonce,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    transaction
+}
+
+fn author_transfer(blockchain: &Blockchain, nonce: u64, value: u64) -> String {
+    let transaction = transfer_transaction(nonce, value);
+    blockchain
+        .add_transaction_to_pool(&transaction)
+        .expect("Failed to add transaction to pool");
+    blockchain
+        .author_new_block()
+        .expect("failed to author new block");
+    transaction.hash
+}
+
+/// The recipient's balance before and after the transfer step of `trace`.
+fn transfer_in_balances(trace: &TransactionTrace) -> (Option<u64>, u64) {
+    let step: &TraceStep = trace
+        .steps
+        .iter()
+        .find(|step| {
+            step.effect
+                .as_ref()
+                .is_some_and(|effect| effect.kind == BalanceEffectKind::TransferIn)
+        })
+        .expect("transfer credits the recipient");
+    let write = step.write.as_ref().expect("credit writes the balance");
+    let balance = |value: &serde_json::Value| value["balance"].as_u64().expect("balance");
+    (write.before.as_ref().map(balance), balance(&write.after))
+}
+
+#[test]
+#[serial]
+fn traces_replay_against_the_state_before_the_block() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-trace-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    let to = TO_ADDRESS_KEY.to_string();
+
+    author_transfer(&blockchain, 1, 20);
+    let second = author_transfer(&blockchain, 2, 30);
+    let to_balance = blockchain.get_account_balance(&to);
+
+    let block_trace = blockchain.trace_block(1);
+    let tx_trace = blockchain.trace_transaction(&second);
+    let beyond_head = blockchain.trace_block(3);
+    let unknown = blockchain.trace_transaction("0x00");
+
+    let traced_to_balance = blockchain.get_account_balance(&to);
+    let head = blockchain.get_latest_block().expect("db read failed");
+    author_transfer(&blockchain, 3, 5);
+    blockchain.shutdown_blockchain();
+
+    let block_trace = block_trace.expect("block 1 traces");
+    assert_eq!(block_trace.block_index, 1);
+    assert_eq!(block_trace.transactions.len(), 1);
+    assert_eq!(block_trace.transactions[0].function_call_type, "Transfer");
+    assert_eq!(
+        transfer_in_balances(&block_trace.transactions[0]),
+        (None, 20)
+    );
+    assert_eq!(block_trace.reward.len(), 1);
+
+    let tx_trace = tx_trace
+        .expect("transaction traces")
+        .expect("transaction is in the chain");
+    assert_eq!(tx_trace.transaction_hash, second);
+    assert_eq!(transfer_in_balances(&tx_trace), (Some(20), 50));
+
+    assert!(beyond_head.is_err());
+    assert!(matches!(unknown, Ok(None)));
+    // Nothing the replay wrote is kept.
+    assert_eq!(traced_to_balance, to_balance);
+    assert_eq!(head.expect("chain has a head").index, 2);
+}
+
+#[test]
+#[serial]
+fn stored_state_diffs_match_the_trace() {
+    let blockchain = Blockchain::new(
+        "clutch-node-state-diff-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        BLOCK_REWARD_AMOUNT,
+        2,
+        2,
+    );
+    author_transfer(&blockchain, 1, 20);
+    let mut blockchain = blockchain.with_state_diffs(true);
+    author_transfer(&blockchain, 2, 30);
+
+    let unstored = blockchain.get_state_diff(1);
+    let stored = blockchain.get_state_diff(2);
+    let traced = blockchain
+        .trace_block(2)
+        .map(|trace| StateDiff::from_trace(&trace));
+    blockchain.shutdown_blockchain();
+
+    assert!(matches!(unstored, Ok(None)));
+    let stored = stored.expect("db read failed").expect("diff is stored");
+    assert_eq!(stored.block_index, 2);
+    // Sender and recipient balances, the sender's nonce and the author's reward.
+    assert_eq!(stored.changes.len(), 4);
+    assert_eq!(Ok(stored), traced);
+}
@@ -0,0 +1,65 @@
+// Imported transactions are found by hash through the tx_location index, with a receipt
+// built from their block and balance effects.
+use clutch_node::node::{
+    blockchain::Blockchain,
+    transactions::{function_call::FunctionCall, transaction::Transaction, transfer::Transfer},
+};
+use serial_test::serial;
+
+const FROM_ADDRESS_KEY: &str = "0xdeb4cfb63db134698e1879ea24904df074726cc0";
+const FROM_SECRET_KEY: &str = "d2c446110cfcecbdf05b2be528e72483de5b6f7ef9c7856df2f81f48e9f2748f";
+const TO_ADDRESS_KEY: &str = "0x8f19077627cde4848b090c53c83b12956837d5e9";
+const AUTHOR_PUBLIC_KEY: &str = "0x9b6e8afff8329743cac73dbef83ca3cbf9a74c20";
+const AUTHOR_SECRET_KEY: &str = "0883ddd3d07303b87c954b0c9383f7b78f45e002520fc03a8adc80595dbf6509";
+
+#[test]
+#[serial]
+fn imported_transaction_has_location_and_receipt() {
+    let mut blockchain = Blockchain::new(
+        "clutch-node-transaction-lookup-test".to_string(),
+        AUTHOR_PUBLIC_KEY.to_string(),
+        AUTHOR_SECRET_KEY.to_string(),
+        true,
+        vec![AUTHOR_PUBLIC_KEY.to_string()],
+        50,
+        2,
+        2,
+    );
+
+    let mut transaction = Transaction::new_transaction(
+        FROM_ADDRESS_KEY.to_string(),
+        1,
+        FunctionCall::Transfer(Transfer {
+            to: TO_ADDRESS_KEY.to_string(),
+            value: 20,
+        }),
+    );
+    transaction.sign(FROM_SECRET_KEY);
+    blockchain
+        .add_transaction_to_pool(&transaction)
+        .expect("Failed to add transaction to pool");
+    let before_import = blockchain.find_transaction(&transaction.hash);
+    let block = blockchain
+        .author_new_block()
+        .expect("failed to author new block");
+
+    let found = blockchain.find_transaction(&transaction.hash);
+    let receipt = blockchain.get_transaction_receipt(&transaction.hash);
+    let unknown = blockchain.get_transaction_receipt("0xunknown");
+    blockchain.shutdown_blockchain();
+
+    assert!(matches!(before_import, Ok(None)));
+    let (found_block, tx_index) = found.expect("lookup failed").expect("transaction indexed");
+    assert_eq!((found_block.index, tx_index), (block.index, 0));
+
+    let receipt = receipt.expect("lookup failed").expect("receipt exists");
+    assert_eq!(receipt.block_hash, block.hash);
+    assert_eq!(receipt.function_call_type, "Transfer");
+    assert_eq!(receipt.status, 1);
+    assert!(receipt
+        .logs
+        .iter()
+        .any(|log| log.effect.delta == 20
+            && log.tx_hash.as_deref() == Some(transaction.hash.as_str())));
+    assert!(matches!(unknown, Ok(None)));
+}
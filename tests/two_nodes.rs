@@ -0,0 +1,196 @@
+// End-to-end sync between two in-process nodes connected over libp2p's memory transport:
+// run with `cargo test --features test-utils`.
+#![cfg(feature = "test-utils")]
+
+use clutch_node::node::p2p_server::commands::DirectMessageType;
+use clutch_node::node::p2p_server::disconnect::DisconnectReason;
+use clutch_node::node::p2p_server::peer_table::PeerSummary;
+use clutch_node::node::p2p_server::ping::{Ping, Pong};
+use clutch_node::node::p2p_server::P2PServer;
+use clutch_node::node::test_utils::TwoNodes;
+use libp2p::{Multiaddr, PeerId};
+use serial_test::serial;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test]
+#[serial]
+async fn follower_catches_up_and_then_follows_new_blocks() {
+    let nodes = TwoNodes::start();
+    let mined = nodes.mine_blocks(5).await;
+    let caught_up = nodes.await_synced(TIMEOUT).await;
+    let connected = nodes.await_connected(TIMEOUT).await;
+    let followed = nodes.mine_blocks(3).await;
+    let synced = nodes.await_synced(TIMEOUT).await;
+    let follower_head = nodes.follower.head().await;
+    nodes.shutdown().await;
+
+    assert_eq!(mined.map(|blocks| blocks.len()), Ok(5));
+    assert_eq!(caught_up, Ok(5));
+    assert_eq!(connected, Ok(()));
+    assert_eq!(synced, Ok(8));
+    let last_mined = followed.expect("author mined the last blocks").pop();
+    assert_eq!(follower_head.ok(), last_mined.map(|block| (8, block.hash)));
+}
+
+#[tokio::test]
+#[serial]
+async fn follower_catches_up_over_the_sync_stream() {
+    let nodes = TwoNodes::start_with(|config| config.capabilities.serves_sync_stream = true);
+    let mined = nodes.mine_blocks(5).await;
+    let synced = nodes.await_synced(TIMEOUT).await;
+    nodes.shutdown().await;
+
+    assert_eq!(mined.map(|blocks| blocks.len()), Ok(5));
+    assert_eq!(synced, Ok(5));
+}
+
+/// The author as the follower's peer table shows it, once the two have handshaked.
+async fn handshaked_author(nodes: &TwoNodes) -> Option<PeerSummary> {
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        let peers = P2PServer::get_peers_command(nodes.follower.commands.clone()).await;
+        if let Some(author) = peers
+            .ok()
+            .and_then(|snapshot| snapshot.peers.into_iter().find(|peer| peer.handshaked))
+        {
+            return Some(author);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    None
+}
+
+#[tokio::test]
+#[serial]
+async fn peers_learn_each_others_signed_addresses() {
+    let nodes = TwoNodes::start();
+    let author = handshaked_author(&nodes).await;
+    nodes.shutdown().await;
+
+    let author = author.expect("nodes handshake");
+    assert!(!author.record_addresses.is_empty());
+    assert!(author
+        .record_addresses
+        .iter()
+        .all(|address| address.starts_with("/memory/")));
+}
+
+/// Whether the follower's connection to `peer` closes within the timeout.
+async fn follower_drops(nodes: &TwoNodes, peer: &PeerId) -> bool {
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        let connected =
+            P2PServer::get_connected_peers_command(nodes.follower.commands.clone()).await;
+        if connected.is_ok_and(|peers| !peers.contains(peer)) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+#[serial]
+async fn peers_are_dropped_and_dialed_on_command() {
+    let nodes = TwoNodes::start();
+    let author = handshaked_author(&nodes).await.expect("nodes handshake");
+    let author_id: PeerId = author.peer_id.parse().expect("peer id");
+    let commands = nodes.follower.commands.clone();
+
+    let removed = P2PServer::disconnect_peer_command(
+        commands.clone(),
+        author_id,
+        DisconnectReason::Requested,
+    )
+    .await;
+    let stranger = P2PServer::disconnect_peer_command(
+        commands.clone(),
+        PeerId::random(),
+        DisconnectReason::Requested,
+    )
+    .await;
+    let dropped = follower_drops(&nodes, &author_id).await;
+    let address: Multiaddr = author.record_addresses[0].parse().expect("multiaddr");
+    let dialed = P2PServer::dial_peer_command(commands, address).await;
+    let reconnected = handshaked_author(&nodes).await;
+    nodes.shutdown().await;
+
+    assert!(removed.expect("command answered"));
+    assert!(!stranger.expect("command answered"));
+    assert!(dropped);
+    assert!(dialed.is_ok());
+    assert_eq!(reconnected.map(|peer| peer.peer_id), Some(author.peer_id));
+}
+
+#[tokio::test]
+#[serial]
+async fn follower_keeps_its_finalized_blocks_when_the_author_rewrites_them() {
+    let nodes = TwoNodes::start();
+    let mined = nodes.mine_blocks(4).await;
+    let synced = nodes.await_synced(TIMEOUT).await;
+    let author = handshaked_author(&nodes).await.expect("nodes handshake");
+    let author_id: PeerId = author.peer_id.parse().expect("peer id");
+    let commands = nodes.follower.commands.clone();
+
+    // While they're apart, the author drops blocks 3 and 4 for a longer branch. Block
+    // timestamps are in seconds, so waiting one gives the new blocks different hashes.
+    // With a single authority every block is finalized as it is sealed, so the follower
+    // must not unwind to block 2 to follow it.
+    let removed = P2PServer::disconnect_peer_command(
+        commands.clone(),
+        author_id,
+        DisconnectReason::Requested,
+    )
+    .await;
+    let dropped = follower_drops(&nodes, &author_id).await;
+    let (_, abandoned) = nodes.follower.head().await.expect("follower head");
+    let rolled_back = nodes.author.blockchain.write().await.rollback_to(2);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let branch = nodes.mine_blocks(4).await;
+    let address: Multiaddr = author.record_addresses[0].parse().expect("multiaddr");
+    let dialed = P2PServer::dial_peer_command(commands, address).await;
+    let resynced = nodes.await_synced(TIMEOUT).await;
+    let follower_head = nodes.follower.head().await;
+    nodes.shutdown().await;
+
+    assert_eq!(mined.map(|blocks| blocks.len()), Ok(4));
+    assert_eq!(synced, Ok(4));
+    assert!(removed.expect("command answered"));
+    assert!(dropped);
+    assert_eq!(rolled_back, Ok(2));
+    assert_eq!(branch.map(|blocks| blocks.len()), Ok(4));
+    assert!(dialed.is_ok());
+    assert!(resynced.is_err());
+    assert_eq!(follower_head, Ok((4, abandoned)));
+}
+
+#[tokio::test]
+#[serial]
+async fn callers_await_the_response_to_their_request() {
+    let nodes = TwoNodes::start();
+    let author = handshaked_author(&nodes).await.expect("nodes handshake");
+    let author_id: PeerId = author.peer_id.parse().expect("peer id");
+
+    let pong: Result<Pong, _> = P2PServer::request_command(
+        nodes.follower.commands.clone(),
+        author_id,
+        DirectMessageType::Ping,
+        &Ping { nonce: 42 },
+        DirectMessageType::Pong,
+    )
+    .await;
+    let mismatched: Result<Pong, _> = P2PServer::request_command(
+        nodes.follower.commands.clone(),
+        author_id,
+        DirectMessageType::Ping,
+        &Ping { nonce: 43 },
+        DirectMessageType::BlockHeaders,
+    )
+    .await;
+    nodes.shutdown().await;
+
+    assert_eq!(pong.expect("author answers").nonce, 42);
+    assert!(mismatched.is_err());
+}